@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use contract1::parimutuel_payout;
+use teloxide::types::ChatId;
+
+use crate::currency::{format_amount, Currency};
+use crate::db::{Bet, OddsWatch, Wager};
+use crate::messenger::Messenger;
+use crate::preview::probability_bps;
+use crate::{BotContext, HandlerResult};
+
+/// Smallest move of a market's odds, in basis points of probability,
+/// worth a private message to its bettors: 15 percentage points.
+pub const ODDS_ALERT_THRESHOLD_BPS: u32 = 1_500;
+
+/// Shortest time between two alerts about the same market to the same user.
+pub const ALERT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Whether a bettor who last heard of `last_yes_bps`, and was last alerted
+/// at `notified_at`, is told about the odds moving to `yes_bps`.
+pub fn should_alert(last_yes_bps: u32, yes_bps: u32, notified_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    let elapsed = |at: DateTime<Utc>| (now - at).to_std().unwrap_or_default();
+    last_yes_bps.abs_diff(yes_bps) > ODDS_ALERT_THRESHOLD_BPS
+        && notified_at.is_none_or(|at| elapsed(at) >= ALERT_INTERVAL)
+}
+
+fn percent(bps: u32) -> String {
+    format!("{}.{}%", bps / 100, bps % 100 / 10)
+}
+
+/// The private message telling `user_id` that the odds of `bet` moved.
+pub fn alert_text(bet: &Bet, user_id: i64, last_yes_bps: u32, wagers: &[Wager], currency: &Currency) -> String {
+    let pool = |side: bool| wagers.iter().filter(|w| w.side == side).map(|w| w.amount.max(0) as u128).sum::<u128>();
+    let (yes_pool, no_pool) = (pool(true), pool(false));
+    let yes_bps = probability_bps(yes_pool, no_pool, true);
+    let mut text = format!(
+        "{} The odds of bet #{} moved\n📄 {}\n📊 YES: {} → {}",
+        if yes_bps > last_yes_bps { "📈" } else { "📉" },
+        bet.bet_id,
+        bet.description,
+        percent(last_yes_bps),
+        percent(yes_bps)
+    );
+    for side in [true, false] {
+        let stake = wagers
+            .iter()
+            .filter(|w| w.user_id == user_id && w.side == side)
+            .map(|w| w.amount.max(0) as u128)
+            .sum::<u128>();
+        if stake > 0 {
+            text.push_str(&format!(
+                "\n💰 Your {} on {} would pay {} right now",
+                format_amount(currency, stake),
+                if side { "YES" } else { "NO" },
+                format_amount(currency, parimutuel_payout(stake, pool(side), yes_pool + no_pool))
+            ));
+        }
+    }
+    text.push_str("\n\nSend /alerts off to stop these messages.");
+    text
+}
+
+/// After `bettor` bet on `bet_id`: watches the odds for them from there, and
+/// tells the other bettors if the odds moved far enough from what they last
+/// heard of.
+pub async fn after_bet(bot: &Messenger, ctx: &BotContext, bet_id: i64, bettor: i64) {
+    if let Err(e) = check(bot, ctx, bet_id, bettor, Utc::now()).await {
+        log::error!("Failed to send the odds alerts of bet #{}: {}", bet_id, e);
+    }
+}
+
+pub async fn check(bot: &Messenger, ctx: &BotContext, bet_id: i64, bettor: i64, now: DateTime<Utc>) -> HandlerResult {
+    let Some(bet) = ctx.db.get_bet_by_id(bet_id).await? else {
+        return Ok(());
+    };
+    let Some(chat_id) = bet.chat_id else {
+        return Ok(());
+    };
+    let wagers = ctx.db.get_wagers_for_bet(bet_id).await?;
+    let pool = |side: bool| wagers.iter().filter(|w| w.side == side).map(|w| w.amount.max(0) as u128).sum::<u128>();
+    let yes_bps = probability_bps(pool(true), pool(false), true);
+    // The bettor just saw these odds
+    ctx.db.watch_odds(bettor, bet_id, yes_bps).await?;
+
+    let currency = ctx.db.get_currency(chat_id).await?;
+    for OddsWatch { user_id, yes_bps: last_yes_bps, notified_at } in ctx.db.get_odds_watches(bet_id).await? {
+        let notified_at = notified_at.and_then(|at| DateTime::parse_from_rfc3339(&at).ok()).map(|at| at.with_timezone(&Utc));
+        let last_yes_bps = last_yes_bps as u32;
+        if !should_alert(last_yes_bps, yes_bps, notified_at, now) {
+            continue;
+        }
+        let text = alert_text(&bet, user_id, last_yes_bps, &wagers, &currency);
+        // Recorded even when it can't be delivered, e.g. to users who never
+        // opened a private chat with the bot, so they aren't retried each bet
+        if let Err(e) = bot.send_message(ChatId(user_id), text).await {
+            log::warn!("Could not send the odds alert of bet #{} to user {}: {}", bet_id, user_id, e);
+        }
+        ctx.db.record_odds_alert(user_id, bet_id, yes_bps, &now.to_rfc3339()).await?;
+    }
+    Ok(())
+}
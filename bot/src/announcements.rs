@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use teloxide::types::{ChatId, MessageId};
+use teloxide::{ApiError, RequestError};
+
+use contract1::api::MarketHistoryPoint;
+
+use crate::currency::{format_amount, Currency};
+use crate::db::Wager;
+use crate::markdown::escape;
+use crate::messenger::Messenger;
+use crate::{BotContext, HandlerResult};
+
+/// Shortest time between two edits of the same announcement, to stay clear
+/// of Telegram's rate limits.
+pub const EDIT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Cells of the pool bar.
+const BAR_WIDTH: usize = 10;
+
+/// Most cells of an odds sparkline; longer histories are sampled.
+pub const SPARKLINE_WIDTH: usize = 40;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// `YES ▓▓▓▓▓░░░░░ 52% · pool 🪙 3,400 coins`: the share of the pool on YES.
+pub fn pool_bar(yes_pool: i64, no_pool: i64, currency: &Currency) -> String {
+    let total = (yes_pool + no_pool).max(0);
+    let share = if total == 0 { 0.0 } else { yes_pool as f64 / total as f64 };
+    let filled = ((share * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+    format!(
+        "YES {}{} {}% · pool {}",
+        "▓".repeat(filled),
+        "░".repeat(BAR_WIDTH - filled),
+        (share * 100.0).round(),
+        format_amount(currency, total as u128)
+    )
+}
+
+/// Share of the pool on YES right after `point`, between 0 and 1.
+pub fn yes_share(point: &MarketHistoryPoint) -> f64 {
+    let total = point.yes_pool + point.no_pool;
+    if total == 0 { 0.0 } else { point.yes_pool as f64 / total as f64 }
+}
+
+/// `▁▃▅█▆`: the YES share after each bet of `history`, at most
+/// [`SPARKLINE_WIDTH`] cells wide. Sampling keeps the latest bet.
+pub fn sparkline(history: &[MarketHistoryPoint]) -> String {
+    let cells = history.len().min(SPARKLINE_WIDTH);
+    (1..=cells)
+        .map(|cell| {
+            let share = yes_share(&history[cell * history.len() / cells - 1]);
+            SPARKS[((share * (SPARKS.len() - 1) as f64).round() as usize).min(SPARKS.len() - 1)]
+        })
+        .collect()
+}
+
+/// The MarkdownV2 announcement `text` followed by the pools of `wagers`.
+pub fn announcement_text(text: &str, wagers: &[Wager], currency: &Currency) -> String {
+    if wagers.is_empty() {
+        return format!("{}\n\n📊 No bets yet", text);
+    }
+    let yes_pool: i64 = wagers.iter().filter(|w| w.side).map(|w| w.amount).sum();
+    let no_pool: i64 = wagers.iter().filter(|w| !w.side).map(|w| w.amount).sum();
+    let mut bettors: Vec<i64> = wagers.iter().map(|w| w.user_id).collect();
+    bettors.sort_unstable();
+    bettors.dedup();
+    format!(
+        "{}\n\n📊 {}\n👥 {} bettor{}",
+        text,
+        escape(&pool_bar(yes_pool, no_pool, currency)),
+        bettors.len(),
+        if bettors.len() == 1 { "" } else { "s" }
+    )
+}
+
+/// What to do with a request to refresh an announcement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditPlan {
+    /// Edit right away
+    Now,
+    /// Edit once the interval since the last edit is over
+    After(Duration),
+    /// An edit is already scheduled and will show this change too
+    Coalesced,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EditSlot {
+    last_edit: Instant,
+    scheduled: bool,
+}
+
+/// Throttles the edits of each announcement to one per [`EDIT_INTERVAL`]:
+/// changes arriving in between are coalesced into a single delayed edit.
+#[derive(Default)]
+pub struct AnnouncementEdits(Mutex<HashMap<i64, EditSlot>>);
+
+impl AnnouncementEdits {
+    pub fn schedule(&self, bet_id: i64, now: Instant) -> EditPlan {
+        let mut slots = self.0.lock().unwrap();
+        match slots.get_mut(&bet_id) {
+            Some(slot) if slot.scheduled => EditPlan::Coalesced,
+            Some(slot) if now.saturating_duration_since(slot.last_edit) < EDIT_INTERVAL => {
+                slot.scheduled = true;
+                EditPlan::After(slot.last_edit + EDIT_INTERVAL - now)
+            }
+            _ => {
+                slots.insert(bet_id, EditSlot { last_edit: now, scheduled: false });
+                EditPlan::Now
+            }
+        }
+    }
+
+    /// Marks the scheduled edit of `bet_id` as done at `now`.
+    pub fn edited(&self, bet_id: i64, now: Instant) {
+        self.0.lock().unwrap().insert(bet_id, EditSlot { last_edit: now, scheduled: false });
+    }
+}
+
+/// Updates the announcement of `bet_id` with its current pools, now or once
+/// the throttle allows it.
+pub async fn refresh(bot: &Messenger, ctx: &Arc<BotContext>, bet_id: i64) {
+    match ctx.announcement_edits.schedule(bet_id, Instant::now()) {
+        EditPlan::Now => {
+            if let Err(e) = edit(bot, ctx, bet_id).await {
+                log::error!("Failed to update the announcement of bet #{}: {}", bet_id, e);
+            }
+        }
+        EditPlan::After(delay) => {
+            let (bot, ctx) = (bot.clone(), Arc::clone(ctx));
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                ctx.announcement_edits.edited(bet_id, Instant::now());
+                if let Err(e) = edit(&bot, &ctx, bet_id).await {
+                    log::error!("Failed to update the announcement of bet #{}: {}", bet_id, e);
+                }
+            });
+        }
+        EditPlan::Coalesced => {}
+    }
+}
+
+async fn edit(bot: &Messenger, ctx: &BotContext, bet_id: i64) -> HandlerResult {
+    let Some(announcement) = ctx.db.get_live_announcement(bet_id).await? else {
+        return Ok(());
+    };
+    let wagers = ctx.db.get_wagers_for_bet(bet_id).await?;
+    let currency = ctx.db.get_currency(announcement.chat_id).await?;
+    let text = announcement_text(&announcement.text, &wagers, &currency);
+    let message_id = MessageId(announcement.message_id as i32);
+
+    match bot.edit_markdown(ChatId(announcement.chat_id), message_id, text).await {
+        Ok(()) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        // Deleted, or too old for Telegram to accept edits: stop trying
+        Err(RequestError::Api(ApiError::MessageToEditNotFound | ApiError::MessageCantBeEdited)) => {
+            log::warn!("The announcement of bet #{} can no longer be edited", bet_id);
+            ctx.db.remove_live_announcement(bet_id).await?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,232 +1,938 @@
-use anyhow::{Result, anyhow};
-use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
+use async_trait::async_trait;
+use contract1::api::{
+    ClaimResult, EventFilter, LeaderboardEntry, MarketFilter, MarketHistoryPoint, MarketSummary, Odds, OpenExposure,
+    ReconcileReport, ReconcileSnapshot, ResolveResult, TreasuryInfo, UserBetInfo, UserInfo, WebhookPayload,
+};
+use contract1::http::{
+    AcceptChallengeRequest, AddCommentRequest, ApiRequest, CancelChallengeRequest, ChallengeRequest, ClaimWinningsRequest,
+    CloseBettingRequest, CreateMarketRequest, ExpireMarketRequest, GetBalanceRequest, GetMarketInfoRequest, GetOpenExposureRequest,
+    InitializeRequest, PlaceBetRequest, ResetBalancesRequest, ResolveMarketRequest, SetAdminRequest, WithdrawTreasuryRequest,
+};
+pub use contract1::http::{ConfigResponse, TxState, TxStatus};
+use futures::Stream;
+use rand::Rng;
+use reqwest::{
+    header::{ACCEPT, RETRY_AFTER},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard, Semaphore};
+use uuid::Uuid;
+
+/// Correlation id attached to every outgoing request and echoed back by the server.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Operator secret required by the server's admin routes.
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+/// Id of the last server-sent event received, sent when reconnecting.
+pub const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// Event ids remembered to drop the events a reconnection delivers again.
+const SEEN_EVENT_IDS: usize = 1024;
+
+/// Errors returned by [`MarketApiClient`], classified so callers can react
+/// differently to an unreachable server and a rejected action.
+#[derive(Debug, Error)]
+pub enum MarketApiError {
+    /// The request never produced a response (DNS, connection refused, reset...)
+    #[error("could not reach the market server: {0}")]
+    Transport(String),
+    /// The request did not complete in time
+    #[error("the market server did not respond in time")]
+    Timeout,
+    /// The server answered with an unexpected status
+    #[error("market server error ({status}): {body}")]
+    ServerError { status: StatusCode, body: String },
+    /// The contract refused the action (insufficient balance, closed market, ...)
+    #[error("{message}")]
+    ContractRejected { message: String },
+    /// An admin route refused the admin key, or none was configured
+    #[error("admin access denied: {body}")]
+    Forbidden { body: String },
+    /// The transaction was submitted but had not settled when the server replied
+    #[error("transaction {tx_hash} is still pending")]
+    Pending { tx_hash: String },
+    /// The response body could not be decoded
+    #[error("unexpected response from the market server: {0}")]
+    Deserialization(String),
+    /// Any of the above, tagged with the `x-request-id` of the failed call
+    #[error("{source} [request {request_id}]")]
+    Request {
+        request_id: String,
+        #[source]
+        source: Box<MarketApiError>,
+    },
+}
+
+impl MarketApiError {
+    fn with_request_id(self, request_id: &str) -> Self {
+        match self {
+            MarketApiError::Request { .. } => self,
+            error => MarketApiError::Request {
+                request_id: request_id.to_string(),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The underlying error, without its request tag.
+    pub fn kind(&self) -> &MarketApiError {
+        match self {
+            MarketApiError::Request { source, .. } => source.kind(),
+            error => error,
+        }
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            MarketApiError::Request { request_id, .. } => Some(request_id),
+            _ => None,
+        }
+    }
+
+    /// Short code users can quote when reporting a problem; it is the
+    /// prefix of the request id, so it can be grepped in server logs.
+    pub fn reference_code(&self) -> Option<String> {
+        self.request_id()
+            .map(|id| id.chars().filter(|c| *c != '-').take(8).collect::<String>().to_uppercase())
+    }
+}
+
+impl From<reqwest::Error> for MarketApiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            MarketApiError::Timeout
+        } else if error.is_decode() {
+            MarketApiError::Deserialization(error.to_string())
+        } else {
+            MarketApiError::Transport(error.to_string())
+        }
+    }
+}
+
+pub type Result<T, E = MarketApiError> = std::result::Result<T, E>;
+
+/// Per-identity locks, created on first use
+type IdentityLocks = Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>;
 
 #[derive(Clone)]
 pub struct MarketApiClient {
     client: Client,
+    /// Without the request timeout, which would cut the event stream
+    stream_client: Client,
     base_url: String,
+    retry_policy: RetryPolicy,
+    /// Bounds the number of HTTP calls in flight at once
+    limiter: Arc<Semaphore>,
+    max_concurrent_requests: usize,
+    /// One lock per identity with an action in flight, when actions from the
+    /// same user are serialized
+    identity_locks: Option<IdentityLocks>,
+    /// Sent only by the admin methods
+    admin_key: Option<String>,
+}
+
+/// Retry behaviour for transient failures (connection errors, timeouts and
+/// 502/503/504 responses). Client errors (4xx) are never retried, and actions
+/// that could apply twice only when the server never processed them: see
+/// [`Retry`]. A `Retry-After` on the response replaces the computed delay.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every subsequent retry
+    pub base_delay: Duration,
+    /// Upper bound for a single delay before jitter is applied
+    pub max_delay: Duration,
+    /// Fraction of the delay (0.0 - 1.0) randomly added or removed
+    pub jitter: f64,
 }
 
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            jitter: 0.2,
+        }
+    }
+}
 
-#[derive(Serialize)]
-struct InitializeRequest {}
+impl RetryPolicy {
+    fn delay_for(&self, retry: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        if self.jitter <= 0.0 {
+            return exponential;
+        }
+        let factor = 1.0 + rand::thread_rng().gen_range(-self.jitter..=self.jitter);
+        exponential.mul_f64(factor.max(0.0))
+    }
+}
 
-#[derive(Serialize)]
-struct CreateMarketRequest {
-    description: String,
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
 }
 
-#[derive(Serialize)]
-struct PlaceBetRequest {
-    market_id: u64,
-    side: bool,
-    amount: u128,
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout()
 }
 
-#[derive(Serialize)]
-struct ResolveMarketRequest {
-    market_id: u64,
-    outcome: bool,
+/// The delay a response asks for before trying again, in seconds or as an
+/// HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default())
 }
 
-#[derive(Serialize)]
-struct ClaimWinningsRequest {
-    market_id: u64,
+/// Which failed attempts a request may be sent again after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Retry {
+    /// Reads and actions that change nothing when repeated: after any
+    /// transient failure
+    Transient,
+    /// Actions that could apply twice: only when the server never processed
+    /// them, because the connection failed or the server refused them with a
+    /// `Retry-After`, which it sends before submitting anything
+    Unprocessed,
 }
 
-#[derive(Serialize)]
-struct GetBalanceRequest {}
+/// Action routes that change nothing when sent twice: queries, and
+/// initialization, which the bot always asks for idempotently.
+const IDEMPOTENT_PATHS: &[&str] = &[
+    InitializeRequest::PATH,
+    GetBalanceRequest::PATH,
+    GetOpenExposureRequest::PATH,
+    GetMarketInfoRequest::PATH,
+];
+
+
+/// Length of a hex-encoded transaction hash.
+const TX_HASH_HEX_LEN: usize = 64;
+
+/// Settled transaction returned by the action endpoints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxReceipt<T = serde_json::Value> {
+    pub tx_hash: String,
+    /// Decoded contract output, when the server includes it
+    pub result: Option<T>,
+}
 
-#[derive(Serialize)]
-struct GetMarketInfoRequest {
-    market_id: u64,
+impl TxReceipt {
+    /// Reads the contract output as `T`. It is left out when the server only
+    /// answered with the hash or sent an output of another shape.
+    pub fn decode<T: DeserializeOwned>(self) -> TxReceipt<T> {
+        TxReceipt {
+            tx_hash: self.tx_hash,
+            result: self.result.and_then(|result| serde_json::from_value(result).ok()),
+        }
+    }
 }
 
+/// Body of an action response: a bare JSON string holding the hash, or an
+/// object carrying the hash alongside the contract's result.
 #[derive(Deserialize)]
-pub struct ConfigResponse {
-    pub contract_name: String,
+#[serde(untagged)]
+enum TxResponse {
+    Hash(String),
+    Detailed {
+        tx_hash: String,
+        #[serde(default)]
+        result: Option<serde_json::Value>,
+    },
 }
 
-impl MarketApiClient {
+fn validate_tx_hash(tx_hash: &str) -> Result<String> {
+    let tx_hash = tx_hash.trim();
+    if tx_hash.len() != TX_HASH_HEX_LEN || !tx_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(MarketApiError::Deserialization(format!(
+            "invalid transaction hash {:?}",
+            tx_hash
+        )));
+    }
+    Ok(tx_hash.to_lowercase())
+}
+
+/// Parses an action response body into a [`TxReceipt`]. Unquoted bodies are
+/// accepted as a bare hash for compatibility with plain-text servers.
+pub fn parse_tx_receipt(body: &str) -> Result<TxReceipt> {
+    let parsed = serde_json::from_str::<TxResponse>(body)
+        .unwrap_or_else(|_| TxResponse::Hash(body.to_string()));
+
+    match parsed {
+        TxResponse::Hash(tx_hash) => Ok(TxReceipt {
+            tx_hash: validate_tx_hash(&tx_hash)?,
+            result: None,
+        }),
+        TxResponse::Detailed { tx_hash, result } => Ok(TxReceipt {
+            tx_hash: validate_tx_hash(&tx_hash)?,
+            result,
+        }),
+    }
+}
+
+/// Builds a [`MarketApiClient`] with a shared, pre-configured HTTP client.
+#[derive(Debug, Clone)]
+pub struct MarketApiClientBuilder {
+    base_url: String,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    keep_alive: Duration,
+    user_agent: String,
+    retry_policy: RetryPolicy,
+    max_concurrent_requests: usize,
+    serialize_per_identity: bool,
+    admin_key: Option<String>,
+}
+
+impl MarketApiClientBuilder {
     pub fn new(base_url: String) -> Self {
         Self {
-            client: Client::new(),
             base_url,
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(15),
+            keep_alive: Duration::from_secs(60),
+            user_agent: format!("groupchat-market-bot/{}", env!("CARGO_PKG_VERSION")),
+            retry_policy: RetryPolicy::default(),
+            max_concurrent_requests: 8,
+            serialize_per_identity: true,
+            admin_key: None,
         }
     }
 
+    /// Maximum time to establish the TCP connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
 
-    pub async fn get_config(&self) -> Result<ConfigResponse> {
-        let url = format!("{}/api/config", self.base_url);
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+    /// Maximum time for a whole request, from connecting to reading the body
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
 
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to get config: {}", error_text));
-        }
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
 
-        let config = response.json::<ConfigResponse>().await?;
-        Ok(config)
+    /// Maximum number of HTTP calls in flight at once; further calls wait
+    pub fn max_concurrent_requests(mut self, permits: usize) -> Self {
+        self.max_concurrent_requests = permits.max(1);
+        self
     }
 
-    pub async fn initialize_user(&self, user_id: String, contract_name: &str) -> Result<String> {
-        let url = format!("{}/api/market/initialize", self.base_url);
-        let request = InitializeRequest {};
+    /// When enabled, two actions from the same user are never in flight together
+    pub fn serialize_per_identity(mut self, enabled: bool) -> Self {
+        self.serialize_per_identity = enabled;
+        self
+    }
 
-        let identity = format!("{}@{}", user_id, contract_name);
-        let response = self.client
-            .post(&url)
-            .header("x-user", identity)
-            .json(&request)
-            .send()
-            .await?;
+    /// Secret for the server's admin routes (`set_admin`, treasury withdrawals)
+    pub fn admin_key(mut self, key: String) -> Self {
+        self.admin_key = Some(key);
+        self
+    }
 
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to initialize user: {}", error_text));
-        }
+    pub fn build(self) -> Result<MarketApiClient> {
+        let client = Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .tcp_keepalive(self.keep_alive)
+            .pool_idle_timeout(self.keep_alive)
+            .user_agent(self.user_agent.clone())
+            .build()
+            .map_err(|e| MarketApiError::Transport(e.to_string()))?;
+        let stream_client = Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .tcp_keepalive(self.keep_alive)
+            .user_agent(self.user_agent)
+            .build()
+            .map_err(|e| MarketApiError::Transport(e.to_string()))?;
+
+        Ok(MarketApiClient {
+            client,
+            stream_client,
+            base_url: self.base_url,
+            retry_policy: self.retry_policy,
+            limiter: Arc::new(Semaphore::new(self.max_concurrent_requests)),
+            max_concurrent_requests: self.max_concurrent_requests,
+            identity_locks: self
+                .serialize_per_identity
+                .then(|| Arc::new(Mutex::new(HashMap::new()))),
+            admin_key: self.admin_key,
+        })
+    }
+}
 
-        let tx_hash = response.text().await?;
-        Ok(tx_hash)
+impl MarketApiClient {
+    pub fn builder(base_url: String) -> MarketApiClientBuilder {
+        MarketApiClientBuilder::new(base_url)
     }
 
-    pub async fn create_market(&self, user_id: String, description: String, contract_name: &str) -> Result<String> {
-        let url = format!("{}/api/market/create", self.base_url);
-        let request = CreateMarketRequest { description };
+    /// Number of HTTP calls currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.max_concurrent_requests - self.limiter.available_permits()
+    }
 
-        let identity = format!("{}@{}", user_id, contract_name);
-        let response = self.client
-            .post(&url)
-            .header("x-user", identity)
-            .json(&request)
-            .send()
-            .await?;
+    /// Waits until no other action from `identity` is in flight. Returns
+    /// `None` when per-identity serialization is disabled.
+    async fn lock_identity(&self, identity: &str) -> Option<OwnedMutexGuard<()>> {
+        let locks = self.identity_locks.as_ref()?;
+        let lock = {
+            let mut locks = locks.lock().unwrap_or_else(|e| e.into_inner());
+            // Drop locks nobody is holding or waiting on
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(identity.to_string()).or_default().clone()
+        };
+        Some(lock.lock_owned().await)
+    }
 
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to create market: {}", error_text));
+    /// Sends the request built by `build` tagged with `request_id`, retrying
+    /// the failures `retry` allows according to the client's retry policy.
+    /// A response asking to wait longer than the policy's longest delay is
+    /// returned as is.
+    async fn send_with_retry<F>(&self, request_id: &str, retry: Retry, build: F) -> Result<Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let max_attempts = self.retry_policy.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let sent = {
+                let _permit = self.limiter.acquire().await.expect("request limiter is never closed");
+                log::debug!("Request {} sent ({} in flight)", request_id, self.in_flight());
+                build().header(REQUEST_ID_HEADER, request_id).send().await
+            };
+            let (retryable, asked_delay) = match sent {
+                Ok(response) if is_transient_status(response.status()) && attempt < max_attempts => {
+                    let asked_delay = retry_after(&response);
+                    // Without a `Retry-After`, the server may have processed it
+                    let may_apply_twice = retry == Retry::Unprocessed && asked_delay.is_none();
+                    if may_apply_twice || asked_delay.is_some_and(|delay| delay > self.retry_policy.max_delay) {
+                        return Ok(response);
+                    }
+                    (format!("status {}", response.status()), asked_delay)
+                }
+                Ok(response) => return Ok(response),
+                // A failed connection never reached the server
+                Err(e) if attempt < max_attempts && (e.is_connect() || (retry == Retry::Transient && is_transient_error(&e))) => {
+                    (e.to_string(), None)
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let delay = asked_delay.unwrap_or_else(|| self.retry_policy.delay_for(attempt - 1));
+            log::warn!(
+                "Request {} attempt {}/{} failed ({}), retrying in {:?}",
+                request_id, attempt, max_attempts, retryable, delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
-
-        let tx_hash = response.text().await?;
-        Ok(tx_hash)
     }
 
-    pub async fn place_bet(&self, user_id: String, market_id: u64, side: bool, amount: u128, contract_name: &str) -> Result<String> {
-        let url = format!("{}/api/market/bet", self.base_url);
-        let request = PlaceBetRequest { market_id, side, amount };
 
-        let identity = format!("{}@{}", user_id, contract_name);
-        let response = self.client
-            .post(&url)
-            .header("x-user", identity)
-            .json(&request)
-            .send()
-            .await?;
+    /// Turns a non-success response into the matching [`MarketApiError`] and
+    /// returns the body of a successful one.
+    async fn read_body(response: Response) -> Result<String> {
+        let status = response.status();
+        let body = response.text().await?;
+
+        match status {
+            StatusCode::OK => Ok(body),
+            StatusCode::ACCEPTED => Err(MarketApiError::Pending {
+                tx_hash: parse_tx_receipt(&body)?.tx_hash,
+            }),
+            // 422: the server's dry run refused it before submitting
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Err(MarketApiError::ContractRejected { message: body }),
+            StatusCode::FORBIDDEN => Err(MarketApiError::Forbidden { body }),
+            _ => Err(MarketApiError::ServerError { status, body }),
+        }
+    }
 
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to place bet: {}", error_text));
+    /// URL of a read route served by the contract's state indexer.
+    fn indexer_url(&self, contract_name: &str, path: &str) -> String {
+        format!("{}/v1/indexer/contract/{}/{}", self.base_url, contract_name, path)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let request_id = new_request_id();
+        let result = async {
+            let response = self
+                .send_with_retry(&request_id, Retry::Transient, || self.client.get(url))
+                .await?;
+            let body = Self::read_body(response).await?;
+            serde_json::from_str::<T>(&body).map_err(|e| MarketApiError::Deserialization(e.to_string()))
         }
+        .await;
+        result.map_err(|e| e.with_request_id(&request_id))
+    }
 
-        let tx_hash = response.text().await?;
-        Ok(tx_hash)
+    /// Posts a market action on behalf of `user_id` and parses the receipt.
+    async fn post_action<R: ApiRequest + Sync>(
+        &self,
+        user_id: &str,
+        contract_name: &str,
+        request: &R,
+    ) -> Result<TxReceipt> {
+        self.send_action(user_id, contract_name, request, None).await
     }
 
-    pub async fn resolve_market(&self, user_id: String, market_id: u64, outcome: bool, contract_name: &str) -> Result<String> {
-        let url = format!("{}/api/market/resolve", self.base_url);
-        let request = ResolveMarketRequest { market_id, outcome };
+    /// Like [`Self::post_action`], for the routes gated by the admin key.
+    async fn post_admin_action<R: ApiRequest + Sync>(
+        &self,
+        user_id: &str,
+        contract_name: &str,
+        request: &R,
+    ) -> Result<TxReceipt> {
+        self.send_action(user_id, contract_name, request, self.admin_key.as_deref())
+            .await
+    }
 
+    async fn send_action<R: ApiRequest + Sync>(
+        &self,
+        user_id: &str,
+        contract_name: &str,
+        request: &R,
+        admin_key: Option<&str>,
+    ) -> Result<TxReceipt> {
+        let url = format!("{}{}", self.base_url, R::PATH);
         let identity = format!("{}@{}", user_id, contract_name);
-        let response = self.client
-            .post(&url)
-            .header("x-user", identity)
-            .json(&request)
-            .send()
-            .await?;
-
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to resolve market: {}", error_text));
+        let request_id = new_request_id();
+        let retry = if IDEMPOTENT_PATHS.contains(&R::PATH) { Retry::Transient } else { Retry::Unprocessed };
+        let _identity_guard = self.lock_identity(&identity).await;
+
+        let result = async {
+            let response = self
+                .send_with_retry(&request_id, retry, || {
+                    let builder = self.client.post(&url).header("x-user", &identity).json(request);
+                    match admin_key {
+                        Some(key) => builder.header(ADMIN_KEY_HEADER, key),
+                        None => builder,
+                    }
+                })
+                .await?;
+            let body = Self::read_body(response).await?;
+            parse_tx_receipt(&body)
         }
+        .await;
+        result.map_err(|e| e.with_request_id(&request_id))
+    }
 
-        let tx_hash = response.text().await?;
-        Ok(tx_hash)
+    /// Follows the server's `/api/events` stream: the events of every settled
+    /// transaction matching `filter`. The stream never ends: a dropped
+    /// connection is reopened with the retry policy's backoff, resuming after
+    /// the last event received, and events delivered again are skipped.
+    pub fn subscribe_events(&self, filter: EventFilter) -> impl Stream<Item = WebhookPayload> + Send + 'static {
+        let mut url = format!("{}/api/events", self.base_url);
+        if let Some(market_id) = filter.market_id {
+            url.push_str(&format!("?market_id={}", market_id));
+        }
+        let subscription = EventSubscription {
+            client: self.stream_client.clone(),
+            url,
+            retry_policy: self.retry_policy.clone(),
+            response: None,
+            buffer: Vec::new(),
+            failures: 0,
+            last_event_id: None,
+            seen: SeenEventIds::default(),
+        };
+        futures::stream::unfold(subscription, |mut subscription| async move {
+            let payload = subscription.next().await;
+            Some((payload, subscription))
+        })
     }
+}
 
-    pub async fn claim_winnings(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<String> {
-        let url = format!("{}/api/market/claim", self.base_url);
-        let request = ClaimWinningsRequest { market_id };
+fn new_request_id() -> String {
+    Uuid::new_v4().to_string()
+}
 
-        let identity = format!("{}@{}", user_id, contract_name);
-        let response = self.client
-            .post(&url)
-            .header("x-user", identity)
-            .json(&request)
-            .send()
-            .await?;
+/// One server-sent event: its `id`, if any, and its data lines joined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseMessage {
+    pub id: Option<String>,
+    pub data: String,
+}
+
+/// Parses one frame of an event stream, without its blank line terminator.
+/// Comments and frames without data, such as keep-alives, give `None`.
+pub fn parse_sse_message(frame: &str) -> Option<SseMessage> {
+    let mut id = None;
+    let mut data: Option<String> = None;
+    for line in frame.lines() {
+        if line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "id" => id = Some(value.to_string()),
+            "data" => match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            },
+            _ => {}
+        }
+    }
+    data.map(|data| SseMessage { id, data })
+}
 
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to claim winnings: {}", error_text));
+/// The last [`SEEN_EVENT_IDS`] event ids, oldest forgotten first.
+#[derive(Default)]
+struct SeenEventIds {
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+}
+
+impl SeenEventIds {
+    /// Remembers `id`, returning whether it is new.
+    fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() == SEEN_EVENT_IDS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
         }
+        self.order.push_back(id.to_string());
+        self.ids.insert(id.to_string());
+        true
+    }
+}
 
-        let tx_hash = response.text().await?;
-        Ok(tx_hash)
+/// State of a [`MarketApiClient::subscribe_events`] stream.
+struct EventSubscription {
+    client: Client,
+    url: String,
+    retry_policy: RetryPolicy,
+    response: Option<Response>,
+    /// Bytes received after the last complete frame
+    buffer: Vec<u8>,
+    /// Connection attempts failed since data last came through
+    failures: u32,
+    last_event_id: Option<String>,
+    seen: SeenEventIds,
+}
+
+impl EventSubscription {
+    async fn next(&mut self) -> WebhookPayload {
+        loop {
+            if let Some(payload) = self.next_buffered() {
+                return payload;
+            }
+            let Some(response) = self.response.as_mut() else {
+                self.connect().await;
+                continue;
+            };
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    self.failures = 0;
+                    // Frames end with a blank line, whatever the line endings
+                    self.buffer.extend(chunk.iter().filter(|byte| **byte != b'\r'));
+                }
+                Ok(None) => self.disconnected("the server closed it"),
+                Err(e) => self.disconnected(&e.to_string()),
+            }
+        }
     }
 
-    pub async fn get_balance(&self, user_id: String, contract_name: &str) -> Result<String> {
-        let url = format!("{}/api/market/balance", self.base_url);
-        let request = GetBalanceRequest {};
+    /// The next complete, new and decodable event already received.
+    fn next_buffered(&mut self) -> Option<WebhookPayload> {
+        while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let frame: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let Some(message) = parse_sse_message(&String::from_utf8_lossy(&frame)) else {
+                continue;
+            };
+            if let Some(id) = &message.id {
+                if !self.seen.insert(id) {
+                    log::debug!("Skipped event {}, already received", id);
+                    continue;
+                }
+                self.last_event_id = Some(id.clone());
+            }
+            match serde_json::from_str::<WebhookPayload>(&message.data) {
+                Ok(payload) => return Some(payload),
+                Err(e) => log::warn!("Skipped an undecodable server event: {}", e),
+            }
+        }
+        None
+    }
 
-        let identity = format!("{}@{}", user_id, contract_name);
-        let response = self.client
-            .post(&url)
-            .header("x-user", identity)
-            .json(&request)
-            .send()
-            .await?;
+    fn disconnected(&mut self, reason: &str) {
+        log::warn!("Event stream interrupted ({}), reconnecting", reason);
+        self.response = None;
+        // A frame cut short is sent again after the last complete one
+        self.buffer.clear();
+        self.failures += 1;
+    }
 
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to get balance: {}", error_text));
+    async fn connect(&mut self) {
+        if self.failures > 0 {
+            tokio::time::sleep(self.retry_policy.delay_for(self.failures - 1)).await;
+        }
+        let mut request = self.client.get(&self.url).header(ACCEPT, "text/event-stream");
+        if let Some(id) = &self.last_event_id {
+            request = request.header(LAST_EVENT_ID_HEADER, id);
         }
+        match request.send().await {
+            Ok(response) if response.status() == StatusCode::OK => {
+                log::info!("Following the server's events from {}", self.url);
+                self.response = Some(response);
+            }
+            Ok(response) => {
+                log::warn!("Event stream refused with status {}", response.status());
+                self.failures += 1;
+            }
+            Err(e) => {
+                log::warn!("Could not open the event stream: {}", e);
+                self.failures += 1;
+            }
+        }
+    }
+}
+
+/// Operations the bot performs against the market server. Handlers depend on
+/// this trait rather than on the HTTP client so they can run against other
+/// implementations.
+#[async_trait]
+pub trait MarketApi: Send + Sync {
+    async fn get_config(&self) -> Result<ConfigResponse>;
+    async fn initialize_user(&self, user_id: String, contract_name: &str) -> Result<TxReceipt>;
+    async fn create_market(&self, user_id: String, description: String, tags: Vec<String>, contract_name: &str) -> Result<TxReceipt>;
+    /// Creates a head-to-head market, escrowing `stake` of `user_id` until `opponent` matches it.
+    async fn create_challenge(&self, user_id: String, opponent: String, stake: u128, description: String, contract_name: &str) -> Result<TxReceipt>;
+    async fn accept_challenge(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt>;
+    /// Declines, withdraws or expires a pending challenge, refunding its creator.
+    async fn cancel_challenge(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt>;
+    async fn place_bet(&self, user_id: String, market_id: u64, side: bool, amount: u128, contract_name: &str) -> Result<TxReceipt>;
+    async fn resolve_market(&self, user_id: String, market_id: u64, outcome: bool, contract_name: &str) -> Result<TxReceipt<ResolveResult>>;
+    async fn close_betting(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt>;
+    /// Voids a market left unresolved past its maximum lifetime, refunding every stake.
+    async fn expire_market(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt>;
+    async fn claim_winnings(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt<ClaimResult>>;
+    async fn get_balance(&self, user_id: String, contract_name: &str) -> Result<TxReceipt>;
+    /// Stake the user has at risk in markets that have not settled yet.
+    async fn get_open_exposure(&self, user_id: String, contract_name: &str) -> Result<TxReceipt<OpenExposure>>;
+    async fn get_market_info(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt>;
+    async fn add_comment(&self, user_id: String, market_id: u64, text: String, contract_name: &str) -> Result<TxReceipt>;
+    async fn set_admin(&self, user_id: String, new_admin: String, contract_name: &str) -> Result<TxReceipt>;
+    async fn withdraw_treasury(&self, user_id: String, to: String, amount: u128, contract_name: &str) -> Result<TxReceipt>;
+    /// Starts a new season on-chain; needs the admin key.
+    async fn reset_balances(&self, user_id: String, contract_name: &str) -> Result<TxReceipt>;
+    async fn health_check(&self) -> Result<bool>;
+    async fn list_markets(&self, filter: &MarketFilter, contract_name: &str) -> Result<Vec<MarketSummary>>;
+    async fn get_odds(&self, market_id: u64, contract_name: &str) -> Result<Odds>;
+    async fn get_market_history(&self, market_id: u64, contract_name: &str) -> Result<Vec<MarketHistoryPoint>>;
+    async fn get_leaderboard(&self, limit: u32, contract_name: &str) -> Result<Vec<(String, u128)>>;
+    async fn get_user_bets(&self, user_id: String, contract_name: &str) -> Result<Vec<UserBetInfo>>;
+    async fn get_user(&self, user_id: String, contract_name: &str) -> Result<UserInfo>;
+    async fn get_treasury(&self, contract_name: &str) -> Result<TreasuryInfo>;
+    /// Compares the bot's ledger against the indexed state; needs the admin key.
+    async fn reconcile(&self, snapshot: &ReconcileSnapshot) -> Result<ReconcileReport>;
+    /// Outcome of a transaction submitted through the server. A 404 means the
+    /// server does not know it, e.g. it restarted before the outcome.
+    async fn tx_status(&self, tx_hash: &str) -> Result<TxStatus>;
+}
+
+#[async_trait]
+impl MarketApi for MarketApiClient {
+    async fn get_config(&self) -> Result<ConfigResponse> {
+        let url = format!("{}/api/config", self.base_url);
+        self.get_json(&url).await
+    }
+
+    async fn initialize_user(&self, user_id: String, contract_name: &str) -> Result<TxReceipt> {
+        let request = InitializeRequest { idempotent: true };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn create_market(&self, user_id: String, description: String, tags: Vec<String>, contract_name: &str) -> Result<TxReceipt> {
+        let request = CreateMarketRequest { description, opens_at: None, stake_cap: None, tags, challenge: None };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn create_challenge(&self, user_id: String, opponent: String, stake: u128, description: String, contract_name: &str) -> Result<TxReceipt> {
+        let challenge = ChallengeRequest { opponent: format!("{}@{}", opponent, contract_name), stake };
+        let request = CreateMarketRequest { description, opens_at: None, stake_cap: None, tags: Vec::new(), challenge: Some(challenge) };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn accept_challenge(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        let request = AcceptChallengeRequest { market_id };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn cancel_challenge(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        let request = CancelChallengeRequest { market_id };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn place_bet(&self, user_id: String, market_id: u64, side: bool, amount: u128, contract_name: &str) -> Result<TxReceipt> {
+        let request = PlaceBetRequest { market_id, side, amount };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn resolve_market(&self, user_id: String, market_id: u64, outcome: bool, contract_name: &str) -> Result<TxReceipt<ResolveResult>> {
+        let request = ResolveMarketRequest { market_id, outcome };
+        self.post_action(&user_id, contract_name, &request).await.map(TxReceipt::decode)
+    }
+
+    async fn close_betting(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        let request = CloseBettingRequest { market_id };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn expire_market(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        let request = ExpireMarketRequest { market_id };
+        self.post_action(&user_id, contract_name, &request).await
+    }
+
+    async fn claim_winnings(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt<ClaimResult>> {
+        let request = ClaimWinningsRequest { market_id };
+        self.post_action(&user_id, contract_name, &request).await.map(TxReceipt::decode)
+    }
 
-        let balance = response.text().await?;
-        Ok(balance)
+    async fn get_balance(&self, user_id: String, contract_name: &str) -> Result<TxReceipt> {
+        let request = GetBalanceRequest {};
+        self.post_action(&user_id, contract_name, &request).await
     }
 
-    pub async fn get_market_info(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<String> {
-        let url = format!("{}/api/market/info", self.base_url);
+    async fn get_open_exposure(&self, user_id: String, contract_name: &str) -> Result<TxReceipt<OpenExposure>> {
+        let request = GetOpenExposureRequest {};
+        self.post_action(&user_id, contract_name, &request).await.map(TxReceipt::decode)
+    }
+
+    async fn get_market_info(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
         let request = GetMarketInfoRequest { market_id };
+        self.post_action(&user_id, contract_name, &request).await
+    }
 
-        let identity = format!("{}@{}", user_id, contract_name);
-        let response = self.client
-            .post(&url)
-            .header("x-user", identity)
-            .json(&request)
-            .send()
-            .await?;
+    async fn add_comment(&self, user_id: String, market_id: u64, text: String, contract_name: &str) -> Result<TxReceipt> {
+        let request = AddCommentRequest { market_id, text };
+        self.post_action(&user_id, contract_name, &request).await
+    }
 
-        if response.status() != StatusCode::OK {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Failed to get market info: {}", error_text));
-        }
+    async fn set_admin(&self, user_id: String, new_admin: String, contract_name: &str) -> Result<TxReceipt> {
+        let request = SetAdminRequest { new_admin: format!("{}@{}", new_admin, contract_name) };
+        self.post_admin_action(&user_id, contract_name, &request).await
+    }
+
+    async fn withdraw_treasury(&self, user_id: String, to: String, amount: u128, contract_name: &str) -> Result<TxReceipt> {
+        let request = WithdrawTreasuryRequest { to: format!("{}@{}", to, contract_name), amount };
+        self.post_admin_action(&user_id, contract_name, &request).await
+    }
 
-        let info = response.text().await?;
-        Ok(info)
+    async fn reset_balances(&self, user_id: String, contract_name: &str) -> Result<TxReceipt> {
+        self.post_admin_action(&user_id, contract_name, &ResetBalancesRequest {}).await
     }
 
-    pub async fn health_check(&self) -> Result<bool> {
+    async fn health_check(&self) -> Result<bool> {
         let url = format!("{}/_health", self.base_url);
-        let response = self.client
-            .get(&url)
-            .send()
-            .await?;
+        let request_id = new_request_id();
+        let response = self
+            .send_with_retry(&request_id, Retry::Transient, || self.client.get(&url))
+            .await
+            .map_err(|e| e.with_request_id(&request_id))?;
 
         Ok(response.status() == StatusCode::OK)
     }
-}
\ No newline at end of file
+
+    async fn list_markets(&self, filter: &MarketFilter, contract_name: &str) -> Result<Vec<MarketSummary>> {
+        let mut url = reqwest::Url::parse(&self.indexer_url(contract_name, "markets"))
+            .map_err(|e| MarketApiError::Transport(e.to_string()))?;
+        if let Some(status) = filter.status {
+            let status = serde_json::to_value(status).map_err(|e| MarketApiError::Deserialization(e.to_string()))?;
+            if let Some(status) = status.as_str() {
+                url.query_pairs_mut().append_pair("status", status);
+            }
+        }
+        if let Some(tag) = &filter.tag {
+            url.query_pairs_mut().append_pair("tag", tag);
+        }
+        self.get_json(url.as_str()).await
+    }
+
+    async fn get_odds(&self, market_id: u64, contract_name: &str) -> Result<Odds> {
+        let url = self.indexer_url(contract_name, &format!("market/{}/odds", market_id));
+        self.get_json(&url).await
+    }
+
+    async fn get_market_history(&self, market_id: u64, contract_name: &str) -> Result<Vec<MarketHistoryPoint>> {
+        let url = self.indexer_url(contract_name, &format!("market/{}/history", market_id));
+        self.get_json(&url).await
+    }
+
+    async fn get_leaderboard(&self, limit: u32, contract_name: &str) -> Result<Vec<(String, u128)>> {
+        let url = self.indexer_url(contract_name, &format!("leaderboard?limit={}", limit));
+        let entries: Vec<LeaderboardEntry> = self.get_json(&url).await?;
+        Ok(entries.into_iter().map(|e| (e.identity, e.balance)).collect())
+    }
+
+    async fn get_user_bets(&self, user_id: String, contract_name: &str) -> Result<Vec<UserBetInfo>> {
+        let identity = format!("{}@{}", user_id, contract_name);
+        let url = self.indexer_url(contract_name, &format!("user/{}/bets", identity));
+        self.get_json(&url).await
+    }
+
+    async fn get_user(&self, user_id: String, contract_name: &str) -> Result<UserInfo> {
+        let identity = format!("{}@{}", user_id, contract_name);
+        let url = self.indexer_url(contract_name, &format!("user/{}", identity));
+        self.get_json(&url).await
+    }
+
+    async fn get_treasury(&self, contract_name: &str) -> Result<TreasuryInfo> {
+        let url = self.indexer_url(contract_name, "treasury");
+        self.get_json(&url).await
+    }
+
+    async fn tx_status(&self, tx_hash: &str) -> Result<TxStatus> {
+        let url = format!("{}/api/tx/{}", self.base_url, tx_hash);
+        self.get_json(&url).await
+    }
+
+    async fn reconcile(&self, snapshot: &ReconcileSnapshot) -> Result<ReconcileReport> {
+        let url = format!("{}{}", self.base_url, ReconcileSnapshot::PATH);
+        let request_id = new_request_id();
+        let result = async {
+            let response = self
+                // Reconciling only compares, so it can be repeated
+                .send_with_retry(&request_id, Retry::Transient, || {
+                    let builder = self.client.post(&url).json(snapshot);
+                    match &self.admin_key {
+                        Some(key) => builder.header(ADMIN_KEY_HEADER, key),
+                        None => builder,
+                    }
+                })
+                .await?;
+            let body = Self::read_body(response).await?;
+            serde_json::from_str(&body).map_err(|e| MarketApiError::Deserialization(e.to_string()))
+        }
+        .await;
+        result.map_err(|e| e.with_request_id(&request_id))
+    }
+}
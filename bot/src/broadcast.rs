@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use teloxide::types::ChatId;
+
+use crate::db::{Broadcast, Database};
+use crate::messenger::Messenger;
+use crate::BotContext;
+
+/// Sends `broadcast` to the chats it has not reached yet and returns how many
+/// chats it reached and missed overall. Each delivery is logged as soon as it
+/// is sent, so a restart resumes without messaging a chat twice. The send
+/// queue paces the messages, behind any interactive reply.
+pub async fn deliver(bot: &Messenger, db: &Database, broadcast: &Broadcast) -> anyhow::Result<(i64, i64)> {
+    let bot = bot.bulk();
+    for chat_id in db.get_pending_deliveries(broadcast.broadcast_id).await? {
+        let sent = match bot.send_message(ChatId(chat_id), broadcast.text.clone()).await {
+            Ok(_) => true,
+            // Typically the bot was removed from the chat: retrying will not help
+            Err(e) => {
+                log::warn!("Broadcast #{} could not reach chat {}: {}", broadcast.broadcast_id, chat_id, e);
+                false
+            }
+        };
+        db.record_delivery(broadcast.broadcast_id, chat_id, sent).await?;
+    }
+    db.finish_broadcast(broadcast.broadcast_id).await
+}
+
+/// Finishes the broadcasts interrupted by a crash or restart, then tells
+/// their senders how they went.
+pub async fn resume(bot: &Messenger, ctx: &BotContext) -> anyhow::Result<()> {
+    for broadcast in ctx.db.get_unfinished_broadcasts().await? {
+        log::info!("Resuming broadcast #{}", broadcast.broadcast_id);
+        let (sent, failed) = deliver(bot, &ctx.db, &broadcast).await?;
+        bot.send_message(ChatId(broadcast.sender_id), summary(&broadcast, sent, failed, true))
+            .await?;
+    }
+    Ok(())
+}
+
+pub fn spawn_resume(bot: Messenger, ctx: Arc<BotContext>) {
+    tokio::spawn(async move {
+        if let Err(e) = resume(&bot, &ctx).await {
+            log::error!("Failed to resume broadcasts: {}", e);
+        }
+    });
+}
+
+/// `✅ Broadcast #3 sent to 4 of 5 chats (1 failed).`
+pub fn summary(broadcast: &Broadcast, sent: i64, failed: i64, resumed: bool) -> String {
+    format!(
+        "✅ Broadcast #{}{} sent to {} of {} chat{}{}.",
+        broadcast.broadcast_id,
+        if resumed { ", resumed after a restart," } else { "" },
+        sent,
+        sent + failed,
+        if sent + failed == 1 { "" } else { "s" },
+        if failed > 0 { format!(" ({} failed)", failed) } else { String::new() }
+    )
+}
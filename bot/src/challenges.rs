@@ -0,0 +1,83 @@
+use chrono::{DateTime, Utc};
+use teloxide::types::ChatId;
+
+use crate::api_client::MarketApiError;
+use crate::currency::format_amount;
+use crate::db::Challenge;
+use crate::messenger::Messenger;
+use crate::parse::{self, Amount, ParseError, Usage};
+use crate::{BotContext, HandlerResult};
+
+/// Prefix of the callback data of the accept and decline buttons.
+const CALLBACK_PREFIX: &str = "challenge:";
+
+/// The `@opponent <amount> <description>` arguments of /challenge.
+pub fn parse_args(args: &str) -> Result<(String, Amount, String), ParseError> {
+    parse::command(args, Usage::CHALLENGE, |args| {
+        Ok((args.mention()?, args.amount()?, args.text("the description")?.to_string()))
+    })
+}
+
+/// Callback data of the accept (`true`) or decline button of the challenge `bet_id`.
+pub fn callback_data(accept: bool, bet_id: i64) -> String {
+    format!("{}{}:{}", CALLBACK_PREFIX, if accept { "accept" } else { "decline" }, bet_id)
+}
+
+/// Whether a challenge button accepts it, and which bet it belongs to.
+pub fn parse_callback(data: &str) -> Option<(bool, i64)> {
+    let (answer, bet_id) = data.strip_prefix(CALLBACK_PREFIX)?.split_once(':')?;
+    let accept = match answer {
+        "accept" => true,
+        "decline" => false,
+        _ => return None,
+    };
+    Some((accept, bet_id.parse().ok()?))
+}
+
+/// Calls `challenge` off on-chain on behalf of `user_id`, refunds its creator
+/// in the ledger and tells the chat, e.g. "was declined".
+pub async fn cancel(bot: &Messenger, ctx: &BotContext, challenge: &Challenge, user_id: i64, reason: &str) -> HandlerResult {
+    let receipt = ctx
+        .api_client
+        .cancel_challenge(user_id.to_string(), challenge.on_chain_id(), &ctx.contract_name)
+        .await?;
+    if !ctx.db.cancel_challenge(challenge.bet_id).await? {
+        return Ok(());
+    }
+    log::info!("Challenge #{} {} with tx {}", challenge.bet_id, reason, receipt.tx_hash);
+    if let Some(chat_id) = challenge.chat_id {
+        let currency = ctx.db.get_currency(chat_id).await?;
+        bot.send_message(
+            ChatId(chat_id),
+            format!(
+                "🚫 Challenge #{} {}: {}\n{} went back to its creator.\nTransaction: {}",
+                challenge.bet_id,
+                reason,
+                challenge.description,
+                format_amount(&currency, challenge.stake.max(0) as u128),
+                receipt.tx_hash
+            ),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Cancels the challenges nobody accepted in time, on behalf of their
+/// creators. A challenge the contract refuses to cancel, e.g. one already
+/// cancelled on-chain, is only cancelled in the ledger.
+pub async fn cancel_expired(bot: &Messenger, ctx: &BotContext, now: DateTime<Utc>) -> HandlerResult {
+    for challenge in ctx.db.get_expired_challenges(&now.to_rfc3339()).await? {
+        let Err(e) = cancel(bot, ctx, &challenge, challenge.creator_id, "expired unanswered").await else {
+            continue;
+        };
+        match e.downcast_ref::<MarketApiError>().map(MarketApiError::kind) {
+            Some(MarketApiError::ContractRejected { .. }) => {
+                log::warn!("Could not cancel challenge #{} on-chain: {}", challenge.bet_id, e);
+                ctx.db.cancel_challenge(challenge.bet_id).await?;
+            }
+            _ => log::error!("Failed to expire challenge #{}: {}", challenge.bet_id, e),
+        }
+    }
+    Ok(())
+}
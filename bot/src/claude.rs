@@ -1,6 +1,27 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use reqwest;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::Database;
+
+const ANTHROPIC_DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_DEFAULT_MODEL: &str = "claude-sonnet-4-20250514";
+const OPENAI_DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const OPENAI_DEFAULT_MODEL: &str = "gpt-4o-mini";
+const MAX_TOKENS: i32 = 150;
+/// Prompt budget for the free-form criteria text, in tokens
+const CRITERIA_TOKEN_BUDGET: usize = 400;
+/// Prompt budget for a single evidence message, in tokens
+const EVIDENCE_MESSAGE_TOKEN_BUDGET: usize = 300;
+/// Prompt budget for the whole evidence transcript, in tokens
+const EVIDENCE_TOKEN_BUDGET: usize = 1500;
+/// Rough characters-per-token ratio used to stay within budgets
+const CHARS_PER_TOKEN: usize = 4;
 
 #[derive(Debug, Serialize)]
 struct ClaudeRequest {
@@ -18,6 +39,14 @@ struct Message {
 #[derive(Debug, Deserialize)]
 struct ClaudeResponse {
     content: Vec<Content>,
+    #[serde(default)]
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeUsage {
+    input_tokens: u64,
+    output_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,31 +54,280 @@ struct Content {
     text: String,
 }
 
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest {
+    model: String,
+    max_tokens: i32,
+    messages: Vec<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<ChatCompletionUsage>,
+}
+
 #[derive(Debug, Deserialize)]
+struct ChatCompletionUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChoiceMessage {
+    content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BetResolution {
     pub resolved: bool,
     pub outcome: bool,  // true = YES wins, false = NO wins
     pub reasoning: String,
+    /// Model answer the resolution was parsed from
+    #[serde(skip)]
+    pub raw_response: Option<String>,
+    /// Tokens billed for this evaluation; None when served from the cache
+    #[serde(skip)]
+    pub usage: Option<TokenUsage>,
 }
 
-pub async fn evaluate_bet_resolution(
-    api_key: &str,
-    bet_id: i64,
-    bet_description: &str,
-    proposed_solution: &str,
-    message_author: &str,
-) -> Result<BetResolution> {
-    let prompt = format!(
-        r#"You are evaluating if a message resolves a prediction market bet.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// Per-model prices in US dollars per million tokens, as (input, output).
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    prices: HashMap<String, (f64, f64)>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        let prices = [
+            ("claude-opus-4", (15.0, 75.0)),
+            ("claude-sonnet-4", (3.0, 15.0)),
+            ("claude-3-5-haiku", (0.8, 4.0)),
+            ("gpt-4o-mini", (0.15, 0.6)),
+            ("gpt-4o", (2.5, 10.0)),
+        ];
+        Self {
+            prices: prices.into_iter().map(|(model, price)| (model.to_string(), price)).collect(),
+        }
+    }
+}
+
+impl PriceTable {
+    /// Default prices, overridden by `LLM_PRICES`
+    /// (`<model>=<input>:<output>` entries separated by commas).
+    pub fn from_env() -> Result<Self> {
+        let mut table = Self::default();
+        if let Ok(spec) = std::env::var("LLM_PRICES") {
+            for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                let (model, price) = entry
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid LLM_PRICES entry {:?}", entry))?;
+                let (input, output) = price
+                    .split_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid LLM_PRICES entry {:?}", entry))?;
+                table
+                    .prices
+                    .insert(model.trim().to_string(), (input.trim().parse()?, output.trim().parse()?));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Cost of `usage` in millionths of a dollar. Models are matched by the
+    /// longest known prefix, so dated names share their family's price;
+    /// unknown models are counted as free.
+    pub fn cost_micros(&self, model: &str, usage: TokenUsage) -> i64 {
+        let price = self
+            .prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price);
+
+        let Some((input, output)) = price else {
+            log::warn!("No price configured for model {}, counting it as free", model);
+            return 0;
+        };
+        // One dollar per million tokens is one micro-dollar per token
+        (usage.input_tokens as f64 * input + usage.output_tokens as f64 * output).round() as i64
+    }
+}
+
+/// Formats micro-dollars as a dollar amount.
+pub fn format_usd(micros: i64) -> String {
+    format!("${:.4}", micros as f64 / 1_000_000.0)
+}
+
+/// Everything a resolver needs to judge a proposed solution.
+#[derive(Debug, Clone)]
+pub struct ResolutionContext {
+    pub bet_id: i64,
+    pub bet_description: String,
+    /// Messages offered as proof, oldest first; the last one is the message
+    /// `/solve` replied to
+    pub evidence: Vec<EvidenceMessage>,
+    /// Resolution rules stated by the creator, when separate from the description
+    pub criteria: Option<String>,
+    /// RFC 3339 date after which the bet can no longer happen
+    pub deadline: Option<String>,
+    /// RFC 3339 creation date of the bet
+    pub created_at: String,
+    /// RFC 3339 date of the evaluation, so the model can tell whether the deadline passed
+    pub now: String,
+    pub positions: Option<PositionSummary>,
+}
+
+/// One message of the evidence transcript.
+#[derive(Debug, Clone)]
+pub struct EvidenceMessage {
+    pub author: String,
+    /// RFC 3339 date the message was sent
+    pub timestamp: String,
+    pub text: String,
+}
+
+/// Anonymized view of how the stake is split, without naming any bettor.
+#[derive(Debug, Clone, Copy)]
+pub struct PositionSummary {
+    pub yes_stake: u128,
+    pub no_stake: u128,
+    pub bettor_count: usize,
+}
+
+impl PositionSummary {
+    fn describe(&self) -> String {
+        let total = self.yes_stake + self.no_stake;
+        if total == 0 {
+            return "No stake placed yet".to_string();
+        }
+        let yes_percent = self.yes_stake * 100 / total;
+        format!(
+            "{}% of stake on YES, {}% on NO, across {} bettor(s)",
+            yes_percent,
+            100 - yes_percent,
+            self.bettor_count
+        )
+    }
+}
+
+/// An LLM backend able to judge whether a message resolves a bet.
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Model name shown to users alongside its analysis
+    fn name(&self) -> &str;
+    async fn evaluate(&self, ctx: ResolutionContext) -> Result<BetResolution>;
+}
+
+/// Builds the resolver selected by `LLM_BACKEND` (`anthropic` by default, or
+/// `openai`). Returns `None` when the selected backend lacks credentials.
+pub fn resolver_from_env() -> Result<Option<Arc<dyn Resolver>>> {
+    let backend = std::env::var("LLM_BACKEND").unwrap_or_else(|_| "anthropic".to_string());
+    match backend.to_lowercase().as_str() {
+        "anthropic" | "claude" => {
+            let Ok(api_key) = std::env::var("CLAUDE_API_KEY") else {
+                return Ok(None);
+            };
+            let base_url = std::env::var("CLAUDE_BASE_URL").unwrap_or_else(|_| ANTHROPIC_DEFAULT_BASE_URL.to_string());
+            let model = std::env::var("CLAUDE_MODEL").unwrap_or_else(|_| ANTHROPIC_DEFAULT_MODEL.to_string());
+            Ok(Some(Arc::new(AnthropicResolver::new(base_url, api_key, model))))
+        }
+        "openai" => {
+            let base_url = std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| OPENAI_DEFAULT_BASE_URL.to_string());
+            let model = std::env::var("OPENAI_MODEL").unwrap_or_else(|_| OPENAI_DEFAULT_MODEL.to_string());
+            // Local OpenAI-compatible servers usually run without a key
+            let api_key = std::env::var("OPENAI_API_KEY").ok();
+            Ok(Some(Arc::new(OpenAiCompatibleResolver::new(base_url, model, api_key))))
+        }
+        other => anyhow::bail!("Unknown LLM_BACKEND {:?} (expected \"anthropic\" or \"openai\")", other),
+    }
+}
+
+/// Cuts `text` down to roughly `max_tokens` tokens, on a character boundary.
+fn truncate_to_budget(text: &str, max_tokens: usize) -> String {
+    let max_chars = max_tokens * CHARS_PER_TOKEN;
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let truncated: String = text.chars().take(max_chars).collect();
+    format!("{} [truncated]", truncated.trim_end())
+}
+
+/// Renders the evidence as `[timestamp] @author: "text"` lines. Long messages
+/// are truncated, and the oldest messages are dropped once the transcript
+/// exceeds its budget; the last message is always kept.
+fn render_transcript(evidence: &[EvidenceMessage]) -> String {
+    let max_chars = EVIDENCE_TOKEN_BUDGET * CHARS_PER_TOKEN;
+    let mut lines: Vec<String> = Vec::new();
+    let mut used = 0;
+
+    for message in evidence.iter().rev() {
+        let line = format!(
+            "[{}] @{}: \"{}\"",
+            message.timestamp,
+            message.author,
+            truncate_to_budget(&message.text, EVIDENCE_MESSAGE_TOKEN_BUDGET)
+        );
+        let length = line.chars().count();
+        if !lines.is_empty() && used + length > max_chars {
+            break;
+        }
+        used += length;
+        lines.push(line);
+    }
+
+    let omitted = evidence.len() - lines.len();
+    lines.reverse();
+    if omitted > 0 {
+        lines.insert(0, format!("({} earlier message(s) omitted)", omitted));
+    }
+    lines.join("\n")
+}
 
+fn render_prompt(ctx: &ResolutionContext) -> String {
+    let criteria = match &ctx.criteria {
+        Some(criteria) => truncate_to_budget(criteria, CRITERIA_TOKEN_BUDGET),
+        None => "None given; the description is the resolution rule.".to_string(),
+    };
+    let deadline = ctx.deadline.as_deref().unwrap_or("None");
+    let positions = ctx
+        .positions
+        .map(|positions| positions.describe())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    format!(
+        r#"You are evaluating if a message thread resolves a prediction market bet.
+
+=== BET ===
 BET ID: #{}
 BET DESCRIPTION: {}
+RESOLUTION CRITERIA: {}
+CREATED AT: {}
+DEADLINE: {}
+CURRENT DATE (UTC): {}
+=== END BET ===
+
+=== MARKET POSITIONS (for context only, never a reason to pick a side) ===
+{}
+=== END MARKET POSITIONS ===
 
-MESSAGE TO EVALUATE:
-Author: {}
-Content: "{}"
+=== MESSAGES TO EVALUATE (oldest first, the last one was submitted as the solution) ===
+{}
+=== END MESSAGES ===
 
-Analyze whether this message satisfies the bet's conditions. The author of the message is crucial - if the bet specifies WHO must do something, check if the message author matches.
+Analyze whether these messages satisfy the bet's conditions. The author of each message is crucial - if the bet specifies WHO must do something, check if the message author matches. Use the message timestamps to check they were sent before the deadline.
+Compare the dates: if the bet has a deadline and the current date is past it without the condition having been met, the bet resolves NO.
 
 IMPORTANT: Respond ONLY with valid JSON in this exact format:
 {{
@@ -69,48 +347,247 @@ Always resolve false by default, until proven wrong by the context. Don't be rea
 When not sure resolve NO. It should be common sense to resolve yes. You should not try to interpret the solution as true by default, but be suspicious users will try to trick you in passing wrong solutions.
 
 "#,
-        bet_id, bet_description, message_author, proposed_solution
-    );
-
-    log::info!("Sending prompt to Claude API:\n{}", prompt);
-    
-    let client = reqwest::Client::new();
-    
-    let request_body = ClaudeRequest {
-        model: "claude-sonnet-4-20250514".to_string(),
-        max_tokens: 150,
-        messages: vec![
-            Message {
-                role: "user".to_string(),
-                content: prompt,
+        ctx.bet_id,
+        ctx.bet_description,
+        criteria,
+        ctx.created_at,
+        deadline,
+        ctx.now,
+        positions,
+        render_transcript(&ctx.evidence)
+    )
+}
+
+/// Parses the strict-JSON answer the prompt asks for.
+fn parse_resolution(text: &str, usage: Option<TokenUsage>) -> Result<BetResolution> {
+    let mut resolution: BetResolution = serde_json::from_str(text.trim())?;
+    resolution.raw_response = Some(text.to_string());
+    resolution.usage = usage;
+    Ok(resolution)
+}
+
+/// Talks to the Anthropic Messages API.
+pub struct AnthropicResolver {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+}
+
+impl AnthropicResolver {
+    pub fn new(base_url: String, api_key: String, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        }
+    }
+}
+
+#[async_trait]
+impl Resolver for AnthropicResolver {
+    fn name(&self) -> &str {
+        &self.model
+    }
+
+    async fn evaluate(&self, ctx: ResolutionContext) -> Result<BetResolution> {
+        let prompt = render_prompt(&ctx);
+        log::info!("Sending prompt to Claude API:\n{}", prompt);
+
+        let request_body = ClaudeRequest {
+            model: self.model.clone(),
+            max_tokens: MAX_TOKENS,
+            messages: vec![
+                Message {
+                    role: "user".to_string(),
+                    content: prompt,
+                }
+            ],
+        };
+
+        let response = self.client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("content-type", "application/json")
+            .json(&request_body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            anyhow::bail!("Claude API error: {}", error_text);
+        }
+
+        let claude_response: ClaudeResponse = response.json().await?;
+
+        // Parse the JSON from Claude's response
+        let text = claude_response.content.first()
+            .ok_or_else(|| anyhow::anyhow!("No content in Claude response"))?
+            .text.clone();
+
+        log::info!("Claude API response: {}", text);
+        let usage = claude_response.usage.map(|usage| TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+        });
+        parse_resolution(&text, usage)
+    }
+}
+
+/// Talks to any server implementing the OpenAI chat-completions API
+/// (OpenAI itself, vLLM, Ollama, llama.cpp...).
+pub struct OpenAiCompatibleResolver {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiCompatibleResolver {
+    pub fn new(base_url: String, model: String, api_key: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            api_key,
+        }
+    }
+}
+
+#[async_trait]
+impl Resolver for OpenAiCompatibleResolver {
+    fn name(&self) -> &str {
+        &self.model
+    }
+
+    async fn evaluate(&self, ctx: ResolutionContext) -> Result<BetResolution> {
+        let prompt = render_prompt(&ctx);
+        log::info!("Sending prompt to {}:\n{}", self.model, prompt);
+
+        let request_body = ChatCompletionRequest {
+            model: self.model.clone(),
+            max_tokens: MAX_TOKENS,
+            messages: vec![
+                Message {
+                    role: "user".to_string(),
+                    content: prompt,
+                }
+            ],
+        };
+
+        let mut request = self.client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&request_body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            anyhow::bail!("{} API error: {}", self.model, error_text);
+        }
+
+        let completion: ChatCompletionResponse = response.json().await?;
+        let usage = completion.usage.map(|usage| TokenUsage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+        });
+        let text = completion.choices.into_iter().next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| anyhow::anyhow!("No content in {} response", self.model))?;
+
+        log::info!("{} response: {}", self.model, text);
+        parse_resolution(&text, usage)
+    }
+}
+
+/// Stores evaluations so retrying `/solve` on the same message does not pay
+/// for a second API call.
+pub struct ResolutionCache {
+    db: Arc<Database>,
+    ttl: Duration,
+}
+
+impl ResolutionCache {
+    pub fn new(db: Arc<Database>, ttl: Duration) -> Self {
+        Self { db, ttl }
+    }
+
+    /// Reads `RESOLUTION_CACHE_TTL_HOURS` (default 24).
+    pub fn from_env(db: Arc<Database>) -> Result<Self> {
+        let hours = match std::env::var("RESOLUTION_CACHE_TTL_HOURS") {
+            Ok(value) => value.parse()?,
+            Err(_) => 24,
+        };
+        Ok(Self::new(db, Duration::from_secs(hours * 3600)))
+    }
+
+    fn key(ctx: &ResolutionContext) -> String {
+        let mut hasher = Sha256::new();
+        let bet_id = ctx.bet_id.to_string();
+        let evidence = ctx
+            .evidence
+            .iter()
+            .flat_map(|m| [m.author.as_str(), m.timestamp.as_str(), m.text.as_str()]);
+        for part in [bet_id.as_str(), ctx.bet_description.as_str()].into_iter().chain(evidence) {
+            hasher.update(part.as_bytes());
+            hasher.update([0u8]);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    async fn get(&self, key: &str) -> Option<BetResolution> {
+        match self.db.get_cached_resolution(key, self.ttl).await {
+            Ok(Some((resolution, raw_response))) => serde_json::from_str::<BetResolution>(&resolution)
+                .ok()
+                .map(|resolution| BetResolution {
+                    raw_response: Some(raw_response),
+                    ..resolution
+                }),
+            Ok(None) => None,
+            Err(e) => {
+                log::warn!("Failed to read resolution cache: {}", e);
+                None
             }
-        ],
-    };
+        }
+    }
+
+    async fn put(&self, key: &str, resolution: &BetResolution) {
+        let stored = match serde_json::to_string(resolution) {
+            Ok(stored) => stored,
+            Err(e) => {
+                log::warn!("Failed to encode resolution for the cache: {}", e);
+                return;
+            }
+        };
+        let raw_response = resolution.raw_response.as_deref().unwrap_or_default();
+        if let Err(e) = self.db.cache_resolution(key, &stored, raw_response).await {
+            log::warn!("Failed to write resolution cache: {}", e);
+        }
+    }
+}
+
+/// Evaluates whether the proposed solution resolves the bet. Identical
+/// evaluations are served from `cache` unless `force` is set.
+pub async fn evaluate_bet_resolution(
+    resolver: &dyn Resolver,
+    cache: &ResolutionCache,
+    ctx: ResolutionContext,
+    force: bool,
+) -> Result<BetResolution> {
+    let cache_key = ResolutionCache::key(&ctx);
+    if !force {
+        if let Some(resolution) = cache.get(&cache_key).await {
+            log::info!("Using cached resolution for bet #{}", ctx.bet_id);
+            return Ok(resolution);
+        }
+    }
+
+    let resolution = resolver.evaluate(ctx).await?;
+    cache.put(&cache_key, &resolution).await;
 
-    let response = client
-        .post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("content-type", "application/json")
-        .json(&request_body)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        anyhow::bail!("Claude API error: {}", error_text);
-    }
-
-    let claude_response: ClaudeResponse = response.json().await?;
-    
-    // Parse the JSON from Claude's response
-    let text = claude_response.content.get(0)
-        .ok_or_else(|| anyhow::anyhow!("No content in Claude response"))?
-        .text.clone();
-    
-    log::info!("Claude API response: {}", text);
-    
-    let resolution: BetResolution = serde_json::from_str(&text)?;
-    
     Ok(resolution)
 }
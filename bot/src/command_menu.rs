@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use teloxide::types::{BotCommand, BotCommandScope, ChatId, Recipient};
+use teloxide::utils::command::BotCommands;
+use teloxide::RequestError;
+
+use crate::messenger::Messenger;
+use crate::Command;
+
+/// Commands only operators can run. They stay out of /help and only show in
+/// the menu of each operator's private chat with the bot.
+pub const OPERATOR_COMMANDS: [(&str, &str); 8] = [
+    ("setadmin", "Make a user the contract admin: /setadmin [user_id]"),
+    ("withdraw", "Send treasury funds to a user: /withdraw <amount> [user_id]"),
+    ("adjust", "Correct a local balance: /adjust @user <+/-amount> <reason>"),
+    ("reconcile", "Compare the local ledger with the chain"),
+    ("broadcast", "Message every chat with an open bet: /broadcast [dry-run] <text>"),
+    ("season", "End the season, or list past winners: /season <end|history>"),
+    ("commands", "Register the command menu again: /commands refresh"),
+    ("reset", "Reset the entire database"),
+];
+
+/// The commands listed by /help, as Telegram's menu names them.
+pub fn public_commands() -> Vec<BotCommand> {
+    Command::bot_commands()
+        .into_iter()
+        .map(|command| BotCommand::new(command.command.trim_start_matches('/'), command.description))
+        .collect()
+}
+
+/// What the "/" menu shows, by scope: the public commands everywhere, and
+/// those plus the operator commands in the private chat of each operator,
+/// whose chat id is their user id. Telegram shows the most specific scope
+/// only, so the operators' lists repeat the public commands.
+pub fn registrations(operators: &HashSet<i64>) -> Vec<(BotCommandScope, Vec<BotCommand>)> {
+    let public = public_commands();
+    let mut operator_menu = public.clone();
+    operator_menu.extend(OPERATOR_COMMANDS.iter().map(|(command, description)| BotCommand::new(*command, *description)));
+
+    let mut operators: Vec<i64> = operators.iter().copied().collect();
+    operators.sort();
+    let mut registrations = vec![(BotCommandScope::Default, public)];
+    registrations.extend(operators.into_iter().map(|operator| {
+        let scope = BotCommandScope::Chat { chat_id: Recipient::Id(ChatId(operator)) };
+        (scope, operator_menu.clone())
+    }));
+    registrations
+}
+
+/// Registers every scope of [`registrations`] and returns how many there
+/// were. Operators no longer configured keep their menu until Telegram
+/// forgets it, but their commands are refused all the same.
+pub async fn register(bot: &Messenger, operators: &HashSet<i64>) -> Result<usize, RequestError> {
+    let registrations = registrations(operators);
+    let count = registrations.len();
+    for (scope, commands) in registrations {
+        bot.set_my_commands(scope, commands).await?;
+    }
+    Ok(count)
+}
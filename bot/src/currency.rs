@@ -0,0 +1,72 @@
+/// Longest currency name a chat can pick.
+pub const MAX_CURRENCY_NAME_CHARS: usize = 24;
+
+/// Longest currency emoji, in chars: flags and skin tones take several.
+const MAX_CURRENCY_EMOJI_CHARS: usize = 8;
+
+/// What a chat calls its coins, e.g. 💎 "aura points". Every amount shown in
+/// the chat goes through [`format_amount`] with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Currency {
+    pub name: String,
+    /// Shown before amounts; may be empty
+    pub emoji: String,
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Self {
+            name: "coins".to_string(),
+            emoji: "🪙".to_string(),
+        }
+    }
+}
+
+impl Currency {
+    /// Parses the arguments of `/currency`: `[emoji] <name>`.
+    pub fn parse(args: &str) -> Option<Self> {
+        let args = args.trim();
+        let (emoji, name) = match args.split_once(char::is_whitespace) {
+            Some((first, rest)) if !first.chars().any(char::is_alphanumeric) => (first, rest.trim()),
+            _ => ("", args),
+        };
+        let valid_name = (1..=MAX_CURRENCY_NAME_CHARS).contains(&name.chars().count()) && !name.contains('\n');
+        let valid_emoji = emoji.chars().count() <= MAX_CURRENCY_EMOJI_CHARS;
+        (valid_name && valid_emoji).then(|| Currency {
+            name: name.to_string(),
+            emoji: emoji.to_string(),
+        })
+    }
+}
+
+/// Digits grouped by thousands, e.g. `10,000`.
+pub fn group_thousands(amount: u128) -> String {
+    let digits = amount.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// An amount in the chat's currency, e.g. `🪙 10,000 coins`.
+pub fn format_amount(currency: &Currency, amount: u128) -> String {
+    with_currency(currency, group_thousands(amount))
+}
+
+/// A gain or loss with its sign, e.g. `🪙 +1,200 coins` or `🪙 -300 coins`.
+pub fn format_signed(currency: &Currency, amount: i64) -> String {
+    let sign = if amount < 0 { "-" } else { "+" };
+    with_currency(currency, format!("{}{}", sign, group_thousands(amount.unsigned_abs() as u128)))
+}
+
+fn with_currency(currency: &Currency, number: String) -> String {
+    if currency.emoji.is_empty() {
+        format!("{} {}", number, currency.name)
+    } else {
+        format!("{} {} {}", currency.emoji, number, currency.name)
+    }
+}
@@ -1,6 +1,14 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
 use sqlx::{FromRow, SqlitePool};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono_tz::Tz;
+
+use crate::currency::Currency;
+use crate::timezone;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
@@ -14,9 +22,65 @@ pub struct User {
 pub struct Bet {
     pub bet_id: i64,
     pub creator_id: i64,
+    pub chat_id: Option<i64>,
     pub description: String,
     pub created_at: String,
     pub status: String,
+    /// RFC 3339 date after which the bet can only resolve NO
+    pub deadline: Option<String>,
+    /// State epoch of the contract the market was created on, NULL until
+    /// the bot first learned it
+    pub epoch: Option<i64>,
+    /// Id of the market on-chain; NULL for bets created before the server
+    /// reported it that no market on-chain was matched to yet, see
+    /// [`crate::market_ids::backfill`], whose market is assumed to have
+    /// the bet's id
+    pub market_id: Option<i64>,
+}
+
+impl Bet {
+    /// The id of the bet's market on-chain.
+    pub fn on_chain_id(&self) -> u64 {
+        self.market_id.unwrap_or(self.bet_id) as u64
+    }
+}
+
+/// An open bet with a deadline, as seen by the deadline scheduler.
+#[derive(Debug, Clone, FromRow)]
+pub struct DeadlineBet {
+    pub bet_id: i64,
+    pub creator_id: i64,
+    pub chat_id: Option<i64>,
+    pub description: String,
+    pub deadline: String,
+    pub deadline_handled: i64,
+    pub market_id: Option<i64>,
+}
+
+/// How far the deadline scheduler took a bet. Persisted so a restart
+/// resumes where the previous run stopped instead of repeating a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeadlineStage {
+    Pending = 0,
+    /// Betting was closed on-chain and announced
+    BettingClosed = 1,
+    /// The grace period ended and the chat was prompted or the bet expired
+    Handled = 2,
+}
+
+impl DeadlineBet {
+    /// The id of the bet's market on-chain, see [`Bet::on_chain_id`].
+    pub fn on_chain_id(&self) -> u64 {
+        self.market_id.unwrap_or(self.bet_id) as u64
+    }
+
+    pub fn stage(&self) -> DeadlineStage {
+        match self.deadline_handled {
+            0 => DeadlineStage::Pending,
+            1 => DeadlineStage::BettingClosed,
+            _ => DeadlineStage::Handled,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -29,6 +93,139 @@ pub struct Wager {
     pub created_at: String,
 }
 
+/// The odds a bettor last heard of for a market they bet on.
+#[derive(Debug, Clone, FromRow)]
+pub struct OddsWatch {
+    pub user_id: i64,
+    /// Implied probability of YES, in basis points
+    pub yes_bps: i64,
+    /// When the last alert was sent, none before the first
+    pub notified_at: Option<String>,
+}
+
+/// The bot message announcing a bet, edited as wagers come in.
+#[derive(Debug, Clone, FromRow)]
+pub struct LiveAnnouncement {
+    pub chat_id: i64,
+    pub message_id: i64,
+    /// The announcement without the pools
+    pub text: String,
+}
+
+/// A Telegram poll a market was created from.
+#[derive(Debug, Clone, FromRow)]
+pub struct PollMarket {
+    pub bet_id: i64,
+    pub chat_id: i64,
+    /// Index of the poll option meaning YES
+    pub yes_option: i64,
+    /// The poll closed and its result was offered as the resolution
+    pub result_offered: bool,
+}
+
+/// LLM spend of a chat over one calendar month.
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct LlmUsage {
+    pub evaluations: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    /// Cost in millionths of a US dollar
+    pub cost_micros: i64,
+}
+
+/// Net profit of a user over a leaderboard window.
+#[derive(Debug, Clone, FromRow)]
+pub struct ProfitEntry {
+    pub user_id: i64,
+    pub username: Option<String>,
+    /// Payouts settled in the window minus stakes placed in it
+    pub profit: i64,
+}
+
+/// Stake a user still has on open bets.
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct OpenExposure {
+    pub open_bets: i64,
+    pub at_risk: i64,
+}
+
+/// Local pools of an open bet, summed from its wagers.
+#[derive(Debug, Clone, FromRow)]
+pub struct OpenPool {
+    /// Id of the bet's market on-chain
+    pub market_id: i64,
+    pub yes_pool: i64,
+    pub no_pool: i64,
+}
+
+/// An operator message sent to every active chat.
+#[derive(Debug, Clone, FromRow)]
+pub struct Broadcast {
+    pub broadcast_id: i64,
+    pub sender_id: i64,
+    pub text: String,
+}
+
+/// An action sent to the market server on behalf of a user, logged before it
+/// is sent so a restart can finish what a crash interrupted. Its status goes
+/// `pending` (sent, no answer yet), `submitted` (the server gave a hash but
+/// no outcome) and `applied` (mirrored locally, reply not sent yet), then
+/// ends as `done`, `failed` or `interrupted` (its outcome cannot be known).
+#[derive(Debug, Clone, FromRow)]
+pub struct Operation {
+    pub operation_id: i64,
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub action: String,
+    /// Parameters of the action, as JSON
+    pub params: String,
+    pub tx_hash: Option<String>,
+    pub status: String,
+}
+
+/// A place on the final leaderboard of an ended season.
+#[derive(Debug, Clone, FromRow)]
+pub struct SeasonStanding {
+    pub season_id: i64,
+    pub ended_at: String,
+    pub rank: i64,
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub balance: i64,
+}
+
+/// A head-to-head bet: its creator backs YES and only the named opponent
+/// may match the stake on NO.
+#[derive(Debug, Clone, FromRow)]
+pub struct Challenge {
+    pub bet_id: i64,
+    pub creator_id: i64,
+    pub opponent_id: i64,
+    pub chat_id: Option<i64>,
+    pub description: String,
+    pub stake: i64,
+    /// RFC 3339 instant after which the opponent can no longer accept
+    pub expires_at: String,
+    /// `pending`, `accepted` or `cancelled`
+    pub status: String,
+    pub market_id: Option<i64>,
+}
+
+impl Challenge {
+    /// The id of the challenge's market on-chain, see [`Bet::on_chain_id`].
+    pub fn on_chain_id(&self) -> u64 {
+        self.market_id.unwrap_or(self.bet_id) as u64
+    }
+}
+
+/// What a user staked and got back over all local wagers, archived included.
+#[derive(Debug, Clone, Default, FromRow)]
+pub struct LifetimeTotals {
+    pub wagered: i64,
+    /// Parimutuel payouts of the bets they won
+    pub won: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Solution {
     pub solution_id: i64,
@@ -38,19 +235,163 @@ pub struct Solution {
     pub created_at: String,
 }
 
+/// Connection and pragma settings for the SQLite pool.
+///
+/// Defaults are tuned for a single bot process handling bursts of concurrent
+/// commands; every field can be overridden through the environment.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub max_connections: u32,
+    pub busy_timeout: Duration,
+    pub journal_mode: SqliteJournalMode,
+    pub synchronous: SqliteSynchronous,
+    pub foreign_keys: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 8,
+            busy_timeout: Duration::from_secs(5),
+            journal_mode: SqliteJournalMode::Wal,
+            synchronous: SqliteSynchronous::Normal,
+            foreign_keys: true,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Reads `DB_MAX_CONNECTIONS`, `DB_BUSY_TIMEOUT_MS`, `DB_JOURNAL_MODE`,
+    /// `DB_SYNCHRONOUS` and `DB_FOREIGN_KEYS`, falling back to the defaults.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+
+        if let Ok(value) = std::env::var("DB_MAX_CONNECTIONS") {
+            config.max_connections = value.parse()?;
+        }
+        if let Ok(value) = std::env::var("DB_BUSY_TIMEOUT_MS") {
+            config.busy_timeout = Duration::from_millis(value.parse()?);
+        }
+        if let Ok(value) = std::env::var("DB_JOURNAL_MODE") {
+            config.journal_mode = SqliteJournalMode::from_str(&value)?;
+        }
+        if let Ok(value) = std::env::var("DB_SYNCHRONOUS") {
+            config.synchronous = SqliteSynchronous::from_str(&value)?;
+        }
+        if let Ok(value) = std::env::var("DB_FOREIGN_KEYS") {
+            config.foreign_keys = matches!(value.to_lowercase().as_str(), "1" | "true" | "on");
+        }
+
+        Ok(config)
+    }
+}
+
+/// How long resolved bets stay in the live tables before being archived,
+/// and group messages in the buffer `/solve` draws context from.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub retention_days: i64,
+    pub message_retention_hours: i64,
+    pub interval: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            retention_days: 90,
+            message_retention_hours: 24,
+            interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Reads `RETENTION_DAYS`, `MESSAGE_RETENTION_HOURS` and
+    /// `CLEANUP_INTERVAL_HOURS`, falling back to the defaults.
+    pub fn from_env() -> Result<Self> {
+        let mut policy = Self::default();
+
+        if let Ok(value) = std::env::var("RETENTION_DAYS") {
+            policy.retention_days = value.parse()?;
+        }
+        if let Ok(value) = std::env::var("MESSAGE_RETENTION_HOURS") {
+            policy.message_retention_hours = value.parse()?;
+        }
+        if let Ok(value) = std::env::var("CLEANUP_INTERVAL_HOURS") {
+            let hours = value.parse::<u64>()?;
+            // The job's interval timer cannot tick every zero hours
+            if hours == 0 {
+                anyhow::bail!("CLEANUP_INTERVAL_HOURS must be at least 1");
+            }
+            policy.interval = Duration::from_secs(hours * 60 * 60);
+        }
+
+        Ok(policy)
+    }
+}
+
+/// Every table with a `chat_id` column, rewritten when a chat changes id.
+pub const CHAT_TABLES: [&str; 10] = [
+    "bets",
+    "bets_archive",
+    "llm_usage",
+    "chat_budgets",
+    "chat_settings",
+    "bet_announcements",
+    "live_announcements",
+    "poll_markets",
+    "broadcast_deliveries",
+    "operations",
+];
+
+/// What identifies a row of a [`CHAT_TABLES`] table within its chat, as a
+/// condition matching a row of `kept` to it, for the tables where a chat
+/// holds at most one row per key.
+fn chat_key(table: &str) -> Option<&'static str> {
+    match table {
+        "chat_budgets" | "chat_settings" => Some(""),
+        "bet_announcements" => Some(" AND kept.message_id = bet_announcements.message_id"),
+        "broadcast_deliveries" => Some(" AND kept.broadcast_id = broadcast_deliveries.broadcast_id"),
+        _ => None,
+    }
+}
+
 pub struct Database {
     pool: SqlitePool,
 }
 
+/// Selects [`Challenge`]s, joined with their bets.
+const CHALLENGE_SELECT: &str = r#"
+    SELECT c.bet_id, b.creator_id, c.opponent_id, b.chat_id, b.description, c.stake, c.expires_at, c.status, b.market_id
+    FROM challenges c JOIN bets b ON b.bet_id = c.bet_id
+"#;
+
 impl Database {
     pub async fn new(database_url: &str) -> Result<Self> {
-        use sqlx::sqlite::SqliteConnectOptions;
-        use std::str::FromStr;
-        
+        Self::with_config(database_url, DatabaseConfig::from_env()?).await
+    }
+
+    pub async fn with_config(database_url: &str, config: DatabaseConfig) -> Result<Self> {
         let options = SqliteConnectOptions::from_str(database_url)?
-            .create_if_missing(true);
-        
-        let pool = SqlitePool::connect_with(options).await?;
+            .create_if_missing(true)
+            .journal_mode(config.journal_mode)
+            .synchronous(config.synchronous)
+            .busy_timeout(config.busy_timeout)
+            .foreign_keys(config.foreign_keys);
+
+        log::info!(
+            "Opening database with {} max connections, journal_mode={:?}, synchronous={:?}, busy_timeout={:?}, foreign_keys={}",
+            config.max_connections,
+            config.journal_mode,
+            config.synchronous,
+            config.busy_timeout,
+            config.foreign_keys
+        );
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(config.max_connections)
+            .connect_with(options)
+            .await?;
         Ok(Self { pool })
     }
 
@@ -128,184 +469,2137 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
-        Ok(())
-    }
+        // Group members the bot has only seen have a row without a balance
+        self.ensure_column("users", "registered", "BOOLEAN NOT NULL DEFAULT TRUE").await?;
+        // Bets created before markets were scoped per chat have a NULL chat_id
+        self.ensure_column("bets", "chat_id", "INTEGER").await?;
+        self.ensure_column("bets", "deadline", "TEXT").await?;
+        self.ensure_column("bets", "deadline_handled", "INTEGER NOT NULL DEFAULT 0").await?;
+        // Bets created before the contract had epochs get theirs in `adopt_epoch`
+        self.ensure_column("bets", "epoch", "INTEGER").await?;
+        self.ensure_column("bets", "market_id", "INTEGER").await?;
+        self.ensure_column("bets", "expiry_refused", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        // When a bet resolved, which the retention job counts from
+        self.ensure_column("bets", "resolved_at", "TEXT").await?;
 
-    pub async fn create_or_update_user(&self, user_id: i64, username: Option<String>, balance: i64) -> Result<()> {
-        let now = chrono::Utc::now().to_rfc3339();
+        // Archive tables hold resolved bets moved out by the retention job
         sqlx::query(
             r#"
-            INSERT INTO users (user_id, username, balance, created_at)
-            VALUES (?1, ?2, ?3, ?4)
-            ON CONFLICT(user_id) DO UPDATE SET
-                username = excluded.username,
-                balance = excluded.balance
+            CREATE TABLE IF NOT EXISTS bets_archive (
+                bet_id INTEGER PRIMARY KEY,
+                creator_id INTEGER NOT NULL,
+                chat_id INTEGER,
+                description TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                status TEXT NOT NULL,
+                archived_at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(user_id)
-        .bind(username)
-        .bind(balance)
-        .bind(now)
         .execute(&self.pool)
         .await?;
-        Ok(())
-    }
+        self.ensure_column("bets_archive", "deadline", "TEXT").await?;
+        self.ensure_column("bets_archive", "epoch", "INTEGER").await?;
+        self.ensure_column("bets_archive", "market_id", "INTEGER").await?;
 
-    pub async fn get_user(&self, user_id: i64) -> Result<Option<User>> {
-        let user = sqlx::query_as::<_, User>(
-            "SELECT user_id, username, balance, created_at FROM users WHERE user_id = ?",
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS wagers_archive (
+                wager_id INTEGER PRIMARY KEY,
+                bet_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                amount INTEGER NOT NULL,
+                side BOOLEAN NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
         )
-        .bind(user_id)
-        .fetch_optional(&self.pool)
+        .execute(&self.pool)
         .await?;
-        Ok(user)
-    }
 
-    pub async fn create_bet(&self, creator_id: i64, description: String) -> Result<i64> {
-        let now = chrono::Utc::now().to_rfc3339();
-        let result = sqlx::query(
+        sqlx::query(
             r#"
-            INSERT INTO bets (creator_id, description, created_at, status)
-            VALUES (?1, ?2, ?3, 'open')
+            CREATE TABLE IF NOT EXISTS solutions_archive (
+                solution_id INTEGER PRIMARY KEY,
+                bet_id INTEGER NOT NULL,
+                solver_id INTEGER NOT NULL,
+                message_id INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(creator_id)
-        .bind(description)
-        .bind(now)
         .execute(&self.pool)
         .await?;
-        
-        Ok(result.last_insert_rowid())
-    }
 
-    pub async fn create_wager(&self, bet_id: i64, user_id: i64, amount: i64, side: bool) -> Result<i64> {
-        let now = chrono::Utc::now().to_rfc3339();
-        let result = sqlx::query(
+        // Claude evaluations, keyed by a hash of the evaluated inputs
+        sqlx::query(
             r#"
-            INSERT INTO wagers (bet_id, user_id, amount, side, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5)
+            CREATE TABLE IF NOT EXISTS resolution_cache (
+                cache_key TEXT PRIMARY KEY,
+                resolution TEXT NOT NULL,
+                raw_response TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
             "#,
         )
-        .bind(bet_id)
-        .bind(user_id)
-        .bind(amount)
-        .bind(side)
-        .bind(now)
         .execute(&self.pool)
         .await?;
-        
-        Ok(result.last_insert_rowid())
-    }
 
-    pub async fn get_all_bets(&self) -> Result<Vec<Bet>> {
-        let bets = sqlx::query_as::<_, Bet>(
-            "SELECT bet_id, creator_id, description, created_at, status FROM bets ORDER BY bet_id DESC",
+        // LLM spend per chat and month ("YYYY-MM"), and optional monthly budgets
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS llm_usage (
+                chat_id INTEGER NOT NULL,
+                month TEXT NOT NULL,
+                evaluations INTEGER NOT NULL DEFAULT 0,
+                input_tokens INTEGER NOT NULL DEFAULT 0,
+                output_tokens INTEGER NOT NULL DEFAULT 0,
+                cost_micros INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (chat_id, month)
+            )
+            "#,
         )
-        .fetch_all(&self.pool)
+        .execute(&self.pool)
         .await?;
-        Ok(bets)
-    }
 
-    pub async fn get_bet_by_id(&self, bet_id: i64) -> Result<Option<Bet>> {
-        let bet = sqlx::query_as::<_, Bet>(
-            "SELECT bet_id, creator_id, description, created_at, status FROM bets WHERE bet_id = ?",
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS chat_budgets (
+                chat_id INTEGER PRIMARY KEY,
+                monthly_budget_micros INTEGER NOT NULL
+            )
+            "#,
         )
-        .bind(bet_id)
-        .fetch_optional(&self.pool)
+        .execute(&self.pool)
         .await?;
-        Ok(bet)
-    }
 
-    pub async fn close_bet(&self, bet_id: i64, resolution: bool) -> Result<()> {
-        let status = if resolution { "resolved_yes" } else { "resolved_no" };
         sqlx::query(
-            "UPDATE bets SET status = ? WHERE bet_id = ?",
+            r#"
+            CREATE TABLE IF NOT EXISTS chat_settings (
+                chat_id INTEGER PRIMARY KEY,
+                auto_expire BOOLEAN NOT NULL DEFAULT FALSE,
+                frozen BOOLEAN NOT NULL DEFAULT FALSE,
+                currency_name TEXT,
+                currency_emoji TEXT,
+                message_buffer BOOLEAN NOT NULL DEFAULT FALSE,
+                timezone TEXT
+            )
+            "#,
         )
-        .bind(status)
-        .bind(bet_id)
         .execute(&self.pool)
         .await?;
-        Ok(())
-    }
+        self.ensure_column("chat_settings", "frozen", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        self.ensure_column("chat_settings", "currency_name", "TEXT").await?;
+        self.ensure_column("chat_settings", "currency_emoji", "TEXT").await?;
+        self.ensure_column("chat_settings", "message_buffer", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        self.ensure_column("chat_settings", "timezone", "TEXT").await?;
 
-    pub async fn update_user_balance(&self, user_id: i64, new_balance: i64) -> Result<()> {
+        // Users who asked, in a private chat, that their group messages never be kept
         sqlx::query(
-            "UPDATE users SET balance = ? WHERE user_id = ?",
+            r#"
+            CREATE TABLE IF NOT EXISTS privacy_optouts (
+                user_id INTEGER PRIMARY KEY,
+                opted_out_at TEXT NOT NULL
+            )
+            "#,
         )
-        .bind(new_balance)
-        .bind(user_id)
         .execute(&self.pool)
         .await?;
-        Ok(())
-    }
 
-    pub async fn get_leaderboard(&self, limit: i64) -> Result<Vec<User>> {
-        let users = sqlx::query_as::<_, User>(
-            "SELECT user_id, username, balance, created_at FROM users ORDER BY balance DESC LIMIT ?",
-        )
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await?;
-        Ok(users)
-    }
-    
-    pub async fn get_wagers_for_bet(&self, bet_id: i64) -> Result<Vec<Wager>> {
-        let wagers = sqlx::query_as::<_, Wager>(
-            "SELECT wager_id, bet_id, user_id, amount, side, created_at FROM wagers WHERE bet_id = ?",
+        // What each bettor staked and got back when a bet resolved
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settlements (
+                bet_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                stake INTEGER NOT NULL,
+                payout INTEGER NOT NULL,
+                settled_at TEXT NOT NULL,
+                PRIMARY KEY (bet_id, user_id)
+            )
+            "#,
         )
-        .bind(bet_id)
-        .fetch_all(&self.pool)
+        .execute(&self.pool)
         .await?;
-        Ok(wagers)
-    }
 
-    pub async fn create_solution(&self, bet_id: i64, solver_id: i64, message_id: i64) -> Result<i64> {
-        let now = chrono::Utc::now().to_rfc3339();
-        let result = sqlx::query(
+        // Bets resolved before `resolved_at` was recorded take their last
+        // settlement, or their creation when nobody bet
+        sqlx::query(
             r#"
-            INSERT INTO solutions (bet_id, solver_id, message_id, created_at)
-            VALUES (?1, ?2, ?3, ?4)
+            UPDATE bets SET resolved_at = COALESCE((SELECT MAX(settled_at) FROM settlements s WHERE s.bet_id = bets.bet_id), created_at)
+            WHERE status IN ('resolved_yes', 'resolved_no') AND resolved_at IS NULL
             "#,
         )
-        .bind(bet_id)
-        .bind(solver_id)
-        .bind(message_id)
-        .bind(now)
         .execute(&self.pool)
         .await?;
-        
-        Ok(result.last_insert_rowid())
-    }
 
-    pub async fn is_user_initialized(&self, user_id: i64) -> Result<bool> {
-        let result = sqlx::query_scalar::<_, bool>(
-            "SELECT initialized FROM user_init_status WHERE user_id = ?"
+        // Bot messages announcing or reminding of a bet, so replying to one names the bet
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS bet_announcements (
+                chat_id INTEGER NOT NULL,
+                message_id INTEGER NOT NULL,
+                bet_id INTEGER NOT NULL,
+                PRIMARY KEY (chat_id, message_id)
+            )
+            "#,
         )
-        .bind(user_id)
-        .fetch_optional(&self.pool)
+        .execute(&self.pool)
         .await?;
-        
-        Ok(result.unwrap_or(false))
-    }
 
-    pub async fn mark_user_initialized(&self, user_id: i64) -> Result<()> {
-        let now = chrono::Utc::now().to_rfc3339();
+        // The message announcing each bet, kept up to date with its pools
         sqlx::query(
             r#"
-            INSERT INTO user_init_status (user_id, initialized, initialized_at)
-            VALUES (?1, TRUE, ?2)
-            ON CONFLICT(user_id) DO NOTHING
+            CREATE TABLE IF NOT EXISTS live_announcements (
+                bet_id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                message_id INTEGER NOT NULL,
+                text TEXT NOT NULL
+            )
             "#,
         )
-        .bind(user_id)
-        .bind(now)
         .execute(&self.pool)
         .await?;
-        Ok(())
-    }
 
-    pub async fn reset_all(&self) -> Result<()> {
-        sqlx::query("DELETE FROM solutions")
+        // Telegram polls turned into markets, to offer their result once they close
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS poll_markets (
+                poll_id TEXT PRIMARY KEY,
+                bet_id INTEGER NOT NULL,
+                chat_id INTEGER NOT NULL,
+                yes_option INTEGER NOT NULL,
+                result_offered BOOLEAN NOT NULL DEFAULT FALSE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Operator broadcasts and, per target chat, whether it was reached yet
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS broadcasts (
+                broadcast_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                sender_id INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                finished_at TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS broadcast_deliveries (
+                broadcast_id INTEGER NOT NULL,
+                chat_id INTEGER NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                PRIMARY KEY (broadcast_id, chat_id),
+                FOREIGN KEY (broadcast_id) REFERENCES broadcasts(broadcast_id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Markets shown in inline query results, by the user who searched
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS inline_impressions (
+                bet_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                shown_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Ended seasons and the top of their final leaderboard
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS seasons (
+                season_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                ended_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS season_standings (
+                season_id INTEGER NOT NULL,
+                rank INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                username TEXT,
+                balance INTEGER NOT NULL,
+                PRIMARY KEY (season_id, rank),
+                FOREIGN KEY (season_id) REFERENCES seasons(season_id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS challenges (
+                bet_id INTEGER PRIMARY KEY,
+                opponent_id INTEGER NOT NULL,
+                stake INTEGER NOT NULL,
+                expires_at TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                FOREIGN KEY (bet_id) REFERENCES bets(bet_id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Manual corrections of local balances by operators. `local_only`
+        // ones were not mirrored on-chain, so reconciliation leaves them out
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS balance_adjustments (
+                adjustment_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                operator_id INTEGER NOT NULL,
+                amount INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                local_only INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Actions sent to the market server, until their outcome is mirrored
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS operations (
+                operation_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                chat_id INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                params TEXT NOT NULL,
+                tx_hash TEXT,
+                status TEXT NOT NULL DEFAULT 'pending',
+                created_at TEXT NOT NULL,
+                finished_at TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Odds each bettor last heard of for a market they bet on, so a move
+        // is only reported once
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS odds_alerts (
+                user_id INTEGER NOT NULL,
+                bet_id INTEGER NOT NULL,
+                yes_bps INTEGER NOT NULL,
+                notified_at TEXT,
+                PRIMARY KEY (user_id, bet_id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Users who turned odds alerts off
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS alert_optouts (
+                user_id INTEGER PRIMARY KEY,
+                opted_out_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Indexes for the hot read paths: /list, /leaderboard and wager lookups
+        for statement in [
+            "CREATE INDEX IF NOT EXISTS idx_bets_status_chat ON bets(status, chat_id)",
+            "CREATE INDEX IF NOT EXISTS idx_wagers_user ON wagers(user_id)",
+            "CREATE INDEX IF NOT EXISTS idx_wagers_bet ON wagers(bet_id)",
+            "CREATE INDEX IF NOT EXISTS idx_users_balance ON users(balance)",
+            "CREATE INDEX IF NOT EXISTS idx_wagers_archive_bet ON wagers_archive(bet_id)",
+        ] {
+            sqlx::query(statement).execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a column to an existing table unless it is already present.
+    async fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let columns = sqlx::query_scalar::<_, String>(&format!(
+            "SELECT name FROM pragma_table_info('{}')",
+            table
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        if !columns.iter().any(|c| c == column) {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn create_or_update_user(&self, user_id: i64, username: Option<String>, balance: i64) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            INSERT INTO users (user_id, username, balance, created_at, registered)
+            VALUES (?1, ?2, ?3, ?4, TRUE)
+            ON CONFLICT(user_id) DO UPDATE SET
+                username = excluded.username,
+                balance = excluded.balance,
+                registered = TRUE
+            "#,
+        )
+        .bind(user_id)
+        .bind(username)
+        .bind(balance)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Remembers the author of a group message so mentions of them resolve,
+    /// without giving them a balance: they stay unknown to `get_user` until
+    /// they initialize.
+    pub async fn remember_user(&self, user_id: i64, username: Option<String>) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            INSERT INTO users (user_id, username, balance, created_at, registered)
+            VALUES (?1, ?2, 0, ?3, FALSE)
+            ON CONFLICT(user_id) DO UPDATE SET username = excluded.username
+            WHERE users.username IS NOT excluded.username
+            "#,
+        )
+        .bind(user_id)
+        .bind(username)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The id of the user known by `username`, compared without a leading
+    /// `@` and regardless of case, whether or not they initialized.
+    pub async fn get_user_id_by_username(&self, username: &str) -> Result<Option<i64>> {
+        let user_id = sqlx::query_scalar::<_, i64>("SELECT user_id FROM users WHERE username = ? COLLATE NOCASE")
+            .bind(username.trim_start_matches('@'))
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(user_id)
+    }
+
+    pub async fn get_user(&self, user_id: i64) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "SELECT user_id, username, balance, created_at FROM users WHERE user_id = ? AND registered",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(user)
+    }
+
+    pub async fn create_bet(
+        &self,
+        creator_id: i64,
+        chat_id: i64,
+        description: String,
+        deadline: Option<String>,
+    ) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO bets (creator_id, chat_id, description, created_at, status, deadline)
+            VALUES (?1, ?2, ?3, ?4, 'open', ?5)
+            "#,
+        )
+        .bind(creator_id)
+        .bind(chat_id)
+        .bind(description)
+        .bind(now)
+        .bind(deadline)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Records which market, of which contract epoch, `bet_id` was created as.
+    pub async fn set_chain_market(&self, bet_id: i64, epoch: Option<u64>, market_id: Option<u64>) -> Result<()> {
+        sqlx::query("UPDATE bets SET epoch = ?1, market_id = ?2 WHERE bet_id = ?3")
+            .bind(epoch.map(|epoch| epoch as i64))
+            .bind(market_id.map(|market_id| market_id as i64))
+            .bind(bet_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Bets of the contract of epoch `epoch`, or of an unknown one, whose
+    /// market id was never reported, oldest first.
+    pub async fn get_bets_without_market(&self, epoch: Option<u64>) -> Result<Vec<Bet>> {
+        let bets = sqlx::query_as::<_, Bet>(
+            r#"
+            SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id FROM bets
+            WHERE market_id IS NULL AND (epoch IS NULL OR ?1 IS NULL OR epoch = ?1)
+            ORDER BY bet_id
+            "#,
+        )
+        .bind(epoch.map(|epoch| epoch as i64))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bets)
+    }
+
+    /// Market ids already recorded for bets of the contract of epoch `epoch`.
+    pub async fn get_known_market_ids(&self, epoch: Option<u64>) -> Result<Vec<u64>> {
+        let ids = sqlx::query_scalar::<_, i64>(
+            "SELECT market_id FROM bets WHERE market_id IS NOT NULL AND (epoch IS NULL OR ?1 IS NULL OR epoch = ?1)",
+        )
+        .bind(epoch.map(|epoch| epoch as i64))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids.into_iter().map(|id| id as u64).collect())
+    }
+
+    /// The bet created as `market_id` on the contract of epoch `epoch`.
+    pub async fn get_bet_by_market(&self, market_id: u64, epoch: Option<u64>) -> Result<Option<Bet>> {
+        let bet = sqlx::query_as::<_, Bet>(
+            r#"
+            SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id FROM bets
+            WHERE COALESCE(market_id, bet_id) = ?1 AND (epoch IS NULL OR ?2 IS NULL OR epoch = ?2)
+            ORDER BY bet_id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(market_id as i64)
+        .bind(epoch.map(|epoch| epoch as i64))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(bet)
+    }
+
+    /// Assigns the contract epoch `epoch`, read at startup, to the bets that
+    /// have none: those created since it was deployed belong to it, older ones
+    /// to the epoch-less contract (0). The deadline scheduler then leaves the
+    /// open bets of other epochs alone; their number is returned.
+    pub async fn adopt_epoch(&self, epoch: u64) -> Result<u64> {
+        let deployed_at = chrono::DateTime::from_timestamp(epoch as i64, 0)
+            .unwrap_or_default()
+            .to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE bets SET epoch = CASE WHEN created_at >= ?1 THEN ?2 ELSE 0 END WHERE epoch IS NULL")
+            .bind(&deployed_at)
+            .bind(epoch as i64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE bets SET deadline_handled = ?1 WHERE status IN ('open', 'resolving') AND epoch != ?2")
+            .bind(DeadlineStage::Handled as i64)
+            .bind(epoch as i64)
+            .execute(&mut *tx)
+            .await?;
+        let open_elsewhere = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM bets WHERE status IN ('open', 'resolving') AND epoch != ?")
+            .bind(epoch as i64)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(open_elsewhere as u64)
+    }
+
+    /// Inserts a wager alone, for test fixtures: bets are mirrored through
+    /// [`Self::apply_bet_operation`].
+    #[cfg(test)]
+    pub async fn create_wager(&self, bet_id: i64, user_id: i64, amount: i64, side: bool) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO wagers (bet_id, user_id, amount, side, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+        )
+        .bind(bet_id)
+        .bind(user_id)
+        .bind(amount)
+        .bind(side)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Most recent bets visible in a chat, newest first. Bets without a chat
+    /// (created before chat scoping) are visible everywhere.
+    pub async fn get_recent_bets(&self, chat_id: i64, limit: i64) -> Result<Vec<Bet>> {
+        let bets = sqlx::query_as::<_, Bet>(
+            r#"
+            SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id FROM bets
+            WHERE chat_id = ?1 OR chat_id IS NULL
+            ORDER BY bet_id DESC
+            LIMIT ?2
+            "#,
+        )
+        .bind(chat_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bets)
+    }
+
+    pub async fn count_bets(&self, chat_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM bets WHERE chat_id = ?1 OR chat_id IS NULL",
+        )
+        .bind(chat_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    pub async fn get_bet_by_id(&self, bet_id: i64) -> Result<Option<Bet>> {
+        let bet = sqlx::query_as::<_, Bet>(
+            "SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id FROM bets WHERE bet_id = ?",
+        )
+        .bind(bet_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(bet)
+    }
+
+    /// Looks a bet up in the live table first, then in the archive.
+    /// The returned flag is true when the bet was found in the archive.
+    pub async fn get_bet_or_archived(&self, bet_id: i64) -> Result<Option<(Bet, bool)>> {
+        if let Some(bet) = self.get_bet_by_id(bet_id).await? {
+            return Ok(Some((bet, false)));
+        }
+
+        let bet = sqlx::query_as::<_, Bet>(
+            "SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id FROM bets_archive WHERE bet_id = ?",
+        )
+        .bind(bet_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(bet.map(|b| (b, true)))
+    }
+
+    /// Stops betting on an open bet while a /solve evaluation decides it, so
+    /// nobody can rush in on the verdict they guess. The bet still counts as
+    /// open for everything else: exposure, pools, deadlines and admins.
+    /// Returns false when the bet is not open, e.g. another evaluation got
+    /// there first.
+    pub async fn begin_resolving(&self, bet_id: i64) -> Result<bool> {
+        let updated = sqlx::query("UPDATE bets SET status = 'resolving' WHERE bet_id = ? AND status = 'open'")
+            .bind(bet_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(updated.rows_affected() == 1)
+    }
+
+    /// Reopens a bet whose evaluation ended without resolving it. Bets
+    /// resolved in the meantime are left as they are.
+    pub async fn reopen_bet(&self, bet_id: i64) -> Result<()> {
+        sqlx::query("UPDATE bets SET status = 'open' WHERE bet_id = ? AND status = 'resolving'")
+            .bind(bet_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Reopens the bets whose evaluation a restart interrupted, returning how
+    /// many there were.
+    pub async fn reopen_resolving_bets(&self) -> Result<u64> {
+        let reopened = sqlx::query("UPDATE bets SET status = 'open' WHERE status = 'resolving'")
+            .execute(&self.pool)
+            .await?;
+        Ok(reopened.rows_affected())
+    }
+
+    pub async fn close_bet(&self, bet_id: i64, resolution: bool) -> Result<()> {
+        self.close_bet_at(bet_id, resolution, &chrono::Utc::now().to_rfc3339()).await
+    }
+
+    /// Closes a bet and records the settlement of each of its bettors at
+    /// `settled_at` (RFC 3339). A bet is only settled once.
+    pub async fn close_bet_at(&self, bet_id: i64, resolution: bool, settled_at: &str) -> Result<()> {
+        let status = if resolution { "resolved_yes" } else { "resolved_no" };
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE bets SET status = ?, resolved_at = ? WHERE bet_id = ?",
+        )
+        .bind(status)
+        .bind(settled_at)
+        .bind(bet_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // Parimutuel payouts; nobody is paid when nobody backed the outcome
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO settlements (bet_id, user_id, stake, payout, settled_at)
+            SELECT w.bet_id, w.user_id, SUM(w.amount),
+                COALESCE(SUM(CASE WHEN w.side = ?2 THEN w.amount ELSE 0 END) * p.total / NULLIF(p.winning, 0), 0),
+                ?3
+            FROM wagers w, (
+                SELECT SUM(amount) AS total, SUM(CASE WHEN side = ?2 THEN amount ELSE 0 END) AS winning
+                FROM wagers WHERE bet_id = ?1
+            ) p
+            WHERE w.bet_id = ?1
+            GROUP BY w.user_id
+            "#,
+        )
+        .bind(bet_id)
+        .bind(resolution)
+        .bind(settled_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Users ranked by net profit between `from` and `to` (RFC 3339, end
+    /// excluded): payouts settled in the window minus stakes placed in it.
+    /// Only users with activity in the window are listed.
+    pub async fn get_profit_leaderboard(&self, from: &str, to: &str, limit: i64) -> Result<Vec<ProfitEntry>> {
+        let entries = sqlx::query_as::<_, ProfitEntry>(
+            r#"
+            WITH stakes AS (
+                SELECT user_id, SUM(amount) AS staked FROM (
+                    SELECT user_id, amount, created_at FROM wagers
+                    UNION ALL SELECT user_id, amount, created_at FROM wagers_archive
+                )
+                WHERE created_at >= ?1 AND created_at < ?2
+                GROUP BY user_id
+            ),
+            payouts AS (
+                SELECT user_id, SUM(payout) AS won FROM settlements
+                WHERE settled_at >= ?1 AND settled_at < ?2
+                GROUP BY user_id
+            ),
+            active AS (
+                SELECT user_id FROM stakes UNION SELECT user_id FROM payouts
+            )
+            SELECT a.user_id, u.username, COALESCE(p.won, 0) - COALESCE(s.staked, 0) AS profit
+            FROM active a
+            LEFT JOIN stakes s ON s.user_id = a.user_id
+            LEFT JOIN payouts p ON p.user_id = a.user_id
+            LEFT JOIN users u ON u.user_id = a.user_id
+            ORDER BY profit DESC, a.user_id
+            LIMIT ?3
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(entries)
+    }
+
+    /// Open bets with a deadline that the deadline scheduler is not done with,
+    /// leaving out the frozen chats.
+    pub async fn get_unhandled_deadlines(&self) -> Result<Vec<DeadlineBet>> {
+        let bets = sqlx::query_as::<_, DeadlineBet>(
+            r#"
+            SELECT bet_id, creator_id, bets.chat_id, description, deadline, deadline_handled, market_id FROM bets
+            LEFT JOIN chat_settings ON chat_settings.chat_id = bets.chat_id
+            WHERE status IN ('open', 'resolving') AND deadline IS NOT NULL AND deadline_handled < ?1
+              AND NOT COALESCE(chat_settings.frozen, FALSE)
+            ORDER BY bet_id
+            "#,
+        )
+        .bind(DeadlineStage::Handled as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bets)
+    }
+
+    /// Open bets of the contract epoch `epoch` created before `created_before`
+    /// (RFC 3339), leaving out those the contract already refused to void.
+    pub async fn get_forgotten_bets(&self, created_before: &str, epoch: Option<u64>) -> Result<Vec<Bet>> {
+        let bets = sqlx::query_as::<_, Bet>(
+            r#"
+            SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id FROM bets
+            WHERE status = 'open' AND created_at < ?1 AND NOT expiry_refused AND (epoch IS NULL OR epoch = ?2)
+            ORDER BY bet_id
+            "#,
+        )
+        .bind(created_before)
+        .bind(epoch.map(|epoch| epoch as i64))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bets)
+    }
+
+    /// Stops offering `bet_id` to [`Self::get_forgotten_bets`].
+    pub async fn mark_expiry_refused(&self, bet_id: i64) -> Result<()> {
+        sqlx::query("UPDATE bets SET expiry_refused = TRUE WHERE bet_id = ?")
+            .bind(bet_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Voids an open bet and refunds every wager on it, settled at par.
+    /// Returns false when the bet was no longer open.
+    pub async fn void_bet(&self, bet_id: i64) -> Result<bool> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        let voided = sqlx::query("UPDATE bets SET status = 'void' WHERE bet_id = ? AND status = 'open'")
+            .bind(bet_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if voided {
+            sqlx::query(
+                r#"
+                UPDATE users SET balance = balance + (SELECT SUM(amount) FROM wagers WHERE bet_id = ?1 AND wagers.user_id = users.user_id)
+                WHERE user_id IN (SELECT user_id FROM wagers WHERE bet_id = ?1)
+                "#,
+            )
+            .bind(bet_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO settlements (bet_id, user_id, stake, payout, settled_at)
+                SELECT bet_id, user_id, SUM(amount), SUM(amount), ?2 FROM wagers WHERE bet_id = ?1 GROUP BY user_id
+                "#,
+            )
+            .bind(bet_id)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(voided)
+    }
+
+    pub async fn set_deadline_stage(&self, bet_id: i64, stage: DeadlineStage) -> Result<()> {
+        sqlx::query("UPDATE bets SET deadline_handled = ? WHERE bet_id = ?")
+            .bind(stage as i64)
+            .bind(bet_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_user_balance(&self, user_id: i64, new_balance: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE users SET balance = ? WHERE user_id = ?",
+        )
+        .bind(new_balance)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Adds `amount`, negative for a debit, to the local balance of `user_id`
+    /// and logs who did it and why. Returns the new balance, or `None` for an
+    /// unknown user.
+    pub async fn adjust_balance(
+        &self,
+        user_id: i64,
+        operator_id: i64,
+        amount: i64,
+        reason: &str,
+        local_only: bool,
+    ) -> Result<Option<i64>> {
+        let mut tx = self.pool.begin().await?;
+        let balance = sqlx::query_scalar::<_, i64>("UPDATE users SET balance = balance + ?1 WHERE user_id = ?2 RETURNING balance")
+            .bind(amount)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if balance.is_none() {
+            return Ok(None);
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO balance_adjustments (user_id, operator_id, amount, reason, local_only, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(user_id)
+        .bind(operator_id)
+        .bind(amount)
+        .bind(reason)
+        .bind(local_only)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(balance)
+    }
+
+    /// Sum of the local-only adjustments of each user who has some, by id.
+    pub async fn get_local_adjustments(&self) -> Result<Vec<(i64, i64)>> {
+        let adjustments = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT user_id, SUM(amount) FROM balance_adjustments WHERE local_only GROUP BY user_id ORDER BY user_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(adjustments)
+    }
+
+    /// Every known user with their local balance, by id.
+    pub async fn get_balances(&self) -> Result<Vec<(i64, i64)>> {
+        let balances = sqlx::query_as::<_, (i64, i64)>("SELECT user_id, balance FROM users WHERE registered ORDER BY user_id")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(balances)
+    }
+
+    /// Pools of every open bet of the contract epoch `epoch` across chats, by id.
+    pub async fn get_open_pools(&self, epoch: Option<u64>) -> Result<Vec<OpenPool>> {
+        let pools = sqlx::query_as::<_, OpenPool>(
+            r#"
+            SELECT COALESCE(b.market_id, b.bet_id) AS market_id,
+                COALESCE(SUM(CASE WHEN w.side THEN w.amount END), 0) AS yes_pool,
+                COALESCE(SUM(CASE WHEN NOT w.side THEN w.amount END), 0) AS no_pool
+            FROM bets b LEFT JOIN wagers w ON w.bet_id = b.bet_id
+            WHERE b.status IN ('open', 'resolving') AND (b.epoch IS NULL OR b.epoch = ?1)
+            GROUP BY b.bet_id
+            ORDER BY b.bet_id
+            "#,
+        )
+        .bind(epoch.map(|epoch| epoch as i64))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(pools)
+    }
+
+    /// Open bets of the contract epoch `epoch` whose description contains
+    /// `query`, newest first. Only bets of `user_id`'s own private chat and of
+    /// the groups where they created or wagered on a bet are searched, frozen
+    /// groups left out; callers still check that they are in the group.
+    pub async fn search_open_bets(&self, user_id: i64, query: &str, epoch: Option<u64>, limit: i64) -> Result<Vec<Bet>> {
+        let escaped = query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let bets = sqlx::query_as::<_, Bet>(
+            r#"
+            WITH known_groups AS (
+                SELECT chat_id FROM bets WHERE creator_id = ?1
+                UNION SELECT chat_id FROM bets_archive WHERE creator_id = ?1
+                UNION SELECT b.chat_id FROM wagers w JOIN bets b ON b.bet_id = w.bet_id WHERE w.user_id = ?1
+                UNION SELECT b.chat_id FROM wagers_archive w JOIN bets_archive b ON b.bet_id = w.bet_id WHERE w.user_id = ?1
+            )
+            SELECT bet_id, creator_id, bets.chat_id, description, created_at, status, deadline, epoch, market_id FROM bets
+            LEFT JOIN chat_settings ON chat_settings.chat_id = bets.chat_id
+            WHERE status = 'open' AND (epoch IS NULL OR epoch = ?2)
+              AND description LIKE '%' || ?3 || '%' ESCAPE '\'
+              AND (bets.chat_id = ?1 OR (bets.chat_id < 0 AND bets.chat_id IN (SELECT chat_id FROM known_groups)))
+              AND NOT COALESCE(chat_settings.frozen, FALSE)
+            ORDER BY bet_id DESC
+            LIMIT ?4
+            "#,
+        )
+        .bind(user_id)
+        .bind(epoch.map(|epoch| epoch as i64))
+        .bind(escaped)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bets)
+    }
+
+    pub async fn record_inline_impressions(&self, user_id: i64, bet_ids: &[i64]) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        for bet_id in bet_ids {
+            sqlx::query("INSERT INTO inline_impressions (bet_id, user_id, shown_at) VALUES (?1, ?2, ?3)")
+                .bind(bet_id)
+                .bind(user_id)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// How many times `bet_id` was shown in inline query results.
+    pub async fn count_inline_impressions(&self, bet_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM inline_impressions WHERE bet_id = ?")
+            .bind(bet_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    /// Chats with at least one open bet.
+    pub async fn get_active_chats(&self) -> Result<Vec<i64>> {
+        let chats = sqlx::query_scalar::<_, i64>(
+            "SELECT DISTINCT chat_id FROM bets WHERE status IN ('open', 'resolving') AND chat_id IS NOT NULL ORDER BY chat_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(chats)
+    }
+
+    /// Logs a broadcast of `text` with one pending delivery per chat of `chat_ids`.
+    pub async fn create_broadcast(&self, sender_id: i64, text: &str, chat_ids: &[i64]) -> Result<Broadcast> {
+        let mut tx = self.pool.begin().await?;
+        let broadcast_id = sqlx::query(
+            "INSERT INTO broadcasts (sender_id, text, created_at) VALUES (?1, ?2, ?3)",
+        )
+        .bind(sender_id)
+        .bind(text)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+
+        for chat_id in chat_ids {
+            sqlx::query("INSERT INTO broadcast_deliveries (broadcast_id, chat_id) VALUES (?1, ?2)")
+                .bind(broadcast_id)
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(Broadcast { broadcast_id, sender_id, text: text.to_string() })
+    }
+
+    /// Chats a broadcast has not been sent to yet, by id.
+    pub async fn get_pending_deliveries(&self, broadcast_id: i64) -> Result<Vec<i64>> {
+        let chats = sqlx::query_scalar::<_, i64>(
+            "SELECT chat_id FROM broadcast_deliveries WHERE broadcast_id = ? AND status = 'pending' ORDER BY chat_id",
+        )
+        .bind(broadcast_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(chats)
+    }
+
+    /// Records whether a broadcast reached `chat_id`; either way it is not retried.
+    pub async fn record_delivery(&self, broadcast_id: i64, chat_id: i64, sent: bool) -> Result<()> {
+        sqlx::query("UPDATE broadcast_deliveries SET status = ?1 WHERE broadcast_id = ?2 AND chat_id = ?3")
+            .bind(if sent { "sent" } else { "failed" })
+            .bind(broadcast_id)
+            .bind(chat_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Marks a broadcast as done and returns how many chats it reached and missed.
+    pub async fn finish_broadcast(&self, broadcast_id: i64) -> Result<(i64, i64)> {
+        sqlx::query("UPDATE broadcasts SET finished_at = ?1 WHERE broadcast_id = ?2")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(broadcast_id)
+            .execute(&self.pool)
+            .await?;
+        let counts = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT COALESCE(SUM(status = 'sent'), 0), COALESCE(SUM(status = 'failed'), 0)
+            FROM broadcast_deliveries WHERE broadcast_id = ?
+            "#,
+        )
+        .bind(broadcast_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(counts)
+    }
+
+    /// Broadcasts a previous run started but did not finish, oldest first.
+    pub async fn get_unfinished_broadcasts(&self) -> Result<Vec<Broadcast>> {
+        let broadcasts = sqlx::query_as::<_, Broadcast>(
+            "SELECT broadcast_id, sender_id, text FROM broadcasts WHERE finished_at IS NULL ORDER BY broadcast_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(broadcasts)
+    }
+
+    /// Logs the intent to send `action` before it is sent, returning its id.
+    pub async fn begin_operation(&self, user_id: i64, chat_id: i64, action: &str, params: &str) -> Result<i64> {
+        let operation_id = sqlx::query(
+            "INSERT INTO operations (user_id, chat_id, action, params, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(user_id)
+        .bind(chat_id)
+        .bind(action)
+        .bind(params)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(operation_id)
+    }
+
+    /// Records the hash of an operation the server accepted but had not settled.
+    pub async fn mark_operation_submitted(&self, operation_id: i64, tx_hash: &str) -> Result<()> {
+        sqlx::query("UPDATE operations SET status = 'submitted', tx_hash = ?1 WHERE operation_id = ?2 AND status = 'pending'")
+            .bind(tx_hash)
+            .bind(operation_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Mirrors a bet that settled on-chain: creates the wager and debits the
+    /// bettor along with marking the operation applied, so none of it can
+    /// happen twice. Returns the new balance, or `None` if the operation was
+    /// already applied or finished.
+    pub async fn apply_bet_operation(
+        &self,
+        operation_id: i64,
+        tx_hash: &str,
+        bet_id: i64,
+        user_id: i64,
+        amount: i64,
+        side: bool,
+    ) -> Result<Option<i64>> {
+        let mut tx = self.pool.begin().await?;
+        let claimed = sqlx::query(
+            "UPDATE operations SET status = 'applied', tx_hash = ?1 WHERE operation_id = ?2 AND status IN ('pending', 'submitted')",
+        )
+        .bind(tx_hash)
+        .bind(operation_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            return Ok(None);
+        }
+
+        let now = chrono::Utc::now().to_rfc3339();
+        sqlx::query("INSERT INTO wagers (bet_id, user_id, amount, side, created_at) VALUES (?1, ?2, ?3, ?4, ?5)")
+            .bind(bet_id)
+            .bind(user_id)
+            .bind(amount)
+            .bind(side)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE users SET balance = balance - ?1 WHERE user_id = ?2")
+            .bind(amount)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let balance = sqlx::query_scalar::<_, i64>("SELECT balance FROM users WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Some(balance))
+    }
+
+    /// Ends an unfinished operation with `status`. Returns whether it was
+    /// still unfinished, so concurrent resumes act on it once.
+    pub async fn finish_operation(&self, operation_id: i64, status: &str) -> Result<bool> {
+        let finished = sqlx::query(
+            "UPDATE operations SET status = ?1, finished_at = ?2 WHERE operation_id = ?3 AND status IN ('pending', 'submitted', 'applied')",
+        )
+        .bind(status)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(operation_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(finished > 0)
+    }
+
+    /// Operations not finished yet, oldest first.
+    pub async fn get_unfinished_operations(&self) -> Result<Vec<Operation>> {
+        let operations = sqlx::query_as::<_, Operation>(
+            r#"
+            SELECT operation_id, user_id, chat_id, action, params, tx_hash, status
+            FROM operations WHERE status IN ('pending', 'submitted', 'applied') ORDER BY operation_id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(operations)
+    }
+
+    pub async fn get_leaderboard(&self, limit: i64) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT user_id, username, balance, created_at FROM users WHERE registered ORDER BY balance DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
+    }
+    
+    pub async fn record_announcement(&self, chat_id: i64, message_id: i64, bet_id: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO bet_announcements (chat_id, message_id, bet_id) VALUES (?1, ?2, ?3)",
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .bind(bet_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Remembers the message announcing a bet; `text` is its MarkdownV2 content without the pools.
+    pub async fn set_live_announcement(&self, bet_id: i64, chat_id: i64, message_id: i64, text: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO live_announcements (bet_id, chat_id, message_id, text) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(bet_id)
+        .bind(chat_id)
+        .bind(message_id)
+        .bind(text)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_live_announcement(&self, bet_id: i64) -> Result<Option<LiveAnnouncement>> {
+        let announcement = sqlx::query_as::<_, LiveAnnouncement>(
+            "SELECT chat_id, message_id, text FROM live_announcements WHERE bet_id = ?",
+        )
+        .bind(bet_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(announcement)
+    }
+
+    /// Stops updating the announcement of a bet, e.g. once it was deleted.
+    pub async fn remove_live_announcement(&self, bet_id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM live_announcements WHERE bet_id = ?")
+            .bind(bet_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The bet a bot message of the chat announced, if any.
+    pub async fn get_announced_bet(&self, chat_id: i64, message_id: i64) -> Result<Option<i64>> {
+        let bet_id = sqlx::query_scalar::<_, i64>(
+            "SELECT bet_id FROM bet_announcements WHERE chat_id = ?1 AND message_id = ?2",
+        )
+        .bind(chat_id)
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(bet_id)
+    }
+
+    pub async fn link_poll(&self, poll_id: &str, bet_id: i64, chat_id: i64, yes_option: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO poll_markets (poll_id, bet_id, chat_id, yes_option) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(poll_id)
+        .bind(bet_id)
+        .bind(chat_id)
+        .bind(yes_option)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_poll_market(&self, poll_id: &str) -> Result<Option<PollMarket>> {
+        let market = sqlx::query_as::<_, PollMarket>(
+            "SELECT bet_id, chat_id, yes_option, result_offered FROM poll_markets WHERE poll_id = ?",
+        )
+        .bind(poll_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(market)
+    }
+
+    /// Records that the result of a closed poll was offered, so it is offered once.
+    pub async fn mark_poll_result_offered(&self, poll_id: &str) -> Result<()> {
+        sqlx::query("UPDATE poll_markets SET result_offered = TRUE WHERE poll_id = ?")
+            .bind(poll_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Open bets of a chat, oldest first.
+    pub async fn get_open_bets(&self, chat_id: i64) -> Result<Vec<Bet>> {
+        let bets = sqlx::query_as::<_, Bet>(
+            r#"
+            SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id FROM bets
+            WHERE status IN ('open', 'resolving') AND (chat_id = ?1 OR chat_id IS NULL)
+            ORDER BY bet_id
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bets)
+    }
+
+    /// Rank of `user_id` by balance among the users who created or wagered on
+    /// a bet of the chat, with the number of such users. None for unknown users.
+    pub async fn get_chat_rank(&self, chat_id: i64, user_id: i64) -> Result<Option<(i64, i64)>> {
+        let rank = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            WITH members AS (
+                SELECT creator_id AS user_id FROM bets WHERE chat_id = ?1
+                UNION SELECT creator_id FROM bets_archive WHERE chat_id = ?1
+                UNION SELECT w.user_id FROM wagers w JOIN bets b ON b.bet_id = w.bet_id WHERE b.chat_id = ?1
+                UNION SELECT w.user_id FROM wagers_archive w JOIN bets_archive b ON b.bet_id = w.bet_id WHERE b.chat_id = ?1
+                UNION SELECT ?2
+            ),
+            ranked AS (
+                SELECT u.user_id, u.balance FROM users u JOIN members m ON m.user_id = u.user_id WHERE u.registered
+            )
+            SELECT
+                (SELECT COUNT(*) FROM ranked WHERE balance > me.balance) + 1,
+                (SELECT COUNT(*) FROM ranked)
+            FROM users me WHERE me.user_id = ?2 AND me.registered
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(rank)
+    }
+
+    pub async fn get_open_exposure(&self, user_id: i64) -> Result<OpenExposure> {
+        let exposure = sqlx::query_as::<_, OpenExposure>(
+            r#"
+            SELECT COUNT(DISTINCT w.bet_id) AS open_bets, COALESCE(SUM(w.amount), 0) AS at_risk
+            FROM wagers w JOIN bets b ON b.bet_id = w.bet_id
+            WHERE w.user_id = ?1 AND b.status IN ('open', 'resolving')
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(exposure)
+    }
+
+    pub async fn get_lifetime_totals(&self, user_id: i64) -> Result<LifetimeTotals> {
+        let totals = sqlx::query_as::<_, LifetimeTotals>(
+            r#"
+            WITH all_bets AS (
+                SELECT bet_id, status FROM bets
+                UNION ALL SELECT bet_id, status FROM bets_archive
+            ),
+            all_wagers AS (
+                SELECT bet_id, user_id, amount, side FROM wagers
+                UNION ALL SELECT bet_id, user_id, amount, side FROM wagers_archive
+            ),
+            pools AS (
+                SELECT w.bet_id,
+                    SUM(w.amount) AS total,
+                    SUM(CASE WHEN w.side = (b.status = 'resolved_yes') THEN w.amount ELSE 0 END) AS winning
+                FROM all_wagers w JOIN all_bets b ON b.bet_id = w.bet_id
+                WHERE b.status IN ('resolved_yes', 'resolved_no')
+                GROUP BY w.bet_id
+            )
+            SELECT
+                (SELECT COALESCE(SUM(amount), 0) FROM all_wagers WHERE user_id = ?1) AS wagered,
+                (
+                    SELECT COALESCE(SUM(w.amount * p.total / p.winning), 0)
+                    FROM all_wagers w
+                    JOIN all_bets b ON b.bet_id = w.bet_id
+                    JOIN pools p ON p.bet_id = w.bet_id
+                    WHERE w.user_id = ?1 AND w.side = (b.status = 'resolved_yes')
+                ) AS won
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(totals)
+    }
+
+    pub async fn get_wagers_for_bet(&self, bet_id: i64) -> Result<Vec<Wager>> {
+        let wagers = sqlx::query_as::<_, Wager>(
+            "SELECT wager_id, bet_id, user_id, amount, side, created_at FROM wagers WHERE bet_id = ?",
+        )
+        .bind(bet_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(wagers)
+    }
+
+    pub async fn get_archived_wagers_for_bet(&self, bet_id: i64) -> Result<Vec<Wager>> {
+        let wagers = sqlx::query_as::<_, Wager>(
+            "SELECT wager_id, bet_id, user_id, amount, side, created_at FROM wagers_archive WHERE bet_id = ?",
+        )
+        .bind(bet_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(wagers)
+    }
+
+    pub async fn create_solution(&self, bet_id: i64, solver_id: i64, message_id: i64) -> Result<i64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO solutions (bet_id, solver_id, message_id, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            "#,
+        )
+        .bind(bet_id)
+        .bind(solver_id)
+        .bind(message_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Returns the cached `(resolution, raw_response)` for `cache_key` if it
+    /// was stored less than `ttl` ago.
+    pub async fn get_cached_resolution(&self, cache_key: &str, ttl: Duration) -> Result<Option<(String, String)>> {
+        let cutoff = (chrono::Utc::now() - chrono::Duration::from_std(ttl)?).to_rfc3339();
+        let cached = sqlx::query_as::<_, (String, String)>(
+            "SELECT resolution, raw_response FROM resolution_cache WHERE cache_key = ? AND created_at >= ?",
+        )
+        .bind(cache_key)
+        .bind(cutoff)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(cached)
+    }
+
+    pub async fn cache_resolution(&self, cache_key: &str, resolution: &str, raw_response: &str) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            INSERT INTO resolution_cache (cache_key, resolution, raw_response, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(cache_key) DO UPDATE SET
+                resolution = excluded.resolution,
+                raw_response = excluded.raw_response,
+                created_at = excluded.created_at
+            "#,
+        )
+        .bind(cache_key)
+        .bind(resolution)
+        .bind(raw_response)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_llm_usage(
+        &self,
+        chat_id: i64,
+        month: &str,
+        input_tokens: i64,
+        output_tokens: i64,
+        cost_micros: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO llm_usage (chat_id, month, evaluations, input_tokens, output_tokens, cost_micros)
+            VALUES (?1, ?2, 1, ?3, ?4, ?5)
+            ON CONFLICT(chat_id, month) DO UPDATE SET
+                evaluations = evaluations + 1,
+                input_tokens = input_tokens + excluded.input_tokens,
+                output_tokens = output_tokens + excluded.output_tokens,
+                cost_micros = cost_micros + excluded.cost_micros
+            "#,
+        )
+        .bind(chat_id)
+        .bind(month)
+        .bind(input_tokens)
+        .bind(output_tokens)
+        .bind(cost_micros)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_llm_usage(&self, chat_id: i64, month: &str) -> Result<LlmUsage> {
+        let usage = sqlx::query_as::<_, LlmUsage>(
+            "SELECT evaluations, input_tokens, output_tokens, cost_micros FROM llm_usage WHERE chat_id = ? AND month = ?",
+        )
+        .bind(chat_id)
+        .bind(month)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(usage.unwrap_or_default())
+    }
+
+    /// Sets the monthly LLM budget of a chat, or removes it with `None`.
+    pub async fn set_chat_budget(&self, chat_id: i64, budget_micros: Option<i64>) -> Result<()> {
+        match budget_micros {
+            Some(budget) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO chat_budgets (chat_id, monthly_budget_micros)
+                    VALUES (?1, ?2)
+                    ON CONFLICT(chat_id) DO UPDATE SET monthly_budget_micros = excluded.monthly_budget_micros
+                    "#,
+                )
+                .bind(chat_id)
+                .bind(budget)
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM chat_budgets WHERE chat_id = ?")
+                    .bind(chat_id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get_chat_budget(&self, chat_id: i64) -> Result<Option<i64>> {
+        let budget = sqlx::query_scalar::<_, i64>(
+            "SELECT monthly_budget_micros FROM chat_budgets WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(budget)
+    }
+
+    /// Whether bets of the chat resolve as NO once their grace period ends.
+    pub async fn set_auto_expire(&self, chat_id: i64, enabled: bool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_settings (chat_id, auto_expire)
+            VALUES (?1, ?2)
+            ON CONFLICT(chat_id) DO UPDATE SET auto_expire = excluded.auto_expire
+            "#,
+        )
+        .bind(chat_id)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_auto_expire(&self, chat_id: i64) -> Result<bool> {
+        let enabled = sqlx::query_scalar::<_, bool>(
+            "SELECT auto_expire FROM chat_settings WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(enabled.unwrap_or(false))
+    }
+
+    /// Names the chat's currency; `None` goes back to the default coins.
+    pub async fn set_currency(&self, chat_id: i64, currency: Option<&Currency>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_settings (chat_id, currency_name, currency_emoji)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(chat_id) DO UPDATE SET
+                currency_name = excluded.currency_name,
+                currency_emoji = excluded.currency_emoji
+            "#,
+        )
+        .bind(chat_id)
+        .bind(currency.map(|currency| currency.name.as_str()))
+        .bind(currency.map(|currency| currency.emoji.as_str()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_currency(&self, chat_id: i64) -> Result<Currency> {
+        let row = sqlx::query_as::<_, (Option<String>, Option<String>)>(
+            "SELECT currency_name, currency_emoji FROM chat_settings WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match row {
+            Some((Some(name), emoji)) => Currency { name, emoji: emoji.unwrap_or_default() },
+            _ => Currency::default(),
+        })
+    }
+
+    /// Sets the timezone times are shown and read in for the chat.
+    pub async fn set_timezone(&self, chat_id: i64, timezone: Tz) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_settings (chat_id, timezone)
+            VALUES (?1, ?2)
+            ON CONFLICT(chat_id) DO UPDATE SET timezone = excluded.timezone
+            "#,
+        )
+        .bind(chat_id)
+        .bind(timezone.name())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The chat's timezone, UTC until it picks one.
+    pub async fn get_timezone(&self, chat_id: i64) -> Result<Tz> {
+        let name = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT timezone FROM chat_settings WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(name.flatten().and_then(|name| timezone::parse(&name)).unwrap_or(Tz::UTC))
+    }
+
+    /// Moves everything kept for `old_chat_id` to `new_chat_id`, for a group
+    /// Telegram upgraded to a supergroup under a new id. The LLM usage of a
+    /// month both chats have is added up; settings, budgets and announcements
+    /// the new chat already has are newer than the group's, which give way.
+    /// Returns the rows moved per table.
+    pub async fn migrate_chat(&self, old_chat_id: i64, new_chat_id: i64) -> Result<Vec<(&'static str, u64)>> {
+        let mut tx = self.pool.begin().await?;
+        let mut moved = Vec::with_capacity(CHAT_TABLES.len());
+        for table in CHAT_TABLES {
+            let rows = if table == "llm_usage" {
+                let merged = sqlx::query(
+                    r#"
+                    INSERT INTO llm_usage (chat_id, month, evaluations, input_tokens, output_tokens, cost_micros)
+                    SELECT ?1, month, evaluations, input_tokens, output_tokens, cost_micros FROM llm_usage WHERE chat_id = ?2
+                    ON CONFLICT(chat_id, month) DO UPDATE SET
+                        evaluations = evaluations + excluded.evaluations,
+                        input_tokens = input_tokens + excluded.input_tokens,
+                        output_tokens = output_tokens + excluded.output_tokens,
+                        cost_micros = cost_micros + excluded.cost_micros
+                    "#,
+                )
+                .bind(new_chat_id)
+                .bind(old_chat_id)
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query("DELETE FROM llm_usage WHERE chat_id = ?")
+                    .bind(old_chat_id)
+                    .execute(&mut *tx)
+                    .await?;
+                merged.rows_affected()
+            } else {
+                if let Some(key) = chat_key(table) {
+                    sqlx::query(&format!(
+                        "DELETE FROM {} WHERE chat_id = ?2 AND EXISTS (SELECT 1 FROM {} AS kept WHERE kept.chat_id = ?1{})",
+                        table, table, key
+                    ))
+                    .bind(new_chat_id)
+                    .bind(old_chat_id)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                sqlx::query(&format!("UPDATE {} SET chat_id = ?1 WHERE chat_id = ?2", table))
+                    .bind(new_chat_id)
+                    .bind(old_chat_id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected()
+            };
+            moved.push((table, rows));
+        }
+        // The migration is announced in the new chat, so the bot is there
+        sqlx::query("UPDATE chat_settings SET frozen = FALSE WHERE chat_id = ?")
+            .bind(new_chat_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(moved)
+    }
+
+    /// Freezes the markets of a chat the bot was removed from, or thaws them
+    /// when it is added back.
+    pub async fn set_chat_frozen(&self, chat_id: i64, frozen: bool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_settings (chat_id, frozen)
+            VALUES (?1, ?2)
+            ON CONFLICT(chat_id) DO UPDATE SET frozen = excluded.frozen
+            "#,
+        )
+        .bind(chat_id)
+        .bind(frozen)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn is_chat_frozen(&self, chat_id: i64) -> Result<bool> {
+        let frozen = sqlx::query_scalar::<_, bool>(
+            "SELECT frozen FROM chat_settings WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(frozen.unwrap_or(false))
+    }
+
+    /// Whether group messages of the chat are kept as `/solve` context.
+    pub async fn set_message_buffer(&self, chat_id: i64, enabled: bool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_settings (chat_id, message_buffer)
+            VALUES (?1, ?2)
+            ON CONFLICT(chat_id) DO UPDATE SET message_buffer = excluded.message_buffer
+            "#,
+        )
+        .bind(chat_id)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_message_buffer(&self, chat_id: i64) -> Result<bool> {
+        let enabled = sqlx::query_scalar::<_, bool>(
+            "SELECT message_buffer FROM chat_settings WHERE chat_id = ?",
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(enabled.unwrap_or(false))
+    }
+
+    /// Records that `user_id` does, or no longer does, refuse to have their
+    /// messages kept.
+    pub async fn set_privacy_optout(&self, user_id: i64, opted_out: bool) -> Result<()> {
+        if opted_out {
+            sqlx::query("INSERT OR IGNORE INTO privacy_optouts (user_id, opted_out_at) VALUES (?1, ?2)")
+                .bind(user_id)
+                .bind(chrono::Utc::now().to_rfc3339())
+                .execute(&self.pool)
+                .await?;
+        } else {
+            sqlx::query("DELETE FROM privacy_optouts WHERE user_id = ?")
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn is_privacy_opted_out(&self, user_id: i64) -> Result<bool> {
+        let opted_out = sqlx::query_scalar::<_, i64>("SELECT 1 FROM privacy_optouts WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(opted_out.is_some())
+    }
+
+    /// Records that `user_id` just saw `yes_bps` as the odds of `bet_id`,
+    /// watching it for moves from now on.
+    pub async fn watch_odds(&self, user_id: i64, bet_id: i64, yes_bps: u32) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO odds_alerts (user_id, bet_id, yes_bps) VALUES (?1, ?2, ?3)
+             ON CONFLICT(user_id, bet_id) DO UPDATE SET yes_bps = excluded.yes_bps",
+        )
+        .bind(user_id)
+        .bind(bet_id)
+        .bind(yes_bps as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Bettors of `bet_id` watching its odds, leaving out those who turned
+    /// alerts off.
+    pub async fn get_odds_watches(&self, bet_id: i64) -> Result<Vec<OddsWatch>> {
+        let watches = sqlx::query_as::<_, OddsWatch>(
+            "SELECT user_id, yes_bps, notified_at FROM odds_alerts
+             WHERE bet_id = ? AND user_id NOT IN (SELECT user_id FROM alert_optouts)",
+        )
+        .bind(bet_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(watches)
+    }
+
+    pub async fn record_odds_alert(&self, user_id: i64, bet_id: i64, yes_bps: u32, notified_at: &str) -> Result<()> {
+        sqlx::query("UPDATE odds_alerts SET yes_bps = ?1, notified_at = ?2 WHERE user_id = ?3 AND bet_id = ?4")
+            .bind(yes_bps as i64)
+            .bind(notified_at)
+            .bind(user_id)
+            .bind(bet_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_alerts_optout(&self, user_id: i64, opted_out: bool) -> Result<()> {
+        if opted_out {
+            sqlx::query("INSERT OR IGNORE INTO alert_optouts (user_id, opted_out_at) VALUES (?1, ?2)")
+                .bind(user_id)
+                .bind(chrono::Utc::now().to_rfc3339())
+                .execute(&self.pool)
+                .await?;
+        } else {
+            sqlx::query("DELETE FROM alert_optouts WHERE user_id = ?")
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn is_alerts_opted_out(&self, user_id: i64) -> Result<bool> {
+        let opted_out = sqlx::query_scalar::<_, i64>("SELECT 1 FROM alert_optouts WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(opted_out.is_some())
+    }
+
+    pub async fn is_user_initialized(&self, user_id: i64) -> Result<bool> {
+        let result = sqlx::query_scalar::<_, bool>(
+            "SELECT initialized FROM user_init_status WHERE user_id = ?"
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(result.unwrap_or(false))
+    }
+
+    pub async fn mark_user_initialized(&self, user_id: i64) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            INSERT INTO user_init_status (user_id, initialized, initialized_at)
+            VALUES (?1, TRUE, ?2)
+            ON CONFLICT(user_id) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Moves bets resolved before `cutoff` (RFC 3339) together with their
+    /// wagers and solutions into the archive tables. Open bets are never archived.
+    /// Returns the number of archived bets.
+    pub async fn archive_resolved_bets(&self, cutoff: &str) -> Result<u64> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+
+        let selection = "SELECT bet_id FROM bets WHERE status IN ('resolved_yes', 'resolved_no') AND resolved_at < ?1";
+
+        sqlx::query(&format!(
+            r#"
+            INSERT OR REPLACE INTO solutions_archive (solution_id, bet_id, solver_id, message_id, created_at)
+            SELECT solution_id, bet_id, solver_id, message_id, created_at FROM solutions
+            WHERE bet_id IN ({})
+            "#,
+            selection
+        ))
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(&format!(
+            r#"
+            INSERT OR REPLACE INTO wagers_archive (wager_id, bet_id, user_id, amount, side, created_at)
+            SELECT wager_id, bet_id, user_id, amount, side, created_at FROM wagers
+            WHERE bet_id IN ({})
+            "#,
+            selection
+        ))
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO bets_archive (bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id, archived_at)
+            SELECT bet_id, creator_id, chat_id, description, created_at, status, deadline, epoch, market_id, ?2 FROM bets
+            WHERE status IN ('resolved_yes', 'resolved_no') AND resolved_at < ?1
+            "#,
+        )
+        .bind(cutoff)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(&format!("DELETE FROM solutions WHERE bet_id IN ({})", selection))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(&format!("DELETE FROM wagers WHERE bet_id IN ({})", selection))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(&format!("DELETE FROM bet_announcements WHERE bet_id IN ({})", selection))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(&format!("DELETE FROM live_announcements WHERE bet_id IN ({})", selection))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(&format!("DELETE FROM poll_markets WHERE bet_id IN ({})", selection))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(&format!("DELETE FROM odds_alerts WHERE bet_id IN ({})", selection))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+
+        let archived = sqlx::query(
+            "DELETE FROM bets WHERE status IN ('resolved_yes', 'resolved_no') AND resolved_at < ?1",
+        )
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+        Ok(archived)
+    }
+
+    /// Applies the retention policy and reclaims the freed space.
+    pub async fn run_retention(&self, policy: &RetentionPolicy) -> Result<u64> {
+        let cutoff = (chrono::Utc::now() - chrono::Duration::days(policy.retention_days)).to_rfc3339();
+        let archived = self.archive_resolved_bets(&cutoff).await?;
+
+        if archived > 0 {
+            sqlx::query("VACUUM").execute(&self.pool).await?;
+        }
+        Ok(archived)
+    }
+
+    /// Number of open bets of the contract epoch `epoch` across chats.
+    /// Makes `bet_id` a challenge of `opponent_id` and escrows the creator's
+    /// `stake` on YES.
+    pub async fn open_challenge(&self, bet_id: i64, opponent_id: i64, stake: i64, expires_at: &str) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO challenges (bet_id, opponent_id, stake, expires_at) VALUES (?1, ?2, ?3, ?4)")
+            .bind(bet_id)
+            .bind(opponent_id)
+            .bind(stake)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO wagers (bet_id, user_id, amount, side, created_at)
+            SELECT bet_id, creator_id, ?2, TRUE, ?3 FROM bets WHERE bet_id = ?1
+            "#,
+        )
+        .bind(bet_id)
+        .bind(stake)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE users SET balance = balance - ?2 WHERE user_id = (SELECT creator_id FROM bets WHERE bet_id = ?1)")
+            .bind(bet_id)
+            .bind(stake)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_challenge(&self, bet_id: i64) -> Result<Option<Challenge>> {
+        let challenge = sqlx::query_as::<_, Challenge>(&format!("{} WHERE c.bet_id = ?", CHALLENGE_SELECT))
+            .bind(bet_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(challenge)
+    }
+
+    /// Pending challenges whose acceptance window closed before `now` (RFC 3339).
+    pub async fn get_expired_challenges(&self, now: &str) -> Result<Vec<Challenge>> {
+        let challenges = sqlx::query_as::<_, Challenge>(&format!(
+            "{} WHERE c.status = 'pending' AND c.expires_at <= ? ORDER BY c.bet_id",
+            CHALLENGE_SELECT
+        ))
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(challenges)
+    }
+
+    /// Escrows the opponent's matching stake on NO. Returns false when the
+    /// challenge was no longer pending.
+    pub async fn accept_challenge(&self, bet_id: i64) -> Result<bool> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        let accepted = sqlx::query("UPDATE challenges SET status = 'accepted' WHERE bet_id = ? AND status = 'pending'")
+            .bind(bet_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if accepted {
+            sqlx::query(
+                "INSERT INTO wagers (bet_id, user_id, amount, side, created_at) SELECT bet_id, opponent_id, stake, FALSE, ?2 FROM challenges WHERE bet_id = ?1",
+            )
+            .bind(bet_id)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "UPDATE users SET balance = balance - (SELECT stake FROM challenges WHERE bet_id = ?1) WHERE user_id = (SELECT opponent_id FROM challenges WHERE bet_id = ?1)",
+            )
+            .bind(bet_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(accepted)
+    }
+
+    /// Calls off a pending challenge: the creator gets the stake back and the
+    /// bet is marked cancelled. Returns false when it was no longer pending.
+    pub async fn cancel_challenge(&self, bet_id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let cancelled = sqlx::query("UPDATE challenges SET status = 'cancelled' WHERE bet_id = ? AND status = 'pending'")
+            .bind(bet_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            > 0;
+        if cancelled {
+            sqlx::query(
+                "UPDATE users SET balance = balance + (SELECT stake FROM challenges WHERE bet_id = ?1) WHERE user_id = (SELECT creator_id FROM bets WHERE bet_id = ?1)",
+            )
+            .bind(bet_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("DELETE FROM wagers WHERE bet_id = ?")
+                .bind(bet_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE bets SET status = 'cancelled' WHERE bet_id = ?")
+                .bind(bet_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(cancelled)
+    }
+
+    pub async fn count_open_bets(&self, epoch: Option<u64>) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM bets WHERE status IN ('open', 'resolving') AND (epoch IS NULL OR epoch = ?1)",
+        )
+        .bind(epoch.map(|epoch| epoch as i64))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// Ends the current season: records the `standings` richest users, sets
+    /// every balance back to `initial_balance` and archives the resolved bets.
+    /// Returns the final standings, numbered by the ended season.
+    pub async fn end_season(&self, initial_balance: i64, standings: i64) -> Result<Vec<SeasonStanding>> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+
+        let season_id = sqlx::query("INSERT INTO seasons (ended_at) VALUES (?1)")
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+
+        sqlx::query(
+            r#"
+            INSERT INTO season_standings (season_id, rank, user_id, username, balance)
+            SELECT ?1, ROW_NUMBER() OVER (ORDER BY balance DESC, user_id), user_id, username, balance FROM users
+            WHERE registered
+            ORDER BY balance DESC, user_id
+            LIMIT ?2
+            "#,
+        )
+        .bind(season_id)
+        .bind(standings)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE users SET balance = ?1 WHERE registered")
+            .bind(initial_balance)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        // Everything still live was created during the season
+        let cutoff = (chrono::Utc::now() + chrono::Duration::seconds(1)).to_rfc3339();
+        self.archive_resolved_bets(&cutoff).await?;
+
+        self.get_season_standings(season_id, standings).await
+    }
+
+    /// The first `limit` places of the season `season_id`.
+    pub async fn get_season_standings(&self, season_id: i64, limit: i64) -> Result<Vec<SeasonStanding>> {
+        let standings = sqlx::query_as::<_, SeasonStanding>(
+            r#"
+            SELECT s.season_id, s.ended_at, st.rank, st.user_id, st.username, st.balance
+            FROM season_standings st JOIN seasons s ON s.season_id = st.season_id
+            WHERE st.season_id = ?1 AND st.rank <= ?2
+            ORDER BY st.rank
+            "#,
+        )
+        .bind(season_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(standings)
+    }
+
+    /// The podium of the `seasons` most recently ended seasons, latest first.
+    pub async fn get_hall_of_fame(&self, seasons: i64) -> Result<Vec<SeasonStanding>> {
+        let standings = sqlx::query_as::<_, SeasonStanding>(
+            r#"
+            SELECT s.season_id, s.ended_at, st.rank, st.user_id, st.username, st.balance
+            FROM season_standings st JOIN seasons s ON s.season_id = st.season_id
+            WHERE st.rank <= 3 AND s.season_id IN (SELECT season_id FROM seasons ORDER BY season_id DESC LIMIT ?1)
+            ORDER BY s.season_id DESC, st.rank
+            "#,
+        )
+        .bind(seasons)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(standings)
+    }
+
+    pub async fn reset_all(&self) -> Result<()> {
+        sqlx::query("DELETE FROM solutions")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM settlements")
             .execute(&self.pool)
             .await?;
         
@@ -324,9 +2618,15 @@ impl Database {
         sqlx::query("DELETE FROM user_init_status")
             .execute(&self.pool)
             .await?;
+
+        for table in ["solutions_archive", "wagers_archive", "bets_archive", "resolution_cache", "bet_announcements", "live_announcements", "poll_markets", "inline_impressions", "season_standings", "seasons", "challenges", "balance_adjustments", "odds_alerts"] {
+            sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(&self.pool)
+                .await?;
+        }
         
         // Reset autoincrement counters
-        sqlx::query("DELETE FROM sqlite_sequence WHERE name IN ('bets', 'solutions', 'wagers')")
+        sqlx::query("DELETE FROM sqlite_sequence WHERE name IN ('bets', 'solutions', 'wagers', 'seasons')")
             .execute(&self.pool)
             .await?;
         
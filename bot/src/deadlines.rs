@@ -0,0 +1,245 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use teloxide::types::ChatId;
+
+use crate::api_client::MarketApiError;
+use crate::challenges;
+use crate::db::{DeadlineBet, DeadlineStage};
+use crate::markdown;
+use crate::messenger::Messenger;
+use crate::timezone;
+use crate::webhook::OwnAction;
+use crate::{BotContext, HandlerResult};
+
+/// When the deadline scheduler runs and how long a bet whose deadline passed
+/// waits for an accepted solution before the chat is asked to settle it.
+#[derive(Debug, Clone)]
+pub struct DeadlineConfig {
+    pub interval: Duration,
+    pub grace: chrono::Duration,
+}
+
+impl Default for DeadlineConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            grace: chrono::Duration::hours(24),
+        }
+    }
+}
+
+impl DeadlineConfig {
+    /// Reads `DEADLINE_CHECK_INTERVAL_SECS` and `DEADLINE_GRACE_HOURS`, falling back to the defaults.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+
+        if let Ok(value) = std::env::var("DEADLINE_CHECK_INTERVAL_SECS") {
+            config.interval = Duration::from_secs(value.parse()?);
+        }
+        if let Ok(value) = std::env::var("DEADLINE_GRACE_HOURS") {
+            config.grace = chrono::Duration::hours(value.parse()?);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Runs [`handle_deadlines`] every `ctx.deadlines.interval`, together with
+/// the expiry of unanswered challenges and of forgotten markets.
+pub fn spawn(bot: Messenger, ctx: Arc<BotContext>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ctx.deadlines.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = handle_deadlines(&bot, &ctx, Utc::now()).await {
+                log::error!("Deadline job failed: {}", e);
+            }
+            if let Err(e) = challenges::cancel_expired(&bot, &ctx, Utc::now()).await {
+                log::error!("Challenge expiry job failed: {}", e);
+            }
+            if let Err(e) = void_forgotten(&bot, &ctx, Utc::now()).await {
+                log::error!("Market lifetime job failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Moves every open bet whose deadline passed before `now` one stage further:
+/// betting is closed on-chain at the deadline, and once the grace period is
+/// over the chat is prompted, or the bet resolves NO if the chat enabled
+/// /autoexpire. Each stage is persisted as soon as it is done, so a bet is
+/// never handled twice, even across restarts; a failed step is retried on the
+/// next run.
+pub async fn handle_deadlines(bot: &Messenger, ctx: &BotContext, now: DateTime<Utc>) -> HandlerResult {
+    for bet in ctx.db.get_unhandled_deadlines().await? {
+        let Ok(deadline) = DateTime::parse_from_rfc3339(&bet.deadline) else {
+            log::warn!("Bet #{} has an unreadable deadline: {}", bet.bet_id, bet.deadline);
+            continue;
+        };
+        let deadline = deadline.with_timezone(&Utc);
+        if deadline > now {
+            continue;
+        }
+        if let Err(e) = advance(bot, ctx, &bet, deadline, now).await {
+            log::error!("Failed to handle the deadline of bet #{}: {}", bet.bet_id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn advance(
+    bot: &Messenger,
+    ctx: &BotContext,
+    bet: &DeadlineBet,
+    deadline: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> HandlerResult {
+    let market_id = bet.on_chain_id();
+    let mut stage = bet.stage();
+
+    if stage == DeadlineStage::Pending {
+        match ctx.api_client.close_betting(bet.creator_id.to_string(), market_id, &ctx.contract_name).await {
+            Ok(receipt) => log::info!("Closed betting on market #{} with tx {}", market_id, receipt.tx_hash),
+            // Nothing left to close on-chain, e.g. the market was resolved meanwhile
+            Err(e) if matches!(e.kind(), MarketApiError::ContractRejected { .. }) => {
+                log::warn!("Could not close betting on market #{}: {}", market_id, e);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        ctx.db.set_deadline_stage(bet.bet_id, DeadlineStage::BettingClosed).await?;
+        stage = DeadlineStage::BettingClosed;
+        if let Some(chat_id) = bet.chat_id {
+            let reminder = bot
+                .send_message(ChatId(chat_id), format!("⏰ Betting closed on #{}: {}", bet.bet_id, bet.description))
+                .await?;
+            ctx.db.record_announcement(chat_id, reminder.0 as i64, bet.bet_id).await?;
+        }
+    }
+
+    if stage == DeadlineStage::BettingClosed && now >= deadline + ctx.deadlines.grace {
+        let auto_expire = match bet.chat_id {
+            Some(chat_id) => ctx.db.get_auto_expire(chat_id).await?,
+            None => false,
+        };
+        if auto_expire {
+            expire(bot, ctx, bet).await?;
+        } else {
+            ctx.db.set_deadline_stage(bet.bet_id, DeadlineStage::Handled).await?;
+            if let Some(chat_id) = bet.chat_id {
+                let tz = ctx.db.get_timezone(chat_id).await?;
+                let reminder = bot
+                    .send_message(
+                        ChatId(chat_id),
+                        format!(
+                            "⏰ Bet #{} passed its deadline {} without an accepted solution: {}\nReply to a proof with /solve {} force, or an admin can settle it as NO with /expire {}.",
+                            bet.bet_id,
+                            timezone::humanize(deadline, now, tz),
+                            bet.description,
+                            bet.bet_id,
+                            bet.bet_id
+                        ),
+                    )
+                    .await?;
+                ctx.db.record_announcement(chat_id, reminder.0 as i64, bet.bet_id).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves `bet` as NO on behalf of its creator.
+async fn expire(bot: &Messenger, ctx: &BotContext, bet: &DeadlineBet) -> HandlerResult {
+    let market_id = bet.on_chain_id();
+    let own_resolution = OwnAction::Resolve { market_id };
+    ctx.own_actions.record(own_resolution.clone());
+    let receipt = match ctx.api_client.resolve_market(bet.creator_id.to_string(), market_id, false, &ctx.contract_name).await {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            ctx.own_actions.take(&own_resolution);
+            // A rejection will not go away by retrying: leave the bet to the chat
+            if matches!(e.kind(), MarketApiError::ContractRejected { .. }) {
+                log::warn!("Could not expire market #{}: {}", market_id, e);
+                ctx.db.set_deadline_stage(bet.bet_id, DeadlineStage::Handled).await?;
+                return Ok(());
+            }
+            return Err(e.into());
+        }
+    };
+
+    ctx.db.close_bet(bet.bet_id, false).await?;
+    ctx.db.set_deadline_stage(bet.bet_id, DeadlineStage::Handled).await?;
+    log::info!("Market #{} expired with tx {}", market_id, receipt.tx_hash);
+    if let Some(chat_id) = bet.chat_id {
+        bot.send_markdown(
+            ChatId(chat_id),
+            format!(
+                "{}\n\n{}",
+                markdown::bold("✅ MARKET EXPIRED"),
+                markdown::escape(&format!(
+                    "📊 Market #{}\n📄 Description: {}\n🎯 Outcome: NO ❌\n⏰ No solution was accepted within {} of the deadline.\n\nTransaction: {}",
+                    bet.bet_id,
+                    bet.description,
+                    format_grace(ctx.deadlines.grace),
+                    receipt.tx_hash
+                ))
+            ),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Voids the markets still open `MAX_MARKET_LIFETIME` after their creation,
+/// on behalf of their creators, and tells their chats every stake was
+/// refunded. A market the contract refuses to void, e.g. one created before
+/// markets recorded their creation time, is not asked about again.
+pub async fn void_forgotten(bot: &Messenger, ctx: &BotContext, now: DateTime<Utc>) -> HandlerResult {
+    let lifetime = chrono::Duration::seconds(contract1::MAX_MARKET_LIFETIME as i64);
+    let created_before = (now - lifetime).to_rfc3339();
+    for bet in ctx.db.get_forgotten_bets(&created_before, ctx.state_epoch).await? {
+        let market_id = bet.on_chain_id();
+        let receipt = match ctx.api_client.expire_market(bet.creator_id.to_string(), market_id, &ctx.contract_name).await {
+            Ok(receipt) => receipt,
+            Err(e) if matches!(e.kind(), MarketApiError::ContractRejected { .. }) => {
+                log::warn!("Could not void market #{}: {}", market_id, e);
+                ctx.db.mark_expiry_refused(bet.bet_id).await?;
+                continue;
+            }
+            Err(e) => {
+                log::error!("Failed to void market #{}: {}", market_id, e);
+                continue;
+            }
+        };
+        if !ctx.db.void_bet(bet.bet_id).await? {
+            continue;
+        }
+        log::info!("Market #{} voided after its maximum lifetime with tx {}", market_id, receipt.tx_hash);
+        if let Some(chat_id) = bet.chat_id {
+            bot.send_message(
+                ChatId(chat_id),
+                format!(
+                    "⌛ Bet #{} expired after {} days without a resolution: {}\n💸 Every stake was refunded.\nTransaction: {}",
+                    bet.bet_id,
+                    lifetime.num_days(),
+                    bet.description,
+                    receipt.tx_hash
+                ),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+fn format_grace(grace: chrono::Duration) -> String {
+    let minutes = grace.num_minutes();
+    match minutes {
+        60 => "1 hour".to_string(),
+        _ if minutes > 0 && minutes % 60 == 0 => format!("{} hours", minutes / 60),
+        _ => format!("{} minutes", minutes),
+    }
+}
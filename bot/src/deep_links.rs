@@ -0,0 +1,79 @@
+use crate::currency::{format_amount, Currency};
+use crate::db::Bet;
+use crate::messenger::Button;
+
+/// Prefix of the `/start` payload opening a market in a private chat.
+const START_PREFIX: &str = "bet_";
+
+/// Longest `/start` payload Telegram passes on.
+const MAX_PAYLOAD_LEN: usize = 64;
+
+/// Prefix of the callback data of the stake buttons under a private market card.
+const CALLBACK_PREFIX: &str = "dmbet:";
+
+/// Stakes offered as buttons under a private market card.
+pub const QUICK_STAKES: [i64; 2] = [100, 500];
+
+/// `bet_<chat>_<bet>`: the `/start` payload naming bet `bet_id` of `chat_id`.
+pub fn start_payload(chat_id: i64, bet_id: i64) -> String {
+    format!("{}{}_{}", START_PREFIX, chat_id, bet_id)
+}
+
+/// Link opening a private chat with the bot on bet `bet_id` of `chat_id`.
+pub fn start_link(bot_username: &str, chat_id: i64, bet_id: i64) -> String {
+    format!("https://t.me/{}?start={}", bot_username, start_payload(chat_id, bet_id))
+}
+
+/// The chat and bet a `/start` payload names, when it is one
+/// [`start_payload`] would have written.
+pub fn parse_start(payload: &str) -> Option<(i64, i64)> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return None;
+    }
+    let (chat_id, bet_id) = payload.strip_prefix(START_PREFIX)?.split_once('_')?;
+    let (chat_id, bet_id) = (chat_id.parse().ok()?, bet_id.parse().ok()?);
+    // Rules out `+5`, `007` and the like, which no announcement links to
+    let canonical = start_payload(chat_id, bet_id) == payload;
+    (canonical && chat_id != 0 && bet_id > 0).then_some((chat_id, bet_id))
+}
+
+/// Callback data of the button staking `amount` on `side` of bet `bet_id`.
+pub fn callback_data(bet_id: i64, side: bool, amount: i64) -> String {
+    format!("{}{}:{}:{}", CALLBACK_PREFIX, bet_id, if side { "yes" } else { "no" }, amount)
+}
+
+/// The bet, side and stake of a stake button.
+pub fn parse_callback(data: &str) -> Option<(i64, bool, i64)> {
+    let mut parts = data.strip_prefix(CALLBACK_PREFIX)?.split(':');
+    let (Some(bet_id), Some(side), Some(amount), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    let side = match side {
+        "yes" => true,
+        "no" => false,
+        _ => return None,
+    };
+    Some((bet_id.parse().ok()?, side, amount.parse().ok()?))
+}
+
+/// The card of an open market shown in a private chat, with a button per
+/// side and stake of [`QUICK_STAKES`].
+pub fn card(bet: &Bet, yes_pool: u128, no_pool: u128, currency: &Currency) -> (String, Vec<Button>) {
+    let text = format!(
+        "📊 Market #{}\n📄 {}\n✅ YES pool: {}\n❌ NO pool: {}\n\nPick a stake below, or send /bet {} yes|no <amount> here. Your bet counts in the group's market.",
+        bet.bet_id,
+        bet.description,
+        format_amount(currency, yes_pool),
+        format_amount(currency, no_pool),
+        bet.bet_id
+    );
+    let buttons = [true, false]
+        .into_iter()
+        .flat_map(|side| QUICK_STAKES.map(|stake| (side, stake)))
+        .map(|(side, stake)| {
+            let label = format!("{} {}", if side { "✅ YES" } else { "❌ NO" }, format_amount(currency, stake as u128));
+            (label, callback_data(bet.bet_id, side, stake))
+        })
+        .collect();
+    (text, buttons)
+}
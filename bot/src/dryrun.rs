@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+use async_trait::async_trait;
+use contract1::api::{
+    ClaimResult, ContractParams, CreatedMarket, InitializeOutcome, MarketFilter, MarketHistoryPoint, MarketSummary, Odds,
+    OpenExposure, ReconcileReport, ReconcileSnapshot, ResolveResult, TreasuryInfo, UserBetInfo, UserInfo,
+};
+use contract1::{Challenge, Contract1, MarketAction};
+use reqwest::StatusCode;
+use sdk::{BlobIndex, Calldata, ContractName, Identity, TimestampMs, TxContext, TxHash, ZkContract};
+use serde::Serialize;
+use sqlx::sqlite::SqliteJournalMode;
+use teloxide::prelude::*;
+use teloxide::types::{BotCommand, BotCommandScope, CallbackQueryId, InlineQueryId, MessageId};
+use teloxide::RequestError;
+
+use crate::api_client::{ConfigResponse, MarketApi, MarketApiError, Result, TxReceipt, TxState, TxStatus};
+use crate::db::{Database, DatabaseConfig};
+use crate::markdown;
+use crate::messenger::{Button, InlineArticle, Transport};
+
+/// First line of every message sent in dry-run mode.
+pub const BANNER: &str = "🧪 DRY RUN: simulated market, nothing here is on-chain";
+
+/// Whether `BOT_MODE` asks for a dry run. Unset or `live` talks to the server.
+pub fn enabled() -> anyhow::Result<bool> {
+    match std::env::var("BOT_MODE").ok().as_deref() {
+        None | Some("live") => Ok(false),
+        Some("dryrun") => Ok(true),
+        Some(mode) => bail!("BOT_MODE must be live or dryrun, not {}", mode),
+    }
+}
+
+/// A database that lives as long as the process, like the simulated
+/// contract it mirrors.
+pub async fn database() -> anyhow::Result<Database> {
+    // A single connection: every `:memory:` connection is its own database
+    let config = DatabaseConfig {
+        max_connections: 1,
+        journal_mode: SqliteJournalMode::Memory,
+        ..DatabaseConfig::default()
+    };
+    Database::with_config("sqlite::memory:", config).await
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default()
+}
+
+/// The market server's API answered in-process: actions are applied to a
+/// local [`Contract1`] as the prover would, stamped with the current time,
+/// and answered with made-up transaction hashes. Everything is lost on exit.
+pub struct DryRunApi {
+    state: Mutex<Contract1>,
+    /// Settled transactions by hash
+    transactions: Mutex<HashMap<String, TxStatus>>,
+    /// Milliseconds the simulated clock runs ahead of the real one
+    clock_offset_ms: AtomicU64,
+}
+
+impl DryRunApi {
+    pub fn new() -> Self {
+        let epoch = (now_ms() / 1000) as u64;
+        Self {
+            state: Mutex::new(Contract1::with_epoch(epoch)),
+            transactions: Mutex::new(HashMap::new()),
+            clock_offset_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Moves the simulated clock `secs` forward, past cooldowns and deadlines.
+    #[cfg(test)]
+    pub fn advance(&self, secs: u64) {
+        self.clock_offset_ms.fetch_add(secs * 1_000, Ordering::Relaxed);
+    }
+
+    fn now_ms(&self) -> u128 {
+        now_ms() + self.clock_offset_ms.load(Ordering::Relaxed) as u128
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, Contract1> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs `action` for `user_id` and, when `result` reads something from
+    /// the resulting state, sends it with the hash like the server does.
+    fn submit<T: Serialize>(
+        &self,
+        user_id: &str,
+        contract_name: &str,
+        action: MarketAction,
+        result: impl FnOnce(&Contract1) -> Option<T>,
+    ) -> Result<TxReceipt> {
+        let mut transactions = self.transactions.lock().unwrap_or_else(|e| e.into_inner());
+        // Counted, so nobody mistakes them for real hashes
+        let tx_hash = format!("{:064x}", transactions.len() + 1);
+        let calldata = Calldata {
+            tx_hash: TxHash(tx_hash.clone()),
+            identity: identity(user_id, contract_name),
+            blobs: vec![action.as_blob(ContractName(contract_name.to_string()))].into(),
+            tx_blob_count: 1,
+            index: BlobIndex(0),
+            tx_ctx: Some(TxContext {
+                timestamp: TimestampMs(self.now_ms()),
+                ..TxContext::default()
+            }),
+            private_input: vec![],
+        };
+
+        let mut state = self.state();
+        state
+            .execute(&calldata)
+            .map_err(|message| MarketApiError::ContractRejected { message })?;
+        let result = result(&state).and_then(|result| serde_json::to_value(result).ok());
+        transactions.insert(
+            tx_hash.clone(),
+            TxStatus { tx_hash: tx_hash.clone(), status: TxState::Success, error: None },
+        );
+        Ok(TxReceipt { tx_hash, result })
+    }
+
+    fn send(&self, user_id: &str, contract_name: &str, action: MarketAction) -> Result<TxReceipt> {
+        self.submit(user_id, contract_name, action, |_| None::<()>)
+    }
+}
+
+impl Default for DryRunApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn identity(user_id: &str, contract_name: &str) -> Identity {
+    Identity(format!("{}@{}", user_id, contract_name))
+}
+
+fn not_found(what: &str) -> MarketApiError {
+    MarketApiError::ServerError { status: StatusCode::NOT_FOUND, body: format!("{} not found", what) }
+}
+
+#[async_trait]
+impl MarketApi for DryRunApi {
+    async fn get_config(&self) -> Result<ConfigResponse> {
+        Ok(ConfigResponse {
+            contract_name: "contract1".to_string(),
+            api_version: 2,
+            params: ContractParams::default(),
+            state_epoch: Some(self.state().state_epoch),
+            paused: self.state().paused,
+        })
+    }
+
+    async fn initialize_user(&self, user_id: String, contract_name: &str) -> Result<TxReceipt> {
+        let identity = identity(&user_id, contract_name);
+        let already_initialized = self.state().users.get(&identity).is_some_and(|user| user.initialized);
+        let action = MarketAction::Initialize { idempotent: true };
+        self.submit(&user_id, contract_name, action, move |state| {
+            state.users.get(&identity).map(|user| InitializeOutcome { balance: user.balance, already_initialized })
+        })
+    }
+
+    async fn create_market(&self, user_id: String, description: String, tags: Vec<String>, contract_name: &str) -> Result<TxReceipt> {
+        let action = MarketAction::CreateMarket { description, opens_at: None, stake_cap: None, tags, challenge: None };
+        self.submit(&user_id, contract_name, action, |state| Some(CreatedMarket { market_id: state.next_market_id }))
+    }
+
+    async fn create_challenge(&self, user_id: String, opponent: String, stake: u128, description: String, contract_name: &str) -> Result<TxReceipt> {
+        let challenge = Challenge { opponent: identity(&opponent, contract_name), stake };
+        let action = MarketAction::CreateMarket { description, opens_at: None, stake_cap: None, tags: Vec::new(), challenge: Some(challenge) };
+        self.submit(&user_id, contract_name, action, |state| Some(CreatedMarket { market_id: state.next_market_id }))
+    }
+
+    async fn accept_challenge(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::AcceptChallenge { market_id })
+    }
+
+    async fn cancel_challenge(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::CancelChallenge { market_id })
+    }
+
+    async fn place_bet(&self, user_id: String, market_id: u64, side: bool, amount: u128, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::PlaceBet { market_id, side, amount })
+    }
+
+    async fn resolve_market(&self, user_id: String, market_id: u64, outcome: bool, contract_name: &str) -> Result<TxReceipt<ResolveResult>> {
+        let action = MarketAction::ResolveMarket { market_id, outcome };
+        self.submit(&user_id, contract_name, action, |state| state.resolve_result(market_id)).map(TxReceipt::decode)
+    }
+
+    async fn close_betting(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::CloseBetting { market_id })
+    }
+
+    async fn expire_market(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::ExpireMarket { market_id })
+    }
+
+    async fn claim_winnings(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt<ClaimResult>> {
+        let identity = identity(&user_id, contract_name);
+        let action = MarketAction::ClaimWinnings { market_id };
+        self.submit(&user_id, contract_name, action, |state| state.claim_result(&identity, market_id)).map(TxReceipt::decode)
+    }
+
+    async fn get_balance(&self, user_id: String, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::GetBalance)
+    }
+
+    async fn get_open_exposure(&self, user_id: String, contract_name: &str) -> Result<TxReceipt<OpenExposure>> {
+        let identity = identity(&user_id, contract_name);
+        self.submit(&user_id, contract_name, MarketAction::GetOpenExposure, |state| Some(state.open_exposure(&identity)))
+            .map(TxReceipt::decode)
+    }
+
+    async fn get_market_info(&self, user_id: String, market_id: u64, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::GetMarketInfo { market_id })
+    }
+
+    async fn add_comment(&self, user_id: String, market_id: u64, text: String, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::AddComment { market_id, text })
+    }
+
+    async fn set_admin(&self, user_id: String, new_admin: String, contract_name: &str) -> Result<TxReceipt> {
+        let new_admin = identity(&new_admin, contract_name);
+        self.send(&user_id, contract_name, MarketAction::SetAdmin { new_admin })
+    }
+
+    async fn withdraw_treasury(&self, user_id: String, to: String, amount: u128, contract_name: &str) -> Result<TxReceipt> {
+        let to = identity(&to, contract_name);
+        self.send(&user_id, contract_name, MarketAction::WithdrawTreasury { to, amount })
+    }
+
+    async fn reset_balances(&self, user_id: String, contract_name: &str) -> Result<TxReceipt> {
+        self.send(&user_id, contract_name, MarketAction::ResetBalances)
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn list_markets(&self, filter: &MarketFilter, _contract_name: &str) -> Result<Vec<MarketSummary>> {
+        Ok(self.state().list_markets(filter, (self.now_ms() / 1000) as u64))
+    }
+
+    async fn get_odds(&self, market_id: u64, _contract_name: &str) -> Result<Odds> {
+        self.state().odds(market_id).ok_or_else(|| not_found("Market"))
+    }
+
+    async fn get_market_history(&self, market_id: u64, _contract_name: &str) -> Result<Vec<MarketHistoryPoint>> {
+        self.state().market_history(market_id).ok_or_else(|| not_found("Market"))
+    }
+
+    async fn get_leaderboard(&self, limit: u32, _contract_name: &str) -> Result<Vec<(String, u128)>> {
+        let entries = self.state().leaderboard(limit as usize);
+        Ok(entries.into_iter().map(|e| (e.identity, e.balance)).collect())
+    }
+
+    async fn get_user_bets(&self, user_id: String, contract_name: &str) -> Result<Vec<UserBetInfo>> {
+        Ok(self.state().user_bets(&identity(&user_id, contract_name)))
+    }
+
+    async fn get_user(&self, user_id: String, contract_name: &str) -> Result<UserInfo> {
+        Ok(self.state().user_info(&identity(&user_id, contract_name)))
+    }
+
+    async fn get_treasury(&self, _contract_name: &str) -> Result<TreasuryInfo> {
+        Ok(self.state().treasury_info())
+    }
+
+    async fn reconcile(&self, snapshot: &ReconcileSnapshot) -> Result<ReconcileReport> {
+        Ok(self.state().reconcile(snapshot))
+    }
+
+    async fn tx_status(&self, tx_hash: &str) -> Result<TxStatus> {
+        let transactions = self.transactions.lock().unwrap_or_else(|e| e.into_inner());
+        transactions.get(tx_hash).cloned().ok_or_else(|| not_found("Transaction"))
+    }
+}
+
+/// Puts [`BANNER`] on top of every message and edit, so simulated balances
+/// are never taken for real ones.
+pub struct BannerTransport(pub Arc<dyn Transport>);
+
+fn with_banner(text: String) -> String {
+    format!("{}\n\n{}", BANNER, text)
+}
+
+fn with_markdown_banner(text: String) -> String {
+    format!("{}\n\n{}", markdown::escape(BANNER), text)
+}
+
+#[async_trait]
+impl Transport for BannerTransport {
+    async fn send_text(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError> {
+        self.0.send_text(chat_id, with_banner(text)).await
+    }
+
+    async fn edit_text(&self, chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError> {
+        self.0.edit_text(chat_id, message_id, with_banner(text)).await
+    }
+
+    async fn send_markdown(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError> {
+        self.0.send_markdown(chat_id, with_markdown_banner(text)).await
+    }
+
+    async fn edit_markdown(&self, chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError> {
+        self.0.edit_markdown(chat_id, message_id, with_markdown_banner(text)).await
+    }
+
+    async fn send_buttons(&self, chat_id: ChatId, text: String, buttons: Vec<Button>) -> Result<MessageId, RequestError> {
+        self.0.send_buttons(chat_id, with_banner(text), buttons).await
+    }
+
+    async fn answer_callback(&self, query_id: CallbackQueryId, text: Option<String>) -> Result<(), RequestError> {
+        self.0.answer_callback(query_id, text).await
+    }
+
+    async fn answer_inline(&self, query_id: InlineQueryId, results: Vec<InlineArticle>) -> Result<(), RequestError> {
+        let results = results
+            .into_iter()
+            .map(|article| InlineArticle { text: with_banner(article.text), ..article })
+            .collect();
+        self.0.answer_inline(query_id, results).await
+    }
+
+    async fn chat_administrators(&self, chat_id: ChatId) -> Result<Vec<UserId>, RequestError> {
+        self.0.chat_administrators(chat_id).await
+    }
+
+    async fn chat_membership(&self, chat_id: ChatId, user_id: UserId) -> Result<bool, RequestError> {
+        self.0.chat_membership(chat_id, user_id).await
+    }
+
+    async fn set_commands(&self, scope: BotCommandScope, commands: Vec<BotCommand>) -> Result<(), RequestError> {
+        self.0.set_commands(scope, commands).await
+    }
+}
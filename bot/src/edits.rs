@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use teloxide::types::{ChatId, MessageId};
+
+/// How long a command message is remembered, and so how long editing it can
+/// correct it.
+pub const EDIT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Reply to an edited /bet whose bet already went through.
+pub const ALREADY_PLACED: &str =
+    "✏️ Editing doesn't change a bet: the one from that message was already placed, and bets are final. Send a new /bet to stake more.";
+
+/// What came of a recent command message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutcome {
+    /// Refused, e.g. mistyped, or not a command the bot knows
+    NotExecuted,
+    /// Its bet was submitted, whether or not it has settled yet
+    BetPlaced,
+}
+
+/// Recent command messages and what came of them, so an edit of one can be
+/// run again only when the original did nothing. Entries older than
+/// [`EDIT_WINDOW`] are dropped.
+#[derive(Default)]
+pub struct RecentCommands(Mutex<HashMap<(ChatId, MessageId), (Instant, CommandOutcome)>>);
+
+impl RecentCommands {
+    /// Remembers a command message, unless it is known already.
+    pub fn seen(&self, chat_id: ChatId, message_id: MessageId) {
+        self.seen_at(chat_id, message_id, Instant::now());
+    }
+
+    pub fn seen_at(&self, chat_id: ChatId, message_id: MessageId, at: Instant) {
+        let mut commands = self.0.lock().unwrap();
+        commands.retain(|_, (seen_at, _)| at.saturating_duration_since(*seen_at) < EDIT_WINDOW);
+        commands.entry((chat_id, message_id)).or_insert((at, CommandOutcome::NotExecuted));
+    }
+
+    pub fn bet_placed(&self, chat_id: ChatId, message_id: MessageId) {
+        self.0.lock().unwrap().insert((chat_id, message_id), (Instant::now(), CommandOutcome::BetPlaced));
+    }
+
+    /// `None` for messages never seen, or seen too long ago to tell.
+    pub fn outcome(&self, chat_id: ChatId, message_id: MessageId) -> Option<CommandOutcome> {
+        self.outcome_at(chat_id, message_id, Instant::now())
+    }
+
+    pub fn outcome_at(&self, chat_id: ChatId, message_id: MessageId, now: Instant) -> Option<CommandOutcome> {
+        let commands = self.0.lock().unwrap();
+        let (seen_at, outcome) = commands.get(&(chat_id, message_id))?;
+        (now.saturating_duration_since(*seen_at) < EDIT_WINDOW).then_some(*outcome)
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use teloxide::types::Message;
+
+use crate::claude::EvidenceMessage;
+
+/// A text message seen in a group chat.
+#[derive(Debug, Clone)]
+pub struct LoggedMessage {
+    pub message_id: i32,
+    pub author_id: i64,
+    pub author: String,
+    pub date: DateTime<Utc>,
+    pub text: String,
+}
+
+impl LoggedMessage {
+    pub fn from_message(msg: &Message) -> Option<Self> {
+        let from = msg.from.as_ref()?;
+        Some(Self {
+            message_id: msg.id.0,
+            author_id: from.id.0 as i64,
+            author: from.username.clone().unwrap_or_else(|| from.first_name.clone()),
+            date: msg.date,
+            text: msg.text().or(msg.caption()).unwrap_or("<no text content>").to_string(),
+        })
+    }
+
+    pub fn to_evidence(&self) -> EvidenceMessage {
+        EvidenceMessage {
+            author: self.author.clone(),
+            timestamp: self.date.to_rfc3339(),
+            text: self.text.clone(),
+        }
+    }
+}
+
+/// Keeps the last `capacity` messages of every chat in memory, so `/solve`
+/// can include the messages leading up to the one it replies to. The Bot
+/// API cannot fetch past messages, and the bot only sees regular messages
+/// when its privacy mode is disabled. Only chats that opted in with
+/// `/privacy optin` are recorded, and the cleanup job drops messages past
+/// the retention limit.
+pub struct RecentMessages {
+    capacity: usize,
+    chats: Mutex<HashMap<i64, VecDeque<LoggedMessage>>>,
+}
+
+impl RecentMessages {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            chats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, chat_id: i64, message: LoggedMessage) {
+        let mut chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        let log = chats.entry(chat_id).or_default();
+        log.push_back(message);
+        while log.len() > self.capacity {
+            log.pop_front();
+        }
+    }
+
+    /// Number of messages kept for the chat.
+    pub fn len(&self, chat_id: i64) -> usize {
+        let chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        chats.get(&chat_id).map_or(0, VecDeque::len)
+    }
+
+    /// Drops every message of the chat, e.g. once it stops opting in.
+    pub fn forget_chat(&self, chat_id: i64) {
+        self.chats.lock().unwrap_or_else(|e| e.into_inner()).remove(&chat_id);
+    }
+
+    /// Drops the messages of `author_id` in every chat, returning how many.
+    pub fn forget_author(&self, author_id: i64) -> usize {
+        self.retain(|message| message.author_id != author_id)
+    }
+
+    /// Drops the messages sent before `cutoff`, returning how many.
+    pub fn prune_before(&self, cutoff: DateTime<Utc>) -> usize {
+        self.retain(|message| message.date >= cutoff)
+    }
+
+    fn retain(&self, keep: impl Fn(&LoggedMessage) -> bool) -> usize {
+        let mut chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut removed = 0;
+        for log in chats.values_mut() {
+            let before = log.len();
+            log.retain(&keep);
+            removed += before - log.len();
+        }
+        chats.retain(|_, log| !log.is_empty());
+        removed
+    }
+
+    /// Up to `count` messages by `author_id` sent before `message_id`, oldest first.
+    pub fn preceding_from_author(
+        &self,
+        chat_id: i64,
+        message_id: i32,
+        author_id: i64,
+        count: usize,
+    ) -> Vec<LoggedMessage> {
+        let chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(log) = chats.get(&chat_id) else {
+            return vec![];
+        };
+
+        let mut messages: Vec<LoggedMessage> = log
+            .iter()
+            .rev()
+            .filter(|m| m.author_id == author_id && m.message_id < message_id)
+            .take(count)
+            .cloned()
+            .collect();
+        messages.reverse();
+        messages
+    }
+}
@@ -0,0 +1,45 @@
+use crate::currency::{format_amount, Currency};
+use crate::db::Bet;
+use crate::messenger::InlineArticle;
+
+/// Results answered per inline query; Telegram accepts up to 50.
+pub const MAX_INLINE_RESULTS: usize = 10;
+
+/// Bets fetched per inline query before the membership checks drop some.
+pub const INLINE_CANDIDATES: i64 = 30;
+
+/// Offset Telegram adds to supergroup ids, which their message links leave out.
+const SUPERGROUP_ID_OFFSET: i64 = 1_000_000_000_000;
+
+/// Link to a message of a supergroup, which only its members can open.
+/// Basic groups and private chats have no message links.
+pub fn message_link(chat_id: i64, message_id: i64) -> Option<String> {
+    let internal_id = -chat_id - SUPERGROUP_ID_OFFSET;
+    (internal_id > 0).then(|| format!("https://t.me/c/{}/{}", internal_id, message_id))
+}
+
+/// The card of an open market, linking back to its announcement when the
+/// group has message links.
+pub fn market_card(bet: &Bet, yes_pool: u128, no_pool: u128, currency: &Currency, announcement: Option<(i64, i64)>) -> InlineArticle {
+    InlineArticle {
+        id: format!("bet:{}", bet.bet_id),
+        title: format!("Market #{}: {}", bet.bet_id, bet.description),
+        description: format!(
+            "YES {} · NO {}",
+            format_amount(currency, yes_pool),
+            format_amount(currency, no_pool)
+        ),
+        text: format!(
+            "📊 Market #{}\n📄 {}\n✅ YES pool: {}\n❌ NO pool: {}\n💰 Total pool: {}\n\nBet on it in its group with /bet {} yes|no <amount>",
+            bet.bet_id,
+            bet.description,
+            format_amount(currency, yes_pool),
+            format_amount(currency, no_pool),
+            format_amount(currency, yes_pool.saturating_add(no_pool)),
+            bet.bet_id
+        ),
+        link: announcement
+            .and_then(|(chat_id, message_id)| message_link(chat_id, message_id))
+            .map(|url| ("Open in the group".to_string(), url)),
+    }
+}
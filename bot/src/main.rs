@@ -1,45 +1,512 @@
 use anyhow::Result;
 use teloxide::prelude::*;
 use teloxide::utils::command::BotCommands;
-use teloxide::types::ChatKind;
+use teloxide::types::{ChatKind, ChatMemberUpdated, InlineQuery, Poll};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
 mod db;
+mod alerts;
+mod announcements;
+mod broadcast;
+mod challenges;
 mod claude;
+mod command_menu;
+mod currency;
+mod deadlines;
+mod deep_links;
+mod dryrun;
+mod edits;
 mod api_client;
-use db::Database;
-use api_client::MarketApiClient;
+mod history;
+mod inline;
+mod market_ids;
+mod markdown;
+mod membership;
+mod mentions;
+mod messenger;
+mod onboarding;
+mod operations;
+mod parse;
+mod polls;
+mod preview;
+mod send_queue;
+mod suggestions;
+mod timezone;
+mod webhook;
+#[cfg(test)]
+mod tests;
+use db::{Bet, Database, RetentionPolicy, User};
+use announcements::AnnouncementEdits;
+use api_client::{MarketApi, MarketApiClient, MarketApiError, RetryPolicy};
+use currency::{format_amount, format_signed, group_thousands, Currency};
+use deadlines::DeadlineConfig;
+use edits::{CommandOutcome, RecentCommands};
+use claude::{format_usd, EvidenceMessage, PositionSummary, PriceTable, ResolutionCache, ResolutionContext, Resolver};
+use contract1::api::{
+    ContractParams, CreatedMarket, EventFilter, InitializeOutcome, LedgerBalance, LedgerMarket, MarketFilter, MarketSummary, ReconcileReport,
+    ReconcileSnapshot, ResolveResult,
+};
+use history::{LoggedMessage, RecentMessages};
+use membership::MembershipCache;
+use messenger::{Messenger, Transport};
+use onboarding::{PendingCommand, PendingOnboardings};
+use parse::Usage;
+use polls::{PendingPolls, PollOffer};
+use send_queue::{SendPacing, SendQueue};
+use suggestions::PendingSolves;
+use webhook::{OwnAction, OwnActions};
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase", description = "These commands are supported:")]
 enum Command {
     #[command(description = "Initialize balance for all users in the group")]
     Init,
-    #[command(description = "Create a new bet: /new <description>")]
+    #[command(description = "Create a new bet: /new <description> [#tag ...] [deadline:YYYY-MM-DD]")]
     New(String),
     #[command(description = "Bet on an existing bet: /bet <bet_id> <yes/no> <amount>")]
     Bet(String),
-    #[command(description = "List all bets")]
-    List,
-    #[command(description = "Solve a bet (reply to a message)")]
+    #[command(description = "Show how a bet would move the odds without placing it: /preview <bet_id> <yes/no> <amount>")]
+    Preview(String),
+    #[command(description = "Challenge someone head-to-head: /challenge @user <amount> <description>")]
+    Challenge(String),
+    #[command(description = "List all bets, or those with a tag: /list [#tag]")]
+    List(String),
+    #[command(description = "Solve a bet (reply to a message): /solve <bet_id> [N earlier messages] [force]")]
     Solve,
-    #[command(description = "Show the top users by balance")]
-    Leaderboard,
-    #[command(description = "Reset the entire database (admin only)")]
-    Reset,
+    #[command(description = "Show the top users: /leaderboard [week|month|all]")]
+    Leaderboard(String),
+    #[command(description = "Show your profile: balance, rank, streak, open bets and badges")]
+    Me,
+    #[command(description = "Show the protocol treasury")]
+    Treasury,
+    #[command(description = "Show details of a bet: /info <bet_id>")]
+    Info(String),
+    #[command(description = "Show how the odds of a bet moved: /stats <bet_id>")]
+    Stats(String),
+    #[command(description = "Resolve a bet without Claude: /resolve <bet_id> <yes/no> (admin only)")]
+    Resolve(String),
+    #[command(description = "Resolve a bet whose deadline passed as NO: /expire <bet_id> (admin only)")]
+    Expire(String),
+    #[command(description = "Resolve bets as NO when nobody solves them after their deadline: /autoexpire <on/off> (admin only)")]
+    AutoExpire(String),
+    #[command(description = "Name this chat's currency: /currency [emoji] <name>|reset (admin only)")]
+    Currency(String),
+    #[command(description = "Show this chat's settings, or set its timezone: /settings [set timezone <name>] (admin only to set)")]
+    Settings(String),
+    #[command(description = "Show this month's Claude spend, or set a budget: /cost [budget <usd>|budget off] (admin only)")]
+    Cost(String),
+    #[command(description = "Show what the bot keeps from this chat, or opt in or out: /privacy [optin|optout]")]
+    Privacy(String),
+    #[command(description = "Turn off or on the private messages sent when the odds of a bet you joined move: /alerts [on|off]")]
+    Alerts(String),
+    #[command(description = "Archive old resolved bets (admin only)")]
+    Cleanup,
     #[command(description = "Show help")]
     Help,
+    // Sent by Telegram when a user opens the bot's private chat, e.g. from a deep link
+    #[command(hide)]
+    Start(String),
+    // Operator commands, hidden from /help
+    #[command(hide)]
+    SetAdmin(String),
+    #[command(hide)]
+    Withdraw(String),
+    #[command(hide)]
+    Adjust(String),
+    #[command(hide)]
+    Reconcile,
+    #[command(hide)]
+    Broadcast(String),
+    #[command(hide)]
+    Season(String),
+    #[command(hide)]
+    Commands(String),
+    #[command(hide)]
+    Reset,
 }
 
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
 struct BotContext {
     db: Arc<Database>,
-    api_client: Arc<MarketApiClient>,
+    api_client: Arc<dyn MarketApi>,
     contract_name: String,
+    retention: RetentionPolicy,
+    deadlines: DeadlineConfig,
+    resolution_cache: ResolutionCache,
+    /// None when no LLM backend is configured; /solve is then unavailable
+    resolver: Option<Arc<dyn Resolver>>,
+    prices: PriceTable,
+    /// Recent group messages, used as context by `/solve <bet_id> <N>`
+    recent_messages: RecentMessages,
+    /// Telegram users allowed to run the operator commands
+    operators: HashSet<i64>,
+    /// Bets and resolutions sent by the bot, which the webhook must not announce again
+    own_actions: OwnActions,
+    /// Economic rules published by the server, fetched at startup
+    params: ContractParams,
+    /// Epoch of the deployed contract, fetched at startup; None when the
+    /// server does not report it
+    state_epoch: Option<u64>,
+    /// `/solve` commands waiting for their author to pick a suggested bet
+    pending_solves: PendingSolves,
+    /// Commands of uninitialized users waiting for them to accept the onboarding offer
+    pending_onboardings: PendingOnboardings,
+    /// Yes/no polls waiting for their author to turn them into markets
+    pending_polls: PendingPolls,
+    /// Recent group membership lookups
+    membership: MembershipCache,
+    /// Throttle of the edits keeping bet announcements up to date
+    announcement_edits: AnnouncementEdits,
+    /// Recent command messages, to tell whether an edit of one may run it again
+    recent_commands: RecentCommands,
+    /// The bot's @username, for the deep links to its private chat; None
+    /// when Telegram did not report it
+    bot_username: Option<String>,
+}
+
+/// Whether `bet` was created on an earlier deployment of the contract, whose
+/// market ids now name other markets.
+fn predates_season(ctx: &BotContext, bet: &db::Bet) -> bool {
+    match (ctx.state_epoch, bet.epoch) {
+        (Some(current), Some(epoch)) => epoch as u64 != current,
+        _ => false,
+    }
+}
+
+fn predates_season_message(bet_id: i64) -> String {
+    format!(
+        "⌛ Bet #{} predates the current season: the market contract was redeployed since, so it can no longer be traded or resolved.",
+        bet_id
+    )
+}
+
+/// What a resolution paid out, when the server reported it.
+fn payout_line(currency: &Currency, result: Option<&ResolveResult>) -> String {
+    match result {
+        Some(result) if result.winner_count > 0 => format!(
+            "💰 {} paid out to {} winner(s)!",
+            format_amount(currency, result.total_distributed),
+            result.winner_count
+        ),
+        Some(_) => "💰 Nobody backed the outcome, so the pool went to the treasury.".to_string(),
+        None => "💰 Winnings have been automatically distributed to all winners!".to_string(),
+    }
+}
+
+fn init_first_message(params: &ContractParams, currency: &Currency) -> String {
+    format!(
+        "You need to use /init first to get your initial balance of {}.",
+        format_amount(currency, params.initial_balance)
+    )
+}
+
+/// The remaining allowance stated by a stake cap rejection.
+fn stake_cap_allowance(error: &MarketApiError) -> Option<u128> {
+    match error.kind() {
+        MarketApiError::ContractRejected { message } => contract1::MarketError::stake_cap_allowance(message),
+        _ => None,
+    }
+}
+
+/// Reply to bets and evaluations of a bet a /solve is deciding.
+fn resolution_in_progress(bet_id: i64) -> String {
+    format!("⏳ Resolution in progress: bet #{} takes no bets until its verdict is in.", bet_id)
+}
+
+/// Renders a failed resolution of bet `bet_id`. A market the contract holds
+/// back after a recent bet is told when it can be resolved, read in `tz`.
+fn resolve_error_message(action: &str, error: &MarketApiError, bet_id: i64, tz: chrono_tz::Tz) -> String {
+    let opens_at = match error.kind() {
+        MarketApiError::ContractRejected { message } => contract1::MarketError::resolution_opens_at(message),
+        _ => None,
+    };
+    match opens_at.and_then(|at| chrono::DateTime::from_timestamp(at as i64, 0)) {
+        Some(at) => format!(
+            "⏳ Market #{} took a bet moments ago, so others get a chance to react: resolution opens {}.",
+            bet_id,
+            timezone::humanize(at, chrono::Utc::now(), tz)
+        ),
+        None => api_error_message(action, error),
+    }
+}
+
+/// Renders an API failure as a user-facing reply, tailored to the error class.
+fn api_error_message(action: &str, error: &MarketApiError) -> String {
+    let message = match error.kind() {
+        MarketApiError::ContractRejected { message } if contract1::MarketError::is_paused(message) => format!(
+            "⏸️ Markets are temporarily paused by the operator, could not {}. Please try again later.",
+            action
+        ),
+        MarketApiError::ContractRejected { message } => {
+            format!("❌ Could not {}: {}", action, message)
+        }
+        MarketApiError::Forbidden { .. } => format!(
+            "🔒 The market server refused to {}: the bot's admin key is missing or wrong.",
+            action
+        ),
+        MarketApiError::Pending { tx_hash } => format!(
+            "⏳ Your request to {} was submitted but is not confirmed yet.\nTransaction: {}\nCheck again in a moment before retrying.",
+            action, tx_hash
+        ),
+        MarketApiError::Timeout => format!(
+            "⌛ The market server took too long to {}. Please try again in a moment.",
+            action
+        ),
+        MarketApiError::Transport(_) => format!(
+            "🔌 The market server is unreachable, could not {}. Please try again later.",
+            action
+        ),
+        MarketApiError::ServerError { status, .. } => format!(
+            "⚠️ The market server failed to {} (HTTP {}). Please try again later.",
+            action,
+            status.as_u16()
+        ),
+        MarketApiError::Deserialization(_) => format!(
+            "⚠️ The market server sent an unexpected reply while trying to {}.",
+            action
+        ),
+        MarketApiError::Request { .. } => unreachable!("kind() strips request tags"),
+    };
+
+    match error.reference_code() {
+        Some(code) => format!("{}\nRef: {}", message, code),
+        None => message,
+    }
+}
+
+/// Messages remembered per chat for evidence threads.
+const RECENT_MESSAGES_PER_CHAT: usize = 200;
+
+/// Most earlier messages `/solve` may pull into the evidence thread.
+const MAX_CONTEXT_MESSAGES: usize = 10;
+
+/// Longest bet button label when `/solve` offers several bets.
+const SUGGESTION_LABEL_CHARS: usize = 40;
+
+/// Keeps a group message as `/solve` context, unless its chat did not opt in
+/// with `/privacy optin` or its author opted out.
+async fn remember_group_message(ctx: &BotContext, msg: &Message) -> Result<()> {
+    let (ChatKind::Public(_), Some(logged)) = (&msg.chat.kind, LoggedMessage::from_message(msg)) else {
+        return Ok(());
+    };
+    if history_allowed(ctx, msg.chat.id.0, logged.author_id).await? {
+        ctx.recent_messages.record(msg.chat.id.0, logged);
+    }
+    Ok(())
+}
+
+/// Whether earlier messages of `author_id` in the chat may go into a prompt.
+async fn history_allowed(ctx: &BotContext, chat_id: i64, author_id: i64) -> Result<bool> {
+    Ok(ctx.db.get_message_buffer(chat_id).await? && !ctx.db.is_privacy_opted_out(author_id).await?)
+}
+
+/// The replied message preceded by up to `count` earlier messages from the same author.
+fn collect_evidence(recent: &RecentMessages, chat_id: i64, replied: &Message, count: usize) -> Vec<EvidenceMessage> {
+    let Some(replied) = LoggedMessage::from_message(replied) else {
+        return vec![];
+    };
+    let mut evidence: Vec<EvidenceMessage> = recent
+        .preceding_from_author(chat_id, replied.message_id, replied.author_id, count)
+        .iter()
+        .map(LoggedMessage::to_evidence)
+        .collect();
+    evidence.push(replied.to_evidence());
+    evidence
+}
+
+/// How an evidence author is named in on-chain comments.
+fn comment_author(msg: &Message) -> String {
+    msg.from.as_ref()
+        .map(|u| u.username.clone().map(|name| format!("@{}", name)).unwrap_or_else(|| format!("User {}", u.id.0)))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// What a `/solve` is judged on.
+struct SolveEvidence {
+    messages: Vec<EvidenceMessage>,
+    author: String,
+    /// Quoted in the verdict and kept on-chain next to the market
+    text: String,
+    message_id: i64,
+}
+
+impl SolveEvidence {
+    /// The replied message, preceded by up to `count` earlier messages from its author.
+    fn replied(recent: &RecentMessages, chat_id: i64, replied: &Message, count: usize) -> Self {
+        Self {
+            messages: collect_evidence(recent, chat_id, replied, count),
+            author: comment_author(replied),
+            text: replied.text().unwrap_or("<no text content>").to_string(),
+            message_id: replied.id.0 as i64,
+        }
+    }
+
+    /// Proof typed after the command itself, when it replies to a bet announcement.
+    fn inline(command: &Message, text: String) -> Self {
+        let author = command.from.as_ref()
+            .map(|u| u.username.clone().unwrap_or_else(|| u.first_name.clone()))
+            .unwrap_or_else(|| "unknown".to_string());
+        Self {
+            messages: vec![EvidenceMessage {
+                author,
+                timestamp: command.date.to_rfc3339(),
+                text: text.clone(),
+            }],
+            author: comment_author(command),
+            text,
+            message_id: command.id.0 as i64,
+        }
+    }
+
+    /// The evidence as an on-chain market comment, cut to the contract's limit.
+    fn comment(&self) -> String {
+        let comment = format!("Evidence from {}: {}", self.author, self.text);
+        if comment.chars().count() <= contract1::MAX_COMMENT_CHARS {
+            return comment;
+        }
+        let mut cut: String = comment.chars().take(contract1::MAX_COMMENT_CHARS - 1).collect();
+        cut.push('…');
+        cut
+    }
+}
+
+/// Anonymized split of the stake on a bet, from the local wager records.
+async fn position_summary(db: &Database, bet_id: i64) -> Option<PositionSummary> {
+    let wagers = match db.get_wagers_for_bet(bet_id).await {
+        Ok(wagers) => wagers,
+        Err(e) => {
+            log::warn!("Failed to load wagers for bet #{}: {}", bet_id, e);
+            return None;
+        }
+    };
+
+    let mut summary = PositionSummary { yes_stake: 0, no_stake: 0, bettor_count: 0 };
+    let mut bettors = HashSet::new();
+    for wager in &wagers {
+        let amount = wager.amount.max(0) as u128;
+        if wager.side {
+            summary.yes_stake += amount;
+        } else {
+            summary.no_stake += amount;
+        }
+        bettors.insert(wager.user_id);
+    }
+    summary.bettor_count = bettors.len();
+    Some(summary)
+}
+
+/// Turns an on-chain identity (`<telegram user id>@<contract>`) into a display name.
+async fn display_name_for_identity(db: &Database, identity: &str) -> anyhow::Result<String> {
+    let user_id = identity.split('@').next().and_then(|id| id.parse::<i64>().ok());
+    let Some(user_id) = user_id else {
+        return Ok(identity.to_string());
+    };
+    Ok(match db.get_user(user_id).await? {
+        Some(User { username: Some(username), .. }) => format!("@{}", username),
+        _ => format!("User {}", user_id),
+    })
+}
+
+/// In group chats only administrators pass; private chats are always allowed.
+async fn is_chat_admin(bot: &Messenger, msg: &Message, user_id: i64) -> Result<bool, teloxide::RequestError> {
+    if !matches!(msg.chat.kind, ChatKind::Public(_)) {
+        return Ok(true);
+    }
+    let admins = bot.get_chat_administrators(msg.chat.id).await?;
+    Ok(admins.iter().any(|admin| admin.0 as i64 == user_id))
+}
+
+/// Whether the sender may act on `bet`, replying with the reason otherwise:
+/// its chat must not be frozen, and a command sent from another chat must
+/// come from a current member of the market's group.
+async fn check_market_access(
+    bot: &Messenger,
+    ctx: &BotContext,
+    msg: &Message,
+    bet: &db::Bet,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    if predates_season(ctx, bet) {
+        bot.send_message(msg.chat.id, predates_season_message(bet.bet_id)).await?;
+        return Ok(false);
+    }
+    let Some(market_chat) = bet.chat_id.map(ChatId) else {
+        return Ok(true);
+    };
+    if ctx.db.is_chat_frozen(market_chat.0).await? {
+        bot.send_message(msg.chat.id, format!("❄️ Market #{} is frozen: the bot was removed from the chat it belongs to.", bet.bet_id))
+            .await?;
+        return Ok(false);
+    }
+    
+    // Writing in the market's own chat already proves membership
+    let Some(from) = msg.from.as_ref() else {
+        return Ok(false);
+    };
+    if market_chat == msg.chat.id || market_chat.is_user() {
+        return Ok(true);
+    }
+    if ctx.membership.is_member(bot, market_chat, from.id).await? {
+        return Ok(true);
+    }
+    bot.send_message(msg.chat.id, format!("Sorry, only current members of the group where market #{} was created can take part in it.", bet.bet_id))
+        .await?;
+    Ok(false)
+}
+
+/// The bot was added to or removed from a chat: the chat's markets are frozen
+/// while the bot is away, since nobody there could follow them anymore. Added
+/// back, it picks up where it left off rather than starting fresh.
+async fn handle_my_chat_member(bot: Messenger, update: ChatMemberUpdated, ctx: Arc<BotContext>) -> HandlerResult {
+    let present = update.new_chat_member.is_present();
+    if present == update.old_chat_member.is_present() {
+        return Ok(());
+    }
+    
+    let chat_id = update.chat.id;
+    log::info!("Bot was {} chat {}", if present { "added to" } else { "removed from" }, chat_id.0);
+    ctx.db.set_chat_frozen(chat_id.0, !present).await?;
+    if !present {
+        return Ok(());
+    }
+    
+    let open = ctx.db.get_open_bets(chat_id.0).await?;
+    let open = open.iter().filter(|bet| bet.chat_id == Some(chat_id.0)).count();
+    if open > 0 {
+        bot.send_message(chat_id, format!("👋 Back again! {} open market{} of this chat can be bet on again, see /list.", open, if open == 1 { "" } else { "s" }))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Telegram gave a chat a new id, as when a group becomes a supergroup: its
+/// markets and settings follow it. Both chats get a service message, so the
+/// move runs twice and the second finds nothing left; only the new chat is
+/// told about it.
+async fn handle_chat_migration(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+    let (old_chat, new_chat) = match (msg.migrate_to_chat_id(), msg.migrate_from_chat_id()) {
+        (Some(to), _) => (msg.chat.id, *to),
+        (None, Some(from)) => (*from, msg.chat.id),
+        (None, None) => return Ok(()),
+    };
+    
+    let moved = ctx.db.migrate_chat(old_chat.0, new_chat.0).await?;
+    log::info!(
+        "Chat {} migrated to {}, {} rows moved",
+        old_chat.0,
+        new_chat.0,
+        moved.iter().map(|(_, rows)| rows).sum::<u64>()
+    );
+    if msg.migrate_from_chat_id().is_some() && ctx.db.count_bets(new_chat.0).await? > 0 {
+        bot.send_message(new_chat, "✅ This group is now a supergroup. Its markets, settings and history came along, nothing was lost.")
+            .await?;
+    }
+    Ok(())
 }
 
-async fn handle_init(bot: Bot, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+async fn handle_init(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
     let chat_id = msg.chat.id;
     let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
     let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
@@ -59,495 +526,2922 @@ async fn handle_init(bot: Bot, msg: Message, ctx: Arc<BotContext>) -> HandlerRes
         return Ok(());
     }
     
-    // Initialize the user's balance on the blockchain
     if let Some(from) = msg.from.as_ref() {
-        let username = from.username.clone();
-        
-        // Call the blockchain API to initialize the user
-        match ctx.api_client.initialize_user(user_id.to_string(), &ctx.contract_name).await {
-            Ok(tx_hash) => {
-                // Record initialization in local database
-                ctx.db.create_or_update_user(from.id.0 as i64, username, 10000).await?;
-                ctx.db.mark_user_initialized(from.id.0 as i64).await?;
-                bot.send_message(chat_id, format!("✅ Your balance has been initialized to 10,000 on-chain.\nTransaction: {}", tx_hash))
+        initialize_user(&bot, &ctx, chat_id, from).await?;
+    }
+    
+    Ok(())
+}
+
+/// Gives `from` their initial balance on-chain and records them locally,
+/// replying in `chat_id`. Returns whether they are initialized afterwards.
+async fn initialize_user(
+    bot: &Messenger,
+    ctx: &BotContext,
+    chat_id: ChatId,
+    from: &teloxide::types::User,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let user_id = from.id.0 as i64;
+    let username = from.username.clone();
+    
+    // Initialization is idempotent on-chain: a user the chain already knows,
+    // e.g. after the local database was reset, gets their current balance back
+    match ctx.api_client.initialize_user(user_id.to_string(), &ctx.contract_name).await {
+        Ok(receipt) => {
+            let outcome = receipt.result.clone().and_then(|result| serde_json::from_value::<InitializeOutcome>(result).ok());
+            let balance = outcome.as_ref().map_or(ctx.params.initial_balance, |outcome| outcome.balance);
+            // Record initialization in local database
+            ctx.db.create_or_update_user(user_id, username, i64::try_from(balance).unwrap_or(i64::MAX)).await?;
+            ctx.db.mark_user_initialized(user_id).await?;
+            let currency = ctx.db.get_currency(chat_id.0).await?;
+            if outcome.is_some_and(|outcome| outcome.already_initialized) {
+                bot.send_message(chat_id, format!("You have already initialized your balance on-chain. Current balance: {}", format_amount(&currency, balance)))
                     .await?;
-                log::info!("Successfully initialized balance for user {} with tx {}", user_id, tx_hash);
+                return Ok(true);
             }
-            Err(e) => {
-                bot.send_message(chat_id, format!("❌ Failed to initialize balance: {}", e))
-                    .await?;
-                log::error!("Failed to initialize user {}: {}", user_id, e);
+            bot.send_message(chat_id, format!("✅ Your balance has been initialized to {} on-chain.\nTransaction: {}", format_amount(&currency, balance), receipt.tx_hash))
+                .await?;
+            log::info!("Successfully initialized balance for user {} with tx {}", user_id, receipt.tx_hash);
+            Ok(true)
+        }
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("initialize your balance", &e))
+                .await?;
+            log::error!("Failed to initialize user {}: {}", user_id, e);
+            Ok(false)
+        }
+    }
+}
+
+/// Answers a command from a user who never ran /init: in groups, offers to
+/// initialize them and then run the command, which is kept meanwhile.
+async fn offer_onboarding(bot: &Messenger, ctx: &BotContext, msg: Message, command: PendingCommand) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    let Some(from) = msg.from.clone().filter(|_| matches!(msg.chat.kind, ChatKind::Public(_))) else {
+        bot.send_message(chat_id, init_first_message(&ctx.params, &currency))
+            .await?;
+        return Ok(());
+    };
+    
+    let user_id = from.id.0 as i64;
+    let text = format!("👋 You need {} to {}. Want your starting balance now?", currency.name, command.purpose());
+    let buttons = vec![
+        (
+            format!("Initialize me ({})", format_amount(&currency, ctx.params.initial_balance)),
+            onboarding::callback_data(true, user_id),
+        ),
+        ("No thanks".to_string(), onboarding::callback_data(false, user_id)),
+    ];
+    ctx.pending_onboardings.stash(chat_id, from.id, msg, command);
+    bot.send_buttons(chat_id, text, buttons).await?;
+    Ok(())
+}
+
+/// An onboarding button was pressed: initializes the user, then runs the
+/// command they tried. The server takes one action per transaction, so the
+/// initialization and the command settle as two transactions.
+async fn handle_onboarding_callback(bot: Messenger, query: CallbackQuery, ctx: Arc<BotContext>) -> HandlerResult {
+    let Some((accept, offered_to)) = query.data.as_deref().and_then(onboarding::parse_callback) else {
+        return Ok(());
+    };
+    let Some(prompt) = query.message.as_ref() else {
+        return Ok(());
+    };
+    let chat_id = prompt.chat().id;
+    
+    log::info!("User {} answered the onboarding offer in chat {}: {}", query.from.id, chat_id.0, accept);
+    
+    if query.from.id.0 as i64 != offered_to {
+        let currency = ctx.db.get_currency(chat_id.0).await?;
+        bot.answer_callback(query.id, Some(format!("This offer is for someone else, use /init to get your own {}.", currency.name)))
+            .await?;
+        return Ok(());
+    }
+    let pending = ctx.pending_onboardings.take(chat_id, query.from.id);
+    if !accept {
+        bot.answer_callback(query.id, Some("No problem, use /init whenever you are ready.".to_string()))
+            .await?;
+        return Ok(());
+    }
+    let Some((msg, command)) = pending else {
+        bot.answer_callback(query.id, Some("This offer has expired, please run your command again.".to_string()))
+            .await?;
+        return Ok(());
+    };
+    bot.answer_callback(query.id, None).await?;
+    
+    if !ctx.db.is_user_initialized(offered_to).await? && !initialize_user(&bot, &ctx, chat_id, &query.from).await? {
+        return Ok(());
+    }
+    match command {
+        PendingCommand::Bet(args) => handle_bet(bot, msg, ctx, args).await,
+        PendingCommand::New(description) => handle_new(bot, msg, ctx, description).await,
+    }
+}
+
+/// A yes/no poll was posted in a group: offers its author to turn it into a market.
+async fn handle_poll_message(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let (Some(poll), Some(from)) = (msg.poll(), msg.from.as_ref()) else {
+        return Ok(());
+    };
+    if !matches!(msg.chat.kind, ChatKind::Public(_)) {
+        return Ok(());
+    }
+    let Some(yes_option) = polls::market_option(poll) else {
+        return Ok(());
+    };
+    
+    log::info!("User {} posted a yes/no poll in chat {}: {}", from.id, chat_id.0, poll.question);
+    
+    ctx.pending_polls.record(
+        chat_id,
+        msg.id,
+        PollOffer {
+            poll_id: poll.id.0.clone(),
+            author_id: from.id.0 as i64,
+            question: poll.question.clone(),
+            yes_option,
+        },
+    );
+    bot.send_buttons(
+        chat_id,
+        format!("📊 Create a market from this poll?\n“{}”", poll.question),
+        vec![("Create market".to_string(), polls::callback_data(msg.id))],
+    )
+    .await?;
+    Ok(())
+}
+
+/// The poll author accepted to turn their poll into a market. The poll
+/// message then names the market, e.g. when replying to it with /solve.
+async fn handle_poll_callback(bot: Messenger, query: CallbackQuery, ctx: Arc<BotContext>) -> HandlerResult {
+    let Some(poll_message) = query.data.as_deref().and_then(polls::parse_callback) else {
+        return Ok(());
+    };
+    let Some(prompt) = query.message.as_ref() else {
+        return Ok(());
+    };
+    let chat_id = prompt.chat().id;
+    let user_id = query.from.id.0 as i64;
+    
+    log::info!("User {} asked for a market from poll message {} in chat {}", user_id, poll_message.0, chat_id.0);
+    
+    let Some(offer) = ctx.pending_polls.get(chat_id, poll_message) else {
+        bot.answer_callback(query.id, Some("This offer expired, use /new to create the market.".to_string()))
+            .await?;
+        return Ok(());
+    };
+    if offer.author_id != user_id {
+        bot.answer_callback(query.id, Some("Only the author of the poll can turn it into a market.".to_string()))
+            .await?;
+        return Ok(());
+    }
+    if ctx.db.get_user(user_id).await?.is_none() {
+        let currency = ctx.db.get_currency(chat_id.0).await?;
+        bot.answer_callback(query.id, Some(init_first_message(&ctx.params, &currency)))
+            .await?;
+        return Ok(());
+    }
+    ctx.pending_polls.take(chat_id, poll_message);
+    bot.answer_callback(query.id, None).await?;
+    
+    let username = query.from.username.clone().unwrap_or_else(|| "unknown".to_string());
+    if let Some(bet_id) = create_market(&bot, &ctx, chat_id, user_id, &username, offer.question, Vec::new(), None).await? {
+        ctx.db.record_announcement(chat_id.0, poll_message.0 as i64, bet_id).await?;
+        ctx.db.link_poll(&offer.poll_id, bet_id, chat_id.0, offer.yes_option as i64).await?;
+    }
+    Ok(())
+}
+
+/// A poll changed state. Once a poll a market was created from closes, its
+/// result is offered as the market's resolution, for an admin to confirm.
+async fn handle_poll_update(bot: Messenger, poll: Poll, ctx: Arc<BotContext>) -> HandlerResult {
+    if !poll.is_closed {
+        return Ok(());
+    }
+    let Some(linked) = ctx.db.get_poll_market(&poll.id.0).await? else {
+        return Ok(());
+    };
+    if linked.result_offered {
+        return Ok(());
+    }
+    ctx.db.mark_poll_result_offered(&poll.id.0).await?;
+    let still_open = ctx.db.get_bet_by_id(linked.bet_id).await?.is_some_and(|bet| bet.status == "open");
+    if !still_open {
+        return Ok(());
+    }
+    
+    log::info!("The poll of bet #{} closed in chat {}", linked.bet_id, linked.chat_id);
+    
+    let (yes, no) = polls::tally(&poll, linked.yes_option as usize);
+    let text = if yes == no {
+        format!(
+            "📊 The poll of bet #{} closed in a tie ({} votes each), so it suggests no outcome.\nReply to a proof with /solve {}, or an admin can settle it with /resolve {} <yes/no>.",
+            linked.bet_id, yes, linked.bet_id, linked.bet_id
+        )
+    } else {
+        let outcome = if yes > no { "yes" } else { "no" };
+        format!(
+            "📊 The poll of bet #{} closed: {} YES, {} NO.\nProposed resolution: {}. An admin can confirm it with /resolve {} {}",
+            linked.bet_id,
+            yes,
+            no,
+            outcome.to_uppercase(),
+            linked.bet_id,
+            outcome
+        )
+    };
+    let sent = bot.send_message(ChatId(linked.chat_id), text).await?;
+    ctx.db.record_announcement(linked.chat_id, sent.0 as i64, linked.bet_id).await?;
+    Ok(())
+}
+
+/// `@bot <query>` from any chat: offers cards of the matching open markets
+/// of the sender's groups, to share them there.
+async fn handle_inline_query(bot: Messenger, query: InlineQuery, ctx: Arc<BotContext>) -> HandlerResult {
+    let user_id = query.from.id.0 as i64;
+    log::info!("User {} searched inline for {:?}", user_id, query.query);
+    
+    let candidates = ctx.db
+        .search_open_bets(user_id, query.query.trim(), ctx.state_epoch, inline::INLINE_CANDIDATES)
+        .await?;
+    let mut articles = Vec::new();
+    let mut shown = Vec::new();
+    for bet in candidates {
+        if articles.len() == inline::MAX_INLINE_RESULTS {
+            break;
+        }
+        let Some(chat_id) = bet.chat_id else {
+            continue;
+        };
+        // Users who left a group no longer see its markets
+        if chat_id != user_id {
+            match ctx.membership.is_member(&bot, ChatId(chat_id), query.from.id).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    log::warn!("Could not check that user {} is in chat {}: {}", user_id, chat_id, e);
+                    continue;
+                }
             }
         }
+        
+        let wagers = ctx.db.get_wagers_for_bet(bet.bet_id).await?;
+        let pool = |side: bool| wagers.iter().filter(|w| w.side == side).map(|w| w.amount.max(0) as u128).sum::<u128>();
+        let currency = ctx.db.get_currency(chat_id).await?;
+        let announcement = ctx.db.get_live_announcement(bet.bet_id).await?
+            .map(|announcement| (announcement.chat_id, announcement.message_id));
+        articles.push(inline::market_card(&bet, pool(true), pool(false), &currency, announcement));
+        shown.push(bet.bet_id);
+    }
+    
+    ctx.db.record_inline_impressions(user_id, &shown).await?;
+    bot.answer_inline_query(query.id, articles).await?;
+    Ok(())
+}
+
+/// Routes a button press to the feature whose buttons use its data prefix.
+async fn handle_callback(bot: Messenger, query: CallbackQuery, ctx: Arc<BotContext>) -> HandlerResult {
+    let data = query.data.as_deref().unwrap_or("");
+    if onboarding::parse_callback(data).is_some() {
+        handle_onboarding_callback(bot, query, ctx).await
+    } else if polls::parse_callback(data).is_some() {
+        handle_poll_callback(bot, query, ctx).await
+    } else if challenges::parse_callback(data).is_some() {
+        handle_challenge_callback(bot, query, ctx).await
+    } else if deep_links::parse_callback(data).is_some() {
+        handle_stake_callback(bot, query, ctx).await
+    } else {
+        handle_solve_callback(bot, query, ctx).await
+    }
+}
+
+/// `/start [payload]`: greets users opening the bot's private chat, or shows
+/// the market a deep link from an announcement names, with buttons to bet
+/// on it from there.
+async fn handle_start(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /start in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    let payload = args.trim();
+    if payload.is_empty() {
+        bot.send_message(chat_id, "👋 Hi! I run prediction markets in group chats. Add me to a group and send /init there, or open a market from its announcement to bet on it here. /help lists the commands.")
+            .await?;
+        return Ok(());
+    }
+    let Some((market_chat, bet_id)) = deep_links::parse_start(payload) else {
+        bot.send_message(chat_id, "❓ This link is broken. Open the market again from its announcement in the group.")
+            .await?;
+        return Ok(());
+    };
+    let bet = match ctx.db.get_bet_by_id(bet_id).await? {
+        Some(bet) if bet.chat_id == Some(market_chat) => bet,
+        _ => {
+            bot.send_message(chat_id, "⌛ This link has expired: its market no longer exists.").await?;
+            return Ok(());
+        }
+    };
+    if bet.status == "resolving" {
+        bot.send_message(chat_id, resolution_in_progress(bet_id))
+            .await?;
+        return Ok(());
+    }
+    if bet.status != "open" {
+        bot.send_message(chat_id, format!("⌛ This link has expired: market #{} is closed.", bet_id))
+            .await?;
+        return Ok(());
+    }
+    if !check_market_access(&bot, &ctx, &msg, &bet).await? {
+        return Ok(());
+    }
+    
+    let wagers = ctx.db.get_wagers_for_bet(bet.bet_id).await?;
+    let pool = |side: bool| wagers.iter().filter(|w| w.side == side).map(|w| w.amount.max(0) as u128).sum::<u128>();
+    let currency = ctx.db.get_currency(market_chat).await?;
+    let (text, buttons) = deep_links::card(&bet, pool(true), pool(false), &currency);
+    bot.send_buttons(chat_id, text, buttons).await?;
+    
+    Ok(())
+}
+
+/// A stake button under a private market card: bets as if its reader had
+/// sent `/bet` in that chat, so the usual checks apply.
+async fn handle_stake_callback(bot: Messenger, query: CallbackQuery, ctx: Arc<BotContext>) -> HandlerResult {
+    let Some((bet_id, side, amount)) = query.data.as_deref().and_then(deep_links::parse_callback) else {
+        return Ok(());
+    };
+    let Some(mut msg) = query.message.as_ref().and_then(|card| card.regular_message()).cloned() else {
+        return Ok(());
+    };
+    
+    log::info!("User {} staked {} on {} of bet #{} from chat {}", query.from.id, amount, if side { "YES" } else { "NO" }, bet_id, msg.chat.id.0);
+    
+    bot.answer_callback(query.id, None).await?;
+    msg.from = Some(query.from);
+    handle_bet(bot, msg, ctx, format!("{} {} {}", bet_id, if side { "yes" } else { "no" }, amount)).await
+}
+
+async fn handle_new(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /new in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    // Tags may come before or after the deadline
+    let (rest, trailing_tags) = split_tags(&args);
+    let tz = ctx.db.get_timezone(chat_id.0).await?;
+    let (description, deadline) = match split_deadline(&rest, tz, chrono::Utc::now()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            bot.send_message(chat_id, format!("❌ {}\nExpected deadline:YYYY-MM-DD, deadline:YYYY-MM-DDTHH:MM, a day like deadline:tomorrow or deadline:friday 18:00, or a duration like deadline:3d", e))
+                .await?;
+            return Ok(());
+        }
+    };
+    
+    let (description, mut tags) = split_tags(&description);
+    tags.extend(trailing_tags);
+    
+    if description.trim().is_empty() {
+        bot.send_message(chat_id, "Usage: /new <description> [#tag ...] [deadline:YYYY-MM-DD]\nExample: /new Will it rain tomorrow? #weather deadline:2025-07-01")
+            .await?;
+        return Ok(());
+    }
+    
+    // Check if user has balance
+    let user = ctx.db.get_user(user_id).await?;
+    if user.is_none() {
+        return offer_onboarding(&bot, &ctx, msg, PendingCommand::New(args)).await;
+    }
+    
+    create_market(&bot, &ctx, chat_id, user_id, &username, description, tags, deadline).await?;
+    Ok(())
+}
+
+/// Creates a market on-chain for `user_id` and announces it in `chat_id`.
+/// Returns the new bet id, or `None` when the server refused it.
+#[allow(clippy::too_many_arguments)]
+async fn create_market(
+    bot: &Messenger,
+    ctx: &BotContext,
+    chat_id: ChatId,
+    user_id: i64,
+    username: &str,
+    description: String,
+    tags: Vec<String>,
+    deadline: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Option<i64>, Box<dyn std::error::Error + Send + Sync>> {
+    match ctx.api_client.create_market(user_id.to_string(), description.clone(), tags.clone(), &ctx.contract_name).await {
+        Ok(receipt) => {
+            // Store in local database for tracking
+            let bet_id = ctx
+                .db
+                .create_bet(user_id, chat_id.0, description.clone(), deadline.map(|d| d.to_rfc3339()))
+                .await?;
+            let created = receipt.result.clone().and_then(|result| serde_json::from_value::<CreatedMarket>(result).ok());
+            let reported = created.map(|created| created.market_id);
+            market_ids::record_created(&ctx.db, ctx.api_client.as_ref(), &ctx.contract_name, ctx.state_epoch, bet_id, reported).await?;
+            let tz = ctx.db.get_timezone(chat_id.0).await?;
+            let deadline_line = deadline
+                .map(|d| format!("\n⏰ Deadline: {}", timezone::humanize(d, chrono::Utc::now(), tz)))
+                .unwrap_or_default();
+            let tags_line = if tags.is_empty() {
+                String::new()
+            } else {
+                format!("\n🏷 {}", tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" "))
+            };
+            
+            // Members who prefer to bet privately open the market in a chat with the bot
+            let link_line = ctx
+                .bot_username
+                .as_deref()
+                .filter(|_| !chat_id.is_user())
+                .map(|bot_username| format!("\n🔒 Bet privately: {}", deep_links::start_link(bot_username, chat_id.0, bet_id)))
+                .unwrap_or_default();
+            
+            let text = format!(
+                "{}\n{}",
+                markdown::bold(&format!("✅ Market #{} created on-chain by @{}", bet_id, username)),
+                markdown::escape(&format!(
+                    "📄 Description: {}{}{}\nTransaction: {}{}",
+                    description, tags_line, deadline_line, receipt.tx_hash, link_line
+                ))
+            );
+            let currency = ctx.db.get_currency(chat_id.0).await?;
+            let announcement = bot.send_markdown(chat_id, announcements::announcement_text(&text, &[], &currency))
+                .await?;
+            ctx.db.record_announcement(chat_id.0, announcement.0 as i64, bet_id).await?;
+            ctx.db.set_live_announcement(bet_id, chat_id.0, announcement.0 as i64, &text).await?;
+            log::info!("Market #{} created successfully by user {} with tx {}", bet_id, user_id, receipt.tx_hash);
+            Ok(Some(bet_id))
+        }
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("create the market", &e))
+                .await?;
+            log::error!("Failed to create market for user {}: {}", user_id, e);
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_bet(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /bet in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    let parsed = parse::command(&args, Usage::BET, |args| Ok((args.market_id()?, args.side()?, args.amount()?)));
+    let (bet_id, side, amount) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    
+    // Check if user has balance
+    let user = ctx.db.get_user(user_id).await?;
+    let user = match user {
+        Some(u) => u,
+        None => return offer_onboarding(&bot, &ctx, msg, PendingCommand::Bet(args)).await,
+    };
+    
+    // `half` and `all` are relative to the balance
+    let amount = amount.of(user.balance);
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    if (amount as u128) < ctx.params.min_bet {
+        bot.send_message(chat_id, format!("The minimum bet is {}.", format_amount(&currency, ctx.params.min_bet)))
+            .await?;
+        return Ok(());
+    }
+    if let Some(max_bet) = ctx.params.max_bet.filter(|max| amount as u128 > *max) {
+        bot.send_message(chat_id, format!("The maximum bet is {}.", format_amount(&currency, max_bet)))
+            .await?;
+        return Ok(());
+    }
+    
+    if user.balance < amount {
+        let reply = format!(
+            "Insufficient balance. You have {} but tried to bet {}.",
+            format_amount(&currency, user.balance.max(0) as u128),
+            format_amount(&currency, amount as u128)
+        );
+        bot.send_message(chat_id, reply).await?;
+        return Ok(());
+    }
+    
+    // Find the bet by ID
+    let bet = ctx.db.get_bet_by_id(bet_id).await?;
+    let bet = match bet {
+        Some(b) if b.status == "open" => b,
+        Some(b) if b.status == "resolving" => {
+            bot.send_message(chat_id, resolution_in_progress(bet_id))
+                .await?;
+            return Ok(());
+        }
+        Some(_) => {
+            bot.send_message(chat_id, format!("Bet #{} is already closed.", bet_id))
+                .await?;
+            return Ok(());
+        }
+        None => {
+            bot.send_message(chat_id, format!("Bet #{} not found. Use /list to see available bets.", bet_id))
+                .await?;
+            return Ok(());
+        }
+    };
+    if !check_market_access(&bot, &ctx, &msg, &bet).await? {
+        return Ok(());
+    }
+    
+    // Place bet on blockchain, logging the intent first so a crash midway
+    // is resumed on restart
+    let params = operations::BetParams { bet_id: bet.bet_id, side, amount };
+    let operation_id = operations::begin_bet(&ctx.db, user_id, chat_id.0, params).await?;
+    let own_bet = OwnAction::Bet { market_id: bet.on_chain_id(), identity: format!("{}@{}", user_id, ctx.contract_name) };
+    ctx.own_actions.record(own_bet.clone());
+    match ctx.api_client.place_bet(user_id.to_string(), bet.on_chain_id(), side, amount as u128, &ctx.contract_name).await {
+        Ok(receipt) => {
+            ctx.recent_commands.bet_placed(chat_id, msg.id);
+            // Create the wager and update balance locally
+            let Some(new_balance) = ctx
+                .db
+                .apply_bet_operation(operation_id, &receipt.tx_hash, bet.bet_id, user_id, amount, side)
+                .await?
+            else {
+                return Ok(());
+            };
+            
+            bot.send_markdown(
+                chat_id,
+                operations::bet_placed("💰 Bet placed on-chain!", &bet.description, &params, new_balance, &currency, &receipt.tx_hash),
+            )
+            .await?;
+            ctx.db.finish_operation(operation_id, "done").await?;
+            log::info!("Bet placed by user {} on market {} for amount {} on side {} with tx {}", 
+                user_id, bet.bet_id, amount, if side { "yes" } else { "no" }, receipt.tx_hash);
+            announcements::refresh(&bot, &ctx, bet_id).await;
+            alerts::after_bet(&bot, &ctx, bet_id, user_id).await;
+        }
+        Err(e) => {
+            // Accepted but not settled yet: confirmed here once it is
+            if let MarketApiError::Pending { tx_hash } = e.kind() {
+                ctx.recent_commands.bet_placed(chat_id, msg.id);
+                ctx.db.mark_operation_submitted(operation_id, tx_hash).await?;
+                bot.send_message(chat_id, api_error_message("place the bet", &e)).await?;
+                operations::spawn_follow_up(bot.clone(), Arc::clone(&ctx));
+                log::warn!("Bet of user {} is pending: {}", user_id, e);
+                return Ok(());
+            }
+            ctx.db.finish_operation(operation_id, "failed").await?;
+            ctx.own_actions.take(&own_bet);
+            let reply = match stake_cap_allowance(&e) {
+                Some(0) => format!(
+                    "🧢 You already hold the most this market allows on {}.",
+                    if side { "YES" } else { "NO" }
+                ),
+                Some(allowed) => format!(
+                    "🧢 Market #{} caps each player's stake. You can add at most {} on {}: /bet {} {} {}",
+                    bet_id, format_amount(&currency, allowed), if side { "YES" } else { "NO" },
+                    bet_id, if side { "yes" } else { "no" }, allowed
+                ),
+                None => api_error_message("place the bet", &e),
+            };
+            bot.send_message(chat_id, reply).await?;
+            log::error!("Failed to place bet for user {}: {}", user_id, e);
+        }
+    }
+    
+    Ok(())
+}
+
+/// `/preview <bet_id> <yes/no> <amount>`: how a bet would move the odds of a
+/// market and what it would pay, from the chat's pools, without placing it.
+async fn handle_preview(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /preview in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    let parsed = parse::command(&args, Usage::PREVIEW, |args| Ok((args.market_id()?, args.side()?, args.amount()?)));
+    let (bet_id, side, amount) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    
+    let bet = match ctx.db.get_bet_by_id(bet_id).await? {
+        Some(b) if b.status == "open" => b,
+        Some(_) => {
+            bot.send_message(chat_id, format!("Bet #{} is already closed.", bet_id))
+                .await?;
+            return Ok(());
+        }
+        None => {
+            bot.send_message(chat_id, format!("Bet #{} not found. Use /list to see available bets.", bet_id))
+                .await?;
+            return Ok(());
+        }
+    };
+    if !check_market_access(&bot, &ctx, &msg, &bet).await? {
+        return Ok(());
+    }
+    
+    let balance = ctx.db.get_user(user_id).await?.map(|user| user.balance);
+    let amount = amount.of(balance.unwrap_or(0));
+    if amount <= 0 {
+        bot.send_message(chat_id, "You have nothing to stake yet.").await?;
+        return Ok(());
+    }
+    
+    // Same pools as /info: nothing is read or sent on-chain
+    let wagers = ctx.db.get_wagers_for_bet(bet.bet_id).await?;
+    let pool = |side: bool| wagers.iter().filter(|w| w.side == side).map(|w| w.amount.max(0) as u128).sum::<u128>();
+    let (yes_pool, no_pool) = (pool(true), pool(false));
+    let simulated = preview::simulate(yes_pool, no_pool, side, amount as u128, ctx.params.fee_bps);
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    bot.send_message(chat_id, preview::render(&bet, yes_pool, no_pool, &simulated, balance, &currency))
+        .await?;
+    
+    Ok(())
+}
+
+/// `/challenge @user <amount> <description>`: a market only the caller and
+/// the named user bet on. The caller's stake is escrowed on YES right away,
+/// and the market opens once the opponent matches it on NO.
+async fn handle_challenge(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /challenge in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    let args = mentions::args_with_text_mentions(&msg, args);
+    let (opponent_name, stake, description) = match challenges::parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    let Some(user) = ctx.db.get_user(user_id).await? else {
+        bot.send_message(chat_id, init_first_message(&ctx.params, &currency))
+            .await?;
+        return Ok(());
+    };
+    let stake = stake.of(user.balance);
+    let Some(mentioned) = mentions::resolve_mention(&ctx.db, &msg, &opponent_name).await? else {
+        bot.send_message(chat_id, format!("I don't know @{} yet: they need to write in this chat or use /init before they can be challenged.", opponent_name))
+            .await?;
+        return Ok(());
+    };
+    let Some(opponent) = ctx.db.get_user(mentioned.user_id).await? else {
+        bot.send_message(chat_id, format!("{} has no balance yet: they need to use /init before they can be challenged.", mentioned.name))
+            .await?;
+        return Ok(());
+    };
+    if opponent.user_id == user_id {
+        bot.send_message(chat_id, "You cannot challenge yourself.").await?;
+        return Ok(());
+    }
+    if (stake as u128) < ctx.params.min_bet {
+        bot.send_message(chat_id, format!("The minimum stake is {}.", format_amount(&currency, ctx.params.min_bet)))
+            .await?;
+        return Ok(());
+    }
+    if user.balance < stake {
+        let reply = format!(
+            "Insufficient balance. You have {} but tried to stake {}.",
+            format_amount(&currency, user.balance.max(0) as u128),
+            format_amount(&currency, stake as u128)
+        );
+        bot.send_message(chat_id, reply).await?;
+        return Ok(());
+    }
+    
+    let created = ctx
+        .api_client
+        .create_challenge(user_id.to_string(), opponent.user_id.to_string(), stake as u128, description.clone(), &ctx.contract_name)
+        .await;
+    let receipt = match created {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("create the challenge", &e)).await?;
+            log::error!("Failed to create a challenge for user {}: {}", user_id, e);
+            return Ok(());
+        }
+    };
+    let bet_id = ctx.db.create_bet(user_id, chat_id.0, description.clone(), None).await?;
+    let reported = receipt.result.clone().and_then(|result| serde_json::from_value::<CreatedMarket>(result).ok()).map(|created| created.market_id);
+    let market_id =
+        market_ids::record_created(&ctx.db, ctx.api_client.as_ref(), &ctx.contract_name, ctx.state_epoch, bet_id, reported).await?;
+    // The escrow is the creator's bet, which the webhook must not announce again
+    let identity = format!("{}@{}", user_id, ctx.contract_name);
+    ctx.own_actions.record(OwnAction::Bet { market_id, identity });
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(contract1::CHALLENGE_ACCEPT_WINDOW as i64);
+    ctx.db.open_challenge(bet_id, opponent.user_id, stake, &expires_at.to_rfc3339()).await?;
+    
+    let opponent_name = opponent.username.map(|name| format!("@{}", name)).unwrap_or(mentioned.name);
+    let text = format!(
+        "🥊 Challenge #{}: @{} challenges {}\n📄 {}\n💵 Stake: {} each, the winner takes {}\n⏳ {} can accept until {}\nTransaction: {}",
+        bet_id,
+        username,
+        opponent_name,
+        description,
+        format_amount(&currency, stake as u128),
+        format_amount(&currency, 2 * stake as u128),
+        opponent_name,
+        timezone::humanize(expires_at, chrono::Utc::now(), ctx.db.get_timezone(chat_id.0).await?),
+        receipt.tx_hash
+    );
+    let buttons = vec![
+        ("Accept".to_string(), challenges::callback_data(true, bet_id)),
+        ("Decline".to_string(), challenges::callback_data(false, bet_id)),
+    ];
+    let announcement = bot.send_buttons(chat_id, text, buttons).await?;
+    ctx.db.record_announcement(chat_id.0, announcement.0 as i64, bet_id).await?;
+    log::info!("Challenge #{} created by user {} against user {} with tx {}", bet_id, user_id, opponent.user_id, receipt.tx_hash);
+    Ok(())
+}
+
+/// The accept and decline buttons of a challenge. Only the opponent accepts;
+/// either player may call a pending challenge off.
+async fn handle_challenge_callback(bot: Messenger, query: CallbackQuery, ctx: Arc<BotContext>) -> HandlerResult {
+    let Some((accept, bet_id)) = query.data.as_deref().and_then(challenges::parse_callback) else {
+        return Ok(());
+    };
+    let Some(prompt) = query.message.as_ref() else {
+        return Ok(());
+    };
+    let chat_id = prompt.chat().id;
+    let user_id = query.from.id.0 as i64;
+    
+    log::info!("User {} answered challenge #{} in chat {}: {}", user_id, bet_id, chat_id.0, accept);
+    
+    let Some(challenge) = ctx.db.get_challenge(bet_id).await?.filter(|challenge| challenge.status == "pending") else {
+        bot.answer_callback(query.id, Some("This challenge is no longer open.".to_string()))
+            .await?;
+        return Ok(());
+    };
+    if !accept {
+        let reason = if user_id == challenge.opponent_id {
+            "was declined"
+        } else if user_id == challenge.creator_id {
+            "was withdrawn"
+        } else {
+            bot.answer_callback(query.id, Some("Only the two players can call this challenge off.".to_string()))
+                .await?;
+            return Ok(());
+        };
+        bot.answer_callback(query.id, None).await?;
+        return challenges::cancel(&bot, &ctx, &challenge, user_id, reason).await;
+    }
+    if user_id != challenge.opponent_id {
+        bot.answer_callback(query.id, Some("Only the challenged player can accept this challenge.".to_string()))
+            .await?;
+        return Ok(());
+    }
+    let expired = chrono::DateTime::parse_from_rfc3339(&challenge.expires_at).is_ok_and(|expires_at| expires_at <= chrono::Utc::now());
+    if expired {
+        bot.answer_callback(query.id, Some("This challenge expired.".to_string()))
+            .await?;
+        return challenges::cancel(&bot, &ctx, &challenge, user_id, "expired unanswered").await;
+    }
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    let Some(user) = ctx.db.get_user(user_id).await? else {
+        bot.answer_callback(query.id, Some(init_first_message(&ctx.params, &currency)))
+            .await?;
+        return Ok(());
+    };
+    if user.balance < challenge.stake {
+        bot.answer_callback(query.id, Some(format!("You need {} to accept this challenge.", format_amount(&currency, challenge.stake as u128))))
+            .await?;
+        return Ok(());
+    }
+    bot.answer_callback(query.id, None).await?;
+    
+    let market_id = challenge.on_chain_id();
+    let own_bet = OwnAction::Bet { market_id, identity: format!("{}@{}", user_id, ctx.contract_name) };
+    ctx.own_actions.record(own_bet.clone());
+    match ctx.api_client.accept_challenge(user_id.to_string(), market_id, &ctx.contract_name).await {
+        Ok(receipt) => {
+            ctx.db.accept_challenge(bet_id).await?;
+            let username = query.from.username.clone().unwrap_or_else(|| "unknown".to_string());
+            bot.send_message(
+                chat_id,
+                format!(
+                    "🤝 @{} accepted challenge #{}: {}\n💰 {} at stake, the winner takes it all.\nTransaction: {}",
+                    username,
+                    bet_id,
+                    challenge.description,
+                    format_amount(&currency, 2 * challenge.stake as u128),
+                    receipt.tx_hash
+                ),
+            )
+            .await?;
+            log::info!("Challenge #{} accepted by user {} with tx {}", bet_id, user_id, receipt.tx_hash);
+        }
+        Err(e) => {
+            ctx.own_actions.take(&own_bet);
+            bot.send_message(chat_id, api_error_message("accept the challenge", &e)).await?;
+            log::error!("Failed to accept challenge #{} for user {}: {}", bet_id, user_id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn handle_solve(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let solver_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let solver_username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /solve in chat {}", solver_username, solver_id, chat_id.0);
+    
+    // Parse optional bet_id from command
+    let text = msg.text().unwrap_or("");
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    // Anything else is the proof when replying to an announcement
+    let bet_id = parts.get(1).and_then(|word| parse::market_id(word).ok());
+    // `/solve <bet_id> [N] [force]`: N earlier messages from the same author are
+    // included as evidence, `force` re-evaluates instead of reusing a cached verdict
+    let extra_args = parts.iter().skip(2);
+    let force = extra_args.clone().any(|arg| arg.eq_ignore_ascii_case("force"));
+    let context_messages = extra_args
+        .filter_map(|arg| arg.parse::<usize>().ok())
+        .next()
+        .unwrap_or(0)
+        .min(MAX_CONTEXT_MESSAGES);
+    
+    let Some(replied_msg) = msg.reply_to_message() else {
+        bot.send_message(chat_id, format!("Please reply to a message to use /solve\n{}", Usage::SOLVE))
+            .await?;
+        return Ok(());
+    };
+    
+    // Check if user has balance
+    let user = ctx.db.get_user(solver_id).await?;
+    if user.is_none() {
+        let currency = ctx.db.get_currency(chat_id.0).await?;
+        bot.send_message(chat_id, init_first_message(&ctx.params, &currency))
+            .await?;
+        return Ok(());
+    }
+    
+    // Replying to the bot's announcement of a bet names the bet: the proof is
+    // then written after the command, e.g. `/solve It rained all morning`
+    if let Some(announced) = ctx.db.get_announced_bet(chat_id.0, replied_msg.id.0 as i64).await? {
+        let args = parts.iter().skip(1);
+        let force = args.clone().any(|arg| arg.eq_ignore_ascii_case("force"));
+        let proof: Vec<&str> = args.filter(|arg| !arg.eq_ignore_ascii_case("force")).copied().collect();
+        if proof.is_empty() {
+            bot.send_message(
+                chat_id,
+                format!("Write the proof after the command when replying to a bet announcement.\nExample: /solve It rained all morning\nOr reply to the proof itself with /solve {}", announced)
+            )
+            .await?;
+            return Ok(());
+        }
+        let evidence = SolveEvidence::inline(&msg, proof.join(" "));
+        return solve_bet(&bot, &ctx, &msg, announced, evidence, force).await;
+    }
+    
+    // Without a bet id, offer the open bets the evidence seems to be about
+    let Some(bet_id) = bet_id else {
+        return suggest_bets(&bot, &ctx, &msg, replied_msg).await;
+    };
+    
+    // Earlier messages only come from the buffer the chat and the author consented to
+    let author_id = replied_msg.from.as_ref().map_or(0, |u| u.id.0 as i64);
+    let context_messages = if context_messages > 0 && history_allowed(&ctx, chat_id.0, author_id).await? {
+        context_messages
+    } else {
+        0
+    };
+    let evidence = SolveEvidence::replied(&ctx.recent_messages, chat_id.0, replied_msg, context_messages);
+    solve_bet(&bot, &ctx, &msg, bet_id, evidence, force).await
+}
+
+/// Answers a `/solve` that named no bet: one or a few "Solve bet #N?" buttons
+/// for the open bets the evidence mentions, or the usage when none matches.
+async fn suggest_bets(bot: &Messenger, ctx: &BotContext, msg: &Message, replied_msg: &Message) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let evidence = replied_msg.text().or(replied_msg.caption()).unwrap_or("");
+    let candidates = suggestions::candidate_bets(evidence, ctx.db.get_open_bets(chat_id.0).await?);
+    
+    let prompt = match candidates.as_slice() {
+        [] => {
+            bot.send_message(chat_id, format!("Please specify which bet this solves.\n{}", Usage::SOLVE))
+                .await?;
+            return Ok(());
+        }
+        [bet] => format!("🔎 This looks like bet #{}: {}", bet.bet_id, bet.description),
+        _ => "🔎 Which bet does this solve?".to_string(),
+    };
+    let buttons = if let [bet] = candidates.as_slice() {
+        vec![(format!("Solve bet #{}?", bet.bet_id), suggestions::callback_data(bet.bet_id))]
+    } else {
+        candidates
+            .iter()
+            .map(|bet| {
+                let label = format!("#{}: {}", bet.bet_id, bet.description);
+                let label = match label.char_indices().nth(SUGGESTION_LABEL_CHARS) {
+                    Some((cut, _)) => format!("{}…", &label[..cut]),
+                    None => label,
+                };
+                (label, suggestions::callback_data(bet.bet_id))
+            })
+            .collect()
+    };
+    
+    let prompt_id = bot.send_buttons(chat_id, prompt, buttons).await?;
+    ctx.pending_solves.record(chat_id, prompt_id, msg.clone());
+    Ok(())
+}
+
+/// A "Solve bet #N?" button was pressed: runs the pending `/solve` on that bet.
+async fn handle_solve_callback(bot: Messenger, query: CallbackQuery, ctx: Arc<BotContext>) -> HandlerResult {
+    let Some(bet_id) = query.data.as_deref().and_then(suggestions::parse_callback) else {
+        return Ok(());
+    };
+    let Some(prompt) = query.message.as_ref() else {
+        return Ok(());
+    };
+    let (chat_id, prompt_id) = (prompt.chat().id, prompt.id());
+    
+    log::info!("User {} picked bet #{} for a /solve in chat {}", query.from.id, bet_id, chat_id.0);
+    
+    let Some(command) = ctx.pending_solves.get(chat_id, prompt_id) else {
+        bot.answer_callback(query.id, Some("This suggestion has expired, please run /solve again.".to_string()))
+            .await?;
+        return Ok(());
+    };
+    if command.from.as_ref().map(|u| u.id) != Some(query.from.id) {
+        bot.answer_callback(query.id, Some("Only the author of the /solve can pick the bet.".to_string()))
+            .await?;
+        return Ok(());
+    }
+    // Pressing twice must not evaluate twice
+    if !ctx.pending_solves.remove(chat_id, prompt_id) {
+        bot.answer_callback(query.id, None).await?;
+        return Ok(());
+    }
+    bot.answer_callback(query.id, Some(format!("Solving bet #{}", bet_id))).await?;
+    
+    let Some(replied_msg) = command.reply_to_message() else {
+        return Ok(());
+    };
+    let evidence = SolveEvidence::replied(&ctx.recent_messages, chat_id.0, replied_msg, 0);
+    solve_bet(&bot, &ctx, &command, bet_id, evidence, false).await
+}
+
+/// Evaluates `evidence` for bet `bet_id` on behalf of the author of the `/solve`
+/// command `msg`, and resolves the market on-chain when the verdict settles it.
+async fn solve_bet(
+    bot: &Messenger,
+    ctx: &BotContext,
+    msg: &Message,
+    bet_id: i64,
+    evidence: SolveEvidence,
+    force: bool,
+) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let solver_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    
+    // Get the bet details
+    let bet = match ctx.db.get_bet_by_id(bet_id).await? {
+        Some(b) if b.status == "open" => b,
+        Some(b) if b.status == "resolving" => {
+            bot.send_message(chat_id, resolution_in_progress(bet_id))
+                .await?;
+            return Ok(());
+        }
+        Some(_) => {
+            bot.send_message(chat_id, "This bet is already closed.")
+                .await?;
+            return Ok(());
+        }
+        None => {
+            bot.send_message(chat_id, format!("Bet #{} not found.", bet_id))
+                .await?;
+            return Ok(());
+        }
+    };
+    if !check_market_access(bot, ctx, msg, &bet).await? {
+        return Ok(());
+    }
+    
+    if force && !is_chat_admin(bot, msg, solver_id).await? {
+        bot.send_message(chat_id, "❌ Only chat admins can force a new evaluation.")
+            .await?;
+        return Ok(());
+    }
+
+    // Past the deadline the bet can only resolve NO, no need to ask the LLM
+    if !force && deadline_passed(&bet) {
+        bot.send_message(
+            chat_id,
+            format!(
+                "⏰ The deadline of bet #{} has passed without an accepted solution.\nAn admin can settle it as NO with /expire {}, or force an evaluation with /solve {} force.",
+                bet_id, bet_id, bet_id
+            )
+        )
+        .await?;
+        return Ok(());
+    }
+    
+    let Some(resolver) = ctx.resolver.clone() else {
+        log::error!("No LLM backend configured");
+        bot.send_message(chat_id, "❌ Bot configuration error: no LLM backend configured.")
+            .await?;
+        return Ok(());
+    };
+    
+    // Chats over their monthly budget fall back to admin resolution
+    if let Some(budget) = ctx.db.get_chat_budget(chat_id.0).await? {
+        let usage = ctx.db.get_llm_usage(chat_id.0, &current_month()).await?;
+        if usage.cost_micros >= budget {
+            bot.send_message(
+                chat_id,
+                format!(
+                    "💸 This chat has used its monthly evaluation budget ({}).\nAn admin can settle the bet with /resolve {} <yes/no>.",
+                    format_usd(budget),
+                    bet_id
+                )
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+    
+    // Nobody bets on the verdict while it is being decided
+    if !ctx.db.begin_resolving(bet_id).await? {
+        bot.send_message(chat_id, resolution_in_progress(bet_id))
+            .await?;
+        return Ok(());
+    }
+    let evaluated = evaluate_solution(bot, ctx, msg, &bet, &evidence, resolver, force).await;
+    // Betting reopens unless the market was resolved, also when the
+    // evaluation failed, whose error is the one reported
+    let reopened = ctx.db.reopen_bet(bet_id).await;
+    evaluated?;
+    reopened?;
+    Ok(())
+}
+
+/// Asks `resolver` whether `evidence` settles `bet` and resolves the market
+/// on-chain when it does. Betting on the bet is frozen meanwhile.
+async fn evaluate_solution(
+    bot: &Messenger,
+    ctx: &BotContext,
+    msg: &Message,
+    bet: &Bet,
+    evidence: &SolveEvidence,
+    resolver: Arc<dyn Resolver>,
+    force: bool,
+) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let solver_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let solver_username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    let bet_id = bet.bet_id;
+    let message_id = evidence.message_id;
+    let replied_text = evidence.text.as_str();
+    
+    // Send processing message
+    bot.send_message(chat_id, format!("🤔 Evaluating solution with {}...", resolver.name()))
+        .await?;
+    
+    // Ask the LLM to evaluate the solution
+    let resolution_ctx = ResolutionContext {
+        bet_id,
+        bet_description: bet.description.clone(),
+        evidence: evidence.messages.clone(),
+        criteria: None,
+        deadline: bet.deadline.clone(),
+        created_at: bet.created_at.clone(),
+        now: chrono::Utc::now().to_rfc3339(),
+        positions: position_summary(&ctx.db, bet_id).await,
+    };
+    let resolution = match claude::evaluate_bet_resolution(
+        resolver.as_ref(),
+        &ctx.resolution_cache,
+        resolution_ctx,
+        force,
+    ).await {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("LLM API error: {:?}", e);
+            bot.send_message(
+                chat_id,
+                format!("❌ Failed to evaluate solution: {}", e)
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    
+    if let Some(usage) = resolution.usage {
+        let cost = ctx.prices.cost_micros(resolver.name(), usage);
+        ctx.db
+            .record_llm_usage(
+                chat_id.0,
+                &current_month(),
+                usage.input_tokens as i64,
+                usage.output_tokens as i64,
+                cost,
+            )
+            .await?;
+    }
+    
+    // Record the solution
+    let solution_id = ctx.db.create_solution(bet_id, solver_id, message_id).await?;
+    
+    if resolution.resolved {
+        // Keep the evidence on-chain next to the market before it closes
+        let comment = evidence.comment();
+        if let Err(e) = ctx.api_client.add_comment(solver_id.to_string(), bet.on_chain_id(), comment, &ctx.contract_name).await {
+            log::warn!("Could not record the evidence of bet #{} on-chain: {}", bet_id, e);
+        }
+        
+        // Resolve the market on blockchain
+        let own_resolution = OwnAction::Resolve { market_id: bet.on_chain_id() };
+        ctx.own_actions.record(own_resolution.clone());
+        match ctx.api_client.resolve_market(
+            solver_id.to_string(),
+            bet.on_chain_id(),
+            resolution.outcome,
+            &ctx.contract_name
+        ).await {
+            Ok(receipt) => {
+                // Close the bet locally
+                ctx.db.close_bet(bet_id, resolution.outcome).await?;
+                
+                // The contract automatically distributes winnings when resolving
+                // Log that resolution was successful but don't update balances locally
+                log::info!("Market #{} resolved. Contract automatically distributed winnings to winners.", bet_id);
+                
+                // Note: In a production system, you might want to:
+                // 1. Query the blockchain for updated balances
+                // 2. Update local database with the new balances
+                // This ensures local state stays in sync with on-chain state
+                
+                let currency = ctx.db.get_currency(chat_id.0).await?;
+                bot.send_markdown(
+                    chat_id,
+                    format!(
+                        "{}\n\n{}",
+                        markdown::bold("✅ MARKET RESOLVED ON-CHAIN!"),
+                        markdown::escape(&format!(
+                            "📊 Market #{}\n📄 Description: {}\n💬 Solution: \"{}\"\n👤 Solved by: @{}\n🎯 Outcome: {}\n\n🤖 Analysis ({}): {}\n\nTransaction: {}\n\n{}",
+                            bet_id,
+                            bet.description,
+                            replied_text,
+                            solver_username,
+                            if resolution.outcome { "YES ✅" } else { "NO ❌" },
+                            resolver.name(),
+                            resolution.reasoning,
+                            receipt.tx_hash,
+                            payout_line(&currency, receipt.result.as_ref())
+                        ))
+                    )
+                )
+                .await?;
+                log::info!("Market #{} resolved on-chain with tx {}", bet_id, receipt.tx_hash);
+            }
+            Err(e) => {
+                ctx.own_actions.take(&own_resolution);
+                bot.send_message(
+                    chat_id,
+                    format!(
+                        "{}\n\nThe bet remains open.",
+                        resolve_error_message("resolve the market on-chain", &e, bet_id, ctx.db.get_timezone(chat_id.0).await?)
+                    )
+                )
+                .await?;
+                log::error!("Failed to resolve market {}: {}", bet_id, e);
+            }
+        }
+    } else {
+        bot.send_markdown(
+            chat_id,
+            format!(
+                "{}\n\n{}",
+                markdown::bold("❌ NOT RESOLVED"),
+                markdown::escape(&format!(
+                    "📊 Market #{}\n📄 Description: {}\n💬 Proposed solution: \"{}\"\n👤 Proposed by: @{}\n\n🤖 Analysis ({}): {}\n\nThe market remains open.",
+                    bet_id,
+                    bet.description,
+                    replied_text,
+                    solver_username,
+                    resolver.name(),
+                    resolution.reasoning
+                ))
+            )
+        )
+        .await?;
+    }
+    
+    log::info!("Solution #{} evaluated for bet #{}: resolved={}", solution_id, bet_id, resolution.resolved);
+    
+    Ok(())
+}
+
+async fn handle_list(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /list in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    let tag = args.split_whitespace().next().map(contract1::normalize_tag).filter(|tag| !tag.is_empty());
+    let filter = MarketFilter { tag: tag.clone(), ..MarketFilter::default() };
+    
+    // On-chain pools and opening times, when the indexer is reachable
+    let markets: HashMap<u64, MarketSummary> = match ctx.api_client.list_markets(&filter, &ctx.contract_name).await {
+        Ok(markets) => markets.into_iter().map(|m| (m.id, m)).collect(),
+        Err(e) if tag.is_some() => {
+            bot.send_message(chat_id, api_error_message("filter the bets by tag", &e))
+                .await?;
+            log::error!("Could not fetch on-chain markets for /list: {}", e);
+            return Ok(());
+        }
+        Err(e) => {
+            log::warn!("Could not fetch on-chain markets for /list: {}", e);
+            HashMap::new()
+        }
+    };
+    
+    // Tags live on-chain: keep the chat's bets the indexer matched
+    let (bets, total) = match &tag {
+        Some(_) => {
+            let mut bets = ctx.db.get_recent_bets(chat_id.0, i64::MAX).await?;
+            bets.retain(|bet| !predates_season(&ctx, bet) && markets.contains_key(&bet.on_chain_id()));
+            let total = bets.len() as i64;
+            bets.truncate(20);
+            (bets, total)
+        }
+        None => (ctx.db.get_recent_bets(chat_id.0, 20).await?, ctx.db.count_bets(chat_id.0).await?),
+    };
+    
+    if bets.is_empty() {
+        let text = match &tag {
+            Some(tag) => format!("No bets tagged #{}. Use /list to see all bets.", tag),
+            None => "No bets available. Use /new to create the first bet!".to_string(),
+        };
+        bot.send_message(chat_id, text)
+            .await?;
+        return Ok(());
+    }
+    
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    let tz = ctx.db.get_timezone(chat_id.0).await?;
+    let now = chrono::Utc::now();
+    let mut message = String::new();
+    
+    for bet in bets.iter() {
+        let market = markets.get(&bet.on_chain_id()).filter(|_| !predates_season(&ctx, bet));
+        let scheduled = market.filter(|m| m.scheduled).and_then(|m| m.opens_at);
+        let status_emoji = match bet.status.as_str() {
+            "open" if scheduled.is_some() => "⏰",
+            "open" if market.is_some_and(|m| m.status == contract1::MarketStatus::PendingAcceptance) => "🥊",
+            "open" => "🟢",
+            "resolving" => "⏳",
+            "resolved_yes" => "✅",
+            "resolved_no" => "❌",
+            "cancelled" => "🚫",
+            "void" => "⌛",
+            _ => "❔",
+        };
+        
+        let truncated_desc = if bet.description.len() > 50 {
+            format!("{}...", &bet.description[..50])
+        } else {
+            bet.description.clone()
+        };
+        
+        let pool_text = market
+            .map(|m| format!(" ({})", format_amount(&currency, m.yes_pool + m.no_pool)))
+            .unwrap_or_default();
+        let opens_text = scheduled
+            .and_then(|opens_at| chrono::DateTime::from_timestamp(opens_at as i64, 0))
+            .map(|opens_at| format!(" — opens {}", timezone::humanize(opens_at, now, tz)))
+            .unwrap_or_default();
+        let deadline_text = bet
+            .deadline
+            .as_deref()
+            .filter(|_| bet.status == "open")
+            .and_then(|deadline| chrono::DateTime::parse_from_rfc3339(deadline).ok())
+            .map(|deadline| format!(" — deadline {}", timezone::humanize(deadline.with_timezone(&chrono::Utc), now, tz)))
+            .unwrap_or_default();
+        
+        message.push_str(&format!(
+            "{} Bet #{}: {}{}{}{}\n",
+            status_emoji, bet.bet_id, truncated_desc, pool_text, opens_text, deadline_text
+        ));
+    }
+    
+    if total > bets.len() as i64 {
+        message.push_str(&format!("\n... and {} more bets", total - bets.len() as i64));
+    }
+    
+    message.push_str("\n\nUse /bet <bet_id> <yes/no> <amount> to place a wager!");
+    
+    let title = match &tag {
+        Some(tag) => format!("BETS TAGGED #{}", tag.to_uppercase()),
+        None => "AVAILABLE BETS".to_string(),
+    };
+    bot.send_markdown(chat_id, format!("📄 {} 📄\n\n{}", markdown::bold(&title), markdown::escape(&message)))
+        .await?;
+    
+    Ok(())
+}
+
+async fn handle_treasury(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /treasury in chat {}", username, user_id, chat_id.0);
+    
+    let treasury = match ctx.api_client.get_treasury(&ctx.contract_name).await {
+        Ok(treasury) => treasury,
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("read the treasury", &e))
+                .await?;
+            log::error!("Failed to fetch treasury: {}", e);
+            return Ok(());
+        }
+    };
+    
+    let admin = match &treasury.admin {
+        Some(identity) => display_name_for_identity(&ctx.db, identity).await?,
+        None => "none".to_string(),
+    };
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    bot.send_message(
+        chat_id,
+        format!(
+            "🏦 TREASURY\n\n💰 Balance: {}\n👤 Admin: {}\n🔥 Burned: {}\n🎁 Dividend pool: {}\n\nPayout dust and pools nobody won end up here. Funds nobody is entitled to are burned, and the house edge waits in the dividend pool for the next distribution.",
+            format_amount(&currency, treasury.balance), admin, format_amount(&currency, treasury.burned), format_amount(&currency, treasury.dividend_pool)
+        ),
+    )
+    .await?;
+    
+    Ok(())
+}
+
+/// Parses `BOT_OPERATOR_IDS`, a comma-separated list of Telegram user ids.
+fn operators_from_env() -> Result<HashSet<i64>> {
+    let Ok(ids) = std::env::var("BOT_OPERATOR_IDS") else {
+        return Ok(HashSet::new());
+    };
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse::<i64>().map_err(|e| anyhow::anyhow!("invalid operator id {:?}: {}", id, e)))
+        .collect()
+}
+
+/// Replies and returns false unless `user_id` is a configured operator.
+async fn ensure_operator(bot: &Messenger, msg: &Message, ctx: &BotContext, user_id: i64) -> Result<bool, teloxide::RequestError> {
+    if ctx.operators.contains(&user_id) {
+        return Ok(true);
+    }
+    bot.send_message(msg.chat.id, "⛔ This command is reserved for bot operators.")
+        .await?;
+    Ok(false)
+}
+
+async fn handle_set_admin(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /setadmin in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
+        return Ok(());
+    }
+    
+    // The user ID defaults to the caller
+    let new_admin = match parse::command(&args, Usage::SET_ADMIN, |args| args.user_id_or(user_id)) {
+        Ok(new_admin) => new_admin,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    
+    match ctx.api_client.set_admin(user_id.to_string(), new_admin.to_string(), &ctx.contract_name).await {
+        Ok(receipt) => {
+            let name = display_name_for_identity(&ctx.db, &new_admin.to_string()).await?;
+            bot.send_message(chat_id, format!("✅ {} is now the contract admin.\nTransaction: {}", name, receipt.tx_hash))
+                .await?;
+            log::info!("User {} set the contract admin to {} with tx {}", user_id, new_admin, receipt.tx_hash);
+        }
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("set the admin", &e))
+                .await?;
+            log::error!("Failed to set admin {} for user {}: {}", new_admin, user_id, e);
+        }
+    }
+    
+    Ok(())
+}
+
+async fn handle_withdraw(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /withdraw in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
+        return Ok(());
+    }
+    
+    // Sends treasury funds to the user, the caller by default
+    let parsed = parse::command(&args, Usage::WITHDRAW, |args| Ok((args.exact_amount()?, args.user_id_or(user_id)?)));
+    let (amount, to) = match parsed {
+        Ok((amount, to)) => (amount as u128, to),
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    
+    match ctx.api_client.withdraw_treasury(user_id.to_string(), to.to_string(), amount, &ctx.contract_name).await {
+        Ok(receipt) => {
+            // Mirror the credit locally, like /bet does for debits
+            if let Some(recipient) = ctx.db.get_user(to).await? {
+                let credited = recipient.balance.saturating_add(i64::try_from(amount).unwrap_or(i64::MAX));
+                ctx.db.update_user_balance(to, credited).await?;
+            }
+            let name = display_name_for_identity(&ctx.db, &to.to_string()).await?;
+            let currency = ctx.db.get_currency(chat_id.0).await?;
+            bot.send_message(chat_id, format!("✅ Withdrew {} from the treasury to {}.\nTransaction: {}", format_amount(&currency, amount), name, receipt.tx_hash))
+                .await?;
+            log::info!("User {} withdrew {} from the treasury to {} with tx {}", user_id, amount, to, receipt.tx_hash);
+        }
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("withdraw from the treasury", &e))
+                .await?;
+            log::error!("Failed to withdraw {} to {} for user {}: {}", amount, to, user_id, e);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Corrects a user's local balance, e.g. to compensate for a bot bug, and
+/// logs who did it and why.
+async fn handle_adjust(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /adjust in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
+        return Ok(());
+    }
+    
+    let args = mentions::args_with_text_mentions(&msg, args);
+    let parsed = parse::command(&args, Usage::ADJUST, |args| Ok((args.mention()?, args.signed_amount()?, args.text("the reason")?)));
+    let (name, amount, reason) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let Some(mentioned) = mentions::resolve_mention(&ctx.db, &msg, &name).await? else {
+        bot.send_message(chat_id, format!("I don't know @{} yet.", name)).await?;
+        return Ok(());
+    };
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    let Some(user) = ctx.db.get_user(mentioned.user_id).await? else {
+        bot.send_message(chat_id, format!("{} has no balance to adjust.", mentioned.name)).await?;
+        return Ok(());
+    };
+    if user.balance + amount < 0 {
+        let reply = format!(
+            "Cannot take {} from {}, who only has {}.",
+            format_amount(&currency, amount.unsigned_abs() as u128),
+            mentioned.name,
+            format_amount(&currency, user.balance.max(0) as u128)
+        );
+        bot.send_message(chat_id, reply).await?;
+        return Ok(());
+    }
+    
+    // The contract has no credit or debit action to mirror the change with,
+    // so it stays local and /reconcile leaves it out
+    let Some(balance) = ctx.db.adjust_balance(user.user_id, user_id, amount, reason, true).await? else {
+        return Ok(());
+    };
+    bot.send_message(
+        chat_id,
+        format!(
+            "🛠 Adjusted the balance of {} by {}: {} → {}\n📝 Reason: {}\nThis change is local only: the chain keeps the previous balance.",
+            mentioned.name,
+            format_signed(&currency, amount),
+            format_amount(&currency, user.balance.max(0) as u128),
+            format_amount(&currency, balance.max(0) as u128),
+            reason
+        ),
+    )
+    .await?;
+    log::info!("Operator {} adjusted the balance of {} by {}: {}", user_id, user.user_id, amount, reason);
+    
+    Ok(())
+}
+
+/// Sends an operator announcement to every chat with an open bet, e.g.
+/// before a migration. `dry-run` first lists the chats instead.
+async fn handle_broadcast(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /broadcast in chat {}", username, user_id, chat_id.0);
+    
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
+        return Ok(());
+    }
+    if !msg.chat.is_private() {
+        bot.send_message(chat_id, "📣 /broadcast only works in a private chat with the bot.")
+            .await?;
+        return Ok(());
+    }
+    
+    let args = args.trim();
+    let (dry_run, text) = match args.split_once(char::is_whitespace) {
+        Some(("dry-run", text)) => (true, text.trim()),
+        _ if args == "dry-run" => (true, ""),
+        _ => (false, args),
+    };
+    if text.is_empty() {
+        bot.send_message(
+            chat_id,
+            "Usage: /broadcast [dry-run] <text>\nSends the text to every chat with an open bet; dry-run only lists them.",
+        )
+        .await?;
+        return Ok(());
+    }
+    
+    let chats = ctx.db.get_active_chats().await?;
+    if chats.is_empty() {
+        bot.send_message(chat_id, "No chat has an open bet, there is nobody to broadcast to.")
+            .await?;
+        return Ok(());
+    }
+    if dry_run {
+        let list = chats.iter().map(|chat| format!("• {}", chat)).collect::<Vec<_>>().join("\n");
+        bot.send_message(chat_id, format!("📣 Would send to {} chat(s):\n{}", chats.len(), list))
+            .await?;
+        return Ok(());
+    }
+    
+    let broadcast = ctx.db.create_broadcast(user_id, text, &chats).await?;
+    log::info!("User {} started broadcast #{} to {} chats", user_id, broadcast.broadcast_id, chats.len());
+    bot.send_message(chat_id, format!("📣 Broadcasting to {} chat(s)…", chats.len()))
+        .await?;
+    let (sent, failed) = broadcast::deliver(&bot, &ctx.db, &broadcast).await?;
+    bot.send_message(chat_id, broadcast::summary(&broadcast, sent, failed, false))
+        .await?;
+    
+    Ok(())
+}
+
+/// Places of the final leaderboard kept when a season ends.
+const SEASON_STANDINGS: i64 = 10;
+
+/// Seasons listed by /season history.
+const SEASON_HISTORY_LIMIT: i64 = 10;
+
+/// One place of a season's final leaderboard, with its medal.
+fn standing_line(currency: &Currency, standing: &db::SeasonStanding) -> String {
+    let medal = match standing.rank {
+        1 => "🥇",
+        2 => "🥈",
+        3 => "🥉",
+        _ => "  ",
+    };
+    let name = standing.username.as_ref()
+        .map(|u| format!("@{}", u))
+        .unwrap_or_else(|| format!("User {}", standing.user_id));
+    format!("{} #{}: {} - {}", medal, standing.rank, name, format_amount(currency, standing.balance.max(0) as u128))
+}
+
+/// Ends the season, giving everyone a fresh balance once the final
+/// leaderboard is in the hall of fame, or lists the past winners.
+async fn handle_season(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /season in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
+        return Ok(());
+    }
+    
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    match args.trim() {
+        "end" => {}
+        "history" => {
+            let standings = ctx.db.get_hall_of_fame(SEASON_HISTORY_LIMIT).await?;
+            if standings.is_empty() {
+                bot.send_message(chat_id, "No season has ended yet.")
+                    .await?;
+                return Ok(());
+            }
+            let mut text = "📜 Hall of fame".to_string();
+            for standing in &standings {
+                if standing.rank == 1 {
+                    let ended = standing.ended_at.get(..10).unwrap_or(&standing.ended_at);
+                    text.push_str(&format!("\n\nSeason {}, ended {}", standing.season_id, ended));
+                }
+                text.push_str(&format!("\n{}", standing_line(&currency, standing)));
+            }
+            bot.send_message(chat_id, text)
+                .await?;
+            return Ok(());
+        }
+        _ => {
+            bot.send_message(
+                chat_id,
+                "Usage: /season end|history\nend gives everyone a fresh balance and archives the season's bets; history lists past winners.",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+    
+    let open = ctx.db.count_open_bets(ctx.state_epoch).await?;
+    if open > 0 {
+        bot.send_message(chat_id, format!("⏳ {} bet(s) are still open, resolve them before ending the season.", open))
+            .await?;
+        return Ok(());
+    }
+    
+    let receipt = match ctx.api_client.reset_balances(user_id.to_string(), &ctx.contract_name).await {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("end the season", &e))
+                .await?;
+            log::error!("Failed to reset balances for user {}: {}", user_id, e);
+            return Ok(());
+        }
+    };
+    
+    let initial_balance = i64::try_from(ctx.params.initial_balance).unwrap_or(i64::MAX);
+    let standings = ctx.db.end_season(initial_balance, SEASON_STANDINGS).await?;
+    let mut text = match standings.first() {
+        Some(standing) => format!("🏁 Season {} is over! Hall of fame:", standing.season_id),
+        None => "🏁 The season is over!".to_string(),
+    };
+    for standing in &standings {
+        text.push_str(&format!("\n{}", standing_line(&currency, standing)));
+    }
+    text.push_str(&format!(
+        "\n\nEveryone starts the new season with {}.\nTransaction: {}",
+        format_amount(&currency, ctx.params.initial_balance),
+        receipt.tx_hash
+    ));
+    bot.send_message(chat_id, text)
+        .await?;
+    log::info!("User {} ended the season with tx {}", user_id, receipt.tx_hash);
+    
+    Ok(())
+}
+
+/// Registers Telegram's "/" menu again, e.g. after the operators changed.
+async fn handle_commands(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
+        return Ok(());
+    }
+    
+    let problem = match args.trim() {
+        "refresh" => None,
+        "" => Some(parse::Problem::NoArguments),
+        other => Some(parse::Problem::Unexpected(other.to_string())),
+    };
+    if let Some(problem) = problem {
+        bot.send_message(chat_id, parse::ParseError { problem, usage: Usage::COMMANDS }.to_string())
+            .await?;
+        return Ok(());
+    }
+    
+    let reply = match command_menu::register(&bot, &ctx.operators).await {
+        Ok(_) => format!(
+            "✅ Command menu registered: {} public commands, plus the operator commands for {} operator(s).",
+            command_menu::public_commands().len(),
+            ctx.operators.len()
+        ),
+        Err(e) => {
+            log::error!("Failed to register the command menu: {}", e);
+            format!("❌ Could not register the command menu: {}", e)
+        }
+    };
+    bot.send_message(chat_id, reply).await?;
+    Ok(())
+}
+
+/// Most entries listed per kind of drift by /reconcile.
+const RECONCILE_LIST_LIMIT: usize = 10;
+
+async fn handle_reconcile(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /reconcile in chat {}", username, user_id, chat_id.0);
+    
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
+        return Ok(());
+    }
+    
+    let adjustments: HashMap<i64, i64> = ctx.db.get_local_adjustments().await?.into_iter().collect();
+    let snapshot = reconcile_snapshot(&ctx, &adjustments).await?;
+    match ctx.api_client.reconcile(&snapshot).await {
+        Ok(report) => {
+            let mut text = reconcile_text(&ctx.db, &report).await?;
+            if !adjustments.is_empty() {
+                text.push_str(&format!("\n\n🛠 Local-only /adjust corrections of {} user(s) were left out.", adjustments.len()));
+            }
+            bot.send_message(chat_id, text).await?;
+            log::info!("Reconciliation for user {}: clean={}", user_id, report.is_clean());
+        }
+        Err(e) => {
+            bot.send_message(chat_id, api_error_message("reconcile with the chain", &e))
+                .await?;
+            log::error!("Failed to reconcile for user {}: {}", user_id, e);
+        }
+    }
+    
+    Ok(())
+}
+
+/// What the database believes the chain holds: every user's balance and the
+/// pools of the open bets. Balances leave out the local-only `adjustments`,
+/// by user, which the chain never saw.
+async fn reconcile_snapshot(ctx: &BotContext, adjustments: &HashMap<i64, i64>) -> anyhow::Result<ReconcileSnapshot> {
+    let balances = ctx
+        .db
+        .get_balances()
+        .await?
+        .into_iter()
+        .map(|(user_id, balance)| LedgerBalance {
+            identity: format!("{}@{}", user_id, ctx.contract_name),
+            balance: balance.saturating_sub(adjustments.get(&user_id).copied().unwrap_or(0)).max(0) as u128,
+        })
+        .collect();
+    let open_markets = ctx
+        .db
+        .get_open_pools(ctx.state_epoch)
+        .await?
+        .into_iter()
+        .map(|pool| LedgerMarket {
+            market_id: pool.market_id as u64,
+            yes_pool: pool.yes_pool.max(0) as u128,
+            no_pool: pool.no_pool.max(0) as u128,
+        })
+        .collect();
+    Ok(ReconcileSnapshot { balances, open_markets })
+}
+
+/// `#2, #5 and 3 more`
+fn bet_id_list(ids: &[u64]) -> String {
+    let mut list = ids.iter().take(RECONCILE_LIST_LIMIT).map(|id| format!("#{}", id)).collect::<Vec<_>>().join(", ");
+    if ids.len() > RECONCILE_LIST_LIMIT {
+        list.push_str(&format!(" and {} more", ids.len() - RECONCILE_LIST_LIMIT));
+    }
+    list
+}
+
+async fn reconcile_text(db: &Database, report: &ReconcileReport) -> anyhow::Result<String> {
+    let totals = &report.totals;
+    let mut text = format!(
+        "🔍 Reconciliation with the chain\n👥 {} users: {} here, {} on-chain\n📂 Open bets: {} here, {} on-chain",
+        totals.users,
+        group_thousands(totals.ledger_balance),
+        group_thousands(totals.on_chain_balance),
+        totals.ledger_open_markets,
+        totals.on_chain_open_markets
+    );
+    if report.is_clean() {
+        text.push_str("\n\n✅ No drift found.");
+        return Ok(text);
+    }
+    
+    if !report.balances.is_empty() {
+        text.push_str(&format!("\n\n⚠️ Balances differ for {} user(s):", report.balances.len()));
+        for drift in report.balances.iter().take(RECONCILE_LIST_LIMIT) {
+            let name = display_name_for_identity(db, &drift.identity).await?;
+            let on_chain = drift.on_chain.map_or_else(|| "unknown".to_string(), group_thousands);
+            text.push_str(&format!("\n• {}: {} here, {} on-chain", name, group_thousands(drift.ledger), on_chain));
+        }
+        if report.balances.len() > RECONCILE_LIST_LIMIT {
+            text.push_str(&format!("\n• … and {} more", report.balances.len() - RECONCILE_LIST_LIMIT));
+        }
+    }
+    if !report.open_only_in_ledger.is_empty() {
+        text.push_str(&format!("\n\n⚠️ Open here only: {}", bet_id_list(&report.open_only_in_ledger)));
+    }
+    if !report.open_only_on_chain.is_empty() {
+        text.push_str(&format!("\n\n⚠️ Open on-chain only: {}", bet_id_list(&report.open_only_on_chain)));
+    }
+    if !report.pools.is_empty() {
+        text.push_str(&format!("\n\n⚠️ Pools differ on {} bet(s):", report.pools.len()));
+        for drift in report.pools.iter().take(RECONCILE_LIST_LIMIT) {
+            text.push_str(&format!(
+                "\n• #{}: YES {} / NO {} here, YES {} / NO {} on-chain",
+                drift.market_id,
+                group_thousands(drift.ledger.0),
+                group_thousands(drift.ledger.1),
+                group_thousands(drift.on_chain.0),
+                group_thousands(drift.on_chain.1)
+            ));
+        }
+        if report.pools.len() > RECONCILE_LIST_LIMIT {
+            text.push_str(&format!("\n• … and {} more", report.pools.len() - RECONCILE_LIST_LIMIT));
+        }
+    }
+    Ok(text)
+}
+
+/// A leaderboard period: a calendar week (from Monday) or month, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LeaderboardWindow {
+    Week,
+    Month,
+}
+
+impl LeaderboardWindow {
+    /// Start of the period containing `now` and start of the one before it.
+    fn bounds(self, now: chrono::DateTime<chrono::Utc>) -> (chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>) {
+        use chrono::Datelike;
+        let today = now.date_naive();
+        let (start, previous) = match self {
+            LeaderboardWindow::Week => {
+                let start = today - chrono::Days::new(today.weekday().num_days_from_monday() as u64);
+                (start, start - chrono::Days::new(7))
+            }
+            LeaderboardWindow::Month => {
+                let start = today.with_day(1).expect("every month has a first day");
+                (start, start - chrono::Months::new(1))
+            }
+        };
+        (start.and_time(chrono::NaiveTime::MIN).and_utc(), previous.and_time(chrono::NaiveTime::MIN).and_utc())
+    }
+    
+    fn title(self) -> &'static str {
+        match self {
+            LeaderboardWindow::Week => "WEEKLY",
+            LeaderboardWindow::Month => "MONTHLY",
+        }
+    }
+}
+
+/// Net profit ranking over the period containing `now`, with each entry's
+/// movement since the previous period.
+async fn profit_leaderboard(
+    db: &Database,
+    currency: &Currency,
+    window: LeaderboardWindow,
+    now: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<String> {
+    let (start, previous_start) = window.bounds(now);
+    let (start, previous_start) = (start.to_rfc3339(), previous_start.to_rfc3339());
+    let end = now.to_rfc3339();
+    let entries = db.get_profit_leaderboard(&start, &end, 10).await?;
+    if entries.is_empty() {
+        return Ok(markdown::escape(&format!(
+            "No bets were placed or settled this {} yet.",
+            if window == LeaderboardWindow::Week { "week" } else { "month" }
+        )));
+    }
+    let previous: HashMap<i64, usize> = db
+        .get_profit_leaderboard(&previous_start, &start, i64::MAX)
+        .await?
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| (entry.user_id, index + 1))
+        .collect();
+    
+    let mut text = format!("Net profit since {}\n\n", &start[..10]);
+    for (index, entry) in entries.iter().enumerate() {
+        let position = index + 1;
+        let medal = match position {
+            1 => "🥇",
+            2 => "🥈",
+            3 => "🥉",
+            _ => "  ",
+        };
+        let movement = match previous.get(&entry.user_id) {
+            None => "🆕".to_string(),
+            Some(before) if *before > position => format!("▲{}", before - position),
+            Some(before) if *before < position => format!("▼{}", position - before),
+            Some(_) => "➖".to_string(),
+        };
+        let name = entry.username.as_ref()
+            .map(|u| format!("@{}", u))
+            .unwrap_or_else(|| format!("User {}", entry.user_id));
+        text.push_str(&format!("{} #{}: {} {} {}\n", medal, position, name, format_signed(currency, entry.profit), movement));
+    }
+    Ok(format!("{}\n{}", markdown::bold(&format!("🏆 {} LEADERBOARD 🏆", window.title())), markdown::escape(&text)))
+}
+
+async fn handle_leaderboard(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /leaderboard in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    let window = match args.trim().to_lowercase().as_str() {
+        "" | "all" => None,
+        "week" => Some(LeaderboardWindow::Week),
+        "month" => Some(LeaderboardWindow::Month),
+        _ => {
+            bot.send_message(chat_id, "Usage: /leaderboard [week|month|all]")
+                .await?;
+            return Ok(());
+        }
+    };
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    if let Some(window) = window {
+        let text = profit_leaderboard(&ctx.db, &currency, window, chrono::Utc::now()).await?;
+        bot.send_markdown(chat_id, text)
+            .await?;
+        return Ok(());
+    }
+    
+    // Get top 10 users, from the on-chain state when available
+    let users: Vec<(String, u128)> = match ctx.api_client.get_leaderboard(10, &ctx.contract_name).await {
+        Ok(entries) => {
+            let mut users = Vec::with_capacity(entries.len());
+            for (identity, balance) in entries {
+                users.push((display_name_for_identity(&ctx.db, &identity).await?, balance));
+            }
+            users
+        }
+        Err(e) => {
+            log::warn!("Could not fetch on-chain leaderboard, using local balances: {}", e);
+            ctx.db.get_leaderboard(10).await?
+                .into_iter()
+                .map(|user| {
+                    let name = user.username
+                        .map(|u| format!("@{}", u))
+                        .unwrap_or_else(|| format!("User {}", user.user_id));
+                    (name, user.balance.max(0) as u128)
+                })
+                .collect()
+        }
+    };
+    
+    if users.is_empty() {
+        bot.send_message(chat_id, "No users have initialized their balance yet. Use /init to get started!")
+            .await?;
+        return Ok(());
+    }
+    
+    let mut leaderboard_text = String::new();
+    
+    for (index, (username_display, balance)) in users.iter().enumerate() {
+        let position = index + 1;
+        let medal = match position {
+            1 => "🥇",
+            2 => "🥈",
+            3 => "🥉",
+            _ => "  ",
+        };
+        
+        leaderboard_text.push_str(&format!(
+            "{} #{}: {} - {}\n",
+            medal, position, username_display, format_amount(&currency, *balance)
+        ));
     }
     
+    bot.send_markdown(chat_id, format!("{}\n\n{}", markdown::bold("🏆 LEADERBOARD 🏆"), markdown::escape(&leaderboard_text)))
+        .await?;
+    
     Ok(())
 }
 
-async fn handle_new(bot: Bot, msg: Message, ctx: Arc<BotContext>, description: String) -> HandlerResult {
+/// Badges earned by a profile, in display order.
+fn profile_badges(rank: Option<(i64, i64)>, streak: u32, totals: &db::LifetimeTotals, params: &ContractParams) -> Vec<&'static str> {
+    let mut badges = Vec::new();
+    if matches!(rank, Some((1, members)) if members > 1) {
+        badges.push("🥇 Top of the chat");
+    }
+    if streak >= 3 {
+        badges.push("🔥 On fire");
+    }
+    if totals.wagered > 0 && totals.wagered as u128 >= params.initial_balance {
+        badges.push("🐋 High roller");
+    }
+    if totals.won > totals.wagered {
+        badges.push("💎 In profit");
+    }
+    badges
+}
+
+async fn handle_me(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
     let chat_id = msg.chat.id;
     let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
     let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
     
-    log::info!("User @{} (ID: {}) called /new in chat {} with: {}", username, user_id, chat_id.0, description);
+    log::info!("User @{} (ID: {}) called /me in chat {}", username, user_id, chat_id.0);
     
-    if description.trim().is_empty() {
-        bot.send_message(chat_id, "Usage: /new <description>\nExample: /new Will it rain tomorrow?")
+    let local = ctx.db.get_user(user_id).await?;
+    let account = match ctx.api_client.get_user(user_id.to_string(), &ctx.contract_name).await {
+        Ok(account) => Some(account).filter(|account| account.initialized),
+        Err(e) => {
+            log::warn!("Could not fetch the on-chain account of {}, using local data: {}", user_id, e);
+            None
+        }
+    };
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    if local.is_none() && account.is_none() {
+        bot.send_message(chat_id, init_first_message(&ctx.params, &currency))
             .await?;
         return Ok(());
     }
     
-    // Check if user has balance
-    let user = ctx.db.get_user(user_id).await?;
-    if user.is_none() {
-        bot.send_message(chat_id, "You need to use /init first to get your initial balance of 10,000.")
-            .await?;
-        return Ok(());
-    }
+    // The chain is authoritative; local balances only fill in when it is unreachable
+    let balance = match (&account, &local) {
+        (Some(account), _) => account.balance,
+        (None, Some(user)) => user.balance.max(0) as u128,
+        (None, None) => 0,
+    };
+    let name = match msg.from.as_ref().and_then(|u| u.username.as_ref()) {
+        Some(username) => format!("@{}", username),
+        None => format!("User {}", user_id),
+    };
+    let rank = ctx.db.get_chat_rank(chat_id.0, user_id).await?;
+    let exposure = ctx.db.get_open_exposure(user_id).await?;
+    // Pending challenges and bets placed elsewhere only show on-chain
+    let on_chain_exposure = match &account {
+        Some(_) => match ctx.api_client.get_open_exposure(user_id.to_string(), &ctx.contract_name).await {
+            Ok(receipt) => receipt.result,
+            Err(e) => {
+                log::warn!("Could not fetch the on-chain exposure of {}, using local data: {}", user_id, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let totals = ctx.db.get_lifetime_totals(user_id).await?;
+    let streak = account.as_ref().map_or(0, |account| account.current_streak);
     
-    // Create market on blockchain
-    match ctx.api_client.create_market(user_id.to_string(), description.clone(), &ctx.contract_name).await {
-        Ok(tx_hash) => {
-            // Store in local database for tracking
-            let bet_id = ctx.db.create_bet(user_id, description.clone()).await?;
-            
-            bot.send_message(
-                chat_id,
-                format!("✅ Market #{} created on-chain by @{}\n📄 Description: {}\nTransaction: {}", 
-                    bet_id, username, description, tx_hash)
-            )
-            .await?;
-            log::info!("Market #{} created successfully by user {} with tx {}", bet_id, user_id, tx_hash);
-        }
-        Err(e) => {
-            bot.send_message(chat_id, format!("❌ Failed to create market: {}", e))
-                .await?;
-            log::error!("Failed to create market for user {}: {}", user_id, e);
+    let mut card = format!("👤 {}
+
+💰 Balance: {}", name, format_amount(&currency, balance));
+    if let Some((position, members)) = rank {
+        card.push_str(&format!("\n🏅 Rank: #{} of {} in this chat", position, members));
+    }
+    if account.is_some() {
+        card.push_str(&format!("\n🔥 Streak: {}", streak));
+    }
+    match on_chain_exposure {
+        Some(exposure) => card.push_str(&format!(
+            "\n🎲 Open bets: {} ({} at risk, {} YES / {} NO)",
+            exposure.positions,
+            format_amount(&currency, exposure.total()),
+            format_amount(&currency, exposure.yes),
+            format_amount(&currency, exposure.no)
+        )),
+        None => card.push_str(&format!(
+            "\n🎲 Open bets: {} ({} at risk)",
+            exposure.open_bets,
+            format_amount(&currency, exposure.at_risk.max(0) as u128)
+        )),
+    }
+    card.push_str(&format!(
+        "\n📈 Lifetime: {} wagered, {} won",
+        format_amount(&currency, totals.wagered.max(0) as u128),
+        format_amount(&currency, totals.won.max(0) as u128)
+    ));
+    let badges = profile_badges(rank, streak, &totals, &ctx.params);
+    card.push_str(&format!(
+        "\n🎖 Badges: {}",
+        if badges.is_empty() { "none yet".to_string() } else { badges.join(", ") }
+    ));
+    match &local {
+        Some(user) => {
+            let tz = ctx.db.get_timezone(chat_id.0).await?;
+            let since = chrono::DateTime::parse_from_rfc3339(&user.created_at)
+                .map(|date| date.with_timezone(&tz).format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|_| user.created_at.clone());
+            card.push_str(&format!("\n\n📅 Since {}", since));
         }
+        // Accounts predating a reset of the local database
+        None => card.push_str("\n\n📅 Known on-chain only, local history was reset"),
     }
     
+    bot.send_message(chat_id, card)
+        .await?;
+    
     Ok(())
 }
 
-async fn handle_bet(bot: Bot, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+/// Calendar month used to bucket LLM spend, e.g. "2025-06".
+fn current_month() -> String {
+    chrono::Utc::now().format("%Y-%m").to_string()
+}
+
+/// Splits trailing `#tag` words off a bet description, e.g. `Pizza on
+/// Friday? #food #work`. Tags come back normalized, in their original order.
+fn split_tags(input: &str) -> (String, Vec<String>) {
+    let mut description = input.trim();
+    let mut tags = Vec::new();
+    loop {
+        let (head, word) = description.rsplit_once(char::is_whitespace).unwrap_or(("", description));
+        if word.len() < 2 || !word.starts_with('#') {
+            break;
+        }
+        tags.push(contract1::normalize_tag(word));
+        description = head.trim_end();
+    }
+    tags.reverse();
+    (description.to_string(), tags)
+}
+
+/// Splits a trailing `deadline:<date>` token off a bet description, as of
+/// `now`. Dates and days like `tomorrow` are read in the chat's timezone
+/// `tz` and mean the end of that day without a time, durations like `3d`
+/// count from now.
+fn split_deadline(
+    input: &str,
+    tz: chrono_tz::Tz,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(String, Option<chrono::DateTime<chrono::Utc>>), String> {
+    let input = input.trim();
+    let Some((description, value)) = input.rsplit_once("deadline:") else {
+        return Ok((input.to_string(), None));
+    };
+    let value = value.trim();
+
+    let deadline = if let Some(deadline) = timezone::parse_local_deadline(value, tz, now) {
+        Some(deadline)
+    } else if let Ok(duration) = parse::duration(value) {
+        Some(now + duration)
+    } else {
+        chrono::DateTime::parse_from_rfc3339(value).ok().map(|d| d.with_timezone(&chrono::Utc))
+    };
+
+    match deadline {
+        Some(deadline) if deadline > now => Ok((description.trim().to_string(), Some(deadline))),
+        Some(_) => Err("The deadline must be in the future.".to_string()),
+        None => Err(format!("Invalid deadline {:?}.", value)),
+    }
+}
+
+fn deadline_passed(bet: &db::Bet) -> bool {
+    bet.deadline
+        .as_deref()
+        .and_then(|d| chrono::DateTime::parse_from_rfc3339(d).ok())
+        .is_some_and(|deadline| deadline < chrono::Utc::now())
+}
+
+async fn handle_resolve(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
     let chat_id = msg.chat.id;
     let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
     let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
     
-    log::info!("User @{} (ID: {}) called /bet in chat {} with: {}", username, user_id, chat_id.0, args);
+    log::info!("User @{} (ID: {}) called /resolve in chat {} with: {}", username, user_id, chat_id.0, args);
     
-    // Parse bet_id, yes/no, and amount
-    let parts: Vec<&str> = args.split_whitespace().collect();
-    if parts.len() < 3 {
-        bot.send_message(chat_id, "Usage: /bet <bet_id> <yes/no> <amount>\nExample: /bet 1 yes 100")
+    if !is_chat_admin(&bot, &msg, user_id).await? {
+        bot.send_message(chat_id, "Only admins can use the /resolve command in group chats.")
             .await?;
         return Ok(());
     }
     
-    let bet_id = match parts[0].parse::<i64>() {
-        Ok(id) => id,
-        Err(_) => {
-            bot.send_message(chat_id, "Invalid bet ID. Please provide a number.\nUsage: /bet <bet_id> <yes/no> <amount>")
-                .await?;
-            return Ok(());
-        }
-    };
-    
-    let side_str = parts[1].to_lowercase();
-    let amount_str = parts[2];
-    
-    // Parse side (yes/no to boolean)
-    let side = match side_str.as_str() {
-        "yes" | "y" => true,
-        "no" | "n" => false,
-        _ => {
-            bot.send_message(chat_id, "Please specify 'yes' or 'no' for the side.\nUsage: /bet <bet_id> <yes/no> <amount>")
-                .await?;
+    let (bet_id, outcome) = match parse::command(&args, Usage::RESOLVE, |args| Ok((args.market_id()?, args.side()?))) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
             return Ok(());
         }
     };
     
-    let amount = match amount_str.parse::<i64>() {
-        Ok(amt) if amt > 0 => amt,
-        _ => {
-            bot.send_message(chat_id, "Invalid amount. Please provide a positive number.")
+    // Admins settle a bet a /solve is still evaluating
+    let bet = match ctx.db.get_bet_by_id(bet_id).await? {
+        Some(b) if b.status == "open" || b.status == "resolving" => b,
+        Some(_) => {
+            bot.send_message(chat_id, "This bet is already closed.")
                 .await?;
             return Ok(());
         }
-    };
-    
-    // Check if user has balance
-    let user = ctx.db.get_user(user_id).await?;
-    let user = match user {
-        Some(u) => u,
         None => {
-            bot.send_message(chat_id, "You need to use /init first to get your initial balance of 10,000.")
+            bot.send_message(chat_id, format!("Bet #{} not found.", bet_id))
                 .await?;
             return Ok(());
         }
     };
     
-    if user.balance < amount {
-        bot.send_message(chat_id, format!("Insufficient balance. You have {} but tried to bet {}.", user.balance, amount))
+    settle_as_admin(&bot, &ctx, chat_id, user_id, &username, &bet, outcome, "MARKET RESOLVED BY ADMIN").await
+}
+
+async fn handle_expire(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /expire in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    if !is_chat_admin(&bot, &msg, user_id).await? {
+        bot.send_message(chat_id, "Only admins can use the /expire command in group chats.")
             .await?;
         return Ok(());
     }
     
-    // Find the bet by ID
-    let bet = ctx.db.get_bet_by_id(bet_id).await?;
-    let bet = match bet {
-        Some(b) if b.status == "open" => b,
+    let bet_id = match parse::command(&args, Usage::EXPIRE, |args| args.market_id()) {
+        Ok(bet_id) => bet_id,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    
+    // Admins settle a bet a /solve is still evaluating
+    let bet = match ctx.db.get_bet_by_id(bet_id).await? {
+        Some(b) if b.status == "open" || b.status == "resolving" => b,
         Some(_) => {
-            bot.send_message(chat_id, format!("Bet #{} is already closed.", bet_id))
+            bot.send_message(chat_id, "This bet is already closed.")
                 .await?;
             return Ok(());
         }
         None => {
-            bot.send_message(chat_id, format!("Bet #{} not found. Use /list to see available bets.", bet_id))
+            bot.send_message(chat_id, format!("Bet #{} not found.", bet_id))
                 .await?;
             return Ok(());
         }
     };
     
-    // Place bet on blockchain
-    match ctx.api_client.place_bet(user_id.to_string(), bet_id as u64, side, amount as u128, &ctx.contract_name).await {
-        Ok(tx_hash) => {
-            // Create the wager and update balance locally
-            let _wager_id = ctx.db.create_wager(bet.bet_id, user_id, amount, side).await?;
-            let new_balance = user.balance - amount;
-            ctx.db.update_user_balance(user_id, new_balance).await?;
-            
-            let side_text = if side { "YES ✅" } else { "NO ❌" };
-            
-            bot.send_message(
+    if !deadline_passed(&bet) {
+        bot.send_message(chat_id, format!("Bet #{} has no deadline or it has not passed yet.", bet_id))
+            .await?;
+        return Ok(());
+    }
+    
+    settle_as_admin(&bot, &ctx, chat_id, user_id, &username, &bet, false, "MARKET EXPIRED").await
+}
+
+/// Resolves `bet` on-chain on behalf of an admin, without an LLM evaluation.
+#[allow(clippy::too_many_arguments)]
+async fn settle_as_admin(
+    bot: &Messenger,
+    ctx: &BotContext,
+    chat_id: ChatId,
+    user_id: i64,
+    username: &str,
+    bet: &db::Bet,
+    outcome: bool,
+    title: &str,
+) -> HandlerResult {
+    let bet_id = bet.bet_id;
+    if predates_season(ctx, bet) {
+        bot.send_message(chat_id, predates_season_message(bet_id)).await?;
+        return Ok(());
+    }
+    let own_resolution = OwnAction::Resolve { market_id: bet.on_chain_id() };
+    ctx.own_actions.record(own_resolution.clone());
+    match ctx.api_client.resolve_market(user_id.to_string(), bet.on_chain_id(), outcome, &ctx.contract_name).await {
+        Ok(receipt) => {
+            ctx.db.close_bet(bet_id, outcome).await?;
+            let currency = ctx.db.get_currency(chat_id.0).await?;
+            // Older servers do not report the payouts
+            let payout = receipt
+                .result
+                .as_ref()
+                .map(|result| format!("\n\n{}", payout_line(&currency, Some(result))))
+                .unwrap_or_default();
+            bot.send_markdown(
                 chat_id,
                 format!(
-                    "💰 Bet placed on-chain!\n📝 Market #{}: {}\n🎯 Side: {}\n💵 Amount: {}\n💳 Remaining balance: {}\nTransaction: {}",
-                    bet_id, bet.description, side_text, amount, new_balance, tx_hash
+                    "{}\n\n{}",
+                    markdown::bold(&format!("✅ {}", title)),
+                    markdown::escape(&format!(
+                        "📊 Market #{}\n📄 Description: {}\n🎯 Outcome: {}\n👤 Resolved by: @{}\n\nTransaction: {}{}",
+                        bet_id,
+                        bet.description,
+                        if outcome { "YES ✅" } else { "NO ❌" },
+                        username,
+                        receipt.tx_hash,
+                        payout
+                    ))
                 )
             )
             .await?;
-            log::info!("Bet placed by user {} on market {} for amount {} on side {} with tx {}", 
-                user_id, bet.bet_id, amount, if side { "yes" } else { "no" }, tx_hash);
+            log::info!("Market #{} resolved by admin {} with tx {}", bet_id, user_id, receipt.tx_hash);
         }
         Err(e) => {
-            bot.send_message(chat_id, format!("❌ Failed to place bet: {}", e))
+            ctx.own_actions.take(&own_resolution);
+            let tz = ctx.db.get_timezone(chat_id.0).await?;
+            bot.send_message(chat_id, resolve_error_message("resolve the market", &e, bet_id, tz))
                 .await?;
-            log::error!("Failed to place bet for user {}: {}", user_id, e);
+            log::error!("Failed to resolve market {} for admin {}: {}", bet_id, user_id, e);
         }
     }
     
     Ok(())
 }
 
-async fn handle_solve(bot: Bot, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+async fn handle_auto_expire(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
     let chat_id = msg.chat.id;
-    let solver_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
-    let solver_username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
-    
-    log::info!("User @{} (ID: {}) called /solve in chat {}", solver_username, solver_id, chat_id.0);
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
     
-    // Parse optional bet_id from command
-    let text = msg.text().unwrap_or("");
-    let parts: Vec<&str> = text.split_whitespace().collect();
-    let bet_id = if parts.len() > 1 {
-        parts[1].parse::<i64>().ok()
-    } else {
-        None
-    };
+    log::info!("User @{} (ID: {}) called /autoexpire in chat {} with: {}", username, user_id, chat_id.0, args);
     
-    if msg.reply_to_message().is_none() {
-        bot.send_message(chat_id, "Please reply to a message to use /solve\nUsage: /solve [bet_id]")
+    if !is_chat_admin(&bot, &msg, user_id).await? {
+        bot.send_message(chat_id, "Only admins can use the /autoexpire command in group chats.")
             .await?;
         return Ok(());
     }
     
-    // Check if user has balance
-    let user = ctx.db.get_user(solver_id).await?;
-    if user.is_none() {
-        bot.send_message(chat_id, "You need to use /init first to get your initial balance of 10,000.")
+    let enabled = match args.trim().to_lowercase().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            let current = if ctx.db.get_auto_expire(chat_id.0).await? { "on" } else { "off" };
+            bot.send_message(chat_id, format!("Usage: /autoexpire <on/off> (currently {})", current))
+                .await?;
+            return Ok(());
+        }
+    };
+    
+    ctx.db.set_auto_expire(chat_id.0, enabled).await?;
+    let reply = if enabled {
+        "Bets still unsolved after their deadline's grace period will now resolve as NO automatically."
+    } else {
+        "Bets still unsolved after their deadline's grace period will now only be flagged in the chat."
+    };
+    bot.send_message(chat_id, reply).await?;
+    
+    Ok(())
+}
+
+async fn handle_currency(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /currency in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    if !is_chat_admin(&bot, &msg, user_id).await? {
+        bot.send_message(chat_id, "Only admins can use the /currency command in group chats.")
             .await?;
         return Ok(());
     }
     
-    let replied_msg = msg.reply_to_message().unwrap();
-    let message_id = replied_msg.id.0 as i64;
-    
-    // Extract the text content of the replied message
-    let replied_text = replied_msg.text().unwrap_or("<no text content>");
-    let replied_user = replied_msg.from.as_ref()
-        .and_then(|u| u.username.clone())
-        .unwrap_or_else(|| "unknown".to_string());
-    
-    // If no bet_id provided, we need to ask for it
-    let bet_id = match bet_id {
-        Some(id) => id,
-        None => {
+    let currency = match args.trim() {
+        "" => {
+            let current = ctx.db.get_currency(chat_id.0).await?;
             bot.send_message(
                 chat_id,
-                "Please specify which bet this solves. Usage: /solve <bet_id>\nExample: /solve 1"
+                format!(
+                    "Usage: /currency [emoji] <name>|reset (currently {}, names up to {} characters)",
+                    format_amount(&current, 100),
+                    currency::MAX_CURRENCY_NAME_CHARS
+                ),
             )
             .await?;
             return Ok(());
         }
-    };
-    
-    // Get the bet details
-    let bet = match ctx.db.get_bet_by_id(bet_id).await? {
-        Some(b) if b.status == "open" => b,
-        Some(_) => {
-            bot.send_message(chat_id, "This bet is already closed.")
-                .await?;
-            return Ok(());
-        }
-        None => {
-            bot.send_message(chat_id, format!("Bet #{} not found.", bet_id))
-                .await?;
-            return Ok(());
-        }
-    };
-    
-    // Get Claude API key from environment
-    let api_key = match std::env::var("CLAUDE_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
-            log::error!("CLAUDE_API_KEY not set");
-            bot.send_message(chat_id, "❌ Bot configuration error: Claude API key not set.")
+        reset if reset.eq_ignore_ascii_case("reset") => None,
+        args => match Currency::parse(args) {
+            Some(currency) => Some(currency),
+            None => {
+                bot.send_message(
+                    chat_id,
+                    format!("Currency names are 1 to {} characters on one line.", currency::MAX_CURRENCY_NAME_CHARS),
+                )
                 .await?;
-            return Ok(());
-        }
+                return Ok(());
+            }
+        },
     };
     
-    // Send processing message
-    bot.send_message(chat_id, "🤔 Evaluating solution with Sonnet...")
+    ctx.db.set_currency(chat_id.0, currency.as_ref()).await?;
+    let shown = currency.unwrap_or_default();
+    bot.send_message(chat_id, format!("✅ Amounts in this chat now read like {}.", format_amount(&shown, 1_000)))
         .await?;
     
-    // Call Claude to evaluate the solution
-    let resolution = match claude::evaluate_bet_resolution(
-        &api_key,
-        bet_id,
-        &bet.description,
-        replied_text,
-        &replied_user,
-    ).await {
-        Ok(res) => res,
-        Err(e) => {
-            log::error!("Claude API error: {:?}", e);
+    Ok(())
+}
+
+async fn handle_settings(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /settings in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    let words: Vec<&str> = args.split_whitespace().collect();
+    match words.as_slice() {
+        [] => {
+            let tz = ctx.db.get_timezone(chat_id.0).await?;
+            let currency = ctx.db.get_currency(chat_id.0).await?;
+            let auto_expire = if ctx.db.get_auto_expire(chat_id.0).await? { "on" } else { "off" };
             bot.send_message(
                 chat_id,
-                format!("❌ Failed to evaluate solution: {}", e)
+                format!(
+                    "⚙️ Settings of this chat\n🌍 Timezone: {} (it is {} there)\n💱 Currency: {}\n⏰ Auto-expire: {}\n\n{}",
+                    tz.name(),
+                    chrono::Utc::now().with_timezone(&tz).format("%H:%M"),
+                    format_amount(&currency, 100),
+                    auto_expire,
+                    Usage::SETTINGS
+                ),
             )
             .await?;
-            return Ok(());
         }
-    };
-    
-    // Record the solution
-    let solution_id = ctx.db.create_solution(bet_id, solver_id, message_id).await?;
-    
-    if resolution.resolved {
-        // Resolve the market on blockchain
-        match ctx.api_client.resolve_market(
-            solver_id.to_string(),
-            bet_id as u64,
-            resolution.outcome,
-            &ctx.contract_name
-        ).await {
-            Ok(tx_hash) => {
-                // Close the bet locally
-                ctx.db.close_bet(bet_id, resolution.outcome).await?;
-                
-                // The contract automatically distributes winnings when resolving
-                // Log that resolution was successful but don't update balances locally
-                log::info!("Market #{} resolved. Contract automatically distributed winnings to winners.", bet_id);
-                
-                // Note: In a production system, you might want to:
-                // 1. Query the blockchain for updated balances
-                // 2. Update local database with the new balances
-                // This ensures local state stays in sync with on-chain state
-                
-                bot.send_message(
-                    chat_id,
-                    format!(
-                        "✅ MARKET RESOLVED ON-CHAIN!\n\n📊 Market #{}\n📄 Description: {}\n💬 Solution: \"{}\"\n👤 Solved by: @{}\n🎯 Outcome: {}\n\n🤖 Sonnet's analysis: {}\n\nTransaction: {}\n\n💰 Winnings have been automatically distributed to all winners!",
-                        bet_id,
-                        bet.description,
-                        replied_text,
-                        solver_username,
-                        if resolution.outcome { "YES ✅" } else { "NO ❌" },
-                        resolution.reasoning,
-                        tx_hash
-                    )
-                )
-                .await?;
-                log::info!("Market #{} resolved on-chain with tx {}", bet_id, tx_hash);
+        ["set", "timezone", name] => {
+            if !is_chat_admin(&bot, &msg, user_id).await? {
+                bot.send_message(chat_id, "Only admins can change the settings of group chats.")
+                    .await?;
+                return Ok(());
             }
-            Err(e) => {
+            let Some(tz) = timezone::parse(name) else {
                 bot.send_message(
                     chat_id,
-                    format!("❌ Failed to resolve market on-chain: {}\n\nThe bet remains open.", e)
+                    format!("Unknown timezone {:?}: expected a name like Europe/Paris, America/New_York or UTC.", name),
                 )
                 .await?;
-                log::error!("Failed to resolve market {}: {}", bet_id, e);
+                return Ok(());
+            };
+            ctx.db.set_timezone(chat_id.0, tz).await?;
+            bot.send_message(
+                chat_id,
+                format!(
+                    "✅ Times in this chat are now shown and read in {}, where it is {}.",
+                    tz.name(),
+                    chrono::Utc::now().with_timezone(&tz).format("%H:%M")
+                ),
+            )
+            .await?;
+        }
+        _ => {
+            bot.send_message(chat_id, Usage::SETTINGS.to_string()).await?;
+        }
+    }
+    
+    Ok(())
+}
+
+async fn handle_cost(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+    
+    log::info!("User @{} (ID: {}) called /cost in chat {} with: {}", username, user_id, chat_id.0, args);
+    
+    if !is_chat_admin(&bot, &msg, user_id).await? {
+        bot.send_message(chat_id, "Only admins can use the /cost command in group chats.")
+            .await?;
+        return Ok(());
+    }
+    
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    match parts.as_slice() {
+        [] => {}
+        ["budget", "off"] => {
+            ctx.db.set_chat_budget(chat_id.0, None).await?;
+            bot.send_message(chat_id, "Monthly evaluation budget removed.")
+                .await?;
+            return Ok(());
+        }
+        ["budget", amount] => {
+            match amount.trim_start_matches('$').parse::<f64>() {
+                Ok(usd) if usd >= 0.0 => {
+                    let budget = (usd * 1_000_000.0).round() as i64;
+                    ctx.db.set_chat_budget(chat_id.0, Some(budget)).await?;
+                    bot.send_message(chat_id, format!("Monthly evaluation budget set to {}.", format_usd(budget)))
+                        .await?;
+                }
+                _ => {
+                    bot.send_message(chat_id, "Budget must be a positive dollar amount, e.g. /cost budget 5")
+                        .await?;
+                }
             }
+            return Ok(());
+        }
+        _ => {
+            bot.send_message(chat_id, "Usage: /cost [budget <usd>|budget off]")
+                .await?;
+            return Ok(());
         }
-    } else {
-        bot.send_message(
-            chat_id,
-            format!(
-                "❌ NOT RESOLVED\n\n📊 Market #{}\n📄 Description: {}\n💬 Proposed solution: \"{}\"\n👤 Proposed by: @{}\n\n🤖 Sonnet's analysis: {}\n\nThe market remains open.",
-                bet_id,
-                bet.description,
-                replied_text,
-                solver_username,
-                resolution.reasoning
-            )
-        )
-        .await?;
     }
     
-    log::info!("Solution #{} evaluated for bet #{}: resolved={}", solution_id, bet_id, resolution.resolved);
+    let month = current_month();
+    let usage = ctx.db.get_llm_usage(chat_id.0, &month).await?;
+    let budget = match ctx.db.get_chat_budget(chat_id.0).await? {
+        Some(budget) => format!(
+            "{} ({} left)",
+            format_usd(budget),
+            format_usd((budget - usage.cost_micros).max(0))
+        ),
+        None => "none".to_string(),
+    };
+    
+    bot.send_message(
+        chat_id,
+        format!(
+            "💸 Evaluation spend for {}\n\n🧮 Evaluations: {}\n🔤 Tokens: {} in / {} out\n💰 Spend: {}\n📏 Budget: {}",
+            month,
+            usage.evaluations,
+            usage.input_tokens,
+            usage.output_tokens,
+            format_usd(usage.cost_micros),
+            budget
+        )
+    )
+    .await?;
     
     Ok(())
 }
 
-async fn handle_list(bot: Bot, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+async fn handle_reset(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
     let chat_id = msg.chat.id;
     let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
     let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
     
-    log::info!("User @{} (ID: {}) called /list in chat {}", username, user_id, chat_id.0);
-    
-    let bets = ctx.db.get_all_bets().await?;
+    log::info!("User @{} (ID: {}) called /reset in chat {}", username, user_id, chat_id.0);
     
-    if bets.is_empty() {
-        bot.send_message(chat_id, "No bets available. Use /new to create the first bet!")
-            .await?;
+    // Wipes every chat's data, so group admins cannot
+    if !ensure_operator(&bot, &msg, &ctx, user_id).await? {
         return Ok(());
     }
     
-    let mut message = "📄 **AVAILABLE BETS** 📄\n\n".to_string();
+    // Reset the database
+    ctx.db.reset_all().await?;
     
-    for bet in bets.iter().take(20) {  // Limit to 20 most recent bets
-        let status_emoji = match bet.status.as_str() {
-            "open" => "🟢",
-            "resolved_yes" => "✅",
-            "resolved_no" => "❌",
-            _ => "❔",
-        };
-        
-        let truncated_desc = if bet.description.len() > 50 {
-            format!("{}...", &bet.description[..50])
-        } else {
-            bet.description.clone()
-        };
-        
-        message.push_str(&format!(
-            "{} Bet #{}: {}\n",
-            status_emoji, bet.bet_id, truncated_desc
-        ));
-    }
+    bot.send_message(
+        chat_id,
+        "⚠️ Database has been reset!\n\n• All user balances cleared\n• All bets removed\n• All solutions deleted\n• All users need to /init again"
+    )
+    .await?;
+    
+    log::info!("Database reset successfully by user {}", user_id);
+    
+    Ok(())
+}
+
+async fn handle_cleanup(bot: Messenger, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
     
-    if bets.len() > 20 {
-        message.push_str(&format!("\n... and {} more bets", bets.len() - 20));
+    log::info!("User @{} (ID: {}) called /cleanup in chat {}", username, user_id, chat_id.0);
+    
+    if !is_chat_admin(&bot, &msg, user_id).await? {
+        bot.send_message(chat_id, "Only admins can use the /cleanup command in group chats.")
+            .await?;
+        return Ok(());
     }
     
-    message.push_str("\n\nUse /bet <bet_id> <yes/no> <amount> to place a wager!");
+    let archived = ctx.db.run_retention(&ctx.retention).await?;
+    let pruned = prune_messages(&ctx);
     
-    bot.send_message(chat_id, message)
-        .await?;
+    bot.send_message(
+        chat_id,
+        format!(
+            "🧹 Archived {} bet(s) resolved over {} days ago.\nArchived bets are still available with /info <bet_id>.",
+            archived, ctx.retention.retention_days
+        )
+    )
+    .await?;
+    
+    log::info!("Cleanup by user {} archived {} bets and dropped {} messages", user_id, archived, pruned);
     
     Ok(())
 }
 
-async fn handle_leaderboard(bot: Bot, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+/// Drops the buffered messages older than the retention limit.
+fn prune_messages(ctx: &BotContext) -> usize {
+    let cutoff = chrono::Utc::now() - chrono::Duration::hours(ctx.retention.message_retention_hours);
+    ctx.recent_messages.prune_before(cutoff)
+}
+
+/// `/alerts [on|off]`: whether the caller is sent a private message when the
+/// odds of a bet they joined move, see [`alerts`]. The choice is the user's
+/// own, for every chat.
+async fn handle_alerts(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let enabled = format!(
+        "🔔 Odds alerts are on: I'll message you privately when the odds of a bet you joined move by more than {} points, at most once an hour per bet.",
+        alerts::ODDS_ALERT_THRESHOLD_BPS / 100
+    );
+
+    let reply = match args.trim().to_lowercase().as_str() {
+        "off" => {
+            ctx.db.set_alerts_optout(user_id, true).await?;
+            "🔕 Odds alerts are off: you won't hear about the bets you joined moving. Send /alerts on to get them again.".to_string()
+        }
+        "on" => {
+            ctx.db.set_alerts_optout(user_id, false).await?;
+            enabled
+        }
+        "" if ctx.db.is_alerts_opted_out(user_id).await? => "🔕 Odds alerts are off. Send /alerts on to get them again.".to_string(),
+        "" => format!("{} Send /alerts off to stop them.", enabled),
+        other => {
+            let problem = parse::Problem::Unexpected(other.to_string());
+            parse::ParseError { problem, usage: Usage::ALERTS }.to_string()
+        }
+    };
+    bot.send_message(chat_id, reply).await?;
+    Ok(())
+}
+
+async fn handle_privacy(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
     let chat_id = msg.chat.id;
     let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
     let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
     
-    log::info!("User @{} (ID: {}) called /leaderboard in chat {}", username, user_id, chat_id.0);
+    log::info!("User @{} (ID: {}) called /privacy in chat {} with: {}", username, user_id, chat_id.0, args);
     
-    // Get top 10 users
-    let users = ctx.db.get_leaderboard(10).await?;
-    
-    if users.is_empty() {
-        bot.send_message(chat_id, "No users have initialized their balance yet. Use /init to get started!")
-            .await?;
+    let choice = match args.trim().to_lowercase().as_str() {
+        "" => None,
+        "optin" => Some(true),
+        "optout" => Some(false),
+        other => {
+            let problem = parse::Problem::Unexpected(other.to_string());
+            bot.send_message(chat_id, parse::ParseError { problem, usage: Usage::PRIVACY }.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let hours = ctx.retention.message_retention_hours;
+    
+    // In a private chat the choice is the user's own, for every group
+    if msg.chat.is_private() {
+        let reply = match choice {
+            Some(false) => {
+                ctx.db.set_privacy_optout(user_id, true).await?;
+                let removed = ctx.recent_messages.forget_author(user_id);
+                log::info!("User {} opted out of message history, {} buffered message(s) dropped", user_id, removed);
+                "🔒 Your group messages will no longer be kept, and those already kept were deleted. /solve can still judge a message of yours it replies to directly.".to_string()
+            }
+            Some(true) => {
+                ctx.db.set_privacy_optout(user_id, false).await?;
+                "🔓 Your messages may be kept again in the groups that opted in.".to_string()
+            }
+            None if ctx.db.is_privacy_opted_out(user_id).await? => {
+                "🔒 You opted out: none of your group messages are kept. Send /privacy optin to allow it again.".to_string()
+            }
+            None => format!(
+                "In groups that opted in, your last messages are kept in memory for {} hours so /solve can show them as context. Send /privacy optout to never have them kept.",
+                hours
+            ),
+        };
+        bot.send_message(chat_id, reply).await?;
         return Ok(());
     }
     
-    let mut leaderboard_text = "🏆 LEADERBOARD 🏆\n\n".to_string();
-    
-    for (index, user) in users.iter().enumerate() {
-        let position = index + 1;
-        let medal = match position {
-            1 => "🥇",
-            2 => "🥈",
-            3 => "🥉",
-            _ => "  ",
+    let Some(enabled) = choice else {
+        let reply = if ctx.db.get_message_buffer(chat_id.0).await? {
+            format!(
+                "🗂 Message history is ON: the last {} text messages of this chat are kept in memory for at most {} hours, only to be quoted as context by /solve <bet_id> <N> (kept right now: {}).\nAnyone can opt out of it in a private chat with me: /privacy optout",
+                RECENT_MESSAGES_PER_CHAT,
+                hours,
+                ctx.recent_messages.len(chat_id.0)
+            )
+        } else {
+            "🗂 Message history is OFF: no messages of this chat are kept, and /solve only judges the message it replies to. Admins can enable it with /privacy optin".to_string()
         };
-        
-        let username_display = user.username.as_ref()
-            .map(|u| format!("@{}", u))
-            .unwrap_or_else(|| format!("User {}", user.user_id));
-        
-        leaderboard_text.push_str(&format!(
-            "{} #{}: {} - {} coins\n",
-            medal, position, username_display, user.balance
-        ));
+        bot.send_message(chat_id, reply).await?;
+        return Ok(());
+    };
+    
+    if !is_chat_admin(&bot, &msg, user_id).await? {
+        bot.send_message(chat_id, "Only admins can use the /privacy command in group chats.")
+            .await?;
+        return Ok(());
     }
     
-    bot.send_message(chat_id, leaderboard_text)
-        .await?;
+    ctx.db.set_message_buffer(chat_id.0, enabled).await?;
+    let reply = if enabled {
+        format!("🗂 Message history enabled: recent messages will be kept for {} hours as /solve context. Members can opt out in a private chat with /privacy optout.", hours)
+    } else {
+        ctx.recent_messages.forget_chat(chat_id.0);
+        "🗂 Message history disabled: the messages kept for this chat were deleted.".to_string()
+    };
+    bot.send_message(chat_id, reply).await?;
     
     Ok(())
 }
 
-async fn handle_reset(bot: Bot, msg: Message, ctx: Arc<BotContext>) -> HandlerResult {
+async fn handle_info(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
     let chat_id = msg.chat.id;
     let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
     let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
     
-    log::info!("User @{} (ID: {}) called /reset in chat {}", username, user_id, chat_id.0);
+    log::info!("User @{} (ID: {}) called /info in chat {} with: {}", username, user_id, chat_id.0, args);
     
-    // Check if it's a group chat
-    if matches!(msg.chat.kind, ChatKind::Public(_)) {
-        // In group chats, only admins can reset
-        let admins = bot.get_chat_administrators(chat_id).await?;
-        let is_admin = admins.iter().any(|admin| {
-            admin.user.id.0 as i64 == user_id
-        });
-        
-        if !is_admin {
-            bot.send_message(chat_id, "Only admins can use the /reset command in group chats.")
+    let bet_id = match parse::command(&args, Usage::INFO, |args| args.market_id()) {
+        Ok(bet_id) => bet_id,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    
+    let (bet, archived) = match ctx.db.get_bet_or_archived(bet_id).await? {
+        Some(found) => found,
+        None => {
+            bot.send_message(chat_id, format!("Bet #{} not found. Use /list to see available bets.", bet_id))
                 .await?;
             return Ok(());
         }
+    };
+    
+    let wagers = if archived {
+        ctx.db.get_archived_wagers_for_bet(bet_id).await?
+    } else {
+        ctx.db.get_wagers_for_bet(bet_id).await?
+    };
+    
+    let yes_pool: i64 = wagers.iter().filter(|w| w.side).map(|w| w.amount).sum();
+    let no_pool: i64 = wagers.iter().filter(|w| !w.side).map(|w| w.amount).sum();
+    
+    let status_text = match bet.status.as_str() {
+        "open" => "🟢 Open",
+        "resolving" => "⏳ Resolution in progress",
+        "resolved_yes" => "✅ Resolved: YES",
+        "resolved_no" => "❌ Resolved: NO",
+        "cancelled" => "🚫 Cancelled",
+        "void" => "⌛ Void: expired unresolved, stakes refunded",
+        _ => "❔ Unknown",
+    };
+    
+    let currency = ctx.db.get_currency(chat_id.0).await?;
+    let tz = ctx.db.get_timezone(chat_id.0).await?;
+    let now = chrono::Utc::now();
+    let when = |rfc3339: &str| match chrono::DateTime::parse_from_rfc3339(rfc3339) {
+        Ok(at) => timezone::humanize(at.with_timezone(&chrono::Utc), now, tz),
+        Err(_) => rfc3339.to_string(),
+    };
+    let mut message = format!(
+        "📊 Market #{}\n📄 Description: {}\n📌 Status: {}\n✅ YES pool: {}\n❌ NO pool: {}\n💰 Total pool: {}\n👥 Wagers: {}\n🕒 Created: {}",
+        bet.bet_id,
+        bet.description,
+        status_text,
+        format_amount(&currency, yes_pool.max(0) as u128),
+        format_amount(&currency, no_pool.max(0) as u128),
+        format_amount(&currency, (yes_pool + no_pool).max(0) as u128),
+        wagers.len(),
+        when(&bet.created_at)
+    );
+    if let Some(deadline) = &bet.deadline {
+        message.push_str(&format!("\n⏰ Deadline: {}", when(deadline)));
+    }
+    let impressions = ctx.db.count_inline_impressions(bet_id).await?;
+    if impressions > 0 {
+        message.push_str(&format!("\n🔎 Shown in inline searches: {}", impressions));
+    }
+    if archived {
+        message.push_str("\n\n🗄 This market has been archived.");
     }
     
-    // Reset the database
-    ctx.db.reset_all().await?;
+    bot.send_message(chat_id, message)
+        .await?;
     
-    bot.send_message(
+    Ok(())
+}
+
+async fn handle_stats(bot: Messenger, msg: Message, ctx: Arc<BotContext>, args: String) -> HandlerResult {
+    let chat_id = msg.chat.id;
+    let user_id = msg.from.as_ref().map(|u| u.id.0 as i64).unwrap_or(0);
+    let username = msg.from.as_ref().and_then(|u| u.username.clone()).unwrap_or_else(|| "unknown".to_string());
+
+    log::info!("User @{} (ID: {}) called /stats in chat {} with: {}", username, user_id, chat_id.0, args);
+
+    let bet_id = match parse::command(&args, Usage::STATS, |args| args.market_id()) {
+        Ok(bet_id) => bet_id,
+        Err(e) => {
+            bot.send_message(chat_id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    let Some((bet, _)) = ctx.db.get_bet_or_archived(bet_id).await? else {
+        bot.send_message(chat_id, format!("Bet #{} not found. Use /list to see available bets.", bet_id)).await?;
+        return Ok(());
+    };
+
+    if predates_season(&ctx, &bet) {
+        bot.send_message(chat_id, predates_season_message(bet_id)).await?;
+        return Ok(());
+    }
+    let history = match ctx.api_client.get_market_history(bet.on_chain_id(), &ctx.contract_name).await {
+        Ok(history) => history,
+        Err(e) => {
+            log::error!("Failed to fetch the history of market #{}: {}", bet_id, e);
+            bot.send_message(chat_id, format!("❌ Couldn't load the history of bet #{}, please try again later.", bet_id))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let header = format!("{}\n{}", markdown::bold(&format!("📈 Odds of bet #{}", bet_id)), markdown::escape(&bet.description));
+    let (Some(first), Some(last)) = (history.first(), history.last()) else {
+        bot.send_markdown(chat_id, format!("{}\n\n{}", header, markdown::escape("No bets yet."))).await?;
+        return Ok(());
+    };
+
+    let percent = |point| (announcements::yes_share(point) * 100.0).round();
+    let summary = format!(
+        "YES {}% → {}% over {} bet{}",
+        percent(first),
+        percent(last),
+        history.len(),
+        if history.len() == 1 { "" } else { "s" }
+    );
+    bot.send_markdown(
         chat_id,
-        "⚠️ Database has been reset!\n\n• All user balances cleared\n• All bets removed\n• All solutions deleted\n• All users need to /init again"
+        format!("{}\n\n{}\n{}", header, announcements::sparkline(&history), markdown::escape(&summary)),
     )
     .await?;
-    
-    log::info!("Database reset successfully by user {}", user_id);
-    
+
     Ok(())
 }
 
-async fn handle_message(bot: Bot, msg: Message, cmd: Command, ctx: Arc<BotContext>) -> HandlerResult {
+/// Remembers a message that looks like a command but is none the bot knows,
+/// e.g. `/bte`, so correcting it by editing still places the bet.
+fn remember_command_attempt(ctx: &BotContext, msg: &Message) {
+    if msg.text().is_some_and(|text| text.starts_with('/')) {
+        ctx.recent_commands.seen(msg.chat.id, msg.id);
+    }
+}
+
+/// Runs an edited /bet again when the original did nothing, e.g. a typo'd
+/// amount; a bet it already placed stays as it is. Edits of other commands,
+/// and of messages too old to tell, are ignored.
+async fn handle_edited_command(bot: Messenger, msg: Message, cmd: Command, ctx: Arc<BotContext>) -> HandlerResult {
+    let Command::Bet(args) = cmd else {
+        return Ok(());
+    };
+    match ctx.recent_commands.outcome(msg.chat.id, msg.id) {
+        Some(CommandOutcome::NotExecuted) => handle_bet(bot, msg, ctx, args).await,
+        Some(CommandOutcome::BetPlaced) => {
+            bot.send_message(msg.chat.id, edits::ALREADY_PLACED).await?;
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+async fn handle_message(bot: Messenger, msg: Message, cmd: Command, ctx: Arc<BotContext>) -> HandlerResult {
+    ctx.recent_commands.seen(msg.chat.id, msg.id);
     match cmd {
         Command::Init => handle_init(bot, msg, ctx).await,
         Command::New(args) => handle_new(bot, msg, ctx, args).await,
         Command::Bet(args) => handle_bet(bot, msg, ctx, args).await,
-        Command::List => handle_list(bot, msg, ctx).await,
+        Command::Preview(args) => handle_preview(bot, msg, ctx, args).await,
+        Command::Challenge(args) => handle_challenge(bot, msg, ctx, args).await,
+        Command::List(args) => handle_list(bot, msg, ctx, args).await,
         Command::Solve => handle_solve(bot, msg, ctx).await,
-        Command::Leaderboard => handle_leaderboard(bot, msg, ctx).await,
+        Command::Leaderboard(args) => handle_leaderboard(bot, msg, ctx, args).await,
+        Command::Me => handle_me(bot, msg, ctx).await,
+        Command::Treasury => handle_treasury(bot, msg, ctx).await,
+        Command::Info(args) => handle_info(bot, msg, ctx, args).await,
+        Command::Stats(args) => handle_stats(bot, msg, ctx, args).await,
+        Command::Resolve(args) => handle_resolve(bot, msg, ctx, args).await,
+        Command::Expire(args) => handle_expire(bot, msg, ctx, args).await,
+        Command::AutoExpire(args) => handle_auto_expire(bot, msg, ctx, args).await,
+        Command::Currency(args) => handle_currency(bot, msg, ctx, args).await,
+        Command::Settings(args) => handle_settings(bot, msg, ctx, args).await,
+        Command::Cost(args) => handle_cost(bot, msg, ctx, args).await,
         Command::Reset => handle_reset(bot, msg, ctx).await,
+        Command::Cleanup => handle_cleanup(bot, msg, ctx).await,
+        Command::Privacy(args) => handle_privacy(bot, msg, ctx, args).await,
+        Command::Alerts(args) => handle_alerts(bot, msg, ctx, args).await,
+        Command::SetAdmin(args) => handle_set_admin(bot, msg, ctx, args).await,
+        Command::Withdraw(args) => handle_withdraw(bot, msg, ctx, args).await,
+        Command::Adjust(args) => handle_adjust(bot, msg, ctx, args).await,
+        Command::Reconcile => handle_reconcile(bot, msg, ctx).await,
+        Command::Broadcast(args) => handle_broadcast(bot, msg, ctx, args).await,
+        Command::Season(args) => handle_season(bot, msg, ctx, args).await,
+        Command::Commands(args) => handle_commands(bot, msg, ctx, args).await,
+        Command::Start(args) => handle_start(bot, msg, ctx, args).await,
         Command::Help => {
             bot.send_message(msg.chat.id, Command::descriptions().to_string())
                 .await?;
@@ -556,23 +3450,66 @@ async fn handle_message(bot: Bot, msg: Message, cmd: Command, ctx: Arc<BotContex
     }
 }
 
+/// The market server's client, configured from the environment.
+fn market_client_from_env() -> Result<MarketApiClient> {
+    // Get server URL from environment or use default
+    let server_url = std::env::var("SERVER_URL").unwrap_or_else(|_| "http://localhost:4001".to_string());
+    log::info!("Connecting to server at: {}", server_url);
+    
+    let mut api_builder = MarketApiClient::builder(server_url);
+    if let Ok(secs) = std::env::var("API_CONNECT_TIMEOUT_SECS") {
+        api_builder = api_builder.connect_timeout(Duration::from_secs(secs.parse()?));
+    }
+    if let Ok(secs) = std::env::var("API_REQUEST_TIMEOUT_SECS") {
+        api_builder = api_builder.request_timeout(Duration::from_secs(secs.parse()?));
+    }
+    if let Ok(attempts) = std::env::var("API_MAX_ATTEMPTS") {
+        api_builder = api_builder.retry_policy(RetryPolicy {
+            max_attempts: attempts.parse()?,
+            ..RetryPolicy::default()
+        });
+    }
+    if let Ok(permits) = std::env::var("API_MAX_CONCURRENT_REQUESTS") {
+        api_builder = api_builder.max_concurrent_requests(permits.parse()?);
+    }
+    if let Ok(enabled) = std::env::var("API_SERIALIZE_PER_USER") {
+        api_builder = api_builder.serialize_per_identity(enabled.parse()?);
+    }
+    if let Ok(key) = std::env::var("ADMIN_API_KEY") {
+        api_builder = api_builder.admin_key(key);
+    }
+    Ok(api_builder.build()?)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     pretty_env_logger::init();
     log::info!("Starting bot...");
     
+    // BOT_MODE=dryrun runs every command against a contract simulated in
+    // memory instead of the server, with a database as short-lived
+    let dry_run = dryrun::enabled()?;
+    
     // Initialize database
     let database_url = "sqlite://bot.db?mode=rwc";
-    let db = Arc::new(Database::new(database_url).await?);
+    let db = Arc::new(if dry_run { dryrun::database().await? } else { Database::new(database_url).await? });
     db.init().await?;
     log::info!("Database initialized");
+    let reopened = db.reopen_resolving_bets().await?;
+    if reopened > 0 {
+        log::warn!("Reopened {} bets whose evaluation was interrupted", reopened);
+    }
     
-    // Get server URL from environment or use default
-    let server_url = std::env::var("SERVER_URL").unwrap_or_else(|_| "http://localhost:4001".to_string());
-    log::info!("Connecting to server at: {}", server_url);
-    
-    // Initialize API client
-    let api_client = Arc::new(MarketApiClient::new(server_url.clone()));
+    let market_client = if dry_run {
+        log::warn!("Dry run: markets and balances are simulated in memory and lost on exit");
+        None
+    } else {
+        Some(market_client_from_env()?)
+    };
+    let api_client: Arc<dyn MarketApi> = match &market_client {
+        Some(market_client) => Arc::new(market_client.clone()),
+        None => Arc::new(dryrun::DryRunApi::new()),
+    };
     
     // Check server health
     match api_client.health_check().await {
@@ -585,15 +3522,65 @@ async fn main() -> Result<()> {
         }
     }
     
-    // Get contract name from server
-    let contract_name = match api_client.get_config().await {
+    // Get the contract name and economic rules from the server
+    let (contract_name, params, state_epoch) = match api_client.get_config().await {
         Ok(config) => {
-            log::info!("Got contract name from server: {}", config.contract_name);
-            config.contract_name
+            log::info!(
+                "Got config from server (API v{}): contract {}, initial balance {}",
+                config.api_version,
+                config.contract_name,
+                config.params.initial_balance
+            );
+            if config.paused {
+                log::warn!("The contract is paused by its admin: actions changing the state are refused until it is unpaused");
+            }
+            (config.contract_name, config.params, config.state_epoch)
         }
         Err(e) => {
             log::warn!("Failed to get config from server: {}. Using default.", e);
-            "contract1".to_string()
+            ("contract1".to_string(), ContractParams::default(), None)
+        }
+    };
+    if let Some(epoch) = state_epoch {
+        let stale = db.adopt_epoch(epoch).await?;
+        if stale > 0 {
+            log::warn!(
+                "The contract was redeployed (epoch {}): {} open bet(s) of earlier epochs can no longer be traded or resolved",
+                epoch,
+                stale
+            );
+        }
+    }
+    
+    // Bets from before the server reported market ids were assumed to share them
+    match market_ids::backfill(&db, api_client.as_ref(), &contract_name, state_epoch).await {
+        Ok(backfill) if backfill.matched + backfill.unmatched > 0 => log::info!(
+            "Matched {} bet(s) to their market on-chain, {} under another id; {} left unmatched",
+            backfill.matched,
+            backfill.diverged,
+            backfill.unmatched
+        ),
+        Ok(_) => {}
+        Err(e) => log::warn!("Could not match bets to their markets on-chain: {}", e),
+    }
+    
+    let retention = RetentionPolicy::from_env()?;
+    
+    let resolution_cache = ResolutionCache::from_env(db.clone())?;
+    let resolver = claude::resolver_from_env()?;
+    let prices = PriceTable::from_env()?;
+    match &resolver {
+        Some(resolver) => log::info!("Resolving bets with {}", resolver.name()),
+        None => log::warn!("No LLM credentials set, /solve is disabled"),
+    }
+
+    let bot = Bot::from_env();
+    // Deep links to the bot's private chat need its username
+    let bot_username = match bot.get_me().await {
+        Ok(me) => me.username.clone(),
+        Err(e) => {
+            log::warn!("Failed to get the bot's username: {}. Announcements will have no private betting link.", e);
+            None
         }
     };
     
@@ -602,21 +3589,221 @@ async fn main() -> Result<()> {
         db,
         api_client,
         contract_name,
+        retention,
+        deadlines: DeadlineConfig::from_env()?,
+        resolution_cache,
+        resolver,
+        prices,
+        recent_messages: RecentMessages::new(RECENT_MESSAGES_PER_CHAT),
+        operators: operators_from_env()?,
+        own_actions: OwnActions::default(),
+        params,
+        state_epoch,
+        pending_solves: PendingSolves::default(),
+        pending_onboardings: PendingOnboardings::default(),
+        pending_polls: PendingPolls::default(),
+        membership: MembershipCache::default(),
+        announcement_edits: AnnouncementEdits::default(),
+        recent_commands: RecentCommands::default(),
+        bot_username,
     });
     
-    let bot = Bot::from_env();
+    // Archive old resolved bets and drop expired messages in the background
+    {
+        let ctx = Arc::clone(&ctx);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ctx.retention.interval);
+            loop {
+                interval.tick().await;
+                match ctx.db.run_retention(&ctx.retention).await {
+                    Ok(0) => {}
+                    Ok(archived) => log::info!("Retention job archived {} resolved bets", archived),
+                    Err(e) => log::error!("Retention job failed: {}", e),
+                }
+                let pruned = prune_messages(&ctx);
+                if pruned > 0 {
+                    log::info!("Retention job dropped {} buffered messages", pruned);
+                }
+            }
+        });
+    }
+    
+    // Every handler sends through the same queue, so bursts stay under Telegram's limits
+    let transport: Arc<dyn Transport> = if dry_run {
+        Arc::new(dryrun::BannerTransport(Arc::new(bot.clone())))
+    } else {
+        Arc::new(bot.clone())
+    };
+    let messenger = Messenger::new(transport, Arc::new(SendQueue::new(SendPacing::default())));
+    
+    // Without it Telegram's "/" menu stays empty
+    match command_menu::register(&messenger, &ctx.operators).await {
+        Ok(scopes) => log::info!("Registered the command menu in {} scope(s)", scopes),
+        Err(e) => log::warn!("Failed to register the command menu: {}", e),
+    }
+    
+    // Announce bets and resolutions pushed by the server's webhook
+    if let Ok(addr) = std::env::var("BOT_WEBHOOK_ADDR") {
+        let secret = std::env::var("BOT_WEBHOOK_SECRET")
+            .map_err(|_| anyhow::anyhow!("BOT_WEBHOOK_ADDR is set without BOT_WEBHOOK_SECRET"))?;
+        let router = webhook::router(messenger.bulk(), Arc::clone(&ctx), &secret);
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        log::info!("Listening for server events on {}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                log::error!("Webhook listener stopped: {}", e);
+            }
+        });
+    } else if let Some(market_client) = market_client.filter(|_| std::env::var("SERVER_EVENTS").ok().as_deref() != Some("0")) {
+        // Without a webhook listener, follow the server's event stream instead
+        let events = market_client.subscribe_events(EventFilter::default());
+        webhook::spawn_subscription(messenger.bulk(), Arc::clone(&ctx), events);
+    }
     
-    let handler = Update::filter_message()
-        .filter_command::<Command>()
-        .endpoint(move |bot: Bot, msg: Message, cmd: Command| {
+    // Close betting at deadlines and follow up on bets nobody solved
+    deadlines::spawn(messenger.bulk(), Arc::clone(&ctx));
+    
+    // Finish broadcasts and bets a crash or restart interrupted
+    broadcast::spawn_resume(messenger.bulk(), Arc::clone(&ctx));
+    operations::spawn_resume(messenger.bulk(), Arc::clone(&ctx));
+    
+    let (command_messenger, edit_messenger, callback_messenger, membership_messenger, migration_messenger) =
+        (messenger.clone(), messenger.clone(), messenger.clone(), messenger.clone(), messenger.clone());
+    let (poll_messenger, poll_update_messenger, inline_messenger) = (messenger.clone(), messenger.clone(), messenger);
+    let command_ctx = Arc::clone(&ctx);
+    let edit_ctx = Arc::clone(&ctx);
+    let callback_ctx = Arc::clone(&ctx);
+    let membership_ctx = Arc::clone(&ctx);
+    let migration_ctx = Arc::clone(&ctx);
+    let poll_ctx = Arc::clone(&ctx);
+    let poll_update_ctx = Arc::clone(&ctx);
+    let inline_ctx = Arc::clone(&ctx);
+    let messages = Update::filter_message()
+        .branch(
+            dptree::entry()
+                .filter_command::<Command>()
+                .endpoint(move |msg: Message, cmd: Command| {
+                    let ctx = Arc::clone(&command_ctx);
+                    let bot = command_messenger.clone();
+                    async move {
+                        if let Err(e) = mentions::remember_author(&ctx.db, &msg).await {
+                            log::error!("Error recording the author of a message: {:?}", e);
+                        }
+                        if let Err(e) = handle_message(bot, msg, cmd, ctx).await {
+                            log::error!("Error handling message: {:?}", e);
+                        }
+                        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                    }
+                }),
+        )
+        // A group upgraded to a supergroup, under a new chat id
+        .branch(
+            dptree::filter(|msg: Message| msg.migrate_to_chat_id().is_some() || msg.migrate_from_chat_id().is_some())
+                .endpoint(move |msg: Message| {
+                    let ctx = Arc::clone(&migration_ctx);
+                    let bot = migration_messenger.clone();
+                    async move {
+                        if let Err(e) = handle_chat_migration(bot, msg, ctx).await {
+                            log::error!("Error migrating chat: {:?}", e);
+                        }
+                        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                    }
+                }),
+        )
+        // Offer to turn yes/no polls into markets
+        .branch(
+            dptree::filter(|msg: Message| msg.poll().is_some()).endpoint(move |msg: Message| {
+                let ctx = Arc::clone(&poll_ctx);
+                let bot = poll_messenger.clone();
+                async move {
+                    if let Err(e) = handle_poll_message(bot, msg, ctx).await {
+                        log::error!("Error handling poll: {:?}", e);
+                    }
+                    Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                }
+            }),
+        )
+        // Remember other group messages so /solve can quote the thread around a reply
+        .branch(dptree::endpoint(move |msg: Message| {
             let ctx = Arc::clone(&ctx);
             async move {
-                if let Err(e) = handle_message(bot, msg, cmd, ctx).await {
-                    log::error!("Error handling message: {:?}", e);
+                if let Err(e) = mentions::remember_author(&ctx.db, &msg).await {
+                    log::error!("Error recording the author of a message: {:?}", e);
+                }
+                remember_command_attempt(&ctx, &msg);
+                if let Err(e) = remember_group_message(&ctx, &msg).await {
+                    log::error!("Error recording message: {:?}", e);
                 }
                 Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
             }
-        });
+        }));
+    
+    // Commands corrected by editing them, e.g. a mistyped /bet amount
+    let edited_messages = Update::filter_edited_message().filter_command::<Command>().endpoint(
+        move |msg: Message, cmd: Command| {
+            let ctx = Arc::clone(&edit_ctx);
+            let bot = edit_messenger.clone();
+            async move {
+                if let Err(e) = handle_edited_command(bot, msg, cmd, ctx).await {
+                    log::error!("Error handling edited message: {:?}", e);
+                }
+                Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+            }
+        },
+    );
+    
+    // Buttons under the bot's offers, e.g. "Solve bet #N?" or "Initialize me"
+    let callbacks = Update::filter_callback_query().endpoint(move |query: CallbackQuery| {
+        let ctx = Arc::clone(&callback_ctx);
+        let bot = callback_messenger.clone();
+        async move {
+            if let Err(e) = handle_callback(bot, query, ctx).await {
+                log::error!("Error handling callback: {:?}", e);
+            }
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        }
+    });
+    
+    // The bot joining or leaving a chat
+    let membership = Update::filter_my_chat_member().endpoint(move |update: ChatMemberUpdated| {
+        let ctx = Arc::clone(&membership_ctx);
+        let bot = membership_messenger.clone();
+        async move {
+            if let Err(e) = handle_my_chat_member(bot, update, ctx).await {
+                log::error!("Error handling membership update: {:?}", e);
+            }
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        }
+    });
+    // `@bot <query>`, once inline mode is enabled with @BotFather
+    let inline_queries = Update::filter_inline_query().endpoint(move |query: InlineQuery| {
+        let ctx = Arc::clone(&inline_ctx);
+        let bot = inline_messenger.clone();
+        async move {
+            if let Err(e) = handle_inline_query(bot, query, ctx).await {
+                log::error!("Error handling inline query: {:?}", e);
+            }
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        }
+    });
+    // Final states of polls: Telegram sends them for polls stopped by their author
+    let poll_updates = Update::filter_poll().endpoint(move |poll: Poll| {
+        let ctx = Arc::clone(&poll_update_ctx);
+        let bot = poll_update_messenger.clone();
+        async move {
+            if let Err(e) = handle_poll_update(bot, poll, ctx).await {
+                log::error!("Error handling poll update: {:?}", e);
+            }
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        }
+    });
+    let handler = dptree::entry()
+        .branch(messages)
+        .branch(edited_messages)
+        .branch(callbacks)
+        .branch(membership)
+        .branch(inline_queries)
+        .branch(poll_updates);
     
     Dispatcher::builder(bot, handler)
         .enable_ctrlc_handler()
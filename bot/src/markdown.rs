@@ -0,0 +1,37 @@
+/// Characters Telegram's MarkdownV2 reserves for formatting, which must be
+/// backslash-escaped to appear literally.
+pub const RESERVED: &[char] = &[
+    '\\', '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!',
+];
+
+/// `text` as literal MarkdownV2, e.g. a description or a username.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if RESERVED.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `text` in bold.
+pub fn bold(text: &str) -> String {
+    format!("*{}*", escape(text))
+}
+
+/// How a MarkdownV2 message reads without its formatting, for clients that
+/// cannot be sent the formatted version.
+pub fn to_plain(markdown: &str) -> String {
+    let mut plain = String::with_capacity(markdown.len());
+    let mut chars = markdown.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => plain.extend(chars.next()),
+            '*' | '_' | '~' | '`' | '|' => {}
+            _ => plain.push(c),
+        }
+    }
+    plain
+}
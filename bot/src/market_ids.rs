@@ -0,0 +1,78 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+
+use crate::api_client::MarketApi;
+use crate::db::Database;
+
+/// What [`backfill`] made of the bets missing their market id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Backfill {
+    /// Bets whose market was found on-chain
+    pub matched: usize,
+    /// Of those, the ones whose market id is not their bet id
+    pub diverged: usize,
+    /// Bets no market matched, which keep being traded as their bet id
+    pub unmatched: usize,
+}
+
+/// Records the market id of the bets created before the server reported
+/// it, whose market was assumed to share their bet id. Each is matched to
+/// the first market on-chain with its creator and description that no
+/// other bet claims, so bets and markets created in the same order pair up
+/// even when their counters drifted apart, e.g. after a reset or markets
+/// created outside the bot.
+pub async fn backfill(db: &Database, api: &dyn MarketApi, contract_name: &str, epoch: Option<u64>) -> Result<Backfill> {
+    let bets = db.get_bets_without_market(epoch).await?;
+    if bets.is_empty() {
+        return Ok(Backfill::default());
+    }
+    let markets = api.list_markets(&Default::default(), contract_name).await?;
+    let mut claimed: BTreeSet<u64> = db.get_known_market_ids(epoch).await?.into_iter().collect();
+
+    let mut backfill = Backfill::default();
+    for bet in bets {
+        let creator = format!("{}@{}", bet.creator_id, contract_name);
+        let market = markets
+            .iter()
+            .filter(|market| market.creator == creator && market.description == bet.description)
+            .map(|market| market.id)
+            .filter(|id| !claimed.contains(id))
+            .min();
+        let Some(market_id) = market else {
+            log::warn!("No market on-chain matches bet #{}, still assumed to be market #{}", bet.bet_id, bet.bet_id);
+            backfill.unmatched += 1;
+            continue;
+        };
+        claimed.insert(market_id);
+        let bet_epoch = bet.epoch.map(|epoch| epoch as u64).or(epoch);
+        db.set_chain_market(bet.bet_id, bet_epoch, Some(market_id)).await?;
+        backfill.matched += 1;
+        if market_id != bet.bet_id as u64 {
+            log::warn!("Bet #{} is market #{} on-chain, not #{}", bet.bet_id, market_id, bet.bet_id);
+            backfill.diverged += 1;
+        }
+    }
+    Ok(backfill)
+}
+
+/// Records the market `bet_id` was just created as, `reported` by the
+/// server or else looked up on-chain, and returns its id.
+pub async fn record_created(
+    db: &Database,
+    api: &dyn MarketApi,
+    contract_name: &str,
+    epoch: Option<u64>,
+    bet_id: i64,
+    reported: Option<u64>,
+) -> Result<u64> {
+    db.set_chain_market(bet_id, epoch, reported).await?;
+    if let Some(market_id) = reported {
+        return Ok(market_id);
+    }
+    // Older servers don't report it
+    if let Err(e) = backfill(db, api, contract_name, epoch).await {
+        log::warn!("Could not look up the market of bet #{}: {}", bet_id, e);
+    }
+    Ok(db.get_bet_by_id(bet_id).await?.map_or(bet_id as u64, |bet| bet.on_chain_id()))
+}
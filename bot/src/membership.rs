@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use teloxide::types::{ChatId, UserId};
+use teloxide::RequestError;
+
+use crate::messenger::Messenger;
+
+/// How long a membership lookup is trusted before Telegram is asked again.
+pub const MEMBERSHIP_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Recent answers to "is this user in that chat?", so betting on a group's
+/// markets from elsewhere does not query Telegram on every command.
+#[derive(Default)]
+pub struct MembershipCache(Mutex<HashMap<(ChatId, UserId), (Instant, bool)>>);
+
+impl MembershipCache {
+    pub async fn is_member(&self, bot: &Messenger, chat_id: ChatId, user_id: UserId) -> Result<bool, RequestError> {
+        self.is_member_at(bot, chat_id, user_id, Instant::now()).await
+    }
+
+    pub async fn is_member_at(
+        &self,
+        bot: &Messenger,
+        chat_id: ChatId,
+        user_id: UserId,
+        now: Instant,
+    ) -> Result<bool, RequestError> {
+        if let Some((checked_at, member)) = self.0.lock().unwrap().get(&(chat_id, user_id)) {
+            if now.saturating_duration_since(*checked_at) < MEMBERSHIP_TTL {
+                return Ok(*member);
+            }
+        }
+
+        let member = bot.get_chat_member(chat_id, user_id).await?;
+        let mut cache = self.0.lock().unwrap();
+        cache.retain(|_, (checked_at, _)| now.saturating_duration_since(*checked_at) < MEMBERSHIP_TTL);
+        cache.insert((chat_id, user_id), (now, member));
+        Ok(member)
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use teloxide::types::{ChatKind, Message, MessageEntityKind};
+
+use crate::db::Database;
+
+/// A user named by the arguments of a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionedUser {
+    pub user_id: i64,
+    /// How the command named them: `@bob`, or the text of a text mention
+    pub name: String,
+}
+
+/// Remembers the author of a group message, so that mentions of members who
+/// never ran a command still resolve.
+pub async fn remember_author(db: &Database, msg: &Message) -> Result<()> {
+    let (ChatKind::Public(_), Some(from)) = (&msg.chat.kind, msg.from.as_ref()) else {
+        return Ok(());
+    };
+    if from.is_bot {
+        return Ok(());
+    }
+    db.remember_user(from.id.0 as i64, from.username.clone()).await
+}
+
+/// The arguments of the command in `msg`, with every text mention written
+/// `@<user id>`. A text mention names a member without a username by their
+/// display name, which the argument parsers could not tell from the next
+/// arguments; usernames never start with a digit, so the two cannot clash.
+pub fn args_with_text_mentions(msg: &Message, args: String) -> String {
+    let (Some(text), Some(entities)) = (msg.text(), msg.parse_entities()) else {
+        return args;
+    };
+    if !entities.iter().any(|entity| matches!(entity.kind(), MessageEntityKind::TextMention { .. })) {
+        return args;
+    }
+    let mut rewritten = String::with_capacity(text.len());
+    let mut copied = 0;
+    for entity in &entities {
+        if let MessageEntityKind::TextMention { user } = entity.kind() {
+            let range = entity.range();
+            rewritten.push_str(&text[copied..range.start]);
+            rewritten.push_str(&format!("@{}", user.id.0));
+            copied = range.end;
+        }
+    }
+    rewritten.push_str(&text[copied..]);
+    // Drop the command itself, as the command parser does
+    rewritten.split_once(char::is_whitespace).map(|(_, args)| args.trim().to_string()).unwrap_or_default()
+}
+
+/// The user behind `mention`, a mention argument without its `@`: the user
+/// carried by a text mention of `msg`, else the member the bot has seen with
+/// that username. `None` when the bot never saw them.
+pub async fn resolve_mention(db: &Database, msg: &Message, mention: &str) -> Result<Option<MentionedUser>> {
+    let text_mention = msg.parse_entities().unwrap_or_default().into_iter().find_map(|entity| match entity.kind() {
+        MessageEntityKind::TextMention { user } if user.id.0.to_string() == mention => {
+            Some(MentionedUser { user_id: user.id.0 as i64, name: entity.text().to_string() })
+        }
+        _ => None,
+    });
+    if text_mention.is_some() {
+        return Ok(text_mention);
+    }
+    let user_id = db.get_user_id_by_username(mention).await?;
+    Ok(user_id.map(|user_id| MentionedUser { user_id, name: format!("@{}", mention) }))
+}
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use teloxide::prelude::*;
+use teloxide::types::{
+    BotCommand, BotCommandScope, CallbackQueryId, InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryId, InlineQueryResult, InlineQueryResultArticle,
+    InputMessageContent, InputMessageContentText, MessageId, ParseMode,
+};
+use teloxide::{ApiError, RequestError};
+
+use crate::markdown;
+use crate::send_queue::{Priority, SendQueue};
+
+/// A button shown under a message: its label and the callback data sent back
+/// when it is pressed.
+pub type Button = (String, String);
+
+/// A result offered to an inline query: a card the user can share into any
+/// chat, with an optional button opening `link`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineArticle {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    /// Message sent when the result is picked
+    pub text: String,
+    /// Label and URL of the button under the message
+    pub link: Option<(String, String)>,
+}
+
+/// The Telegram calls handlers make. `Bot` implements it; tests record calls
+/// instead of sending them.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send_text(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError>;
+    async fn edit_text(&self, chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError>;
+    /// Sends `text` parsed as MarkdownV2.
+    async fn send_markdown(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError>;
+    async fn edit_markdown(&self, chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError>;
+    /// Sends `text` with one button per row.
+    async fn send_buttons(&self, chat_id: ChatId, text: String, buttons: Vec<Button>) -> Result<MessageId, RequestError>;
+    async fn answer_callback(&self, query_id: CallbackQueryId, text: Option<String>) -> Result<(), RequestError>;
+    /// Answers an inline query with results only its sender gets to see.
+    async fn answer_inline(&self, query_id: InlineQueryId, results: Vec<InlineArticle>) -> Result<(), RequestError>;
+    async fn chat_administrators(&self, chat_id: ChatId) -> Result<Vec<UserId>, RequestError>;
+    /// Whether `user_id` is currently in the chat.
+    async fn chat_membership(&self, chat_id: ChatId, user_id: UserId) -> Result<bool, RequestError>;
+    /// Sets the commands Telegram's "/" menu lists in `scope`.
+    async fn set_commands(&self, scope: BotCommandScope, commands: Vec<BotCommand>) -> Result<(), RequestError>;
+}
+
+#[async_trait]
+impl Transport for Bot {
+    async fn send_text(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError> {
+        let sent = Requester::send_message(self, chat_id, text).await?;
+        Ok(sent.id)
+    }
+
+    async fn edit_text(&self, chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError> {
+        Requester::edit_message_text(self, chat_id, message_id, text).await?;
+        Ok(())
+    }
+
+    async fn send_markdown(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError> {
+        let sent = Requester::send_message(self, chat_id, text).parse_mode(ParseMode::MarkdownV2).await?;
+        Ok(sent.id)
+    }
+
+    async fn edit_markdown(&self, chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError> {
+        Requester::edit_message_text(self, chat_id, message_id, text)
+            .parse_mode(ParseMode::MarkdownV2)
+            .await?;
+        Ok(())
+    }
+
+    async fn send_buttons(&self, chat_id: ChatId, text: String, buttons: Vec<Button>) -> Result<MessageId, RequestError> {
+        let keyboard = InlineKeyboardMarkup::new(
+            buttons
+                .into_iter()
+                .map(|(label, data)| vec![InlineKeyboardButton::callback(label, data)]),
+        );
+        let sent = Requester::send_message(self, chat_id, text).reply_markup(keyboard).await?;
+        Ok(sent.id)
+    }
+
+    async fn answer_callback(&self, query_id: CallbackQueryId, text: Option<String>) -> Result<(), RequestError> {
+        let mut request = Requester::answer_callback_query(self, query_id);
+        if let Some(text) = text {
+            request = request.text(text);
+        }
+        request.await?;
+        Ok(())
+    }
+
+    async fn answer_inline(&self, query_id: InlineQueryId, results: Vec<InlineArticle>) -> Result<(), RequestError> {
+        let results = results.into_iter().map(|article| {
+            let content = InputMessageContent::Text(InputMessageContentText::new(article.text));
+            let mut result = InlineQueryResultArticle::new(article.id, article.title, content).description(article.description);
+            if let Some((label, url)) = article.link.and_then(|(label, url)| Some((label, reqwest::Url::parse(&url).ok()?))) {
+                result = result.reply_markup(InlineKeyboardMarkup::new([[InlineKeyboardButton::url(label, url)]]));
+            }
+            InlineQueryResult::Article(result)
+        });
+        // Results depend on the sender's groups, so Telegram must not share them
+        Requester::answer_inline_query(self, query_id, results).is_personal(true).cache_time(0).await?;
+        Ok(())
+    }
+
+    async fn chat_administrators(&self, chat_id: ChatId) -> Result<Vec<UserId>, RequestError> {
+        let admins = Requester::get_chat_administrators(self, chat_id).await?;
+        Ok(admins.into_iter().map(|admin| admin.user.id).collect())
+    }
+
+    async fn chat_membership(&self, chat_id: ChatId, user_id: UserId) -> Result<bool, RequestError> {
+        match Requester::get_chat_member(self, chat_id, user_id).await {
+            Ok(member) => Ok(member.is_present()),
+            // Users who never joined the chat are unknown to it
+            Err(RequestError::Api(ApiError::UserNotFound)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn set_commands(&self, scope: BotCommandScope, commands: Vec<BotCommand>) -> Result<(), RequestError> {
+        Requester::set_my_commands(self, commands).scope(scope).await?;
+        Ok(())
+    }
+}
+
+/// Handle passed to every command handler, with the same call shape as `Bot`
+/// for the requests they use. Messages and edits wait their turn in the
+/// shared [`SendQueue`].
+#[derive(Clone)]
+pub struct Messenger {
+    transport: Arc<dyn Transport>,
+    queue: Arc<SendQueue>,
+    priority: Priority,
+}
+
+impl Messenger {
+    pub fn new(transport: Arc<dyn Transport>, queue: Arc<SendQueue>) -> Self {
+        Self { transport, queue, priority: Priority::Interactive }
+    }
+
+    /// The same handle for messages nobody is waiting on, which let
+    /// interactive replies go first.
+    pub fn bulk(&self) -> Self {
+        Self { priority: Priority::Bulk, ..self.clone() }
+    }
+
+    pub async fn send_message(&self, chat_id: ChatId, text: impl Into<String>) -> Result<MessageId, RequestError> {
+        let text = text.into();
+        self.queue
+            .send(chat_id, self.priority, || self.transport.send_text(chat_id, text.clone()))
+            .await
+    }
+
+    /// Sends a MarkdownV2 message built with [`markdown`], falling back to its
+    /// plain text if Telegram cannot parse it.
+    pub async fn send_markdown(&self, chat_id: ChatId, text: impl Into<String>) -> Result<MessageId, RequestError> {
+        let text = text.into();
+        let sent = self
+            .queue
+            .send(chat_id, self.priority, || self.transport.send_markdown(chat_id, text.clone()))
+            .await;
+        match sent {
+            Err(RequestError::Api(ApiError::CantParseEntities(reason))) => {
+                log::warn!("Telegram rejected a formatted message, sending it as plain text: {}", reason);
+                self.send_message(chat_id, markdown::to_plain(&text)).await
+            }
+            result => result,
+        }
+    }
+
+    /// Replaces a message with MarkdownV2 `text`, falling back to plain text like [`Self::send_markdown`].
+    pub async fn edit_markdown(&self, chat_id: ChatId, message_id: MessageId, text: impl Into<String>) -> Result<(), RequestError> {
+        let text = text.into();
+        let edited = self
+            .queue
+            .send(chat_id, self.priority, || self.transport.edit_markdown(chat_id, message_id, text.clone()))
+            .await;
+        match edited {
+            Err(RequestError::Api(ApiError::CantParseEntities(reason))) => {
+                log::warn!("Telegram rejected a formatted edit, sending it as plain text: {}", reason);
+                let plain = markdown::to_plain(&text);
+                self.queue
+                    .send(chat_id, self.priority, || self.transport.edit_text(chat_id, message_id, plain.clone()))
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    pub async fn send_buttons(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        buttons: Vec<Button>,
+    ) -> Result<MessageId, RequestError> {
+        let text = text.into();
+        self.queue
+            .send(chat_id, self.priority, || self.transport.send_buttons(chat_id, text.clone(), buttons.clone()))
+            .await
+    }
+
+    pub async fn answer_callback(&self, query_id: CallbackQueryId, text: Option<String>) -> Result<(), RequestError> {
+        self.transport.answer_callback(query_id, text).await
+    }
+
+    pub async fn answer_inline_query(&self, query_id: InlineQueryId, results: Vec<InlineArticle>) -> Result<(), RequestError> {
+        self.transport.answer_inline(query_id, results).await
+    }
+
+    pub async fn get_chat_administrators(&self, chat_id: ChatId) -> Result<Vec<UserId>, RequestError> {
+        self.transport.chat_administrators(chat_id).await
+    }
+
+    pub async fn get_chat_member(&self, chat_id: ChatId, user_id: UserId) -> Result<bool, RequestError> {
+        self.transport.chat_membership(chat_id, user_id).await
+    }
+
+    pub async fn set_my_commands(&self, scope: BotCommandScope, commands: Vec<BotCommand>) -> Result<(), RequestError> {
+        self.transport.set_commands(scope, commands).await
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use teloxide::types::{ChatId, Message, UserId};
+
+/// How long the "Initialize me" offer keeps the command that triggered it.
+pub const ONBOARDING_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Prefix of the callback data of the onboarding buttons.
+const CALLBACK_PREFIX: &str = "onboard:";
+
+/// A command an uninitialized user tried, replayed once they accept the offer.
+#[derive(Debug, Clone)]
+pub enum PendingCommand {
+    Bet(String),
+    New(String),
+}
+
+impl PendingCommand {
+    /// What the user was trying to do, e.g. "place a bet".
+    pub fn purpose(&self) -> &'static str {
+        match self {
+            PendingCommand::Bet(_) => "place a bet",
+            PendingCommand::New(_) => "create a market",
+        }
+    }
+}
+
+/// Callback data of the accept (`true`) or decline button offered to `user_id`.
+pub fn callback_data(accept: bool, user_id: i64) -> String {
+    format!("{}{}:{}", CALLBACK_PREFIX, if accept { "yes" } else { "no" }, user_id)
+}
+
+/// Whether an onboarding button accepts the offer, and whom it was offered to.
+pub fn parse_callback(data: &str) -> Option<(bool, i64)> {
+    let (answer, user_id) = data.strip_prefix(CALLBACK_PREFIX)?.split_once(':')?;
+    let accept = match answer {
+        "yes" => true,
+        "no" => false,
+        _ => return None,
+    };
+    Some((accept, user_id.parse().ok()?))
+}
+
+/// When a command was stashed, the message that carried it and the command.
+type Stashed = (Instant, Message, PendingCommand);
+
+/// Commands stashed while their author decides on the onboarding offer, one
+/// per user and chat. Entries older than [`ONBOARDING_TTL`] are dropped.
+#[derive(Default)]
+pub struct PendingOnboardings(Mutex<HashMap<(ChatId, UserId), Stashed>>);
+
+impl PendingOnboardings {
+    pub fn stash(&self, chat_id: ChatId, user_id: UserId, msg: Message, command: PendingCommand) {
+        self.stash_at(chat_id, user_id, msg, command, Instant::now());
+    }
+
+    pub fn stash_at(&self, chat_id: ChatId, user_id: UserId, msg: Message, command: PendingCommand, at: Instant) {
+        let mut pending = self.0.lock().unwrap();
+        pending.retain(|_, (stashed_at, _, _)| stashed_at.elapsed() < ONBOARDING_TTL);
+        pending.insert((chat_id, user_id), (at, msg, command));
+    }
+
+    /// The stashed command, unless it expired. Either way it is forgotten.
+    pub fn take(&self, chat_id: ChatId, user_id: UserId) -> Option<(Message, PendingCommand)> {
+        let (stashed_at, msg, command) = self.0.lock().unwrap().remove(&(chat_id, user_id))?;
+        (stashed_at.elapsed() < ONBOARDING_TTL).then_some((msg, command))
+    }
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatId;
+
+use crate::{alerts, announcements};
+use crate::api_client::{MarketApiError, TxState};
+use crate::currency::{format_amount, Currency};
+use crate::db::{Database, Operation, User};
+use crate::markdown;
+use crate::messenger::Messenger;
+use crate::BotContext;
+
+/// How often bets the server accepted without settling them are checked again.
+const FOLLOW_UP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What `/bet` sends, logged as the parameters of a `bet` operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BetParams {
+    pub bet_id: i64,
+    pub side: bool,
+    pub amount: i64,
+}
+
+impl BetParams {
+    pub const ACTION: &'static str = "bet";
+}
+
+/// Logs the intent to place a bet before it is sent, returning the id of
+/// the operation.
+pub async fn begin_bet(db: &Database, user_id: i64, chat_id: i64, params: BetParams) -> Result<i64> {
+    db.begin_operation(user_id, chat_id, BetParams::ACTION, &serde_json::to_string(&params)?)
+        .await
+}
+
+/// The confirmation of a bet placed on-chain, in MarkdownV2.
+pub fn bet_placed(title: &str, description: &str, params: &BetParams, balance: i64, currency: &Currency, tx_hash: &str) -> String {
+    format!(
+        "{}\n{}",
+        markdown::bold(title),
+        markdown::escape(&format!(
+            "📝 Market #{}: {}\n🎯 Side: {}\n💵 Amount: {}\n💳 Remaining balance: {}\nTransaction: {}",
+            params.bet_id,
+            description,
+            if params.side { "YES ✅" } else { "NO ❌" },
+            format_amount(currency, params.amount as u128),
+            format_amount(currency, balance.max(0) as u128),
+            tx_hash
+        ))
+    )
+}
+
+/// Finishes the operations left unfinished, returning how many still wait
+/// for the server to settle them. After a restart, operations sent without
+/// an answer can no longer be followed and are reported as interrupted, and
+/// applied ones get the reply the crash swallowed; otherwise those belong to
+/// the handlers still running them and are left alone.
+pub async fn resume(bot: &Messenger, ctx: &Arc<BotContext>, after_restart: bool) -> Result<usize> {
+    let mut unsettled = 0;
+    for operation in ctx.db.get_unfinished_operations().await? {
+        if operation.action != BetParams::ACTION {
+            log::warn!("Operation #{} has unknown action {}", operation.operation_id, operation.action);
+            continue;
+        }
+        if resume_bet(bot, ctx, &operation, after_restart).await? {
+            unsettled += 1;
+        }
+    }
+    Ok(unsettled)
+}
+
+/// Returns whether the bet is still waiting to settle.
+async fn resume_bet(bot: &Messenger, ctx: &Arc<BotContext>, operation: &Operation, after_restart: bool) -> Result<bool> {
+    let params: BetParams = serde_json::from_str(&operation.params)?;
+    match (operation.status.as_str(), operation.tx_hash.as_deref()) {
+        ("pending", _) if after_restart => interrupt(bot, ctx, operation, &params).await?,
+        ("submitted", Some(tx_hash)) => match ctx.api_client.tx_status(tx_hash).await {
+            Ok(status) => match status.status {
+                TxState::Pending => return Ok(true),
+                TxState::Success => {
+                    let applied = ctx
+                        .db
+                        .apply_bet_operation(operation.operation_id, tx_hash, params.bet_id, operation.user_id, params.amount, params.side)
+                        .await?;
+                    if applied.is_some() {
+                        confirm(bot, ctx, operation, &params, tx_hash).await?;
+                    }
+                }
+                TxState::Failed => {
+                    if ctx.db.finish_operation(operation.operation_id, "failed").await? {
+                        let reply = format!(
+                            "❌ {}, your bet of {} on #{} could not be placed: {}",
+                            name(&ctx.db, operation.user_id).await?,
+                            format_amount(&ctx.db.get_currency(operation.chat_id).await?, params.amount as u128),
+                            params.bet_id,
+                            status.error.as_deref().unwrap_or("refused by the contract")
+                        );
+                        bot.send_message(ChatId(operation.chat_id), reply).await?;
+                    }
+                }
+            },
+            // The server restarted before the outcome, or pruned it
+            Err(e) if matches!(e.kind(), MarketApiError::ServerError { status: StatusCode::NOT_FOUND, .. }) => {
+                interrupt(bot, ctx, operation, &params).await?
+            }
+            Err(e) => {
+                log::warn!("Could not check operation #{} ({}): {}", operation.operation_id, tx_hash, e);
+                return Ok(true);
+            }
+        },
+        ("applied", Some(tx_hash)) if after_restart => confirm(bot, ctx, operation, &params, tx_hash).await?,
+        _ => {}
+    }
+    Ok(false)
+}
+
+/// Sends the confirmation of an applied bet and finishes its operation.
+async fn confirm(bot: &Messenger, ctx: &Arc<BotContext>, operation: &Operation, params: &BetParams, tx_hash: &str) -> Result<()> {
+    let balance = ctx.db.get_user(operation.user_id).await?.map(|user| user.balance).unwrap_or_default();
+    let description = ctx
+        .db
+        .get_bet_or_archived(params.bet_id)
+        .await?
+        .map(|(bet, _)| bet.description)
+        .unwrap_or_default();
+    let title = format!("💰 Bet by {} confirmed on-chain!", name(&ctx.db, operation.user_id).await?);
+    let currency = ctx.db.get_currency(operation.chat_id).await?;
+    bot.send_markdown(ChatId(operation.chat_id), bet_placed(&title, &description, params, balance, &currency, tx_hash))
+        .await?;
+    ctx.db.finish_operation(operation.operation_id, "done").await?;
+    log::info!("Operation #{} confirmed with tx {}", operation.operation_id, tx_hash);
+    announcements::refresh(bot, ctx, params.bet_id).await;
+    alerts::after_bet(bot, ctx, params.bet_id, operation.user_id).await;
+    Ok(())
+}
+
+/// Gives up on a bet whose outcome cannot be known and warns its author.
+async fn interrupt(bot: &Messenger, ctx: &BotContext, operation: &Operation, params: &BetParams) -> Result<()> {
+    if !ctx.db.finish_operation(operation.operation_id, "interrupted").await? {
+        return Ok(());
+    }
+    log::warn!("Operation #{} interrupted, its outcome is unknown", operation.operation_id);
+    let reply = format!(
+        "⚠️ {}, your bet of {} on #{} was interrupted by a restart of the bot and may not have gone through. Check /me before betting again.",
+        name(&ctx.db, operation.user_id).await?,
+        format_amount(&ctx.db.get_currency(operation.chat_id).await?, params.amount as u128),
+        params.bet_id
+    );
+    bot.send_message(ChatId(operation.chat_id), reply).await?;
+    Ok(())
+}
+
+async fn name(db: &Database, user_id: i64) -> Result<String> {
+    Ok(match db.get_user(user_id).await? {
+        Some(User { username: Some(username), .. }) => format!("@{}", username),
+        _ => format!("User {}", user_id),
+    })
+}
+
+/// Resumes what the previous run left unfinished, then keeps following the
+/// bets the server had not settled yet.
+pub fn spawn_resume(bot: Messenger, ctx: Arc<BotContext>) {
+    tokio::spawn(async move {
+        match resume(&bot, &ctx, true).await {
+            Ok(0) => {}
+            Ok(_) => follow_up(&bot, &ctx).await,
+            Err(e) => log::error!("Failed to resume operations: {}", e),
+        }
+    });
+}
+
+/// Follows the bets the server accepted without settling them, until none is left.
+pub fn spawn_follow_up(bot: Messenger, ctx: Arc<BotContext>) {
+    tokio::spawn(async move { follow_up(&bot, &ctx).await });
+}
+
+async fn follow_up(bot: &Messenger, ctx: &Arc<BotContext>) {
+    loop {
+        tokio::time::sleep(FOLLOW_UP_INTERVAL).await;
+        match resume(bot, ctx, false).await {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to follow up on operations: {}", e),
+        }
+    }
+}
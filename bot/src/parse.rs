@@ -0,0 +1,316 @@
+use std::fmt;
+
+/// Canonical usage of a command, shown under every argument error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub syntax: &'static str,
+    pub example: &'static str,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Usage: {}\nExample: {}", self.syntax, self.example)
+    }
+}
+
+impl Usage {
+    pub const BET: Usage = Usage { syntax: "/bet <bet_id> <yes/no> <amount>", example: "/bet 1 yes 100" };
+    pub const PREVIEW: Usage = Usage { syntax: "/preview <bet_id> <yes/no> <amount>", example: "/preview 1 yes 100" };
+    pub const CHALLENGE: Usage = Usage {
+        syntax: "/challenge @user <amount> <description>",
+        example: "/challenge @bob 100 I finish the marathon before you",
+    };
+    pub const RESOLVE: Usage = Usage { syntax: "/resolve <bet_id> <yes/no>", example: "/resolve 1 no" };
+    pub const EXPIRE: Usage = Usage { syntax: "/expire <bet_id>", example: "/expire 1" };
+    pub const INFO: Usage = Usage { syntax: "/info <bet_id>", example: "/info 1" };
+    pub const STATS: Usage = Usage { syntax: "/stats <bet_id>", example: "/stats 1" };
+    pub const SOLVE: Usage = Usage { syntax: "/solve [bet_id] [N] [force]", example: "/solve 1 3" };
+    pub const PRIVACY: Usage = Usage { syntax: "/privacy [optin|optout]", example: "/privacy optin" };
+    pub const ALERTS: Usage = Usage { syntax: "/alerts [on|off]", example: "/alerts off" };
+    pub const SETTINGS: Usage = Usage {
+        syntax: "/settings [set timezone <name>]",
+        example: "/settings set timezone Europe/Paris",
+    };
+    pub const ADJUST: Usage = Usage {
+        syntax: "/adjust @user <+/-amount> <reason>",
+        example: "/adjust @bob +500 refund of a bet lost to a bot bug",
+    };
+    pub const SET_ADMIN: Usage = Usage { syntax: "/setadmin [user_id]", example: "/setadmin 123456789" };
+    pub const WITHDRAW: Usage = Usage { syntax: "/withdraw <amount> [user_id]", example: "/withdraw 500 123456789" };
+    pub const COMMANDS: Usage = Usage { syntax: "/commands refresh", example: "/commands refresh" };
+}
+
+/// What is wrong with the arguments of a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The command was sent without any argument
+    NoArguments,
+    /// A required argument, e.g. "the amount", is missing
+    Missing(&'static str),
+    MarketId(String),
+    Side(String),
+    Amount(String),
+    SignedAmount(String),
+    Mention(String),
+    UserId(String),
+    Duration(String),
+    /// Arguments left over once every expected one was read
+    Unexpected(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NoArguments => write!(f, "This command needs arguments."),
+            Problem::Missing(what) => write!(f, "Missing {}.", what),
+            Problem::MarketId(value) => write!(f, "Invalid bet ID {:?}: expected a number like 1 or #1.", value),
+            Problem::Side(value) => write!(f, "Invalid side {:?}: expected yes or no.", value),
+            Problem::Amount(value) => {
+                write!(f, "Invalid amount {:?}: expected a positive number like 100, 1.5k, half or all.", value)
+            }
+            Problem::SignedAmount(value) => {
+                write!(f, "Invalid adjustment {:?}: expected a signed amount like +500 or -1.5k.", value)
+            }
+            Problem::Mention(value) => write!(f, "Invalid user {:?}: expected a username like @bob.", value),
+            Problem::UserId(value) => write!(f, "Invalid user ID {:?}: expected a number.", value),
+            Problem::Duration(value) => write!(f, "Invalid duration {:?}: expected e.g. 30m, 12h, 3d or 2w.", value),
+            Problem::Unexpected(value) => write!(f, "Unexpected {:?} after the arguments.", value),
+        }
+    }
+}
+
+/// Arguments a command could not make sense of, rendered as the problem
+/// followed by the command's usage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub problem: Problem,
+    pub usage: Usage,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.problem != Problem::NoArguments {
+            writeln!(f, "{}", self.problem)?;
+        }
+        write!(f, "{}", self.usage)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An amount of tokens as typed by a user, possibly relative to their balance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Amount {
+    Exact(i64),
+    Half,
+    All,
+}
+
+impl Amount {
+    /// The number of tokens this amount stands for out of `balance`.
+    pub fn of(self, balance: i64) -> i64 {
+        match self {
+            Amount::Exact(amount) => amount,
+            Amount::Half => balance.max(0) / 2,
+            Amount::All => balance.max(0),
+        }
+    }
+}
+
+/// A bet ID, optionally written `#1`.
+pub fn market_id(word: &str) -> Result<i64, Problem> {
+    word.strip_prefix('#')
+        .unwrap_or(word)
+        .parse::<i64>()
+        .ok()
+        .filter(|id| *id > 0)
+        .ok_or_else(|| Problem::MarketId(word.to_string()))
+}
+
+/// A side, `true` for yes.
+pub fn side(word: &str) -> Result<bool, Problem> {
+    match word.to_lowercase().as_str() {
+        "yes" | "y" => Ok(true),
+        "no" | "n" => Ok(false),
+        _ => Err(Problem::Side(word.to_string())),
+    }
+}
+
+/// A positive amount: a number with optional thousands separators and `k`
+/// or `m` suffix (`1,000`, `1.5k`, `2m`), or `half` / `all` of the balance.
+pub fn amount(word: &str) -> Result<Amount, Problem> {
+    let invalid = || Problem::Amount(word.to_string());
+    let lower = word.to_lowercase();
+    match lower.as_str() {
+        "half" => return Ok(Amount::Half),
+        "all" => return Ok(Amount::All),
+        _ => {}
+    }
+    let digits = lower.replace(',', "");
+    let (number, scale) = match digits.char_indices().last() {
+        Some((at, 'k')) => (&digits[..at], 1_000),
+        Some((at, 'm')) => (&digits[..at], 1_000_000),
+        _ => (digits.as_str(), 1),
+    };
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) || fraction.len() > 6 {
+        return Err(invalid());
+    }
+    let whole = whole.parse::<i64>().map_err(|_| invalid())?;
+    // Fractions only make sense as long as they land on a whole token
+    let fraction_scale = 10_i64.pow(fraction.len() as u32);
+    if scale % fraction_scale != 0 {
+        return Err(invalid());
+    }
+    let fraction = if fraction.is_empty() { 0 } else { fraction.parse::<i64>().map_err(|_| invalid())? };
+    whole
+        .checked_mul(scale)
+        .and_then(|tokens| tokens.checked_add(fraction * (scale / fraction_scale)))
+        .filter(|tokens| *tokens > 0)
+        .map(Amount::Exact)
+        .ok_or_else(invalid)
+}
+
+/// An exact amount with an explicit sign, e.g. `+500` or `-1.5k`.
+pub fn signed_amount(word: &str) -> Result<i64, Problem> {
+    let invalid = || Problem::SignedAmount(word.to_string());
+    let (sign, magnitude) = match word.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    match amount(magnitude) {
+        Ok(Amount::Exact(amount)) => Ok(sign * amount),
+        _ => Err(invalid()),
+    }
+}
+
+/// A `@username` mention, returned without the `@`.
+pub fn mention(word: &str) -> Result<String, Problem> {
+    word.strip_prefix('@')
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .map(str::to_string)
+        .ok_or_else(|| Problem::Mention(word.to_string()))
+}
+
+/// A Telegram user ID.
+pub fn user_id(word: &str) -> Result<i64, Problem> {
+    word.parse::<i64>().map_err(|_| Problem::UserId(word.to_string()))
+}
+
+/// A duration in minutes, hours, days or weeks: `30m`, `12h`, `3d`, `2w`.
+pub fn duration(word: &str) -> Result<chrono::Duration, Problem> {
+    let invalid = || Problem::Duration(word.to_string());
+    let lower = word.to_lowercase();
+    let unit = lower.chars().last().ok_or_else(invalid)?;
+    let count = lower[..lower.len() - unit.len_utf8()]
+        .parse::<i64>()
+        .ok()
+        .filter(|count| *count > 0 && *count <= 10_000)
+        .ok_or_else(invalid)?;
+    match unit {
+        'm' => Ok(chrono::Duration::minutes(count)),
+        'h' => Ok(chrono::Duration::hours(count)),
+        'd' => Ok(chrono::Duration::days(count)),
+        'w' => Ok(chrono::Duration::weeks(count)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Reads the arguments of a command with `read`, failing on anything left
+/// over, e.g. `command(args, Usage::INFO, |args| args.market_id())`.
+pub fn command<'a, T>(
+    args: &'a str,
+    usage: Usage,
+    read: impl FnOnce(&mut Args<'a>) -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    let mut args = Args { rest: args.trim(), usage, read_any: false };
+    let parsed = read(&mut args)?;
+    args.finish()?;
+    Ok(parsed)
+}
+
+/// The whitespace-separated arguments of a command, read one by one.
+pub struct Args<'a> {
+    rest: &'a str,
+    usage: Usage,
+    read_any: bool,
+}
+
+impl<'a> Args<'a> {
+    fn error(&self, problem: Problem) -> ParseError {
+        ParseError { problem, usage: self.usage }
+    }
+
+    fn next_word(&mut self, what: &'static str) -> Result<&'a str, ParseError> {
+        if self.rest.is_empty() {
+            let problem = if self.read_any { Problem::Missing(what) } else { Problem::NoArguments };
+            return Err(self.error(problem));
+        }
+        let (word, rest) = self.rest.split_once(char::is_whitespace).unwrap_or((self.rest, ""));
+        self.rest = rest.trim_start();
+        self.read_any = true;
+        Ok(word)
+    }
+
+    fn next<T>(&mut self, what: &'static str, extract: fn(&str) -> Result<T, Problem>) -> Result<T, ParseError> {
+        let word = self.next_word(what)?;
+        extract(word).map_err(|problem| self.error(problem))
+    }
+
+    pub fn market_id(&mut self) -> Result<i64, ParseError> {
+        self.next("the bet ID", market_id)
+    }
+
+    pub fn side(&mut self) -> Result<bool, ParseError> {
+        self.next("the side (yes or no)", side)
+    }
+
+    pub fn amount(&mut self) -> Result<Amount, ParseError> {
+        self.next("the amount", amount)
+    }
+
+    /// An amount that cannot depend on a balance, e.g. for the operator commands.
+    pub fn exact_amount(&mut self) -> Result<i64, ParseError> {
+        let word = self.next_word("the amount")?;
+        match amount(word) {
+            Ok(Amount::Exact(amount)) => Ok(amount),
+            _ => Err(self.error(Problem::Amount(word.to_string()))),
+        }
+    }
+
+    pub fn signed_amount(&mut self) -> Result<i64, ParseError> {
+        self.next("the amount", signed_amount)
+    }
+
+    pub fn mention(&mut self) -> Result<String, ParseError> {
+        self.next("the @user", mention)
+    }
+
+    /// An optional trailing user ID, `default` when there is none.
+    pub fn user_id_or(&mut self, default: i64) -> Result<i64, ParseError> {
+        if self.rest.is_empty() {
+            return Ok(default);
+        }
+        self.next("the user ID", user_id)
+    }
+
+    /// Everything left, which must not be empty, e.g. a description.
+    pub fn text(&mut self, what: &'static str) -> Result<&'a str, ParseError> {
+        let rest = std::mem::take(&mut self.rest);
+        if rest.is_empty() {
+            return Err(self.error(if self.read_any { Problem::Missing(what) } else { Problem::NoArguments }));
+        }
+        self.read_any = true;
+        Ok(rest)
+    }
+
+    fn finish(self) -> Result<(), ParseError> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(self.error(Problem::Unexpected(self.rest.to_string())))
+        }
+    }
+}
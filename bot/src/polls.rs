@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use teloxide::types::{ChatId, MessageId, Poll, PollType};
+
+/// Offers remembered at most, oldest forgotten first.
+const PENDING_CAPACITY: usize = 64;
+
+/// Prefix of the callback data of a "Create a market" button.
+const CALLBACK_PREFIX: &str = "poll:";
+
+const YES_ANSWERS: &[&str] = &[
+    "yes", "y", "yeah", "yep", "yup", "sure", "definitely", "absolutely", "true", "oui", "si", "sí", "ja", "da", "👍",
+    "✅", "✔️", "✔",
+];
+const NO_ANSWERS: &[&str] = &[
+    "no", "n", "nope", "nah", "never", "false", "non", "nein", "nee", "niet", "👎", "❌", "✖️", "✖",
+];
+
+/// Whether a poll option reads as YES (`true`) or NO (`false`). Only its
+/// first word counts, so "Yes, obviously" and "No way" qualify.
+pub fn answer(option: &str) -> Option<bool> {
+    let option = option.trim().to_lowercase();
+    let first = option
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .next()
+        .unwrap_or("")
+        .trim_end_matches(['!', '.', '?']);
+    if YES_ANSWERS.contains(&first) {
+        Some(true)
+    } else if NO_ANSWERS.contains(&first) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The index of the YES option when `options` are exactly a YES and a NO,
+/// in either order.
+pub fn yes_option(options: &[&str]) -> Option<usize> {
+    match options {
+        [first, second] => match (answer(first)?, answer(second)?) {
+            (true, false) => Some(0),
+            (false, true) => Some(1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The index of the YES option of a poll a market can be made of: open,
+/// regular, single-answer, with yes/no options.
+pub fn market_option(poll: &Poll) -> Option<usize> {
+    if poll.is_closed || poll.poll_type != PollType::Regular || poll.allows_multiple_answers {
+        return None;
+    }
+    let options: Vec<&str> = poll.options.iter().map(|option| option.text.as_str()).collect();
+    yes_option(&options)
+}
+
+/// Votes for YES and for NO.
+pub fn tally(poll: &Poll, yes_option: usize) -> (u32, u32) {
+    let votes = |index: usize| poll.options.get(index).map_or(0, |option| option.voter_count);
+    (votes(yes_option), votes(1 - yes_option.min(1)))
+}
+
+/// Callback data of the button offering to turn the poll `poll_message` into a market.
+pub fn callback_data(poll_message: MessageId) -> String {
+    format!("{}{}", CALLBACK_PREFIX, poll_message.0)
+}
+
+/// The poll message a "Create a market" button refers to.
+pub fn parse_callback(data: &str) -> Option<MessageId> {
+    data.strip_prefix(CALLBACK_PREFIX)?.parse().ok().map(MessageId)
+}
+
+/// A poll the bot offered to turn into a market.
+#[derive(Debug, Clone)]
+pub struct PollOffer {
+    pub poll_id: String,
+    pub author_id: i64,
+    pub question: String,
+    pub yes_option: usize,
+}
+
+/// Polls waiting for their author to accept the offer, keyed by the chat and
+/// the poll message; only the most recent offers are kept.
+#[derive(Default)]
+pub struct PendingPolls(Mutex<VecDeque<(ChatId, MessageId, PollOffer)>>);
+
+impl PendingPolls {
+    pub fn record(&self, chat_id: ChatId, poll_message: MessageId, offer: PollOffer) {
+        let mut pending = self.0.lock().unwrap();
+        if pending.len() == PENDING_CAPACITY {
+            pending.pop_front();
+        }
+        pending.push_back((chat_id, poll_message, offer));
+    }
+
+    pub fn get(&self, chat_id: ChatId, poll_message: MessageId) -> Option<PollOffer> {
+        let pending = self.0.lock().unwrap();
+        pending
+            .iter()
+            .find(|(chat, message, _)| *chat == chat_id && *message == poll_message)
+            .map(|(_, _, offer)| offer.clone())
+    }
+
+    /// Forgets an offer once accepted, so a second press creates nothing.
+    pub fn take(&self, chat_id: ChatId, poll_message: MessageId) -> Option<PollOffer> {
+        let mut pending = self.0.lock().unwrap();
+        let index = pending.iter().position(|(chat, message, _)| *chat == chat_id && *message == poll_message)?;
+        pending.remove(index).map(|(_, _, offer)| offer)
+    }
+}
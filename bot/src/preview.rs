@@ -0,0 +1,85 @@
+use contract1::parimutuel_payout;
+
+use crate::currency::{format_amount, Currency};
+use crate::db::Bet;
+
+/// What a bet would do to a market, computed from its pools the way the
+/// contract prices and settles it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preview {
+    pub side: bool,
+    pub amount: u128,
+    /// Implied probability of `side` before the bet, in basis points
+    pub probability_before_bps: u32,
+    /// Implied probability of `side` once the bet is in, in basis points
+    pub probability_after_bps: u32,
+    /// What the bet pays out if `side` wins and nobody else bets
+    pub payout: u128,
+}
+
+/// Implied probability of `side` in basis points: its share of the pools,
+/// or even odds while they are empty, like the contract's odds.
+pub fn probability_bps(yes_pool: u128, no_pool: u128, side: bool) -> u32 {
+    let yes_bps = (yes_pool * 10_000).checked_div(yes_pool + no_pool).map_or(5_000, |bps| bps as u32);
+    if side {
+        yes_bps
+    } else {
+        10_000 - yes_bps
+    }
+}
+
+/// Simulates a bet of `amount` on `side` against the pools. A win pays out
+/// of the pools less the house edge of `fee_bps`, taken from the losing pool.
+pub fn simulate(yes_pool: u128, no_pool: u128, side: bool, amount: u128, fee_bps: u32) -> Preview {
+    let (yes_after, no_after) = if side { (yes_pool + amount, no_pool) } else { (yes_pool, no_pool + amount) };
+    let (side_pool_after, losing_pool) = if side { (yes_after, no_after) } else { (no_after, yes_after) };
+    let rake = losing_pool * fee_bps as u128 / 10_000;
+    Preview {
+        side,
+        amount,
+        probability_before_bps: probability_bps(yes_pool, no_pool, side),
+        probability_after_bps: probability_bps(yes_after, no_after, side),
+        payout: parimutuel_payout(amount, side_pool_after, yes_after + no_after - rake),
+    }
+}
+
+fn percent(bps: u32) -> String {
+    format!("{}.{}%", bps / 100, bps % 100 / 10)
+}
+
+/// The reply to /preview. `balance` is `None` for users without one yet.
+pub fn render(bet: &Bet, yes_pool: u128, no_pool: u128, preview: &Preview, balance: Option<i64>, currency: &Currency) -> String {
+    let side = if preview.side { "YES" } else { "NO" };
+    let multiplier = preview.payout * 100 / preview.amount.max(1);
+    let mut message = format!(
+        "🔮 Preview of {} on {} in bet #{}\n📄 {}\n📊 {}: {} now → {} after your bet\n💰 If {} wins: {} back (×{}.{:02}) unless others bet after you",
+        format_amount(currency, preview.amount),
+        side,
+        bet.bet_id,
+        bet.description,
+        side,
+        percent(preview.probability_before_bps),
+        percent(preview.probability_after_bps),
+        side,
+        format_amount(currency, preview.payout),
+        multiplier / 100,
+        multiplier % 100
+    );
+    let other_pool = if preview.side { no_pool } else { yes_pool };
+    if other_pool == 0 {
+        message.push_str(&format!(
+            "\nℹ️ Nobody backs {} yet, so a win only returns stakes until someone does.",
+            if preview.side { "NO" } else { "YES" }
+        ));
+    }
+    match balance {
+        None => message.push_str("\n⚠️ You have no balance yet: use /init before betting."),
+        Some(balance) if (balance.max(0) as u128) < preview.amount => message.push_str(&format!(
+            "\n⚠️ You only have {}, so this bet would be refused.",
+            format_amount(currency, balance.max(0) as u128)
+        )),
+        Some(_) => {}
+    }
+    message.push_str("\n\nNothing was placed: this is only a simulation.");
+    message
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use teloxide::types::ChatId;
+use teloxide::RequestError;
+use tokio::time::Instant;
+
+/// How fast the bot sends. Telegram answers 429 past about one message a
+/// second in a chat or 30 a second overall.
+#[derive(Debug, Clone, Copy)]
+pub struct SendPacing {
+    /// Minimum gap between two messages to the same chat
+    pub per_chat: Duration,
+    /// Minimum gap between two messages to any chats
+    pub global: Duration,
+    /// Times a message refused with a 429 is sent again before giving up
+    pub max_retries: u32,
+}
+
+impl Default for SendPacing {
+    fn default() -> Self {
+        Self {
+            per_chat: Duration::from_secs(1),
+            global: Duration::from_millis(40),
+            max_retries: 3,
+        }
+    }
+}
+
+/// Which messages go first when the bot has more to send than it may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Replies to a user who is waiting for them
+    Interactive,
+    /// Announcements, notifications and broadcasts nobody is waiting on
+    Bulk,
+}
+
+#[derive(Default)]
+struct Slots {
+    global_next: Option<Instant>,
+    chat_next: HashMap<ChatId, Instant>,
+    /// Interactive messages waiting for a slot, per chat
+    interactive: HashMap<ChatId, usize>,
+}
+
+impl Slots {
+    fn chat_ready(&self, chat_id: ChatId, now: Instant) -> bool {
+        self.chat_next.get(&chat_id).filter(|next| **next > now).is_none()
+    }
+
+    /// Takes the slot for a message to `chat_id` at `now`, or tells how long
+    /// to wait before asking again.
+    fn take(&mut self, chat_id: ChatId, priority: Priority, now: Instant, pacing: &SendPacing) -> Result<(), Duration> {
+        let ready_at = [self.global_next, self.chat_next.get(&chat_id).copied()].into_iter().flatten().max();
+        if let Some(ready_at) = ready_at.filter(|ready_at| *ready_at > now) {
+            return Err(ready_at - now);
+        }
+        // Bulk messages let every interactive one that could go now go first
+        if priority == Priority::Bulk && self.interactive.keys().any(|chat| self.chat_ready(*chat, now)) {
+            return Err(pacing.global.max(Duration::from_millis(1)));
+        }
+        if priority == Priority::Interactive {
+            if let Some(waiting) = self.interactive.get_mut(&chat_id) {
+                *waiting -= 1;
+                if *waiting == 0 {
+                    self.interactive.remove(&chat_id);
+                }
+            }
+        }
+        self.chat_next.retain(|_, next| *next > now);
+        self.global_next = Some(now + pacing.global);
+        self.chat_next.insert(chat_id, now + pacing.per_chat);
+        Ok(())
+    }
+}
+
+/// Paces every message the bot sends, shared by all the handlers so that a
+/// burst of notifications cannot get the bot rate-limited, and sends again
+/// the messages Telegram refused with a 429 once its `retry_after` is over.
+pub struct SendQueue {
+    pacing: SendPacing,
+    slots: Mutex<Slots>,
+}
+
+impl SendQueue {
+    pub fn new(pacing: SendPacing) -> Self {
+        Self { pacing, slots: Mutex::default() }
+    }
+
+    /// Runs `request`, a message to `chat_id`, once the pacing allows it.
+    pub async fn send<T, F, Fut>(&self, chat_id: ChatId, priority: Priority, mut request: F) -> Result<T, RequestError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RequestError>>,
+    {
+        let mut retries = 0;
+        loop {
+            self.acquire(chat_id, priority).await;
+            match request().await {
+                Err(RequestError::RetryAfter(wait)) if retries < self.pacing.max_retries => {
+                    retries += 1;
+                    log::warn!("Telegram asked to wait {}s before sending to chat {} again", wait.seconds(), chat_id.0);
+                    self.defer(chat_id, wait.duration());
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn acquire(&self, chat_id: ChatId, priority: Priority) {
+        if priority == Priority::Interactive {
+            *self.slots.lock().unwrap().interactive.entry(chat_id).or_default() += 1;
+        }
+        loop {
+            let taken = self.slots.lock().unwrap().take(chat_id, priority, Instant::now(), &self.pacing);
+            match taken {
+                Ok(()) => return,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    /// Holds the messages to `chat_id` back for `wait`.
+    fn defer(&self, chat_id: ChatId, wait: Duration) {
+        let until = Instant::now() + wait;
+        let mut slots = self.slots.lock().unwrap();
+        let next = slots.chat_next.entry(chat_id).or_insert(until);
+        *next = (*next).max(until);
+    }
+}
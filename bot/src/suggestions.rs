@@ -0,0 +1,98 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+use teloxide::types::{ChatId, Message, MessageId};
+
+use crate::db::Bet;
+
+/// Share of a bet's keywords the evidence must mention for the bet to be suggested.
+pub const SUGGESTION_THRESHOLD: f64 = 0.3;
+
+/// Bets offered at most when the evidence matches several.
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// Prompts remembered at most, oldest forgotten first.
+const PENDING_CAPACITY: usize = 64;
+
+/// Prefix of the callback data of a "Solve bet #N?" button.
+const CALLBACK_PREFIX: &str = "solve:";
+
+/// Words too common to tell bets apart.
+const STOPWORDS: &[&str] = &[
+    "about", "after", "and", "are", "before", "but", "can", "did", "does", "for", "from", "has", "have", "how", "its",
+    "not", "than", "that", "the", "this", "was", "were", "what", "when", "who", "will", "with", "would",
+];
+
+fn keywords(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() >= 3 && !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Share of the keywords of `description` that `evidence` mentions, from 0 to 1.
+pub fn keyword_overlap(evidence: &str, description: &str) -> f64 {
+    let wanted = keywords(description);
+    if wanted.is_empty() {
+        return 0.0;
+    }
+    let found = keywords(evidence);
+    wanted.intersection(&found).count() as f64 / wanted.len() as f64
+}
+
+/// The bets `evidence` could be about, best match first.
+pub fn candidate_bets(evidence: &str, bets: Vec<Bet>) -> Vec<Bet> {
+    let mut scored: Vec<(f64, Bet)> = bets
+        .into_iter()
+        .map(|bet| (keyword_overlap(evidence, &bet.description), bet))
+        .filter(|(score, _)| *score >= SUGGESTION_THRESHOLD)
+        .collect();
+    scored.sort_by(|(a, a_bet), (b, b_bet)| b.total_cmp(a).then_with(|| a_bet.bet_id.cmp(&b_bet.bet_id)));
+    scored.into_iter().take(MAX_SUGGESTIONS).map(|(_, bet)| bet).collect()
+}
+
+pub fn callback_data(bet_id: i64) -> String {
+    format!("{}{}", CALLBACK_PREFIX, bet_id)
+}
+
+/// The bet a "Solve bet #N?" button confirms.
+pub fn parse_callback(data: &str) -> Option<i64> {
+    data.strip_prefix(CALLBACK_PREFIX)?.parse().ok()
+}
+
+/// `/solve` commands that named no bet, waiting for their author to pick one
+/// of the suggested bets. Keyed by the chat and the message carrying the
+/// buttons; only the most recent prompts are kept.
+#[derive(Default)]
+pub struct PendingSolves(Mutex<VecDeque<(ChatId, MessageId, Message)>>);
+
+impl PendingSolves {
+    pub fn record(&self, chat_id: ChatId, prompt_id: MessageId, command: Message) {
+        let mut pending = self.0.lock().unwrap();
+        if pending.len() == PENDING_CAPACITY {
+            pending.pop_front();
+        }
+        pending.push_back((chat_id, prompt_id, command));
+    }
+
+    /// The `/solve` command the prompt was sent for.
+    pub fn get(&self, chat_id: ChatId, prompt_id: MessageId) -> Option<Message> {
+        let pending = self.0.lock().unwrap();
+        pending
+            .iter()
+            .find(|(chat, prompt, _)| *chat == chat_id && *prompt == prompt_id)
+            .map(|(_, _, command)| command.clone())
+    }
+
+    /// Forgets the prompt, returning whether it was still pending.
+    pub fn remove(&self, chat_id: ChatId, prompt_id: MessageId) -> bool {
+        let mut pending = self.0.lock().unwrap();
+        match pending.iter().position(|(chat, prompt, _)| *chat == chat_id && *prompt == prompt_id) {
+            Some(index) => {
+                pending.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
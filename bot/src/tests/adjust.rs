@@ -0,0 +1,71 @@
+use super::*;
+use crate::{handle_adjust, handle_reconcile};
+use contract1::api::LedgerBalance;
+
+async fn adjust(h: &Harness, from: i64, args: &str) -> String {
+    handle_adjust(h.messenger(), group_message(from, "op", "/adjust"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+    h.last_reply()
+}
+
+async fn balance(h: &Harness, user_id: i64) -> i64 {
+    h.ctx.db.get_user(user_id).await.unwrap().unwrap().balance
+}
+
+#[tokio::test]
+async fn operators_adjust_balances_with_a_reason() {
+    let h = Harness::new().await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+
+    assert_eq!(
+        adjust(&h, OPERATOR, "@bob +500 refund of a bet lost to a bot bug").await,
+        "🛠 Adjusted the balance of @bob by 🪙 +500 coins: 🪙 1,000 coins → 🪙 1,500 coins\n📝 Reason: refund of a bet lost to a bot bug\nThis change is local only: the chain keeps the previous balance."
+    );
+    adjust(&h, OPERATOR, "@bob -1.2k duplicate payout").await;
+
+    assert_eq!(balance(&h, BOB).await, 300);
+    assert_eq!(h.ctx.db.get_local_adjustments().await.unwrap(), vec![(BOB, -700)]);
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn adjusting_is_for_operators() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    h.initialized_user(BOB, "bob", 1_000).await;
+
+    assert_eq!(adjust(&h, ALICE, "@bob +500 a gift").await, "⛔ This command is reserved for bot operators.");
+    assert_eq!(balance(&h, BOB).await, 1_000);
+    assert!(h.ctx.db.get_local_adjustments().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn overdrafts_unknown_users_and_bad_amounts_are_refused() {
+    let h = Harness::new().await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+
+    assert_eq!(adjust(&h, OPERATOR, "@bob -1001 too much").await, "Cannot take 🪙 1,001 coins from @bob, who only has 🪙 1,000 coins.");
+    assert_eq!(adjust(&h, OPERATOR, "@carol +10 welcome").await, "I don't know @carol yet.");
+    for args in ["@bob 500 no sign", "@bob +500", "@bob +all everything", "bob +500 no at"] {
+        assert!(adjust(&h, OPERATOR, args).await.contains("/adjust @user <+/-amount> <reason>"), "{}: {}", args, h.last_reply());
+    }
+
+    assert_eq!(balance(&h, BOB).await, 1_000);
+    assert!(h.ctx.db.get_local_adjustments().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn reconcile_leaves_local_adjustments_out() {
+    let h = Harness::new().await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    adjust(&h, OPERATOR, "@bob +500 refund").await;
+
+    handle_reconcile(h.messenger(), group_message(OPERATOR, "op", "/reconcile"), h.ctx.clone())
+        .await
+        .unwrap();
+
+    let snapshot = h.api.reconciled().unwrap();
+    assert_eq!(snapshot.balances, vec![LedgerBalance { identity: format!("{}@contract1", BOB), balance: 1_000 }]);
+    assert!(h.last_reply().ends_with("🛠 Local-only /adjust corrections of 1 user(s) were left out."), "{}", h.last_reply());
+}
@@ -0,0 +1,66 @@
+use chrono::Utc;
+
+use super::*;
+use crate::alerts::{self, should_alert, ALERT_INTERVAL};
+use crate::{handle_alerts, handle_bet};
+
+/// Alice, with a YES stake of 100 on her bet #1, and Bob.
+async fn setup() -> Harness {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    h.open_bet(ALICE, "Will it snow?").await;
+    bet(&h, ALICE, "alice", "1 yes 100").await;
+    h
+}
+
+async fn bet(h: &Harness, from: i64, username: &str, args: &str) {
+    let msg = group_message(from, username, &format!("/bet {}", args));
+    handle_bet(h.messenger(), msg, h.ctx.clone(), args.to_string()).await.unwrap();
+}
+
+#[test]
+fn alerts_need_a_move_past_the_threshold_and_an_hour_since_the_last() {
+    let now = Utc::now();
+    assert!(!should_alert(4_000, 5_500, None, now));
+    assert!(should_alert(4_000, 5_501, None, now));
+    assert!(should_alert(4_000, 2_000, None, now));
+
+    let hour = chrono::Duration::from_std(ALERT_INTERVAL).unwrap();
+    assert!(!should_alert(4_000, 8_000, Some(now - hour + chrono::Duration::minutes(1)), now));
+    assert!(should_alert(4_000, 8_000, Some(now - hour), now));
+}
+
+#[tokio::test]
+async fn bettors_hear_of_big_moves_once() {
+    let h = setup().await;
+    // Even pools: YES fell from 100% to 50%
+    bet(&h, BOB, "bob", "1 no 100").await;
+    let sent = h.sent_to(ALICE);
+    assert_eq!(sent.len(), 1, "{:?}", sent);
+    assert!(sent[0].contains("YES: 100.0% → 50.0%"), "{}", sent[0]);
+    assert!(sent[0].contains("would pay"), "{}", sent[0]);
+    // Bob just saw the odds his bet made
+    assert!(h.sent_to(BOB).is_empty());
+
+    // Down to 20% within the hour: nothing more
+    bet(&h, BOB, "bob", "1 no 300").await;
+    assert_eq!(h.sent_to(ALICE).len(), 1);
+
+    // An hour later, the move since the last alert is reported
+    alerts::check(&h.messenger(), &h.ctx, 1, BOB, Utc::now() + ALERT_INTERVAL).await.unwrap();
+    let sent = h.sent_to(ALICE);
+    assert_eq!(sent.len(), 2, "{:?}", sent);
+    assert!(sent[1].contains("YES: 50.0% → 20.0%"), "{}", sent[1]);
+}
+
+#[tokio::test]
+async fn alerts_can_be_turned_off() {
+    let h = setup().await;
+    let msg = private_message(ALICE, "alice", "/alerts off");
+    handle_alerts(h.messenger(), msg, h.ctx.clone(), "off".to_string()).await.unwrap();
+    assert!(h.last_reply().starts_with("🔕 Odds alerts are off"), "{}", h.last_reply());
+
+    bet(&h, BOB, "bob", "1 no 100").await;
+    assert_eq!(h.sent_to(ALICE).len(), 1);
+}
@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+use super::*;
+use crate::announcements::{announcement_text, pool_bar, EditPlan, EDIT_INTERVAL};
+use crate::currency::Currency;
+use crate::{handle_bet, handle_new};
+
+/// Alice announces a market in the group; Bob can bet on it.
+async fn announced_market(h: &Harness) -> i64 {
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.initialized_user(BOB, "bob", 10_000).await;
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), "Will it rain?".to_string())
+        .await
+        .unwrap();
+    1
+}
+
+async fn bet(h: &Harness, user: i64, args: &str) {
+    handle_bet(h.messenger(), group_message(user, "bob", "/bet"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+}
+
+#[test]
+fn pool_bar_shows_the_yes_share() {
+    let coins = Currency::default();
+    assert_eq!(pool_bar(1_768, 1_632, &coins), "YES ▓▓▓▓▓░░░░░ 52% · pool 🪙 3,400 coins");
+    assert_eq!(pool_bar(0, 500, &coins), "YES ░░░░░░░░░░ 0% · pool 🪙 500 coins");
+    assert_eq!(pool_bar(500, 0, &coins), "YES ▓▓▓▓▓▓▓▓▓▓ 100% · pool 🪙 500 coins");
+    assert_eq!(announcement_text("Market #1", &[], &coins), "Market #1\n\n📊 No bets yet");
+}
+
+#[tokio::test]
+async fn bets_update_the_announcement() {
+    let h = Harness::new().await;
+    let bet_id = announced_market(&h).await;
+    assert!(h.replies()[0].ends_with("\n\n📊 No bets yet"), "{}", h.replies()[0]);
+
+    bet(&h, BOB, &format!("{} no 300", bet_id)).await;
+
+    let edits = h.edits();
+    assert_eq!(edits.len(), 1);
+    let (message_id, text) = &edits[0];
+    assert_eq!(*message_id, MessageId(FIRST_SENT_ID));
+    assert!(text.starts_with("✅ Market #1 created on-chain by @alice"), "{}", text);
+    assert!(text.ends_with("\n\n📊 YES ░░░░░░░░░░ 0% · pool 🪙 300 coins\n👥 1 bettor"), "{}", text);
+}
+
+#[tokio::test]
+async fn edits_within_the_interval_are_coalesced() {
+    let h = Harness::new().await;
+    let edits = &h.ctx.announcement_edits;
+    let start = Instant::now();
+
+    assert_eq!(edits.schedule(1, start), EditPlan::Now);
+    assert_eq!(edits.schedule(1, start + Duration::from_secs(4)), EditPlan::After(EDIT_INTERVAL - Duration::from_secs(4)));
+    assert_eq!(edits.schedule(1, start + Duration::from_secs(6)), EditPlan::Coalesced);
+    // Other markets have their own budget
+    assert_eq!(edits.schedule(2, start + Duration::from_secs(6)), EditPlan::Now);
+
+    // The delayed edit went out; the next change waits for the interval again
+    edits.edited(1, start + EDIT_INTERVAL);
+    assert_eq!(edits.schedule(1, start + EDIT_INTERVAL + Duration::from_secs(1)), EditPlan::After(EDIT_INTERVAL - Duration::from_secs(1)));
+    edits.edited(1, start + EDIT_INTERVAL * 2);
+    assert_eq!(edits.schedule(1, start + EDIT_INTERVAL * 3), EditPlan::Now);
+}
+
+#[tokio::test]
+async fn deleted_announcements_are_no_longer_edited() {
+    let h = Harness::new().await;
+    let bet_id = announced_market(&h).await;
+    h.delete_message(MessageId(FIRST_SENT_ID));
+
+    bet(&h, BOB, &format!("{} yes 100", bet_id)).await;
+
+    assert!(h.edits().is_empty());
+    assert!(h.ctx.db.get_live_announcement(bet_id).await.unwrap().is_none());
+    // The bet itself went through
+    assert_eq!(h.api.calls().len(), 2);
+}
@@ -0,0 +1,96 @@
+use super::*;
+use crate::broadcast::resume;
+use crate::handle_broadcast;
+
+const OTHER_CHAT: i64 = -1002;
+const QUIET_CHAT: i64 = -1003;
+
+/// Open bets in the test group and `OTHER_CHAT`; `QUIET_CHAT` only has a resolved one.
+async fn active_chats(h: &Harness) {
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.open_bet(ALICE, "Will it snow?").await;
+    h.ctx.db.create_bet(ALICE, OTHER_CHAT, "Will it rain?".to_string(), None).await.unwrap();
+    let resolved = h.ctx.db.create_bet(ALICE, QUIET_CHAT, "Will it hail?".to_string(), None).await.unwrap();
+    h.ctx.db.close_bet(resolved, false).await.unwrap();
+}
+
+async fn broadcast(h: &Harness, msg: Message, args: &str) {
+    handle_broadcast(h.messenger(), msg, h.ctx.clone(), args.to_string()).await.unwrap();
+}
+
+#[tokio::test]
+async fn broadcast_reaches_every_chat_with_an_open_bet_once() {
+    let h = Harness::new().await;
+    active_chats(&h).await;
+
+    broadcast(&h, private_message(OPERATOR, "op", "/broadcast"), "Maintenance at 18:00").await;
+
+    assert_eq!(h.sent_to(CHAT_ID), vec!["Maintenance at 18:00"]);
+    assert_eq!(h.sent_to(OTHER_CHAT), vec!["Maintenance at 18:00"]);
+    assert!(h.sent_to(QUIET_CHAT).is_empty());
+    assert_eq!(
+        h.sent_to(OPERATOR),
+        vec!["📣 Broadcasting to 2 chat(s)…", "✅ Broadcast #1 sent to 2 of 2 chats."]
+    );
+    assert!(h.ctx.db.get_unfinished_broadcasts().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn dry_run_only_lists_the_chats() {
+    let h = Harness::new().await;
+    active_chats(&h).await;
+
+    broadcast(&h, private_message(OPERATOR, "op", "/broadcast"), "dry-run Maintenance at 18:00").await;
+
+    assert_eq!(h.replies(), vec![format!("📣 Would send to 2 chat(s):\n• {}\n• {}", OTHER_CHAT, CHAT_ID)]);
+    assert!(h.ctx.db.get_unfinished_broadcasts().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn unreachable_chats_are_reported_and_not_retried() {
+    let h = Harness::new().await;
+    active_chats(&h).await;
+    h.kick_from(OTHER_CHAT);
+
+    broadcast(&h, private_message(OPERATOR, "op", "/broadcast"), "Maintenance at 18:00").await;
+
+    assert_eq!(h.last_reply(), "✅ Broadcast #1 sent to 1 of 2 chats (1 failed).");
+    assert!(h.ctx.db.get_pending_deliveries(1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn interrupted_broadcast_resumes_without_duplicates() {
+    let h = Harness::new().await;
+    active_chats(&h).await;
+    // A crash right after the first chat was reached
+    let interrupted = h.ctx.db.create_broadcast(OPERATOR, "Back soon", &[CHAT_ID, OTHER_CHAT]).await.unwrap();
+    h.ctx.db.record_delivery(interrupted.broadcast_id, OTHER_CHAT, true).await.unwrap();
+
+    resume(&h.messenger(), &h.ctx).await.unwrap();
+
+    assert_eq!(h.sent_to(CHAT_ID), vec!["Back soon"]);
+    assert!(h.sent_to(OTHER_CHAT).is_empty());
+    assert_eq!(h.sent_to(OPERATOR), vec!["✅ Broadcast #1, resumed after a restart, sent to 2 of 2 chats."]);
+
+    // Nothing is left for the next start
+    resume(&h.messenger(), &h.ctx).await.unwrap();
+    assert_eq!(h.replies().len(), 2);
+}
+
+#[tokio::test]
+async fn broadcast_is_for_operators_in_private() {
+    let h = Harness::new().await;
+    active_chats(&h).await;
+
+    broadcast(&h, private_message(ALICE, "alice", "/broadcast"), "Hi").await;
+    assert_eq!(h.last_reply(), "⛔ This command is reserved for bot operators.");
+
+    broadcast(&h, group_message(OPERATOR, "op", "/broadcast"), "Hi").await;
+    assert_eq!(h.last_reply(), "📣 /broadcast only works in a private chat with the bot.");
+
+    broadcast(&h, private_message(OPERATOR, "op", "/broadcast"), "dry-run").await;
+    assert!(h.last_reply().starts_with("Usage: /broadcast"), "{}", h.last_reply());
+
+    assert!(h.sent_to(OTHER_CHAT).is_empty());
+    assert!(h.ctx.db.get_unfinished_broadcasts().await.unwrap().is_empty());
+}
@@ -0,0 +1,132 @@
+use super::*;
+use crate::challenges::{callback_data, cancel_expired, parse_args, parse_callback};
+use crate::parse::{Amount, Problem};
+use crate::{handle_callback, handle_challenge};
+
+async fn challenge(h: &Harness, args: &str) {
+    handle_challenge(h.messenger(), group_message(ALICE, "alice", "/challenge"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+}
+
+async fn press(h: &Harness, from: i64, username: &str, accept: bool) {
+    handle_callback(h.messenger(), button_press(from, username, FIRST_SENT_ID, &callback_data(accept, 1)), h.ctx.clone())
+        .await
+        .unwrap();
+}
+
+async fn balance(h: &Harness, user_id: i64) -> i64 {
+    h.ctx.db.get_user(user_id).await.unwrap().unwrap().balance
+}
+
+#[test]
+fn challenge_arguments_and_buttons() {
+    assert_eq!(
+        parse_args("@bob 1k I finish the marathon first"),
+        Ok(("bob".to_string(), Amount::Exact(1_000), "I finish the marathon first".to_string()))
+    );
+    assert_eq!(parse_args("@bob half Rain").unwrap().1, Amount::Half);
+    assert_eq!(parse_args("bob 100 Rain").unwrap_err().problem, Problem::Mention("bob".to_string()));
+    assert_eq!(parse_args("@bob -5 Rain").unwrap_err().problem, Problem::Amount("-5".to_string()));
+    assert_eq!(parse_args("@bob 100").unwrap_err().problem, Problem::Missing("the description"));
+    assert_eq!(parse_args("@ 100 Rain").unwrap_err().problem, Problem::Mention("@".to_string()));
+
+    assert_eq!(callback_data(true, 7), "challenge:accept:7");
+    assert_eq!(parse_callback(&callback_data(false, 7)), Some((false, 7)));
+    assert_eq!(parse_callback("challenge:maybe:7"), None);
+    assert_eq!(parse_callback("poll:7"), None);
+}
+
+#[tokio::test]
+async fn the_opponent_matches_the_stake() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+
+    challenge(&h, "@Bob 100 I finish the marathon first").await;
+
+    assert_eq!(h.api.calls(), vec![format!("challenge {} {} 100 I finish the marathon first", ALICE, BOB)]);
+    assert!(h.last_reply().starts_with("🥊 Challenge #1: @alice challenges @bob"), "{}", h.last_reply());
+    assert_eq!(
+        h.last_buttons(),
+        vec![("Accept".to_string(), "challenge:accept:1".to_string()), ("Decline".to_string(), "challenge:decline:1".to_string())]
+    );
+    assert_eq!(balance(&h, ALICE).await, 900);
+    assert_eq!(h.ctx.db.get_announced_bet(CHAT_ID, FIRST_SENT_ID as i64).await.unwrap(), Some(1));
+
+    press(&h, BOB, "bob", true).await;
+
+    assert_eq!(h.api.calls().last().unwrap(), &format!("accept {} #1", BOB));
+    assert!(h.last_reply().starts_with("🤝 @bob accepted challenge #1"), "{}", h.last_reply());
+    assert_eq!(balance(&h, BOB).await, 900);
+    assert_eq!(h.ctx.db.get_challenge(1).await.unwrap().unwrap().status, "accepted");
+
+    // Accepting twice does nothing
+    press(&h, BOB, "bob", true).await;
+    assert_eq!(h.callback_answers().last().unwrap().as_deref(), Some("This challenge is no longer open."));
+    assert_eq!(h.api.calls().len(), 2);
+}
+
+#[tokio::test]
+async fn challenges_need_a_known_opponent_and_the_stake() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 50).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+
+    challenge(&h, "@carol 10 Rain").await;
+    assert!(h.last_reply().starts_with("I don't know @carol yet"), "{}", h.last_reply());
+    h.ctx.db.remember_user(44, Some("carol".to_string())).await.unwrap();
+    challenge(&h, "@carol 10 Rain").await;
+    assert!(h.last_reply().starts_with("@carol has no balance yet"), "{}", h.last_reply());
+    challenge(&h, "@alice 10 Rain").await;
+    assert_eq!(h.last_reply(), "You cannot challenge yourself.");
+    challenge(&h, "@bob 100 Rain").await;
+    assert!(h.last_reply().starts_with("Insufficient balance."), "{}", h.last_reply());
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn only_the_opponent_accepts_and_declining_refunds() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    h.initialized_user(43_000, "carol", 1_000).await;
+    challenge(&h, "@bob 100 Rain").await;
+
+    press(&h, 43_000, "carol", true).await;
+    press(&h, 43_000, "carol", false).await;
+    assert_eq!(
+        h.callback_answers(),
+        vec![
+            Some("Only the challenged player can accept this challenge.".to_string()),
+            Some("Only the two players can call this challenge off.".to_string()),
+        ]
+    );
+    assert_eq!(h.api.calls().len(), 1);
+
+    press(&h, BOB, "bob", false).await;
+
+    assert_eq!(h.api.calls().last().unwrap(), &format!("cancel {} #1", BOB));
+    assert!(h.last_reply().starts_with("🚫 Challenge #1 was declined: Rain"), "{}", h.last_reply());
+    assert_eq!(balance(&h, ALICE).await, 1_000);
+    assert_eq!(h.ctx.db.get_bet_by_id(1).await.unwrap().unwrap().status, "cancelled");
+}
+
+#[tokio::test]
+async fn unanswered_challenges_expire() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    challenge(&h, "@bob 100 Rain").await;
+
+    cancel_expired(&h.messenger(), &h.ctx, chrono::Utc::now()).await.unwrap();
+    assert_eq!(h.api.calls().len(), 1);
+
+    let later = chrono::Utc::now() + chrono::Duration::days(2);
+    cancel_expired(&h.messenger(), &h.ctx, later).await.unwrap();
+
+    assert_eq!(h.api.calls().last().unwrap(), &format!("cancel {} #1", ALICE));
+    assert!(h.last_reply().starts_with("🚫 Challenge #1 expired unanswered: Rain"), "{}", h.last_reply());
+    assert_eq!(balance(&h, ALICE).await, 1_000);
+    assert!(h.ctx.db.get_expired_challenges(&later.to_rfc3339()).await.unwrap().is_empty());
+}
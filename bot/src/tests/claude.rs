@@ -0,0 +1,639 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode, Uri},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use super::*;
+use crate::claude::{evaluate_bet_resolution, format_usd, AnthropicResolver, EvidenceMessage, OpenAiCompatibleResolver, PositionSummary, TokenUsage};
+use crate::{current_month, handle_cost, handle_solve, remember_group_message};
+
+/// What the mock model server answers, in order, and every request it got.
+#[derive(Clone, Default)]
+struct Llm {
+    replies: Arc<Mutex<VecDeque<(StatusCode, Value)>>>,
+    requests: Arc<Mutex<Vec<(String, HeaderMap, Value)>>>,
+}
+
+impl Llm {
+    fn reply(&self, status: StatusCode, body: Value) {
+        self.replies.lock().unwrap().push_back((status, body));
+    }
+
+    fn request_count(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+}
+
+async fn answer(State(llm): State<Llm>, uri: Uri, headers: HeaderMap, Json(body): Json<Value>) -> (StatusCode, Json<Value>) {
+    llm.requests.lock().unwrap().push((uri.path().to_string(), headers, body));
+    let reply = llm.replies.lock().unwrap().pop_front();
+    let (status, body) = reply.unwrap_or((StatusCode::INTERNAL_SERVER_ERROR, json!("no reply scripted")));
+    (status, Json(body))
+}
+
+/// A model server on a free port, returning its base URL.
+async fn llm_server() -> (String, Llm) {
+    let llm = Llm::default();
+    let router = Router::new().fallback(answer).with_state(llm.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (url, llm)
+}
+
+/// An Anthropic message answering `text`.
+fn message(text: &str) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "usage": { "input_tokens": 1000, "output_tokens": 100 },
+    })
+}
+
+/// A chat completion answering `text`.
+fn completion(text: &str) -> Value {
+    json!({
+        "choices": [{ "message": { "content": text } }],
+        "usage": { "prompt_tokens": 1000, "completion_tokens": 100 },
+    })
+}
+
+/// The JSON verdict the prompt asks the model for.
+fn verdict_text(resolved: bool, outcome: bool) -> String {
+    json!({ "resolved": resolved, "outcome": outcome, "reasoning": "John said hello" }).to_string()
+}
+
+fn anthropic(url: &str) -> AnthropicResolver {
+    AnthropicResolver::new(url.to_string(), "test-key".to_string(), "claude-sonnet-4-20250514".to_string())
+}
+
+fn openai(url: &str) -> OpenAiCompatibleResolver {
+    OpenAiCompatibleResolver::new(url.to_string(), "gpt-4o-mini".to_string(), None)
+}
+
+fn context(bet_id: i64, evidence: &str) -> ResolutionContext {
+    ResolutionContext {
+        bet_id,
+        bet_description: "Will John say hello?".to_string(),
+        evidence: vec![EvidenceMessage {
+            author: "john".to_string(),
+            timestamp: "2025-01-02T10:00:00+00:00".to_string(),
+            text: evidence.to_string(),
+        }],
+        criteria: None,
+        deadline: None,
+        created_at: "2025-01-01T00:00:00+00:00".to_string(),
+        now: "2025-01-02T12:00:00+00:00".to_string(),
+        positions: None,
+    }
+}
+
+/// The prompt the model is sent for `ctx`.
+async fn sent_prompt(ctx: ResolutionContext) -> String {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, completion(&verdict_text(false, false)));
+    openai(&url).evaluate(ctx).await.unwrap();
+    let body = llm.requests.lock().unwrap()[0].2.clone();
+    body["messages"][0]["content"].as_str().unwrap().to_string()
+}
+
+/// The line of `prompt` starting with `label`.
+fn prompt_line<'a>(prompt: &'a str, label: &str) -> &'a str {
+    prompt.lines().find(|line| line.starts_with(label)).unwrap_or_else(|| panic!("no {} in {}", label, prompt))
+}
+
+async fn cache(ttl: Duration) -> ResolutionCache {
+    let h = Harness::new().await;
+    ResolutionCache::new(h.ctx.db.clone(), ttl)
+}
+
+// --------------------------------------------------------
+//     Cache
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn identical_evaluations_are_answered_from_the_cache() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, completion(&verdict_text(true, true)));
+    let (resolver, cache) = (openai(&url), cache(Duration::from_secs(3600)).await);
+
+    let first = evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    let second = evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+
+    assert_eq!(llm.request_count(), 1);
+    assert_eq!((second.resolved, second.outcome, second.reasoning.as_str()), (true, true, "John said hello"));
+    assert_eq!(second.raw_response, first.raw_response);
+    // Only the call that reached the model is billed
+    assert_eq!(first.usage, Some(TokenUsage { input_tokens: 1000, output_tokens: 100 }));
+    assert_eq!(second.usage, None);
+}
+
+#[tokio::test]
+async fn forced_evaluations_skip_the_cache_and_refresh_it() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, completion(&verdict_text(false, false)));
+    llm.reply(StatusCode::OK, completion(&verdict_text(true, true)));
+    let (resolver, cache) = (openai(&url), cache(Duration::from_secs(3600)).await);
+
+    evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    let forced = evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), true).await.unwrap();
+    assert!(forced.resolved);
+    assert_eq!(llm.request_count(), 2);
+
+    let cached = evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    assert!(cached.resolved);
+    assert_eq!(llm.request_count(), 2);
+}
+
+#[tokio::test]
+async fn other_bets_and_evidence_are_evaluated_again() {
+    let (url, llm) = llm_server().await;
+    for _ in 0..3 {
+        llm.reply(StatusCode::OK, completion(&verdict_text(false, false)));
+    }
+    let (resolver, cache) = (openai(&url), cache(Duration::from_secs(3600)).await);
+
+    evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    evaluate_bet_resolution(&resolver, &cache, context(1, "hello there"), false).await.unwrap();
+    evaluate_bet_resolution(&resolver, &cache, context(2, "hello"), false).await.unwrap();
+    assert_eq!(llm.request_count(), 3);
+}
+
+#[tokio::test]
+async fn failed_evaluations_are_not_cached() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, completion("I think John said hello"));
+    llm.reply(StatusCode::OK, completion(&verdict_text(true, true)));
+    let (resolver, cache) = (openai(&url), cache(Duration::from_secs(3600)).await);
+
+    evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap_err();
+    let retried = evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    assert!(retried.resolved);
+    assert_eq!(llm.request_count(), 2);
+}
+
+#[tokio::test]
+async fn expired_evaluations_are_asked_again() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, completion(&verdict_text(true, true)));
+    llm.reply(StatusCode::OK, completion(&verdict_text(true, true)));
+    let (resolver, cache) = (openai(&url), cache(Duration::ZERO).await);
+
+    evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    // Stored, but already past its TTL
+    let again = evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    assert!(again.usage.is_some());
+    assert_eq!(llm.request_count(), 2);
+}
+
+// --------------------------------------------------------
+//     Backends
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn both_backends_parse_into_the_same_resolution() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, message(&verdict_text(true, false)));
+    llm.reply(StatusCode::OK, completion(&verdict_text(true, false)));
+
+    let from_anthropic = anthropic(&url).evaluate(context(1, "hello")).await.unwrap();
+    let from_openai = openai(&url).evaluate(context(1, "hello")).await.unwrap();
+
+    for resolution in [from_anthropic, from_openai] {
+        assert_eq!((resolution.resolved, resolution.outcome, resolution.reasoning.as_str()), (true, false, "John said hello"));
+        assert_eq!(resolution.raw_response, Some(verdict_text(true, false)));
+        assert_eq!(resolution.usage, Some(TokenUsage { input_tokens: 1000, output_tokens: 100 }));
+    }
+}
+
+#[tokio::test]
+async fn anthropic_requests_carry_the_key_version_and_model() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, message(&verdict_text(false, false)));
+
+    anthropic(&format!("{}/", url)).evaluate(context(1, "hello")).await.unwrap();
+
+    let (path, headers, body) = llm.requests.lock().unwrap()[0].clone();
+    assert_eq!(path, "/messages");
+    assert_eq!(headers["x-api-key"], "test-key");
+    assert_eq!(headers["anthropic-version"], "2023-06-01");
+    assert_eq!((&body["model"], &body["max_tokens"]), (&json!("claude-sonnet-4-20250514"), &json!(150)));
+    assert_eq!(body["messages"][0]["role"], "user");
+}
+
+#[tokio::test]
+async fn openai_requests_authenticate_only_with_a_key() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, completion(&verdict_text(false, false)));
+    llm.reply(StatusCode::OK, completion(&verdict_text(false, false)));
+
+    let keyed = OpenAiCompatibleResolver::new(format!("{}/", url), "gpt-4o-mini".to_string(), Some("sk-test".to_string()));
+    keyed.evaluate(context(1, "hello")).await.unwrap();
+    openai(&url).evaluate(context(1, "hello")).await.unwrap();
+
+    let requests = llm.requests.lock().unwrap().clone();
+    assert_eq!((requests[0].0.as_str(), requests[1].0.as_str()), ("/chat/completions", "/chat/completions"));
+    assert_eq!(requests[0].1["authorization"], "Bearer sk-test");
+    assert!(!requests[1].1.contains_key("authorization"));
+    assert_eq!(requests[0].2["model"], "gpt-4o-mini");
+}
+
+#[tokio::test]
+async fn whitespace_around_the_answer_is_ignored_and_usage_is_optional() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, json!({ "content": [{ "type": "text", "text": format!("\n  {}\n", verdict_text(true, true)) }] }));
+
+    let resolution = anthropic(&url).evaluate(context(1, "hello")).await.unwrap();
+    assert!(resolution.resolved && resolution.outcome);
+    assert_eq!(resolution.usage, None);
+}
+
+#[tokio::test]
+async fn malformed_answers_are_errors() {
+    let (url, llm) = llm_server().await;
+    let answers = [
+        "John said hello, so YES wins",
+        r#"{"resolved": true, "outcome": true, "reasoning": "cut off"#,
+        r#"{"resolved": "yes", "outcome": true, "reasoning": "John said hello"}"#,
+        r#"{"resolved": true}"#,
+        "{}",
+        "",
+    ];
+    for answer in answers {
+        llm.reply(StatusCode::OK, message(answer));
+        llm.reply(StatusCode::OK, completion(answer));
+        assert!(anthropic(&url).evaluate(context(1, "hello")).await.is_err(), "{:?}", answer);
+        assert!(openai(&url).evaluate(context(1, "hello")).await.is_err(), "{:?}", answer);
+    }
+}
+
+#[tokio::test]
+async fn empty_answers_are_errors() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, json!({ "content": [] }));
+    llm.reply(StatusCode::OK, json!({ "choices": [] }));
+    llm.reply(StatusCode::OK, json!({ "choices": [{ "message": { "content": null } }] }));
+
+    let err = anthropic(&url).evaluate(context(1, "hello")).await.unwrap_err();
+    assert_eq!(err.to_string(), "No content in Claude response");
+    for _ in 0..2 {
+        let err = openai(&url).evaluate(context(1, "hello")).await.unwrap_err();
+        assert_eq!(err.to_string(), "No content in gpt-4o-mini response");
+    }
+}
+
+#[tokio::test]
+async fn api_errors_report_the_response_body() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::from_u16(529).unwrap(), json!({ "error": { "type": "overloaded_error" } }));
+    llm.reply(StatusCode::UNAUTHORIZED, json!({ "error": { "message": "bad key" } }));
+
+    let err = anthropic(&url).evaluate(context(1, "hello")).await.unwrap_err();
+    assert_eq!(err.to_string(), r#"Claude API error: {"error":{"type":"overloaded_error"}}"#);
+    let err = openai(&url).evaluate(context(1, "hello")).await.unwrap_err();
+    assert_eq!(err.to_string(), r#"gpt-4o-mini API error: {"error":{"message":"bad key"}}"#);
+}
+
+// --------------------------------------------------------
+//     Prompt
+// --------------------------------------------------------
+
+/// A bet with everything the prompt can show.
+fn full_context() -> ResolutionContext {
+    ResolutionContext {
+        bet_id: 7,
+        bet_description: "Will John run a marathon before March?".to_string(),
+        evidence: vec![
+            EvidenceMessage {
+                author: "john".to_string(),
+                timestamp: "2025-02-16T08:00:00+00:00".to_string(),
+                text: "Starting line, wish me luck".to_string(),
+            },
+            EvidenceMessage {
+                author: "john".to_string(),
+                timestamp: "2025-02-16T12:31:00+00:00".to_string(),
+                text: "Finished in 4:12, results are on the race website".to_string(),
+            },
+        ],
+        criteria: Some("Only a full 42.195 km race with official results counts".to_string()),
+        deadline: Some("2025-03-01T00:00:00+00:00".to_string()),
+        created_at: "2025-01-10T18:00:00+00:00".to_string(),
+        now: "2025-02-16T13:00:00+00:00".to_string(),
+        positions: Some(PositionSummary { yes_stake: 630, no_stake: 370, bettor_count: 5 }),
+    }
+}
+
+#[tokio::test]
+async fn prompt_renders_every_section_of_the_context() {
+    assert_eq!(sent_prompt(full_context()).await, include_str!("fixtures/resolution_prompt.txt"));
+}
+
+#[tokio::test]
+async fn prompt_says_what_the_context_lacks() {
+    let prompt = sent_prompt(context(1, "hello")).await;
+    assert_eq!(prompt_line(&prompt, "RESOLUTION CRITERIA:"), "RESOLUTION CRITERIA: None given; the description is the resolution rule.");
+    assert_eq!(prompt_line(&prompt, "DEADLINE:"), "DEADLINE: None");
+    assert!(prompt.contains("=== MARKET POSITIONS (for context only, never a reason to pick a side) ===\nUnknown\n"), "{}", prompt);
+
+    let unstaked = ResolutionContext {
+        positions: Some(PositionSummary { yes_stake: 0, no_stake: 0, bettor_count: 0 }),
+        ..context(1, "hello")
+    };
+    assert!(sent_prompt(unstaked).await.contains("\nNo stake placed yet\n"));
+}
+
+#[tokio::test]
+async fn long_criteria_are_cut_to_their_token_budget() {
+    // 400 tokens of about 4 characters
+    let ctx = ResolutionContext { criteria: Some("rule ".repeat(1000)), ..context(1, "hello") };
+    let prompt = sent_prompt(ctx).await;
+    assert_eq!(
+        prompt_line(&prompt, "RESOLUTION CRITERIA:"),
+        format!("RESOLUTION CRITERIA: {} [truncated]", "rule ".repeat(320).trim_end())
+    );
+
+    let ctx = ResolutionContext { criteria: Some("rule ".repeat(320)), ..context(1, "hello") };
+    let prompt = sent_prompt(ctx).await;
+    assert!(!prompt.contains("[truncated]"));
+}
+
+// --------------------------------------------------------
+//     Spend
+// --------------------------------------------------------
+
+const USAGE: TokenUsage = TokenUsage { input_tokens: 1000, output_tokens: 100 };
+
+#[test]
+fn cost_is_priced_by_the_longest_model_prefix() {
+    let prices = PriceTable::default();
+    // $3 and $15 per million tokens
+    assert_eq!(prices.cost_micros("claude-sonnet-4-20250514", USAGE), 4_500);
+    assert_eq!(prices.cost_micros("gpt-4o-mini", USAGE), 210);
+    assert_eq!(prices.cost_micros("gpt-4o-2024-08-06", USAGE), 3_500);
+    assert_eq!(prices.cost_micros("llama3", USAGE), 0);
+    assert_eq!((format_usd(4_500), format_usd(210), format_usd(12_345_678)), ("$0.0045".to_string(), "$0.0002".to_string(), "$12.3457".to_string()));
+}
+
+#[test]
+fn prices_can_be_overridden_from_the_environment() {
+    std::env::set_var("LLM_PRICES", "llama3=0.5:1, gpt-4o-mini = 1:2");
+    let prices = PriceTable::from_env().unwrap();
+    assert_eq!(prices.cost_micros("llama3:8b", USAGE), 600);
+    assert_eq!(prices.cost_micros("gpt-4o-mini", USAGE), 1_200);
+    assert_eq!(prices.cost_micros("claude-sonnet-4-20250514", USAGE), 4_500);
+
+    for invalid in ["llama3", "llama3=0.5", "llama3=cheap:1"] {
+        std::env::set_var("LLM_PRICES", invalid);
+        assert!(PriceTable::from_env().is_err(), "{}", invalid);
+    }
+    std::env::remove_var("LLM_PRICES");
+}
+
+async fn solve(h: &Harness, bet_id: i64, evidence: &str) -> String {
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, evidence);
+    handle_solve(h.messenger(), msg, h.ctx.clone()).await.unwrap();
+    h.last_reply()
+}
+
+async fn cost(h: &Harness, args: &str) -> String {
+    let msg = group_message(ALICE, "alice", &format!("/cost {}", args));
+    handle_cost(h.messenger(), msg, h.ctx.clone(), args.to_string()).await.unwrap();
+    h.last_reply()
+}
+
+/// A harness resolving with gpt-4o-mini on a model server answering "not
+/// resolved" up to five times, each for 1000 input and 100 output tokens.
+async fn billed_harness() -> (Harness, Llm) {
+    let (url, llm) = llm_server().await;
+    for _ in 0..5 {
+        llm.reply(StatusCode::OK, completion(&verdict_text(false, false)));
+    }
+    let h = Harness::with_resolver(Some(Arc::new(openai(&url)))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    (h, llm)
+}
+
+#[tokio::test]
+async fn evaluations_are_billed_to_their_chat() {
+    let (h, llm) = billed_harness().await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    assert!(solve(&h, bet_id, "Clouds are forming").await.starts_with("❌ NOT RESOLVED"));
+    // Served from the cache, so not billed again
+    solve(&h, bet_id, "Clouds are forming").await;
+    assert_eq!(llm.request_count(), 1);
+
+    let usage = h.ctx.db.get_llm_usage(CHAT_ID, &current_month()).await.unwrap();
+    assert_eq!((usage.evaluations, usage.input_tokens, usage.output_tokens, usage.cost_micros), (1, 1000, 100, 210));
+    assert_eq!(h.ctx.db.get_llm_usage(CHAT_ID + 1, &current_month()).await.unwrap().evaluations, 0);
+}
+
+#[tokio::test]
+async fn chats_over_their_budget_fall_back_to_admin_resolution() {
+    let (h, llm) = billed_harness().await;
+    h.ctx.db.set_chat_budget(CHAT_ID, Some(210)).await.unwrap();
+    let first = h.open_bet(ALICE, "Will it rain?").await;
+    let second = h.open_bet(ALICE, "Will it snow?").await;
+
+    // Under budget until this evaluation reaches it
+    assert!(solve(&h, first, "Clouds are forming").await.starts_with("❌ NOT RESOLVED"));
+    assert_eq!(
+        solve(&h, second, "Snowflakes!").await,
+        format!("💸 This chat has used its monthly evaluation budget ($0.0002).\nAn admin can settle the bet with /resolve {} <yes/no>.", second)
+    );
+    assert_eq!(llm.request_count(), 1);
+    assert_eq!(h.ctx.db.get_bet_by_id(second).await.unwrap().unwrap().status, "open");
+
+    // Raising the budget lets evaluations through again
+    h.ctx.db.set_chat_budget(CHAT_ID, Some(1_000)).await.unwrap();
+    assert!(solve(&h, second, "Snowflakes!").await.starts_with("❌ NOT RESOLVED"));
+    assert_eq!(llm.request_count(), 2);
+}
+
+#[tokio::test]
+async fn last_months_spend_does_not_count_against_the_budget() {
+    let (h, llm) = billed_harness().await;
+    h.ctx.db.set_chat_budget(CHAT_ID, Some(210)).await.unwrap();
+    h.ctx.db.record_llm_usage(CHAT_ID, "2000-01", 1_000_000, 100_000, 1_000_000).await.unwrap();
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    assert!(solve(&h, bet_id, "Clouds are forming").await.starts_with("❌ NOT RESOLVED"));
+    assert_eq!(llm.request_count(), 1);
+}
+
+#[tokio::test]
+async fn cost_reports_the_months_spend_to_admins() {
+    let (h, _llm) = billed_harness().await;
+    assert_eq!(cost(&h, "").await, "Only admins can use the /cost command in group chats.");
+
+    h.make_admin(ALICE);
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    solve(&h, bet_id, "Clouds are forming").await;
+    assert_eq!(cost(&h, "budget 0.01").await, "Monthly evaluation budget set to $0.0100.");
+    assert_eq!(h.ctx.db.get_chat_budget(CHAT_ID).await.unwrap(), Some(10_000));
+    assert_eq!(
+        cost(&h, "").await,
+        format!(
+            "💸 Evaluation spend for {}\n\n🧮 Evaluations: 1\n🔤 Tokens: 1000 in / 100 out\n💰 Spend: $0.0002\n📏 Budget: $0.0100 ($0.0098 left)",
+            current_month()
+        )
+    );
+
+    assert_eq!(cost(&h, "budget -1").await, "Budget must be a positive dollar amount, e.g. /cost budget 5");
+    assert_eq!(cost(&h, "budget off").await, "Monthly evaluation budget removed.");
+    assert!(cost(&h, "").await.ends_with("📏 Budget: none"));
+}
+
+// --------------------------------------------------------
+//     Deadlines
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn solving_past_the_deadline_skips_the_model_unless_forced() {
+    let (h, llm) = billed_harness().await;
+    let deadline = "2026-03-01T12:00:00+00:00";
+    let bet_id = h.ctx.db.create_bet(ALICE, CHAT_ID, "Will it snow?".to_string(), Some(deadline.to_string())).await.unwrap();
+
+    assert_eq!(
+        solve(&h, bet_id, "Snowflakes!").await,
+        format!(
+            "⏰ The deadline of bet #{} has passed without an accepted solution.\nAn admin can settle it as NO with /expire {}, or force an evaluation with /solve {} force.",
+            bet_id, bet_id, bet_id
+        )
+    );
+    assert_eq!(llm.request_count(), 0);
+
+    h.make_admin(ALICE);
+    let forced = group_reply(ALICE, "alice", &format!("/solve {} force", bet_id), BOB, "Snowflakes!");
+    handle_solve(h.messenger(), forced, h.ctx.clone()).await.unwrap();
+    assert!(h.last_reply().starts_with("❌ NOT RESOLVED"), "{}", h.last_reply());
+
+    // The model is told the deadline and today's date to rule on it
+    let body = llm.requests.lock().unwrap()[0].2.clone();
+    let prompt = body["messages"][0]["content"].as_str().unwrap();
+    let bet = h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap();
+    assert_eq!(prompt_line(prompt, "DEADLINE:"), format!("DEADLINE: {}", deadline));
+    assert_eq!(prompt_line(prompt, "CREATED AT:"), format!("CREATED AT: {}", bet.created_at));
+    let now = prompt_line(prompt, "CURRENT DATE (UTC):").trim_start_matches("CURRENT DATE (UTC): ");
+    let now = DateTime::parse_from_rfc3339(now).unwrap();
+    assert!((Utc::now() - now.with_timezone(&Utc)).num_seconds().abs() < 60, "{}", now);
+}
+
+#[tokio::test]
+async fn bets_before_their_deadline_are_evaluated() {
+    let (h, llm) = billed_harness().await;
+    let bet_id = h.ctx.db.create_bet(ALICE, CHAT_ID, "Will it snow?".to_string(), Some("2999-01-01T00:00:00+00:00".to_string())).await.unwrap();
+
+    assert!(solve(&h, bet_id, "Snowflakes!").await.starts_with("❌ NOT RESOLVED"));
+    assert_eq!(llm.request_count(), 1);
+}
+
+// --------------------------------------------------------
+//     Evidence transcripts
+// --------------------------------------------------------
+
+/// The transcript lines of `prompt`.
+fn transcript(prompt: &str) -> Vec<&str> {
+    prompt
+        .lines()
+        .skip_while(|line| !line.starts_with("=== MESSAGES TO EVALUATE"))
+        .skip(1)
+        .take_while(|line| *line != "=== END MESSAGES ===")
+        .collect()
+}
+
+/// A message by `author` `minutes` after the first of the thread.
+fn evidence(author: &str, minutes: u32, text: &str) -> EvidenceMessage {
+    EvidenceMessage {
+        author: author.to_string(),
+        timestamp: format!("2025-01-02T10:{:02}:00+00:00", minutes),
+        text: text.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn solve_sends_the_authors_preceding_messages_as_a_transcript() {
+    let (h, llm) = billed_harness().await;
+    h.ctx.db.set_message_buffer(CHAT_ID, true).await.unwrap();
+    let bet_id = h.open_bet(ALICE, "Will the author finish the marathon?").await;
+    // Sent before the replied message (id 99); only the author's count as evidence
+    for (id, from, username, text) in [
+        (10, BOB, "author", "Signed up for the marathon"),
+        (11, BOB, "author", "Starting line, wish me luck"),
+        (12, ALICE, "alice", "Go go go"),
+        (13, BOB, "author", "Crossed the finish line in 4:12"),
+    ] {
+        let mut msg = group_message(from, username, text);
+        msg.id = MessageId(id);
+        msg.date = DateTime::from_timestamp(1_699_999_000 + id as i64, 0).unwrap();
+        remember_group_message(&h.ctx, &msg).await.unwrap();
+    }
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {} 2", bet_id), BOB, "Photo of my medal");
+    handle_solve(h.messenger(), msg, h.ctx.clone()).await.unwrap();
+
+    let body = llm.requests.lock().unwrap()[0].2.clone();
+    assert_eq!(
+        transcript(body["messages"][0]["content"].as_str().unwrap()),
+        [
+            r#"[2023-11-14T21:56:51+00:00] @author: "Starting line, wish me luck""#,
+            r#"[2023-11-14T21:56:53+00:00] @author: "Crossed the finish line in 4:12""#,
+            r#"[2023-11-14T22:13:20+00:00] @author: "Photo of my medal""#,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn long_messages_are_cut_to_their_budget() {
+    // 300 tokens of about 4 characters
+    let ctx = ResolutionContext {
+        evidence: vec![evidence("john", 0, &"hello ".repeat(300)), evidence("john", 1, "hello")],
+        ..context(1, "hello")
+    };
+    let prompt = sent_prompt(ctx).await;
+    assert_eq!(
+        transcript(&prompt),
+        [
+            format!(r#"[2025-01-02T10:00:00+00:00] @john: "{} [truncated]""#, "hello ".repeat(200).trim_end()),
+            r#"[2025-01-02T10:01:00+00:00] @john: "hello""#.to_string(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn oldest_messages_are_dropped_past_the_transcript_budget() {
+    let texts: Vec<String> = (0..10).map(|i| format!("{} {}", i, "x".repeat(1000))).collect();
+    let ctx = ResolutionContext {
+        evidence: texts.iter().enumerate().map(|(i, text)| evidence("john", i as u32, text)).collect(),
+        ..context(1, "hello")
+    };
+    let prompt = sent_prompt(ctx).await;
+    let lines = transcript(&prompt);
+
+    // Lines of about 1040 characters: five fit in the 1500 tokens
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "(5 earlier message(s) omitted)");
+    for (line, text) in lines[1..].iter().zip(&texts[5..]) {
+        assert!(line.ends_with(&format!("\"{}\"", text)), "{}", line);
+    }
+}
+
+#[tokio::test]
+async fn earlier_messages_are_part_of_the_cache_key() {
+    let (url, llm) = llm_server().await;
+    llm.reply(StatusCode::OK, completion(&verdict_text(false, false)));
+    llm.reply(StatusCode::OK, completion(&verdict_text(true, true)));
+    let (resolver, cache) = (openai(&url), cache(Duration::from_secs(3600)).await);
+
+    let thread = |first: &str| ResolutionContext {
+        evidence: vec![evidence("john", 0, first), evidence("john", 1, "hello")],
+        ..context(1, "hello")
+    };
+    evaluate_bet_resolution(&resolver, &cache, context(1, "hello"), false).await.unwrap();
+    let resolution = evaluate_bet_resolution(&resolver, &cache, thread("Watch this"), false).await.unwrap();
+    assert!(resolution.resolved);
+    evaluate_bet_resolution(&resolver, &cache, thread("Watch this"), false).await.unwrap();
+    assert_eq!(llm.request_count(), 2);
+}
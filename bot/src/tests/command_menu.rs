@@ -0,0 +1,92 @@
+use teloxide::types::Recipient;
+
+use super::*;
+use crate::command_menu::{public_commands, registrations, OPERATOR_COMMANDS};
+use crate::{handle_commands, handle_reset};
+
+fn names(commands: &[BotCommand]) -> Vec<&str> {
+    commands.iter().map(|command| command.command.as_str()).collect()
+}
+
+fn operator_scope(operator: i64) -> BotCommandScope {
+    BotCommandScope::Chat { chat_id: Recipient::Id(ChatId(operator)) }
+}
+
+#[test]
+fn operator_commands_only_show_in_the_operators_private_chats() {
+    let menus = registrations(&HashSet::from([OPERATOR, 5]));
+    let scopes: Vec<_> = menus.iter().map(|(scope, _)| scope.clone()).collect();
+    assert_eq!(scopes, [BotCommandScope::Default, operator_scope(5), operator_scope(OPERATOR)]);
+
+    let public = names(&menus[0].1);
+    assert!(public.contains(&"bet") && public.contains(&"help"), "{:?}", public);
+    for (operator_command, _) in OPERATOR_COMMANDS {
+        assert!(!public.contains(&operator_command), "{} is public", operator_command);
+        assert!(names(&menus[2].1).contains(&operator_command));
+    }
+    // Telegram shows the most specific menu only
+    assert_eq!(menus[1].1[..public.len()], menus[0].1[..]);
+    // Nor do hidden commands other than the operators' show
+    assert!(!public.contains(&"start"));
+    // Resetting wipes every chat, so it is an operator's alone
+    assert!(!public.contains(&"reset") && names(&menus[2].1).contains(&"reset"));
+
+    assert_eq!(registrations(&HashSet::new()).len(), 1);
+}
+
+#[test]
+fn every_menu_entry_is_valid_for_telegram() {
+    let (_, menu) = registrations(&HashSet::from([OPERATOR])).pop().unwrap();
+    let mut seen = HashSet::new();
+    for command in &menu {
+        assert!((1..=32).contains(&command.command.len()), "{:?}", command);
+        assert!(
+            command.command.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+            "{:?}",
+            command
+        );
+        assert!((3..=256).contains(&command.description.chars().count()), "{:?}", command);
+        assert!(seen.insert(&command.command), "{} listed twice", command.command);
+    }
+    assert!(menu.len() <= 100);
+}
+
+#[tokio::test]
+async fn operators_refresh_the_menu() {
+    let h = Harness::new().await;
+    let refresh = |from: i64| handle_commands(h.messenger(), private_message(from, "op", "/commands refresh"), h.ctx.clone(), "refresh".to_string());
+
+    refresh(ALICE).await.unwrap();
+    assert_eq!(h.last_reply(), "⛔ This command is reserved for bot operators.");
+    assert!(h.registered_commands().is_empty());
+
+    refresh(OPERATOR).await.unwrap();
+    assert_eq!(
+        h.last_reply(),
+        format!(
+            "✅ Command menu registered: {} public commands, plus the operator commands for 1 operator(s).",
+            public_commands().len()
+        )
+    );
+    assert_eq!(h.registered_commands(), registrations(&h.ctx.operators));
+
+    let msg = private_message(OPERATOR, "op", "/commands");
+    handle_commands(h.messenger(), msg, h.ctx.clone(), String::new()).await.unwrap();
+    assert!(h.last_reply().contains("Usage: /commands refresh"), "{}", h.last_reply());
+    assert_eq!(h.registered_commands().len(), 2);
+}
+
+#[tokio::test]
+async fn only_operators_reset_the_database() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+
+    // Everyone is an admin of their own private chat
+    handle_reset(h.messenger(), private_message(ALICE, "alice", "/reset"), h.ctx.clone()).await.unwrap();
+    assert_eq!(h.last_reply(), "⛔ This command is reserved for bot operators.");
+    assert!(h.ctx.db.get_user(ALICE).await.unwrap().is_some());
+
+    handle_reset(h.messenger(), private_message(OPERATOR, "op", "/reset"), h.ctx.clone()).await.unwrap();
+    assert!(h.last_reply().starts_with("⚠️ Database has been reset!"), "{}", h.last_reply());
+    assert!(h.ctx.db.get_user(ALICE).await.unwrap().is_none());
+}
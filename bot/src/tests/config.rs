@@ -0,0 +1,101 @@
+use contract1::api::{ContractFeatures, ContractParams};
+
+use super::*;
+use crate::currency::group_thousands;
+use crate::{handle_bet, handle_init};
+
+fn params(initial_balance: u128, min_bet: u128, max_bet: Option<u128>) -> ContractParams {
+    ContractParams {
+        initial_balance,
+        min_bet,
+        max_bet,
+        ..ContractParams::default()
+    }
+}
+
+#[test]
+fn config_from_a_version_1_server_falls_back_to_the_contract_constants() {
+    let config: ConfigResponse = serde_json::from_str(r#"{"contract_name":"contract1"}"#).unwrap();
+
+    assert_eq!(config.contract_name, "contract1");
+    assert_eq!(config.api_version, 1);
+    assert_eq!(config.params, ContractParams::default());
+    assert_eq!(config.params.initial_balance, 10_000);
+}
+
+#[test]
+fn config_reads_the_published_params() {
+    let body = r#"{
+        "contract_name": "contract1",
+        "api_version": 2,
+        "params": {
+            "initial_balance": 5000,
+            "min_bet": 10,
+            "max_bet": 1000,
+            "creation_fee": 25,
+            "fee_bps": 150,
+            "features": {"deadlines": true, "multi_outcome": false, "amm": false}
+        }
+    }"#;
+    let config: ConfigResponse = serde_json::from_str(body).unwrap();
+
+    assert_eq!(config.api_version, 2);
+    assert_eq!(
+        config.params,
+        ContractParams {
+            initial_balance: 5000,
+            min_bet: 10,
+            max_bet: Some(1000),
+            creation_fee: 25,
+            fee_bps: 150,
+            resolution_cooldown_secs: 600,
+            features: ContractFeatures { deadlines: true, multi_outcome: false, amm: false },
+        }
+    );
+}
+
+#[test]
+fn config_ignores_fields_it_does_not_know() {
+    let body = r#"{
+        "contract_name": "contract1",
+        "api_version": 3,
+        "params": {"min_bet": 5, "features": {"amm": true, "orderbook": true}},
+        "motd": "hi"
+    }"#;
+    let config: ConfigResponse = serde_json::from_str(body).unwrap();
+
+    assert_eq!(config.params.min_bet, 5);
+    assert!(config.params.features.amm);
+    assert_eq!(config.params.initial_balance, 10_000);
+}
+
+#[test]
+fn amounts_are_grouped_by_thousands() {
+    assert_eq!(group_thousands(0), "0");
+    assert_eq!(group_thousands(999), "999");
+    assert_eq!(group_thousands(10_000), "10,000");
+    assert_eq!(group_thousands(1_234_567), "1,234,567");
+}
+
+#[tokio::test]
+async fn init_uses_the_published_initial_balance() {
+    let h = Harness::with_params(params(5_000, 1, None)).await;
+    handle_init(h.messenger(), group_message(ALICE, "alice", "/init"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.last_reply(), "✅ Your balance has been initialized to 🪙 5,000 coins on-chain.\nTransaction: tx1");
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 5_000);
+}
+
+#[tokio::test]
+async fn bets_outside_the_published_limits_are_refused() {
+    let h = Harness::with_params(params(10_000, 50, Some(1_000))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    for (amount, reply) in [(10, "The minimum bet is 🪙 50 coins."), (2_000, "The maximum bet is 🪙 1,000 coins.")] {
+        let args = format!("{} yes {}", bet_id, amount);
+        handle_bet(h.messenger(), group_message(ALICE, "alice", "/bet"), h.ctx.clone(), args).await.unwrap();
+        assert_eq!(h.last_reply(), reply);
+    }
+    assert!(h.api.calls().is_empty());
+}
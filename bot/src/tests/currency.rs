@@ -0,0 +1,85 @@
+use super::*;
+use crate::currency::{format_amount, format_signed, Currency};
+use crate::{handle_bet, handle_currency, handle_treasury};
+
+fn aura() -> Currency {
+    Currency { name: "aura".to_string(), emoji: "💎".to_string() }
+}
+
+async fn currency(h: &Harness, from: i64, args: &str) {
+    handle_currency(h.messenger(), group_message(from, "user", "/currency"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+}
+
+#[test]
+fn amounts_carry_the_chat_currency() {
+    let coins = Currency::default();
+    assert_eq!(format_amount(&coins, 0), "🪙 0 coins");
+    assert_eq!(format_amount(&coins, 1), "🪙 1 coins");
+    assert_eq!(format_amount(&aura(), 12_500), "💎 12,500 aura");
+    assert_eq!(
+        format_amount(&coins, u128::MAX),
+        "🪙 340,282,366,920,938,463,463,374,607,431,768,211,455 coins"
+    );
+
+    let plain = Currency { name: "pts".to_string(), emoji: String::new() };
+    assert_eq!(format_amount(&plain, 1_000), "1,000 pts");
+    assert_eq!(format_signed(&plain, -1_200), "-1,200 pts");
+    assert_eq!(format_signed(&plain, 0), "+0 pts");
+    assert_eq!(format_signed(&plain, i64::MIN), "-9,223,372,036,854,775,808 pts");
+}
+
+#[test]
+fn currency_arguments() {
+    assert_eq!(Currency::parse("💎 aura"), Some(aura()));
+    assert_eq!(Currency::parse("aura points"), Some(Currency { name: "aura points".to_string(), emoji: String::new() }));
+    assert_eq!(Currency::parse("💎"), Some(Currency { name: "💎".to_string(), emoji: String::new() }));
+    assert_eq!(Currency::parse(""), None);
+    assert_eq!(Currency::parse(&"x".repeat(25)), None);
+}
+
+#[tokio::test]
+async fn custom_currency_shows_in_the_chat_replies() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    h.initialized_user(ALICE, "alice", 50).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    currency(&h, ALICE, "💎 aura").await;
+    assert_eq!(h.last_reply(), "✅ Amounts in this chat now read like 💎 1,000 aura.");
+
+    handle_bet(h.messenger(), group_message(ALICE, "alice", "/bet"), h.ctx.clone(), format!("{} yes 100", bet_id))
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), "Insufficient balance. You have 💎 50 aura but tried to bet 💎 100 aura.");
+
+    h.api.set_treasury(1_250);
+    handle_treasury(h.messenger(), group_message(BOB, "bob", "/treasury"), h.ctx.clone()).await.unwrap();
+    assert!(h.last_reply().contains("Balance: 💎 1,250 aura"), "{}", h.last_reply());
+
+    // Other chats keep their own currency
+    assert_eq!(h.ctx.db.get_currency(-1002).await.unwrap(), Currency::default());
+
+    currency(&h, ALICE, "reset").await;
+    assert_eq!(h.ctx.db.get_currency(CHAT_ID).await.unwrap(), Currency::default());
+}
+
+#[tokio::test]
+async fn currency_is_an_admin_setting() {
+    let h = Harness::new().await;
+
+    currency(&h, BOB, "💎 aura").await;
+    assert_eq!(h.last_reply(), "Only admins can use the /currency command in group chats.");
+    assert_eq!(h.ctx.db.get_currency(CHAT_ID).await.unwrap(), Currency::default());
+
+    h.make_admin(BOB);
+    currency(&h, BOB, "").await;
+    assert_eq!(
+        h.last_reply(),
+        "Usage: /currency [emoji] <name>|reset (currently 🪙 100 coins, names up to 24 characters)"
+    );
+    currency(&h, BOB, &"x".repeat(30)).await;
+    assert_eq!(h.last_reply(), "Currency names are 1 to 24 characters on one line.");
+    assert_eq!(h.ctx.db.get_currency(CHAT_ID).await.unwrap(), Currency::default());
+}
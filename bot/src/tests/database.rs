@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+
+use super::*;
+use crate::db::{Database, DatabaseConfig};
+
+/// A database file of its own: `:memory:` would give every pooled
+/// connection a separate database, hiding the locking this tests.
+struct FileDatabase {
+    db: Arc<Database>,
+    path: PathBuf,
+}
+
+impl FileDatabase {
+    async fn open(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("bot-{}-{}.sqlite", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = Database::with_config(&format!("sqlite://{}", path.display()), DatabaseConfig::default())
+            .await
+            .unwrap();
+        db.init().await.unwrap();
+        Self { db: Arc::new(db), path }
+    }
+
+    /// A connection of its own, for what the bot never does itself.
+    async fn raw(&self) -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(&format!("sqlite://{}", self.path.display()))
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for FileDatabase {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.path.display(), suffix));
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_wagers_never_hit_a_locked_database() {
+    let file = FileDatabase::open("concurrent-wagers").await;
+    let db = &file.db;
+    for user_id in 0..10 {
+        db.create_or_update_user(user_id, None, 1_000).await.unwrap();
+    }
+    let bet_id = db.create_bet(0, CHAT_ID, "Will it rain?".to_string(), None).await.unwrap();
+
+    let inserts: Vec<_> = (0..100)
+        .map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move { db.create_wager(bet_id, i % 10, i + 1, i % 2 == 0).await })
+        })
+        .collect();
+    for insert in inserts {
+        insert.await.unwrap().unwrap_or_else(|e| panic!("wager insert failed: {}", e));
+    }
+
+    // Every insert landed exactly once
+    let wagers = db.get_wagers_for_bet(bet_id).await.unwrap();
+    assert_eq!(wagers.len(), 100);
+    assert_eq!(wagers.iter().map(|w| w.amount).sum::<i64>(), (1..=100).sum::<i64>());
+}
+
+#[tokio::test]
+async fn foreign_keys_are_enforced() {
+    let file = FileDatabase::open("foreign-keys").await;
+    let db = &file.db;
+    db.create_or_update_user(ALICE, None, 1_000).await.unwrap();
+    let bet_id = db.create_bet(ALICE, CHAT_ID, "Will it rain?".to_string(), None).await.unwrap();
+
+    assert!(db.create_wager(bet_id + 1, ALICE, 100, true).await.is_err());
+    assert!(db.create_wager(bet_id, BOB, 100, true).await.is_err());
+    db.create_wager(bet_id, ALICE, 100, true).await.unwrap();
+    assert_eq!(db.get_wagers_for_bet(bet_id).await.unwrap().len(), 1);
+}
+
+/// Seeds `count` rows into each of bets and wagers, spread over 100 chats
+/// and 5 000 users.
+async fn seed(raw: &SqlitePool, count: i64) {
+    let rows = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < ?1)";
+    for statement in [
+        format!(
+            "{} INSERT INTO users (user_id, username, balance, created_at) SELECT i, 'user' || i, i * 7 % 10000, '' FROM n WHERE i <= 5000",
+            rows
+        ),
+        format!(
+            "{} INSERT INTO bets (bet_id, creator_id, chat_id, description, created_at, status) \
+             SELECT i, i % 5000 + 1, -(i % 100), 'Bet ' || i, '', CASE WHEN i % 10 = 0 THEN 'open' ELSE 'resolved_yes' END FROM n",
+            rows
+        ),
+        format!(
+            "{} INSERT INTO wagers (bet_id, user_id, amount, side, created_at) SELECT i, i % 5000 + 1, 10, i % 2, '' FROM n",
+            rows
+        ),
+    ] {
+        sqlx::query(&statement).bind(count).execute(raw).await.unwrap();
+    }
+}
+
+/// Total time of `rounds` runs of each hot read path.
+async fn time_hot_paths(db: &Database, rounds: u32) -> Duration {
+    let started = Instant::now();
+    for round in 0..rounds as i64 {
+        let bet_id = round * 997 % 50_000 + 1;
+        assert_eq!(db.get_wagers_for_bet(bet_id).await.unwrap().len(), 1);
+        db.get_open_exposure(bet_id % 5000 + 1).await.unwrap();
+        assert_eq!(db.get_leaderboard(10).await.unwrap().len(), 10);
+        assert_eq!(db.get_recent_bets(-(round % 100), 20).await.unwrap().len(), 20);
+    }
+    started.elapsed()
+}
+
+/// A micro-benchmark rather than a test of correctness: the hot read paths
+/// over 50 000 bets and wagers, with the indexes and without them. Timings
+/// are printed (run with `--nocapture`); only the query plans and a
+/// generous comparison are asserted, so a busy machine cannot fail it.
+#[tokio::test]
+async fn indexes_speed_up_the_hot_read_paths() {
+    let file = FileDatabase::open("hot-paths").await;
+    let raw = file.raw().await;
+    seed(&raw, 50_000).await;
+    sqlx::query("ANALYZE").execute(&raw).await.unwrap();
+
+    for (query, index) in [
+        ("SELECT * FROM wagers WHERE bet_id = 1", "idx_wagers_bet"),
+        ("SELECT * FROM wagers WHERE user_id = 1", "idx_wagers_user"),
+        ("SELECT user_id FROM users ORDER BY balance DESC LIMIT 10", "idx_users_balance"),
+    ] {
+        let plan: Vec<String> = sqlx::query_as::<_, (i64, i64, i64, String)>(&format!("EXPLAIN QUERY PLAN {}", query))
+            .fetch_all(&raw)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, _, _, detail)| detail)
+            .collect();
+        assert!(plan.iter().any(|step| step.contains(index)), "{}: {:?}", query, plan);
+    }
+
+    let indexed = time_hot_paths(&file.db, 50).await;
+    for index in ["idx_bets_status_chat", "idx_wagers_user", "idx_wagers_bet", "idx_users_balance"] {
+        sqlx::query(&format!("DROP INDEX {}", index)).execute(&raw).await.unwrap();
+    }
+    let scanned = time_hot_paths(&file.db, 50).await;
+
+    println!("hot read paths over 50 000 rows: {:?} with indexes, {:?} without", indexed, scanned);
+    assert!(indexed < scanned, "{:?} with indexes, {:?} without", indexed, scanned);
+}
@@ -0,0 +1,355 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::*;
+use crate::api_client::MarketApiError;
+use crate::db::DeadlineStage;
+use crate::deadlines::{handle_deadlines, void_forgotten};
+use crate::{handle_auto_expire, handle_expire};
+use crate::webhook::OwnAction;
+
+fn at(rfc3339: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+}
+
+const DEADLINE: &str = "2026-03-01T12:00:00+00:00";
+
+async fn bet_with_deadline(h: &Harness) -> i64 {
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.ctx
+        .db
+        .create_bet(ALICE, CHAT_ID, "Will it snow?".to_string(), Some(DEADLINE.to_string()))
+        .await
+        .unwrap()
+}
+
+async fn run(h: &Harness, now: DateTime<Utc>) {
+    handle_deadlines(&h.messenger(), &h.ctx, now).await.unwrap();
+}
+
+async fn stage(h: &Harness, bet_id: i64) -> Option<DeadlineStage> {
+    h.ctx
+        .db
+        .get_unhandled_deadlines()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|bet| bet.bet_id == bet_id)
+        .map(|bet| bet.stage())
+}
+
+#[tokio::test]
+async fn nothing_happens_before_the_deadline() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+
+    run(&h, at(DEADLINE) - Duration::minutes(1)).await;
+
+    assert!(h.api.calls().is_empty());
+    assert!(h.replies().is_empty());
+    assert_eq!(stage(&h, bet_id).await, Some(DeadlineStage::Pending));
+}
+
+#[tokio::test]
+async fn bets_without_deadline_are_ignored() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.open_bet(ALICE, "Will it rain?").await;
+
+    run(&h, at(DEADLINE) + Duration::days(30)).await;
+
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn betting_closes_once_at_the_deadline() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+
+    run(&h, at(DEADLINE) + Duration::seconds(30)).await;
+
+    assert_eq!(h.api.calls(), vec![format!("close {} #{}", ALICE, bet_id)]);
+    assert_eq!(h.replies(), vec![format!("⏰ Betting closed on #{}: Will it snow?", bet_id)]);
+    assert_eq!(stage(&h, bet_id).await, Some(DeadlineStage::BettingClosed));
+
+    // Later runs within the grace period have nothing left to do
+    run(&h, at(DEADLINE) + Duration::minutes(1)).await;
+    run(&h, at(DEADLINE) + Duration::hours(23)).await;
+    assert_eq!(h.api.calls().len(), 1);
+    assert_eq!(h.replies().len(), 1);
+}
+
+#[tokio::test]
+async fn failed_close_is_retried_on_the_next_run() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    h.api.fail_next(MarketApiError::Timeout);
+
+    run(&h, at(DEADLINE)).await;
+    assert!(h.replies().is_empty());
+    assert_eq!(stage(&h, bet_id).await, Some(DeadlineStage::Pending));
+
+    run(&h, at(DEADLINE) + Duration::minutes(1)).await;
+    assert_eq!(h.api.calls().len(), 2);
+    assert_eq!(h.replies(), vec![format!("⏰ Betting closed on #{}: Will it snow?", bet_id)]);
+}
+
+#[tokio::test]
+async fn rejected_close_still_moves_on() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    h.api.fail_next(MarketApiError::ContractRejected { message: "Market is not open".to_string() });
+
+    run(&h, at(DEADLINE)).await;
+
+    assert_eq!(stage(&h, bet_id).await, Some(DeadlineStage::BettingClosed));
+    run(&h, at(DEADLINE) + Duration::minutes(1)).await;
+    assert_eq!(h.api.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn chat_is_prompted_after_the_grace_period() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    run(&h, at(DEADLINE)).await;
+
+    run(&h, at(DEADLINE) + Duration::hours(24)).await;
+
+    let prompt = h.last_reply();
+    assert!(prompt.contains(&format!("Bet #{} passed its deadline yesterday at 12:00 without", bet_id)), "{}", prompt);
+    assert!(prompt.contains(&format!("/expire {}", bet_id)), "{}", prompt);
+    // Only the close went on-chain, and the bet is left open for the chat
+    assert_eq!(h.api.calls().len(), 1);
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+    assert_eq!(stage(&h, bet_id).await, None);
+
+    run(&h, at(DEADLINE) + Duration::days(3)).await;
+    assert_eq!(h.replies().len(), 2);
+}
+
+#[tokio::test]
+async fn auto_expire_resolves_no_after_the_grace_period() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    h.ctx.db.set_auto_expire(CHAT_ID, true).await.unwrap();
+    run(&h, at(DEADLINE)).await;
+
+    run(&h, at(DEADLINE) + Duration::hours(25)).await;
+
+    assert_eq!(
+        h.api.calls(),
+        vec![format!("close {} #{}", ALICE, bet_id), format!("resolve {} #{} no", ALICE, bet_id)]
+    );
+    assert!(h.last_reply().starts_with("✅ MARKET EXPIRED"), "{}", h.last_reply());
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "resolved_no");
+    // The webhook must not announce the resolution a second time
+    assert!(h.ctx.own_actions.take(&OwnAction::Resolve { market_id: bet_id as u64 }));
+
+    run(&h, at(DEADLINE) + Duration::days(3)).await;
+    assert_eq!(h.api.calls().len(), 2);
+}
+
+#[tokio::test]
+async fn failed_expiry_is_retried_without_closing_again() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    h.ctx.db.set_auto_expire(CHAT_ID, true).await.unwrap();
+    run(&h, at(DEADLINE)).await;
+
+    h.api.fail_next(MarketApiError::Transport("connection refused".to_string()));
+    run(&h, at(DEADLINE) + Duration::hours(24)).await;
+    assert_eq!(stage(&h, bet_id).await, Some(DeadlineStage::BettingClosed));
+    assert!(!h.ctx.own_actions.take(&OwnAction::Resolve { market_id: bet_id as u64 }));
+
+    run(&h, at(DEADLINE) + Duration::hours(24) + Duration::minutes(1)).await;
+    let calls = h.api.calls();
+    assert_eq!(calls.iter().filter(|call| call.starts_with("close")).count(), 1);
+    assert_eq!(calls.iter().filter(|call| call.starts_with("resolve")).count(), 2);
+    assert_eq!(h.replies().len(), 2);
+}
+
+#[tokio::test]
+async fn restart_resumes_from_the_persisted_stage() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    run(&h, at(DEADLINE)).await;
+
+    // A new process shares nothing with the previous one but the database
+    let restarted = Harness::new().await;
+    let ctx = Arc::new(BotContext {
+        db: h.ctx.db.clone(),
+        api_client: restarted.api.clone(),
+        contract_name: h.ctx.contract_name.clone(),
+        retention: RetentionPolicy::default(),
+        deadlines: DeadlineConfig::default(),
+        resolution_cache: ResolutionCache::new(h.ctx.db.clone(), Duration::hours(1).to_std().unwrap()),
+        resolver: None,
+        prices: PriceTable::default(),
+        recent_messages: RecentMessages::new(50),
+        operators: HashSet::new(),
+        own_actions: OwnActions::default(),
+        params: ContractParams::default(),
+        state_epoch: None,
+        pending_solves: PendingSolves::default(),
+        pending_onboardings: PendingOnboardings::default(),
+        pending_polls: PendingPolls::default(),
+        membership: MembershipCache::default(),
+        announcement_edits: AnnouncementEdits::default(),
+        recent_commands: RecentCommands::default(),
+        bot_username: None,
+    });
+    let messenger = restarted.messenger();
+
+    handle_deadlines(&messenger, &ctx, at(DEADLINE) + Duration::minutes(5)).await.unwrap();
+    assert!(restarted.api.calls().is_empty());
+    assert!(restarted.replies().is_empty());
+
+    handle_deadlines(&messenger, &ctx, at(DEADLINE) + Duration::hours(24)).await.unwrap();
+    assert!(restarted.api.calls().is_empty());
+    assert!(restarted.last_reply().contains(&format!("Bet #{} passed its deadline", bet_id)));
+}
+
+#[tokio::test]
+async fn downtime_past_the_grace_period_catches_up_in_one_run() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    h.ctx.db.set_auto_expire(CHAT_ID, true).await.unwrap();
+
+    run(&h, at(DEADLINE) + Duration::days(2)).await;
+
+    assert_eq!(
+        h.api.calls(),
+        vec![format!("close {} #{}", ALICE, bet_id), format!("resolve {} #{} no", ALICE, bet_id)]
+    );
+    assert_eq!(h.replies().len(), 2);
+    assert_eq!(stage(&h, bet_id).await, None);
+}
+
+#[tokio::test]
+async fn resolved_bets_are_left_alone() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    h.ctx.db.close_bet(bet_id, true).await.unwrap();
+
+    run(&h, at(DEADLINE) + Duration::days(2)).await;
+
+    assert!(h.api.calls().is_empty());
+    assert!(h.replies().is_empty());
+}
+
+#[tokio::test]
+async fn auto_expire_is_an_admin_setting() {
+    let h = Harness::new().await;
+    handle_auto_expire(h.messenger(), group_message(BOB, "bob", "/autoexpire on"), h.ctx.clone(), "on".to_string())
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), "Only admins can use the /autoexpire command in group chats.");
+    assert!(!h.ctx.db.get_auto_expire(CHAT_ID).await.unwrap());
+
+    h.make_admin(BOB);
+    handle_auto_expire(h.messenger(), group_message(BOB, "bob", "/autoexpire on"), h.ctx.clone(), "on".to_string())
+        .await
+        .unwrap();
+    assert!(h.ctx.db.get_auto_expire(CHAT_ID).await.unwrap());
+
+    handle_auto_expire(h.messenger(), group_message(BOB, "bob", "/autoexpire"), h.ctx.clone(), String::new())
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), "Usage: /autoexpire <on/off> (currently on)");
+
+    handle_auto_expire(h.messenger(), group_message(BOB, "bob", "/autoexpire off"), h.ctx.clone(), "off".to_string())
+        .await
+        .unwrap();
+    assert!(!h.ctx.db.get_auto_expire(CHAT_ID).await.unwrap());
+}
+
+/// Alice's open bet with 100 of hers on YES and 50 of Bob's on NO, already
+/// taken out of their balances.
+async fn forgotten_bet(h: &Harness) -> i64 {
+    h.initialized_user(ALICE, "alice", 900).await;
+    h.initialized_user(BOB, "bob", 950).await;
+    let bet_id = h.open_bet(ALICE, "Will anyone remember?").await;
+    h.ctx.db.create_wager(bet_id, ALICE, 100, true).await.unwrap();
+    h.ctx.db.create_wager(bet_id, BOB, 50, false).await.unwrap();
+    bet_id
+}
+
+#[tokio::test]
+async fn forgotten_markets_are_voided_and_refunded() {
+    let h = Harness::new().await;
+    let bet_id = forgotten_bet(&h).await;
+    let lifetime = Duration::seconds(contract1::MAX_MARKET_LIFETIME as i64);
+
+    void_forgotten(&h.messenger(), &h.ctx, Utc::now() + lifetime - Duration::hours(1)).await.unwrap();
+    assert!(h.api.calls().is_empty());
+
+    void_forgotten(&h.messenger(), &h.ctx, Utc::now() + lifetime + Duration::hours(1)).await.unwrap();
+
+    assert_eq!(h.api.calls(), vec![format!("void {} #{}", ALICE, bet_id)]);
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "void");
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 1_000);
+    assert_eq!(h.ctx.db.get_user(BOB).await.unwrap().unwrap().balance, 1_000);
+    assert_eq!(
+        h.last_reply(),
+        format!("⌛ Bet #{} expired after 90 days without a resolution: Will anyone remember?\n💸 Every stake was refunded.\nTransaction: tx1", bet_id)
+    );
+
+    void_forgotten(&h.messenger(), &h.ctx, Utc::now() + lifetime * 2).await.unwrap();
+    assert_eq!(h.api.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn markets_the_contract_refuses_to_void_are_not_retried() {
+    let h = Harness::new().await;
+    let bet_id = forgotten_bet(&h).await;
+    let later = Utc::now() + Duration::seconds(contract1::MAX_MARKET_LIFETIME as i64 + 60);
+    h.api.fail_next(MarketApiError::ContractRejected { message: "Market #1 has no creation time and cannot be expired".to_string() });
+
+    void_forgotten(&h.messenger(), &h.ctx, later).await.unwrap();
+    void_forgotten(&h.messenger(), &h.ctx, later).await.unwrap();
+
+    assert_eq!(h.api.calls().len(), 1);
+    assert!(h.replies().is_empty());
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+    assert_eq!(h.ctx.db.get_user(BOB).await.unwrap().unwrap().balance, 950);
+}
+
+async fn expire(h: &Harness, bet_id: i64) -> String {
+    let msg = group_message(ALICE, "alice", &format!("/expire {}", bet_id));
+    handle_expire(h.messenger(), msg, h.ctx.clone(), bet_id.to_string()).await.unwrap();
+    h.last_reply()
+}
+
+#[tokio::test]
+async fn expire_settles_a_bet_past_its_deadline_as_no() {
+    let h = Harness::new().await;
+    let bet_id = bet_with_deadline(&h).await;
+    assert_eq!(expire(&h, bet_id).await, "Only admins can use the /expire command in group chats.");
+
+    h.make_admin(ALICE);
+    let reply = expire(&h, bet_id).await;
+    assert!(reply.starts_with("✅ MARKET EXPIRED"), "{}", reply);
+    assert!(reply.contains("🎯 Outcome: NO ❌"), "{}", reply);
+    assert_eq!(h.api.calls(), vec![format!("resolve 42 #{} no", bet_id)]);
+    assert_ne!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+
+    assert_eq!(expire(&h, bet_id).await, "This bet is already closed.");
+}
+
+#[tokio::test]
+async fn expire_waits_for_the_deadline() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    let undated = h.open_bet(ALICE, "Will it rain?").await;
+    let future = h
+        .ctx
+        .db
+        .create_bet(ALICE, CHAT_ID, "Will it snow?".to_string(), Some("2999-01-01T00:00:00+00:00".to_string()))
+        .await
+        .unwrap();
+
+    for bet_id in [undated, future] {
+        assert_eq!(expire(&h, bet_id).await, format!("Bet #{} has no deadline or it has not passed yet.", bet_id));
+    }
+    assert!(h.api.calls().is_empty());
+}
@@ -0,0 +1,95 @@
+use super::*;
+use crate::deep_links::{callback_data, parse_callback, parse_start, start_link, start_payload};
+use crate::{handle_callback, handle_start};
+
+async fn start(h: &Harness, from: i64, username: &str, payload: &str) -> String {
+    handle_start(h.messenger(), private_message(from, username, "/start"), h.ctx.clone(), payload.to_string())
+        .await
+        .unwrap();
+    h.last_reply()
+}
+
+/// Bob, with 1,000 coins, and Alice's open bet #1 in the test group.
+async fn setup() -> Harness {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    h.open_bet(ALICE, "Will it snow?").await;
+    h
+}
+
+#[test]
+fn start_payloads_name_a_chat_and_a_bet() {
+    assert_eq!(start_payload(CHAT_ID, 7), "bet_-1001_7");
+    assert_eq!(start_link("market_bot", CHAT_ID, 7), "https://t.me/market_bot?start=bet_-1001_7");
+    assert_eq!(parse_start("bet_-1001_7"), Some((CHAT_ID, 7)));
+    assert_eq!(parse_start(&start_payload(-1_002_345_678_901, 123_456)), Some((-1_002_345_678_901, 123_456)));
+
+    let too_long = format!("bet_-1001_{}", "1".repeat(60));
+    for payload in ["bet_", "bet_-1001", "bet_-1001_", "bet_x_7", "bet_-1001_0", "bet_0_7", "bet_+5_7", "bet_-1001_007", "bet_-1001_7_8", "vote_-1001_7", too_long.as_str()] {
+        assert_eq!(parse_start(payload), None, "{}", payload);
+    }
+
+    assert_eq!(parse_callback(&callback_data(7, false, 500)), Some((7, false, 500)));
+    for data in ["dmbet:7:maybe:500", "dmbet:7:yes", "dmbet:7:yes:500:1", "challenge:accept:7"] {
+        assert_eq!(parse_callback(data), None, "{}", data);
+    }
+}
+
+#[tokio::test]
+async fn members_bet_on_the_group_market_from_their_private_chat() {
+    let h = setup().await;
+
+    let card = start(&h, BOB, "bob", "bet_-1001_1").await;
+    assert!(card.starts_with("📊 Market #1\n📄 Will it snow?\n✅ YES pool: 🪙 0 coins\n❌ NO pool: 🪙 0 coins\n"), "{}", card);
+    let labels: Vec<String> = h.last_buttons().into_iter().map(|(label, _)| label).collect();
+    assert_eq!(labels, vec!["✅ YES 🪙 100 coins", "✅ YES 🪙 500 coins", "❌ NO 🪙 100 coins", "❌ NO 🪙 500 coins"]);
+
+    let (_, data) = h.last_buttons()[3].clone();
+    handle_callback(h.messenger(), private_button_press(BOB, "bob", FIRST_SENT_ID, &data), h.ctx.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(h.api.calls(), vec![format!("bet {} #1 no 500", BOB)]);
+    assert!(h.last_reply().starts_with("💰 Bet placed on-chain!"), "{}", h.last_reply());
+    assert_eq!(h.sent_to(BOB).len(), 2);
+    let wagers = h.ctx.db.get_wagers_for_bet(1).await.unwrap();
+    assert_eq!(wagers.iter().map(|w| (w.user_id, w.amount, w.side)).collect::<Vec<_>>(), vec![(BOB, 500, false)]);
+}
+
+#[tokio::test]
+async fn only_members_of_the_group_can_open_or_use_the_card() {
+    let h = setup().await;
+    h.leave_chat(BOB);
+    let refused = "Sorry, only current members of the group where market #1 was created can take part in it.";
+
+    assert_eq!(start(&h, BOB, "bob", "bet_-1001_1").await, refused);
+    assert!(h.last_buttons().is_empty());
+
+    // Nor do the buttons of a card opened before leaving
+    let data = callback_data(1, true, 100);
+    handle_callback(h.messenger(), private_button_press(BOB, "bob", FIRST_SENT_ID, &data), h.ctx.clone())
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), refused);
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn malformed_and_expired_links_are_explained() {
+    let h = setup().await;
+    let closed = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.close_bet(closed, true).await.unwrap();
+
+    assert!(start(&h, BOB, "bob", "").await.starts_with("👋 Hi!"));
+    let broken = "❓ This link is broken. Open the market again from its announcement in the group.";
+    for payload in ["bet_oops", "bet_-1001_1_extra", "https://t.me/market_bot"] {
+        assert_eq!(start(&h, BOB, "bob", payload).await, broken, "{}", payload);
+    }
+    let gone = "⌛ This link has expired: its market no longer exists.";
+    assert_eq!(start(&h, BOB, "bob", "bet_-1001_9").await, gone);
+    // Bet #1 belongs to another chat than the link says
+    assert_eq!(start(&h, BOB, "bob", "bet_-2002_1").await, gone);
+    assert_eq!(start(&h, BOB, "bob", "bet_-1001_2").await, "⌛ This link has expired: market #2 is closed.");
+    assert!(h.api.calls().is_empty());
+}
@@ -0,0 +1,67 @@
+use super::*;
+use crate::dryrun::{BannerTransport, DryRunApi, BANNER};
+use crate::markdown;
+use crate::{handle_bet, handle_init, handle_new, handle_resolve};
+
+async fn run(h: &Harness, from: i64, username: &str, command: &str, args: &str) {
+    let msg = group_message(from, username, command);
+    let (bot, ctx) = (h.messenger(), h.ctx.clone());
+    match command {
+        "/init" => handle_init(bot, msg, ctx).await,
+        "/new" => handle_new(bot, msg, ctx, args.to_string()).await,
+        "/bet" => handle_bet(bot, msg, ctx, args.to_string()).await,
+        "/resolve" => handle_resolve(bot, msg, ctx, args.to_string()).await,
+        _ => unreachable!("{}", command),
+    }
+    .unwrap();
+}
+
+async fn chain_balance(h: &Harness, user_id: i64) -> u128 {
+    h.ctx.api_client.get_user(user_id.to_string(), "contract1").await.unwrap().balance
+}
+
+#[tokio::test]
+async fn commands_play_a_market_out_against_the_simulated_contract() {
+    let api = Arc::new(DryRunApi::new());
+    let h = Harness::dry_run(api.clone()).await;
+    h.make_admin(ALICE);
+    run(&h, ALICE, "alice", "/init", "").await;
+    run(&h, BOB, "bob", "/init", "").await;
+    assert_eq!(chain_balance(&h, BOB).await, 10_000);
+
+    run(&h, ALICE, "alice", "/new", "Will it snow?").await;
+    run(&h, BOB, "bob", "/bet", "1 yes 500").await;
+    assert!(h.last_reply().starts_with("💰 Bet placed on-chain!"), "{}", h.last_reply());
+    assert_eq!(chain_balance(&h, BOB).await, 9_500);
+
+    // The contract's own rules apply, like the cooldown after a bet
+    run(&h, ALICE, "alice", "/resolve", "1 yes").await;
+    assert!(h.last_reply().starts_with("⏳ Market #1 took a bet moments ago"), "{}", h.last_reply());
+
+    // Closing betting does not lift it
+    h.ctx.api_client.close_betting(ALICE.to_string(), 1, "contract1").await.unwrap();
+    run(&h, ALICE, "alice", "/resolve", "1 yes").await;
+    assert!(h.last_reply().starts_with("⏳ Market #1 took a bet moments ago"), "{}", h.last_reply());
+
+    api.advance(contract1::RESOLUTION_COOLDOWN);
+    run(&h, ALICE, "alice", "/resolve", "1 yes").await;
+    let announcement = h.last_markdown();
+    assert!(announcement.contains("paid out to 1 winner"), "{}", announcement);
+    assert_eq!(chain_balance(&h, BOB).await, 10_000);
+
+    // Six transactions settled under made-up hashes, the refused one is not counted
+    let tx_hash = format!("{:064x}", 6);
+    assert!(announcement.contains(&tx_hash), "{}", announcement);
+    assert_eq!(h.ctx.api_client.tx_status(&tx_hash).await.unwrap().status, TxState::Success);
+}
+
+#[tokio::test]
+async fn every_message_carries_the_banner() {
+    let h = Harness::new().await;
+    let bot = Messenger::new(Arc::new(BannerTransport(h.transport.clone())), h.send_queue.clone());
+
+    bot.send_message(ChatId(CHAT_ID), "💰 Bet placed").await.unwrap();
+    assert_eq!(h.last_reply(), format!("{}\n\n💰 Bet placed", BANNER));
+    bot.send_markdown(ChatId(CHAT_ID), markdown::bold("Resolved!")).await.unwrap();
+    assert_eq!(h.last_markdown(), format!("{}\n\n*Resolved\\!*", markdown::escape(BANNER)));
+}
@@ -0,0 +1,80 @@
+use std::time::Instant;
+
+use super::*;
+use crate::edits::{CommandOutcome, ALREADY_PLACED, EDIT_WINDOW};
+use crate::{handle_edited_command, handle_message, remember_command_attempt, Command};
+
+/// Bob, with 1,000 coins, and Alice's open bet #1.
+async fn setup() -> Harness {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    h.open_bet(ALICE, "Will it snow?").await;
+    h
+}
+
+async fn send(h: &Harness, text: &str) {
+    let args = text.trim_start_matches("/bet ").to_string();
+    handle_message(h.messenger(), group_message(BOB, "bob", text), Command::Bet(args), h.ctx.clone()).await.unwrap();
+}
+
+/// Bob editing his message 100 into `text`.
+async fn edit(h: &Harness, text: &str) {
+    let args = text.trim_start_matches("/bet ").to_string();
+    handle_edited_command(h.messenger(), group_message(BOB, "bob", text), Command::Bet(args), h.ctx.clone())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn editing_a_refused_bet_places_the_corrected_one() {
+    let h = setup().await;
+    send(&h, "/bet 1 yes lots").await;
+    assert!(h.api.calls().is_empty());
+
+    edit(&h, "/bet 1 yes 100").await;
+    assert_eq!(h.api.calls(), vec![format!("bet {} #1 yes 100", BOB)]);
+    assert!(h.last_reply().starts_with("💰 Bet placed on-chain!"), "{}", h.last_reply());
+
+    // The corrected bet is placed now, so editing again changes nothing
+    edit(&h, "/bet 1 yes 200").await;
+    assert_eq!(h.last_reply(), ALREADY_PLACED);
+    assert_eq!(h.api.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn editing_a_mistyped_command_places_the_bet() {
+    let h = setup().await;
+    remember_command_attempt(&h.ctx, &group_message(BOB, "bob", "/bte 1 yes 100"));
+
+    edit(&h, "/bet 1 yes 100").await;
+    assert_eq!(h.api.calls(), vec![format!("bet {} #1 yes 100", BOB)]);
+}
+
+#[tokio::test]
+async fn editing_a_placed_bet_explains_bets_are_final() {
+    let h = setup().await;
+    send(&h, "/bet 1 yes 10").await;
+    assert_eq!(h.api.calls(), vec![format!("bet {} #1 yes 10", BOB)]);
+
+    edit(&h, "/bet 1 yes 100").await;
+    assert_eq!(h.last_reply(), ALREADY_PLACED);
+    assert_eq!(h.api.calls().len(), 1);
+    let wagers = h.ctx.db.get_wagers_for_bet(1).await.unwrap();
+    assert_eq!(wagers.iter().map(|w| w.amount).collect::<Vec<_>>(), vec![10]);
+}
+
+#[tokio::test]
+async fn edits_of_unknown_or_old_messages_are_ignored() {
+    let h = setup().await;
+    edit(&h, "/bet 1 yes 100").await;
+    assert!(h.api.calls().is_empty());
+    assert!(h.replies().is_empty());
+
+    let (chat_id, message_id) = (ChatId(CHAT_ID), MessageId(100));
+    let seen_at = Instant::now();
+    h.ctx.recent_commands.seen_at(chat_id, message_id, seen_at);
+    let outcome = |after| h.ctx.recent_commands.outcome_at(chat_id, message_id, seen_at + after);
+    assert_eq!(outcome(EDIT_WINDOW - Duration::from_secs(1)), Some(CommandOutcome::NotExecuted));
+    assert_eq!(outcome(EDIT_WINDOW), None);
+}
@@ -0,0 +1,96 @@
+use std::io;
+
+use axum::{body::Body, extract::State, http::HeaderMap, routing::get, Router};
+use contract1::api::{EventFilter, MarketEvent, WebhookPayload};
+use futures::StreamExt;
+
+use super::*;
+use crate::api_client::{parse_sse_message, MarketApiClient, RetryPolicy, SseMessage, LAST_EVENT_ID_HEADER};
+
+/// `Last-Event-ID` sent by each connection to the mock server, in order.
+type Connections = Arc<Mutex<Vec<Option<String>>>>;
+
+fn frame(tx_hash: &str, amount: u128) -> String {
+    let payload = WebhookPayload {
+        tx_hash: tx_hash.to_string(),
+        events: vec![MarketEvent::BetPlaced { market_id: 1, bettor: "43@contract1".to_string(), side: true, amount }],
+    };
+    format!("id: {}\ndata: {}\n\n", tx_hash, serde_json::to_string(&payload).unwrap())
+}
+
+/// The first connection drops in the middle of its third event. The next
+/// ones repeat the second event, like a server whose replay starts too
+/// early, then send the third and stay open.
+async fn events(State(connections): State<Connections>, headers: HeaderMap) -> Body {
+    let last_event_id = headers.get(LAST_EVENT_ID_HEADER).map(|value| value.to_str().unwrap().to_string());
+    let connection = {
+        let mut connections = connections.lock().unwrap();
+        connections.push(last_event_id);
+        connections.len()
+    };
+    let (second, third) = (frame("tx2", 200), frame("tx3", 300));
+    let chunks: Vec<Result<String, io::Error>> = if connection == 1 {
+        let (head, tail) = second.split_at(10);
+        vec![
+            Ok(frame("tx1", 100)),
+            Ok(": keep-alive\n\n".to_string()),
+            Ok(head.to_string()),
+            Ok(tail.to_string()),
+            Ok(third[..20].to_string()),
+        ]
+    } else {
+        vec![Ok(second), Ok(third)]
+    };
+    let chunks = futures::stream::iter(chunks);
+    if connection == 1 {
+        // Failing right away would abort the response before it is sent
+        let reset = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(io::Error::other("connection reset"))
+        });
+        Body::from_stream(chunks.chain(reset))
+    } else {
+        Body::from_stream(chunks.chain(futures::stream::pending()))
+    }
+}
+
+async fn mock_server() -> (String, Connections) {
+    let connections = Connections::default();
+    let router = Router::new().route("/api/events", get(events)).with_state(connections.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (url, connections)
+}
+
+#[tokio::test]
+async fn reconnects_after_a_drop_without_delivering_twice() {
+    let (url, connections) = mock_server().await;
+    let client = MarketApiClient::builder(url)
+        .retry_policy(RetryPolicy { base_delay: Duration::from_millis(10), jitter: 0.0, ..RetryPolicy::default() })
+        .build()
+        .unwrap();
+
+    let mut events = std::pin::pin!(client.subscribe_events(EventFilter::default()));
+    let mut received = vec![];
+    for _ in 0..3 {
+        let payload = tokio::time::timeout(Duration::from_secs(5), events.next()).await.expect("an event in time").unwrap();
+        received.push(payload.tx_hash);
+    }
+    assert_eq!(received, ["tx1", "tx2", "tx3"]);
+
+    // The repeated event never comes out, nor does anything else
+    assert!(tokio::time::timeout(Duration::from_millis(200), events.next()).await.is_err());
+    assert_eq!(connections.lock().unwrap().clone(), [None, Some("tx2".to_string())]);
+}
+
+#[test]
+fn sse_frames_are_parsed() {
+    assert_eq!(
+        parse_sse_message("id: tx1\nevent: market\ndata: {\"a\":\ndata:1}"),
+        Some(SseMessage { id: Some("tx1".to_string()), data: "{\"a\":\n1}".to_string() })
+    );
+    assert_eq!(parse_sse_message("data: plain"), Some(SseMessage { id: None, data: "plain".to_string() }));
+    assert_eq!(parse_sse_message(": keep-alive"), None);
+    assert_eq!(parse_sse_message("id: tx1"), None);
+}
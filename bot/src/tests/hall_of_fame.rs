@@ -0,0 +1,92 @@
+use super::*;
+use crate::handle_season;
+
+async fn season(h: &Harness, from: i64, args: &str) {
+    handle_season(h.messenger(), group_message(from, "op", "/season"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+}
+
+/// Alice won a resolved bet against Bob, who keeps a bet open.
+async fn played_season(h: &Harness) -> (i64, i64) {
+    h.initialized_user(ALICE, "alice", 10_500).await;
+    h.initialized_user(BOB, "bob", 9_000).await;
+    let resolved = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.create_wager(resolved, ALICE, 500, true).await.unwrap();
+    h.ctx.db.create_wager(resolved, BOB, 500, false).await.unwrap();
+    h.ctx.db.close_bet(resolved, true).await.unwrap();
+    let open = h.open_bet(BOB, "Will it snow?").await;
+    h.ctx.db.create_wager(open, BOB, 500, true).await.unwrap();
+    (resolved, open)
+}
+
+#[tokio::test]
+async fn season_waits_for_every_bet_to_resolve() {
+    let h = Harness::new().await;
+    played_season(&h).await;
+
+    season(&h, ALICE, "end").await;
+    assert_eq!(h.last_reply(), "⛔ This command is reserved for bot operators.");
+
+    season(&h, OPERATOR, "end").await;
+    assert_eq!(h.last_reply(), "⏳ 1 bet(s) are still open, resolve them before ending the season.");
+    assert!(h.api.calls().is_empty());
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 10_500);
+}
+
+#[tokio::test]
+async fn ending_the_season_resets_balances_and_archives_its_bets() {
+    let h = Harness::new().await;
+    let (resolved, open) = played_season(&h).await;
+    h.ctx.db.close_bet(open, false).await.unwrap();
+
+    season(&h, OPERATOR, "end").await;
+
+    assert_eq!(h.api.calls(), vec![format!("reset_balances {}", OPERATOR)]);
+    let reply = h.last_reply();
+    assert!(
+        reply.starts_with("🏁 Season 1 is over! Hall of fame:\n🥇 #1: @alice - 🪙 10,500 coins\n🥈 #2: @bob - 🪙 9,000 coins\n\nEveryone starts the new season with 🪙 10,000 coins."),
+        "{}",
+        reply
+    );
+    for user in [ALICE, BOB] {
+        assert_eq!(h.ctx.db.get_user(user).await.unwrap().unwrap().balance, 10_000);
+    }
+
+    // The season's bets and wagers move to the archive, where they stay readable
+    for bet_id in [resolved, open] {
+        let (bet, archived) = h.ctx.db.get_bet_or_archived(bet_id).await.unwrap().unwrap();
+        assert!(archived, "bet #{} still live", bet_id);
+        assert_ne!(bet.status, "open");
+    }
+    assert!(h.ctx.db.get_wagers_for_bet(resolved).await.unwrap().is_empty());
+    let wagers = h.ctx.db.get_archived_wagers_for_bet(resolved).await.unwrap();
+    assert_eq!(wagers.iter().map(|w| (w.user_id, w.side)).collect::<Vec<_>>(), vec![(ALICE, true), (BOB, false)]);
+    assert_eq!(h.ctx.db.get_lifetime_totals(BOB).await.unwrap().wagered, 1_000);
+}
+
+#[tokio::test]
+async fn season_history_lists_the_podiums() {
+    let h = Harness::new().await;
+    season(&h, OPERATOR, "history").await;
+    assert_eq!(h.last_reply(), "No season has ended yet.");
+
+    h.initialized_user(ALICE, "alice", 12_000).await;
+    h.initialized_user(BOB, "bob", 8_000).await;
+    season(&h, OPERATOR, "end").await;
+    h.ctx.db.update_user_balance(BOB, 15_000).await.unwrap();
+    season(&h, OPERATOR, "end").await;
+
+    season(&h, OPERATOR, "history").await;
+    let today = chrono::Utc::now().format("%Y-%m-%d");
+    assert_eq!(
+        h.last_reply(),
+        format!(
+            "📜 Hall of fame\n\nSeason 2, ended {today}\n🥇 #1: @bob - 🪙 15,000 coins\n🥈 #2: @alice - 🪙 10,000 coins\n\n\
+             Season 1, ended {today}\n🥇 #1: @alice - 🪙 12,000 coins\n🥈 #2: @bob - 🪙 8,000 coins"
+        )
+    );
+
+    season(&h, OPERATOR, "").await;
+    assert!(h.last_reply().starts_with("Usage: /season end|history"));
+}
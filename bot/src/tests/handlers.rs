@@ -0,0 +1,1176 @@
+use std::sync::Arc;
+
+use super::*;
+use crate::api_client::MarketApiError;
+use crate::currency::Currency;
+use crate::onboarding::{PendingCommand, ONBOARDING_TTL};
+use crate::{
+    handle_bet, handle_callback, handle_init, handle_leaderboard, handle_list, handle_me, handle_new, handle_reconcile, handle_resolve, handle_set_admin,
+    handle_solve, handle_solve_callback, handle_stats, handle_treasury, handle_withdraw, profit_leaderboard, split_tags, LeaderboardWindow,
+};
+use contract1::api::{BalanceDrift, LedgerBalance, LedgerMarket, PoolDrift, ReconcileTotals};
+
+fn rejected(message: &str) -> MarketApiError {
+    MarketApiError::ContractRejected { message: message.to_string() }
+}
+
+// --------------------------------------------------------
+//     /init
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn init_only_works_in_groups() {
+    let h = Harness::new().await;
+    handle_init(h.messenger(), private_message(ALICE, "alice", "/init"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.last_reply(), "This command only works in group chats.");
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn init_initializes_on_chain_then_locally() {
+    let h = Harness::new().await;
+    handle_init(h.messenger(), group_message(ALICE, "alice", "/init"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.api.calls(), vec!["initialize 42"]);
+    assert_eq!(
+        h.last_reply(),
+        "✅ Your balance has been initialized to 🪙 10,000 coins on-chain.\nTransaction: tx1"
+    );
+    assert!(h.ctx.db.is_user_initialized(ALICE).await.unwrap());
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 10_000);
+}
+
+#[tokio::test]
+async fn init_twice_is_refused_without_calling_the_api() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    handle_init(h.messenger(), group_message(ALICE, "alice", "/init"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(
+        h.last_reply(),
+        "You have already initialized your balance. You can only use /init once."
+    );
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn init_rejected_on_chain_records_nothing() {
+    let h = Harness::new().await;
+    h.api.fail_next(rejected("User already initialized"));
+    handle_init(h.messenger(), group_message(ALICE, "alice", "/init"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.last_reply(), "❌ Could not initialize your balance: User already initialized");
+    assert!(!h.ctx.db.is_user_initialized(ALICE).await.unwrap());
+}
+
+#[tokio::test]
+async fn init_already_done_on_chain_restores_the_local_user() {
+    let h = Harness::new().await;
+    h.api.set_account(ALICE, 7_500);
+    handle_init(h.messenger(), group_message(ALICE, "alice", "/init"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.api.calls(), vec!["initialize 42"]);
+    assert_eq!(
+        h.last_reply(),
+        "You have already initialized your balance on-chain. Current balance: 🪙 7,500 coins"
+    );
+    assert!(h.ctx.db.is_user_initialized(ALICE).await.unwrap());
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 7_500);
+}
+
+#[tokio::test]
+async fn init_after_database_resets_grants_once() {
+    let h = Harness::new().await;
+    for _ in 0..3 {
+        handle_init(h.messenger(), group_message(ALICE, "alice", "/init"), h.ctx.clone()).await.unwrap();
+        h.ctx.db.reset_all().await.unwrap();
+    }
+
+    assert_eq!(h.api.calls(), vec!["initialize 42"; 3]);
+    let replies = h.replies();
+    assert!(replies[0].starts_with("✅ Your balance has been initialized to 🪙 10,000 coins"), "{}", replies[0]);
+    assert_eq!(
+        replies[1..],
+        ["You have already initialized your balance on-chain. Current balance: 🪙 10,000 coins"; 2]
+    );
+}
+
+// --------------------------------------------------------
+//     /new
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn new_without_description_shows_usage() {
+    let h = Harness::new().await;
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), "  ".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        h.last_reply(),
+        "Usage: /new <description> [#tag ...] [deadline:YYYY-MM-DD]\nExample: /new Will it rain tomorrow? #weather deadline:2025-07-01"
+    );
+}
+
+#[tokio::test]
+async fn new_rejects_a_malformed_deadline() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let args = "Will it rain? deadline:someday".to_string();
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), args).await.unwrap();
+
+    let reply = h.last_reply();
+    assert!(reply.starts_with("❌ "), "{}", reply);
+    assert!(
+        reply.ends_with("Expected deadline:YYYY-MM-DD, deadline:YYYY-MM-DDTHH:MM, a day like deadline:tomorrow or deadline:friday 18:00, or a duration like deadline:3d"),
+        "{}",
+        reply
+    );
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn new_requires_an_initialized_user() {
+    let h = Harness::new().await;
+    handle_new(h.messenger(), private_message(ALICE, "alice", "/new"), h.ctx.clone(), "Will it rain?".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(h.last_reply(), "You need to use /init first to get your initial balance of 🪙 10,000 coins.");
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn new_creates_the_market_then_the_local_bet() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), "Will it rain?".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(h.api.calls(), vec!["create 42 Will it rain?"]);
+    assert_eq!(
+        h.last_reply(),
+        "✅ Market #1 created on-chain by @alice\n📄 Description: Will it rain?\nTransaction: tx1\n🔒 Bet privately: https://t.me/market_bot?start=bet_-1001_1\n\n📊 No bets yet"
+    );
+    let bet = h.ctx.db.get_bet_by_id(1).await.unwrap().unwrap();
+    assert_eq!(bet.description, "Will it rain?");
+    assert_eq!(bet.chat_id, Some(CHAT_ID));
+}
+
+#[tokio::test]
+async fn new_moves_trailing_hashtags_into_tags() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let args = "Pizza on Friday? #Food deadline:2099-01-01 #work".to_string();
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), args).await.unwrap();
+
+    assert_eq!(h.api.calls(), vec!["create 42 Pizza on Friday? #food #work"]);
+    assert!(h.last_reply().contains("Description: Pizza on Friday?\n🏷 #food #work\n⏰ Deadline: 1 Jan 2099 at 23:59"), "{}", h.last_reply());
+    assert_eq!(h.ctx.db.get_bet_by_id(1).await.unwrap().unwrap().description, "Pizza on Friday?");
+}
+
+#[test]
+fn only_trailing_hashtags_are_tags() {
+    assert_eq!(split_tags("Is #1 the best? #sports"), ("Is #1 the best?".to_string(), vec!["sports".to_string()]));
+    assert_eq!(split_tags("Will it rain? # #"), ("Will it rain? # #".to_string(), vec![]));
+    assert_eq!(split_tags("#food"), (String::new(), vec!["food".to_string()]));
+}
+
+#[tokio::test]
+async fn new_rejected_on_chain_creates_no_bet() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.api.fail_next(MarketApiError::Timeout);
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), "Will it rain?".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        h.last_reply(),
+        "⌛ The market server took too long to create the market. Please try again in a moment."
+    );
+    assert!(h.ctx.db.get_bet_by_id(1).await.unwrap().is_none());
+}
+
+// --------------------------------------------------------
+//     /bet
+// --------------------------------------------------------
+
+async fn bet(h: &Harness, user: i64, args: &str) -> String {
+    handle_bet(h.messenger(), group_message(user, "alice", "/bet"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+    h.last_reply()
+}
+
+#[tokio::test]
+async fn bet_argument_errors() {
+    let h = Harness::new().await;
+
+    assert_eq!(bet(&h, ALICE, "").await, "Usage: /bet <bet_id> <yes/no> <amount>\nExample: /bet 1 yes 100");
+    assert_eq!(
+        bet(&h, ALICE, "1 yes").await,
+        "Missing the amount.\nUsage: /bet <bet_id> <yes/no> <amount>\nExample: /bet 1 yes 100"
+    );
+    assert!(bet(&h, ALICE, "first yes 100").await.starts_with("Invalid bet ID \"first\""));
+    assert!(bet(&h, ALICE, "1 maybe 100").await.starts_with("Invalid side \"maybe\""));
+    assert!(bet(&h, ALICE, "1 yes 0").await.starts_with("Invalid amount \"0\""));
+    assert!(bet(&h, ALICE, "1 yes -5").await.starts_with("Invalid amount \"-5\""));
+    assert!(bet(&h, ALICE, "1 yes 100 please").await.starts_with("Unexpected \"please\""));
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn bets_can_stake_part_of_the_balance() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_001).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    bet(&h, ALICE, &format!("#{} yes half", bet_id)).await;
+    bet(&h, ALICE, &format!("{} no all", bet_id)).await;
+
+    assert_eq!(h.api.calls(), vec![format!("bet {} #1 yes 500", ALICE), format!("bet {} #1 no 501", ALICE)]);
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 0);
+}
+
+#[tokio::test]
+async fn bet_offers_to_initialize_new_users() {
+    let h = Harness::new().await;
+    assert_eq!(bet(&h, ALICE, "1 yes 100").await, "👋 You need coins to place a bet. Want your starting balance now?");
+    assert_eq!(
+        h.last_buttons(),
+        vec![
+            ("Initialize me (🪙 10,000 coins)".to_string(), "onboard:yes:42".to_string()),
+            ("No thanks".to_string(), "onboard:no:42".to_string()),
+        ]
+    );
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn bet_checks_the_balance_before_the_bet() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 50).await;
+    assert_eq!(
+        bet(&h, ALICE, "1 yes 100").await,
+        "Insufficient balance. You have 🪙 50 coins but tried to bet 🪙 100 coins."
+    );
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn bet_on_unknown_or_closed_bets() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    assert_eq!(
+        bet(&h, ALICE, "7 yes 100").await,
+        "Bet #7 not found. Use /list to see available bets."
+    );
+
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.close_bet(bet_id, true).await.unwrap();
+    assert_eq!(bet(&h, ALICE, &format!("{} no 100", bet_id)).await, format!("Bet #{} is already closed.", bet_id));
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn bet_is_placed_on_chain_then_recorded() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let reply = bet(&h, ALICE, &format!("{} y 250", bet_id)).await;
+
+    assert_eq!(h.api.calls(), vec![format!("bet 42 #{} yes 250", bet_id)]);
+    assert_eq!(
+        reply,
+        format!(
+            "💰 Bet placed on-chain!\n📝 Market #{}: Will it rain?\n🎯 Side: YES ✅\n💵 Amount: 🪙 250 coins\n💳 Remaining balance: 🪙 9,750 coins\nTransaction: tx1",
+            bet_id
+        )
+    );
+    let wagers = h.ctx.db.get_wagers_for_bet(bet_id).await.unwrap();
+    assert_eq!(wagers.len(), 1);
+    assert_eq!((wagers[0].user_id, wagers[0].amount, wagers[0].side), (ALICE, 250, true));
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 9_750);
+}
+
+#[tokio::test]
+async fn bet_rejected_on_chain_leaves_the_balance() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.api.fail_next(rejected("Market is not open for betting"));
+
+    let reply = bet(&h, ALICE, &format!("{} no 100", bet_id)).await;
+
+    assert_eq!(reply, "❌ Could not place the bet: Market is not open for betting");
+    assert!(h.ctx.db.get_wagers_for_bet(bet_id).await.unwrap().is_empty());
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 10_000);
+}
+
+#[tokio::test]
+async fn bets_on_a_paused_contract_are_told_to_wait() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.api.fail_next(rejected(&contract1::MarketError::ContractPaused.to_string()));
+
+    let reply = bet(&h, ALICE, &format!("{} no 100", bet_id)).await;
+
+    assert_eq!(reply, "⏸️ Markets are temporarily paused by the operator, could not place the bet. Please try again later.");
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 10_000);
+}
+
+#[tokio::test]
+async fn bet_over_the_stake_cap_states_the_allowance() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.api.fail_next(rejected(&format!(
+        "Bet exceeds the stake cap, you can add at most 1500 on market #{}",
+        bet_id
+    )));
+    h.api.fail_next(rejected(&format!(
+        "Bet exceeds the stake cap, you can add at most 0 on market #{}",
+        bet_id
+    )));
+
+    assert_eq!(
+        bet(&h, ALICE, &format!("{} yes 2000", bet_id)).await,
+        format!(
+            "🧢 Market #{} caps each player's stake. You can add at most 🪙 1,500 coins on YES: /bet {} yes 1500",
+            bet_id, bet_id
+        )
+    );
+    assert_eq!(
+        bet(&h, ALICE, &format!("{} no 10", bet_id)).await,
+        "🧢 You already hold the most this market allows on NO."
+    );
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 10_000);
+}
+
+// --------------------------------------------------------
+//     Onboarding
+// --------------------------------------------------------
+
+async fn press(h: &Harness, from: i64, data: &str) {
+    handle_callback(h.messenger(), button_press(from, "alice", FIRST_SENT_ID, data), h.ctx.clone())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn accepting_the_offer_initializes_then_places_the_bet() {
+    let h = Harness::new().await;
+    h.initialized_user(BOB, "bob", 10_000).await;
+    let bet_id = h.open_bet(BOB, "Will it rain?").await;
+    bet(&h, ALICE, &format!("{} yes 100", bet_id)).await;
+
+    press(&h, ALICE, "onboard:yes:42").await;
+
+    assert_eq!(h.api.calls(), vec![format!("initialize {}", ALICE), format!("bet {} #{} yes 100", ALICE, bet_id)]);
+    assert!(h.ctx.db.is_user_initialized(ALICE).await.unwrap());
+    let replies = h.replies();
+    assert!(replies[1].starts_with("✅ Your balance has been initialized to 🪙 10,000 coins"), "{}", replies[1]);
+    assert_eq!(replies.len(), 3);
+}
+
+#[tokio::test]
+async fn accepting_the_offer_creates_the_market() {
+    let h = Harness::new().await;
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), "Will it rain?".to_string())
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), "👋 You need coins to create a market. Want your starting balance now?");
+
+    press(&h, ALICE, "onboard:yes:42").await;
+
+    assert_eq!(h.api.calls(), vec![format!("initialize {}", ALICE), format!("create {} Will it rain?", ALICE)]);
+}
+
+#[tokio::test]
+async fn declining_the_offer_drops_the_command() {
+    let h = Harness::new().await;
+    bet(&h, ALICE, "1 yes 100").await;
+
+    press(&h, ALICE, "onboard:no:42").await;
+    assert_eq!(h.callback_answers(), vec![Some("No problem, use /init whenever you are ready.".to_string())]);
+
+    // A later tap on the other button finds nothing to replay
+    press(&h, ALICE, "onboard:yes:42").await;
+    assert_eq!(h.callback_answers()[1].as_deref(), Some("This offer has expired, please run your command again."));
+    assert!(h.api.calls().is_empty());
+    assert!(!h.ctx.db.is_user_initialized(ALICE).await.unwrap());
+}
+
+#[tokio::test]
+async fn stashed_commands_expire() {
+    let h = Harness::new().await;
+    let stashed_at = std::time::Instant::now() - ONBOARDING_TTL - std::time::Duration::from_secs(1);
+    h.ctx.pending_onboardings.stash_at(
+        ChatId(CHAT_ID),
+        UserId(ALICE as u64),
+        group_message(ALICE, "alice", "/bet"),
+        PendingCommand::Bet("1 yes 100".to_string()),
+        stashed_at,
+    );
+
+    press(&h, ALICE, "onboard:yes:42").await;
+
+    assert_eq!(h.callback_answers(), vec![Some("This offer has expired, please run your command again.".to_string())]);
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn only_the_new_user_can_accept_the_offer() {
+    let h = Harness::new().await;
+    bet(&h, ALICE, "1 yes 100").await;
+
+    press(&h, BOB, "onboard:yes:42").await;
+
+    assert_eq!(
+        h.callback_answers(),
+        vec![Some("This offer is for someone else, use /init to get your own coins.".to_string())]
+    );
+    assert!(h.api.calls().is_empty());
+    // The offer still stands for Alice
+    assert!(h.ctx.pending_onboardings.take(ChatId(CHAT_ID), UserId(ALICE as u64)).is_some());
+}
+
+// --------------------------------------------------------
+//     /solve
+// --------------------------------------------------------
+
+async fn solve(h: &Harness, msg: Message) -> String {
+    handle_solve(h.messenger(), msg, h.ctx.clone()).await.unwrap();
+    h.last_reply()
+}
+
+#[tokio::test]
+async fn solve_requires_a_reply() {
+    let h = Harness::new().await;
+    assert_eq!(
+        solve(&h, group_message(ALICE, "alice", "/solve 1")).await,
+        "Please reply to a message to use /solve\nUsage: /solve [bet_id] [N] [force]\nExample: /solve 1 3"
+    );
+}
+
+#[tokio::test]
+async fn solve_argument_and_state_errors() {
+    let h = Harness::new().await;
+    let reply = |text: &str| group_reply(ALICE, "alice", text, BOB, "It rained");
+
+    assert_eq!(
+        solve(&h, reply("/solve 1")).await,
+        "You need to use /init first to get your initial balance of 🪙 10,000 coins."
+    );
+
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    assert_eq!(
+        solve(&h, reply("/solve")).await,
+        "Please specify which bet this solves.\nUsage: /solve [bet_id] [N] [force]\nExample: /solve 1 3"
+    );
+    assert_eq!(solve(&h, reply("/solve 9")).await, "Bet #9 not found.");
+
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.close_bet(bet_id, true).await.unwrap();
+    assert_eq!(solve(&h, reply(&format!("/solve {}", bet_id))).await, "This bet is already closed.");
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn solve_without_a_backend_reports_the_configuration_error() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "It rained");
+    assert_eq!(solve(&h, msg).await, "❌ Bot configuration error: no LLM backend configured.");
+}
+
+#[tokio::test]
+async fn solve_force_is_reserved_to_admins() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, true))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {} force", bet_id), BOB, "It rained");
+    assert_eq!(solve(&h, msg.clone()).await, "❌ Only chat admins can force a new evaluation.");
+    assert!(h.api.calls().is_empty());
+
+    h.make_admin(ALICE);
+    assert!(solve(&h, msg).await.starts_with("✅ MARKET RESOLVED ON-CHAIN!"));
+    assert_eq!(
+        h.api.calls(),
+        vec![format!("comment 42 #{} Evidence from @author: It rained", bet_id), format!("resolve 42 #{} yes", bet_id)]
+    );
+}
+
+#[tokio::test]
+async fn solve_resolves_on_chain_then_closes_the_bet() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, true))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "It rained");
+    let reply = solve(&h, msg).await;
+
+    assert_eq!(h.replies()[0], "🤔 Evaluating solution with test-model...");
+    assert_eq!(
+        h.api.calls(),
+        vec![format!("comment 42 #{} Evidence from @author: It rained", bet_id), format!("resolve 42 #{} yes", bet_id)]
+    );
+    assert!(reply.starts_with("✅ MARKET RESOLVED ON-CHAIN!"), "{}", reply);
+    assert!(reply.contains("💬 Solution: \"It rained\""), "{}", reply);
+    assert!(reply.contains("🤖 Analysis (test-model): the message settles it"), "{}", reply);
+    assert_ne!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+}
+
+#[tokio::test]
+async fn solve_rejected_on_chain_keeps_the_bet_open() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, false))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    // Both the evidence comment and the resolution are refused
+    h.api.fail_next(rejected("Market is not open"));
+    h.api.fail_next(rejected("Market is not open"));
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "It stayed dry");
+    let reply = solve(&h, msg).await;
+
+    assert_eq!(reply, "❌ Could not resolve the market on-chain: Market is not open\n\nThe bet remains open.");
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+}
+
+#[tokio::test]
+async fn solve_resolves_even_if_the_evidence_comment_fails() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, true))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.api.fail_next(rejected(&format!("Market #{} already has 20 comments", bet_id)));
+
+    let long_evidence = "rain ".repeat(60);
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, &long_evidence);
+    let reply = solve(&h, msg).await;
+
+    assert!(reply.starts_with("✅ MARKET RESOLVED ON-CHAIN!"), "{}", reply);
+    let calls = h.api.calls();
+    let comment = calls[0].strip_prefix(&format!("comment 42 #{} ", bet_id)).unwrap();
+    assert_eq!(comment.chars().count(), contract1::MAX_COMMENT_CHARS);
+    assert!(comment.ends_with('…'));
+    assert_eq!(calls[1], format!("resolve 42 #{} yes", bet_id));
+}
+
+#[tokio::test]
+async fn solve_unresolved_verdict_does_not_touch_the_chain() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(false, false))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "Clouds are forming");
+    let reply = solve(&h, msg).await;
+
+    assert!(reply.starts_with("❌ NOT RESOLVED"), "{}", reply);
+    assert!(reply.ends_with("The market remains open."), "{}", reply);
+    assert!(h.api.calls().is_empty());
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+}
+
+/// A resolver whose model is unreachable.
+struct FailingResolver;
+
+#[async_trait]
+impl Resolver for FailingResolver {
+    fn name(&self) -> &str {
+        "test-model"
+    }
+
+    async fn evaluate(&self, _ctx: ResolutionContext) -> anyhow::Result<BetResolution> {
+        anyhow::bail!("model unreachable")
+    }
+}
+
+#[tokio::test]
+async fn bets_wait_while_a_solution_is_evaluated() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, true))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    assert!(h.ctx.db.begin_resolving(bet_id).await.unwrap());
+
+    let waiting = format!("⏳ Resolution in progress: bet #{} takes no bets until its verdict is in.", bet_id);
+    assert_eq!(bet(&h, ALICE, &format!("{} yes 100", bet_id)).await, waiting);
+    // A second evaluation of the same bet waits for the first
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "It is raining");
+    assert_eq!(solve(&h, msg).await, waiting);
+    assert!(h.api.calls().is_empty());
+
+    h.ctx.db.reopen_bet(bet_id).await.unwrap();
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+}
+
+#[tokio::test]
+async fn failed_evaluations_reopen_betting() {
+    let h = Harness::with_resolver(Some(Arc::new(FailingResolver))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "It is raining");
+    let reply = solve(&h, msg).await;
+
+    assert_eq!(reply, "❌ Failed to evaluate solution: model unreachable");
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+}
+
+#[tokio::test]
+async fn evaluations_cut_short_by_an_error_reopen_betting() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, true))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    // The evaluation fails as soon as it tells the chat it started
+    h.kick_from(CHAT_ID);
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "It is raining");
+    assert!(handle_solve(h.messenger(), msg, h.ctx.clone()).await.is_err());
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn bets_being_evaluated_still_count_as_open() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.create_wager(bet_id, ALICE, 300, true).await.unwrap();
+    h.ctx.db.begin_resolving(bet_id).await.unwrap();
+
+    let exposure = h.ctx.db.get_open_exposure(ALICE).await.unwrap();
+    assert_eq!((exposure.open_bets, exposure.at_risk), (1, 300));
+    assert_eq!(h.ctx.db.count_open_bets(None).await.unwrap(), 1);
+    assert_eq!(h.ctx.db.get_open_pools(None).await.unwrap()[0].yes_pool, 300);
+    assert_eq!(h.ctx.db.get_active_chats().await.unwrap(), [CHAT_ID]);
+
+    // An admin settles it without waiting for the verdict, which then leaves it settled
+    handle_resolve(h.messenger(), group_message(ALICE, "alice", "/resolve"), h.ctx.clone(), format!("{} yes", bet_id))
+        .await
+        .unwrap();
+    assert!(h.last_reply().contains("MARKET RESOLVED BY ADMIN"), "{}", h.last_reply());
+    h.ctx.db.reopen_bet(bet_id).await.unwrap();
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "resolved_yes");
+}
+
+#[tokio::test]
+async fn interrupted_evaluations_reopen_at_startup() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.begin_resolving(bet_id).await.unwrap();
+
+    assert_eq!(h.ctx.db.reopen_resolving_bets().await.unwrap(), 1);
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+    // Only an open bet can start resolving
+    assert!(h.ctx.db.begin_resolving(bet_id).await.unwrap());
+    assert!(!h.ctx.db.begin_resolving(bet_id).await.unwrap());
+}
+
+#[tokio::test]
+async fn new_market_announcements_are_remembered() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new Will it rain?"), h.ctx.clone(), "Will it rain?".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(h.ctx.db.get_announced_bet(CHAT_ID, FIRST_SENT_ID as i64).await.unwrap(), Some(1));
+}
+
+#[tokio::test]
+async fn solve_replying_to_an_announcement_takes_the_proof_inline() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, true))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    // The replied message (id 99) is the bot's announcement of the bet
+    h.ctx.db.record_announcement(CHAT_ID, 99, bet_id).await.unwrap();
+
+    let usage = solve(&h, group_reply(ALICE, "alice", "/solve", 1, "✅ Market #1 created")).await;
+    assert!(usage.starts_with("Write the proof after the command"), "{}", usage);
+
+    let reply = solve(&h, group_reply(ALICE, "alice", "/solve It is pouring", 1, "✅ Market #1 created")).await;
+    assert!(reply.starts_with("✅ MARKET RESOLVED ON-CHAIN!"), "{}", reply);
+    assert!(reply.contains("💬 Solution: \"It is pouring\""), "{}", reply);
+    assert_eq!(
+        h.api.calls(),
+        vec![format!("comment 42 #{} Evidence from @alice: It is pouring", bet_id), format!("resolve 42 #{} yes", bet_id)]
+    );
+}
+
+#[tokio::test]
+async fn solve_without_bet_id_suggests_the_matching_bet() {
+    let h = Harness::with_resolver(Some(Arc::new(FixedResolver(verdict(true, true))))).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let rain = h.open_bet(ALICE, "Will it rain in Paris tomorrow?").await;
+    h.open_bet(ALICE, "Will Bob finish the marathon?").await;
+
+    let command = group_reply(ALICE, "alice", "/solve", BOB, "Heavy rain in Paris right now");
+    let prompt = solve(&h, command).await;
+    assert_eq!(prompt, format!("🔎 This looks like bet #{}: Will it rain in Paris tomorrow?", rain));
+    assert_eq!(h.last_buttons(), vec![(format!("Solve bet #{}?", rain), format!("solve:{}", rain))]);
+    assert!(h.api.calls().is_empty());
+
+    // Only the author of the /solve may confirm
+    let data = format!("solve:{}", rain);
+    handle_solve_callback(h.messenger(), button_press(BOB, "bob", FIRST_SENT_ID, &data), h.ctx.clone()).await.unwrap();
+    assert_eq!(h.callback_answers().last().unwrap().as_deref(), Some("Only the author of the /solve can pick the bet."));
+    assert!(h.api.calls().is_empty());
+
+    handle_solve_callback(h.messenger(), button_press(ALICE, "alice", FIRST_SENT_ID, &data), h.ctx.clone()).await.unwrap();
+    assert!(h.last_reply().starts_with("✅ MARKET RESOLVED ON-CHAIN!"), "{}", h.last_reply());
+    assert_eq!(h.api.calls().last().unwrap(), &format!("resolve 42 #{} yes", rain));
+
+    // A second press finds nothing left to solve
+    let calls = h.api.calls().len();
+    handle_solve_callback(h.messenger(), button_press(ALICE, "alice", FIRST_SENT_ID, &data), h.ctx.clone()).await.unwrap();
+    assert_eq!(h.api.calls().len(), calls);
+    assert_eq!(
+        h.callback_answers().last().unwrap().as_deref(),
+        Some("This suggestion has expired, please run /solve again.")
+    );
+}
+
+#[tokio::test]
+async fn solve_without_bet_id_lets_the_author_pick_among_matches() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let paris = h.open_bet(ALICE, "Will it rain in Paris?").await;
+    let london = h.open_bet(ALICE, "Rain in London this weekend?").await;
+    h.open_bet(ALICE, "Will Bob finish the marathon?").await;
+
+    let prompt = solve(&h, group_reply(ALICE, "alice", "/solve", BOB, "Rain everywhere, Paris and London")).await;
+
+    assert_eq!(prompt, "🔎 Which bet does this solve?");
+    let buttons: Vec<String> = h.last_buttons().into_iter().map(|(_, data)| data).collect();
+    assert_eq!(buttons, vec![format!("solve:{}", paris), format!("solve:{}", london)]);
+}
+
+// --------------------------------------------------------
+//     /list
+// --------------------------------------------------------
+
+fn summary(id: i64, pool: u128, opens_at: Option<u64>, scheduled: bool) -> MarketSummary {
+    MarketSummary {
+        id: id as u64,
+        description: String::new(),
+        creator: format!("{}@contract1", ALICE),
+        status: contract1::MarketStatus::Open,
+        yes_pool: pool,
+        no_pool: 0,
+        bettor_count: 1,
+        opens_at,
+        scheduled,
+        stake_cap: None,
+        betting_closed: false,
+        tags: vec![],
+        challenge: None,
+        accept_by: None,
+    }
+}
+
+#[tokio::test]
+async fn list_shows_when_scheduled_markets_open() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let open = h.open_bet(ALICE, "Will it rain?").await;
+    let scheduled = h.open_bet(ALICE, "Who wins the final?").await;
+    h.api.set_markets(vec![
+        summary(open, 300, None, false),
+        // 2025-06-15 15:06:40 UTC
+        summary(scheduled, 0, Some(1_750_000_000), true),
+    ]);
+    handle_list(h.messenger(), group_message(ALICE, "alice", "/list"), h.ctx.clone(), String::new()).await.unwrap();
+
+    let reply = h.last_reply();
+    assert!(reply.contains(&format!("🟢 Bet #{}: Will it rain? (🪙 300 coins)\n", open)), "{}", reply);
+    assert!(
+        reply.contains(&format!("⏰ Bet #{}: Who wins the final? (🪙 0 coins) — opens 15 Jun 2025 at 15:06\n", scheduled)),
+        "{}",
+        reply
+    );
+}
+
+#[tokio::test]
+async fn list_filters_by_tag() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let pizza = h.open_bet(ALICE, "Pizza on Friday?").await;
+    let rain = h.open_bet(ALICE, "Will it rain?").await;
+    h.api.set_markets(vec![
+        MarketSummary { tags: vec!["food".to_string()], ..summary(pizza, 100, None, false) },
+        summary(rain, 300, None, false),
+    ]);
+
+    handle_list(h.messenger(), group_message(ALICE, "alice", "/list"), h.ctx.clone(), "#Food".to_string()).await.unwrap();
+    let reply = h.last_reply();
+    assert!(reply.starts_with("📄 BETS TAGGED #FOOD 📄"), "{}", reply);
+    assert!(reply.contains(&format!("Bet #{}: Pizza on Friday?", pizza)), "{}", reply);
+    assert!(!reply.contains("Will it rain?"), "{}", reply);
+
+    handle_list(h.messenger(), group_message(ALICE, "alice", "/list"), h.ctx.clone(), "#music".to_string()).await.unwrap();
+    assert_eq!(h.last_reply(), "No bets tagged #music. Use /list to see all bets.");
+}
+
+// --------------------------------------------------------
+//     /leaderboard
+// --------------------------------------------------------
+
+/// Alice stakes 300 on YES and Bob 100 on NO, then the bet resolves YES at `settled_at`.
+async fn settled_bet(h: &Harness, settled_at: chrono::DateTime<chrono::Utc>) {
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.initialized_user(BOB, "bob", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.create_wager(bet_id, ALICE, 300, true).await.unwrap();
+    h.ctx.db.create_wager(bet_id, BOB, 100, false).await.unwrap();
+    h.ctx.db.close_bet_at(bet_id, true, &settled_at.to_rfc3339()).await.unwrap();
+}
+
+#[tokio::test]
+async fn profit_windows_split_stakes_and_payouts_at_the_boundary() {
+    let h = Harness::new().await;
+    let placed = chrono::Utc::now();
+    settled_bet(&h, placed + chrono::Duration::days(8)).await;
+    let window = |from: chrono::Duration, to: chrono::Duration| ((placed + from).to_rfc3339(), (placed + to).to_rfc3339());
+    let profits = |entries: Vec<crate::db::ProfitEntry>| entries.into_iter().map(|e| (e.user_id, e.profit)).collect::<Vec<_>>();
+
+    // The stakes count where they were placed...
+    let (from, to) = window(chrono::Duration::hours(-1), chrono::Duration::hours(1));
+    assert_eq!(profits(h.ctx.db.get_profit_leaderboard(&from, &to, 10).await.unwrap()), vec![(BOB, -100), (ALICE, -300)]);
+
+    // ...and the payouts where the bet settled, losers included
+    let (from, to) = window(chrono::Duration::days(7), chrono::Duration::days(9));
+    assert_eq!(profits(h.ctx.db.get_profit_leaderboard(&from, &to, 10).await.unwrap()), vec![(ALICE, 400), (BOB, 0)]);
+
+    let (from, to) = window(chrono::Duration::hours(-1), chrono::Duration::days(9));
+    assert_eq!(profits(h.ctx.db.get_profit_leaderboard(&from, &to, 10).await.unwrap()), vec![(ALICE, 100), (BOB, -100)]);
+
+    // Nothing happened in between
+    let (from, to) = window(chrono::Duration::days(1), chrono::Duration::days(7));
+    assert!(h.ctx.db.get_profit_leaderboard(&from, &to, 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn bets_are_only_settled_once() {
+    let h = Harness::new().await;
+    let now = chrono::Utc::now();
+    settled_bet(&h, now).await;
+    h.ctx.db.close_bet(1, false).await.unwrap();
+
+    let (from, to) = ((now - chrono::Duration::hours(1)).to_rfc3339(), (now + chrono::Duration::hours(1)).to_rfc3339());
+    let entries = h.ctx.db.get_profit_leaderboard(&from, &to, 10).await.unwrap();
+    assert_eq!(entries.iter().map(|e| e.profit).collect::<Vec<_>>(), vec![100, -100]);
+}
+
+#[tokio::test]
+async fn weekly_leaderboard_shows_movement_since_last_week() {
+    let h = Harness::new().await;
+    settled_bet(&h, chrono::Utc::now() - chrono::Duration::days(7)).await;
+
+    // Last week Alice collected 400 and Bob nothing; this week only their stakes count
+    let text = profit_leaderboard(&h.ctx.db, &Currency::default(), LeaderboardWindow::Week, chrono::Utc::now()).await.unwrap();
+    let text = crate::markdown::to_plain(&text);
+    assert!(text.starts_with("🏆 WEEKLY LEADERBOARD 🏆\nNet profit since "), "{}", text);
+    assert!(text.ends_with("🥇 #1: @bob 🪙 -100 coins ▲1\n🥈 #2: @alice 🪙 -300 coins ▼1\n"), "{}", text);
+}
+
+#[tokio::test]
+async fn leaderboard_window_arguments() {
+    let h = Harness::new().await;
+    handle_leaderboard(h.messenger(), group_message(ALICE, "alice", "/leaderboard month"), h.ctx.clone(), "month".to_string())
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), "No bets were placed or settled this month yet.");
+
+    handle_leaderboard(h.messenger(), group_message(ALICE, "alice", "/leaderboard year"), h.ctx.clone(), "year".to_string())
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), "Usage: /leaderboard [week|month|all]");
+}
+
+// --------------------------------------------------------
+//     /treasury
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn treasury_shows_balance_and_admin() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.api.set_treasury(1_250);
+    handle_treasury(h.messenger(), group_message(BOB, "bob", "/treasury"), h.ctx.clone()).await.unwrap();
+
+    let reply = h.last_reply();
+    assert!(reply.contains("Balance: 🪙 1,250 coins"), "{}", reply);
+    assert!(reply.contains("Admin: @alice"), "{}", reply);
+}
+
+// --------------------------------------------------------
+//     /stats
+// --------------------------------------------------------
+
+fn point(yes_pool: u128, no_pool: u128) -> MarketHistoryPoint {
+    MarketHistoryPoint {
+        bettor: format!("{}@contract1", ALICE),
+        side: true,
+        amount: 100,
+        timestamp: None,
+        yes_pool,
+        no_pool,
+    }
+}
+
+#[tokio::test]
+async fn stats_charts_the_odds_over_time() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.api.set_history(bet_id as u64, vec![point(100, 0), point(100, 100), point(100, 300), point(700, 300)]);
+
+    handle_stats(h.messenger(), group_message(BOB, "bob", "/stats"), h.ctx.clone(), format!("{}", bet_id)).await.unwrap();
+
+    assert_eq!(
+        h.last_reply(),
+        format!("📈 Odds of bet #{}\nWill it rain?\n\n█▅▃▆\nYES 100% → 70% over 4 bets", bet_id)
+    );
+}
+
+#[tokio::test]
+async fn stats_of_a_market_without_bets() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    handle_stats(h.messenger(), group_message(BOB, "bob", "/stats"), h.ctx.clone(), format!("{}", bet_id)).await.unwrap();
+    assert!(h.last_reply().ends_with("No bets yet."), "{}", h.last_reply());
+
+    handle_stats(h.messenger(), group_message(BOB, "bob", "/stats"), h.ctx.clone(), "99".to_string()).await.unwrap();
+    assert_eq!(h.last_reply(), "Bet #99 not found. Use /list to see available bets.");
+}
+
+#[test]
+fn sparkline_samples_long_histories() {
+    let history: Vec<_> = (0..200).map(|i| point(i, 199 - i)).collect();
+
+    let line = crate::announcements::sparkline(&history);
+
+    assert_eq!(line.chars().count(), crate::announcements::SPARKLINE_WIDTH);
+    assert_eq!(line.chars().next(), Some('▁'));
+    assert_eq!(line.chars().last(), Some('█'));
+}
+
+// --------------------------------------------------------
+//     /me
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn me_shows_a_profile_card() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 2_000).await;
+    h.api.set_account(ALICE, 9_500);
+    h.api.accounts.lock().unwrap().get_mut(&ALICE.to_string()).unwrap().current_streak = 3;
+
+    let settled = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.create_wager(settled, ALICE, 300, true).await.unwrap();
+    h.ctx.db.create_wager(settled, BOB, 100, false).await.unwrap();
+    h.ctx.db.close_bet(settled, true).await.unwrap();
+    let open = h.open_bet(BOB, "Will it snow?").await;
+    h.ctx.db.create_wager(open, ALICE, 200, true).await.unwrap();
+
+    handle_me(h.messenger(), group_message(ALICE, "alice", "/me"), h.ctx.clone()).await.unwrap();
+
+    let card = h.last_reply();
+    assert!(card.starts_with("👤 @alice\n\n💰 Balance: 🪙 9,500 coins\n🏅 Rank: #2 of 2 in this chat\n🔥 Streak: 3\n"), "{}", card);
+    assert!(card.contains("🎲 Open bets: 1 (🪙 200 coins at risk)\n📈 Lifetime: 🪙 500 coins wagered, 🪙 400 coins won"), "{}", card);
+    assert!(card.contains("🎖 Badges: 🔥 On fire"), "{}", card);
+    assert!(card.contains("📅 Since 20"), "{}", card);
+}
+
+#[tokio::test]
+async fn me_prefers_the_exposure_reported_on_chain() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    h.api.set_account(ALICE, 9_500);
+    let open = h.open_bet(BOB, "Will it snow?").await;
+    h.ctx.db.create_wager(open, ALICE, 200, true).await.unwrap();
+    // Includes a challenge only the chain knows about
+    h.api.set_exposure(ALICE, OpenExposure { yes: 200, no: 50, positions: 2 });
+
+    handle_me(h.messenger(), group_message(ALICE, "alice", "/me"), h.ctx.clone()).await.unwrap();
+
+    let card = h.last_reply();
+    assert!(card.contains("🎲 Open bets: 2 (🪙 250 coins at risk, 🪙 200 coins YES / 🪙 50 coins NO)\n"), "{}", card);
+    assert!(h.api.calls().contains(&format!("exposure {}", ALICE)));
+}
+
+#[tokio::test]
+async fn me_covers_accounts_lost_by_a_local_reset() {
+    let h = Harness::new().await;
+    h.api.set_account(ALICE, 12_000);
+
+    handle_me(h.messenger(), group_message(ALICE, "alice", "/me"), h.ctx.clone()).await.unwrap();
+
+    let card = h.last_reply();
+    assert!(card.contains("💰 Balance: 🪙 12,000 coins"), "{}", card);
+    assert!(!card.contains("Rank"), "{}", card);
+    assert!(card.ends_with("📅 Known on-chain only, local history was reset"), "{}", card);
+}
+
+#[tokio::test]
+async fn me_asks_unknown_users_to_init() {
+    let h = Harness::new().await;
+    handle_me(h.messenger(), group_message(ALICE, "alice", "/me"), h.ctx.clone()).await.unwrap();
+
+    assert!(h.last_reply().starts_with("You need to use /init first"), "{}", h.last_reply());
+}
+
+// --------------------------------------------------------
+//     Operator commands
+// --------------------------------------------------------
+
+#[tokio::test]
+async fn operator_commands_reject_other_users() {
+    let h = Harness::new().await;
+    // Being a chat admin is not enough
+    h.make_admin(ALICE);
+    handle_set_admin(h.messenger(), group_message(ALICE, "alice", "/setadmin"), h.ctx.clone(), String::new())
+        .await
+        .unwrap();
+    handle_withdraw(h.messenger(), group_message(ALICE, "alice", "/withdraw 10"), h.ctx.clone(), "10".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(h.replies(), vec!["⛔ This command is reserved for bot operators."; 2]);
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn set_admin_defaults_to_the_operator() {
+    let h = Harness::new().await;
+    handle_set_admin(h.messenger(), group_message(OPERATOR, "op", "/setadmin"), h.ctx.clone(), String::new())
+        .await
+        .unwrap();
+    handle_set_admin(h.messenger(), group_message(OPERATOR, "op", "/setadmin 42"), h.ctx.clone(), "42".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(h.api.calls(), vec!["set_admin 7 7", "set_admin 7 42"]);
+    assert!(h.last_reply().starts_with("✅ User 42 is now the contract admin."), "{}", h.last_reply());
+}
+
+#[tokio::test]
+async fn withdraw_credits_the_recipient_locally() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    handle_withdraw(h.messenger(), group_message(OPERATOR, "op", "/withdraw 250 42"), h.ctx.clone(), "250 42".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(h.api.calls(), vec!["withdraw 7 42 250"]);
+    assert!(h.last_reply().starts_with("✅ Withdrew 🪙 250 coins from the treasury to @alice."), "{}", h.last_reply());
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 1_250);
+}
+
+#[tokio::test]
+async fn withdraw_rejects_bad_arguments() {
+    let h = Harness::new().await;
+    for args in ["", "0", "ten", "half", "10 alice", "10 7 8"] {
+        handle_withdraw(h.messenger(), group_message(OPERATOR, "op", "/withdraw"), h.ctx.clone(), args.to_string())
+            .await
+            .unwrap();
+        assert!(h.last_reply().contains("Usage: /withdraw"), "{}: {}", args, h.last_reply());
+    }
+    assert!(h.api.calls().is_empty());
+}
+
+async fn reconcile(h: &Harness, user_id: i64) {
+    handle_reconcile(h.messenger(), group_message(user_id, "op", "/reconcile"), h.ctx.clone())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn reconcile_sends_balances_and_open_pools() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 900).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it snow?").await;
+    h.ctx.db.create_wager(bet_id, ALICE, 100, true).await.unwrap();
+    let closed = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.close_bet(closed, false).await.unwrap();
+
+    reconcile(&h, OPERATOR).await;
+
+    let snapshot = h.api.reconciled().unwrap();
+    assert_eq!(
+        snapshot.balances,
+        vec![
+            LedgerBalance { identity: format!("{}@contract1", ALICE), balance: 900 },
+            LedgerBalance { identity: format!("{}@contract1", BOB), balance: 1_000 },
+        ]
+    );
+    assert_eq!(snapshot.open_markets, vec![LedgerMarket { market_id: bet_id as u64, yes_pool: 100, no_pool: 0 }]);
+    assert!(h.last_reply().ends_with("✅ No drift found."), "{}", h.last_reply());
+}
+
+#[tokio::test]
+async fn reconcile_lists_each_kind_of_drift() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 900).await;
+    h.api.set_reconcile_report(ReconcileReport {
+        balances: vec![
+            BalanceDrift { identity: format!("{}@contract1", ALICE), ledger: 900, on_chain: Some(1_000) },
+            BalanceDrift { identity: "99@contract1".to_string(), ledger: 50, on_chain: None },
+        ],
+        open_only_in_ledger: vec![3],
+        open_only_on_chain: vec![4, 5],
+        pools: vec![PoolDrift { market_id: 6, ledger: (1_500, 20), on_chain: (1_500, 0) }],
+        totals: ReconcileTotals { users: 2, ledger_balance: 950, on_chain_balance: 1_000, ledger_open_markets: 2, on_chain_open_markets: 3 },
+    });
+
+    reconcile(&h, OPERATOR).await;
+
+    let reply = h.last_reply();
+    for line in [
+        "👥 2 users: 950 here, 1,000 on-chain",
+        "📂 Open bets: 2 here, 3 on-chain",
+        "⚠️ Balances differ for 2 user(s):\n• @alice: 900 here, 1,000 on-chain\n• User 99: 50 here, unknown on-chain",
+        "⚠️ Open here only: #3",
+        "⚠️ Open on-chain only: #4, #5",
+        "• #6: YES 1,500 / NO 20 here, YES 1,500 / NO 0 on-chain",
+    ] {
+        assert!(reply.contains(line), "missing {:?} in {}", line, reply);
+    }
+}
+
+#[tokio::test]
+async fn reconcile_is_for_operators() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+
+    reconcile(&h, ALICE).await;
+
+    assert_eq!(h.last_reply(), "⛔ This command is reserved for bot operators.");
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn refused_admin_key_is_reported() {
+    let h = Harness::new().await;
+    h.api.fail_next(MarketApiError::Forbidden { body: "{\"error\":\"admin_key_invalid\"}".to_string() });
+    handle_set_admin(h.messenger(), group_message(OPERATOR, "op", "/setadmin"), h.ctx.clone(), String::new())
+        .await
+        .unwrap();
+
+    assert!(h.last_reply().contains("admin key is missing or wrong"), "{}", h.last_reply());
+}
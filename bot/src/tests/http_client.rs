@@ -0,0 +1,340 @@
+use axum::{
+    extract::State,
+    http::{header::RETRY_AFTER, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+use super::*;
+use crate::api_client::{MarketApiClient, MarketApiClientBuilder, RetryPolicy, REQUEST_ID_HEADER};
+
+const TX_HASH: &str = "abababababababababababababababababababababababababababababababab";
+
+/// A status and the `Retry-After` sent with it.
+type Failure = (StatusCode, Option<&'static str>);
+
+/// The path and `x-request-id` of a request.
+type Received = (String, Option<String>);
+
+/// Failures the mock server answers with, in order, before succeeding; and
+/// every request it got.
+#[derive(Clone, Default)]
+struct Script {
+    failures: Arc<Mutex<VecDeque<Failure>>>,
+    requests: Arc<Mutex<Vec<Received>>>,
+}
+
+async fn respond(State(script): State<Script>, uri: Uri, headers: axum::http::HeaderMap) -> Response {
+    let request_id = headers.get(REQUEST_ID_HEADER).map(|id| id.to_str().unwrap().to_string());
+    script.requests.lock().unwrap().push((uri.path().to_string(), request_id));
+    match script.failures.lock().unwrap().pop_front() {
+        Some((status, Some(retry_after))) => (status, [(RETRY_AFTER, retry_after)]).into_response(),
+        Some((status, None)) => status.into_response(),
+        None => TX_HASH.into_response(),
+    }
+}
+
+/// Serves `router` on a free port, returning its base URL.
+async fn serve(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+/// A client sending each request once to a server answering every request
+/// with `status` and `body`.
+async fn canned_server(status: StatusCode, body: &'static str) -> MarketApiClient {
+    let url = serve(Router::new().fallback(move || async move { (status, body) })).await;
+    MarketApiClient::builder(url)
+        .retry_policy(RetryPolicy { max_attempts: 1, ..RetryPolicy::default() })
+        .build()
+        .unwrap()
+}
+
+async fn mock_server(failures: Vec<Failure>) -> (MarketApiClient, Script) {
+    let script = Script { failures: Arc::new(Mutex::new(failures.into())), ..Script::default() };
+    let url = serve(Router::new().fallback(respond).with_state(script.clone())).await;
+    let client = MarketApiClient::builder(url)
+        .retry_policy(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(2),
+            jitter: 0.0,
+        })
+        .build()
+        .unwrap();
+    (client, script)
+}
+
+fn requests(script: &Script) -> Vec<String> {
+    script.requests.lock().unwrap().iter().map(|(path, _)| path.clone()).collect()
+}
+
+fn request_ids(script: &Script) -> Vec<String> {
+    script.requests.lock().unwrap().iter().map(|(path, id)| id.clone().unwrap_or_else(|| panic!("{} sent no id", path))).collect()
+}
+
+#[tokio::test]
+async fn reads_are_retried_through_transient_failures() {
+    let unavailable = (StatusCode::SERVICE_UNAVAILABLE, None);
+    let (client, script) = mock_server(vec![unavailable, unavailable]).await;
+
+    assert!(client.health_check().await.unwrap());
+    assert_eq!(requests(&script), ["/_health"; 3]);
+}
+
+#[tokio::test]
+async fn bets_are_never_sent_twice_after_the_server_may_have_taken_them() {
+    let (client, script) = mock_server(vec![(StatusCode::BAD_GATEWAY, None)]).await;
+
+    let err = client.place_bet(ALICE.to_string(), 1, true, 100, "contract1").await.unwrap_err();
+    assert!(matches!(err.kind(), MarketApiError::ServerError { status, .. } if status.as_u16() == 502), "{:?}", err);
+    assert_eq!(requests(&script), ["/api/market/bet"]);
+}
+
+#[tokio::test]
+async fn idempotent_actions_are_retried() {
+    let (client, script) = mock_server(vec![(StatusCode::GATEWAY_TIMEOUT, None)]).await;
+
+    assert_eq!(client.initialize_user(ALICE.to_string(), "contract1").await.unwrap().tx_hash, TX_HASH);
+    assert_eq!(requests(&script), ["/api/market/initialize"; 2]);
+}
+
+#[tokio::test]
+async fn retry_after_is_waited_for_and_marks_a_refused_action() {
+    let (client, script) = mock_server(vec![(StatusCode::SERVICE_UNAVAILABLE, Some("1"))]).await;
+
+    let started = std::time::Instant::now();
+    let receipt = client.place_bet(ALICE.to_string(), 1, true, 100, "contract1").await.unwrap();
+    assert_eq!(receipt.tx_hash, TX_HASH);
+    assert!(started.elapsed() >= Duration::from_secs(1), "retried after {:?}", started.elapsed());
+    assert_eq!(requests(&script), ["/api/market/bet"; 2]);
+}
+
+#[tokio::test]
+async fn retry_after_past_the_longest_delay_is_not_waited_for() {
+    let (client, script) = mock_server(vec![(StatusCode::SERVICE_UNAVAILABLE, Some("60"))]).await;
+
+    assert!(!client.health_check().await.unwrap());
+    assert_eq!(requests(&script), ["/_health"]);
+}
+
+#[tokio::test]
+async fn client_errors_are_never_retried() {
+    let (client, script) = mock_server(vec![(StatusCode::TOO_MANY_REQUESTS, Some("1"))]).await;
+
+    assert!(!client.health_check().await.unwrap());
+    assert_eq!(requests(&script), ["/_health"]);
+}
+
+async fn bet_error(status: StatusCode, body: &'static str) -> MarketApiError {
+    let client = canned_server(status, body).await;
+    let err = client.place_bet(ALICE.to_string(), 1, true, 100, "contract1").await.unwrap_err();
+    assert!(err.request_id().is_some(), "{:?}", err);
+    err
+}
+
+#[tokio::test]
+async fn responses_are_classified_into_error_variants() {
+    let err = bet_error(StatusCode::BAD_REQUEST, "Insufficient balance").await;
+    assert!(matches!(err.kind(), MarketApiError::ContractRejected { message } if message == "Insufficient balance"), "{:?}", err);
+    // Refused by the server's dry run before submitting
+    let err = bet_error(StatusCode::UNPROCESSABLE_ENTITY, "Market is closed").await;
+    assert!(matches!(err.kind(), MarketApiError::ContractRejected { message } if message == "Market is closed"), "{:?}", err);
+
+    let err = bet_error(StatusCode::FORBIDDEN, "admin_key_invalid").await;
+    assert!(matches!(err.kind(), MarketApiError::Forbidden { body } if body == "admin_key_invalid"), "{:?}", err);
+
+    let err = bet_error(StatusCode::ACCEPTED, TX_HASH).await;
+    assert!(matches!(err.kind(), MarketApiError::Pending { tx_hash } if tx_hash == TX_HASH), "{:?}", err);
+
+    let err = bet_error(StatusCode::INTERNAL_SERVER_ERROR, "boom").await;
+    assert!(
+        matches!(err.kind(), MarketApiError::ServerError { status, body } if status.as_u16() == 500 && body == "boom"),
+        "{:?}",
+        err
+    );
+
+    // A success whose body is not a transaction hash
+    let err = bet_error(StatusCode::OK, "<html>proxy</html>").await;
+    assert!(matches!(err.kind(), MarketApiError::Deserialization(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn undecodable_reads_are_deserialization_errors() {
+    let client = canned_server(StatusCode::OK, "not json").await;
+    let err = client.get_treasury("contract1").await.unwrap_err();
+    assert!(matches!(err.kind(), MarketApiError::Deserialization(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn unreachable_servers_are_transport_errors() {
+    // A port nothing listens on anymore
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let client = MarketApiClient::builder(url)
+        .retry_policy(RetryPolicy { max_attempts: 1, ..RetryPolicy::default() })
+        .build()
+        .unwrap();
+
+    let err = client.place_bet(ALICE.to_string(), 1, true, 100, "contract1").await.unwrap_err();
+    assert!(matches!(err.kind(), MarketApiError::Transport(_)), "{:?}", err);
+    assert!(err.to_string().starts_with("could not reach the market server"), "{}", err);
+}
+
+#[tokio::test]
+async fn silent_servers_time_out_within_the_request_timeout() {
+    // Accepts connections and holds them without ever answering
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((socket, _)) = listener.accept().await {
+            held.push(socket);
+        }
+    });
+    let client = MarketApiClient::builder(url)
+        .request_timeout(Duration::from_millis(200))
+        .retry_policy(RetryPolicy { max_attempts: 1, ..RetryPolicy::default() })
+        .build()
+        .unwrap();
+
+    let started = std::time::Instant::now();
+    let err = client.place_bet(ALICE.to_string(), 1, true, 100, "contract1").await.unwrap_err();
+    assert!(matches!(err.kind(), MarketApiError::Timeout), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(2), "gave up after {:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn requests_name_the_bot_in_their_user_agent() {
+    let agents = Arc::new(Mutex::new(Vec::new()));
+    let recorded = agents.clone();
+    let router = Router::new().fallback(move |headers: axum::http::HeaderMap| async move {
+        recorded.lock().unwrap().push(headers["user-agent"].to_str().unwrap().to_string());
+        TX_HASH
+    });
+    let client = MarketApiClient::builder(serve(router).await).build().unwrap();
+
+    client.health_check().await.unwrap();
+    assert_eq!(agents.lock().unwrap().clone(), [format!("groupchat-market-bot/{}", env!("CARGO_PKG_VERSION"))]);
+}
+
+#[tokio::test]
+async fn every_request_carries_its_own_request_id() {
+    let (client, script) = mock_server(vec![]).await;
+
+    client.health_check().await.unwrap();
+    client.place_bet(ALICE.to_string(), 1, true, 100, "contract1").await.unwrap();
+    // Neither decodes the hash the mock answers with, but both are sent
+    client.get_treasury("contract1").await.unwrap_err();
+    client.reconcile(&ReconcileSnapshot::default()).await.unwrap_err();
+
+    let ids = request_ids(&script);
+    assert_eq!(ids.len(), 4);
+    assert!(ids.iter().all(|id| uuid::Uuid::parse_str(id).is_ok()), "{:?}", ids);
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 4, "{:?}", ids);
+}
+
+#[tokio::test]
+async fn retries_and_errors_keep_the_request_id() {
+    let (client, script) = mock_server(vec![(StatusCode::SERVICE_UNAVAILABLE, None); 3]).await;
+
+    let err = client.get_treasury("contract1").await.unwrap_err();
+    let ids = request_ids(&script);
+    assert_eq!(ids.len(), 3);
+    assert!(ids.iter().all(|id| id == &ids[0]), "{:?}", ids);
+
+    assert_eq!(err.request_id(), Some(ids[0].as_str()));
+    assert!(err.to_string().ends_with(&format!("[request {}]", ids[0])), "{}", err);
+    // The reference users quote is the start of the id
+    let reference = err.reference_code().unwrap();
+    assert_eq!(reference, ids[0].replace('-', "")[..8].to_uppercase());
+}
+
+/// Requests a mock server is handling at once, overall and per `x-user`, and
+/// the most it ever handled together.
+#[derive(Clone, Default)]
+struct Gauge {
+    counts: Arc<Mutex<HashMap<String, (usize, usize)>>>,
+}
+
+impl Gauge {
+    const ALL: &'static str = "";
+
+    fn enter(&self, user: &str) {
+        let mut counts = self.counts.lock().unwrap();
+        for key in [Self::ALL, user] {
+            let (current, peak) = counts.entry(key.to_string()).or_default();
+            *current += 1;
+            *peak = (*peak).max(*current);
+        }
+    }
+
+    fn leave(&self, user: &str) {
+        let mut counts = self.counts.lock().unwrap();
+        for key in [Self::ALL, user] {
+            counts.get_mut(key).unwrap().0 -= 1;
+        }
+    }
+
+    fn peak(&self, key: &str) -> usize {
+        self.counts.lock().unwrap()[key].1
+    }
+}
+
+async fn hold(State(gauge): State<Gauge>, headers: axum::http::HeaderMap) -> &'static str {
+    let user = headers.get("x-user").map_or("", |user| user.to_str().unwrap()).to_string();
+    gauge.enter(&user);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    gauge.leave(&user);
+    TX_HASH
+}
+
+/// A client configured by `configure` against a server holding each request
+/// for a while.
+async fn gauged_server(configure: impl FnOnce(MarketApiClientBuilder) -> MarketApiClientBuilder) -> (MarketApiClient, Gauge) {
+    let gauge = Gauge::default();
+    let url = serve(Router::new().fallback(hold).with_state(gauge.clone())).await;
+    (configure(MarketApiClient::builder(url)).build().unwrap(), gauge)
+}
+
+/// Places a bet for each of `users` at once.
+async fn burst(client: &MarketApiClient, users: impl IntoIterator<Item = i64>) {
+    let bets = users.into_iter().map(|user| {
+        let client = client.clone();
+        tokio::spawn(async move { client.place_bet(user.to_string(), 1, true, 100, "contract1").await })
+    });
+    for bet in futures::future::join_all(bets).await {
+        bet.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn bursts_never_exceed_the_request_limit() {
+    let (client, gauge) = gauged_server(|builder| builder).await;
+    burst(&client, 0..100).await;
+    // The default of 8 permits is reached but never passed
+    assert_eq!(gauge.peak(Gauge::ALL), 8);
+    assert_eq!(client.in_flight(), 0);
+
+    let (client, gauge) = gauged_server(|builder| builder.max_concurrent_requests(3)).await;
+    burst(&client, 0..100).await;
+    assert_eq!(gauge.peak(Gauge::ALL), 3);
+}
+
+#[tokio::test]
+async fn actions_from_one_identity_are_never_in_flight_together() {
+    let (client, gauge) = gauged_server(|builder| builder.serialize_per_identity(true)).await;
+    burst(&client, [ALICE; 10].into_iter().chain([BOB; 10])).await;
+    let alice = format!("{}@contract1", ALICE);
+    let bob = format!("{}@contract1", BOB);
+    assert_eq!((gauge.peak(&alice), gauge.peak(&bob)), (1, 1));
+    // Different identities still go out together
+    assert_eq!(gauge.peak(Gauge::ALL), 2);
+
+    let (client, gauge) = gauged_server(|builder| builder.serialize_per_identity(false)).await;
+    burst(&client, [ALICE; 10]).await;
+    assert!(gauge.peak(&alice) > 1);
+}
@@ -0,0 +1,89 @@
+use teloxide::types::InlineQuery;
+
+use super::*;
+use crate::currency::Currency;
+use crate::handle_inline_query;
+use crate::inline::{market_card, message_link};
+
+const SUPERGROUP: i64 = -1_001_234_567_890;
+const OTHER_CHAT: i64 = -1002;
+
+fn inline_query(from: i64, username: &str, query: &str) -> InlineQuery {
+    serde_json::from_value(serde_json::json!({
+        "id": "query-1",
+        "from": user_json(from, username),
+        "query": query,
+        "offset": "",
+        "chat_type": "sender",
+    }))
+    .unwrap()
+}
+
+/// Bet descriptions of the cards Alice gets for `query`.
+async fn search(h: &Harness, query: &str) -> Vec<String> {
+    handle_inline_query(h.messenger(), inline_query(ALICE, "alice", query), h.ctx.clone()).await.unwrap();
+    h.inline_results().into_iter().map(|article| article.title).collect()
+}
+
+#[test]
+fn message_links_only_exist_for_supergroups() {
+    assert_eq!(message_link(SUPERGROUP, 55), Some("https://t.me/c/1234567890/55".to_string()));
+    assert_eq!(message_link(CHAT_ID, 55), None);
+    assert_eq!(message_link(ALICE, 55), None);
+}
+
+#[tokio::test]
+async fn market_cards_show_the_pools_and_link_back() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    let bet_id = h.ctx.db.create_bet(ALICE, SUPERGROUP, "Will it rain?".to_string(), None).await.unwrap();
+    let bet = h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap();
+
+    let card = market_card(&bet, 300, 1_200, &Currency::default(), Some((SUPERGROUP, 55)));
+
+    assert_eq!(card.id, "bet:1");
+    assert_eq!(card.title, "Market #1: Will it rain?");
+    assert_eq!(card.description, "YES 🪙 300 coins · NO 🪙 1,200 coins");
+    assert_eq!(
+        card.text,
+        "📊 Market #1\n📄 Will it rain?\n✅ YES pool: 🪙 300 coins\n❌ NO pool: 🪙 1,200 coins\n💰 Total pool: 🪙 1,500 coins\n\n\
+         Bet on it in its group with /bet 1 yes|no <amount>"
+    );
+    assert_eq!(card.link, Some(("Open in the group".to_string(), "https://t.me/c/1234567890/55".to_string())));
+    assert_eq!(market_card(&bet, 0, 0, &Currency::default(), Some((CHAT_ID, 55))).link, None);
+}
+
+#[tokio::test]
+async fn inline_search_only_offers_the_users_own_markets() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    let group_bet = h.open_bet(BOB, "Will it rain?").await;
+    h.ctx.db.create_wager(group_bet, ALICE, 100, true).await.unwrap();
+    h.ctx.db.create_wager(group_bet, BOB, 50, false).await.unwrap();
+    let resolved = h.open_bet(ALICE, "Will it rain yesterday?").await;
+    h.ctx.db.close_bet(resolved, true).await.unwrap();
+    // A group Alice never took part in and Bob's private chat with the bot
+    h.ctx.db.create_bet(BOB, OTHER_CHAT, "Will it rain in Paris?".to_string(), None).await.unwrap();
+    h.ctx.db.create_bet(BOB, BOB, "Will it rain on Bob?".to_string(), None).await.unwrap();
+    h.ctx.db.create_bet(ALICE, ALICE, "Will I get wet?".to_string(), None).await.unwrap();
+
+    assert_eq!(search(&h, "rain").await, vec!["Market #1: Will it rain?"]);
+    let card = h.inline_results().remove(0);
+    assert!(card.description.starts_with("YES 🪙 100 coins · NO 🪙 50 coins"), "{}", card.description);
+    assert_eq!(search(&h, "").await, vec!["Market #5: Will I get wet?", "Market #1: Will it rain?"]);
+    assert!(search(&h, "%").await.is_empty());
+    assert_eq!(h.ctx.db.count_inline_impressions(group_bet).await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn former_members_no_longer_find_the_group_markets() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    let group_bet = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.create_bet(ALICE, ALICE, "Will I get wet?".to_string(), None).await.unwrap();
+    h.leave_chat(ALICE);
+
+    assert_eq!(search(&h, "").await, vec!["Market #2: Will I get wet?"]);
+    assert_eq!(h.ctx.db.count_inline_impressions(group_bet).await.unwrap(), 0);
+}
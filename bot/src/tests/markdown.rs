@@ -0,0 +1,55 @@
+use super::*;
+use crate::markdown::{bold, escape, to_plain, RESERVED};
+use crate::{handle_list, handle_new};
+
+#[test]
+fn every_reserved_character_is_escaped() {
+    for c in RESERVED {
+        assert_eq!(escape(&c.to_string()), format!("\\{}", c), "{}", c);
+    }
+    let all: String = RESERVED.iter().collect();
+    assert_eq!(parse_markdown(&escape(&all)).unwrap(), all);
+}
+
+#[test]
+fn other_characters_are_left_alone() {
+    let text = "Héllo @alice, 52% · pool 3,400 ▓░ 👋 ?:;'\"/$&^@<";
+    assert_eq!(escape(text), text);
+}
+
+#[test]
+fn plain_rendering_drops_the_formatting() {
+    assert_eq!(to_plain(&bold("Bet #1: a_b")), "Bet #1: a_b");
+    assert_eq!(to_plain(&format!("{} {}", bold("x"), escape("*y* [z](w).!"))), "x *y* [z](w).!");
+}
+
+#[tokio::test]
+async fn user_content_cannot_break_the_formatting() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "al_ice", 10_000).await;
+    let description = "Will *BTC* hit $100k (by 2025-12-31)? [yes] _maybe_ `x` ~y~ > #1 + = | {} . !";
+    handle_new(h.messenger(), group_message(ALICE, "al_ice", "/new"), h.ctx.clone(), description.to_string())
+        .await
+        .unwrap();
+
+    assert!(h.last_markdown().starts_with("*✅ Market \\#1 created on\\-chain by @al\\_ice*\n"), "{}", h.last_markdown());
+    assert!(h.last_reply().contains(&format!("📄 Description: {}\n", description)), "{}", h.last_reply());
+}
+
+#[tokio::test]
+async fn list_header_is_bold() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.open_bet(ALICE, "Will it rain?").await;
+    handle_list(h.messenger(), group_message(ALICE, "alice", "/list"), h.ctx.clone(), String::new()).await.unwrap();
+
+    assert!(h.last_markdown().starts_with("📄 *AVAILABLE BETS* 📄\n\n🟢 Bet \\#1: Will it rain?"), "{}", h.last_markdown());
+}
+
+#[tokio::test]
+async fn rejected_formatting_falls_back_to_plain_text() {
+    let h = Harness::new().await;
+    h.messenger().send_markdown(ChatId(CHAT_ID), "*Unclosed bold. Still readable").await.unwrap();
+
+    assert_eq!(h.last_reply(), "Unclosed bold. Still readable");
+}
@@ -0,0 +1,81 @@
+use super::*;
+use crate::handle_bet;
+use crate::market_ids::{backfill, Backfill};
+
+fn market(id: u64, creator: i64, description: &str) -> MarketSummary {
+    MarketSummary {
+        id,
+        description: description.to_string(),
+        creator: format!("{}@contract1", creator),
+        status: contract1::MarketStatus::Open,
+        yes_pool: 0,
+        no_pool: 0,
+        bettor_count: 0,
+        opens_at: None,
+        scheduled: false,
+        stake_cap: None,
+        betting_closed: false,
+        tags: vec![],
+        challenge: None,
+        accept_by: None,
+    }
+}
+
+async fn run_backfill(h: &Harness) -> Backfill {
+    backfill(&h.ctx.db, h.ctx.api_client.as_ref(), "contract1", None).await.unwrap()
+}
+
+async fn market_id(h: &Harness, bet_id: i64) -> Option<i64> {
+    h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().market_id
+}
+
+#[tokio::test]
+async fn bets_are_traded_on_their_market_once_the_counters_drifted() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    let snow = h.open_bet(ALICE, "Will it snow?").await;
+    let rain = h.open_bet(ALICE, "Will it rain?").await;
+    // Markets #1 and #2 were created outside the bot
+    h.api.set_markets(vec![
+        market(1, BOB, "Will it snow?"),
+        market(2, BOB, "Pizza on Friday?"),
+        market(3, ALICE, "Will it snow?"),
+        market(4, ALICE, "Will it rain?"),
+    ]);
+
+    assert_eq!(run_backfill(&h).await, Backfill { matched: 2, diverged: 2, unmatched: 0 });
+    assert_eq!(market_id(&h, snow).await, Some(3));
+    assert_eq!(market_id(&h, rain).await, Some(4));
+
+    let args = format!("{} yes 100", snow);
+    handle_bet(h.messenger(), group_message(BOB, "bob", "/bet"), h.ctx.clone(), args).await.unwrap();
+    assert_eq!(h.api.calls(), vec![format!("bet {} #3 yes 100", BOB)]);
+    // The bet keeps its own id in the chat
+    assert!(h.last_reply().contains("Will it snow?"), "{}", h.last_reply());
+
+    // Matched bets are not looked up again
+    assert_eq!(run_backfill(&h).await, Backfill::default());
+}
+
+#[tokio::test]
+async fn lookalike_bets_pair_with_their_markets_in_order() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    let first = h.open_bet(ALICE, "Will it snow?").await;
+    let second = h.open_bet(ALICE, "Will it snow?").await;
+    let orphan = h.open_bet(ALICE, "Will it hail?").await;
+    let known = h.open_bet(ALICE, "Will it snow?").await;
+    h.ctx.db.set_chain_market(known, None, Some(5)).await.unwrap();
+    h.api.set_markets(vec![
+        market(9, ALICE, "Will it snow?"),
+        market(5, ALICE, "Will it snow?"),
+        market(7, ALICE, "Will it snow?"),
+    ]);
+
+    // Market #5 belongs to a bet already, and no market was created for the orphan
+    assert_eq!(run_backfill(&h).await, Backfill { matched: 2, diverged: 2, unmatched: 1 });
+    assert_eq!(market_id(&h, first).await, Some(7));
+    assert_eq!(market_id(&h, second).await, Some(9));
+    assert_eq!(market_id(&h, orphan).await, None);
+}
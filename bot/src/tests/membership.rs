@@ -0,0 +1,222 @@
+use std::time::Instant;
+
+use super::*;
+use crate::deadlines::handle_deadlines;
+use crate::db::CHAT_TABLES;
+use crate::membership::MEMBERSHIP_TTL;
+use crate::{handle_bet, handle_chat_migration, handle_my_chat_member};
+
+/// The bot's own membership in the test group changing from `old` to `new`.
+fn bot_status_change(old: &str, new: &str) -> ChatMemberUpdated {
+    let bot = serde_json::json!({ "id": 1, "is_bot": true, "first_name": "bot", "username": "bot" });
+    serde_json::from_value(serde_json::json!({
+        "chat": { "id": CHAT_ID, "type": "supergroup", "title": "Test group" },
+        "from": user_json(ALICE, "alice"),
+        "date": 1_700_000_000,
+        "old_chat_member": { "user": bot, "status": old },
+        "new_chat_member": { "user": bot, "status": new },
+    }))
+    .unwrap()
+}
+
+/// The id the test group had before it became a supergroup.
+const BASIC_GROUP_ID: i64 = -4_001;
+
+/// The service message Telegram posts in the supergroup once the group
+/// `from_chat_id` was upgraded to it.
+fn migrated_from(from_chat_id: i64) -> Message {
+    serde_json::from_value(serde_json::json!({
+        "message_id": 1,
+        "date": 1_700_000_000,
+        "chat": { "id": CHAT_ID, "type": "supergroup", "title": "Test group" },
+        "from": user_json(ALICE, "alice"),
+        "migrate_from_chat_id": from_chat_id,
+    }))
+    .unwrap()
+}
+
+/// Alice and a group bet created by Bob, with nothing on it yet.
+async fn group_bet(h: &Harness) -> i64 {
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    h.open_bet(BOB, "Will it rain?").await
+}
+
+async fn bet_from_private_chat(h: &Harness, bet_id: i64) {
+    handle_bet(h.messenger(), private_message(ALICE, "alice", "/bet"), h.ctx.clone(), format!("{} yes 100", bet_id))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn members_can_bet_from_a_private_chat() {
+    let h = Harness::new().await;
+    let bet_id = group_bet(&h).await;
+
+    bet_from_private_chat(&h, bet_id).await;
+
+    assert_eq!(h.api.calls(), vec![format!("bet {} #{} yes 100", ALICE, bet_id)]);
+    assert_eq!(h.membership_lookups(), 1);
+}
+
+#[tokio::test]
+async fn former_members_cannot_bet() {
+    let h = Harness::new().await;
+    let bet_id = group_bet(&h).await;
+    h.leave_chat(ALICE);
+
+    bet_from_private_chat(&h, bet_id).await;
+
+    assert_eq!(
+        h.last_reply(),
+        format!("Sorry, only current members of the group where market #{} was created can take part in it.", bet_id)
+    );
+    assert!(h.api.calls().is_empty());
+}
+
+#[tokio::test]
+async fn bets_in_the_group_itself_need_no_lookup() {
+    let h = Harness::new().await;
+    let bet_id = group_bet(&h).await;
+
+    handle_bet(h.messenger(), group_message(ALICE, "alice", "/bet"), h.ctx.clone(), format!("{} yes 100", bet_id))
+        .await
+        .unwrap();
+
+    assert_eq!(h.api.calls().len(), 1);
+    assert_eq!(h.membership_lookups(), 0);
+}
+
+#[tokio::test]
+async fn membership_is_cached_until_it_expires() {
+    let h = Harness::new().await;
+    let (chat, alice) = (ChatId(CHAT_ID), UserId(ALICE as u64));
+    let now = Instant::now();
+
+    assert!(h.ctx.membership.is_member_at(&h.messenger(), chat, alice, now).await.unwrap());
+    h.leave_chat(ALICE);
+    assert!(h.ctx.membership.is_member_at(&h.messenger(), chat, alice, now + MEMBERSHIP_TTL / 2).await.unwrap());
+    assert_eq!(h.membership_lookups(), 1);
+
+    // Once the cached answer is too old, Telegram is asked again and the departure shows
+    assert!(!h.ctx.membership.is_member_at(&h.messenger(), chat, alice, now + MEMBERSHIP_TTL).await.unwrap());
+    assert_eq!(h.membership_lookups(), 2);
+}
+
+#[tokio::test]
+async fn removing_the_bot_freezes_the_chat_markets() {
+    let h = Harness::new().await;
+    let bet_id = group_bet(&h).await;
+    h.ctx
+        .db
+        .create_bet(BOB, CHAT_ID, "Will it snow?".to_string(), Some("2026-03-01T12:00:00+00:00".to_string()))
+        .await
+        .unwrap();
+
+    handle_my_chat_member(h.messenger(), bot_status_change("member", "left"), h.ctx.clone()).await.unwrap();
+    assert!(h.ctx.db.is_chat_frozen(CHAT_ID).await.unwrap());
+
+    bet_from_private_chat(&h, bet_id).await;
+    assert_eq!(
+        h.last_reply(),
+        format!("❄️ Market #{} is frozen: the bot was removed from the chat it belongs to.", bet_id)
+    );
+    // Nor does the deadline job try to post in a chat it left
+    handle_deadlines(&h.messenger(), &h.ctx, chrono::Utc::now()).await.unwrap();
+    assert!(h.api.calls().is_empty());
+
+    handle_my_chat_member(h.messenger(), bot_status_change("left", "member"), h.ctx.clone()).await.unwrap();
+    bet_from_private_chat(&h, bet_id).await;
+    assert_eq!(h.api.calls(), vec![format!("bet {} #{} yes 100", ALICE, bet_id)]);
+}
+
+#[tokio::test]
+async fn readding_the_bot_keeps_the_chat_markets() {
+    let h = Harness::new().await;
+    let bet_id = group_bet(&h).await;
+    h.ctx.db.set_auto_expire(CHAT_ID, true).await.unwrap();
+
+    handle_my_chat_member(h.messenger(), bot_status_change("member", "left"), h.ctx.clone()).await.unwrap();
+    handle_my_chat_member(h.messenger(), bot_status_change("left", "member"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.replies(), vec!["👋 Back again! 1 open market of this chat can be bet on again, see /list."]);
+    assert!(h.ctx.db.get_auto_expire(CHAT_ID).await.unwrap());
+    assert_eq!(h.ctx.db.get_open_bets(CHAT_ID).await.unwrap()[0].bet_id, bet_id);
+}
+
+#[tokio::test]
+async fn chat_migrations_move_every_table() {
+    let h = Harness::new().await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    let db = &h.ctx.db;
+    let archived = db.create_bet(BOB, BASIC_GROUP_ID, "Did it rain?".to_string(), None).await.unwrap();
+    db.close_bet(archived, true).await.unwrap();
+    db.archive_resolved_bets("2999-01-01T00:00:00+00:00").await.unwrap();
+    let bet_id = db.create_bet(BOB, BASIC_GROUP_ID, "Will it rain?".to_string(), None).await.unwrap();
+    db.record_llm_usage(BASIC_GROUP_ID, "2026-10", 100, 10, 5).await.unwrap();
+    db.set_chat_budget(BASIC_GROUP_ID, Some(1_000_000)).await.unwrap();
+    db.set_auto_expire(BASIC_GROUP_ID, true).await.unwrap();
+    db.record_announcement(BASIC_GROUP_ID, 10, bet_id).await.unwrap();
+    db.set_live_announcement(bet_id, BASIC_GROUP_ID, 10, "Will it rain?").await.unwrap();
+    db.link_poll("poll", bet_id, BASIC_GROUP_ID, 0).await.unwrap();
+    db.create_broadcast(OPERATOR, "Hello", &[BASIC_GROUP_ID]).await.unwrap();
+    db.begin_operation(BOB, BASIC_GROUP_ID, "bet", "{}").await.unwrap();
+
+    let moved = db.migrate_chat(BASIC_GROUP_ID, CHAT_ID).await.unwrap();
+
+    assert_eq!(moved.iter().map(|(table, _)| *table).collect::<Vec<_>>(), CHAT_TABLES);
+    assert!(moved.iter().all(|(_, rows)| *rows == 1), "{:?}", moved);
+    assert!(db.migrate_chat(BASIC_GROUP_ID, CHAT_ID).await.unwrap().iter().all(|(_, rows)| *rows == 0));
+    assert_eq!(db.get_bet_by_id(bet_id).await.unwrap().unwrap().chat_id, Some(CHAT_ID));
+    assert_eq!(db.get_chat_budget(CHAT_ID).await.unwrap(), Some(1_000_000));
+    assert!(db.get_auto_expire(CHAT_ID).await.unwrap());
+    assert_eq!(db.get_announced_bet(CHAT_ID, 10).await.unwrap(), Some(bet_id));
+}
+
+#[tokio::test]
+async fn chat_migrations_merge_rows_both_chats_have() {
+    let h = Harness::new().await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    let db = &h.ctx.db;
+    let group_bet = db.create_bet(BOB, BASIC_GROUP_ID, "Will it rain?".to_string(), None).await.unwrap();
+    let supergroup_bet = db.create_bet(BOB, CHAT_ID, "Will it snow?".to_string(), None).await.unwrap();
+    for (chat_id, cost, budget, bet_id) in [(BASIC_GROUP_ID, 300, 1_000_000, group_bet), (CHAT_ID, 200, 2_000_000, supergroup_bet)] {
+        db.record_llm_usage(chat_id, "2026-10", 100, 10, cost).await.unwrap();
+        db.set_chat_budget(chat_id, Some(budget)).await.unwrap();
+        db.record_announcement(chat_id, 10, bet_id).await.unwrap();
+    }
+    db.record_llm_usage(BASIC_GROUP_ID, "2026-09", 100, 10, 50).await.unwrap();
+    db.set_auto_expire(BASIC_GROUP_ID, true).await.unwrap();
+    // Joining the supergroup already gave it a settings row
+    db.set_chat_frozen(CHAT_ID, true).await.unwrap();
+
+    let moved: HashMap<_, _> = db.migrate_chat(BASIC_GROUP_ID, CHAT_ID).await.unwrap().into_iter().collect();
+
+    assert_eq!((moved["llm_usage"], moved["chat_budgets"], moved["chat_settings"], moved["bet_announcements"]), (2, 0, 0, 0));
+    // Spend adds up, so the month's budget still counts the group's
+    let october = db.get_llm_usage(CHAT_ID, "2026-10").await.unwrap();
+    assert_eq!((october.evaluations, october.cost_micros), (2, 500));
+    assert_eq!(db.get_llm_usage(CHAT_ID, "2026-09").await.unwrap().cost_micros, 50);
+    assert_eq!(db.get_llm_usage(BASIC_GROUP_ID, "2026-10").await.unwrap().evaluations, 0);
+    // The supergroup's own rows are the newer ones
+    assert_eq!(db.get_chat_budget(CHAT_ID).await.unwrap(), Some(2_000_000));
+    assert!(!db.get_auto_expire(CHAT_ID).await.unwrap());
+    assert!(!db.is_chat_frozen(CHAT_ID).await.unwrap());
+    assert_eq!(db.get_announced_bet(CHAT_ID, 10).await.unwrap(), Some(supergroup_bet));
+    assert_eq!(db.get_bet_by_id(group_bet).await.unwrap().unwrap().chat_id, Some(CHAT_ID));
+}
+
+#[tokio::test]
+async fn supergroup_upgrades_are_confirmed_in_the_new_chat() {
+    let h = Harness::new().await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    let bet_id = h.ctx.db.create_bet(BOB, BASIC_GROUP_ID, "Will it rain?".to_string(), None).await.unwrap();
+
+    handle_chat_migration(h.messenger(), migrated_from(BASIC_GROUP_ID), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(
+        h.replies(),
+        vec!["✅ This group is now a supergroup. Its markets, settings and history came along, nothing was lost."]
+    );
+    assert_eq!(h.ctx.db.get_open_bets(CHAT_ID).await.unwrap()[0].bet_id, bet_id);
+}
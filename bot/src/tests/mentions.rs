@@ -0,0 +1,78 @@
+use super::*;
+use crate::handle_challenge;
+use crate::mentions::{args_with_text_mentions, remember_author, resolve_mention, MentionedUser};
+
+/// Has no username, so only text mentions can name them.
+const CAROL: i64 = 44;
+
+/// `text` sent by alice in the group, where `Carol Smith` is a text mention.
+fn with_text_mention(text: &str) -> Message {
+    let mut message = message_json(group_chat(), ALICE, "alice", text);
+    let offset = text.find("Carol Smith").unwrap();
+    message["entities"] = serde_json::json!([{
+        "type": "text_mention",
+        "offset": offset,
+        "length": "Carol Smith".len(),
+        "user": { "id": CAROL, "is_bot": false, "first_name": "Carol" },
+    }]);
+    serde_json::from_value(message).unwrap()
+}
+
+#[tokio::test]
+async fn group_authors_are_remembered_without_a_balance() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+
+    remember_author(&h.ctx.db, &group_message(BOB, "bob", "hello")).await.unwrap();
+    remember_author(&h.ctx.db, &group_message(ALICE, "alice", "hi")).await.unwrap();
+    remember_author(&h.ctx.db, &private_message(45, "dave", "hi")).await.unwrap();
+
+    assert_eq!(h.ctx.db.get_user_id_by_username("@Bob").await.unwrap(), Some(BOB));
+    assert_eq!(h.ctx.db.get_user_id_by_username("dave").await.unwrap(), None);
+    // Only /init gives a balance, and seen members stay off the leaderboard
+    assert!(h.ctx.db.get_user(BOB).await.unwrap().is_none());
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 1_000);
+    let leaderboard = h.ctx.db.get_leaderboard(10).await.unwrap();
+    assert_eq!(leaderboard.iter().map(|user| user.user_id).collect::<Vec<_>>(), [ALICE]);
+
+    // Renamed members are found under their new username
+    remember_author(&h.ctx.db, &group_message(BOB, "robert", "hello again")).await.unwrap();
+    assert_eq!(h.ctx.db.get_user_id_by_username("bob").await.unwrap(), None);
+    assert_eq!(h.ctx.db.get_user_id_by_username("robert").await.unwrap(), Some(BOB));
+}
+
+#[tokio::test]
+async fn mentions_resolve_by_username_or_text_mention() {
+    let h = Harness::new().await;
+    remember_author(&h.ctx.db, &group_message(BOB, "bob", "hello")).await.unwrap();
+    let msg = with_text_mention("/challenge Carol Smith 100 Rain");
+
+    assert_eq!(
+        resolve_mention(&h.ctx.db, &msg, "bob").await.unwrap(),
+        Some(MentionedUser { user_id: BOB, name: "@bob".to_string() })
+    );
+    // Text mentions carry the user, who does not need to be known
+    assert_eq!(args_with_text_mentions(&msg, "Carol Smith 100 Rain".to_string()), format!("@{} 100 Rain", CAROL));
+    assert_eq!(
+        resolve_mention(&h.ctx.db, &msg, &CAROL.to_string()).await.unwrap(),
+        Some(MentionedUser { user_id: CAROL, name: "Carol Smith".to_string() })
+    );
+    // Without the entity, the same number is just an unknown username
+    assert_eq!(resolve_mention(&h.ctx.db, &group_message(ALICE, "alice", "/challenge"), &CAROL.to_string()).await.unwrap(), None);
+    assert_eq!(resolve_mention(&h.ctx.db, &msg, "nobody").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn members_without_a_username_can_be_challenged() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    let msg = with_text_mention("/challenge Carol Smith 100 Rain");
+
+    handle_challenge(h.messenger(), msg.clone(), h.ctx.clone(), "Carol Smith 100 Rain".to_string()).await.unwrap();
+    assert!(h.last_reply().starts_with("Carol Smith has no balance yet"), "{}", h.last_reply());
+
+    h.ctx.db.create_or_update_user(CAROL, None, 1_000).await.unwrap();
+    handle_challenge(h.messenger(), msg, h.ctx.clone(), "Carol Smith 100 Rain".to_string()).await.unwrap();
+    assert_eq!(h.api.calls(), vec![format!("challenge {} {} 100 Rain", ALICE, CAROL)]);
+    assert!(h.last_reply().starts_with("🥊 Challenge #1: @alice challenges Carol Smith"), "{}", h.last_reply());
+}
@@ -0,0 +1,260 @@
+//! A scripted [`MarketApi`] standing in for the market server, so handlers
+//! run without the server or a node.
+
+use super::*;
+
+/// Records action calls in order and succeeds unless a failure was queued.
+#[derive(Default)]
+pub struct MockMarketApi {
+    calls: Mutex<Vec<String>>,
+    failures: Mutex<VecDeque<MarketApiError>>,
+    pub(super) accounts: Mutex<HashMap<String, UserInfo>>,
+    treasury: Mutex<u128>,
+    markets: Mutex<Vec<MarketSummary>>,
+    histories: Mutex<HashMap<u64, Vec<MarketHistoryPoint>>>,
+    /// Published by `get_config`; sets the balance `initialize_user` grants
+    params: ContractParams,
+    reconcile_report: Mutex<ReconcileReport>,
+    reconciled: Mutex<Option<ReconcileSnapshot>>,
+    /// Markets created so far, numbered from 1 like the contract does
+    created_markets: Mutex<u64>,
+    /// Reported by `resolve_market`, by market; none by default, like older servers
+    resolutions: Mutex<HashMap<u64, ResolveResult>>,
+    /// Reported by `get_open_exposure`, by user; none by default, like older servers
+    exposures: Mutex<HashMap<String, OpenExposure>>,
+    /// Reported by `tx_status`, by hash; others are unknown to the server
+    tx_statuses: Mutex<HashMap<String, TxStatus>>,
+}
+
+impl MockMarketApi {
+    pub fn new(params: ContractParams) -> Self {
+        Self { params, ..Self::default() }
+    }
+
+    pub fn fail_next(&self, error: MarketApiError) {
+        self.failures.lock().unwrap().push_back(error);
+    }
+
+    /// Makes `user_id` known on-chain with `balance`.
+    pub fn set_account(&self, user_id: i64, balance: u128) {
+        let account = UserInfo {
+            identity: format!("{}@contract1", user_id),
+            balance,
+            initialized: true,
+            bets: vec![],
+            current_streak: 0,
+        };
+        self.accounts.lock().unwrap().insert(user_id.to_string(), account);
+    }
+
+    /// Markets returned by `list_markets`.
+    pub fn set_markets(&self, markets: Vec<MarketSummary>) {
+        *self.markets.lock().unwrap() = markets;
+    }
+
+    /// Payouts reported when `market_id` is resolved.
+    pub fn set_resolution(&self, market_id: u64, result: ResolveResult) {
+        self.resolutions.lock().unwrap().insert(market_id, result);
+    }
+
+    /// Stake reported at risk for `user_id` by `get_open_exposure`.
+    pub fn set_exposure(&self, user_id: i64, exposure: OpenExposure) {
+        self.exposures.lock().unwrap().insert(user_id.to_string(), exposure);
+    }
+
+    /// Bets returned by `get_market_history` for `market_id`.
+    pub fn set_history(&self, market_id: u64, history: Vec<MarketHistoryPoint>) {
+        self.histories.lock().unwrap().insert(market_id, history);
+    }
+
+    /// Report returned by `reconcile`.
+    pub fn set_reconcile_report(&self, report: ReconcileReport) {
+        *self.reconcile_report.lock().unwrap() = report;
+    }
+
+    /// The snapshot last sent to `reconcile`.
+    pub fn reconciled(&self) -> Option<ReconcileSnapshot> {
+        self.reconciled.lock().unwrap().clone()
+    }
+
+    pub fn set_treasury(&self, balance: u128) {
+        *self.treasury.lock().unwrap() = balance;
+    }
+
+    pub fn set_tx_status(&self, tx_hash: &str, status: TxState, error: Option<&str>) {
+        let status = TxStatus { tx_hash: tx_hash.to_string(), status, error: error.map(str::to_string) };
+        self.tx_statuses.lock().unwrap().insert(tx_hash.to_string(), status);
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn action(&self, call: String) -> api_client::Result<TxReceipt> {
+        let mut calls = self.calls.lock().unwrap();
+        calls.push(call);
+        if let Some(error) = self.failures.lock().unwrap().pop_front() {
+            return Err(error);
+        }
+        Ok(TxReceipt {
+            tx_hash: format!("tx{}", calls.len()),
+            result: None,
+        })
+    }
+}
+
+#[async_trait]
+impl MarketApi for MockMarketApi {
+    async fn get_config(&self) -> api_client::Result<ConfigResponse> {
+        Ok(ConfigResponse {
+            contract_name: "contract1".to_string(),
+            api_version: 2,
+            params: self.params.clone(),
+            state_epoch: None,
+            paused: false,
+        })
+    }
+
+    /// Idempotent like the contract: only the first call grants a balance.
+    async fn initialize_user(&self, user_id: String, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        let mut receipt = self.action(format!("initialize {}", user_id))?;
+        let already_initialized = self.accounts.lock().unwrap().contains_key(&user_id);
+        if !already_initialized {
+            let id = user_id.parse().expect("numeric user id");
+            self.set_account(id, self.params.initial_balance);
+        }
+        let balance = self.accounts.lock().unwrap()[&user_id].balance;
+        receipt.result = Some(serde_json::to_value(InitializeOutcome { balance, already_initialized }).unwrap());
+        Ok(receipt)
+    }
+
+    async fn create_market(&self, user_id: String, description: String, tags: Vec<String>, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        let tags: String = tags.iter().map(|tag| format!(" #{}", tag)).collect();
+        let mut receipt = self.action(format!("create {} {}{}", user_id, description, tags))?;
+        let mut created = self.created_markets.lock().unwrap();
+        *created += 1;
+        receipt.result = Some(serde_json::to_value(CreatedMarket { market_id: *created }).unwrap());
+        Ok(receipt)
+    }
+
+    async fn create_challenge(&self, user_id: String, opponent: String, stake: u128, description: String, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        let mut receipt = self.action(format!("challenge {} {} {} {}", user_id, opponent, stake, description))?;
+        let mut created = self.created_markets.lock().unwrap();
+        *created += 1;
+        receipt.result = Some(serde_json::to_value(CreatedMarket { market_id: *created }).unwrap());
+        Ok(receipt)
+    }
+
+    async fn accept_challenge(&self, user_id: String, market_id: u64, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("accept {} #{}", user_id, market_id))
+    }
+
+    async fn cancel_challenge(&self, user_id: String, market_id: u64, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("cancel {} #{}", user_id, market_id))
+    }
+
+    async fn place_bet(&self, user_id: String, market_id: u64, side: bool, amount: u128, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("bet {} #{} {} {}", user_id, market_id, if side { "yes" } else { "no" }, amount))
+    }
+
+    async fn resolve_market(&self, user_id: String, market_id: u64, outcome: bool, _contract_name: &str) -> api_client::Result<TxReceipt<ResolveResult>> {
+        let receipt = self.action(format!("resolve {} #{} {}", user_id, market_id, if outcome { "yes" } else { "no" }))?;
+        Ok(TxReceipt { tx_hash: receipt.tx_hash, result: self.resolutions.lock().unwrap().get(&market_id).cloned() })
+    }
+
+    async fn close_betting(&self, user_id: String, market_id: u64, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("close {} #{}", user_id, market_id))
+    }
+
+    async fn expire_market(&self, user_id: String, market_id: u64, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("void {} #{}", user_id, market_id))
+    }
+
+    async fn claim_winnings(&self, user_id: String, market_id: u64, _contract_name: &str) -> api_client::Result<TxReceipt<ClaimResult>> {
+        self.action(format!("claim {} #{}", user_id, market_id)).map(TxReceipt::decode)
+    }
+
+    async fn get_balance(&self, user_id: String, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("balance {}", user_id))
+    }
+
+    async fn get_open_exposure(&self, user_id: String, _contract_name: &str) -> api_client::Result<TxReceipt<OpenExposure>> {
+        let receipt = self.action(format!("exposure {}", user_id))?;
+        Ok(TxReceipt { tx_hash: receipt.tx_hash, result: self.exposures.lock().unwrap().get(&user_id).copied() })
+    }
+
+    async fn get_market_info(&self, user_id: String, market_id: u64, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("info {} #{}", user_id, market_id))
+    }
+
+    async fn add_comment(&self, user_id: String, market_id: u64, text: String, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("comment {} #{} {}", user_id, market_id, text))
+    }
+
+    async fn set_admin(&self, user_id: String, new_admin: String, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("set_admin {} {}", user_id, new_admin))
+    }
+
+    async fn withdraw_treasury(&self, user_id: String, to: String, amount: u128, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("withdraw {} {} {}", user_id, to, amount))
+    }
+
+    async fn reset_balances(&self, user_id: String, _contract_name: &str) -> api_client::Result<TxReceipt> {
+        self.action(format!("reset_balances {}", user_id))
+    }
+
+    async fn health_check(&self) -> api_client::Result<bool> {
+        Ok(true)
+    }
+
+    async fn list_markets(&self, filter: &MarketFilter, _contract_name: &str) -> api_client::Result<Vec<MarketSummary>> {
+        let mut markets = self.markets.lock().unwrap().clone();
+        if let Some(tag) = &filter.tag {
+            markets.retain(|market| market.tags.contains(&contract1::normalize_tag(tag)));
+        }
+        Ok(markets)
+    }
+
+    async fn get_odds(&self, market_id: u64, _contract_name: &str) -> api_client::Result<Odds> {
+        Err(MarketApiError::ContractRejected { message: format!("Market {} not found", market_id) })
+    }
+
+    async fn get_market_history(&self, market_id: u64, _contract_name: &str) -> api_client::Result<Vec<MarketHistoryPoint>> {
+        Ok(self.histories.lock().unwrap().get(&market_id).cloned().unwrap_or_default())
+    }
+
+    async fn get_leaderboard(&self, _limit: u32, _contract_name: &str) -> api_client::Result<Vec<(String, u128)>> {
+        Ok(vec![])
+    }
+
+    async fn get_user_bets(&self, _user_id: String, _contract_name: &str) -> api_client::Result<Vec<UserBetInfo>> {
+        Ok(vec![])
+    }
+
+    async fn get_user(&self, user_id: String, contract_name: &str) -> api_client::Result<UserInfo> {
+        let identity = format!("{}@{}", user_id, contract_name);
+        Ok(self.accounts.lock().unwrap().get(&user_id).cloned().unwrap_or(UserInfo { identity, ..UserInfo::default() }))
+    }
+
+    async fn get_treasury(&self, _contract_name: &str) -> api_client::Result<TreasuryInfo> {
+        Ok(TreasuryInfo {
+            balance: *self.treasury.lock().unwrap(),
+            admin: Some(format!("{}@contract1", ALICE)),
+            burned: 0,
+            dividend_pool: 0,
+        })
+    }
+
+    async fn reconcile(&self, snapshot: &ReconcileSnapshot) -> api_client::Result<ReconcileReport> {
+        self.action("reconcile".to_string())?;
+        *self.reconciled.lock().unwrap() = Some(snapshot.clone());
+        Ok(self.reconcile_report.lock().unwrap().clone())
+    }
+
+    async fn tx_status(&self, tx_hash: &str) -> api_client::Result<TxStatus> {
+        self.tx_statuses.lock().unwrap().get(tx_hash).cloned().ok_or_else(|| MarketApiError::ServerError {
+            status: reqwest::StatusCode::NOT_FOUND,
+            body: format!("Unknown transaction {}", tx_hash),
+        })
+    }
+}
@@ -0,0 +1,461 @@
+//! Handler tests: commands run against an in-memory database, a scripted
+//! market API and a transport that records replies instead of sending them.
+
+mod adjust;
+mod alerts;
+mod announcements;
+mod broadcast;
+mod challenges;
+mod claude;
+mod command_menu;
+mod config;
+mod currency;
+mod database;
+mod deadlines;
+mod deep_links;
+mod dryrun;
+mod edits;
+mod event_stream;
+mod hall_of_fame;
+mod handlers;
+mod http_client;
+mod inline;
+mod markdown;
+mod market_ids;
+mod membership;
+mod mentions;
+mod mock_api;
+mod operations;
+mod parse;
+mod polls;
+mod preview;
+mod privacy;
+mod receipts;
+mod retention;
+mod seasons;
+mod send_queue;
+mod timezone;
+mod webhook;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use contract1::api::{
+    ClaimResult, ContractParams, CreatedMarket, InitializeOutcome, MarketFilter, MarketHistoryPoint, MarketSummary, Odds, OpenExposure,
+    ReconcileReport, ReconcileSnapshot, ResolveResult, TreasuryInfo, UserBetInfo, UserInfo,
+};
+use sqlx::sqlite::SqliteJournalMode;
+use teloxide::prelude::*;
+use teloxide::types::{BotCommand, BotCommandScope, CallbackQueryId, InlineQueryId, MessageId};
+use teloxide::{ApiError, RequestError};
+
+use crate::api_client::{self, ConfigResponse, MarketApi, MarketApiError, TxReceipt, TxState, TxStatus};
+use crate::claude::{BetResolution, PriceTable, ResolutionCache, ResolutionContext, Resolver};
+use crate::db::{Database, DatabaseConfig, RetentionPolicy};
+use crate::deadlines::DeadlineConfig;
+use crate::dryrun::DryRunApi;
+use crate::edits::RecentCommands;
+use crate::history::RecentMessages;
+use crate::messenger::{Button, InlineArticle, Messenger, Transport};
+use crate::send_queue::{SendPacing, SendQueue};
+use crate::announcements::AnnouncementEdits;
+use crate::membership::MembershipCache;
+use crate::onboarding::PendingOnboardings;
+use crate::polls::PendingPolls;
+use crate::suggestions::PendingSolves;
+use crate::webhook::OwnActions;
+use crate::BotContext;
+
+pub use mock_api::MockMarketApi;
+
+pub const CHAT_ID: i64 = -1001;
+pub const ALICE: i64 = 42;
+pub const BOB: i64 = 43;
+/// Listed in the harness' operator ids
+pub const OPERATOR: i64 = 7;
+
+/// The bot's @username, which its deep links name
+pub const BOT_USERNAME: &str = "market_bot";
+
+/// Id of the first message the recording transport sends, clear of the ids
+/// used by the test messages.
+pub const FIRST_SENT_ID: i32 = 1_000;
+
+/// Records every reply and answers admin lookups from a fixed list.
+#[derive(Default)]
+pub struct RecordingTransport {
+    sent: Mutex<Vec<(i64, String)>>,
+    /// Buttons of the messages sent with some, by message id
+    buttons: Mutex<Vec<(MessageId, Vec<Button>)>>,
+    answered: Mutex<Vec<Option<String>>>,
+    inline_answers: Mutex<Vec<Vec<InlineArticle>>>,
+    edits: Mutex<Vec<(MessageId, String)>>,
+    /// MarkdownV2 sources of the formatted messages and edits, in order
+    markdown: Mutex<Vec<String>>,
+    /// Messages deleted from the chat, which can no longer be edited
+    deleted: Mutex<HashSet<MessageId>>,
+    admins: Mutex<Vec<UserId>>,
+    /// Users who left the chat they are listed with; everyone else is a member
+    left: Mutex<HashSet<(ChatId, UserId)>>,
+    membership_lookups: Mutex<usize>,
+    /// Chats the bot was removed from, where sending fails
+    kicked: Mutex<HashSet<i64>>,
+    /// Command menus registered, in order
+    commands: Mutex<Vec<(BotCommandScope, Vec<BotCommand>)>>,
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn send_text(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError> {
+        if self.kicked.lock().unwrap().contains(&chat_id.0) {
+            return Err(RequestError::Api(ApiError::BotKicked));
+        }
+        let mut sent = self.sent.lock().unwrap();
+        sent.push((chat_id.0, text));
+        Ok(MessageId(FIRST_SENT_ID + sent.len() as i32 - 1))
+    }
+
+    async fn edit_text(&self, _chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError> {
+        if self.deleted.lock().unwrap().contains(&message_id) {
+            return Err(RequestError::Api(ApiError::MessageToEditNotFound));
+        }
+        self.edits.lock().unwrap().push((message_id, text));
+        Ok(())
+    }
+
+    async fn send_markdown(&self, chat_id: ChatId, text: String) -> Result<MessageId, RequestError> {
+        let plain = parse_markdown(&text)?;
+        self.markdown.lock().unwrap().push(text);
+        self.send_text(chat_id, plain).await
+    }
+
+    async fn edit_markdown(&self, chat_id: ChatId, message_id: MessageId, text: String) -> Result<(), RequestError> {
+        let plain = parse_markdown(&text)?;
+        self.markdown.lock().unwrap().push(text);
+        self.edit_text(chat_id, message_id, plain).await
+    }
+
+    async fn send_buttons(&self, chat_id: ChatId, text: String, buttons: Vec<Button>) -> Result<MessageId, RequestError> {
+        let id = self.send_text(chat_id, text).await?;
+        self.buttons.lock().unwrap().push((id, buttons));
+        Ok(id)
+    }
+
+    async fn answer_callback(&self, _query_id: CallbackQueryId, text: Option<String>) -> Result<(), RequestError> {
+        self.answered.lock().unwrap().push(text);
+        Ok(())
+    }
+
+    async fn answer_inline(&self, _query_id: InlineQueryId, results: Vec<InlineArticle>) -> Result<(), RequestError> {
+        self.inline_answers.lock().unwrap().push(results);
+        Ok(())
+    }
+
+    async fn chat_administrators(&self, _chat_id: ChatId) -> Result<Vec<UserId>, RequestError> {
+        Ok(self.admins.lock().unwrap().clone())
+    }
+
+    async fn chat_membership(&self, chat_id: ChatId, user_id: UserId) -> Result<bool, RequestError> {
+        *self.membership_lookups.lock().unwrap() += 1;
+        Ok(!self.left.lock().unwrap().contains(&(chat_id, user_id)))
+    }
+
+    async fn set_commands(&self, scope: BotCommandScope, commands: Vec<BotCommand>) -> Result<(), RequestError> {
+        self.commands.lock().unwrap().push((scope, commands));
+        Ok(())
+    }
+}
+
+/// Checks MarkdownV2 like Telegram does for the entities the bot uses and
+/// returns how the message reads.
+fn parse_markdown(text: &str) -> Result<String, RequestError> {
+    let rejected = |reason: String| RequestError::Api(ApiError::CantParseEntities(format!("Bad Request: can't parse entities: {}", reason)));
+    let mut open: Vec<char> = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next().ok_or_else(|| rejected("dangling backslash".to_string()))?;
+            }
+            '*' | '_' | '~' | '`' if open.last() == Some(&c) => {
+                open.pop();
+            }
+            '*' | '_' | '~' | '`' => open.push(c),
+            c if crate::markdown::RESERVED.contains(&c) => {
+                return Err(rejected(format!("character '{}' is reserved and must be escaped", c)));
+            }
+            _ => {}
+        }
+    }
+    if let Some(c) = open.pop() {
+        return Err(rejected(format!("can't find end of '{}' entity", c)));
+    }
+    Ok(crate::markdown::to_plain(text))
+}
+
+/// Always returns the same verdict.
+pub struct FixedResolver(pub BetResolution);
+
+#[async_trait]
+impl Resolver for FixedResolver {
+    fn name(&self) -> &str {
+        "test-model"
+    }
+
+    async fn evaluate(&self, _ctx: ResolutionContext) -> anyhow::Result<BetResolution> {
+        Ok(self.0.clone())
+    }
+}
+
+pub fn verdict(resolved: bool, outcome: bool) -> BetResolution {
+    BetResolution {
+        resolved,
+        outcome,
+        reasoning: "the message settles it".to_string(),
+        raw_response: None,
+        usage: None,
+    }
+}
+
+pub struct Harness {
+    pub ctx: Arc<BotContext>,
+    pub api: Arc<MockMarketApi>,
+    pub transport: Arc<RecordingTransport>,
+    /// Sends without pacing, the send queue has its own tests
+    pub send_queue: Arc<SendQueue>,
+}
+
+impl Harness {
+    pub async fn new() -> Self {
+        Self::with_resolver(None).await
+    }
+
+    pub async fn with_resolver(resolver: Option<Arc<dyn Resolver>>) -> Self {
+        Self::build(resolver, ContractParams::default()).await
+    }
+
+    /// A harness whose server published `params` instead of the crate defaults.
+    pub async fn with_params(params: ContractParams) -> Self {
+        Self::build(None, params).await
+    }
+
+    /// A harness whose server reports the contract epoch `state_epoch`.
+    pub async fn with_epoch(state_epoch: u64) -> Self {
+        let h = Self::new().await;
+        h.redeployed(state_epoch).await
+    }
+
+    /// The bot restarted against a contract redeployed with `state_epoch`:
+    /// only the database is kept, and the new epoch is adopted as at startup.
+    pub async fn redeployed(&self, state_epoch: u64) -> Self {
+        self.ctx.db.adopt_epoch(state_epoch).await.unwrap();
+        Self::on_database(self.ctx.db.clone(), None, ContractParams::default(), Some(state_epoch), None)
+    }
+
+    async fn build(resolver: Option<Arc<dyn Resolver>>, params: ContractParams) -> Self {
+        // A single connection: every `:memory:` connection is its own database
+        let config = DatabaseConfig {
+            max_connections: 1,
+            journal_mode: SqliteJournalMode::Memory,
+            ..DatabaseConfig::default()
+        };
+        let db = Arc::new(Database::with_config("sqlite::memory:", config).await.unwrap());
+        db.init().await.unwrap();
+        Self::on_database(db, resolver, params, None, None)
+    }
+
+    /// A harness whose actions go to a contract simulated in memory, as with
+    /// `BOT_MODE=dryrun`, instead of the scripted server.
+    pub async fn dry_run(api: Arc<DryRunApi>) -> Self {
+        let h = Self::new().await;
+        Self::on_database(h.ctx.db.clone(), None, ContractParams::default(), None, Some(api))
+    }
+
+    /// `api_client` replaces the scripted server, which is then left unused.
+    fn on_database(
+        db: Arc<Database>,
+        resolver: Option<Arc<dyn Resolver>>,
+        params: ContractParams,
+        state_epoch: Option<u64>,
+        api_client: Option<Arc<dyn MarketApi>>,
+    ) -> Self {
+        let api = Arc::new(MockMarketApi::new(params.clone()));
+        let ctx = Arc::new(BotContext {
+            db: db.clone(),
+            api_client: api_client.unwrap_or_else(|| api.clone()),
+            contract_name: "contract1".to_string(),
+            retention: RetentionPolicy::default(),
+            deadlines: DeadlineConfig::default(),
+            resolution_cache: ResolutionCache::new(db, Duration::from_secs(3600)),
+            resolver,
+            prices: PriceTable::default(),
+            recent_messages: RecentMessages::new(50),
+            operators: HashSet::from([OPERATOR]),
+            own_actions: OwnActions::default(),
+            params,
+            state_epoch,
+            pending_solves: PendingSolves::default(),
+            pending_onboardings: PendingOnboardings::default(),
+            pending_polls: PendingPolls::default(),
+            membership: MembershipCache::default(),
+            announcement_edits: AnnouncementEdits::default(),
+            recent_commands: RecentCommands::default(),
+            bot_username: Some(BOT_USERNAME.to_string()),
+        });
+
+        Self {
+            ctx,
+            api,
+            transport: Arc::new(RecordingTransport::default()),
+            send_queue: Arc::new(SendQueue::new(SendPacing { per_chat: Duration::ZERO, global: Duration::ZERO, ..SendPacing::default() })),
+        }
+    }
+
+    pub fn messenger(&self) -> Messenger {
+        Messenger::new(self.transport.clone(), self.send_queue.clone())
+    }
+
+    pub fn make_admin(&self, user_id: i64) {
+        self.transport.admins.lock().unwrap().push(UserId(user_id as u64));
+    }
+
+    /// Makes `user_id` a former member of the test group.
+    pub fn leave_chat(&self, user_id: i64) {
+        self.transport.left.lock().unwrap().insert((ChatId(CHAT_ID), UserId(user_id as u64)));
+    }
+
+    /// MarkdownV2 source of the last formatted message or edit.
+    pub fn last_markdown(&self) -> String {
+        self.transport.markdown.lock().unwrap().last().cloned().expect("a formatted message was sent")
+    }
+
+    /// Edits made to sent messages, in order.
+    pub fn edits(&self) -> Vec<(MessageId, String)> {
+        self.transport.edits.lock().unwrap().clone()
+    }
+
+    /// Removes the bot from `chat_id`, so messages sent there fail.
+    pub fn kick_from(&self, chat_id: i64) {
+        self.transport.kicked.lock().unwrap().insert(chat_id);
+    }
+
+    /// Messages sent to `chat_id`, in order.
+    pub fn sent_to(&self, chat_id: i64) -> Vec<String> {
+        self.transport.sent.lock().unwrap().iter().filter(|(chat, _)| *chat == chat_id).map(|(_, text)| text.clone()).collect()
+    }
+
+    pub fn delete_message(&self, message_id: MessageId) {
+        self.transport.deleted.lock().unwrap().insert(message_id);
+    }
+
+    /// How many times Telegram was asked about a membership.
+    pub fn membership_lookups(&self) -> usize {
+        *self.transport.membership_lookups.lock().unwrap()
+    }
+
+    pub fn registered_commands(&self) -> Vec<(BotCommandScope, Vec<BotCommand>)> {
+        self.transport.commands.lock().unwrap().clone()
+    }
+
+    pub fn replies(&self) -> Vec<String> {
+        self.transport.sent.lock().unwrap().iter().map(|(_, text)| text.clone()).collect()
+    }
+
+    pub fn last_reply(&self) -> String {
+        self.replies().pop().expect("the handler replied")
+    }
+
+    /// Buttons of the last message sent with some.
+    pub fn last_buttons(&self) -> Vec<Button> {
+        self.transport.buttons.lock().unwrap().last().map(|(_, buttons)| buttons.clone()).unwrap_or_default()
+    }
+
+    /// Results of the last answered inline query.
+    pub fn inline_results(&self) -> Vec<InlineArticle> {
+        self.transport.inline_answers.lock().unwrap().last().cloned().expect("no inline query answered")
+    }
+
+    /// Texts of the answered button presses.
+    pub fn callback_answers(&self) -> Vec<Option<String>> {
+        self.transport.answered.lock().unwrap().clone()
+    }
+
+    /// A user who went through /init.
+    pub async fn initialized_user(&self, user_id: i64, username: &str, balance: i64) {
+        self.ctx.db.create_or_update_user(user_id, Some(username.to_string()), balance).await.unwrap();
+        self.ctx.db.mark_user_initialized(user_id).await.unwrap();
+    }
+
+    pub async fn open_bet(&self, creator: i64, description: &str) -> i64 {
+        self.ctx.db.create_bet(creator, CHAT_ID, description.to_string(), None).await.unwrap()
+    }
+}
+
+fn user_json(user_id: i64, username: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": user_id,
+        "is_bot": false,
+        "first_name": username,
+        "username": username,
+    })
+}
+
+fn message_json(chat: serde_json::Value, from: i64, username: &str, text: &str) -> serde_json::Value {
+    serde_json::json!({
+        "message_id": 100,
+        "date": 1_700_000_000,
+        "chat": chat,
+        "from": user_json(from, username),
+        "text": text,
+    })
+}
+
+fn group_chat() -> serde_json::Value {
+    serde_json::json!({ "id": CHAT_ID, "type": "supergroup", "title": "Test group" })
+}
+
+/// A message sent by `from` in the test group.
+pub fn group_message(from: i64, username: &str, text: &str) -> Message {
+    serde_json::from_value(message_json(group_chat(), from, username, text)).unwrap()
+}
+
+/// A message sent by `from` in a private chat with the bot.
+pub fn private_message(from: i64, username: &str, text: &str) -> Message {
+    let chat = serde_json::json!({ "id": from, "type": "private", "first_name": username });
+    serde_json::from_value(message_json(chat, from, username, text)).unwrap()
+}
+
+/// A group message replying to `replied_text`, written by `author`.
+pub fn group_reply(from: i64, username: &str, text: &str, author: i64, replied_text: &str) -> Message {
+    let mut message = message_json(group_chat(), from, username, text);
+    let mut replied = message_json(group_chat(), author, "author", replied_text);
+    replied["message_id"] = 99.into();
+    message["reply_to_message"] = replied;
+    serde_json::from_value(message).unwrap()
+}
+
+/// `from` pressing a button with `data` under the bot message `prompt_id`.
+pub fn button_press(from: i64, username: &str, prompt_id: i32, data: &str) -> CallbackQuery {
+    press(group_chat(), from, username, prompt_id, data)
+}
+
+/// Like [`button_press`], under a message of `from`'s private chat with the bot.
+pub fn private_button_press(from: i64, username: &str, prompt_id: i32, data: &str) -> CallbackQuery {
+    let chat = serde_json::json!({ "id": from, "type": "private", "first_name": username });
+    press(chat, from, username, prompt_id, data)
+}
+
+fn press(chat: serde_json::Value, from: i64, username: &str, prompt_id: i32, data: &str) -> CallbackQuery {
+    let mut prompt = message_json(chat, 1, "bot", "prompt");
+    prompt["message_id"] = prompt_id.into();
+    serde_json::from_value(serde_json::json!({
+        "id": "query",
+        "from": user_json(from, username),
+        "message": prompt,
+        "chat_instance": "instance",
+        "data": data,
+    }))
+    .unwrap()
+}
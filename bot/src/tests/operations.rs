@@ -0,0 +1,131 @@
+use super::*;
+use crate::handle_bet;
+use crate::operations::{begin_bet, resume, BetParams};
+
+async fn bet(h: &Harness, args: &str) {
+    handle_bet(h.messenger(), group_message(ALICE, "alice", "/bet"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+}
+
+/// Alice with 1,000 coins and an open bet #1.
+async fn setup() -> Harness {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.open_bet(ALICE, "Will it snow?").await;
+    h
+}
+
+async fn balance(h: &Harness) -> i64 {
+    h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance
+}
+
+async fn wagers(h: &Harness) -> usize {
+    h.ctx.db.get_wagers_for_bet(1).await.unwrap().len()
+}
+
+/// The intent to bet 100 on YES, as logged before it is sent.
+async fn begin(h: &Harness) -> i64 {
+    let params = BetParams { bet_id: 1, side: true, amount: 100 };
+    begin_bet(&h.ctx.db, ALICE, CHAT_ID, params).await.unwrap()
+}
+
+#[tokio::test]
+async fn answered_bets_leave_no_operation_behind() {
+    let h = setup().await;
+
+    bet(&h, "1 yes 100").await;
+    assert!(h.last_reply().starts_with("💰 Bet placed on-chain!"), "{}", h.last_reply());
+    h.api.fail_next(MarketApiError::ContractRejected { message: "Market is closed".to_string() });
+    bet(&h, "1 no 100").await;
+    assert_eq!(h.last_reply(), "❌ Could not place the bet: Market is closed");
+
+    assert!(h.ctx.db.get_unfinished_operations().await.unwrap().is_empty());
+    assert_eq!((wagers(&h).await, balance(&h).await), (1, 900));
+    assert_eq!(resume(&h.messenger(), &h.ctx, true).await.unwrap(), 0);
+    assert_eq!(h.replies().len(), 2);
+}
+
+#[tokio::test]
+async fn bets_sent_without_an_answer_are_reported_after_a_restart() {
+    let h = setup().await;
+    begin(&h).await;
+
+    // Still in flight as far as a running bot knows
+    assert_eq!(resume(&h.messenger(), &h.ctx, false).await.unwrap(), 0);
+    assert!(h.replies().is_empty());
+
+    resume(&h.messenger(), &h.ctx, true).await.unwrap();
+    assert_eq!(
+        h.replies(),
+        vec![
+            "⚠️ @alice, your bet of 🪙 100 coins on #1 was interrupted by a restart of the bot and may not have gone through. Check /me before betting again."
+        ]
+    );
+    assert_eq!((wagers(&h).await, balance(&h).await), (0, 1_000));
+    assert!(h.ctx.db.get_unfinished_operations().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn pending_bets_are_mirrored_once_they_settle() {
+    let h = setup().await;
+    h.api.fail_next(MarketApiError::Pending { tx_hash: "tx9".to_string() });
+    bet(&h, "1 yes 100").await;
+    assert!(h.last_reply().starts_with("⏳ Your request to place the bet was submitted"), "{}", h.last_reply());
+
+    h.api.set_tx_status("tx9", TxState::Pending, None);
+    assert_eq!(resume(&h.messenger(), &h.ctx, true).await.unwrap(), 1);
+    assert_eq!((wagers(&h).await, balance(&h).await), (0, 1_000));
+
+    h.api.set_tx_status("tx9", TxState::Success, None);
+    assert_eq!(resume(&h.messenger(), &h.ctx, false).await.unwrap(), 0);
+    assert_eq!(
+        h.last_reply(),
+        "💰 Bet by @alice confirmed on-chain!\n📝 Market #1: Will it snow?\n🎯 Side: YES ✅\n💵 Amount: 🪙 100 coins\n💳 Remaining balance: 🪙 900 coins\nTransaction: tx9"
+    );
+    assert_eq!((wagers(&h).await, balance(&h).await), (1, 900));
+
+    // Nothing is applied twice
+    resume(&h.messenger(), &h.ctx, true).await.unwrap();
+    assert_eq!((wagers(&h).await, balance(&h).await, h.replies().len()), (1, 900, 2));
+}
+
+#[tokio::test]
+async fn refused_and_forgotten_submissions_are_reported() {
+    let h = setup().await;
+    let refused = begin(&h).await;
+    h.ctx.db.mark_operation_submitted(refused, "tx1").await.unwrap();
+    h.api.set_tx_status("tx1", TxState::Failed, Some("Insufficient balance"));
+    // The server restarted before settling this one
+    let forgotten = begin(&h).await;
+    h.ctx.db.mark_operation_submitted(forgotten, "tx2").await.unwrap();
+
+    assert_eq!(resume(&h.messenger(), &h.ctx, true).await.unwrap(), 0);
+    assert_eq!(
+        h.replies(),
+        vec![
+            "❌ @alice, your bet of 🪙 100 coins on #1 could not be placed: Insufficient balance".to_string(),
+            "⚠️ @alice, your bet of 🪙 100 coins on #1 was interrupted by a restart of the bot and may not have gone through. Check /me before betting again.".to_string(),
+        ]
+    );
+    assert_eq!((wagers(&h).await, balance(&h).await), (0, 1_000));
+}
+
+#[tokio::test]
+async fn applied_bets_get_the_reply_the_crash_swallowed() {
+    let h = setup().await;
+    let operation = begin(&h).await;
+    let balance_after = h.ctx.db.apply_bet_operation(operation, "tx1", 1, ALICE, 100, true).await.unwrap();
+    assert_eq!(balance_after, Some(900));
+    // Applying again, e.g. from a concurrent follow-up, changes nothing
+    assert_eq!(h.ctx.db.apply_bet_operation(operation, "tx1", 1, ALICE, 100, true).await.unwrap(), None);
+
+    // The handler is still sending its reply
+    resume(&h.messenger(), &h.ctx, false).await.unwrap();
+    assert!(h.replies().is_empty());
+
+    resume(&h.messenger(), &h.ctx, true).await.unwrap();
+    assert!(h.last_reply().starts_with("💰 Bet by @alice confirmed on-chain!\n"), "{}", h.last_reply());
+    assert_eq!((wagers(&h).await, balance(&h).await), (1, 900));
+    assert!(h.ctx.db.get_unfinished_operations().await.unwrap().is_empty());
+}
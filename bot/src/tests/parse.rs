@@ -0,0 +1,148 @@
+use crate::parse::{self, amount, duration, market_id, mention, side, signed_amount, Amount, ParseError, Problem, Usage};
+use crate::split_deadline;
+
+#[test]
+fn market_ids() {
+    let cases = [
+        ("1", Ok(1)),
+        ("#12", Ok(12)),
+        ("0", Err(Problem::MarketId("0".to_string()))),
+        ("-3", Err(Problem::MarketId("-3".to_string()))),
+        ("##1", Err(Problem::MarketId("##1".to_string()))),
+        ("first", Err(Problem::MarketId("first".to_string()))),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(market_id(input), expected, "{}", input);
+    }
+}
+
+#[test]
+fn sides() {
+    let cases = [
+        ("yes", Ok(true)),
+        ("Y", Ok(true)),
+        ("NO", Ok(false)),
+        ("n", Ok(false)),
+        ("maybe", Err(Problem::Side("maybe".to_string()))),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(side(input), expected, "{}", input);
+    }
+}
+
+#[test]
+fn amounts() {
+    let cases = [
+        ("100", Ok(Amount::Exact(100))),
+        ("1,000", Ok(Amount::Exact(1_000))),
+        ("2k", Ok(Amount::Exact(2_000))),
+        ("1.5K", Ok(Amount::Exact(1_500))),
+        ("1.25k", Ok(Amount::Exact(1_250))),
+        ("2m", Ok(Amount::Exact(2_000_000))),
+        ("0.5m", Ok(Amount::Exact(500_000))),
+        ("half", Ok(Amount::Half)),
+        ("ALL", Ok(Amount::All)),
+        ("0", Err(Problem::Amount("0".to_string()))),
+        ("-5", Err(Problem::Amount("-5".to_string()))),
+        ("1.5", Err(Problem::Amount("1.5".to_string()))),
+        ("1.2345k", Err(Problem::Amount("1.2345k".to_string()))),
+        ("k", Err(Problem::Amount("k".to_string()))),
+        (".5k", Err(Problem::Amount(".5k".to_string()))),
+        ("ten", Err(Problem::Amount("ten".to_string()))),
+        ("99999999999999999999", Err(Problem::Amount("99999999999999999999".to_string()))),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(amount(input), expected, "{}", input);
+    }
+
+    assert_eq!(Amount::Exact(300).of(1_001), 300);
+    assert_eq!(Amount::Half.of(1_001), 500);
+    assert_eq!(Amount::All.of(1_001), 1_001);
+    assert_eq!(Amount::All.of(-20), 0);
+}
+
+#[test]
+fn signed_amounts() {
+    let cases = [
+        ("+500", Ok(500)),
+        ("-500", Ok(-500)),
+        ("+1.5k", Ok(1_500)),
+        ("-1,000", Ok(-1_000)),
+        ("500", Err(Problem::SignedAmount("500".to_string()))),
+        ("+0", Err(Problem::SignedAmount("+0".to_string()))),
+        ("-all", Err(Problem::SignedAmount("-all".to_string()))),
+        ("+-5", Err(Problem::SignedAmount("+-5".to_string()))),
+        ("+", Err(Problem::SignedAmount("+".to_string()))),
+        ("", Err(Problem::SignedAmount("".to_string()))),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(signed_amount(input), expected, "{}", input);
+    }
+
+    let adjust = |args: &str| parse::command(args, Usage::ADJUST, |args| Ok((args.mention()?, args.signed_amount()?, args.text("the reason")?.to_string())));
+    assert_eq!(adjust("@bob -250 double payout"), Ok(("bob".to_string(), -250, "double payout".to_string())));
+    assert_eq!(
+        adjust("@bob 250 refund").unwrap_err().to_string(),
+        "Invalid adjustment \"250\": expected a signed amount like +500 or -1.5k.\nUsage: /adjust @user <+/-amount> <reason>\nExample: /adjust @bob +500 refund of a bet lost to a bot bug"
+    );
+    assert_eq!(adjust("@bob +250").unwrap_err().problem, Problem::Missing("the reason"));
+}
+
+#[test]
+fn mentions() {
+    let cases = [
+        ("@bob", Ok("bob".to_string())),
+        ("@Bob_99", Ok("Bob_99".to_string())),
+        ("bob", Err(Problem::Mention("bob".to_string()))),
+        ("@", Err(Problem::Mention("@".to_string()))),
+        ("@bob!", Err(Problem::Mention("@bob!".to_string()))),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(mention(input), expected, "{}", input);
+    }
+}
+
+#[test]
+fn durations() {
+    let cases = [
+        ("30m", Ok(chrono::Duration::minutes(30))),
+        ("12H", Ok(chrono::Duration::hours(12))),
+        ("3d", Ok(chrono::Duration::days(3))),
+        ("2w", Ok(chrono::Duration::weeks(2))),
+        ("0d", Err(Problem::Duration("0d".to_string()))),
+        ("3", Err(Problem::Duration("3".to_string()))),
+        ("d", Err(Problem::Duration("d".to_string()))),
+        ("3y", Err(Problem::Duration("3y".to_string()))),
+        ("", Err(Problem::Duration("".to_string()))),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(duration(input), expected, "{}", input);
+    }
+
+    let (description, deadline) = split_deadline("Will it rain? deadline:3d", chrono_tz::Tz::UTC, chrono::Utc::now()).unwrap();
+    let in_three_days = chrono::Utc::now() + chrono::Duration::days(3);
+    assert_eq!(description, "Will it rain?");
+    assert!((in_three_days - deadline.unwrap()).num_seconds().abs() < 5);
+}
+
+#[test]
+fn errors_show_the_problem_and_the_usage() {
+    let read = |args| parse::command(args, Usage::RESOLVE, |args| Ok((args.market_id()?, args.side()?)));
+
+    assert_eq!(read(" 1  no "), Ok((1, false)));
+    let cases = [
+        ("", "Usage: /resolve <bet_id> <yes/no>\nExample: /resolve 1 no"),
+        ("1", "Missing the side (yes or no).\nUsage: /resolve <bet_id> <yes/no>\nExample: /resolve 1 no"),
+        ("one no", "Invalid bet ID \"one\": expected a number like 1 or #1.\nUsage: /resolve <bet_id> <yes/no>\nExample: /resolve 1 no"),
+        ("1 no thanks", "Unexpected \"thanks\" after the arguments.\nUsage: /resolve <bet_id> <yes/no>\nExample: /resolve 1 no"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(read(input).map_err(|e: ParseError| e.to_string()), Err(expected.to_string()), "{}", input);
+    }
+
+    let withdraw = |args| parse::command(args, Usage::WITHDRAW, |args| Ok((args.exact_amount()?, args.user_id_or(7)?)));
+    assert_eq!(withdraw("1k"), Ok((1_000, 7)));
+    assert_eq!(withdraw("1k 42"), Ok((1_000, 42)));
+    assert_eq!(withdraw("all").unwrap_err().problem, Problem::Amount("all".to_string()));
+    assert_eq!(withdraw("10 alice").unwrap_err().problem, Problem::UserId("alice".to_string()));
+}
@@ -0,0 +1,143 @@
+use teloxide::types::Poll;
+
+use super::*;
+use crate::polls::{answer, yes_option};
+use crate::{handle_callback, handle_poll_message, handle_poll_update};
+
+/// Id of the test poll messages, see `message_json`.
+const POLL_MESSAGE_ID: i32 = 100;
+
+fn poll_json(options: &[&str], closed: bool) -> serde_json::Value {
+    serde_json::json!({
+        "id": "poll-1",
+        "question": "Will it rain tomorrow?",
+        "options": options.iter().enumerate().map(|(i, text)| serde_json::json!({ "text": text, "voter_count": i + 1 })).collect::<Vec<_>>(),
+        "is_closed": closed,
+        "total_voter_count": 3,
+        "is_anonymous": false,
+        "type": "regular",
+        "allows_multiple_answers": false,
+    })
+}
+
+fn poll_message(from: i64, options: &[&str]) -> Message {
+    let mut message = message_json(group_chat(), from, "alice", "");
+    let fields = message.as_object_mut().unwrap();
+    fields.remove("text");
+    fields.insert("poll".to_string(), poll_json(options, false));
+    serde_json::from_value(message).unwrap()
+}
+
+/// The final state of the test poll, with one vote for its first option and
+/// two for its second.
+fn closed_poll(options: &[&str]) -> Poll {
+    serde_json::from_value(poll_json(options, true)).unwrap()
+}
+
+async fn post_poll(h: &Harness, options: &[&str]) {
+    handle_poll_message(h.messenger(), poll_message(ALICE, options), h.ctx.clone()).await.unwrap();
+}
+
+async fn press(h: &Harness, from: i64) {
+    let data = format!("poll:{}", POLL_MESSAGE_ID);
+    handle_callback(h.messenger(), button_press(from, "alice", FIRST_SENT_ID, &data), h.ctx.clone())
+        .await
+        .unwrap();
+}
+
+#[test]
+fn options_reading_as_yes_or_no() {
+    for option in ["Yes", "yes!", "Yep", "Yes, obviously", "👍", "✅ Sure", "Oui"] {
+        assert_eq!(answer(option), Some(true), "{}", option);
+    }
+    for option in ["No", "NO.", "Nope", "No way", "👎", "❌", "Nein"] {
+        assert_eq!(answer(option), Some(false), "{}", option);
+    }
+    for option in ["Maybe", "Yesterday", "Nobody", "Not sure", ""] {
+        assert_eq!(answer(option), None, "{}", option);
+    }
+}
+
+#[test]
+fn only_a_yes_and_a_no_make_a_market() {
+    assert_eq!(yes_option(&["Yes", "No"]), Some(0));
+    assert_eq!(yes_option(&["Nah", "Yeah"]), Some(1));
+    assert_eq!(yes_option(&["Yes", "Yes"]), None);
+    assert_eq!(yes_option(&["Yes", "Maybe"]), None);
+    assert_eq!(yes_option(&["Yes", "No", "Maybe"]), None);
+    assert_eq!(yes_option(&["Yes"]), None);
+}
+
+#[tokio::test]
+async fn yes_no_polls_are_offered_as_markets() {
+    let h = Harness::new().await;
+
+    post_poll(&h, &["Pizza", "Sushi"]).await;
+    assert!(h.replies().is_empty());
+
+    post_poll(&h, &["Yes", "No"]).await;
+    assert_eq!(h.last_reply(), "📊 Create a market from this poll?\n“Will it rain tomorrow?”");
+    assert_eq!(h.last_buttons(), vec![("Create market".to_string(), format!("poll:{}", POLL_MESSAGE_ID))]);
+
+    let mut private = poll_message(ALICE, &["Yes", "No"]);
+    private.chat = private_message(ALICE, "alice", "").chat;
+    handle_poll_message(h.messenger(), private, h.ctx.clone()).await.unwrap();
+    assert_eq!(h.replies().len(), 1);
+}
+
+#[tokio::test]
+async fn the_author_turns_the_poll_into_a_market() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.initialized_user(BOB, "bob", 10_000).await;
+    post_poll(&h, &["Yes", "No"]).await;
+
+    press(&h, BOB).await;
+    assert_eq!(h.callback_answers(), vec![Some("Only the author of the poll can turn it into a market.".to_string())]);
+    assert!(h.api.calls().is_empty());
+
+    press(&h, ALICE).await;
+    assert_eq!(h.api.calls(), vec![format!("create {} Will it rain tomorrow?", ALICE)]);
+    assert!(h.last_reply().starts_with("✅ Market #1 created on-chain by @alice"), "{}", h.last_reply());
+    // Replying to the poll names the market
+    assert_eq!(h.ctx.db.get_announced_bet(CHAT_ID, POLL_MESSAGE_ID as i64).await.unwrap(), Some(1));
+
+    // The offer is used up
+    press(&h, ALICE).await;
+    assert_eq!(h.callback_answers().last().unwrap().as_deref(), Some("This offer expired, use /new to create the market."));
+    assert_eq!(h.api.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn the_closed_poll_proposes_a_resolution_once() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    post_poll(&h, &["No", "Yes"]).await;
+    press(&h, ALICE).await;
+
+    let mut open = closed_poll(&["No", "Yes"]);
+    open.is_closed = false;
+    handle_poll_update(h.messenger(), open, h.ctx.clone()).await.unwrap();
+    let before = h.replies().len();
+
+    handle_poll_update(h.messenger(), closed_poll(&["No", "Yes"]), h.ctx.clone()).await.unwrap();
+    handle_poll_update(h.messenger(), closed_poll(&["No", "Yes"]), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.replies().len(), before + 1);
+    assert_eq!(
+        h.last_reply(),
+        "📊 The poll of bet #1 closed: 2 YES, 1 NO.\nProposed resolution: YES. An admin can confirm it with /resolve 1 yes"
+    );
+    // Resolving is left to an admin
+    assert_eq!(h.api.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn polls_without_a_market_are_ignored_when_they_close() {
+    let h = Harness::new().await;
+    post_poll(&h, &["Yes", "No"]).await;
+
+    handle_poll_update(h.messenger(), closed_poll(&["Yes", "No"]), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.replies().len(), 1);
+}
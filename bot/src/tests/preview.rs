@@ -0,0 +1,92 @@
+use contract1::Contract1;
+use sdk::Identity;
+
+use super::*;
+use crate::handle_preview;
+use crate::preview::simulate;
+
+async fn preview(h: &Harness, args: &str) -> String {
+    handle_preview(h.messenger(), group_message(ALICE, "alice", "/preview"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+    h.last_reply()
+}
+
+#[test]
+fn previews_match_what_the_contract_settles() {
+    // Pools before the bet, the previewed bet, then the house edge
+    let cases = [
+        (300, 200, true, 100, 0),
+        (300, 200, false, 100, 0),
+        (0, 0, true, 50, 0),
+        (0, 120, true, 40, 0),
+        (1, 999, false, 7, 0),
+        (300, 200, true, 100, 500),
+        (1, 999, false, 7, 2_000),
+    ];
+    for (yes_pool, no_pool, side, amount, fee_bps) in cases {
+        let user = |name: &str| Identity(format!("{}@contract1", name));
+        let mut state = Contract1::new_with_admin(user("alice"));
+        for name in ["alice", "bob", "carol"] {
+            state.initialize(user(name), false).unwrap();
+        }
+        state.set_house_edge(user("alice"), fee_bps).unwrap();
+        state.create_market(user("alice"), "Will it rain?".to_string(), None, None, vec![], None, None).unwrap();
+        let market_id = *state.markets.keys().next().unwrap();
+        for (name, side, pool) in [("bob", true, yes_pool), ("carol", false, no_pool)] {
+            if pool > 0 {
+                state.place_bet(user(name), market_id, side, pool, None).unwrap();
+            }
+        }
+
+        let preview = simulate(yes_pool, no_pool, side, amount, fee_bps as u32);
+        let before = state.odds(market_id).unwrap();
+        state.place_bet(user("alice"), market_id, side, amount, None).unwrap();
+        let after = state.odds(market_id).unwrap();
+        state.resolve_market(user("alice"), market_id, side, None).unwrap();
+
+        let case = format!("{} / {} then {} on {}, {} bps", yes_pool, no_pool, amount, side, fee_bps);
+        let side_bps = |odds: &contract1::api::Odds| if side { odds.yes_probability_bps } else { odds.no_probability_bps };
+        assert_eq!(preview.probability_before_bps, side_bps(&before), "{}", case);
+        assert_eq!(preview.probability_after_bps, side_bps(&after), "{}", case);
+        assert_eq!(preview.payout, state.claim_result(&user("alice"), market_id).unwrap().payout, "{}", case);
+    }
+}
+
+#[tokio::test]
+async fn preview_shows_the_odds_move_without_betting() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 50).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    h.initialized_user(44, "carol", 1_000).await;
+    let bet_id = h.open_bet(BOB, "Will it rain?").await;
+    h.ctx.db.create_wager(bet_id, BOB, 300, true).await.unwrap();
+    h.ctx.db.create_wager(bet_id, 44, 200, false).await.unwrap();
+
+    assert_eq!(
+        preview(&h, &format!("{} yes 100", bet_id)).await,
+        "🔮 Preview of 🪙 100 coins on YES in bet #1\n📄 Will it rain?\n📊 YES: 60.0% now → 66.6% after your bet\n\
+         💰 If YES wins: 🪙 150 coins back (×1.50) unless others bet after you\n\
+         ⚠️ You only have 🪙 50 coins, so this bet would be refused.\n\nNothing was placed: this is only a simulation."
+    );
+    assert!(h.api.calls().is_empty());
+    assert_eq!(h.ctx.db.get_user(ALICE).await.unwrap().unwrap().balance, 50);
+}
+
+#[tokio::test]
+async fn previews_of_one_sided_markets_say_a_win_returns_stakes() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    h.initialized_user(BOB, "bob", 1_000).await;
+    let bet_id = h.open_bet(BOB, "Will it rain?").await;
+
+    let reply = preview(&h, &format!("{} no half", bet_id)).await;
+    assert!(reply.starts_with("🔮 Preview of 🪙 500 coins on NO in bet #1"), "{}", reply);
+    assert!(reply.contains("📊 NO: 50.0% now → 100.0% after your bet"), "{}", reply);
+    assert!(reply.contains("🪙 500 coins back (×1.00)"), "{}", reply);
+    assert!(reply.contains("Nobody backs YES yet"), "{}", reply);
+    assert!(!reply.contains("⚠️"), "{}", reply);
+
+    assert!(preview(&h, "9 no 10").await.starts_with("Bet #9 not found."));
+    assert!(preview(&h, "1 maybe 10").await.starts_with("Invalid side \"maybe\""));
+}
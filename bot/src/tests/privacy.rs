@@ -0,0 +1,129 @@
+use chrono::Utc;
+
+use super::*;
+use crate::{handle_cleanup, handle_privacy, handle_solve, remember_group_message};
+
+const CAROL: i64 = 44;
+
+/// A resolver remembering the evidence of every prompt it was asked to judge.
+#[derive(Default)]
+struct RecordingResolver {
+    evidence: Mutex<Vec<Vec<String>>>,
+}
+
+#[async_trait]
+impl Resolver for RecordingResolver {
+    fn name(&self) -> &str {
+        "test-model"
+    }
+
+    async fn evaluate(&self, ctx: ResolutionContext) -> anyhow::Result<BetResolution> {
+        self.evidence.lock().unwrap().push(ctx.evidence.into_iter().map(|message| message.text).collect());
+        Ok(verdict(false, false))
+    }
+}
+
+async fn privacy(h: &Harness, msg: Message, args: &str) -> String {
+    handle_privacy(h.messenger(), msg, h.ctx.clone(), args.to_string()).await.unwrap();
+    h.last_reply()
+}
+
+/// A group message with an id below the replied message's (99).
+async fn chat(h: &Harness, message_id: i32, from: i64, text: &str) {
+    let mut msg = group_message(from, "member", text);
+    msg.id = MessageId(message_id);
+    msg.date = Utc::now();
+    remember_group_message(&h.ctx, &msg).await.unwrap();
+}
+
+/// The evidence `/solve <bet> 5 force` replying to `author` sends to the
+/// model; `force` skips the verdict cached by the previous attempt.
+async fn solve_with_context(h: &Harness, resolver: &RecordingResolver, bet_id: i64, author: i64) -> Vec<String> {
+    let msg = group_reply(ALICE, "alice", &format!("/solve {} 5 force", bet_id), author, "It rained");
+    handle_solve(h.messenger(), msg, h.ctx.clone()).await.unwrap();
+    resolver.evidence.lock().unwrap().pop().expect("the model was asked")
+}
+
+#[tokio::test]
+async fn messages_are_only_kept_once_the_chat_opts_in() {
+    let resolver = Arc::new(RecordingResolver::default());
+    let h = Harness::with_resolver(Some(resolver.clone())).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.make_admin(ALICE);
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    assert!(privacy(&h, group_message(BOB, "bob", "/privacy"), "").await.starts_with("🗂 Message history is OFF"));
+    chat(&h, 10, BOB, "Clouds over the bay").await;
+    assert_eq!(h.ctx.recent_messages.len(CHAT_ID), 0);
+    assert_eq!(solve_with_context(&h, &resolver, bet_id, BOB).await, ["It rained"]);
+
+    assert_eq!(
+        privacy(&h, group_message(BOB, "bob", "/privacy optin"), "optin").await,
+        "Only admins can use the /privacy command in group chats."
+    );
+    assert!(privacy(&h, group_message(ALICE, "alice", "/privacy optin"), "optin").await.starts_with("🗂 Message history enabled"));
+    chat(&h, 11, BOB, "Clouds over the bay").await;
+    assert_eq!(solve_with_context(&h, &resolver, bet_id, BOB).await, ["Clouds over the bay", "It rained"]);
+    assert!(privacy(&h, group_message(BOB, "bob", "/privacy"), "").await.contains("(kept right now: 1)"));
+
+    // Opting the chat out forgets what was kept
+    privacy(&h, group_message(ALICE, "alice", "/privacy optout"), "optout").await;
+    assert_eq!(h.ctx.recent_messages.len(CHAT_ID), 0);
+    assert_eq!(solve_with_context(&h, &resolver, bet_id, BOB).await, ["It rained"]);
+}
+
+#[tokio::test]
+async fn opting_out_in_private_redacts_the_user_everywhere() {
+    let resolver = Arc::new(RecordingResolver::default());
+    let h = Harness::with_resolver(Some(resolver.clone())).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.make_admin(ALICE);
+    h.ctx.db.set_message_buffer(CHAT_ID, true).await.unwrap();
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    chat(&h, 10, BOB, "Clouds over the bay").await;
+    chat(&h, 11, CAROL, "Bring an umbrella").await;
+
+    let reply = privacy(&h, private_message(BOB, "bob", "/privacy optout"), "optout").await;
+    assert!(reply.starts_with("🔒 Your group messages will no longer be kept"), "{}", reply);
+    assert_eq!(h.ctx.recent_messages.len(CHAT_ID), 1);
+    chat(&h, 12, BOB, "Thunder now").await;
+    assert_eq!(h.ctx.recent_messages.len(CHAT_ID), 1);
+
+    // The replied message is still judged, without any earlier one
+    assert_eq!(solve_with_context(&h, &resolver, bet_id, BOB).await, ["It rained"]);
+    assert_eq!(solve_with_context(&h, &resolver, bet_id, CAROL).await, ["Bring an umbrella", "It rained"]);
+
+    assert!(privacy(&h, private_message(BOB, "bob", "/privacy"), "").await.starts_with("🔒 You opted out"));
+    privacy(&h, private_message(BOB, "bob", "/privacy optin"), "optin").await;
+    chat(&h, 13, BOB, "Thunder now").await;
+    assert_eq!(solve_with_context(&h, &resolver, bet_id, BOB).await, ["Thunder now", "It rained"]);
+}
+
+#[tokio::test]
+async fn cleanup_drops_messages_past_the_retention_limit() {
+    let h = Harness::new().await;
+    h.ctx.db.set_message_buffer(CHAT_ID, true).await.unwrap();
+    let hours = h.ctx.retention.message_retention_hours;
+    for (message_id, age) in [(10, hours + 1), (11, hours - 1), (12, 0)] {
+        let mut msg = group_message(BOB, "bob", "hello");
+        msg.id = MessageId(message_id);
+        msg.date = Utc::now() - chrono::Duration::hours(age);
+        remember_group_message(&h.ctx, &msg).await.unwrap();
+    }
+    assert_eq!(h.ctx.recent_messages.len(CHAT_ID), 3);
+
+    handle_cleanup(h.messenger(), private_message(ALICE, "alice", "/cleanup"), h.ctx.clone()).await.unwrap();
+
+    assert_eq!(h.ctx.recent_messages.len(CHAT_ID), 2);
+    let kept = h.ctx.recent_messages.preceding_from_author(CHAT_ID, 99, BOB, 5);
+    assert_eq!(kept.iter().map(|message| message.message_id).collect::<Vec<_>>(), [11, 12]);
+}
+
+#[tokio::test]
+async fn unknown_privacy_arguments_show_the_usage() {
+    let h = Harness::new().await;
+    assert_eq!(
+        privacy(&h, group_message(BOB, "bob", "/privacy maybe"), "maybe").await,
+        "Unexpected \"maybe\" after the arguments.\nUsage: /privacy [optin|optout]\nExample: /privacy optin"
+    );
+}
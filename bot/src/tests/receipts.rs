@@ -0,0 +1,112 @@
+use contract1::api::{ClaimResult, ResolveResult};
+
+use super::*;
+use crate::api_client::parse_tx_receipt;
+use crate::handle_resolve;
+
+const HASH: &str = "3f1c2a9e8b7d6c5b4a39281706f5e4d3c2b1a09f8e7d6c5b4a3928170615243a";
+
+/// A resolution as answered by servers before results were sent.
+fn bare_resolution() -> String {
+    format!("\"{}\"", HASH)
+}
+
+fn detailed_resolution() -> String {
+    format!(
+        r#"{{"tx_hash":"{}","result":{{"outcome":true,"total_distributed":4010,"winner_count":3}}}}"#,
+        HASH
+    )
+}
+
+fn detailed_claim() -> String {
+    format!(r#"{{"tx_hash":"{}","result":{{"market_id":7,"payout":1337}}}}"#, HASH)
+}
+
+#[test]
+fn resolutions_decode_with_or_without_a_result() {
+    let bare = parse_tx_receipt(&bare_resolution()).unwrap().decode::<ResolveResult>();
+    assert_eq!(bare, TxReceipt { tx_hash: HASH.to_string(), result: None });
+    // Plain-text servers send the hash unquoted
+    assert_eq!(parse_tx_receipt(HASH).unwrap().decode::<ResolveResult>(), bare);
+
+    let detailed = parse_tx_receipt(&detailed_resolution()).unwrap().decode::<ResolveResult>();
+    assert_eq!(detailed.tx_hash, HASH);
+    assert_eq!(detailed.result, Some(ResolveResult { outcome: true, total_distributed: 4_010, winner_count: 3 }));
+}
+
+#[test]
+fn hashes_are_unquoted_and_validated() {
+    // Quotes, surrounding whitespace and upper case never reach the replies
+    let quoted = parse_tx_receipt(&format!("\"{}\"", HASH.to_uppercase())).unwrap();
+    assert_eq!(quoted.tx_hash, HASH);
+    assert_eq!(parse_tx_receipt(&format!(" {}\n", HASH)).unwrap().tx_hash, HASH);
+    assert_eq!(parse_tx_receipt(&format!(r#"{{"tx_hash":"{}"}}"#, HASH)).unwrap().tx_hash, HASH);
+
+    for body in [
+        String::new(),
+        "\"\"".to_string(),
+        format!("\"{}\"", &HASH[1..]),
+        format!("\"{}0\"", HASH),
+        format!("\"{}g\"", &HASH[1..]),
+        format!(r#"{{"tx_hash":"{}"}}"#, &HASH[..10]),
+        "{\"error\":\"oops\"}".to_string(),
+    ] {
+        let err = parse_tx_receipt(&body).unwrap_err();
+        assert!(matches!(err, MarketApiError::Deserialization(_)), "{:?}: {:?}", body, err);
+    }
+}
+
+#[test]
+fn claims_decode_their_payout() {
+    let claim = parse_tx_receipt(&detailed_claim()).unwrap().decode::<ClaimResult>();
+    assert_eq!(claim.result, Some(ClaimResult { market_id: 7, payout: 1_337 }));
+
+    // An output of another shape is left out rather than failing the call
+    let other = parse_tx_receipt(&detailed_resolution()).unwrap().decode::<ClaimResult>();
+    assert_eq!(other, TxReceipt { tx_hash: HASH.to_string(), result: None });
+}
+
+async fn resolve(h: &Harness, bet_id: i64) -> String {
+    handle_resolve(h.messenger(), group_message(ALICE, "alice", "/resolve"), h.ctx.clone(), format!("{} yes", bet_id))
+        .await
+        .unwrap();
+    h.last_reply()
+}
+
+#[tokio::test]
+async fn resolution_announces_the_payouts() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let paid = h.open_bet(ALICE, "Will it rain?").await;
+    let unbacked = h.open_bet(ALICE, "Will it snow?").await;
+    let unreported = h.open_bet(ALICE, "Will it hail?").await;
+    h.api.set_resolution(paid as u64, ResolveResult { outcome: true, total_distributed: 4_010, winner_count: 3 });
+    h.api.set_resolution(unbacked as u64, ResolveResult { outcome: true, total_distributed: 0, winner_count: 0 });
+
+    let reply = resolve(&h, paid).await;
+    assert!(reply.ends_with("\n\n💰 🪙 4,010 coins paid out to 3 winner(s)!"), "{}", reply);
+    let reply = resolve(&h, unbacked).await;
+    assert!(reply.ends_with("\n\n💰 Nobody backed the outcome, so the pool went to the treasury."), "{}", reply);
+    // Older servers do not report the payouts
+    let reply = resolve(&h, unreported).await;
+    assert!(reply.ends_with(&format!("Transaction: tx{}", h.api.calls().len())), "{}", reply);
+}
+
+#[tokio::test]
+async fn resolutions_held_back_by_a_recent_bet_say_when_they_open() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    // Half a minute of slack keeps the wait at 10 whole minutes
+    let resolvable_at = chrono::Utc::now().timestamp() as u64 + 10 * 60 + 30;
+    let error = contract1::MarketError::ResolutionTooEarly { market_id: bet_id as u64, resolvable_at };
+    h.api.fail_next(MarketApiError::ContractRejected { message: error.to_string() });
+
+    assert_eq!(
+        resolve(&h, bet_id).await,
+        "⏳ Market #1 took a bet moments ago, so others get a chance to react: resolution opens in 10 minutes."
+    );
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+}
@@ -0,0 +1,62 @@
+use super::*;
+
+const RESOLVED_AT: &str = "2024-03-01T12:00:00+00:00";
+
+#[tokio::test]
+async fn bets_resolved_exactly_at_the_cutoff_are_kept() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.create_wager(bet_id, ALICE, 100, true).await.unwrap();
+    h.ctx.db.close_bet_at(bet_id, true, RESOLVED_AT).await.unwrap();
+
+    assert_eq!(h.ctx.db.archive_resolved_bets(RESOLVED_AT).await.unwrap(), 0);
+    assert!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().is_some());
+
+    assert_eq!(h.ctx.db.archive_resolved_bets("2024-03-01T12:00:01+00:00").await.unwrap(), 1);
+    assert!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().is_none());
+    let (archived, is_archived) = h.ctx.db.get_bet_or_archived(bet_id).await.unwrap().unwrap();
+    assert_eq!((archived.status.as_str(), is_archived), ("resolved_yes", true));
+    assert_eq!(h.ctx.db.get_archived_wagers_for_bet(bet_id).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn the_cutoff_counts_from_the_resolution() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    // Created after the cutoff, resolved before it
+    let long_resolved = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.close_bet_at(long_resolved, true, RESOLVED_AT).await.unwrap();
+    // Created before the cutoff, resolved after it
+    let just_resolved = h.open_bet(ALICE, "Will it snow?").await;
+    let cutoff = chrono::Utc::now().to_rfc3339();
+    h.ctx.db.close_bet(just_resolved, false).await.unwrap();
+
+    assert_eq!(h.ctx.db.archive_resolved_bets(&cutoff).await.unwrap(), 1);
+    assert!(h.ctx.db.get_bet_by_id(long_resolved).await.unwrap().is_none());
+    assert!(h.ctx.db.get_bet_by_id(just_resolved).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn open_bets_are_never_archived() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.db.create_wager(bet_id, ALICE, 100, true).await.unwrap();
+
+    assert_eq!(h.ctx.db.archive_resolved_bets("2999-01-01T00:00:00+00:00").await.unwrap(), 0);
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "open");
+    assert_eq!(h.ctx.db.get_wagers_for_bet(bet_id).await.unwrap().len(), 1);
+}
+
+#[test]
+fn cleanup_interval_must_be_at_least_an_hour() {
+    std::env::set_var("CLEANUP_INTERVAL_HOURS", "0");
+    let zero = RetentionPolicy::from_env();
+    std::env::set_var("CLEANUP_INTERVAL_HOURS", "2");
+    let two = RetentionPolicy::from_env();
+    std::env::remove_var("CLEANUP_INTERVAL_HOURS");
+
+    assert_eq!(zero.unwrap_err().to_string(), "CLEANUP_INTERVAL_HOURS must be at least 1");
+    assert_eq!(two.unwrap().interval, Duration::from_secs(2 * 60 * 60));
+}
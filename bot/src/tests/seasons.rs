@@ -0,0 +1,94 @@
+use super::*;
+use crate::{handle_bet, handle_new, handle_resolve};
+
+const FIRST_EPOCH: u64 = 1_700_000_000;
+const SECOND_EPOCH: u64 = 1_800_000_000;
+
+const PREDATES_SEASON: &str =
+    "⌛ Bet #1 predates the current season: the market contract was redeployed since, so it can no longer be traded or resolved.";
+
+async fn new_bet(h: &Harness, description: &str) {
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), description.to_string())
+        .await
+        .unwrap();
+}
+
+async fn bet(h: &Harness, args: &str) {
+    handle_bet(h.messenger(), group_message(ALICE, "alice", "/bet"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+}
+
+/// An open bet created now whose deadline already passed.
+async fn overdue_bet(h: &Harness) -> i64 {
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    let deadline = Some("2020-01-01T00:00:00+00:00".to_string());
+    h.ctx.db.create_bet(ALICE, CHAT_ID, "Will it rain?".to_string(), deadline).await.unwrap()
+}
+
+#[tokio::test]
+async fn bets_keep_the_market_and_epoch_they_were_created_on() {
+    let h = Harness::with_epoch(FIRST_EPOCH).await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    // A bet the contract never heard of takes the first local id
+    h.open_bet(ALICE, "Local only").await;
+
+    new_bet(&h, "Will it rain?").await;
+
+    let created = h.ctx.db.get_bet_by_id(2).await.unwrap().unwrap();
+    assert_eq!((created.epoch, created.market_id), (Some(FIRST_EPOCH as i64), Some(1)));
+    bet(&h, "2 yes 10").await;
+    assert_eq!(h.api.calls().last().unwrap(), &format!("bet {} #1 yes 10", ALICE));
+    assert_eq!(h.ctx.db.get_bet_by_market(1, Some(FIRST_EPOCH)).await.unwrap().unwrap().bet_id, 2);
+}
+
+#[tokio::test]
+async fn bets_from_before_a_redeploy_are_refused() {
+    let h = Harness::with_epoch(FIRST_EPOCH).await;
+    h.initialized_user(ALICE, "alice", 1_000).await;
+    new_bet(&h, "Will it rain?").await;
+
+    let h = h.redeployed(SECOND_EPOCH).await;
+    h.make_admin(ALICE);
+
+    bet(&h, "1 yes 10").await;
+    assert_eq!(h.last_reply(), PREDATES_SEASON);
+    handle_resolve(h.messenger(), group_message(ALICE, "alice", "/resolve"), h.ctx.clone(), "1 yes".to_string())
+        .await
+        .unwrap();
+    assert_eq!(h.last_reply(), PREDATES_SEASON);
+    assert!(h.api.calls().is_empty());
+
+    // The redeployed contract numbers its markets from 1 again
+    new_bet(&h, "Will it snow?").await;
+    bet(&h, "2 no 10").await;
+    assert_eq!(h.api.calls().last().unwrap(), &format!("bet {} #1 no 10", ALICE));
+    assert_eq!(h.ctx.db.get_bet_by_market(1, Some(SECOND_EPOCH)).await.unwrap().unwrap().bet_id, 2);
+    assert_eq!(h.ctx.db.get_bet_by_market(1, Some(FIRST_EPOCH)).await.unwrap().unwrap().bet_id, 1);
+
+    // Only the current season is compared with the chain
+    let pools = h.ctx.db.get_open_pools(Some(SECOND_EPOCH)).await.unwrap();
+    assert_eq!(pools.iter().map(|pool| (pool.market_id, pool.no_pool)).collect::<Vec<_>>(), vec![(1, 10)]);
+}
+
+#[tokio::test]
+async fn bets_without_an_epoch_are_dated_by_their_creation() {
+    let h = Harness::new().await;
+    let bet_id = overdue_bet(&h).await;
+
+    // Deployed before the bet was created: the bet belongs to that deployment
+    let deployed_before = chrono::Utc::now().timestamp() as u64 - 60;
+    assert_eq!(h.ctx.db.adopt_epoch(deployed_before).await.unwrap(), 0);
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().epoch, Some(deployed_before as i64));
+    assert_eq!(h.ctx.db.get_unhandled_deadlines().await.unwrap().len(), 1);
+
+    let h = Harness::new().await;
+    let bet_id = overdue_bet(&h).await;
+
+    // Deployed after: the bet was made on the epoch-less contract before it,
+    // and the deadline scheduler leaves it alone
+    let deployed_after = chrono::Utc::now().timestamp() as u64 + 60;
+    assert_eq!(h.ctx.db.adopt_epoch(deployed_after).await.unwrap(), 1);
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().epoch, Some(0));
+    assert!(h.ctx.db.get_unhandled_deadlines().await.unwrap().is_empty());
+}
@@ -0,0 +1,118 @@
+use teloxide::types::Seconds;
+use tokio::time::Instant;
+
+use super::*;
+use crate::send_queue::Priority;
+
+const GROUP: ChatId = ChatId(CHAT_ID);
+const OTHER_GROUP: ChatId = ChatId(-1002);
+
+/// A sender recording when each message went out, which Telegram refuses
+/// with a 429 as many times as scripted.
+struct MockSender {
+    started: Instant,
+    sent: Mutex<Vec<(Duration, &'static str)>>,
+    rate_limited: Mutex<VecDeque<u32>>,
+}
+
+impl MockSender {
+    fn new() -> Arc<Self> {
+        Arc::new(Self { started: Instant::now(), sent: Mutex::default(), rate_limited: Mutex::default() })
+    }
+
+    /// Refuses the next attempt, asking to wait `seconds`.
+    fn rate_limit_next(&self, seconds: u32) {
+        self.rate_limited.lock().unwrap().push_back(seconds);
+    }
+
+    async fn send(&self, label: &'static str) -> Result<(), RequestError> {
+        if let Some(seconds) = self.rate_limited.lock().unwrap().pop_front() {
+            return Err(RequestError::RetryAfter(Seconds::from_seconds(seconds)));
+        }
+        self.sent.lock().unwrap().push((self.started.elapsed(), label));
+        Ok(())
+    }
+
+    fn sent(&self) -> Vec<(Duration, &'static str)> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+fn send(
+    queue: &Arc<SendQueue>,
+    sender: &Arc<MockSender>,
+    chat_id: ChatId,
+    priority: Priority,
+    label: &'static str,
+) -> tokio::task::JoinHandle<Result<(), RequestError>> {
+    let (queue, sender) = (queue.clone(), sender.clone());
+    tokio::spawn(async move { queue.send(chat_id, priority, || sender.send(label)).await })
+}
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+#[tokio::test(start_paused = true)]
+async fn messages_are_paced_per_chat_and_overall() {
+    let queue = Arc::new(SendQueue::new(SendPacing::default()));
+    let sender = MockSender::new();
+
+    let sends = vec![
+        send(&queue, &sender, GROUP, Priority::Interactive, "first"),
+        send(&queue, &sender, GROUP, Priority::Interactive, "second"),
+        send(&queue, &sender, OTHER_GROUP, Priority::Interactive, "elsewhere"),
+    ];
+    for handle in sends {
+        handle.await.unwrap().unwrap();
+    }
+
+    assert_eq!(sender.sent(), vec![(ms(0), "first"), (ms(40), "elsewhere"), (ms(1_000), "second")]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn interactive_replies_jump_ahead_of_bulk_messages() {
+    let queue = Arc::new(SendQueue::new(SendPacing::default()));
+    let sender = MockSender::new();
+
+    let mut sends = vec![];
+    for label in ["digest 1", "digest 2", "digest 3"] {
+        sends.push(send(&queue, &sender, GROUP, Priority::Bulk, label));
+        tokio::task::yield_now().await;
+    }
+    sends.push(send(&queue, &sender, GROUP, Priority::Interactive, "reply"));
+    for handle in sends {
+        handle.await.unwrap().unwrap();
+    }
+
+    let order: Vec<_> = sender.sent().into_iter().map(|(_, label)| label).collect();
+    assert_eq!(order[..2], ["digest 1", "reply"]);
+    assert_eq!(sender.sent()[1].0, ms(1_000));
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limited_messages_wait_for_retry_after() {
+    let queue = Arc::new(SendQueue::new(SendPacing::default()));
+    let sender = MockSender::new();
+    sender.rate_limit_next(5);
+
+    send(&queue, &sender, GROUP, Priority::Bulk, "announcement").await.unwrap().unwrap();
+    send(&queue, &sender, GROUP, Priority::Interactive, "reply").await.unwrap().unwrap();
+
+    assert_eq!(sender.sent(), vec![(ms(5_000), "announcement"), (ms(6_000), "reply")]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limits_surface_after_the_last_retry() {
+    let queue = Arc::new(SendQueue::new(SendPacing { max_retries: 2, ..SendPacing::default() }));
+    let sender = MockSender::new();
+    for _ in 0..3 {
+        sender.rate_limit_next(1);
+    }
+
+    let result = send(&queue, &sender, GROUP, Priority::Interactive, "reply").await.unwrap();
+
+    assert!(matches!(result, Err(RequestError::RetryAfter(_))), "{:?}", result);
+    assert!(sender.sent().is_empty());
+    assert_eq!(sender.started.elapsed(), ms(2_000));
+}
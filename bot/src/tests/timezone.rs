@@ -0,0 +1,159 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::{Europe::Paris, Tz};
+
+use super::*;
+use crate::timezone::{humanize, parse_local_deadline, to_utc};
+use crate::{handle_info, handle_new, handle_settings, split_deadline};
+
+fn at(rfc3339: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+}
+
+/// Saturday evening in Paris, the night clocks go forward.
+const BEFORE_SPRING_FORWARD: &str = "2026-03-28T20:00:00Z";
+
+async fn settings(h: &Harness, from: i64, args: &str) -> String {
+    handle_settings(h.messenger(), group_message(from, "user", "/settings"), h.ctx.clone(), args.to_string())
+        .await
+        .unwrap();
+    h.last_reply()
+}
+
+#[test]
+fn close_times_are_relative_and_others_name_the_day() {
+    let now = at("2026-03-28T12:00:00Z");
+    let cases = [
+        ("2026-03-28T12:00:30Z", "now"),
+        ("2026-03-28T12:05:00Z", "in 5 minutes"),
+        ("2026-03-28T11:59:00Z", "1 minute ago"),
+        ("2026-03-28T15:00:00Z", "in 3 hours"),
+        ("2026-03-28T10:59:00Z", "1 hour ago"),
+        ("2026-03-28T23:00:00Z", "today at 23:00"),
+        ("2026-03-27T18:02:00Z", "yesterday at 18:02"),
+        ("2026-03-29T09:30:00Z", "tomorrow at 09:30"),
+        ("2026-03-31T10:00:00Z", "Tuesday at 10:00"),
+        ("2026-03-23T10:00:00Z", "last Monday at 10:00"),
+        ("2026-06-01T10:00:00Z", "1 Jun at 10:00"),
+        ("2027-03-03T18:02:00Z", "3 Mar 2027 at 18:02"),
+    ];
+    for (time, expected) in cases {
+        assert_eq!(humanize(at(time), now, Tz::UTC), expected, "{}", time);
+    }
+}
+
+#[test]
+fn days_and_hours_are_those_of_the_chat_across_dst() {
+    // 18:02 is CET the day before the change and CEST the day after
+    let now = at(BEFORE_SPRING_FORWARD);
+    assert_eq!(humanize(at("2026-03-29T16:02:00Z"), now, Paris), "tomorrow at 18:02");
+    assert_eq!(humanize(at("2026-03-29T16:02:00Z"), now, Tz::UTC), "tomorrow at 16:02");
+    let now = at("2026-03-29T20:00:00Z");
+    assert_eq!(humanize(at("2026-03-28T17:02:00Z"), now, Paris), "yesterday at 18:02");
+
+    // Half past midnight in Paris is still the evening before in UTC
+    let now = at("2026-03-28T23:30:00Z");
+    assert_eq!(humanize(at("2026-03-28T17:00:00Z"), now, Paris), "yesterday at 18:00");
+    assert_eq!(humanize(at("2026-03-28T17:00:00Z"), now, Tz::UTC), "today at 17:00");
+
+    // Clocks going back make the day 25 hours long
+    let now = at("2026-10-24T20:00:00Z");
+    assert_eq!(humanize(at("2026-10-25T17:02:00Z"), now, Paris), "tomorrow at 18:02");
+}
+
+#[test]
+fn local_times_skipped_or_repeated_by_dst_resolve_once() {
+    let local = |value: &str| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M").unwrap();
+
+    // 02:30 never happens on the spring day: it means 03:30 CEST
+    assert_eq!(to_utc(local("2026-03-29T02:30"), Paris), Some(at("2026-03-29T01:30:00Z")));
+    // 02:30 happens twice on the autumn day: the first, still CEST, wins
+    assert_eq!(to_utc(local("2026-10-25T02:30"), Paris), Some(at("2026-10-25T00:30:00Z")));
+    assert_eq!(to_utc(local("2026-10-25T02:30"), Tz::UTC), Some(at("2026-10-25T02:30:00Z")));
+}
+
+#[test]
+fn deadlines_are_read_in_the_chat_timezone() {
+    let now = at(BEFORE_SPRING_FORWARD);
+    let cases = [
+        // The end of tomorrow is already on summer time
+        ("tomorrow", Paris, "2026-03-29T21:59:59Z"),
+        ("tomorrow", Tz::UTC, "2026-03-29T23:59:59Z"),
+        ("Tomorrow 18:00", Paris, "2026-03-29T16:00:00Z"),
+        ("today", Paris, "2026-03-28T22:59:59Z"),
+        ("monday 09:00", Paris, "2026-03-30T07:00:00Z"),
+        // A weekday is never today
+        ("saturday", Tz::UTC, "2026-04-04T23:59:59Z"),
+        ("2026-07-01", Paris, "2026-07-01T21:59:59Z"),
+        ("2026-07-01 08:15", Paris, "2026-07-01T06:15:00Z"),
+        ("2026-12-01T08:15", Paris, "2026-12-01T07:15:00Z"),
+        ("2026-12-01T08:15", Tz::UTC, "2026-12-01T08:15:00Z"),
+    ];
+    for (value, tz, expected) in cases {
+        assert_eq!(parse_local_deadline(value, tz, now), Some(at(expected)), "{} in {}", value, tz);
+    }
+    // Past midnight in Paris, today is already the next day
+    assert_eq!(parse_local_deadline("today", Paris, at("2026-03-28T23:30:00Z")), Some(at("2026-03-29T21:59:59Z")));
+
+    for value in ["someday", "tomorrow 25:00", "tomorrow at 18:00", "3d"] {
+        assert_eq!(parse_local_deadline(value, Paris, now), None, "{}", value);
+    }
+
+    let (description, deadline) = split_deadline("Will it rain? deadline:tomorrow 18:00", Paris, now).unwrap();
+    assert_eq!((description.as_str(), deadline), ("Will it rain?", Some(at("2026-03-29T16:00:00Z"))));
+    // Durations and explicit offsets do not depend on the timezone
+    let (_, deadline) = split_deadline("Rain? deadline:3h", Paris, now).unwrap();
+    assert_eq!(deadline, Some(now + chrono::Duration::hours(3)));
+    let (_, deadline) = split_deadline("Rain? deadline:2026-04-01T12:00:00+02:00", Paris, now).unwrap();
+    assert_eq!(deadline, Some(at("2026-04-01T10:00:00Z")));
+    assert_eq!(
+        split_deadline("Rain? deadline:today 18:00", Paris, at("2026-03-28T18:00:00Z")),
+        Err("The deadline must be in the future.".to_string())
+    );
+}
+
+#[tokio::test]
+async fn chats_use_utc_until_an_admin_sets_a_timezone() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    assert_eq!(h.ctx.db.get_timezone(CHAT_ID).await.unwrap(), Tz::UTC);
+    let shown = settings(&h, BOB, "").await;
+    assert!(shown.starts_with("⚙️ Settings of this chat\n🌍 Timezone: UTC (it is "), "{}", shown);
+    assert!(shown.contains("\n💱 Currency: 🪙 100 coins\n⏰ Auto-expire: off\n"), "{}", shown);
+
+    assert_eq!(settings(&h, BOB, "set timezone Europe/Paris").await, "Only admins can change the settings of group chats.");
+    assert_eq!(
+        settings(&h, ALICE, "set timezone Mars/Olympus").await,
+        "Unknown timezone \"Mars/Olympus\": expected a name like Europe/Paris, America/New_York or UTC."
+    );
+    assert_eq!(
+        settings(&h, ALICE, "set currency aura").await,
+        "Usage: /settings [set timezone <name>]\nExample: /settings set timezone Europe/Paris"
+    );
+    assert_eq!(h.ctx.db.get_timezone(CHAT_ID).await.unwrap(), Tz::UTC);
+
+    let reply = settings(&h, ALICE, "set timezone Europe/Paris").await;
+    assert!(reply.starts_with("✅ Times in this chat are now shown and read in Europe/Paris, where it is "), "{}", reply);
+    assert_eq!(h.ctx.db.get_timezone(CHAT_ID).await.unwrap(), Paris);
+    assert!(settings(&h, BOB, "").await.contains("🌍 Timezone: Europe/Paris"));
+    // Other chats keep UTC
+    assert_eq!(h.ctx.db.get_timezone(CHAT_ID - 1).await.unwrap(), Tz::UTC);
+}
+
+#[tokio::test]
+async fn new_deadlines_are_read_and_shown_in_the_chat_timezone() {
+    let h = Harness::new().await;
+    h.make_admin(ALICE);
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    settings(&h, ALICE, "set timezone Europe/Paris").await;
+
+    let args = "Snow on new year? deadline:2099-01-01T18:00".to_string();
+    handle_new(h.messenger(), group_message(ALICE, "alice", "/new"), h.ctx.clone(), args).await.unwrap();
+
+    assert!(h.last_reply().contains("⏰ Deadline: 1 Jan 2099 at 18:00"), "{}", h.last_reply());
+    let bet = h.ctx.db.get_bet_by_id(1).await.unwrap().unwrap();
+    assert_eq!(bet.deadline.as_deref(), Some("2099-01-01T17:00:00+00:00"));
+
+    handle_info(h.messenger(), group_message(BOB, "bob", "/info"), h.ctx.clone(), "1".to_string()).await.unwrap();
+    let info = h.last_reply();
+    assert!(info.contains("\n🕒 Created: now\n⏰ Deadline: 1 Jan 2099 at 18:00"), "{}", info);
+}
@@ -0,0 +1,251 @@
+use contract1::api::{MarketEvent, WebhookPayload};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::Notify;
+
+use super::*;
+use crate::handle_solve;
+use crate::webhook::{announce, verify_signature, OwnAction, SIGNATURE_HEADER, SIGNATURE_TOLERANCE_SECS, TIMESTAMP_HEADER};
+
+const SECRET: &[u8] = b"webhook-secret";
+
+fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn resolved(market_id: u64, outcome: bool) -> MarketEvent {
+    MarketEvent::MarketResolved {
+        market_id,
+        description: "Will it rain?".to_string(),
+        outcome,
+        yes_pool: 300,
+        no_pool: 100,
+    }
+}
+
+fn payload(events: Vec<MarketEvent>) -> WebhookPayload {
+    WebhookPayload { tx_hash: "abc".to_string(), events }
+}
+
+#[test]
+fn signature_verification() {
+    let body = br#"{"tx_hash":"abc","events":[]}"#;
+    let now = 1_700_000_000;
+    let signature = sign(SECRET, now, body);
+    assert!(verify_signature(SECRET, "1700000000", body, &signature, now));
+
+    assert!(!verify_signature(b"other-secret", "1700000000", body, &signature, now));
+    assert!(!verify_signature(SECRET, "1700000000", br#"{"tx_hash":"abd","events":[]}"#, &signature, now));
+    assert!(!verify_signature(SECRET, "1700000000", body, signature.trim_start_matches("sha256="), now));
+    assert!(!verify_signature(SECRET, "1700000000", body, "sha256=not-hex", now));
+    assert!(!verify_signature(SECRET, "1700000000", body, "", now));
+    // The timestamp is signed too, and must be a number
+    assert!(!verify_signature(SECRET, "1700000001", body, &signature, now + 1));
+    assert!(!verify_signature(SECRET, "", body, &signature, now));
+}
+
+#[test]
+fn signatures_expire_outside_the_tolerance() {
+    let body = br#"{"tx_hash":"abc","events":[]}"#;
+    let signed_at = 1_700_000_000;
+    let signature = sign(SECRET, signed_at, body);
+    let verify = |now| verify_signature(SECRET, "1700000000", body, &signature, now);
+
+    assert!(verify(signed_at + SIGNATURE_TOLERANCE_SECS));
+    assert!(verify(signed_at - SIGNATURE_TOLERANCE_SECS));
+    assert!(!verify(signed_at + SIGNATURE_TOLERANCE_SECS + 1));
+    assert!(!verify(signed_at - SIGNATURE_TOLERANCE_SECS - 1));
+}
+
+#[tokio::test]
+async fn resolution_from_elsewhere_is_announced_in_the_market_chat() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    announce(&h.messenger(), &h.ctx, &payload(vec![resolved(bet_id as u64, true)])).await.unwrap();
+
+    let sent = h.transport.sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, CHAT_ID);
+    assert!(sent[0].1.starts_with("✅ MARKET RESOLVED"), "{}", sent[0].1);
+    assert!(sent[0].1.contains("Total pool: 🪙 400 coins"), "{}", sent[0].1);
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "resolved_yes");
+
+    // A redelivery finds the bet closed and stays quiet
+    announce(&h.messenger(), &h.ctx, &payload(vec![resolved(bet_id as u64, true)])).await.unwrap();
+    assert_eq!(h.replies().len(), 1);
+}
+
+/// Holds its verdict back until the test releases it.
+#[derive(Default)]
+struct GatedResolver {
+    started: Notify,
+    release: Notify,
+}
+
+#[async_trait]
+impl Resolver for GatedResolver {
+    fn name(&self) -> &str {
+        "test-model"
+    }
+
+    async fn evaluate(&self, _ctx: ResolutionContext) -> anyhow::Result<BetResolution> {
+        self.started.notify_one();
+        self.release.notified().await;
+        Ok(verdict(false, false))
+    }
+}
+
+#[tokio::test]
+async fn resolution_from_elsewhere_closes_a_bet_being_solved() {
+    let resolver = Arc::new(GatedResolver::default());
+    let h = Harness::with_resolver(Some(resolver.clone())).await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let msg = group_reply(ALICE, "alice", &format!("/solve {}", bet_id), BOB, "Not a cloud");
+    let solve = handle_solve(h.messenger(), msg, h.ctx.clone());
+    let settled_elsewhere = async {
+        resolver.started.notified().await;
+        announce(&h.messenger(), &h.ctx, &payload(vec![resolved(bet_id as u64, false)])).await.unwrap();
+        assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "resolved_no");
+        resolver.release.notify_one();
+    };
+    let (solved, ()) = tokio::join!(solve, settled_elsewhere);
+    solved.unwrap();
+
+    assert!(h.replies().iter().any(|reply| reply.starts_with("✅ MARKET RESOLVED")), "{:?}", h.replies());
+    // The evaluation ending does not reopen it
+    assert_eq!(h.ctx.db.get_bet_by_id(bet_id).await.unwrap().unwrap().status, "resolved_no");
+}
+
+#[tokio::test]
+async fn bet_from_elsewhere_is_announced() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.initialized_user(BOB, "bob", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let event = MarketEvent::BetPlaced {
+        market_id: bet_id as u64,
+        bettor: format!("{}@contract1", BOB),
+        side: false,
+        amount: 250,
+    };
+    announce(&h.messenger(), &h.ctx, &payload(vec![event])).await.unwrap();
+
+    assert_eq!(h.last_reply(), format!("💰 New bet on market #{}: Will it rain?\n👤 @bob put 🪙 250 coins on NO ❌", bet_id));
+}
+
+#[tokio::test]
+async fn own_actions_and_unknown_markets_are_skipped() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.own_actions.record(OwnAction::Resolve { market_id: bet_id as u64 });
+
+    announce(&h.messenger(), &h.ctx, &payload(vec![resolved(bet_id as u64, false), resolved(99, true)]))
+        .await
+        .unwrap();
+
+    assert!(h.replies().is_empty());
+    // The skip is used up: a later event for the market is announced
+    assert!(!h.ctx.own_actions.take(&OwnAction::Resolve { market_id: bet_id as u64 }));
+}
+
+fn milestone(market_id: u64, user_id: i64, streak: u32, bonus: u128) -> MarketEvent {
+    MarketEvent::StreakMilestone {
+        market_id,
+        identity: format!("{}@contract1", user_id),
+        streak,
+        bonus,
+    }
+}
+
+#[tokio::test]
+async fn streak_milestones_are_celebrated_with_the_resolution() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    h.initialized_user(BOB, "bob", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+
+    let events = vec![
+        resolved(bet_id as u64, true),
+        milestone(bet_id as u64, ALICE, 3, 100),
+        milestone(bet_id as u64, BOB, 10, 0),
+    ];
+    announce(&h.messenger(), &h.ctx, &payload(events)).await.unwrap();
+
+    assert_eq!(h.replies().len(), 1, "{:?}", h.replies());
+    assert!(
+        h.last_reply().ends_with("Transaction: abc\n🔥 @alice won 3 markets in a row! Streak bonus: 🪙 100 coins\n🔥 @bob won 10 markets in a row!"),
+        "{}",
+        h.last_reply()
+    );
+}
+
+#[tokio::test]
+async fn streak_milestones_of_an_own_resolution_are_announced_alone() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    h.ctx.own_actions.record(OwnAction::Resolve { market_id: bet_id as u64 });
+
+    let events = vec![resolved(bet_id as u64, true), milestone(bet_id as u64, ALICE, 5, 1_250)];
+    announce(&h.messenger(), &h.ctx, &payload(events)).await.unwrap();
+
+    assert_eq!(h.replies(), vec!["🔥 @alice won 5 markets in a row! Streak bonus: 🪙 1,250 coins".to_string()]);
+}
+
+#[tokio::test]
+async fn bot_bets_are_not_announced_twice() {
+    let h = Harness::new().await;
+    h.initialized_user(ALICE, "alice", 10_000).await;
+    let bet_id = h.open_bet(ALICE, "Will it rain?").await;
+    crate::handle_bet(h.messenger(), group_message(ALICE, "alice", "/bet"), h.ctx.clone(), format!("{} yes 100", bet_id))
+        .await
+        .unwrap();
+
+    let event = MarketEvent::BetPlaced {
+        market_id: bet_id as u64,
+        bettor: format!("{}@contract1", ALICE),
+        side: true,
+        amount: 100,
+    };
+    announce(&h.messenger(), &h.ctx, &payload(vec![event])).await.unwrap();
+
+    assert_eq!(h.replies().len(), 1, "{:?}", h.replies());
+    assert!(h.last_reply().starts_with("💰 Bet placed on-chain!"));
+}
+
+#[tokio::test]
+async fn listener_rejects_bad_signatures() {
+    let h = Harness::new().await;
+    let router = crate::webhook::router(h.messenger(), h.ctx.clone(), "webhook-secret");
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/webhook/events", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    let http = reqwest::Client::new();
+    let post = |signed: Option<(u64, String)>, body: Vec<u8>| {
+        let mut request = http.post(&url).body(body);
+        if let Some((timestamp, signature)) = signed {
+            request = request.header(TIMESTAMP_HEADER, timestamp).header(SIGNATURE_HEADER, signature);
+        }
+        async move { request.send().await.unwrap().status().as_u16() }
+    };
+
+    let now = chrono::Utc::now().timestamp() as u64;
+    let stale = now - SIGNATURE_TOLERANCE_SECS - 60;
+    let body = serde_json::to_vec(&payload(vec![])).unwrap();
+    assert_eq!(post(None, body.clone()).await, 401);
+    assert_eq!(post(Some((now, sign(b"wrong", now, &body))), body.clone()).await, 401);
+    // A captured delivery replayed later
+    assert_eq!(post(Some((stale, sign(SECRET, stale, &body))), body.clone()).await, 401);
+    assert_eq!(post(Some((now, sign(SECRET, now, &body))), body.clone()).await, 200);
+    assert_eq!(post(Some((now, sign(SECRET, now, b"{}"))), b"{}".to_vec()).await, 400);
+}
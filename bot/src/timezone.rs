@@ -0,0 +1,93 @@
+use chrono::{DateTime, Datelike, Days, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// How far off a time may be before it is shown as a day and time rather
+/// than relative to now.
+const RELATIVE_HOURS: i64 = 6;
+
+/// The timezone a chat picked with `/settings set timezone`, e.g.
+/// `Europe/Paris`. Names are the IANA ones, matched case-sensitively.
+pub fn parse(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+/// `at` as read at `now` in a chat living in `tz`: relative while it is
+/// close, e.g. "in 3 hours" or "5 minutes ago", else the local day and time,
+/// e.g. "yesterday at 18:02", "Friday at 09:30" or "3 Mar 2027 at 18:02".
+pub fn humanize(at: DateTime<Utc>, now: DateTime<Utc>, tz: Tz) -> String {
+    let minutes = (at - now).num_minutes();
+    if minutes == 0 {
+        return "now".to_string();
+    }
+    if minutes.abs() < 60 {
+        return relative(minutes, "minute");
+    }
+    if minutes.abs() < RELATIVE_HOURS * 60 {
+        return relative(minutes / 60, "hour");
+    }
+
+    let (local, today) = (at.with_timezone(&tz), now.with_timezone(&tz).date_naive());
+    let day = match (local.date_naive() - today).num_days() {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        -1 => "yesterday".to_string(),
+        2..=6 => local.format("%A").to_string(),
+        -6..=-2 => local.format("last %A").to_string(),
+        _ if local.year() == today.year() => local.format("%-d %b").to_string(),
+        _ => local.format("%-d %b %Y").to_string(),
+    };
+    format!("{} at {}", day, local.format("%H:%M"))
+}
+
+fn relative(count: i64, unit: &str) -> String {
+    let plural = if count.abs() == 1 { "" } else { "s" };
+    if count > 0 {
+        format!("in {} {}{}", count, unit, plural)
+    } else {
+        format!("{} {}{} ago", -count, unit, plural)
+    }
+}
+
+/// A local date and time of `tz` as an instant. Times skipped when clocks go
+/// forward mean the same time on the clocks' previous offset, i.e. an hour
+/// later on the new one; times repeated when they go back mean the first.
+pub fn to_utc(local: NaiveDateTime, tz: Tz) -> Option<DateTime<Utc>> {
+    let resolved = match tz.from_local_datetime(&local) {
+        LocalResult::None => tz.from_local_datetime(&(local + chrono::Duration::hours(1))).earliest(),
+        resolved => resolved.earliest(),
+    };
+    resolved.map(|at| at.with_timezone(&Utc))
+}
+
+/// Reads a deadline written in the chat's local time, at `now`: a date
+/// (`2025-07-01`), a date and time (`2025-07-01T18:00`), or a day relative to
+/// today: `today`, `tomorrow` or a weekday, the next one strictly after
+/// today. Days may be followed by a time, e.g. `tomorrow 18:00`, and
+/// otherwise mean their end.
+pub fn parse_local_deadline(value: &str, tz: Tz, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M") {
+        return to_utc(datetime, tz);
+    }
+
+    let (day, time) = match value.split_once(char::is_whitespace) {
+        Some((day, time)) => (day, Some(NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()?)),
+        None => (value, None),
+    };
+    let today = now.with_timezone(&tz).date_naive();
+    let date = match day.to_lowercase().as_str() {
+        "today" => today,
+        "tomorrow" => today.succ_opt()?,
+        day => match day.parse::<Weekday>() {
+            Ok(weekday) => next_weekday(today, weekday)?,
+            Err(_) => NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?,
+        },
+    };
+    let end_of_day = NaiveTime::from_hms_opt(23, 59, 59)?;
+    to_utc(date.and_time(time.unwrap_or(end_of_day)), tz)
+}
+
+fn next_weekday(today: NaiveDate, weekday: Weekday) -> Option<NaiveDate> {
+    let ahead = (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+    today.checked_add_days(Days::new(if ahead == 0 { 7 } else { ahead as u64 }))
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use contract1::api::{MarketEvent, WebhookPayload};
+use futures::{Stream, StreamExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use teloxide::types::ChatId;
+
+use crate::currency::{format_amount, Currency};
+use crate::markdown;
+use crate::messenger::Messenger;
+use crate::{display_name_for_identity, BotContext, HandlerResult};
+
+/// Header the server signs its deliveries with:
+/// `sha256=<hex hmac of "<timestamp>.<body>">`.
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// Header carrying the unix seconds the server signed the delivery at.
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+
+/// How far, either way, a delivery's timestamp may be from the bot's clock.
+/// Older deliveries are refused, so a captured one cannot be replayed.
+pub const SIGNATURE_TOLERANCE_SECS: u64 = 5 * 60;
+
+/// Actions remembered at most, oldest forgotten first.
+const OWN_ACTIONS_CAPACITY: usize = 256;
+
+/// Checks `signature` against the HMAC of `timestamp`, a `.` and `body` in
+/// constant time, refusing timestamps outside the tolerance around `now`.
+pub fn verify_signature(secret: &[u8], timestamp: &str, body: &[u8], signature: &str, now: u64) -> bool {
+    let Ok(signed_at) = timestamp.parse::<u64>() else {
+        return false;
+    };
+    if signed_at.abs_diff(now) > SIGNATURE_TOLERANCE_SECS {
+        return false;
+    }
+    let Some(expected) = signature.strip_prefix("sha256=").and_then(|hex| hex::decode(hex).ok()) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// An action the bot submits itself, whose event it already announces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnAction {
+    Bet { market_id: u64, identity: String },
+    Resolve { market_id: u64 },
+}
+
+impl OwnAction {
+    /// The action behind `event`; milestones follow from a resolution and
+    /// are never the bot's own.
+    fn of(event: &MarketEvent) -> Option<Self> {
+        match event {
+            MarketEvent::BetPlaced { market_id, bettor, .. } => Some(OwnAction::Bet {
+                market_id: *market_id,
+                identity: bettor.clone(),
+            }),
+            MarketEvent::MarketResolved { market_id, .. } => Some(OwnAction::Resolve { market_id: *market_id }),
+            MarketEvent::StreakMilestone { .. } => None,
+        }
+    }
+}
+
+/// Actions in flight from the bot. They are recorded before the request is
+/// sent, so the webhook can never beat the bot's own reply.
+#[derive(Default)]
+pub struct OwnActions(Mutex<VecDeque<OwnAction>>);
+
+impl OwnActions {
+    pub fn record(&self, action: OwnAction) {
+        let mut actions = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if actions.len() == OWN_ACTIONS_CAPACITY {
+            actions.pop_front();
+        }
+        actions.push_back(action);
+    }
+
+    /// Removes `action`, returning whether it was recorded.
+    pub fn take(&self, action: &OwnAction) -> bool {
+        let mut actions = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match actions.iter().position(|a| a == action) {
+            Some(index) => {
+                actions.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct WebhookState {
+    bot: Messenger,
+    ctx: Arc<BotContext>,
+    secret: Arc<[u8]>,
+}
+
+/// Routes for the server's webhook, mounted on `/webhook/events`.
+pub fn router(bot: Messenger, ctx: Arc<BotContext>, secret: &str) -> Router {
+    Router::new().route("/webhook/events", post(receive)).with_state(WebhookState {
+        bot,
+        ctx,
+        secret: Arc::from(secret.as_bytes()),
+    })
+}
+
+async fn receive(State(state): State<WebhookState>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let timestamp = headers.get(TIMESTAMP_HEADER).and_then(|v| v.to_str().ok());
+    let signature = headers.get(SIGNATURE_HEADER).and_then(|v| v.to_str().ok());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let verified = match (timestamp, signature) {
+        (Some(timestamp), Some(signature)) => verify_signature(&state.secret, timestamp, &body, signature, now),
+        _ => false,
+    };
+    if !verified {
+        log::warn!("Rejected a webhook delivery with a missing, invalid or stale signature");
+        return StatusCode::UNAUTHORIZED;
+    }
+    let payload = match serde_json::from_slice::<WebhookPayload>(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::warn!("Rejected an undecodable webhook delivery: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    // Failures are logged rather than reported: a retry would repeat the
+    // announcements that did go out
+    if let Err(e) = announce(&state.bot, &state.ctx, &payload).await {
+        log::error!("Failed to announce events of tx {}: {}", payload.tx_hash, e);
+    }
+    StatusCode::OK
+}
+
+/// Announces the events of the server's `/api/events` stream, when the
+/// server cannot reach a webhook listener of the bot.
+pub fn spawn_subscription(bot: Messenger, ctx: Arc<BotContext>, events: impl Stream<Item = WebhookPayload> + Send + 'static) {
+    tokio::spawn(async move {
+        let mut events = std::pin::pin!(events);
+        while let Some(payload) = events.next().await {
+            if let Err(e) = announce(&bot, &ctx, &payload).await {
+                log::error!("Failed to announce events of tx {}: {}", payload.tx_hash, e);
+            }
+        }
+    });
+}
+
+/// Posts each event the bot did not trigger itself to the chat its market was
+/// created in. Streak milestones are celebrated in their market's resolution
+/// announcement, or on their own after a resolution the bot announced itself.
+pub async fn announce(bot: &Messenger, ctx: &BotContext, payload: &WebhookPayload) -> HandlerResult {
+    let mut own_resolutions = Vec::new();
+    for event in &payload.events {
+        if let Some(own) = OwnAction::of(event).filter(|own| ctx.own_actions.take(own)) {
+            if let OwnAction::Resolve { market_id } = own {
+                own_resolutions.push(market_id);
+            }
+            continue;
+        }
+        let market_id = event.market_id();
+        if matches!(event, MarketEvent::StreakMilestone { .. }) && !own_resolutions.contains(&market_id) {
+            continue;
+        }
+        let Some(bet) = ctx.db.get_bet_by_market(market_id, ctx.state_epoch).await? else {
+            continue;
+        };
+        let Some(chat_id) = bet.chat_id else {
+            continue;
+        };
+        let currency = ctx.db.get_currency(chat_id).await?;
+
+        let message = match event {
+            MarketEvent::BetPlaced { bettor, side, amount, .. } => markdown::escape(&format!(
+                "💰 New bet on market #{}: {}\n👤 {} put {} on {}",
+                bet.bet_id,
+                bet.description,
+                display_name_for_identity(&ctx.db, bettor).await?,
+                format_amount(&currency, *amount),
+                if *side { "YES ✅" } else { "NO ❌" }
+            )),
+            MarketEvent::MarketResolved { outcome, yes_pool, no_pool, .. } => {
+                // Also settled while a /solve of ours is still evaluating it
+                if bet.status != "open" && bet.status != "resolving" {
+                    continue;
+                }
+                ctx.db.close_bet(bet.bet_id, *outcome).await?;
+                let mut details = format!(
+                    "📊 Market #{}\n📄 Description: {}\n🎯 Outcome: {}\n💰 Total pool: {}\n\nTransaction: {}",
+                    bet.bet_id,
+                    bet.description,
+                    if *outcome { "YES ✅" } else { "NO ❌" },
+                    format_amount(&currency, yes_pool + no_pool),
+                    payload.tx_hash
+                );
+                for milestone in &payload.events {
+                    if let MarketEvent::StreakMilestone { market_id: of, identity, streak, bonus } = milestone {
+                        if *of == market_id {
+                            details.push_str(&format!("\n{}", celebrate(ctx, &currency, identity, *streak, *bonus).await?));
+                        }
+                    }
+                }
+                format!("{}\n\n{}", markdown::bold("✅ MARKET RESOLVED"), markdown::escape(&details))
+            }
+            MarketEvent::StreakMilestone { identity, streak, bonus, .. } => {
+                markdown::escape(&celebrate(ctx, &currency, identity, *streak, *bonus).await?)
+            }
+        };
+        bot.send_markdown(ChatId(chat_id), message).await?;
+    }
+    Ok(())
+}
+
+async fn celebrate(ctx: &BotContext, currency: &Currency, identity: &str, streak: u32, bonus: u128) -> anyhow::Result<String> {
+    let name = display_name_for_identity(&ctx.db, identity).await?;
+    Ok(if bonus > 0 {
+        format!("🔥 {} won {} markets in a row! Streak bonus: {}", name, streak, format_amount(currency, bonus))
+    } else {
+        format!("🔥 {} won {} markets in a row!", name, streak)
+    })
+}
@@ -0,0 +1,76 @@
+//! Contract latency on large synthetic states.
+//!
+//! Sizes default to 10,000 users and 500 open markets of 40 bets each and can
+//! be overridden with BENCH_USERS, BENCH_MARKETS and BENCH_BETTORS:
+//!
+//!     BENCH_USERS=1000 cargo bench -p contract1
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::time::Duration;
+
+use common::{calldata, identity, synthetic_state};
+use contract1::{Contract1, MarketAction};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use sdk::ZkContract;
+
+fn size(var: &str, default: usize) -> usize {
+    std::env::var(var).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn state() -> Contract1 {
+    synthetic_state(
+        size("BENCH_USERS", 10_000),
+        size("BENCH_MARKETS", 500),
+        size("BENCH_BETTORS", 40),
+    )
+}
+
+fn execute(c: &mut Criterion) {
+    let state = state();
+    let user = identity("user0");
+    let market_id = state.next_market_id;
+
+    let mut group = c.benchmark_group("execute");
+    group.measurement_time(Duration::from_secs(10));
+
+    let actions = [
+        ("create_market", MarketAction::CreateMarket { description: "bench".to_string(), opens_at: None, stake_cap: None, tags: vec![], challenge: None }),
+        ("place_bet", MarketAction::PlaceBet { market_id, side: true, amount: 5 }),
+        ("resolve_market", MarketAction::ResolveMarket { market_id, outcome: true }),
+        ("get_market_info", MarketAction::GetMarketInfo { market_id }),
+    ];
+    for (name, action) in actions {
+        let calldata = calldata(&user, &action);
+        // Each iteration gets a fresh copy: resolving twice would only hit the error path
+        group.bench_function(name, |b| {
+            b.iter_batched_ref(
+                || state.clone(),
+                |state| state.execute(&calldata),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn commit(c: &mut Criterion) {
+    let state = state();
+    println!("commitment size: {} bytes", state.commit().0.len());
+
+    let mut group = c.benchmark_group("commit");
+    group.measurement_time(Duration::from_secs(10));
+    group.bench_function("commit", |b| b.iter(|| state.commit()));
+    let commitment = state.commit();
+    group.bench_function("decode", |b| {
+        b.iter(|| Contract1::try_from(commitment.clone()).expect("valid state"))
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = execute, commit
+}
+criterion_main!(benches);
@@ -0,0 +1,913 @@
+use serde::{Deserialize, Serialize};
+
+use sdk::Identity;
+
+use crate::{
+    normalize_tag, parimutuel_payout, streak_bonus, Challenge, Contract1, Market, MarketStatus, StakeCap, UserBet, INITIAL_BALANCE, MIN_BET,
+    RESOLUTION_COOLDOWN,
+};
+
+// Read-only views of the contract state. They are served by the indexer
+// routes and decoded by API clients, so both sides share one schema. They
+// only depend on serde, which keeps them usable without the `client` feature.
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarketSummary {
+    pub id: u64,
+    pub description: String,
+    pub creator: String,
+    pub status: MarketStatus,
+    pub yes_pool: u128,
+    pub no_pool: u128,
+    pub bettor_count: usize,
+    /// Unix seconds at which a scheduled market starts taking bets
+    #[serde(default)]
+    pub opens_at: Option<u64>,
+    /// Still `Open` on-chain but before `opens_at`: presented as scheduled
+    #[serde(default)]
+    pub scheduled: bool,
+    #[serde(default)]
+    pub stake_cap: Option<StakeCap>,
+    #[serde(default)]
+    pub betting_closed: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub challenge: Option<Challenge>,
+    /// Unix seconds until which a pending challenge can be accepted
+    #[serde(default)]
+    pub accept_by: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Odds {
+    pub market_id: u64,
+    pub yes_pool: u128,
+    pub no_pool: u128,
+    /// Implied probability of YES in basis points (0 - 10,000)
+    pub yes_probability_bps: u32,
+    /// Implied probability of NO in basis points (0 - 10,000)
+    pub no_probability_bps: u32,
+}
+
+/// One bet of a market's history, with the pools right after it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarketHistoryPoint {
+    pub bettor: String,
+    pub side: bool,
+    pub amount: u128,
+    /// Unix seconds, when the bet's transaction carried a block time
+    pub timestamp: Option<u64>,
+    pub yes_pool: u128,
+    pub no_pool: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub identity: String,
+    pub balance: u128,
+}
+
+/// Open markets listed in a [`Snapshot`], newest first.
+pub const SNAPSHOT_OPEN_MARKETS: usize = 50;
+/// Resolved markets listed in a [`Snapshot`], latest first.
+pub const SNAPSHOT_RESOLUTIONS: usize = 10;
+pub const SNAPSHOT_LEADERBOARD: usize = 10;
+
+/// Everything a read-only dashboard shows, in one document of bounded size.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub open_markets: Vec<SnapshotMarket>,
+    /// All open markets, including those past the listed ones
+    pub open_market_count: usize,
+    pub recent_resolutions: Vec<SnapshotResolution>,
+    pub leaderboard: Vec<LeaderboardEntry>,
+    /// Stakes placed on every market, open or resolved
+    pub total_volume: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotMarket {
+    pub id: u64,
+    pub description: String,
+    pub yes_pool: u128,
+    pub no_pool: u128,
+    pub yes_probability_bps: u32,
+    pub no_probability_bps: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotResolution {
+    pub id: u64,
+    pub description: String,
+    pub outcome: bool,
+    pub total_pool: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserBetInfo {
+    pub market_id: u64,
+    pub side: bool,
+    pub amount: u128,
+    pub claimed: bool,
+    /// None if the market no longer exists in the state
+    pub market_status: Option<MarketStatus>,
+}
+
+/// A user's on-chain account. Identities the contract has never seen get
+/// the default: zero balance, not initialized, no bets.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct UserInfo {
+    pub identity: String,
+    pub balance: u128,
+    pub initialized: bool,
+    pub bets: Vec<UserBetInfo>,
+    #[serde(default)]
+    pub current_streak: u32,
+}
+
+/// Stake a user has locked in markets not settled yet, answered by
+/// `GetOpenExposure`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenExposure {
+    pub yes: u128,
+    pub no: u128,
+    /// Markets holding some of it
+    pub positions: u32,
+}
+
+impl OpenExposure {
+    pub fn total(&self) -> u128 {
+        self.yes + self.no
+    }
+}
+
+/// Result of an idempotent `Initialize`, sent alongside its transaction hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InitializeOutcome {
+    /// Balance once the transaction settled
+    pub balance: u128,
+    /// The user was already initialized, so nothing was granted
+    pub already_initialized: bool,
+}
+
+/// Result of a `CreateMarket`, sent alongside its transaction hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CreatedMarket {
+    pub market_id: u64,
+}
+
+/// Result of a `ResolveMarket`, sent alongside its transaction hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResolveResult {
+    pub outcome: bool,
+    /// Paid out to the winners; the rest of the pool went to the treasury
+    pub total_distributed: u128,
+    pub winner_count: u32,
+}
+
+/// Result of a `ClaimWinnings`, sent alongside its transaction hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClaimResult {
+    pub market_id: u64,
+    /// 0 when the claimed bet lost
+    pub payout: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TreasuryInfo {
+    pub balance: u128,
+    pub admin: Option<String>,
+    /// Funds destroyed so far, absent from older servers
+    #[serde(default)]
+    pub burned: u128,
+    /// House edge waiting to be distributed, absent from older servers
+    #[serde(default)]
+    pub dividend_pool: u128,
+}
+
+/// A change worth announcing, found by comparing the state before and after
+/// a settled transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarketEvent {
+    BetPlaced {
+        market_id: u64,
+        bettor: String,
+        side: bool,
+        #[serde(deserialize_with = "tagged_amount::deserialize")]
+        amount: u128,
+    },
+    MarketResolved {
+        market_id: u64,
+        description: String,
+        outcome: bool,
+        #[serde(deserialize_with = "tagged_amount::deserialize")]
+        yes_pool: u128,
+        #[serde(deserialize_with = "tagged_amount::deserialize")]
+        no_pool: u128,
+    },
+    /// A resolution brought a bettor's streak to a bonus milestone
+    StreakMilestone {
+        market_id: u64,
+        identity: String,
+        streak: u32,
+        /// Zero when the treasury could not cover the bonus
+        #[serde(deserialize_with = "tagged_amount::deserialize")]
+        bonus: u128,
+    },
+}
+
+/// Amounts inside an internally tagged enum: serde buffers its fields
+/// before reading the tag, and that buffer has no room for a `u128`. Any
+/// amount that fits a `u64` is read back as is.
+mod tagged_amount {
+    use serde::{de, Deserializer};
+
+    struct Amount;
+
+    impl de::Visitor<'_> for Amount {
+        type Value = u128;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a non-negative amount")
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<u128, E> {
+            Ok(value.into())
+        }
+
+        fn visit_u128<E: de::Error>(self, value: u128) -> Result<u128, E> {
+            Ok(value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        deserializer.deserialize_any(Amount)
+    }
+}
+
+impl MarketEvent {
+    pub fn market_id(&self) -> u64 {
+        match self {
+            MarketEvent::BetPlaced { market_id, .. }
+            | MarketEvent::MarketResolved { market_id, .. }
+            | MarketEvent::StreakMilestone { market_id, .. } => *market_id,
+        }
+    }
+}
+
+/// Body of the server's outbound webhook, and of every message of its
+/// `/api/events` stream: the events of one transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookPayload {
+    pub tx_hash: String,
+    pub events: Vec<MarketEvent>,
+}
+
+/// Query of `/api/events`, narrowing the stream down to some events.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    /// Only the events of this market
+    #[serde(default)]
+    pub market_id: Option<u64>,
+}
+
+impl EventFilter {
+    /// The events of `payload` this filter lets through, `None` when there
+    /// are none left.
+    pub fn apply(&self, mut payload: WebhookPayload) -> Option<WebhookPayload> {
+        if let Some(market_id) = self.market_id {
+            payload.events.retain(|event| event.market_id() == market_id);
+        }
+        (!payload.events.is_empty()).then_some(payload)
+    }
+}
+
+/// Economic rules of the contract, published by the server's `/api/config`
+/// so clients do not hard-code them. Fields missing from an older server
+/// fall back to this crate's values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ContractParams {
+    pub initial_balance: u128,
+    pub min_bet: u128,
+    /// `None` when bets are only bounded by the bettor's balance
+    pub max_bet: Option<u128>,
+    /// Charged to the creator of a market
+    pub creation_fee: u128,
+    /// House edge: cut of the losing pool set aside for dividends, in basis
+    /// points. Set by the admin, so it follows the indexed state
+    pub fee_bps: u32,
+    /// Seconds after the latest bet before a market still taking bets can
+    /// be resolved
+    pub resolution_cooldown_secs: u64,
+    pub features: ContractFeatures,
+}
+
+impl Default for ContractParams {
+    fn default() -> Self {
+        Self {
+            initial_balance: INITIAL_BALANCE,
+            min_bet: MIN_BET,
+            max_bet: None,
+            // The treasury only collects payout rounding dust
+            creation_fee: 0,
+            fee_bps: 0,
+            resolution_cooldown_secs: RESOLUTION_COOLDOWN,
+            features: ContractFeatures::default(),
+        }
+    }
+}
+
+/// Optional market mechanics and whether this contract enforces them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ContractFeatures {
+    /// Betting deadlines checked on-chain
+    pub deadlines: bool,
+    /// Markets with more than two outcomes
+    pub multi_outcome: bool,
+    /// Pricing by an automated market maker instead of parimutuel pools
+    pub amm: bool,
+}
+
+/// What an off-chain ledger (the bot's database) believes the contract
+/// holds, compared against the state by [`Contract1::reconcile`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ReconcileSnapshot {
+    #[serde(default)]
+    pub balances: Vec<LedgerBalance>,
+    /// Markets the ledger considers open, with their pools
+    #[serde(default)]
+    pub open_markets: Vec<LedgerMarket>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LedgerBalance {
+    pub identity: String,
+    pub balance: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LedgerMarket {
+    pub market_id: u64,
+    pub yes_pool: u128,
+    pub no_pool: u128,
+}
+
+/// A balance the ledger and the contract disagree on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BalanceDrift {
+    pub identity: String,
+    pub ledger: u128,
+    /// `None` when the contract has never seen the identity
+    pub on_chain: Option<u128>,
+}
+
+/// A market open on both sides whose pools differ.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PoolDrift {
+    pub market_id: u64,
+    pub ledger: (u128, u128),
+    pub on_chain: (u128, u128),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ReconcileTotals {
+    /// Identities of the snapshot, all compared
+    pub users: usize,
+    pub ledger_balance: u128,
+    /// Balance of the same identities on-chain
+    pub on_chain_balance: u128,
+    pub ledger_open_markets: usize,
+    pub on_chain_open_markets: usize,
+}
+
+/// Differences between a [`ReconcileSnapshot`] and the contract state, each
+/// list ordered by identity or market id.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ReconcileReport {
+    pub balances: Vec<BalanceDrift>,
+    /// Open in the ledger, resolved or unknown on-chain
+    pub open_only_in_ledger: Vec<u64>,
+    /// Open on-chain, resolved or unknown in the ledger
+    pub open_only_on_chain: Vec<u64>,
+    pub pools: Vec<PoolDrift>,
+    pub totals: ReconcileTotals,
+}
+
+impl ReconcileReport {
+    pub fn is_clean(&self) -> bool {
+        self.balances.is_empty()
+            && self.open_only_in_ledger.is_empty()
+            && self.open_only_on_chain.is_empty()
+            && self.pools.is_empty()
+    }
+}
+
+/// Raw bytes per chunk of a [`SnapshotExport`].
+pub const SNAPSHOT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// A backup of the state: its commitment bytes, hex-encoded in chunks of at
+/// most [`SNAPSHOT_CHUNK_BYTES`] so no single string grows with the state.
+/// Returned by the export route and accepted as is by the import route and
+/// the simulation CLI.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotExport {
+    pub state_epoch: u64,
+    pub total_bytes: u64,
+    pub chunks: Vec<String>,
+}
+
+/// How big the state has grown, for operators watching it approach the caps
+/// that bound proof size.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StateStats {
+    /// Length of the encoded state committed on-chain, in bytes
+    pub state_bytes: u64,
+    pub users: u64,
+    pub markets: MarketCounts,
+    pub parlays: u64,
+    /// Stakes in markets not settled yet, pending challenges included
+    pub open_pool: u128,
+    /// The market with the most distinct bettors, if any has one
+    pub largest_market: Option<LargestMarket>,
+    pub treasury: u128,
+    pub burned: u128,
+}
+
+/// Number of markets in each status.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MarketCounts {
+    pub open: u64,
+    pub pending_acceptance: u64,
+    pub resolved_yes: u64,
+    pub resolved_no: u64,
+    pub cancelled: u64,
+    pub void: u64,
+}
+
+impl MarketCounts {
+    /// Each count with the snake_case name of its status.
+    pub fn by_status(&self) -> [(&'static str, u64); 6] {
+        [
+            ("open", self.open),
+            ("pending_acceptance", self.pending_acceptance),
+            ("resolved_yes", self.resolved_yes),
+            ("resolved_no", self.resolved_no),
+            ("cancelled", self.cancelled),
+            ("void", self.void),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LargestMarket {
+    pub market_id: u64,
+    pub bettors: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketStatusFilter {
+    Open,
+    Resolved,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MarketFilter {
+    #[serde(default)]
+    pub status: Option<MarketStatusFilter>,
+    /// Only markets carrying this tag, matched like the contract stores tags
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+impl MarketFilter {
+    pub fn matches(&self, market: &Market) -> bool {
+        let status = match self.status {
+            None => true,
+            Some(MarketStatusFilter::Open) => market.status == MarketStatus::Open,
+            Some(MarketStatusFilter::Resolved) => market.status.is_resolved(),
+        };
+        status && self.tag.as_deref().map_or(true, |tag| market.tags.contains(&normalize_tag(tag)))
+    }
+}
+
+impl MarketSummary {
+    /// Summary of `market` as seen at `now` (unix seconds).
+    pub fn of(market: &Market, now: u64) -> Self {
+        MarketSummary {
+            id: market.id,
+            description: market.description.clone(),
+            creator: market.creator.0.clone(),
+            status: market.status.clone(),
+            yes_pool: market.yes_pool,
+            no_pool: market.no_pool,
+            bettor_count: market.bettor_count(),
+            opens_at: market.opens_at,
+            scheduled: market.is_scheduled(Some(now)),
+            stake_cap: market.stake_cap,
+            betting_closed: market.betting_closed,
+            tags: market.tags.clone(),
+            challenge: market.challenge.clone(),
+            accept_by: market.accept_by,
+        }
+    }
+}
+
+impl Contract1 {
+    /// Markets matching `filter` as seen at `now` (unix seconds), newest first.
+    pub fn list_markets(&self, filter: &MarketFilter, now: u64) -> Vec<MarketSummary> {
+        let mut markets: Vec<MarketSummary> = self
+            .markets
+            .values()
+            .filter(|market| filter.matches(market))
+            .map(|market| MarketSummary::of(market, now))
+            .collect();
+        markets.sort_by_key(|market| std::cmp::Reverse(market.id));
+        markets
+    }
+
+    pub fn odds(&self, market_id: u64) -> Option<Odds> {
+        let market = self.markets.get(&market_id)?;
+        let total = market.yes_pool + market.no_pool;
+        let yes_probability_bps = (market.yes_pool * 10_000)
+            .checked_div(total)
+            .map_or(5_000, |bps| bps as u32);
+
+        Some(Odds {
+            market_id,
+            yes_pool: market.yes_pool,
+            no_pool: market.no_pool,
+            yes_probability_bps,
+            no_probability_bps: 10_000 - yes_probability_bps,
+        })
+    }
+
+    /// The bets a market still remembers, oldest first.
+    pub fn market_history(&self, market_id: u64) -> Option<Vec<MarketHistoryPoint>> {
+        let market = self.markets.get(&market_id)?;
+        Some(
+            market
+                .history
+                .iter()
+                .map(|entry| MarketHistoryPoint {
+                    bettor: entry.bettor.0.clone(),
+                    side: entry.side,
+                    amount: entry.amount,
+                    timestamp: entry.timestamp,
+                    yes_pool: entry.yes_pool,
+                    no_pool: entry.no_pool,
+                })
+                .collect(),
+        )
+    }
+
+    /// Top `limit` balances, highest first, ties broken by identity.
+    pub fn leaderboard(&self, limit: usize) -> Vec<LeaderboardEntry> {
+        let mut entries: Vec<LeaderboardEntry> = self
+            .users
+            .iter()
+            .filter(|(_, user)| user.initialized)
+            .map(|(identity, user)| LeaderboardEntry {
+                identity: identity.0.clone(),
+                balance: user.balance,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.balance.cmp(&a.balance).then_with(|| a.identity.cmp(&b.identity)));
+        entries.truncate(limit);
+        entries
+    }
+
+    /// The dashboard view of the state. Markets have no resolution time, so
+    /// the latest resolutions are those of the newest markets.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut markets: Vec<&Market> = self.markets.values().collect();
+        markets.sort_by_key(|market| std::cmp::Reverse(market.id));
+
+        let open: Vec<&Market> = markets.iter().copied().filter(|market| market.status == MarketStatus::Open).collect();
+        let open_markets = open
+            .iter()
+            .take(SNAPSHOT_OPEN_MARKETS)
+            .filter_map(|market| {
+                let odds = self.odds(market.id)?;
+                Some(SnapshotMarket {
+                    id: market.id,
+                    description: market.description.clone(),
+                    yes_pool: odds.yes_pool,
+                    no_pool: odds.no_pool,
+                    yes_probability_bps: odds.yes_probability_bps,
+                    no_probability_bps: odds.no_probability_bps,
+                })
+            })
+            .collect();
+        let recent_resolutions = markets
+            .iter()
+            .filter_map(|market| {
+                let outcome = match market.status {
+                    MarketStatus::ResolvedYes => true,
+                    MarketStatus::ResolvedNo => false,
+                    MarketStatus::Open | MarketStatus::PendingAcceptance | MarketStatus::Cancelled | MarketStatus::Void => return None,
+                };
+                Some(SnapshotResolution {
+                    id: market.id,
+                    description: market.description.clone(),
+                    outcome,
+                    total_pool: market.yes_pool.saturating_add(market.no_pool),
+                })
+            })
+            .take(SNAPSHOT_RESOLUTIONS)
+            .collect();
+
+        Snapshot {
+            open_markets,
+            open_market_count: open.len(),
+            recent_resolutions,
+            leaderboard: self.leaderboard(SNAPSHOT_LEADERBOARD),
+            total_volume: markets
+                .iter()
+                .fold(0u128, |total, market| total.saturating_add(market.yes_pool).saturating_add(market.no_pool)),
+        }
+    }
+
+    pub fn user_bets(&self, identity: &Identity) -> Vec<UserBetInfo> {
+        self.users
+            .get(identity)
+            .map(|user| {
+                user.bets
+                    .iter()
+                    .map(|bet| UserBetInfo {
+                        market_id: bet.market_id,
+                        side: bet.side,
+                        amount: bet.amount,
+                        claimed: bet.claimed,
+                        market_status: self.markets.get(&bet.market_id).map(|m| m.status.clone()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn user_info(&self, identity: &Identity) -> UserInfo {
+        let user = self.users.get(identity);
+        UserInfo {
+            identity: identity.0.clone(),
+            balance: user.map_or(0, |user| user.balance),
+            initialized: user.is_some_and(|user| user.initialized),
+            bets: self.user_bets(identity),
+            current_streak: user.map_or(0, |user| user.current_streak),
+        }
+    }
+
+    /// What `identity` has staked on markets still open, betting closed or
+    /// not, and on challenges waiting to be accepted. Resolved markets are
+    /// settled, claimed or not, so nothing in them is at risk anymore.
+    pub fn open_exposure(&self, identity: &Identity) -> OpenExposure {
+        let Some(user) = self.users.get(identity) else {
+            return OpenExposure::default();
+        };
+        let mut exposure = OpenExposure::default();
+        let mut markets = std::collections::BTreeSet::new();
+        for bet in &user.bets {
+            let open = self
+                .markets
+                .get(&bet.market_id)
+                .is_some_and(|market| matches!(market.status, MarketStatus::Open | MarketStatus::PendingAcceptance));
+            if !open {
+                continue;
+            }
+            if bet.side {
+                exposure.yes += bet.amount;
+            } else {
+                exposure.no += bet.amount;
+            }
+            markets.insert(bet.market_id);
+        }
+        exposure.positions = markets.len() as u32;
+        exposure
+    }
+
+    /// Bets placed, markets resolved and streak milestones reached between
+    /// `before` and `self`. Bets come first, ordered by market then bettor,
+    /// and milestones last, ordered by market then identity. A state
+    /// imported from a snapshot announces nothing: its bets are old news.
+    pub fn events_since(&self, before: &Contract1) -> Vec<MarketEvent> {
+        if before.users.is_empty() && before.markets.is_empty() {
+            return Vec::new();
+        }
+        let mut bets: Vec<(&Identity, &UserBet)> = self
+            .users
+            .iter()
+            .flat_map(|(identity, user)| {
+                let known = before.users.get(identity).map_or(0, |user| user.bets.len());
+                user.bets.iter().skip(known).map(move |bet| (identity, bet))
+            })
+            .collect();
+        bets.sort_by(|(a, a_bet), (b, b_bet)| a_bet.market_id.cmp(&b_bet.market_id).then_with(|| a.0.cmp(&b.0)));
+
+        let mut resolved: Vec<&Market> = self
+            .markets
+            .values()
+            .filter(|market| market.status.is_resolved())
+            .filter(|market| before.markets.get(&market.id).is_some_and(|m| m.status == MarketStatus::Open))
+            .collect();
+        resolved.sort_by_key(|market| market.id);
+
+        let bets = bets.into_iter().map(|(identity, bet)| MarketEvent::BetPlaced {
+            market_id: bet.market_id,
+            bettor: identity.0.clone(),
+            side: bet.side,
+            amount: bet.amount,
+        });
+        let milestones: Vec<MarketEvent> = resolved
+            .iter()
+            .flat_map(|market| self.streak_milestones(before, market))
+            .collect();
+        let resolutions = resolved.into_iter().map(|market| MarketEvent::MarketResolved {
+            market_id: market.id,
+            description: market.description.clone(),
+            outcome: market.status == MarketStatus::ResolvedYes,
+            yes_pool: market.yes_pool,
+            no_pool: market.no_pool,
+        });
+        bets.chain(resolutions).chain(milestones).collect()
+    }
+
+    /// Winners of `market` whose streak reached a milestone with its
+    /// resolution. The bonus is what their balance gained beyond the payout.
+    fn streak_milestones(&self, before: &Contract1, market: &Market) -> Vec<MarketEvent> {
+        let outcome = market.status == MarketStatus::ResolvedYes;
+        let (winners, winning_pool) = if outcome {
+            (&market.yes_bettors, market.yes_pool)
+        } else {
+            (&market.no_bettors, market.no_pool)
+        };
+        let mut winners: Vec<(&Identity, &u128)> = winners.iter().collect();
+        winners.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+        winners
+            .into_iter()
+            .filter_map(|(identity, stake)| {
+                let user = self.users.get(identity)?;
+                let previous = before.users.get(identity)?;
+                if user.current_streak == previous.current_streak {
+                    return None;
+                }
+                streak_bonus(user.current_streak)?;
+                let payout = parimutuel_payout(*stake, winning_pool, market.payout_pool());
+                Some(MarketEvent::StreakMilestone {
+                    market_id: market.id,
+                    identity: identity.0.clone(),
+                    streak: user.current_streak,
+                    bonus: user.balance.saturating_sub(previous.balance).saturating_sub(payout),
+                })
+            })
+            .collect()
+    }
+
+    /// What the resolution of `market_id` paid out, once it is resolved.
+    pub fn resolve_result(&self, market_id: u64) -> Option<ResolveResult> {
+        let market = self.markets.get(&market_id)?;
+        let outcome = match market.status {
+            MarketStatus::ResolvedYes => true,
+            MarketStatus::ResolvedNo => false,
+            _ => return None,
+        };
+        let (winners, winning_pool) = if outcome {
+            (&market.yes_bettors, market.yes_pool)
+        } else {
+            (&market.no_bettors, market.no_pool)
+        };
+        let total_pool = market.payout_pool();
+        Some(ResolveResult {
+            outcome,
+            total_distributed: winners.values().map(|stake| parimutuel_payout(*stake, winning_pool, total_pool)).sum(),
+            winner_count: winners.len() as u32,
+        })
+    }
+
+    /// What `identity` is owed by the resolved `market_id`: its share of the
+    /// pool when it backed the outcome, nothing otherwise.
+    pub fn claim_result(&self, identity: &Identity, market_id: u64) -> Option<ClaimResult> {
+        let market = self.markets.get(&market_id)?;
+        let (winners, winning_pool) = match market.status {
+            MarketStatus::ResolvedYes => (&market.yes_bettors, market.yes_pool),
+            MarketStatus::ResolvedNo => (&market.no_bettors, market.no_pool),
+            _ => return None,
+        };
+        let payout = winners
+            .get(identity)
+            .map_or(0, |stake| parimutuel_payout(*stake, winning_pool, market.payout_pool()));
+        Some(ClaimResult { market_id, payout })
+    }
+
+    pub fn treasury_info(&self) -> TreasuryInfo {
+        TreasuryInfo {
+            balance: self.treasury,
+            admin: self.admin.as_ref().map(|admin| admin.0.clone()),
+            burned: self.burned,
+            dividend_pool: self.dividend_pool,
+        }
+    }
+
+    /// Where `snapshot` drifted from the state. Only the snapshot's identities
+    /// are compared: the contract may know users the ledger never saw.
+    pub fn reconcile(&self, snapshot: &ReconcileSnapshot) -> ReconcileReport {
+        let mut balances: Vec<BalanceDrift> = snapshot
+            .balances
+            .iter()
+            .filter_map(|entry| {
+                let on_chain = self.users.get(&Identity(entry.identity.clone())).map(|user| user.balance);
+                (on_chain != Some(entry.balance)).then(|| BalanceDrift {
+                    identity: entry.identity.clone(),
+                    ledger: entry.balance,
+                    on_chain,
+                })
+            })
+            .collect();
+        balances.sort_by(|a, b| a.identity.cmp(&b.identity));
+
+        let open_on_chain: Vec<&Market> = self
+            .markets
+            .values()
+            .filter(|market| market.status == MarketStatus::Open)
+            .collect();
+        let mut open_only_in_ledger = Vec::new();
+        let mut pools = Vec::new();
+        for ledger in &snapshot.open_markets {
+            match open_on_chain.iter().find(|market| market.id == ledger.market_id) {
+                None => open_only_in_ledger.push(ledger.market_id),
+                Some(market) if (market.yes_pool, market.no_pool) != (ledger.yes_pool, ledger.no_pool) => {
+                    pools.push(PoolDrift {
+                        market_id: market.id,
+                        ledger: (ledger.yes_pool, ledger.no_pool),
+                        on_chain: (market.yes_pool, market.no_pool),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        let mut open_only_on_chain: Vec<u64> = open_on_chain
+            .iter()
+            .map(|market| market.id)
+            .filter(|id| !snapshot.open_markets.iter().any(|ledger| ledger.market_id == *id))
+            .collect();
+        open_only_in_ledger.sort_unstable();
+        open_only_on_chain.sort_unstable();
+        pools.sort_by_key(|drift| drift.market_id);
+
+        let totals = ReconcileTotals {
+            users: snapshot.balances.len(),
+            ledger_balance: snapshot.balances.iter().map(|entry| entry.balance).sum(),
+            on_chain_balance: snapshot
+                .balances
+                .iter()
+                .filter_map(|entry| self.users.get(&Identity(entry.identity.clone())))
+                .map(|user| user.balance)
+                .sum(),
+            ledger_open_markets: snapshot.open_markets.len(),
+            on_chain_open_markets: open_on_chain.len(),
+        };
+        ReconcileReport { balances, open_only_in_ledger, open_only_on_chain, pools, totals }
+    }
+
+    pub fn state_stats(&self) -> StateStats {
+        let mut markets = MarketCounts::default();
+        let mut open_pool = 0;
+        for market in self.markets.values() {
+            let count = match market.status {
+                MarketStatus::Open => &mut markets.open,
+                MarketStatus::PendingAcceptance => &mut markets.pending_acceptance,
+                MarketStatus::ResolvedYes => &mut markets.resolved_yes,
+                MarketStatus::ResolvedNo => &mut markets.resolved_no,
+                MarketStatus::Cancelled => &mut markets.cancelled,
+                MarketStatus::Void => &mut markets.void,
+            };
+            *count += 1;
+            if matches!(market.status, MarketStatus::Open | MarketStatus::PendingAcceptance) {
+                open_pool += market.yes_pool + market.no_pool;
+            }
+        }
+        let largest_market = self
+            .markets
+            .values()
+            .map(|market| {
+                let no_only = market.no_bettors.keys().filter(|identity| !market.yes_bettors.contains_key(*identity));
+                LargestMarket { market_id: market.id, bettors: (market.yes_bettors.len() + no_only.count()) as u64 }
+            })
+            .filter(|market| market.bettors > 0)
+            // Ties go to the oldest market
+            .max_by(|a, b| a.bettors.cmp(&b.bettors).then(b.market_id.cmp(&a.market_id)));
+        StateStats {
+            state_bytes: self.as_bytes().map_or(0, |bytes| bytes.len() as u64),
+            users: self.users.len() as u64,
+            markets,
+            parlays: self.parlays.len() as u64,
+            open_pool,
+            largest_market,
+            treasury: self.treasury,
+            burned: self.burned,
+        }
+    }
+}
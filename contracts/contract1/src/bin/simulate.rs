@@ -0,0 +1,88 @@
+//! Replays a JSONL action log against the contract and writes the final
+//! state, or compares two saved states.
+//!
+//!     cargo run -p contract1 --features client --bin simulate -- --log actions.jsonl --out final.state
+//!     cargo run -p contract1 --features client --bin simulate -- --diff final.state other.state
+//!
+//! Saved states are commitment bytes or snapshots exported by the server's
+//! `/api/admin/export` route.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use contract1::{
+    client::replay::{diff_states, read_log, read_state, replay, write_state},
+    Contract1,
+};
+use sdk::{ContractName, ZkContract};
+
+#[derive(Parser, Debug)]
+#[command(about = "Replay recorded market actions against the contract")]
+struct Args {
+    /// JSONL file of {"identity", "action"} entries to apply
+    #[arg(long, required_unless_present = "diff")]
+    log: Option<PathBuf>,
+
+    /// Saved state (commitment bytes, or a snapshot from `/api/admin/export`)
+    /// to start from; defaults to an empty contract
+    #[arg(long)]
+    state: Option<PathBuf>,
+
+    /// Where to write the final commitment; a JSON copy is written alongside
+    #[arg(long, default_value = "final.state")]
+    out: PathBuf,
+
+    #[arg(long, default_value = "contract1")]
+    contract_name: String,
+
+    /// Compare two saved states field by field instead of replaying
+    #[arg(long, num_args = 2, value_names = ["LEFT", "RIGHT"], conflicts_with_all = ["log", "state"])]
+    diff: Option<Vec<PathBuf>>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(paths) = args.diff {
+        let diffs = diff_states(&read_state(&paths[0])?, &read_state(&paths[1])?);
+        if diffs.is_empty() {
+            println!("States are identical");
+            return Ok(());
+        }
+        for diff in &diffs {
+            println!("{}", diff);
+        }
+        println!("{} field(s) differ", diffs.len());
+        std::process::exit(1);
+    }
+
+    let mut state = match &args.state {
+        Some(path) => read_state(path)?,
+        None => Contract1::default(),
+    };
+    let Some(log) = &args.log else {
+        bail!("--log is required");
+    };
+    let entries = read_log(log)?;
+
+    let contract_name = ContractName(args.contract_name);
+    let steps = replay(&mut state, &contract_name, entries);
+    let failed = steps.iter().filter(|step| step.result.is_err()).count();
+    for step in &steps {
+        match &step.result {
+            Ok(message) => println!("{:>5} ok    {} {:?}: {}", step.step, step.entry.identity.0, step.entry.action, message),
+            Err(error) => println!("{:>5} error {} {:?}: {}", step.step, step.entry.identity.0, step.entry.action, error),
+        }
+    }
+
+    write_state(&state, &args.out)?;
+    println!(
+        "Replayed {} action(s), {} failed. Final state written to {} (commitment {} bytes)",
+        steps.len(),
+        failed,
+        args.out.display(),
+        state.commit().0.len()
+    );
+    Ok(())
+}
@@ -1 +1,115 @@
+pub mod replay;
 pub mod tx_executor_handler;
+
+use sdk::{
+    BlobIndex, BlobTransaction, Calldata, ContractName, HyleOutput, Identity, TxHash, ZkContract,
+};
+
+use crate::{
+    api::{SnapshotExport, SNAPSHOT_CHUNK_BYTES},
+    Contract1, MarketAction,
+};
+
+// Helpers for scripts and off-chain tools that talk to the contract without
+// going through the server.
+
+/// Builds a transaction carrying `action` as its only blob, ready to be sent
+/// with `send_tx_blob`.
+///
+/// ```
+/// use contract1::{client::build_transaction, MarketAction};
+/// use sdk::{ContractName, Identity};
+///
+/// let action = MarketAction::PlaceBet { market_id: 1, side: true, amount: 100 };
+/// let tx = build_transaction(Identity("alice@contract1".to_string()), ContractName("contract1".to_string()), &action);
+/// assert_eq!(tx.blobs.len(), 1);
+/// ```
+pub fn build_transaction(
+    identity: impl Into<Identity>,
+    contract_name: ContractName,
+    action: &MarketAction,
+) -> BlobTransaction {
+    BlobTransaction::new(identity.into(), vec![action.as_blob(contract_name)])
+}
+
+/// Decodes the message returned by the contract for a settled transaction.
+/// Failed transactions yield the contract's error message as `Err`.
+pub fn decode_output(output: &HyleOutput) -> Result<String, String> {
+    let message = String::from_utf8_lossy(&output.program_outputs).into_owned();
+    if output.success {
+        Ok(message)
+    } else {
+        Err(message)
+    }
+}
+
+/// Whether a settled transaction changed the contract's state. Queries never
+/// do, so their proofs can be batched or skipped where the node allows it.
+pub fn changes_state(output: &HyleOutput) -> bool {
+    output.initial_state != output.next_state
+}
+
+/// Runs `action` against a copy of `state` exactly as the prover would, and
+/// returns the resulting state with the contract's message. `state` itself is
+/// left untouched, so the action can be checked before it is submitted.
+///
+/// ```
+/// use contract1::{client::simulate, Contract1, MarketAction};
+/// use sdk::{ContractName, Identity};
+///
+/// let state = Contract1::new();
+/// let alice = Identity("alice@contract1".to_string());
+/// let contract_name = ContractName("contract1".to_string());
+///
+/// let (next, message) = simulate(&state, alice.clone(), contract_name.clone(), &MarketAction::Initialize { idempotent: false }).unwrap();
+/// assert_eq!(message, "Initialized with 10000 balance");
+/// assert_eq!(next.users[&alice].balance, 10_000);
+/// assert!(state.users.is_empty());
+///
+/// // A refused action never reaches the chain
+/// let bet = MarketAction::PlaceBet { market_id: 1, side: true, amount: 100 };
+/// assert!(simulate(&next, alice, contract_name, &bet).is_err());
+/// ```
+pub fn simulate(
+    state: &Contract1,
+    identity: impl Into<Identity>,
+    contract_name: ContractName,
+    action: &MarketAction,
+) -> Result<(Contract1, String), String> {
+    let calldata = Calldata {
+        tx_hash: TxHash::default(),
+        identity: identity.into(),
+        blobs: vec![action.as_blob(contract_name)].into(),
+        tx_blob_count: 1,
+        index: BlobIndex(0),
+        tx_ctx: None,
+        private_input: vec![],
+    };
+
+    let mut next = state.clone();
+    let (output, _, _) = next.execute(&calldata)?;
+    Ok((next, String::from_utf8_lossy(&output).into_owned()))
+}
+
+/// Backs `state` up as its commitment bytes, hex-encoded in chunks.
+pub fn snapshot_of(state: &Contract1) -> SnapshotExport {
+    let bytes = state.commit().0;
+    SnapshotExport {
+        state_epoch: state.state_epoch,
+        total_bytes: bytes.len() as u64,
+        chunks: bytes.chunks(SNAPSHOT_CHUNK_BYTES).map(hex::encode).collect(),
+    }
+}
+
+/// The commitment bytes of `snapshot`, ready for `ImportSnapshot`.
+pub fn snapshot_data(snapshot: &SnapshotExport) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(snapshot.total_bytes as usize);
+    for (i, chunk) in snapshot.chunks.iter().enumerate() {
+        let bytes = hex::decode(chunk).map_err(|e| anyhow::anyhow!("chunk {} is not hex: {}", i, e))?;
+        data.extend(bytes);
+    }
+    if data.len() as u64 != snapshot.total_bytes {
+        anyhow::bail!("snapshot has {} bytes, expected {}", data.len(), snapshot.total_bytes);
+    }
+    Ok(data)
+}
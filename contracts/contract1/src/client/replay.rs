@@ -0,0 +1,155 @@
+//! Replays a recorded log of actions against the contract, to reproduce a
+//! state offline or compare it with what the bot believes happened.
+//!
+//! A log is JSONL, one entry per line:
+//!
+//! ```text
+//! {"identity":"42@contract1","action":{"PlaceBet":{"market_id":1,"side":true,"amount":100}}}
+//! ```
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Debug,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use sdk::{ContractName, Identity, StateCommitment, ZkContract};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::SnapshotExport,
+    client::{simulate, snapshot_data},
+    Contract1, MarketAction,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogEntry {
+    pub identity: Identity,
+    pub action: MarketAction,
+}
+
+/// Outcome of one replayed entry. Failed actions leave the state unchanged,
+/// as on chain.
+#[derive(Debug, Clone)]
+pub struct ReplayStep {
+    pub step: usize,
+    pub entry: LogEntry,
+    pub result: Result<String, String>,
+}
+
+pub fn read_log(path: &Path) -> Result<Vec<LogEntry>> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: invalid log entry", path.display(), i + 1))
+        })
+        .collect()
+}
+
+pub fn read_state(path: &Path) -> Result<Contract1> {
+    let mut bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    // A snapshot downloaded from the export route rather than raw commitment bytes
+    if let Ok(snapshot) = serde_json::from_slice::<SnapshotExport>(&bytes) {
+        bytes = snapshot_data(&snapshot).with_context(|| format!("decoding {}", path.display()))?;
+    }
+    Contract1::try_from(StateCommitment(bytes)).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// Writes the commitment to `path` and a readable JSON copy of the state next
+/// to it.
+pub fn write_state(state: &Contract1, path: &Path) -> Result<()> {
+    std::fs::write(path, state.commit().0).with_context(|| format!("writing {}", path.display()))?;
+    let json_path = path.with_extension("json");
+    let json = serde_json::to_string_pretty(state).context("encoding state as JSON")?;
+    std::fs::write(&json_path, json).with_context(|| format!("writing {}", json_path.display()))
+}
+
+/// Applies `entries` in order, updating `state` after every successful action.
+pub fn replay(state: &mut Contract1, contract_name: &ContractName, entries: Vec<LogEntry>) -> Vec<ReplayStep> {
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let result = simulate(state, entry.identity.clone(), contract_name.clone(), &entry.action)
+                .map(|(next, message)| {
+                    *state = next;
+                    message
+                });
+            ReplayStep { step: i + 1, entry, result }
+        })
+        .collect()
+}
+
+/// Lists every field that differs between two states, one line each. An
+/// empty list means the states commit to the same bytes.
+pub fn diff_states(left: &Contract1, right: &Contract1) -> Vec<String> {
+    let mut diffs = vec![];
+    field(&mut diffs, "next_market_id", &left.next_market_id, &right.next_market_id);
+    field(&mut diffs, "admin", &left.admin, &right.admin);
+    field(&mut diffs, "treasury", &left.treasury, &right.treasury);
+    field(&mut diffs, "state_epoch", &left.state_epoch, &right.state_epoch);
+
+    let mut users: Vec<&Identity> = left.users.keys().chain(right.users.keys()).collect();
+    users.sort_by(|a, b| a.0.cmp(&b.0));
+    users.dedup();
+    for identity in users {
+        let prefix = format!("users[{}]", identity.0);
+        match (left.users.get(identity), right.users.get(identity)) {
+            (Some(l), Some(r)) => {
+                field(&mut diffs, &format!("{}.balance", prefix), &l.balance, &r.balance);
+                field(&mut diffs, &format!("{}.initialized", prefix), &l.initialized, &r.initialized);
+                let bets = l.bets.len().max(r.bets.len());
+                for i in 0..bets {
+                    field(&mut diffs, &format!("{}.bets[{}]", prefix, i), &l.bets.get(i), &r.bets.get(i));
+                }
+            }
+            (l, r) => diffs.push(format!("{}: {} vs {}", prefix, presence(l), presence(r))),
+        }
+    }
+
+    let markets: BTreeSet<&u64> = left.markets.keys().chain(right.markets.keys()).collect();
+    for id in markets {
+        let prefix = format!("markets[{}]", id);
+        match (left.markets.get(id), right.markets.get(id)) {
+            (Some(l), Some(r)) => {
+                field(&mut diffs, &format!("{}.creator", prefix), &l.creator, &r.creator);
+                field(&mut diffs, &format!("{}.description", prefix), &l.description, &r.description);
+                field(&mut diffs, &format!("{}.status", prefix), &l.status, &r.status);
+                field(&mut diffs, &format!("{}.yes_pool", prefix), &l.yes_pool, &r.yes_pool);
+                field(&mut diffs, &format!("{}.no_pool", prefix), &l.no_pool, &r.no_pool);
+                field(&mut diffs, &format!("{}.yes_bettors", prefix), &sorted(&l.yes_bettors), &sorted(&r.yes_bettors));
+                field(&mut diffs, &format!("{}.no_bettors", prefix), &sorted(&l.no_bettors), &sorted(&r.no_bettors));
+                field(&mut diffs, &format!("{}.created_at", prefix), &l.created_at, &r.created_at);
+                field(&mut diffs, &format!("{}.comments", prefix), &l.comments, &r.comments);
+            }
+            (l, r) => diffs.push(format!("{}: {} vs {}", prefix, presence(l), presence(r))),
+        }
+    }
+    diffs
+}
+
+fn field<T: PartialEq + Debug>(diffs: &mut Vec<String>, name: &str, left: &T, right: &T) {
+    if left != right {
+        diffs.push(format!("{}: {:?} vs {:?}", name, left, right));
+    }
+}
+
+fn presence<T>(value: Option<T>) -> &'static str {
+    if value.is_some() {
+        "present"
+    } else {
+        "missing"
+    }
+}
+
+fn sorted(bettors: &HashMap<Identity, u128>) -> Vec<(&str, u128)> {
+    let mut bettors: Vec<(&str, u128)> = bettors.iter().map(|(id, stake)| (id.0.as_str(), *stake)).collect();
+    bettors.sort();
+    bettors
+}
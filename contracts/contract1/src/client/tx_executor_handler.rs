@@ -2,7 +2,7 @@ use anyhow::Context;
 use client_sdk::transaction_builder::TxExecutorHandler;
 use sdk::{utils::as_hyle_output, Blob, Calldata, RegisterContractEffect, ZkContract};
 
-use crate::Contract1;
+use crate::{Contract1, MarketAction};
 
 pub mod metadata {
     pub const CONTRACT1_ELF: &[u8] = include_bytes!("../../contract1.img");
@@ -16,8 +16,15 @@ impl TxExecutorHandler for Contract1 {
 
     fn handle(&mut self, calldata: &Calldata) -> anyhow::Result<sdk::HyleOutput> {
         let initial_state_commitment = <Self as ZkContract>::commit(self);
+        let read_only = sdk::utils::parse_raw_calldata::<MarketAction>(calldata)
+            .is_ok_and(|(action, _)| action.is_read_only());
         let mut res = <Self as ZkContract>::execute(self, calldata);
-        let next_state_commitment = <Self as ZkContract>::commit(self);
+        // A query leaves the state as it was: no need to encode it again
+        let next_state_commitment = if read_only {
+            initial_state_commitment.clone()
+        } else {
+            <Self as ZkContract>::commit(self)
+        };
         Ok(as_hyle_output(
             initial_state_commitment,
             next_state_commitment,
@@ -0,0 +1,212 @@
+use std::fmt;
+
+use crate::RESOLUTION_COOLDOWN;
+
+/// Why an action was rejected. `Display` gives the messages the contract has
+/// always returned, so clients matching on them keep working.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketError {
+    EmptyIdentity,
+    IdentityTooLong { max: usize },
+    MalformedIdentity { identity: String, contract_name: String },
+    UserAlreadyInitialized,
+    UserNotInitialized,
+    UserNotFound,
+    RecipientNotInitialized,
+    InsufficientBalance { have: u128, need: u128 },
+    InsufficientTreasury { have: u128, need: u128 },
+    InvalidAmount,
+    Overflow,
+    MarketNotFound,
+    MarketNotOpen,
+    BettingClosed,
+    NotOpenYet { market_id: u64, opens_at: u64 },
+    InvalidStakeCap,
+    StakeCapExceeded { market_id: u64, allowed: u128 },
+    MarketFull { market_id: u64, max: usize },
+    MarketNotResolved,
+    NoUnclaimedBet,
+    NoWinningPool,
+    NoAdmin,
+    Unauthorized,
+    EmptyComment,
+    CommentTooLong { max: usize },
+    TooManyComments { market_id: u64, max: usize },
+    InvalidTag { max: usize },
+    TooManyTags { max: usize },
+    ParlayLegCount { min: usize, max: usize },
+    DuplicateParlayLeg { market_id: u64 },
+    ParlayExposureExceeded { have: u128, need: u128 },
+    ParlayNotFound,
+    ParlayAlreadySettled,
+    ParlayLegOpen { market_id: u64 },
+    MarketsStillOpen { open: usize },
+    InvalidChallenge,
+    ChallengeNotPending { market_id: u64 },
+    ChallengeExpired { market_id: u64 },
+    NotChallengeOpponent { market_id: u64 },
+    NotChallengeParty { market_id: u64 },
+    MarketNotExpired { market_id: u64, expires_at: Option<u64> },
+    ResolutionTooEarly { market_id: u64, resolvable_at: u64 },
+    StateNotEmpty { users: usize, markets: usize },
+    InvalidSnapshot,
+    NonceReplayed { nonce: u64, expected: u64 },
+    NonceGap { nonce: u64, expected: u64 },
+    NonceRequired,
+    InvalidNoncedAction,
+    InvalidHouseEdge { bps: u16, max: u16 },
+    NoDividends,
+    NoDividendRecipients,
+    DividendPoolTooSmall { pool: u128, recipients: u128 },
+    ContractPaused,
+}
+
+impl fmt::Display for MarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketError::EmptyIdentity => write!(f, "Identity is empty"),
+            MarketError::IdentityTooLong { max } => write!(f, "Identity is longer than {} bytes", max),
+            MarketError::MalformedIdentity { identity, contract_name } => {
+                write!(f, "Identity {} is not of the form <name>@{}", identity, contract_name)
+            }
+            MarketError::UserAlreadyInitialized => write!(f, "User already initialized"),
+            MarketError::UserNotInitialized => write!(f, "User not initialized"),
+            MarketError::UserNotFound => write!(f, "User not found"),
+            MarketError::RecipientNotInitialized => write!(f, "Recipient not initialized"),
+            MarketError::InsufficientBalance { have, need } => {
+                write!(f, "Insufficient balance. Have: {}, Need: {}", have, need)
+            }
+            MarketError::InsufficientTreasury { have, need } => {
+                write!(f, "Insufficient treasury. Have: {}, Need: {}", have, need)
+            }
+            MarketError::InvalidAmount => write!(f, "Amount must be positive"),
+            MarketError::Overflow => write!(f, "Amount overflows"),
+            MarketError::MarketNotFound => write!(f, "Market not found"),
+            MarketError::MarketNotOpen => write!(f, "Market is not open"),
+            MarketError::BettingClosed => write!(f, "Market is not open for betting"),
+            MarketError::NotOpenYet { market_id, opens_at } => {
+                write!(f, "Market #{} opens for betting at {}", market_id, opens_at)
+            }
+            MarketError::InvalidStakeCap => write!(f, "Stake cap must be positive"),
+            MarketError::StakeCapExceeded { market_id, allowed } => {
+                write!(f, "{}{} on market #{}", STAKE_CAP_PREFIX, allowed, market_id)
+            }
+            MarketError::MarketFull { market_id, max } => {
+                write!(f, "Market #{} already has {} bettors, only they can add to their bets", market_id, max)
+            }
+            MarketError::MarketNotResolved => write!(f, "Market not resolved yet"),
+            MarketError::NoUnclaimedBet => write!(f, "No unclaimed bet found for this market"),
+            MarketError::NoWinningPool => write!(f, "No winning pool"),
+            MarketError::NoAdmin => write!(f, "No admin set"),
+            MarketError::Unauthorized => write!(f, "Only the admin can do this"),
+            MarketError::EmptyComment => write!(f, "Comment is empty"),
+            MarketError::CommentTooLong { max } => write!(f, "Comment is longer than {} characters", max),
+            MarketError::TooManyComments { market_id, max } => {
+                write!(f, "Market #{} already has {} comments", market_id, max)
+            }
+            MarketError::InvalidTag { max } => {
+                write!(f, "Tags must be 1 to {} characters, without spaces", max)
+            }
+            MarketError::TooManyTags { max } => write!(f, "A market can have at most {} tags", max),
+            MarketError::ParlayLegCount { min, max } => {
+                write!(f, "A parlay needs between {} and {} legs", min, max)
+            }
+            MarketError::DuplicateParlayLeg { market_id } => {
+                write!(f, "Market #{} appears more than once in the parlay", market_id)
+            }
+            MarketError::ParlayExposureExceeded { have, need } => {
+                write!(f, "The treasury cannot back this parlay. Have: {}, Need: {}", have, need)
+            }
+            MarketError::ParlayNotFound => write!(f, "Parlay not found"),
+            MarketError::ParlayAlreadySettled => write!(f, "Parlay already settled"),
+            MarketError::ParlayLegOpen { market_id } => {
+                write!(f, "Market #{} of the parlay is not resolved yet", market_id)
+            }
+            MarketError::MarketsStillOpen { open } => {
+                write!(f, "{} market(s) are still open, resolve them before resetting balances", open)
+            }
+            MarketError::InvalidChallenge => write!(
+                f,
+                "A challenge needs an opponent other than its creator and a positive stake, without schedule or stake cap"
+            ),
+            MarketError::ChallengeNotPending { market_id } => {
+                write!(f, "Market #{} is not a challenge waiting for its opponent", market_id)
+            }
+            MarketError::ChallengeExpired { market_id } => {
+                write!(f, "The challenge of market #{} expired", market_id)
+            }
+            MarketError::NotChallengeOpponent { market_id } => {
+                write!(f, "Only the opponent named by challenge #{} can accept it", market_id)
+            }
+            MarketError::NotChallengeParty { market_id } => {
+                write!(f, "Only the players of challenge #{} can cancel it before it expires", market_id)
+            }
+            MarketError::MarketNotExpired { market_id, expires_at: Some(expires_at) } => {
+                write!(f, "Market #{} cannot be expired before {}", market_id, expires_at)
+            }
+            MarketError::MarketNotExpired { market_id, expires_at: None } => {
+                write!(f, "Market #{} has no creation time and cannot be expired", market_id)
+            }
+            MarketError::ResolutionTooEarly { market_id, resolvable_at } => write!(
+                f,
+                "Market #{} was bet on less than {} seconds ago. {}{}",
+                market_id, RESOLUTION_COOLDOWN, RESOLUTION_OPENS_PREFIX, resolvable_at
+            ),
+            MarketError::StateNotEmpty { users, markets } => {
+                write!(f, "Snapshots only import into an empty state, this one has {} users and {} markets", users, markets)
+            }
+            MarketError::InvalidSnapshot => write!(f, "Snapshot is not an encoded contract state"),
+            MarketError::NonceReplayed { nonce, expected } => {
+                write!(f, "Nonce {} was already used, the next one is {}", nonce, expected)
+            }
+            MarketError::NonceGap { nonce, expected } => {
+                write!(f, "Nonce {} skips ahead, the next one is {}", nonce, expected)
+            }
+            MarketError::NonceRequired => write!(f, "Actions changing the state must carry the sender's next nonce"),
+            MarketError::InvalidNoncedAction => write!(f, "Only a single action changing the state can carry a nonce"),
+            MarketError::InvalidHouseEdge { bps, max } => {
+                write!(f, "House edge of {} bps is above the maximum of {} bps", bps, max)
+            }
+            MarketError::NoDividends => write!(f, "The dividend pool is empty"),
+            MarketError::NoDividendRecipients => write!(f, "Nobody bet since the last dividend distribution"),
+            MarketError::DividendPoolTooSmall { pool, recipients } => {
+                write!(f, "The dividend pool of {} is too small to split among {} bettors", pool, recipients)
+            }
+            MarketError::ContractPaused => write!(f, "{}", PAUSED_MESSAGE),
+        }
+    }
+}
+
+impl std::error::Error for MarketError {}
+
+const STAKE_CAP_PREFIX: &str = "Bet exceeds the stake cap, you can add at most ";
+const RESOLUTION_OPENS_PREFIX: &str = "Resolution opens at ";
+const PAUSED_MESSAGE: &str = "Contract is paused";
+
+impl MarketError {
+    /// The allowed stake in a rejection message from `StakeCapExceeded`, for
+    /// clients that only see the message.
+    pub fn stake_cap_allowance(message: &str) -> Option<u128> {
+        let rest = &message[message.find(STAKE_CAP_PREFIX)? + STAKE_CAP_PREFIX.len()..];
+        rest.split_whitespace().next()?.parse().ok()
+    }
+
+    /// The unix seconds from which the market can be resolved, in a
+    /// rejection message from `ResolutionTooEarly`.
+    pub fn resolution_opens_at(message: &str) -> Option<u64> {
+        let rest = &message[message.find(RESOLUTION_OPENS_PREFIX)? + RESOLUTION_OPENS_PREFIX.len()..];
+        rest.split_whitespace().next()?.parse().ok()
+    }
+
+    /// Whether a rejection message comes from `ContractPaused`.
+    pub fn is_paused(message: &str) -> bool {
+        message.contains(PAUSED_MESSAGE)
+    }
+}
+
+/// `execute` reports errors to the sdk as plain strings
+impl From<MarketError> for String {
+    fn from(error: MarketError) -> Self {
+        error.to_string()
+    }
+}
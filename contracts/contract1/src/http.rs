@@ -0,0 +1,265 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    api::{ContractParams, ReconcileSnapshot, SnapshotExport},
+    StakeCap,
+};
+
+// Bodies of the server's HTTP routes. The server parses them and the bot's
+// client sends them, so a field renamed on one side is renamed on the other
+// instead of turning into a 422. Like the `api` views, they only depend on
+// serde.
+
+/// Limit of `/api/market/leaderboard` when the request names none.
+pub const LEADERBOARD_DEFAULT_LIMIT: u32 = 10;
+
+/// A request body, tied to the one route that takes it. Clients post it to
+/// [`ApiRequest::PATH`] and the server routes that path to a handler parsing
+/// this type, so the two cannot disagree on where a body goes.
+pub trait ApiRequest: Serialize + DeserializeOwned {
+    const PATH: &'static str;
+}
+
+macro_rules! routes {
+    ($($request:ty => $path:literal,)*) => {
+        $(impl ApiRequest for $request {
+            const PATH: &'static str = $path;
+        })*
+
+        /// Path of every route taking an [`ApiRequest`].
+        pub const REQUEST_PATHS: &[&str] = &[$($path),*];
+    };
+}
+
+routes! {
+    SetAdminRequest => "/api/market/set_admin",
+    InitializeRequest => "/api/market/initialize",
+    CreateMarketRequest => "/api/market/create",
+    PlaceBetRequest => "/api/market/bet",
+    CloseBettingRequest => "/api/market/close",
+    AcceptChallengeRequest => "/api/market/challenge/accept",
+    CancelChallengeRequest => "/api/market/challenge/cancel",
+    ExpireMarketRequest => "/api/market/expire",
+    ResolveMarketRequest => "/api/market/resolve",
+    ClaimWinningsRequest => "/api/market/claim",
+    GetBalanceRequest => "/api/market/balance",
+    GetMarketInfoRequest => "/api/market/info",
+    AddCommentRequest => "/api/market/comment",
+    GetTreasuryRequest => "/api/market/treasury",
+    WithdrawTreasuryRequest => "/api/market/treasury/withdraw",
+    ResetBalancesRequest => "/api/market/reset_balances",
+    ConfiscateBalanceRequest => "/api/market/confiscate",
+    SetHouseEdgeRequest => "/api/market/house_edge",
+    DistributeDividendsRequest => "/api/market/dividends/distribute",
+    PauseRequest => "/api/market/pause",
+    UnpauseRequest => "/api/market/unpause",
+    GetUserStatsRequest => "/api/market/stats",
+    GetOpenExposureRequest => "/api/market/exposure",
+    GetLeaderboardRequest => "/api/market/leaderboard",
+    PlaceParlayRequest => "/api/market/parlay",
+    SettleParlayRequest => "/api/market/parlay/settle",
+    ReconcileSnapshot => "/api/admin/reconcile",
+    ExportSnapshotRequest => "/api/admin/export",
+    SnapshotExport => "/api/admin/import",
+    DeployRequest => "/api/admin/deploy",
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SetAdminRequest {
+    /// Full identity of the new admin, e.g. `alice@contract1`
+    pub new_admin: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InitializeRequest {
+    /// Succeed without granting anything if the user is already initialized,
+    /// and answer with an [`InitializeOutcome`](crate::api::InitializeOutcome)
+    #[serde(default)]
+    pub idempotent: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CreateMarketRequest {
+    pub description: String,
+    /// Unix seconds before which the market refuses bets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opens_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stake_cap: Option<StakeCap>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Makes the market a head-to-head challenge of `opponent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<ChallengeRequest>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChallengeRequest {
+    /// Full identity of the opponent, e.g. `bob@contract1`
+    pub opponent: String,
+    pub stake: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlaceBetRequest {
+    pub market_id: u64,
+    pub side: bool,
+    pub amount: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CloseBettingRequest {
+    pub market_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AcceptChallengeRequest {
+    pub market_id: u64,
+}
+
+/// Declines, withdraws or expires a pending challenge.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CancelChallengeRequest {
+    pub market_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExpireMarketRequest {
+    pub market_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResolveMarketRequest {
+    pub market_id: u64,
+    pub outcome: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClaimWinningsRequest {
+    pub market_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GetBalanceRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GetMarketInfoRequest {
+    pub market_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AddCommentRequest {
+    pub market_id: u64,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GetTreasuryRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WithdrawTreasuryRequest {
+    /// Full identity of the recipient
+    pub to: String,
+    pub amount: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResetBalancesRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfiscateBalanceRequest {
+    /// Full identity of the banned user
+    pub user: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SetHouseEdgeRequest {
+    pub bps: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DistributeDividendsRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PauseRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UnpauseRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GetUserStatsRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GetOpenExposureRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GetLeaderboardRequest {
+    #[serde(default = "default_leaderboard_limit")]
+    pub limit: u32,
+}
+
+fn default_leaderboard_limit() -> u32 {
+    LEADERBOARD_DEFAULT_LIMIT
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlaceParlayRequest {
+    /// `[market_id, side]` pairs
+    pub legs: Vec<(u64, bool)>,
+    pub amount: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SettleParlayRequest {
+    pub parlay_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportSnapshotRequest {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeployRequest {
+    pub contract_name: String,
+}
+
+/// Body of `/api/config`. Servers older than API version 2 only send the
+/// contract name; clients then fall back to this crate's contract constants.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigResponse {
+    pub contract_name: String,
+    #[serde(default = "first_api_version")]
+    pub api_version: u32,
+    #[serde(default)]
+    pub params: ContractParams,
+    /// Epoch of the deployed contract, once its state is indexed: market ids
+    /// only identify a market within one epoch. Absent from older servers
+    #[serde(default)]
+    pub state_epoch: Option<u64>,
+    /// Whether the admin paused every action changing the state. False
+    /// until the state is indexed, and from older servers
+    #[serde(default)]
+    pub paused: bool,
+}
+
+fn first_api_version() -> u32 {
+    1
+}
+
+/// Body of `/api/tx/{hash}`: where a transaction submitted through the server
+/// stands, for clients that lost track of it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxStatus {
+    pub tx_hash: String,
+    pub status: TxState,
+    /// Why the contract refused it, when `status` is `Failed`
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TxState {
+    Pending,
+    Success,
+    Failed,
+}
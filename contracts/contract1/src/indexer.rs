@@ -2,12 +2,18 @@ use std::str;
 
 use anyhow::{anyhow, Result};
 use client_sdk::contract_indexer::{
-    axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router},
+    axum::{
+        extract::{Path, Query, State},
+        http::StatusCode,
+        response::IntoResponse,
+        Json, Router,
+    },
     utoipa::openapi::OpenApi,
     utoipa_axum::{router::OpenApiRouter, routes},
     AppError, ContractHandler, ContractHandlerStore,
 };
 
+use crate::api::MarketFilter;
 use crate::*;
 use client_sdk::contract_indexer::axum;
 use client_sdk::contract_indexer::utoipa;
@@ -16,6 +22,13 @@ impl ContractHandler for Contract1 {
     async fn api(store: ContractHandlerStore<Contract1>) -> (Router<()>, OpenApi) {
         let (router, api) = OpenApiRouter::default()
             .routes(routes!(get_state))
+            .routes(routes!(list_markets))
+            .routes(routes!(get_odds))
+            .routes(routes!(get_market_history))
+            .routes(routes!(get_leaderboard))
+            .routes(routes!(get_treasury))
+            .routes(routes!(get_user))
+            .routes(routes!(get_user_bets))
             .split_for_parts();
 
         (router.with_state(store), api)
@@ -39,3 +52,174 @@ pub async fn get_state(
         anyhow!("No state found for contract '{}'", store.contract_name),
     ))
 }
+
+/// Number of entries returned by the leaderboard route when none is asked
+const DEFAULT_LEADERBOARD_LIMIT: usize = 10;
+
+#[derive(serde::Deserialize)]
+pub struct LeaderboardQuery {
+    limit: Option<usize>,
+}
+
+fn no_state(contract_name: &sdk::ContractName) -> AppError {
+    AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("No state found for contract '{}'", contract_name),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/markets",
+    tag = "Contract",
+    params(
+        ("status" = Option<String>, Query, description = "Filter by status: open or resolved"),
+        ("tag" = Option<String>, Query, description = "Only markets with this tag, case-insensitive")
+    ),
+    responses(
+        (status = OK, description = "List markets, newest first")
+    )
+)]
+pub async fn list_markets(
+    State(state): State<ContractHandlerStore<Contract1>>,
+    Query(filter): Query<MarketFilter>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let contract = store.state.as_ref().ok_or_else(|| no_state(&store.contract_name))?;
+    Ok(Json(contract.list_markets(&filter, unix_now())))
+}
+
+/// Scheduled markets are presented against the indexer's clock.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[utoipa::path(
+    get,
+    path = "/market/{market_id}/odds",
+    tag = "Contract",
+    params(
+        ("market_id" = u64, Path, description = "Market id")
+    ),
+    responses(
+        (status = OK, description = "Get pools and implied odds of a market"),
+        (status = NOT_FOUND, description = "Market not found")
+    )
+)]
+pub async fn get_odds(
+    State(state): State<ContractHandlerStore<Contract1>>,
+    Path(market_id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let contract = store.state.as_ref().ok_or_else(|| no_state(&store.contract_name))?;
+    contract.odds(market_id).map(Json).ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("Market #{} not found", market_id),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/market/{market_id}/history",
+    tag = "Contract",
+    params(
+        ("market_id" = u64, Path, description = "Market id")
+    ),
+    responses(
+        (status = OK, description = "Get the latest bets of a market with the pools after each"),
+        (status = NOT_FOUND, description = "Market not found")
+    )
+)]
+pub async fn get_market_history(
+    State(state): State<ContractHandlerStore<Contract1>>,
+    Path(market_id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let contract = store.state.as_ref().ok_or_else(|| no_state(&store.contract_name))?;
+    contract.market_history(market_id).map(Json).ok_or(AppError(
+        StatusCode::NOT_FOUND,
+        anyhow!("Market #{} not found", market_id),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/leaderboard",
+    tag = "Contract",
+    params(
+        ("limit" = Option<usize>, Query, description = "Number of entries, at most 100")
+    ),
+    responses(
+        (status = OK, description = "Get the highest balances")
+    )
+)]
+pub async fn get_leaderboard(
+    State(state): State<ContractHandlerStore<Contract1>>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let contract = store.state.as_ref().ok_or_else(|| no_state(&store.contract_name))?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LEADERBOARD_LIMIT)
+        .min(MAX_LEADERBOARD_LIMIT);
+    Ok(Json(contract.leaderboard(limit)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/treasury",
+    tag = "Contract",
+    responses(
+        (status = OK, description = "Get the treasury balance and the admin")
+    )
+)]
+pub async fn get_treasury(
+    State(state): State<ContractHandlerStore<Contract1>>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let contract = store.state.as_ref().ok_or_else(|| no_state(&store.contract_name))?;
+    Ok(Json(contract.treasury_info()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/{identity}",
+    tag = "Contract",
+    params(
+        ("identity" = String, Path, description = "User identity")
+    ),
+    responses(
+        (status = OK, description = "Get a user's balance, initialization status and bets")
+    )
+)]
+pub async fn get_user(
+    State(state): State<ContractHandlerStore<Contract1>>,
+    Path(identity): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let contract = store.state.as_ref().ok_or_else(|| no_state(&store.contract_name))?;
+    Ok(Json(contract.user_info(&Identity(identity))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/{identity}/bets",
+    tag = "Contract",
+    params(
+        ("identity" = String, Path, description = "User identity")
+    ),
+    responses(
+        (status = OK, description = "Get the bets placed by a user")
+    )
+)]
+pub async fn get_user_bets(
+    State(state): State<ContractHandlerStore<Contract1>>,
+    Path(identity): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    let contract = store.state.as_ref().ok_or_else(|| no_state(&store.contract_name))?;
+    Ok(Json(contract.user_bets(&Identity(identity))))
+}
@@ -0,0 +1,403 @@
+//! Layouts earlier versions of the contract committed the state in. Each one
+//! is frozen as it was deployed: a layout never changes once states were
+//! committed with it, a new one is added when `Contract1` changes. They only
+//! decode, and convert one way into the current types. Types whose layout
+//! never changed, like `UserBet` or `Parlay`, are shared with the current
+//! state until they do.
+//!
+//! | Layout | Adds |
+//! |--------|------|
+//! | [`StateV1`] | |
+//! | [`StateV2`] | `state_epoch` |
+//! | [`StateV3`] | challenges on markets |
+//! | [`StateV4`] | `burned` |
+//! | [`StateV5`] | `Market::last_bet_at` |
+//! | [`StateV6`] | nonces |
+//! | [`StateV7`] | house edge and dividends |
+//!
+//! The current layout adds `paused` to [`StateV7`].
+use std::collections::HashMap;
+
+use borsh::BorshDeserialize;
+use sdk::Identity;
+
+use crate::{
+    Challenge, Contract1, Market, MarketComment, MarketHistoryEntry, MarketStatus, Parlay, StakeCap, UserBet, UserState,
+};
+
+/// A user before nonces.
+#[derive(BorshDeserialize)]
+struct UserStateV1 {
+    balance: u128,
+    initialized: bool,
+    bets: Vec<UserBet>,
+    current_streak: u32,
+}
+
+impl From<UserStateV1> for UserState {
+    fn from(user: UserStateV1) -> Self {
+        UserStateV2 {
+            balance: user.balance,
+            initialized: user.initialized,
+            bets: user.bets,
+            current_streak: user.current_streak,
+            next_nonce: 0,
+        }
+        .into()
+    }
+}
+
+/// A user before dividends.
+#[derive(BorshDeserialize)]
+struct UserStateV2 {
+    balance: u128,
+    initialized: bool,
+    bets: Vec<UserBet>,
+    current_streak: u32,
+    next_nonce: u64,
+}
+
+impl From<UserStateV2> for UserState {
+    fn from(user: UserStateV2) -> Self {
+        UserStateV3 {
+            balance: user.balance,
+            initialized: user.initialized,
+            bets: user.bets,
+            current_streak: user.current_streak,
+            next_nonce: user.next_nonce,
+            bets_since_dividend: 0,
+        }
+        .into()
+    }
+}
+
+/// A user since dividends.
+#[derive(BorshDeserialize)]
+struct UserStateV3 {
+    balance: u128,
+    initialized: bool,
+    bets: Vec<UserBet>,
+    current_streak: u32,
+    next_nonce: u64,
+    bets_since_dividend: u32,
+}
+
+impl From<UserStateV3> for UserState {
+    fn from(user: UserStateV3) -> Self {
+        Self {
+            balance: user.balance,
+            initialized: user.initialized,
+            bets: user.bets,
+            current_streak: user.current_streak,
+            next_nonce: user.next_nonce,
+            bets_since_dividend: user.bets_since_dividend,
+        }
+    }
+}
+
+/// A market before challenges.
+#[derive(BorshDeserialize)]
+struct MarketV1 {
+    id: u64,
+    creator: Identity,
+    description: String,
+    yes_pool: u128,
+    no_pool: u128,
+    yes_bettors: HashMap<Identity, u128>,
+    no_bettors: HashMap<Identity, u128>,
+    status: MarketStatus,
+    created_at: u64,
+    comments: Vec<MarketComment>,
+    opens_at: Option<u64>,
+    stake_cap: Option<StakeCap>,
+    betting_closed: bool,
+    history: Vec<MarketHistoryEntry>,
+    tags: Vec<String>,
+}
+
+impl From<MarketV1> for Market {
+    fn from(market: MarketV1) -> Self {
+        MarketV2 {
+            id: market.id,
+            creator: market.creator,
+            description: market.description,
+            yes_pool: market.yes_pool,
+            no_pool: market.no_pool,
+            yes_bettors: market.yes_bettors,
+            no_bettors: market.no_bettors,
+            status: market.status,
+            created_at: market.created_at,
+            comments: market.comments,
+            opens_at: market.opens_at,
+            stake_cap: market.stake_cap,
+            betting_closed: market.betting_closed,
+            history: market.history,
+            tags: market.tags,
+            challenge: None,
+            accept_by: None,
+        }
+        .into()
+    }
+}
+
+/// A market before the time of its latest bet was recorded.
+#[derive(BorshDeserialize)]
+struct MarketV2 {
+    id: u64,
+    creator: Identity,
+    description: String,
+    yes_pool: u128,
+    no_pool: u128,
+    yes_bettors: HashMap<Identity, u128>,
+    no_bettors: HashMap<Identity, u128>,
+    status: MarketStatus,
+    created_at: u64,
+    comments: Vec<MarketComment>,
+    opens_at: Option<u64>,
+    stake_cap: Option<StakeCap>,
+    betting_closed: bool,
+    history: Vec<MarketHistoryEntry>,
+    tags: Vec<String>,
+    challenge: Option<Challenge>,
+    accept_by: Option<u64>,
+}
+
+impl From<MarketV2> for Market {
+    /// The history ends with the latest bet, which tells its time
+    fn from(market: MarketV2) -> Self {
+        let last_bet_at = market.history.iter().rev().find_map(|entry| entry.timestamp);
+        MarketV3 {
+            id: market.id,
+            creator: market.creator,
+            description: market.description,
+            yes_pool: market.yes_pool,
+            no_pool: market.no_pool,
+            yes_bettors: market.yes_bettors,
+            no_bettors: market.no_bettors,
+            status: market.status,
+            created_at: market.created_at,
+            comments: market.comments,
+            opens_at: market.opens_at,
+            stake_cap: market.stake_cap,
+            betting_closed: market.betting_closed,
+            history: market.history,
+            tags: market.tags,
+            challenge: market.challenge,
+            accept_by: market.accept_by,
+            last_bet_at,
+        }
+        .into()
+    }
+}
+
+/// A market before the house edge.
+#[derive(BorshDeserialize)]
+struct MarketV3 {
+    id: u64,
+    creator: Identity,
+    description: String,
+    yes_pool: u128,
+    no_pool: u128,
+    yes_bettors: HashMap<Identity, u128>,
+    no_bettors: HashMap<Identity, u128>,
+    status: MarketStatus,
+    created_at: u64,
+    comments: Vec<MarketComment>,
+    opens_at: Option<u64>,
+    stake_cap: Option<StakeCap>,
+    betting_closed: bool,
+    history: Vec<MarketHistoryEntry>,
+    tags: Vec<String>,
+    challenge: Option<Challenge>,
+    accept_by: Option<u64>,
+    last_bet_at: Option<u64>,
+}
+
+impl From<MarketV3> for Market {
+    fn from(market: MarketV3) -> Self {
+        MarketV4 {
+            id: market.id,
+            creator: market.creator,
+            description: market.description,
+            yes_pool: market.yes_pool,
+            no_pool: market.no_pool,
+            yes_bettors: market.yes_bettors,
+            no_bettors: market.no_bettors,
+            status: market.status,
+            created_at: market.created_at,
+            comments: market.comments,
+            opens_at: market.opens_at,
+            stake_cap: market.stake_cap,
+            betting_closed: market.betting_closed,
+            history: market.history,
+            tags: market.tags,
+            challenge: market.challenge,
+            accept_by: market.accept_by,
+            last_bet_at: market.last_bet_at,
+            rake: 0,
+        }
+        .into()
+    }
+}
+
+/// A market since the house edge.
+#[derive(BorshDeserialize)]
+struct MarketV4 {
+    id: u64,
+    creator: Identity,
+    description: String,
+    yes_pool: u128,
+    no_pool: u128,
+    yes_bettors: HashMap<Identity, u128>,
+    no_bettors: HashMap<Identity, u128>,
+    status: MarketStatus,
+    created_at: u64,
+    comments: Vec<MarketComment>,
+    opens_at: Option<u64>,
+    stake_cap: Option<StakeCap>,
+    betting_closed: bool,
+    history: Vec<MarketHistoryEntry>,
+    tags: Vec<String>,
+    challenge: Option<Challenge>,
+    accept_by: Option<u64>,
+    last_bet_at: Option<u64>,
+    rake: u128,
+}
+
+impl From<MarketV4> for Market {
+    fn from(market: MarketV4) -> Self {
+        Self {
+            id: market.id,
+            creator: market.creator,
+            description: market.description,
+            yes_pool: market.yes_pool,
+            no_pool: market.no_pool,
+            yes_bettors: market.yes_bettors,
+            no_bettors: market.no_bettors,
+            status: market.status,
+            created_at: market.created_at,
+            comments: market.comments,
+            opens_at: market.opens_at,
+            stake_cap: market.stake_cap,
+            betting_closed: market.betting_closed,
+            history: market.history,
+            tags: market.tags,
+            challenge: market.challenge,
+            accept_by: market.accept_by,
+            last_bet_at: market.last_bet_at,
+            rake: market.rake,
+        }
+    }
+}
+
+/// The fields every layout starts with, then those its version appended
+/// after `parlay_reserve`.
+#[derive(BorshDeserialize)]
+struct State<U, M, T> {
+    users: HashMap<Identity, U>,
+    markets: HashMap<u64, M>,
+    next_market_id: u64,
+    admin: Option<Identity>,
+    treasury: u128,
+    parlays: HashMap<u64, Parlay>,
+    next_parlay_id: u64,
+    parlay_reserve: u128,
+    tail: T,
+}
+
+#[derive(BorshDeserialize)]
+struct Epoch {
+    state_epoch: u64,
+}
+
+#[derive(BorshDeserialize)]
+struct Burns {
+    state_epoch: u64,
+    burned: u128,
+}
+
+#[derive(BorshDeserialize)]
+struct Nonces {
+    state_epoch: u64,
+    burned: u128,
+    require_nonces: bool,
+}
+
+/// Everything `Contract1` has after `parlay_reserve` but `paused`.
+#[derive(BorshDeserialize, Default)]
+struct Dividends {
+    state_epoch: u64,
+    burned: u128,
+    require_nonces: bool,
+    house_edge_bps: u16,
+    dividend_pool: u128,
+}
+
+impl From<()> for Dividends {
+    fn from(_: ()) -> Self {
+        Self::default()
+    }
+}
+
+impl From<Epoch> for Dividends {
+    fn from(tail: Epoch) -> Self {
+        Self { state_epoch: tail.state_epoch, ..Self::default() }
+    }
+}
+
+impl From<Burns> for Dividends {
+    fn from(tail: Burns) -> Self {
+        Self { state_epoch: tail.state_epoch, burned: tail.burned, ..Self::default() }
+    }
+}
+
+impl From<Nonces> for Dividends {
+    fn from(tail: Nonces) -> Self {
+        Self { state_epoch: tail.state_epoch, burned: tail.burned, require_nonces: tail.require_nonces, ..Self::default() }
+    }
+}
+
+impl<U: Into<UserState>, M: Into<Market>, T: Into<Dividends>> From<State<U, M, T>> for Contract1 {
+    fn from(state: State<U, M, T>) -> Self {
+        let tail = state.tail.into();
+        Self {
+            users: state.users.into_iter().map(|(identity, user)| (identity, user.into())).collect(),
+            markets: state.markets.into_iter().map(|(id, market)| (id, market.into())).collect(),
+            next_market_id: state.next_market_id,
+            admin: state.admin,
+            treasury: state.treasury,
+            parlays: state.parlays,
+            next_parlay_id: state.next_parlay_id,
+            parlay_reserve: state.parlay_reserve,
+            state_epoch: tail.state_epoch,
+            burned: tail.burned,
+            require_nonces: tail.require_nonces,
+            house_edge_bps: tail.house_edge_bps,
+            dividend_pool: tail.dividend_pool,
+            paused: false,
+        }
+    }
+}
+
+type StateV1 = State<UserStateV1, MarketV1, ()>;
+type StateV2 = State<UserStateV1, MarketV1, Epoch>;
+type StateV3 = State<UserStateV1, MarketV2, Epoch>;
+type StateV4 = State<UserStateV1, MarketV2, Burns>;
+type StateV5 = State<UserStateV1, MarketV3, Burns>;
+type StateV6 = State<UserStateV2, MarketV3, Nonces>;
+type StateV7 = State<UserStateV3, MarketV4, Dividends>;
+
+/// Decodes a state committed in an earlier layout, trying the newest first.
+pub(crate) fn decode(bytes: &[u8]) -> Option<Contract1> {
+    fn layout<S: BorshDeserialize + Into<Contract1>>(bytes: &[u8]) -> Option<Contract1> {
+        borsh::from_slice::<S>(bytes).ok().map(Into::into)
+    }
+
+    layout::<StateV7>(bytes)
+        .or_else(|| layout::<StateV6>(bytes))
+        .or_else(|| layout::<StateV5>(bytes))
+        .or_else(|| layout::<StateV4>(bytes))
+        .or_else(|| layout::<StateV3>(bytes))
+        .or_else(|| layout::<StateV2>(bytes))
+        .or_else(|| layout::<StateV1>(bytes))
+}
@@ -4,6 +4,13 @@ use std::collections::HashMap;
 
 use sdk::{Identity, RunResult};
 
+pub mod api;
+mod error;
+pub mod http;
+mod legacy;
+
+pub use error::MarketError;
+
 #[cfg(feature = "client")]
 pub mod client;
 #[cfg(feature = "client")]
@@ -15,25 +22,42 @@ impl sdk::ZkContract for Contract1 {
         // Parse contract inputs
         let (action, ctx) = sdk::utils::parse_raw_calldata::<MarketAction>(calldata)?;
         let identity = calldata.identity.clone();
+        let now = block_time(calldata);
+        let (action, nonce) = match action {
+            MarketAction::Nonced { nonce, action } => (*action, Some(nonce)),
+            action => (action, None),
+        };
 
-        // Execute the given action
-        let res = match action {
-            MarketAction::SetAdmin { new_admin } => self.set_admin(identity, new_admin)?,
-            MarketAction::Initialize {} => self.initialize(identity)?,
-            MarketAction::CreateMarket { description } => {
-                self.create_market(identity, description)?
+        // Reject malformed identities before any state is touched
+        validate_identity(&identity, &ctx.contract_name)?;
+        match &action {
+            MarketAction::SetAdmin { new_admin } => validate_identity(new_admin, &ctx.contract_name)?,
+            MarketAction::WithdrawTreasury { to, .. } => validate_identity(to, &ctx.contract_name)?,
+            MarketAction::ConfiscateBalance { user } => validate_identity(user, &ctx.contract_name)?,
+            MarketAction::CreateMarket { challenge: Some(challenge), .. } => {
+                validate_identity(&challenge.opponent, &ctx.contract_name)?
             }
-            MarketAction::PlaceBet { market_id, side, amount } => {
-                self.place_bet(identity, market_id, side, amount)?
-            }
-            MarketAction::ResolveMarket { market_id, outcome } => {
-                self.resolve_market(identity, market_id, outcome)?
+            _ => {}
+        }
+
+        // Queries only borrow the state: they leave its commitment as it was,
+        // so the prover can tell they need no new state
+        let res = if action.is_read_only() {
+            if nonce.is_some() {
+                return Err(MarketError::InvalidNoncedAction.into());
             }
-            MarketAction::ClaimWinnings { market_id } => {
-                self.claim_winnings(identity, market_id)?
+            let before = cfg!(debug_assertions).then(|| self.commit());
+            let res = self.query(identity, action, now)?;
+            debug_assert!(before.map_or(true, |before| before == self.commit()), "a query changed the state");
+            res
+        } else {
+            // A replayed blob carries a nonce its sender already used
+            self.check_nonce(&identity, nonce)?;
+            let res = self.apply(identity.clone(), action, now)?;
+            if nonce.is_some() {
+                self.get_or_create_user(identity).next_nonce += 1;
             }
-            MarketAction::GetBalance => self.get_balance(identity)?,
-            MarketAction::GetMarketInfo { market_id } => self.get_market_info(market_id)?,
+            res
         };
 
         Ok((res.into_bytes(), ctx, vec![]))
@@ -45,18 +69,100 @@ impl sdk::ZkContract for Contract1 {
     }
 }
 
+impl Contract1 {
+    /// Answers a read-only action, see [`MarketAction::is_read_only`].
+    fn query(&self, identity: Identity, action: MarketAction, now: Option<u64>) -> Result<String, MarketError> {
+        match action {
+            MarketAction::GetBalance => self.get_balance(identity),
+            MarketAction::GetMarketInfo { market_id } => self.get_market_info(market_id, now),
+            MarketAction::GetTreasury => self.get_treasury(),
+            MarketAction::GetUserStats => self.get_user_stats(identity),
+            MarketAction::GetOpenExposure => self.get_open_exposure(identity),
+            MarketAction::GetLeaderboard { limit } => self.get_leaderboard(limit),
+            MarketAction::GetMarketHistory { market_id } => self.get_market_history(market_id),
+            MarketAction::ExportSnapshot => self.export_snapshot(identity),
+            action => unreachable!("{:?} changes the state", action),
+        }
+    }
+
+    /// Runs an action that changes the state. While the contract is paused
+    /// only [`MarketAction::Unpause`] goes through.
+    fn apply(&mut self, identity: Identity, action: MarketAction, now: Option<u64>) -> Result<String, MarketError> {
+        if self.paused && action != MarketAction::Unpause {
+            return Err(MarketError::ContractPaused);
+        }
+        match action {
+            MarketAction::SetAdmin { new_admin } => self.set_admin(identity, new_admin),
+            MarketAction::Initialize { idempotent } => self.initialize(identity, idempotent),
+            MarketAction::CreateMarket { description, opens_at, stake_cap, tags, challenge } => {
+                self.create_market(identity, description, opens_at, stake_cap, tags, challenge, now)
+            }
+            MarketAction::PlaceBet { market_id, side, amount } => self.place_bet(identity, market_id, side, amount, now),
+            MarketAction::ResolveMarket { market_id, outcome } => self.resolve_market(identity, market_id, outcome, now),
+            MarketAction::ClaimWinnings { market_id } => self.claim_winnings(identity, market_id),
+            MarketAction::WithdrawTreasury { to, amount } => self.withdraw_treasury(identity, to, amount),
+            MarketAction::AddComment { market_id, text } => self.add_comment(identity, market_id, text),
+            MarketAction::PlaceParlay { legs, amount } => self.place_parlay(identity, legs, amount, now),
+            MarketAction::SettleParlay { parlay_id } => self.settle_parlay(parlay_id),
+            MarketAction::CloseBetting { market_id } => self.close_betting(identity, market_id),
+            MarketAction::ResetBalances => self.reset_balances(identity),
+            MarketAction::AcceptChallenge { market_id } => self.accept_challenge(identity, market_id, now),
+            MarketAction::CancelChallenge { market_id } => self.cancel_challenge(identity, market_id, now),
+            MarketAction::ExpireMarket { market_id } => self.expire_market(market_id, now),
+            MarketAction::ImportSnapshot { data } => self.import_snapshot(identity, data),
+            MarketAction::ConfiscateBalance { user } => self.confiscate_balance(identity, user),
+            MarketAction::RequireNonces { required } => self.require_nonces(identity, required),
+            MarketAction::SetHouseEdge { bps } => self.set_house_edge(identity, bps),
+            MarketAction::DistributeDividends => self.distribute_dividends(identity),
+            MarketAction::Pause => self.set_paused(identity, true),
+            MarketAction::Unpause => self.set_paused(identity, false),
+            MarketAction::Nonced { .. } => Err(MarketError::InvalidNoncedAction),
+            MarketAction::GetBalance
+            | MarketAction::GetMarketInfo { .. }
+            | MarketAction::GetTreasury
+            | MarketAction::GetUserStats
+            | MarketAction::GetOpenExposure
+            | MarketAction::GetLeaderboard { .. }
+            | MarketAction::GetMarketHistory { .. }
+            | MarketAction::ExportSnapshot => unreachable!("queries are answered by Contract1::query"),
+        }
+    }
+}
+
 impl Contract1 {
     pub fn new() -> Self {
         Self {
             users: HashMap::new(),
             markets: HashMap::new(),
             next_market_id: 0,
+            admin: None,
+            treasury: 0,
+            parlays: HashMap::new(),
+            next_parlay_id: 0,
+            parlay_reserve: 0,
+            state_epoch: 0,
+            burned: 0,
+            require_nonces: false,
+            house_edge_bps: 0,
+            dividend_pool: 0,
+            paused: false,
         }
     }
     
-    pub fn new_with_admin(_admin: Identity) -> Self {
-        // Admin functionality removed - just create a normal instance
-        Self::new()
+    /// A fresh state for a deployment identified by `state_epoch`, e.g. its
+    /// unix time.
+    pub fn with_epoch(state_epoch: u64) -> Self {
+        Self {
+            state_epoch,
+            ..Self::new()
+        }
+    }
+    
+    pub fn new_with_admin(admin: Identity) -> Self {
+        Self {
+            admin: Some(admin),
+            ..Self::new()
+        }
     }
 
     fn get_or_create_user(&mut self, identity: Identity) -> &mut UserState {
@@ -64,18 +170,82 @@ impl Contract1 {
             balance: 0,
             initialized: false,
             bets: Vec::new(),
+            current_streak: 0,
+            next_nonce: 0,
+            bets_since_dividend: 0,
+        })
+    }
+
+    /// The nonce the next [`MarketAction::Nonced`] action of `identity` must carry.
+    pub fn next_nonce(&self, identity: &Identity) -> u64 {
+        self.users.get(identity).map_or(0, |user| user.next_nonce)
+    }
+
+    /// A nonce must be the sender's next one. Actions without one are
+    /// accepted unless the admin turned on [`Contract1::require_nonces`].
+    fn check_nonce(&self, identity: &Identity, nonce: Option<u64>) -> Result<(), MarketError> {
+        let expected = self.next_nonce(identity);
+        match nonce {
+            None if self.require_nonces => Err(MarketError::NonceRequired),
+            None => Ok(()),
+            Some(nonce) if nonce < expected => Err(MarketError::NonceReplayed { nonce, expected }),
+            Some(nonce) if nonce > expected => Err(MarketError::NonceGap { nonce, expected }),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Admin only: whether actions changing the state must carry a nonce.
+    /// Off by default, so clients predating nonces keep working.
+    pub fn require_nonces(&mut self, identity: Identity, required: bool) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        self.require_nonces = required;
+        Ok(if required {
+            "Actions now need a nonce".to_string()
+        } else {
+            "Actions without a nonce are accepted again".to_string()
         })
     }
     
-    pub fn set_admin(&mut self, _identity: Identity, new_admin: Identity) -> Result<String, String> {
-        // Admin functionality removed - this is now a no-op
-        Ok(format!("Admin functionality removed - anyone can do everything now"))
+    /// Admin only: while paused, every action changing the state but
+    /// [`MarketAction::Unpause`] is refused, e.g. to stop a payout bug from
+    /// spreading. Queries keep working.
+    pub fn set_paused(&mut self, identity: Identity, paused: bool) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        self.paused = paused;
+        Ok(if paused {
+            "Contract paused".to_string()
+        } else {
+            "Contract unpaused".to_string()
+        })
+    }
+
+    /// The first call claims the admin role; afterwards only the current
+    /// admin can hand it over.
+    pub fn set_admin(&mut self, identity: Identity, new_admin: Identity) -> Result<String, MarketError> {
+        if self.admin.is_some() {
+            self.ensure_admin(&identity)?;
+        }
+        self.admin = Some(new_admin.clone());
+        Ok(format!("Admin set to {}", new_admin.0))
     }
 
-    pub fn initialize(&mut self, identity: Identity) -> Result<String, String> {
+    fn ensure_admin(&self, identity: &Identity) -> Result<(), MarketError> {
+        match &self.admin {
+            Some(admin) if admin == identity => Ok(()),
+            Some(_) => Err(MarketError::Unauthorized),
+            None => Err(MarketError::NoAdmin),
+        }
+    }
+
+    /// With `idempotent`, initializing an already initialized user succeeds
+    /// without granting anything, so clients that lost track of it can retry.
+    pub fn initialize(&mut self, identity: Identity, idempotent: bool) -> Result<String, MarketError> {
         let user = self.get_or_create_user(identity.clone());
         if user.initialized {
-            return Err("User already initialized".to_string());
+            if idempotent {
+                return Ok(format!("Already initialized with {} balance", user.balance));
+            }
+            return Err(MarketError::UserAlreadyInitialized);
         }
         
         user.balance = INITIAL_BALANCE;
@@ -84,20 +254,48 @@ impl Contract1 {
         Ok(format!("Initialized with {} balance", INITIAL_BALANCE))
     }
 
+    /// `opens_at` (unix seconds) schedules the market: it is listed right
+    /// away but only takes bets from that instant on. `stake_cap` bounds
+    /// what a single user may stake on either side. `tags` are stored
+    /// lowercased, without a leading `#` and without duplicates.
+    ///
+    /// With a `challenge`, the creator's stake is escrowed on YES right away
+    /// and the market waits for the named opponent to match it on NO.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_market(
         &mut self,
         identity: Identity,
         description: String,
-    ) -> Result<String, String> {
-        let user = self.users.get(&identity).ok_or("User not initialized")?;
+        opens_at: Option<u64>,
+        stake_cap: Option<StakeCap>,
+        tags: Vec<String>,
+        challenge: Option<Challenge>,
+        now: Option<u64>,
+    ) -> Result<String, MarketError> {
+        let user = self.users.get(&identity).ok_or(MarketError::UserNotInitialized)?;
         if !user.initialized {
-            return Err("User not initialized. Use Initialize first.".to_string());
+            return Err(MarketError::UserNotInitialized);
+        }
+        if matches!(stake_cap, Some(StakeCap::OppositePoolBps(0)) | Some(StakeCap::Absolute(0))) {
+            return Err(MarketError::InvalidStakeCap);
+        }
+        let tags = normalize_tags(tags)?;
+        if let Some(challenge) = &challenge {
+            if challenge.opponent == identity || challenge.stake < MIN_BET || opens_at.is_some() || stake_cap.is_some() {
+                return Err(MarketError::InvalidChallenge);
+            }
+            if user.balance < challenge.stake {
+                return Err(MarketError::InsufficientBalance {
+                    have: user.balance,
+                    need: challenge.stake,
+                });
+            }
         }
 
         self.next_market_id += 1;
         let market_id = self.next_market_id;
 
-        let market = Market {
+        let mut market = Market {
             id: market_id,
             creator: identity,
             description,
@@ -106,12 +304,161 @@ impl Contract1 {
             yes_bettors: HashMap::new(),
             no_bettors: HashMap::new(),
             status: MarketStatus::Open,
-            created_at: 0, // In production, use actual timestamp
+            created_at: now.unwrap_or(0),
+            comments: Vec::new(),
+            opens_at,
+            stake_cap,
+            betting_closed: false,
+            history: Vec::new(),
+            tags,
+            challenge: None,
+            accept_by: None,
+            last_bet_at: None,
+            rake: 0,
+        };
+
+        let Some(challenge) = challenge else {
+            self.markets.insert(market_id, market);
+            return Ok(format!("Market #{} created", market_id));
         };
 
+        // Escrow the creator's side of the challenge
+        let user = self.users.get_mut(&market.creator).ok_or(MarketError::UserNotInitialized)?;
+        user.balance -= challenge.stake;
+        user.bets.push(UserBet {
+            market_id,
+            side: true,
+            amount: challenge.stake,
+            claimed: false,
+        });
+        user.bets_since_dividend += 1;
+        market.yes_pool = challenge.stake;
+        market.yes_bettors.insert(market.creator.clone(), challenge.stake);
+        market.record_history(MarketHistoryEntry {
+            bettor: market.creator.clone(),
+            side: true,
+            amount: challenge.stake,
+            timestamp: now,
+            yes_pool: challenge.stake,
+            no_pool: 0,
+        });
+        market.status = MarketStatus::PendingAcceptance;
+        market.accept_by = now.map(|now| now.saturating_add(CHALLENGE_ACCEPT_WINDOW));
+        let message = format!(
+            "Challenge #{} created, waiting for {} to match {}",
+            market_id, challenge.opponent.0, challenge.stake
+        );
+        market.challenge = Some(challenge);
         self.markets.insert(market_id, market);
-        
-        Ok(format!("Market #{} created", market_id))
+        Ok(message)
+    }
+
+    /// The named opponent matches the creator's stake on NO, which opens the
+    /// market. Nobody else can bet on it, so betting closes at once.
+    pub fn accept_challenge(&mut self, identity: Identity, market_id: u64, now: Option<u64>) -> Result<String, MarketError> {
+        let market = self.markets.get_mut(&market_id).ok_or(MarketError::MarketNotFound)?;
+        let Some(challenge) = market.challenge.clone().filter(|_| market.status == MarketStatus::PendingAcceptance) else {
+            return Err(MarketError::ChallengeNotPending { market_id });
+        };
+        if challenge.opponent != identity {
+            return Err(MarketError::NotChallengeOpponent { market_id });
+        }
+        if market.is_challenge_expired(now) {
+            return Err(MarketError::ChallengeExpired { market_id });
+        }
+        let user = self.users.get_mut(&identity).ok_or(MarketError::UserNotInitialized)?;
+        if !user.initialized {
+            return Err(MarketError::UserNotInitialized);
+        }
+        if user.balance < challenge.stake {
+            return Err(MarketError::InsufficientBalance {
+                have: user.balance,
+                need: challenge.stake,
+            });
+        }
+
+        user.balance -= challenge.stake;
+        user.bets.push(UserBet {
+            market_id,
+            side: false,
+            amount: challenge.stake,
+            claimed: false,
+        });
+        user.bets_since_dividend += 1;
+        market.no_pool = challenge.stake;
+        market.no_bettors.insert(identity.clone(), challenge.stake);
+        market.record_history(MarketHistoryEntry {
+            bettor: identity,
+            side: false,
+            amount: challenge.stake,
+            timestamp: now,
+            yes_pool: market.yes_pool,
+            no_pool: challenge.stake,
+        });
+        market.status = MarketStatus::Open;
+        market.betting_closed = true;
+        Ok(format!(
+            "Challenge #{} accepted, {} at stake",
+            market_id,
+            market.yes_pool + market.no_pool
+        ))
+    }
+
+    /// Calls off a challenge nobody accepted and refunds its creator. The
+    /// opponent may decline it, the creator may withdraw it, and anyone may
+    /// cancel it once its acceptance window is over.
+    pub fn cancel_challenge(&mut self, identity: Identity, market_id: u64, now: Option<u64>) -> Result<String, MarketError> {
+        let market = self.markets.get_mut(&market_id).ok_or(MarketError::MarketNotFound)?;
+        let Some(challenge) = market.challenge.as_ref().filter(|_| market.status == MarketStatus::PendingAcceptance) else {
+            return Err(MarketError::ChallengeNotPending { market_id });
+        };
+        if identity != market.creator && identity != challenge.opponent && !market.is_challenge_expired(now) {
+            return Err(MarketError::NotChallengeParty { market_id });
+        }
+
+        let refund = market.yes_pool;
+        if let Some(creator) = self.users.get_mut(&market.creator) {
+            creator.balance = creator.balance.checked_add(refund).ok_or(MarketError::Overflow)?;
+            creator.bets.retain(|bet| bet.market_id != market_id);
+        }
+        market.yes_pool = 0;
+        market.yes_bettors.clear();
+        market.status = MarketStatus::Cancelled;
+        Ok(format!("Challenge #{} cancelled, {} refunded to {}", market_id, refund, market.creator.0))
+    }
+
+    /// Voids a market nobody resolved within `MAX_MARKET_LIFETIME` of its
+    /// creation and refunds every stake. Anyone may call it, so a forgotten
+    /// market cannot lock its pools forever.
+    pub fn expire_market(&mut self, market_id: u64, now: Option<u64>) -> Result<String, MarketError> {
+        let market = self.markets.get_mut(&market_id).ok_or(MarketError::MarketNotFound)?;
+        if market.status != MarketStatus::Open {
+            return Err(MarketError::MarketNotOpen);
+        }
+        let expires_at = market.expires_at();
+        if !expires_at.is_some_and(|expires_at| now.is_some_and(|now| now >= expires_at)) {
+            return Err(MarketError::MarketNotExpired { market_id, expires_at });
+        }
+
+        let mut refunded = 0u128;
+        for (bettor, stake) in market.yes_bettors.iter().chain(&market.no_bettors) {
+            if let Some(user) = self.users.get_mut(bettor) {
+                user.balance += stake;
+                refunded += stake;
+                for bet in user.bets.iter_mut().filter(|b| b.market_id == market_id) {
+                    bet.claimed = true;
+                }
+            }
+        }
+        market.status = MarketStatus::Void;
+        // Nobody is left to refund the stakes of bettors no longer known
+        self.burned += (market.yes_pool + market.no_pool).saturating_sub(refunded);
+        Ok(format!(
+            "Market #{} expired unresolved: refunded {} to {} bettors",
+            market_id,
+            refunded,
+            market.bettor_count()
+        ))
     }
 
     pub fn place_bet(
@@ -120,27 +467,43 @@ impl Contract1 {
         market_id: u64,
         side: bool, // true = yes, false = no
         amount: u128,
-    ) -> Result<String, String> {
+        now: Option<u64>,
+    ) -> Result<String, MarketError> {
+        if amount < MIN_BET {
+            return Err(MarketError::InvalidAmount);
+        }
+
         // Check user has enough balance
-        let user = self.users.get_mut(&identity).ok_or("User not initialized")?;
+        let user = self.users.get_mut(&identity).ok_or(MarketError::UserNotInitialized)?;
         if !user.initialized {
-            return Err("User not initialized. Use Initialize first.".to_string());
+            return Err(MarketError::UserNotInitialized);
         }
         
         if user.balance < amount {
-            return Err(format!(
-                "Insufficient balance. Have: {}, Need: {}",
-                user.balance, amount
-            ));
+            return Err(MarketError::InsufficientBalance {
+                have: user.balance,
+                need: amount,
+            });
         }
 
         // Check market exists and is open
         let market = self.markets.get_mut(&market_id)
-            .ok_or("Market not found")?;
+            .ok_or(MarketError::MarketNotFound)?;
         
-        if market.status != MarketStatus::Open {
-            return Err("Market is not open for betting".to_string());
+        if market.status != MarketStatus::Open || market.betting_closed {
+            return Err(MarketError::BettingClosed);
+        }
+        if let Some(opens_at) = market.opens_at.filter(|_| market.is_scheduled(now)) {
+            return Err(MarketError::NotOpenYet { market_id, opens_at });
+        }
+        if market.bettor_count() >= MAX_BETTORS_PER_MARKET && !market.has_bettor(&identity) {
+            return Err(MarketError::MarketFull { market_id, max: MAX_BETTORS_PER_MARKET });
+        }
+        if let Some(allowed) = market.stake_allowance(&identity, side).filter(|allowed| amount > *allowed) {
+            return Err(MarketError::StakeCapExceeded { market_id, allowed });
         }
+        let pool = if side { market.yes_pool } else { market.no_pool };
+        pool.checked_add(amount).ok_or(MarketError::Overflow)?;
 
         // Deduct balance and place bet
         user.balance -= amount;
@@ -150,15 +513,25 @@ impl Contract1 {
             amount,
             claimed: false,
         });
+        user.bets_since_dividend += 1;
 
         // Add to market pools
         if side {
             market.yes_pool += amount;
-            *market.yes_bettors.entry(identity).or_insert(0) += amount;
+            *market.yes_bettors.entry(identity.clone()).or_insert(0) += amount;
         } else {
             market.no_pool += amount;
-            *market.no_bettors.entry(identity).or_insert(0) += amount;
+            *market.no_bettors.entry(identity.clone()).or_insert(0) += amount;
         }
+        let (yes_pool, no_pool) = (market.yes_pool, market.no_pool);
+        market.record_history(MarketHistoryEntry {
+            bettor: identity,
+            side,
+            amount,
+            timestamp: now,
+            yes_pool,
+            no_pool,
+        });
 
         let side_str = if side { "YES" } else { "NO" };
         Ok(format!(
@@ -167,50 +540,76 @@ impl Contract1 {
         ))
     }
 
+    /// Stops `market_id` from taking bets while it waits for its resolution,
+    /// typically once its deadline passed. Only its creator or the admin may
+    /// close it; closing it again succeeds so callers can safely retry.
+    pub fn close_betting(&mut self, identity: Identity, market_id: u64) -> Result<String, MarketError> {
+        let admin = self.admin.clone();
+        let market = self.markets.get_mut(&market_id).ok_or(MarketError::MarketNotFound)?;
+        if market.status != MarketStatus::Open {
+            return Err(MarketError::MarketNotOpen);
+        }
+        if market.creator != identity && admin.as_ref() != Some(&identity) {
+            return Err(MarketError::Unauthorized);
+        }
+        if market.betting_closed {
+            return Ok(format!("Betting on market #{} is already closed", market_id));
+        }
+        market.betting_closed = true;
+        Ok(format!("Betting closed on market #{}", market_id))
+    }
+
+    /// Settles `market_id` and pays its winners. A market cannot be resolved
+    /// within [`RESOLUTION_COOLDOWN`] of its latest bet, so nobody can bet
+    /// big and resolve before others can react.
     pub fn resolve_market(
         &mut self,
         _identity: Identity,
         market_id: u64,
         outcome: bool, // true = yes won, false = no won
-    ) -> Result<String, String> {
+        now: Option<u64>,
+    ) -> Result<String, MarketError> {
         // Anyone can resolve markets now
         
         let market = self.markets.get_mut(&market_id)
-            .ok_or("Market not found")?;
+            .ok_or(MarketError::MarketNotFound)?;
         
         if market.status != MarketStatus::Open {
-            return Err("Market is not open".to_string());
+            return Err(MarketError::MarketNotOpen);
+        }
+        if let Some(resolvable_at) = market.resolvable_at().filter(|at| now.map_or(true, |now| now < *at)) {
+            return Err(MarketError::ResolutionTooEarly { market_id, resolvable_at });
         }
 
         // Calculate payouts before changing status
         let winning_pool = if outcome { market.yes_pool } else { market.no_pool };
         let losing_pool = if outcome { market.no_pool } else { market.yes_pool };
-        let total_pool = winning_pool + losing_pool;
-        
-        // Get winners list
-        let winners: Vec<(Identity, u128)> = if outcome {
-            market.yes_bettors.clone().into_iter().collect()
-        } else {
-            market.no_bettors.clone().into_iter().collect()
-        };
+        // The house edge is taken from the losers' stakes only, so winners
+        // never get back less than they staked
+        market.rake = losing_pool.checked_mul(self.house_edge_bps as u128).ok_or(MarketError::Overflow)? / 10_000;
+        let total_pool = market.payout_pool();
         
+        // Borrow the winners in place: users and markets are separate fields
+        let winners = if outcome { &market.yes_bettors } else { &market.no_bettors };
+        let winner_count = winners.len();
+
         // Distribute winnings to all winners
         let mut total_distributed = 0u128;
-        for (winner_id, stake) in winners.iter() {
+        let mut unpayable = 0u128;
+        for (winner_id, stake) in winners {
             if winning_pool > 0 {
-                // Calculate payout using parimutuel formula
-                let payout = (*stake as f64 / winning_pool as f64 * total_pool as f64) as u128;
+                let payout = parimutuel_payout(*stake, winning_pool, total_pool);
                 
-                // Add winnings to user balance
+                // Add winnings to user balance. The payout covers the stake
+                // the bettor map aggregates, so every leg the winner holds on
+                // this market, on either side, is settled with it
                 if let Some(user) = self.users.get_mut(winner_id) {
-                    user.balance += payout;
-                    total_distributed += payout;
-                    
-                    // Mark their bet as claimed
-                    if let Some(bet) = user.bets.iter_mut()
-                        .find(|b| b.market_id == market_id && !b.claimed) {
-                        bet.claimed = true;
+                    if user.settle_bets(market_id, outcome) != Some(true) {
+                        user.balance += payout;
+                        total_distributed += payout;
                     }
+                } else {
+                    unpayable += payout;
                 }
             }
         }
@@ -221,10 +620,19 @@ impl Contract1 {
             MarketStatus::ResolvedNo
         };
 
+        // Rounding dust, and the whole pool when nobody backed the winning
+        // side, goes to the treasury so every unit stays accounted for. The
+        // payouts of winners no longer known are burned instead: the
+        // treasury did not earn them.
+        self.burned += unpayable;
+        self.treasury += total_pool.saturating_sub(total_distributed + unpayable);
+        self.dividend_pool += market.rake;
+        self.settle_streaks(market_id, outcome);
+
         let outcome_str = if outcome { "YES" } else { "NO" };
         Ok(format!(
             "Market #{} resolved as {}. Distributed {} to {} winners", 
-            market_id, outcome_str, total_distributed, winners.len()
+            market_id, outcome_str, total_distributed, winner_count
         ))
     }
 
@@ -232,9 +640,9 @@ impl Contract1 {
         &mut self,
         identity: Identity,
         market_id: u64,
-    ) -> Result<String, String> {
+    ) -> Result<String, MarketError> {
         let market = self.markets.get(&market_id)
-            .ok_or("Market not found")?;
+            .ok_or(MarketError::MarketNotFound)?;
         
         let (is_resolved, winning_side) = match market.status {
             MarketStatus::ResolvedYes => (true, true),
@@ -243,63 +651,484 @@ impl Contract1 {
         };
         
         if !is_resolved {
-            return Err("Market not resolved yet".to_string());
+            return Err(MarketError::MarketNotResolved);
         }
 
         let user = self.users.get_mut(&identity)
-            .ok_or("User not found")?;
-        
-        // Find user's bet on this market
-        let bet = user.bets.iter_mut()
-            .find(|b| b.market_id == market_id && !b.claimed)
-            .ok_or("No unclaimed bet found for this market")?;
+            .ok_or(MarketError::UserNotFound)?;
         
-        if bet.side != winning_side {
-            bet.claimed = true;
-            return Ok("Your bet did not win".to_string());
+        let winning_pool = if winning_side { market.yes_pool } else { market.no_pool };
+        let total_pool = market.payout_pool();
+        let holds_winning_leg = user
+            .bets
+            .iter()
+            .any(|b| b.market_id == market_id && !b.claimed && b.side == winning_side);
+        if winning_pool == 0 && holds_winning_leg {
+            return Err(MarketError::NoWinningPool);
         }
 
-        // Calculate winnings using parimutuel formula
+        // Every leg is settled at once, whatever order they were placed in
+        let already_paid = user.settle_bets(market_id, winning_side).ok_or(MarketError::NoUnclaimedBet)?;
         let user_stake = if winning_side {
-            *market.yes_bettors.get(&identity).unwrap_or(&0)
+            market.yes_bettors.get(&identity).copied().unwrap_or(0)
         } else {
-            *market.no_bettors.get(&identity).unwrap_or(&0)
+            market.no_bettors.get(&identity).copied().unwrap_or(0)
         };
-        
-        let winning_pool = if winning_side { market.yes_pool } else { market.no_pool };
-        let losing_pool = if winning_side { market.no_pool } else { market.yes_pool };
-        let total_pool = winning_pool + losing_pool;
-        
-        if winning_pool == 0 {
-            return Err("No winning pool".to_string());
+        if already_paid || user_stake == 0 {
+            return Ok("Your bet did not win".to_string());
         }
-        
-        // Payout = (user_stake / winning_pool) * total_pool
-        let payout = (user_stake as f64 / winning_pool as f64 * total_pool as f64) as u128;
-        
+
+        // Calculate winnings using parimutuel formula
+        let payout = parimutuel_payout(user_stake, winning_pool, total_pool);
         user.balance += payout;
-        bet.claimed = true;
         
         Ok(format!("Claimed {} winnings from market #{}", payout, market_id))
     }
 
-    pub fn get_balance(&self, identity: Identity) -> Result<String, String> {
-        let user = self.users.get(&identity)
-            .ok_or("User not found")?;
-        Ok(format!("Balance: {}", user.balance))
+    /// Stakes `amount` on every leg winning. The stake stays out of the
+    /// markets' pools: it is locked in the parlay reserve together with the
+    /// treasury funds needed to pay the largest possible payout, so a
+    /// parlay is refused when the treasury cannot back it.
+    pub fn place_parlay(
+        &mut self,
+        identity: Identity,
+        legs: Vec<(u64, bool)>,
+        amount: u128,
+        now: Option<u64>,
+    ) -> Result<String, MarketError> {
+        if !(MIN_PARLAY_LEGS..=MAX_PARLAY_LEGS).contains(&legs.len()) {
+            return Err(MarketError::ParlayLegCount {
+                min: MIN_PARLAY_LEGS,
+                max: MAX_PARLAY_LEGS,
+            });
+        }
+        if amount < MIN_BET {
+            return Err(MarketError::InvalidAmount);
+        }
+
+        let user = self.users.get(&identity).ok_or(MarketError::UserNotInitialized)?;
+        if !user.initialized {
+            return Err(MarketError::UserNotInitialized);
+        }
+        if user.balance < amount {
+            return Err(MarketError::InsufficientBalance {
+                have: user.balance,
+                need: amount,
+            });
+        }
+
+        for (index, (market_id, _)) in legs.iter().enumerate() {
+            if legs[..index].iter().any(|(other, _)| other == market_id) {
+                return Err(MarketError::DuplicateParlayLeg { market_id: *market_id });
+            }
+            let market = self.markets.get(market_id).ok_or(MarketError::MarketNotFound)?;
+            if market.status != MarketStatus::Open || market.betting_closed {
+                return Err(MarketError::BettingClosed);
+            }
+            if let Some(opens_at) = market.opens_at.filter(|_| market.is_scheduled(now)) {
+                return Err(MarketError::NotOpenYet { market_id: *market_id, opens_at });
+            }
+        }
+
+        let max_payout = amount.checked_mul(MAX_PARLAY_MULTIPLIER).ok_or(MarketError::Overflow)?;
+        let backing = max_payout - amount;
+        if backing > self.treasury {
+            return Err(MarketError::ParlayExposureExceeded {
+                have: self.treasury,
+                need: backing,
+            });
+        }
+
+        let user = self.users.get_mut(&identity).ok_or(MarketError::UserNotInitialized)?;
+        user.balance -= amount;
+        user.bets_since_dividend += 1;
+        self.treasury -= backing;
+        self.parlay_reserve += max_payout;
+
+        self.next_parlay_id += 1;
+        let parlay_id = self.next_parlay_id;
+        let leg_count = legs.len();
+        self.parlays.insert(
+            parlay_id,
+            Parlay {
+                id: parlay_id,
+                owner: identity,
+                legs: legs
+                    .into_iter()
+                    .map(|(market_id, side)| ParlayLeg { market_id, side })
+                    .collect(),
+                stake: amount,
+                max_payout,
+                status: ParlayStatus::Open,
+            },
+        );
+
+        Ok(format!(
+            "Parlay #{} placed: {} on {} legs, paying up to {}",
+            parlay_id, amount, leg_count, max_payout
+        ))
+    }
+
+    /// Pays out `parlay_id` once all its markets are resolved. Anyone may
+    /// settle it; the payout goes to its owner.
+    ///
+    /// Each winning leg multiplies the stake by its market's total pool over
+    /// its winning pool. Pools stop moving at resolution, so these are the
+    /// odds the market closed at. A leg whose side won although nobody in
+    /// the pool backed it has no odds and is void: it neither multiplies
+    /// nor sinks the parlay, and a parlay of only void legs is refunded.
+    pub fn settle_parlay(&mut self, parlay_id: u64) -> Result<String, MarketError> {
+        let parlay = self.parlays.get(&parlay_id).ok_or(MarketError::ParlayNotFound)?;
+        if parlay.status != ParlayStatus::Open {
+            return Err(MarketError::ParlayAlreadySettled);
+        }
+
+        let mut payout = parlay.stake;
+        let mut lost = false;
+        let mut void_legs = 0;
+        for leg in &parlay.legs {
+            let market = self.markets.get(&leg.market_id).ok_or(MarketError::MarketNotFound)?;
+            let outcome = match market.status {
+                MarketStatus::Open | MarketStatus::PendingAcceptance => {
+                    return Err(MarketError::ParlayLegOpen { market_id: leg.market_id })
+                }
+                MarketStatus::ResolvedYes => true,
+                MarketStatus::ResolvedNo => false,
+                // Parlays only take legs on open markets, which are never cancelled
+                MarketStatus::Cancelled => return Err(MarketError::MarketNotResolved),
+                MarketStatus::Void => {
+                    void_legs += 1;
+                    continue;
+                }
+            };
+            let winning_pool = if outcome { market.yes_pool } else { market.no_pool };
+            let total_pool = market.payout_pool();
+            if leg.side != outcome {
+                lost = true;
+            } else if let Some(multiplied) = payout.saturating_mul(total_pool).checked_div(winning_pool) {
+                payout = multiplied;
+            } else {
+                void_legs += 1;
+            }
+        }
+
+        let status = if lost {
+            ParlayStatus::Lost
+        } else if void_legs == parlay.legs.len() {
+            ParlayStatus::Void
+        } else {
+            ParlayStatus::Won {
+                payout: payout.min(parlay.max_payout),
+            }
+        };
+        let paid = match status {
+            ParlayStatus::Won { payout } => payout,
+            ParlayStatus::Void => parlay.stake,
+            _ => 0,
+        };
+        let owner = parlay.owner.clone();
+        let max_payout = parlay.max_payout;
+
+        // What the parlay did not pay, the stake included when it lost,
+        // returns to the treasury
+        self.parlay_reserve -= max_payout;
+        self.treasury += max_payout - paid;
+        if let Some(user) = self.users.get_mut(&owner) {
+            user.balance += paid;
+        }
+        let message = match status {
+            ParlayStatus::Won { payout } => format!("Parlay #{} won: paid {} to {}", parlay_id, payout, owner.0),
+            ParlayStatus::Void => format!("Parlay #{} is void: refunded {} to {}", parlay_id, paid, owner.0),
+            _ => format!("Parlay #{} lost", parlay_id),
+        };
+        if let Some(parlay) = self.parlays.get_mut(&parlay_id) {
+            parlay.status = status;
+        }
+        Ok(message)
     }
 
-    pub fn get_market_info(&self, market_id: u64) -> Result<String, String> {
+    /// Moves the streak of everyone who bet on the just resolved `market_id`.
+    /// This runs once, at resolution, whichever way the winnings are later
+    /// collected: a market counts once per user however many bets they
+    /// placed on it, and backing both sides counts as a loss.
+    ///
+    /// Milestone bonuses are paid in identity order, so that when the
+    /// treasury runs short every node skips the same users.
+    fn settle_streaks(&mut self, market_id: u64, outcome: bool) {
+        let Some(market) = self.markets.get(&market_id) else {
+            return;
+        };
+        let (winners, losers) = if outcome {
+            (&market.yes_bettors, &market.no_bettors)
+        } else {
+            (&market.no_bettors, &market.yes_bettors)
+        };
+        let mut bettors: Vec<(&Identity, bool)> = winners
+            .keys()
+            .map(|identity| (identity, !losers.contains_key(identity)))
+            .chain(losers.keys().filter(|identity| !winners.contains_key(*identity)).map(|identity| (identity, false)))
+            .collect();
+        bettors.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+
+        for (identity, won) in bettors {
+            let Some(user) = self.users.get_mut(identity) else {
+                continue;
+            };
+            if !won {
+                user.current_streak = 0;
+                continue;
+            }
+            user.current_streak = user.current_streak.saturating_add(1);
+            if let Some(bonus) = streak_bonus(user.current_streak).filter(|bonus| *bonus <= self.treasury) {
+                user.balance = user.balance.saturating_add(bonus);
+                self.treasury -= bonus;
+            }
+        }
+    }
+
+    pub fn add_comment(
+        &mut self,
+        identity: Identity,
+        market_id: u64,
+        text: String,
+    ) -> Result<String, MarketError> {
+        let user = self.users.get(&identity).ok_or(MarketError::UserNotInitialized)?;
+        if !user.initialized {
+            return Err(MarketError::UserNotInitialized);
+        }
+
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(MarketError::EmptyComment);
+        }
+        if text.chars().count() > MAX_COMMENT_CHARS {
+            return Err(MarketError::CommentTooLong { max: MAX_COMMENT_CHARS });
+        }
+
+        let market = self.markets.get_mut(&market_id)
+            .ok_or(MarketError::MarketNotFound)?;
+        if market.status != MarketStatus::Open {
+            return Err(MarketError::MarketNotOpen);
+        }
+        if market.comments.len() >= MAX_COMMENTS_PER_MARKET {
+            return Err(MarketError::TooManyComments {
+                market_id,
+                max: MAX_COMMENTS_PER_MARKET,
+            });
+        }
+
+        market.comments.push(MarketComment {
+            author: identity,
+            text: text.to_string(),
+        });
+        Ok(format!("Comment #{} added to market #{}", market.comments.len(), market_id))
+    }
+
+    pub fn withdraw_treasury(
+        &mut self,
+        identity: Identity,
+        to: Identity,
+        amount: u128,
+    ) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        if amount == 0 {
+            return Err(MarketError::InvalidAmount);
+        }
+        if amount > self.treasury {
+            return Err(MarketError::InsufficientTreasury {
+                have: self.treasury,
+                need: amount,
+            });
+        }
+        let recipient = self.users.get_mut(&to).ok_or(MarketError::RecipientNotInitialized)?;
+        if !recipient.initialized {
+            return Err(MarketError::RecipientNotInitialized);
+        }
+
+        recipient.balance = recipient.balance.checked_add(amount).ok_or(MarketError::Overflow)?;
+        self.treasury -= amount;
+        Ok(format!("Withdrew {} from the treasury to {}", amount, to.0))
+    }
+
+    /// Admin only: burns the free balance of `user`, e.g. a banned account.
+    /// Their stakes in open markets stay in play.
+    pub fn confiscate_balance(&mut self, identity: Identity, user: Identity) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        let account = self.users.get_mut(&user).ok_or(MarketError::UserNotInitialized)?;
+        let confiscated = std::mem::take(&mut account.balance);
+        self.burned += confiscated;
+        Ok(format!("Burned {} from {}", confiscated, user.0))
+    }
+
+    /// Admin only: sets the share of every losing pool taken into the
+    /// dividend pool from the next resolution on.
+    pub fn set_house_edge(&mut self, identity: Identity, bps: u16) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        if bps > MAX_HOUSE_EDGE_BPS {
+            return Err(MarketError::InvalidHouseEdge { bps, max: MAX_HOUSE_EDGE_BPS });
+        }
+        self.house_edge_bps = bps;
+        Ok(format!("House edge set to {} bps", bps))
+    }
+
+    /// Admin only: splits the dividend pool equally among the users who
+    /// placed a bet since the last distribution, and starts counting again.
+    /// What does not split evenly stays in the pool for the next one.
+    pub fn distribute_dividends(&mut self, identity: Identity) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        if self.dividend_pool == 0 {
+            return Err(MarketError::NoDividends);
+        }
+        let eligible = self
+            .users
+            .values()
+            .filter(|user| user.initialized && user.bets_since_dividend > 0)
+            .count() as u128;
+        if eligible == 0 {
+            return Err(MarketError::NoDividendRecipients);
+        }
+
+        // Distributing nothing would still cost every bettor their eligibility
+        let share = self.dividend_pool / eligible;
+        if share == 0 {
+            return Err(MarketError::DividendPoolTooSmall { pool: self.dividend_pool, recipients: eligible });
+        }
+        for user in self.users.values_mut() {
+            if user.initialized && user.bets_since_dividend > 0 {
+                user.balance += share;
+            }
+            user.bets_since_dividend = 0;
+        }
+        self.dividend_pool -= share * eligible;
+        Ok(format!("Distributed {} to each of {} bettors", share, eligible))
+    }
+
+    /// Gives every initialized user the initial balance again. Refused while
+    /// a market is open or a challenge pending, so no stake carries over into
+    /// the new season.
+    pub fn reset_balances(&mut self, identity: Identity) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        let open = self
+            .markets
+            .values()
+            .filter(|market| matches!(market.status, MarketStatus::Open | MarketStatus::PendingAcceptance))
+            .count();
+        if open > 0 {
+            return Err(MarketError::MarketsStillOpen { open });
+        }
+
+        let mut reset = 0;
+        for user in self.users.values_mut().filter(|user| user.initialized) {
+            user.balance = INITIAL_BALANCE;
+            reset += 1;
+        }
+        Ok(format!("Reset {} balances to {}", reset, INITIAL_BALANCE))
+    }
+
+    pub fn export_snapshot(&self, identity: Identity) -> Result<String, MarketError> {
+        self.ensure_admin(&identity)?;
+        Ok(format!(
+            "Snapshot of {} users and {} markets at epoch {}",
+            self.users.len(),
+            self.markets.len(),
+            self.state_epoch
+        ))
+    }
+
+    /// Replaces the empty state with `data`, the commitment of an exported
+    /// one. Only the admin of the fresh contract, set at deployment or with
+    /// `SetAdmin`, can import, and stays admin whatever the snapshot says.
+    pub fn import_snapshot(&mut self, identity: Identity, data: Vec<u8>) -> Result<String, MarketError> {
+        if !self.users.is_empty() || !self.markets.is_empty() {
+            return Err(MarketError::StateNotEmpty { users: self.users.len(), markets: self.markets.len() });
+        }
+        self.ensure_admin(&identity)?;
+        let snapshot = Contract1::try_from(sdk::StateCommitment(data)).map_err(|_| MarketError::InvalidSnapshot)?;
+        *self = Contract1 { admin: self.admin.take(), ..snapshot };
+        Ok(format!("Imported {} users and {} markets", self.users.len(), self.markets.len()))
+    }
+
+    pub fn get_treasury(&self) -> Result<String, MarketError> {
+        Ok(format!(
+            "Treasury: {}\nBurned: {}\nDividend pool: {}",
+            self.treasury, self.burned, self.dividend_pool
+        ))
+    }
+
+    /// Read-only: identities that never initialized have a zero balance
+    pub fn get_balance(&self, identity: Identity) -> Result<String, MarketError> {
+        let balance = self.users.get(&identity).map_or(0, |user| user.balance);
+        Ok(format!("Balance: {}", balance))
+    }
+
+    /// Read-only: identities that never initialized have empty stats
+    pub fn get_user_stats(&self, identity: Identity) -> Result<String, MarketError> {
+        let Some(user) = self.users.get(&identity) else {
+            return Ok("Balance: 0\nCurrent streak: 0\nBets: 0 (0 open)".to_string());
+        };
+        let open = user
+            .bets
+            .iter()
+            .filter(|bet| self.markets.get(&bet.market_id).is_some_and(|m| m.status == MarketStatus::Open))
+            .count();
+        Ok(format!(
+            "Balance: {}\nCurrent streak: {}\nBets: {} ({} open)",
+            user.balance,
+            user.current_streak,
+            user.bets.len(),
+            open
+        ))
+    }
+
+    /// Read-only: the stake the caller has at risk in markets not settled
+    /// yet, see [`Contract1::open_exposure`].
+    pub fn get_open_exposure(&self, identity: Identity) -> Result<String, MarketError> {
+        let exposure = self.open_exposure(&identity);
+        Ok(format!(
+            "Open exposure: {} (YES {}, NO {}) in {} markets",
+            exposure.total(),
+            exposure.yes,
+            exposure.no,
+            exposure.positions
+        ))
+    }
+
+    /// Read-only: the top `limit` balances, at most `MAX_LEADERBOARD_LIMIT`,
+    /// ordered like `leaderboard` so a proven answer matches the indexed one.
+    pub fn get_leaderboard(&self, limit: u32) -> Result<String, MarketError> {
+        let limit = usize::try_from(limit).map_or(MAX_LEADERBOARD_LIMIT, |limit| limit.min(MAX_LEADERBOARD_LIMIT));
+        let entries = self.leaderboard(limit);
+        if entries.is_empty() {
+            return Ok("Leaderboard is empty".to_string());
+        }
+        let mut board = "Leaderboard:".to_string();
+        for (rank, entry) in entries.iter().enumerate() {
+            board.push_str(&format!("\n{}. {}: {}", rank + 1, entry.identity, entry.balance));
+        }
+        Ok(board)
+    }
+
+    pub fn get_market_info(&self, market_id: u64, now: Option<u64>) -> Result<String, MarketError> {
         let market = self.markets.get(&market_id)
-            .ok_or("Market not found")?;
+            .ok_or(MarketError::MarketNotFound)?;
         
-        let status_str = match market.status {
-            MarketStatus::Open => "Open",
-            MarketStatus::ResolvedYes => "Resolved: YES",
-            MarketStatus::ResolvedNo => "Resolved: NO",
+        let status_str = match (market.status.clone(), market.opens_at) {
+            (MarketStatus::Open, _) if market.betting_closed => "Betting closed".to_string(),
+            (MarketStatus::Open, Some(opens_at)) if market.is_scheduled(now) => {
+                format!("Scheduled (opens at {})", opens_at)
+            }
+            (MarketStatus::Open, _) => "Open".to_string(),
+            (MarketStatus::ResolvedYes, _) => "Resolved: YES".to_string(),
+            (MarketStatus::ResolvedNo, _) => "Resolved: NO".to_string(),
+            (MarketStatus::PendingAcceptance, _) => match &market.challenge {
+                Some(challenge) => format!("Waiting for {} to accept", challenge.opponent.0),
+                None => "Waiting for the opponent to accept".to_string(),
+            },
+            (MarketStatus::Cancelled, _) => "Cancelled".to_string(),
+            (MarketStatus::Void, _) => "Void: expired unresolved, stakes refunded".to_string(),
         };
         
-        Ok(format!(
+        let mut info = format!(
             "Market #{}: {}\nStatus: {}\nYES pool: {}\nNO pool: {}\nTotal pool: {}",
             market.id,
             market.description,
@@ -307,12 +1136,154 @@ impl Contract1 {
             market.yes_pool,
             market.no_pool,
             market.yes_pool + market.no_pool
-        ))
+        );
+        match market.stake_cap {
+            Some(StakeCap::OppositePoolBps(bps)) => info.push_str(&format!(
+                "\nStake cap: {}.{:02}% of the opposite pool per user",
+                bps / 100,
+                bps % 100
+            )),
+            Some(StakeCap::Absolute(max)) => info.push_str(&format!("\nStake cap: {} per user and side", max)),
+            None => {}
+        }
+        info.push_str(&format!("\nBettors: {}/{}", market.bettor_count(), MAX_BETTORS_PER_MARKET));
+
+        let shown = market.comments.len().saturating_sub(COMMENTS_IN_INFO);
+        if !market.comments.is_empty() {
+            info.push_str(&format!("\nComments ({}):", market.comments.len()));
+        }
+        for comment in &market.comments[shown..] {
+            info.push_str(&format!("\n- {}: {}", comment.author.0, comment.text));
+        }
+        Ok(info)
+    }
+
+    /// Read-only: the latest bets on `market_id`, oldest first, with the
+    /// pools each one left behind.
+    pub fn get_market_history(&self, market_id: u64) -> Result<String, MarketError> {
+        let market = self.markets.get(&market_id).ok_or(MarketError::MarketNotFound)?;
+        if market.history.is_empty() {
+            return Ok(format!("Market #{} has no bets yet", market_id));
+        }
+
+        let mut history = format!("Market #{} history ({} bets):", market_id, market.history.len());
+        for (index, entry) in market.history.iter().enumerate() {
+            history.push_str(&format!(
+                "\n{}. {} {} {}",
+                index + 1,
+                entry.bettor.0,
+                if entry.side { "YES" } else { "NO" },
+                entry.amount
+            ));
+            if let Some(timestamp) = entry.timestamp {
+                history.push_str(&format!(" at {}", timestamp));
+            }
+            history.push_str(&format!(" -> YES {} / NO {}", entry.yes_pool, entry.no_pool));
+        }
+        Ok(history)
     }
 }
 
+/// Share of the total pool owed to a winning `stake`: stake / winning_pool × total_pool.
+pub fn parimutuel_payout(stake: u128, winning_pool: u128, total_pool: u128) -> u128 {
+    (stake as f64 / winning_pool as f64 * total_pool as f64) as u128
+}
+
+/// Bonus credited from the treasury when a streak reaches `streak` wins.
+pub fn streak_bonus(streak: u32) -> Option<u128> {
+    STREAK_BONUSES
+        .iter()
+        .find(|(milestone, _)| *milestone == streak)
+        .map(|(_, bonus)| *bonus)
+}
+
+/// Unix seconds of the block the transaction is sequenced in, when known.
+fn block_time(calldata: &sdk::Calldata) -> Option<u64> {
+    calldata.tx_ctx.as_ref().map(|ctx| (ctx.timestamp.0 / 1000) as u64)
+}
+
 // Constants
-const INITIAL_BALANCE: u128 = 10_000;
+pub const INITIAL_BALANCE: u128 = 10_000;
+/// Smallest accepted stake; bets are otherwise bounded by the bettor's balance
+pub const MIN_BET: u128 = 1;
+pub const MAX_IDENTITY_LEN: usize = 128;
+/// Most entries a leaderboard returns, on-chain or from the indexer
+pub const MAX_LEADERBOARD_LIMIT: usize = 100;
+pub const MIN_PARLAY_LEGS: usize = 2;
+pub const MAX_PARLAY_LEGS: usize = 5;
+/// Bets a market remembers in its history; older ones are dropped first
+pub const MAX_MARKET_HISTORY: usize = 200;
+/// A parlay pays at most this many times its stake, which bounds what the
+/// treasury has to set aside for it
+pub const MAX_PARLAY_MULTIPLIER: u128 = 10;
+/// Consecutive winning markets and the bonus paid on reaching them. The
+/// treasury pays them only while it can cover them.
+pub const STREAK_BONUSES: [(u32, u128); 3] = [(3, 100), (5, 250), (10, 1_000)];
+/// Comments are committed with their market forever, so both their length
+/// and their number are capped: a full log adds at most ~14 KB
+/// (20 × (140 chars × 4 bytes + identity)) to the commitment.
+pub const MAX_COMMENT_CHARS: usize = 140;
+pub const MAX_COMMENTS_PER_MARKET: usize = 20;
+pub const MAX_MARKET_TAGS: usize = 5;
+pub const MAX_TAG_CHARS: usize = 20;
+/// Resolution pays every winner in one transaction, so the number of
+/// distinct bettors bounds the proving cost of a market. Bettors already
+/// in a full market can still add to their stakes.
+pub const MAX_BETTORS_PER_MARKET: usize = 500;
+/// How long the opponent of a challenge has to accept it, in seconds
+pub const CHALLENGE_ACCEPT_WINDOW: u64 = 24 * 60 * 60;
+/// Seconds after its creation from which anyone can void an unresolved market
+pub const MAX_MARKET_LIFETIME: u64 = 90 * 24 * 60 * 60;
+/// Seconds after the latest bet during which a market still taking bets
+/// cannot be resolved
+pub const RESOLUTION_COOLDOWN: u64 = 10 * 60;
+/// Highest house edge the admin can set, in basis points of the losing pool
+pub const MAX_HOUSE_EDGE_BPS: u16 = 2_000;
+/// Latest comments shown by GetMarketInfo
+const COMMENTS_IN_INFO: usize = 3;
+
+/// `tag` as stored and matched: trimmed, without a leading `#`, lowercased.
+pub fn normalize_tag(tag: &str) -> String {
+    let tag = tag.trim();
+    tag.strip_prefix('#').unwrap_or(tag).to_lowercase()
+}
+
+/// Normalizes `tags` and drops duplicates, keeping the first occurrence.
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, MarketError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags.iter().map(|tag| normalize_tag(tag)) {
+        let chars = tag.chars().count();
+        if chars == 0 || chars > MAX_TAG_CHARS || tag.chars().any(char::is_whitespace) {
+            return Err(MarketError::InvalidTag { max: MAX_TAG_CHARS });
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_MARKET_TAGS {
+        return Err(MarketError::TooManyTags { max: MAX_MARKET_TAGS });
+    }
+    Ok(normalized)
+}
+
+/// Checks that `identity` has the `name@contract` shape the server signs
+/// with, for this contract, and a bounded length.
+pub fn validate_identity(identity: &Identity, contract_name: &sdk::ContractName) -> Result<(), MarketError> {
+    let value = identity.0.as_str();
+    if value.is_empty() {
+        return Err(MarketError::EmptyIdentity);
+    }
+    if value.len() > MAX_IDENTITY_LEN {
+        return Err(MarketError::IdentityTooLong { max: MAX_IDENTITY_LEN });
+    }
+    match value.rsplit_once('@') {
+        Some((name, suffix)) if !name.is_empty() && suffix == contract_name.0 => Ok(()),
+        _ => Err(MarketError::MalformedIdentity {
+            identity: value.to_string(),
+            contract_name: contract_name.0.clone(),
+        }),
+    }
+}
 
 // Data structures
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Default)]
@@ -320,9 +1291,31 @@ pub struct UserState {
     pub balance: u128,
     pub initialized: bool,
     pub bets: Vec<UserBet>,
+    /// Markets won in a row, reset by a loss
+    pub current_streak: u32,
+    /// Nonce of the user's next [`MarketAction::Nonced`] action
+    pub next_nonce: u64,
+    /// Bets placed since the last [`MarketAction::DistributeDividends`];
+    /// only users with some share the dividends
+    pub bets_since_dividend: u32,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
+impl UserState {
+    /// Marks every bet held on `market_id` settled. Returns whether one on
+    /// `winning_side` already was: the payout covers all winning legs at
+    /// once, so it was made then. `None` when no bet was left to settle.
+    pub fn settle_bets(&mut self, market_id: u64, winning_side: bool) -> Option<bool> {
+        let (mut paid, mut unsettled) = (false, false);
+        for bet in self.bets.iter_mut().filter(|bet| bet.market_id == market_id) {
+            paid |= bet.claimed && bet.side == winning_side;
+            unsettled |= !bet.claimed;
+            bet.claimed = true;
+        }
+        unsettled.then_some(paid)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct UserBet {
     pub market_id: u64,
     pub side: bool, // true = yes, false = no
@@ -340,7 +1333,170 @@ pub struct Market {
     pub yes_bettors: HashMap<Identity, u128>,
     pub no_bettors: HashMap<Identity, u128>,
     pub status: MarketStatus,
+    /// Unix seconds; 0 for markets created without a block time
     pub created_at: u64,
+    pub comments: Vec<MarketComment>,
+    /// Unix seconds before which bets are refused; `None` opens on creation
+    pub opens_at: Option<u64>,
+    pub stake_cap: Option<StakeCap>,
+    /// Still to be resolved, but no longer taking bets
+    pub betting_closed: bool,
+    /// The latest `MAX_MARKET_HISTORY` bets, oldest first
+    pub history: Vec<MarketHistoryEntry>,
+    /// Lowercase labels used to filter market lists, e.g. `sports`
+    pub tags: Vec<String>,
+    /// Set on head-to-head markets, which only their creator and the named
+    /// opponent bet on
+    pub challenge: Option<Challenge>,
+    /// Unix seconds after which a pending challenge can no longer be
+    /// accepted; `None` when created without a block time
+    pub accept_by: Option<u64>,
+    /// Unix seconds of the latest bet carrying a block time
+    pub last_bet_at: Option<u64>,
+    /// House edge taken from the losing pool into the dividend pool when
+    /// the market was resolved
+    pub rake: u128,
+}
+
+/// Terms of a head-to-head market: the creator backs YES with `stake` and
+/// only `opponent` may match it on NO.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub opponent: Identity,
+    pub stake: u128,
+}
+
+/// Most a single user may have staked on one side of a market, so one large
+/// balance cannot take the market over.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StakeCap {
+    /// Share of the opposite pool at the time of the bet, in basis points.
+    /// It follows the pools as they grow, and does not apply while the
+    /// opposite side is still empty.
+    OppositePoolBps(u32),
+    Absolute(u128),
+}
+
+impl Market {
+    /// What the winners share: both pools less the house edge taken at
+    /// resolution.
+    pub fn payout_pool(&self) -> u128 {
+        self.yes_pool + self.no_pool - self.rake
+    }
+
+    /// Appends `entry` to the history, dropping the oldest entries past the cap.
+    pub fn record_history(&mut self, entry: MarketHistoryEntry) {
+        if self.history.len() >= MAX_MARKET_HISTORY {
+            let excess = self.history.len() + 1 - MAX_MARKET_HISTORY;
+            self.history.drain(..excess);
+        }
+        self.last_bet_at = entry.timestamp.or(self.last_bet_at);
+        self.history.push(entry);
+    }
+
+    /// Unix seconds from which the market can be resolved, `RESOLUTION_COOLDOWN`
+    /// after its latest bet; `None` when nothing holds its resolution back.
+    /// Closing betting does not lift it: its creator could otherwise bet
+    /// big, close and resolve at once. A market closed at a deadline past
+    /// the cooldown resolves right away all the same. Without a block time
+    /// the cooldown cannot be proven over, so the market waits.
+    pub fn resolvable_at(&self) -> Option<u64> {
+        self.last_bet_at.map(|at| at.saturating_add(RESOLUTION_COOLDOWN))
+    }
+
+    /// Open but not taking bets yet. Without a block time the opening
+    /// cannot be proven to have passed, so the market stays scheduled.
+    pub fn is_scheduled(&self, now: Option<u64>) -> bool {
+        self.status == MarketStatus::Open
+            && self
+                .opens_at
+                .is_some_and(|opens_at| now.map_or(true, |now| now < opens_at))
+    }
+
+    /// A pending challenge past its acceptance window. Without a block time
+    /// the window cannot be proven over, so the challenge stays acceptable.
+    pub fn is_challenge_expired(&self, now: Option<u64>) -> bool {
+        self.status == MarketStatus::PendingAcceptance
+            && self.accept_by.is_some_and(|accept_by| now.is_some_and(|now| now > accept_by))
+    }
+
+    /// When the market can be voided if still unresolved, see
+    /// `MAX_MARKET_LIFETIME`. Without a creation time it never can.
+    pub fn expires_at(&self) -> Option<u64> {
+        (self.created_at > 0).then(|| self.created_at.saturating_add(MAX_MARKET_LIFETIME))
+    }
+
+    /// Distinct identities with a stake on either side.
+    pub fn bettor_count(&self) -> usize {
+        self.yes_bettors.len()
+            + self.no_bettors.keys().filter(|identity| !self.yes_bettors.contains_key(*identity)).count()
+    }
+
+    pub fn has_bettor(&self, identity: &Identity) -> bool {
+        self.yes_bettors.contains_key(identity) || self.no_bettors.contains_key(identity)
+    }
+
+    /// How much more `identity` may stake on `side`; `None` when uncapped.
+    pub fn stake_allowance(&self, identity: &Identity, side: bool) -> Option<u128> {
+        let (bettors, opposite_pool) = if side {
+            (&self.yes_bettors, self.no_pool)
+        } else {
+            (&self.no_bettors, self.yes_pool)
+        };
+        let cap = match self.stake_cap? {
+            StakeCap::Absolute(max) => max,
+            StakeCap::OppositePoolBps(_) if opposite_pool == 0 => return None,
+            StakeCap::OppositePoolBps(bps) => opposite_pool.saturating_mul(bps as u128) / 10_000,
+        };
+        let staked = bettors.get(identity).copied().unwrap_or(0);
+        Some(cap.saturating_sub(staked))
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarketComment {
+    pub author: Identity,
+    pub text: String,
+}
+
+/// One bet in a market's history, with the pools right after it.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarketHistoryEntry {
+    pub bettor: Identity,
+    pub side: bool,
+    pub amount: u128,
+    /// Block time in unix seconds, when the transaction carried one
+    pub timestamp: Option<u64>,
+    pub yes_pool: u128,
+    pub no_pool: u128,
+}
+
+/// A wager on several markets at once, paid only if every leg wins.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Parlay {
+    pub id: u64,
+    pub owner: Identity,
+    pub legs: Vec<ParlayLeg>,
+    pub stake: u128,
+    /// Locked in the parlay reserve until settlement
+    pub max_payout: u128,
+    pub status: ParlayStatus,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ParlayLeg {
+    pub market_id: u64,
+    pub side: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ParlayStatus {
+    Open,
+    Won { payout: u128 },
+    Lost,
+    /// Every leg was void, the stake was refunded
+    Void,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -348,6 +1504,18 @@ pub enum MarketStatus {
     Open,
     ResolvedYes,
     ResolvedNo,
+    /// A challenge whose opponent has not matched the stake yet
+    PendingAcceptance,
+    /// A challenge declined, withdrawn or never accepted; its stake was refunded
+    Cancelled,
+    /// Left unresolved past `MAX_MARKET_LIFETIME`; every stake was refunded
+    Void,
+}
+
+impl MarketStatus {
+    pub fn is_resolved(&self) -> bool {
+        matches!(self, MarketStatus::ResolvedYes | MarketStatus::ResolvedNo)
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
@@ -355,6 +1523,30 @@ pub struct Contract1 {
     pub users: HashMap<Identity, UserState>,
     pub markets: HashMap<u64, Market>,
     pub next_market_id: u64,
+    pub admin: Option<Identity>,
+    /// Funds owned by the protocol: payout dust and pools nobody won
+    pub treasury: u128,
+    pub parlays: HashMap<u64, Parlay>,
+    pub next_parlay_id: u64,
+    /// Stakes of open parlays plus the treasury funds backing their payouts
+    pub parlay_reserve: u128,
+    /// Set when the contract is deployed, so clients can tell a redeployed
+    /// contract, whose market ids start over, from the one they knew
+    pub state_epoch: u64,
+    /// Funds destroyed because nobody is entitled to them: stakes of bettors
+    /// no longer known and confiscated balances. Nothing ever spends them.
+    pub burned: u128,
+    /// Refuse actions changing the state unless they carry a nonce, see
+    /// [`MarketAction::Nonced`]
+    pub require_nonces: bool,
+    /// Share of every losing pool, in basis points, set aside for the
+    /// dividends at resolution
+    pub house_edge_bps: u16,
+    /// House edge collected since the last [`MarketAction::DistributeDividends`],
+    /// plus the dust of earlier distributions
+    pub dividend_pool: u128,
+    /// Refuse every action changing the state but [`MarketAction::Unpause`]
+    pub paused: bool,
 }
 
 impl Default for Contract1 {
@@ -368,13 +1560,64 @@ impl Default for Contract1 {
 #[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub enum MarketAction {
     SetAdmin { new_admin: Identity },
-    Initialize {},
-    CreateMarket { description: String },
+    Initialize { idempotent: bool },
+    CreateMarket {
+        description: String,
+        opens_at: Option<u64>,
+        stake_cap: Option<StakeCap>,
+        tags: Vec<String>,
+        challenge: Option<Challenge>,
+    },
     PlaceBet { market_id: u64, side: bool, amount: u128 },
     ResolveMarket { market_id: u64, outcome: bool },
     ClaimWinnings { market_id: u64 },
     GetBalance,
     GetMarketInfo { market_id: u64 },
+    WithdrawTreasury { to: Identity, amount: u128 },
+    GetTreasury,
+    AddComment { market_id: u64, text: String },
+    GetUserStats,
+    GetLeaderboard { limit: u32 },
+    PlaceParlay { legs: Vec<(u64, bool)>, amount: u128 },
+    SettleParlay { parlay_id: u64 },
+    CloseBetting { market_id: u64 },
+    GetMarketHistory { market_id: u64 },
+    /// Admin only: starts a new season with every initialized user back at
+    /// the initial balance
+    ResetBalances,
+    /// The opponent named by a pending challenge matches its stake
+    AcceptChallenge { market_id: u64 },
+    /// Declines, withdraws or expires a pending challenge, refunding its creator
+    CancelChallenge { market_id: u64 },
+    /// Voids a market left unresolved past `MAX_MARKET_LIFETIME`, refunding every stake
+    ExpireMarket { market_id: u64 },
+    /// Admin only: vouches for a backup of the state, which the server reads
+    /// from the settled state and returns alongside the transaction
+    ExportSnapshot,
+    /// Restores the users, markets, counters and treasury of an exported
+    /// state (its commitment bytes) onto a contract with no users and no
+    /// markets yet, e.g. right after a redeployment
+    ImportSnapshot { data: Vec<u8> },
+    /// Admin only: burns a user's free balance
+    ConfiscateBalance { user: Identity },
+    /// Admin only: whether actions changing the state must be [`MarketAction::Nonced`]
+    RequireNonces { required: bool },
+    /// Runs `action`, which changes the state, only if `nonce` is the
+    /// sender's next one, and advances it: the same blob applied twice is
+    /// refused the second time
+    Nonced { nonce: u64, action: Box<MarketAction> },
+    /// Admin only: share of every losing pool, in basis points, taken into
+    /// the dividend pool, at most `MAX_HOUSE_EDGE_BPS`
+    SetHouseEdge { bps: u16 },
+    /// Admin only: splits the dividend pool equally among the users who bet
+    /// since the last distribution
+    DistributeDividends,
+    /// Stake the caller has at risk in open markets and pending challenges
+    GetOpenExposure,
+    /// Admin only: refuses every action changing the state until [`MarketAction::Unpause`]
+    Pause,
+    /// Admin only: lifts a [`MarketAction::Pause`]
+    Unpause,
 }
 
 impl MarketAction {
@@ -384,18 +1627,77 @@ impl MarketAction {
             data: sdk::BlobData(borsh::to_vec(self).expect("Failed to encode MarketAction")),
         }
     }
+
+    /// Wraps the action with the sender's next `nonce`.
+    pub fn with_nonce(self, nonce: u64) -> MarketAction {
+        MarketAction::Nonced { nonce, action: Box::new(self) }
+    }
+
+    /// Whether the action only reads the state. It leaves the commitment as
+    /// it was, so submitting it twice is harmless and proving it needs no
+    /// new state.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            MarketAction::GetBalance
+                | MarketAction::GetMarketInfo { .. }
+                | MarketAction::GetTreasury
+                | MarketAction::GetUserStats
+                | MarketAction::GetOpenExposure
+                | MarketAction::GetLeaderboard { .. }
+                | MarketAction::GetMarketHistory { .. }
+                | MarketAction::ExportSnapshot
+        )
+    }
 }
 
 impl Contract1 {
     pub fn as_bytes(&self) -> Result<Vec<u8>, Error> {
-        borsh::to_vec(self)
+        let mut bytes = Vec::with_capacity(self.encoded_size_hint());
+        BorshSerialize::serialize(self, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Rough size of the borsh encoding, so `commit` allocates its buffer
+    /// once instead of doubling it a dozen times on large states.
+    fn encoded_size_hint(&self) -> usize {
+        const USER: usize = 64;
+        const BET: usize = 41;
+        const MARKET: usize = 128;
+        const BETTOR: usize = 48;
+        const COMMENT: usize = 40;
+        const HISTORY_ENTRY: usize = 96;
+        const TAG: usize = 4;
+        const PARLAY: usize = 112;
+        const LEG: usize = 9;
+
+        let users: usize = self.users.values().map(|u| USER + u.bets.len() * BET).sum();
+        let markets: usize = self
+            .markets
+            .values()
+            .map(|m| {
+                MARKET
+                    + m.description.len()
+                    + (m.yes_bettors.len() + m.no_bettors.len()) * BETTOR
+                    + m.comments.iter().map(|c| COMMENT + c.text.len()).sum::<usize>()
+                    + m.history.len() * HISTORY_ENTRY
+                    + m.tags.iter().map(|t| TAG + t.len()).sum::<usize>()
+            })
+            .sum();
+        let parlays: usize = self.parlays.values().map(|p| PARLAY + p.legs.len() * LEG).sum();
+        users + markets + parlays
     }
 }
 
-impl From<sdk::StateCommitment> for Contract1 {
-    fn from(state: sdk::StateCommitment) -> Self {
-        borsh::from_slice(&state.0)
-            .map_err(|_| "Could not decode parimutuel market state".to_string())
-            .unwrap()
+impl TryFrom<sdk::StateCommitment> for Contract1 {
+    type Error = String;
+
+    /// Decodes a committed state. The bytes come from outside the contract,
+    /// so malformed input is reported instead of panicking. States committed
+    /// by earlier versions decode through their frozen layout, see `legacy`.
+    fn try_from(state: sdk::StateCommitment) -> Result<Self, Self::Error> {
+        borsh::from_slice::<Self>(&state.0)
+            .or_else(|e| legacy::decode(&state.0).ok_or(e))
+            .map_err(|e| format!("Could not decode parimutuel market state: {}", e))
     }
 }
@@ -0,0 +1,103 @@
+//! The `client` helpers off-chain tools build, decode and simulate
+//! transactions with. A simulation must land exactly where the prover does.
+mod common;
+
+use client_sdk::transaction_builder::TxExecutorHandler;
+use common::{balance, identity, with_users, CONTRACT_NAME};
+use contract1::client::{build_transaction, changes_state, decode_output, simulate};
+use contract1::{Contract1, MarketAction};
+use sdk::{BlobIndex, BlobTransaction, Calldata, ContractName, HyleOutput, TxHash, ZkContract};
+
+fn contract_name() -> ContractName {
+    ContractName(CONTRACT_NAME.to_string())
+}
+
+/// Settles `tx` the way the prover does, returning its output.
+fn settle(state: &mut Contract1, tx: &BlobTransaction) -> HyleOutput {
+    let calldata = Calldata {
+        tx_hash: TxHash::default(),
+        identity: tx.identity.clone(),
+        blobs: tx.blobs.clone().into(),
+        tx_blob_count: tx.blobs.len(),
+        index: BlobIndex(0),
+        tx_ctx: None,
+        private_input: vec![],
+    };
+    state.handle(&calldata).unwrap()
+}
+
+#[test]
+fn transactions_carry_the_action_as_their_only_blob() {
+    let action = MarketAction::PlaceBet { market_id: 3, side: true, amount: 100 };
+    let tx = build_transaction(identity("alice"), contract_name(), &action);
+
+    assert_eq!(tx.identity, identity("alice"));
+    assert_eq!(tx.blobs.len(), 1);
+    assert_eq!(tx.blobs[0].contract_name, contract_name());
+    assert_eq!(borsh::from_slice::<MarketAction>(&tx.blobs[0].data.0).unwrap(), action);
+}
+
+#[test]
+fn outputs_decode_into_the_contract_message() {
+    let mut state = with_users(&["alice"]);
+
+    let initialized = settle(&mut state, &build_transaction(identity("bob"), contract_name(), &MarketAction::Initialize { idempotent: false }));
+    assert_eq!(decode_output(&initialized), Ok("Initialized with 10000 balance".to_string()));
+    assert!(changes_state(&initialized));
+
+    let again = settle(&mut state, &build_transaction(identity("alice"), contract_name(), &MarketAction::Initialize { idempotent: false }));
+    assert_eq!(decode_output(&again), Err("User already initialized".to_string()));
+
+    // Queries leave the state as it was
+    let query = settle(&mut state, &build_transaction(identity("alice"), contract_name(), &MarketAction::GetBalance));
+    assert!(decode_output(&query).is_ok());
+    assert!(!changes_state(&query));
+}
+
+#[test]
+fn simulating_then_executing_lands_on_the_same_state() {
+    let actions = [
+        ("alice", MarketAction::Initialize { idempotent: false }),
+        ("bob", MarketAction::Initialize { idempotent: false }),
+        (
+            "alice",
+            MarketAction::CreateMarket {
+                description: "Will it rain tomorrow?".to_string(),
+                opens_at: None,
+                stake_cap: None,
+                tags: vec![],
+                challenge: None,
+            },
+        ),
+        ("alice", MarketAction::PlaceBet { market_id: 1, side: true, amount: 300 }),
+        ("bob", MarketAction::PlaceBet { market_id: 1, side: false, amount: 100 }),
+        // Refused: more than bob holds
+        ("bob", MarketAction::PlaceBet { market_id: 1, side: false, amount: 1_000_000 }),
+        ("bob", MarketAction::GetBalance),
+        ("alice", MarketAction::ResolveMarket { market_id: 1, outcome: true }),
+        ("alice", MarketAction::ClaimWinnings { market_id: 1 }),
+    ];
+
+    let mut state = Contract1::new();
+    for (step, (name, action)) in actions.into_iter().enumerate() {
+        let before = state.commit();
+        let simulated = simulate(&state, identity(name), contract_name(), &action);
+        // Simulating never touches the state it starts from
+        assert!(state.commit() == before, "step {}", step);
+
+        let mut executed = state.clone();
+        let output = settle(&mut executed, &build_transaction(identity(name), contract_name(), &action));
+        match simulated {
+            Ok((next, message)) => {
+                assert_eq!(decode_output(&output), Ok(message), "step {}", step);
+                assert!(next.commit() == executed.commit(), "step {}", step);
+                assert!(output.next_state == executed.commit(), "step {}", step);
+                state = next;
+            }
+            Err(error) => assert_eq!(decode_output(&output), Err(error), "step {}", step),
+        }
+    }
+
+    assert_eq!(balance(&state, "alice"), 10_000 + 100);
+    assert_eq!(balance(&state, "bob"), 10_000 - 100);
+}
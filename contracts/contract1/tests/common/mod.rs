@@ -0,0 +1,100 @@
+//! Helpers shared by the contract integration tests: they drive actions
+//! through `ZkContract::execute` exactly like the prover does.
+#![allow(dead_code)]
+
+use contract1::{Contract1, MarketAction};
+use sdk::{BlobIndex, Calldata, ContractName, Identity, TimestampMs, TxContext, TxHash, ZkContract};
+
+pub const CONTRACT_NAME: &str = "contract1";
+
+pub fn identity(name: &str) -> Identity {
+    Identity(format!("{}@{}", name, CONTRACT_NAME))
+}
+
+pub fn calldata(identity: &Identity, action: &MarketAction) -> Calldata {
+    Calldata {
+        tx_hash: TxHash::default(),
+        identity: identity.clone(),
+        blobs: vec![action.as_blob(ContractName(CONTRACT_NAME.to_string()))].into(),
+        tx_blob_count: 1,
+        index: BlobIndex(0),
+        tx_ctx: None,
+        private_input: vec![],
+    }
+}
+
+/// Executes `action` as `identity` and returns the contract's message.
+pub fn run(state: &mut Contract1, identity: &Identity, action: MarketAction) -> Result<String, String> {
+    let (output, _, _) = state.execute(&calldata(identity, &action))?;
+    Ok(String::from_utf8(output).expect("contract output is UTF-8"))
+}
+
+/// Like [`run`], in a block stamped `timestamp_ms`.
+pub fn run_at(
+    state: &mut Contract1,
+    identity: &Identity,
+    action: MarketAction,
+    timestamp_ms: u128,
+) -> Result<String, String> {
+    let calldata = Calldata {
+        tx_ctx: Some(TxContext { timestamp: TimestampMs(timestamp_ms), ..TxContext::default() }),
+        ..calldata(identity, &action)
+    };
+    let (output, _, _) = state.execute(&calldata)?;
+    Ok(String::from_utf8(output).expect("contract output is UTF-8"))
+}
+
+/// A state where each of `names` has initialized their balance.
+pub fn with_users(names: &[&str]) -> Contract1 {
+    let mut state = Contract1::new();
+    for name in names {
+        run(&mut state, &identity(name), MarketAction::Initialize { idempotent: false }).expect("initialize");
+    }
+    state
+}
+
+pub fn balance(state: &Contract1, name: &str) -> u128 {
+    state.users.get(&identity(name)).map(|u| u.balance).unwrap_or(0)
+}
+
+/// Sum of every user balance, the stake still sitting in open markets, the
+/// treasury, the parlay reserve and the burned funds: everything ever minted.
+pub fn total_funds(state: &Contract1) -> u128 {
+    let balances: u128 = state.users.values().map(|u| u.balance).sum();
+    let open_pools: u128 = state
+        .markets
+        .values()
+        .filter(|m| matches!(m.status, contract1::MarketStatus::Open | contract1::MarketStatus::PendingAcceptance))
+        .map(|m| m.yes_pool + m.no_pool)
+        .sum();
+    balances + open_pools + state.treasury + state.parlay_reserve + state.burned + state.dividend_pool
+}
+
+/// A state with `users` initialized users and `markets` open markets, each
+/// holding `bettors_per_market` bets split across both sides. Built through
+/// `execute` so it looks exactly like a state grown on chain.
+pub fn synthetic_state(users: usize, markets: usize, bettors_per_market: usize) -> Contract1 {
+    let names: Vec<String> = (0..users).map(|i| format!("user{}", i)).collect();
+    let mut state = Contract1::new();
+    for name in &names {
+        run(&mut state, &identity(name), MarketAction::Initialize { idempotent: false }).expect("initialize");
+    }
+
+    for m in 0..markets {
+        let creator = &names[m % users];
+        run(
+            &mut state,
+            &identity(creator),
+            MarketAction::CreateMarket { description: format!("Synthetic market #{}", m), opens_at: None, stake_cap: None, tags: vec![], challenge: None },
+        )
+        .expect("create market");
+        let market_id = state.next_market_id;
+
+        for b in 0..bettors_per_market {
+            let bettor = &names[(m * bettors_per_market + b) % users];
+            let action = MarketAction::PlaceBet { market_id, side: b % 2 == 0, amount: 1 + (b as u128 % 7) };
+            run(&mut state, &identity(bettor), action).expect("place bet");
+        }
+    }
+    state
+}
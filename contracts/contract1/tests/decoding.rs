@@ -0,0 +1,223 @@
+//! The contract decodes two byte strings it does not control: the action blob
+//! of each transaction and the committed state handed to the prover. Both
+//! must reject garbage with an error, without panicking and without trusting
+//! the length prefixes embedded in the input.
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use common::{calldata, identity, run, with_users};
+use contract1::{Contract1, MarketAction};
+use proptest::prelude::*;
+use sdk::{Blob, BlobData, ContractName, StateCommitment, ZkContract};
+
+/// Records the largest single allocation made by the current thread, so tests
+/// can check that a forged length prefix does not turn into a huge buffer.
+struct TrackingAllocator;
+
+thread_local! {
+    static LARGEST_ALLOCATION: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        track(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        track(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        track(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+fn track(size: usize) {
+    let _ = LARGEST_ALLOCATION.try_with(|largest| largest.set(largest.get().max(size)));
+}
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+/// borsh caps what it preallocates for a collection at 1 MiB, whatever the
+/// length prefix claims; decoding a few hundred bytes must stay under it.
+const ALLOCATION_LIMIT: usize = 1024 * 1024;
+
+/// Runs `f` and returns its result with the largest allocation it made.
+fn largest_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    LARGEST_ALLOCATION.with(|largest| largest.set(0));
+    let result = f();
+    (result, LARGEST_ALLOCATION.with(|largest| largest.get()))
+}
+
+fn decode_action(bytes: &[u8]) -> Result<MarketAction, String> {
+    let identity = identity("fuzzer");
+    let mut calldata = calldata(&identity, &MarketAction::GetBalance);
+    calldata.blobs = vec![Blob {
+        contract_name: ContractName(common::CONTRACT_NAME.to_string()),
+        data: BlobData(bytes.to_vec()),
+    }]
+    .into();
+    sdk::utils::parse_raw_calldata::<MarketAction>(&calldata).map(|(action, _)| action)
+}
+
+fn decode_state(bytes: &[u8]) -> Result<Contract1, String> {
+    Contract1::try_from(StateCommitment(bytes.to_vec()))
+}
+
+/// Valid encodings used as seeds for the mutation strategies.
+fn action_corpus() -> Vec<Vec<u8>> {
+    [
+        MarketAction::SetAdmin { new_admin: identity("admin") },
+        MarketAction::Initialize { idempotent: false },
+        MarketAction::CreateMarket { description: "Will it rain tomorrow?".to_string(), opens_at: None, stake_cap: None, tags: vec![], challenge: None },
+        MarketAction::PlaceBet { market_id: 1, side: true, amount: 500 },
+        MarketAction::ResolveMarket { market_id: 1, outcome: false },
+        MarketAction::ClaimWinnings { market_id: 1 },
+        MarketAction::GetBalance,
+        MarketAction::GetMarketInfo { market_id: u64::MAX },
+    ]
+    .iter()
+    .map(|action| borsh::to_vec(action).unwrap())
+    .collect()
+}
+
+fn state_corpus() -> Vec<Vec<u8>> {
+    let empty = Contract1::new();
+
+    let mut open = with_users(&["alice", "bob"]);
+    run(&mut open, &identity("alice"), MarketAction::CreateMarket { description: "seed".to_string(), opens_at: None, stake_cap: None, tags: vec![], challenge: None })
+        .unwrap();
+    run(&mut open, &identity("alice"), MarketAction::PlaceBet { market_id: 1, side: true, amount: 100 })
+        .unwrap();
+    run(&mut open, &identity("bob"), MarketAction::PlaceBet { market_id: 1, side: false, amount: 40 })
+        .unwrap();
+
+    let mut resolved = open.clone();
+    run(&mut resolved, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true })
+        .unwrap();
+
+    [empty, open, resolved].iter().map(|state| state.commit().0).collect()
+}
+
+/// A seed with some bytes overwritten, truncated, or extended.
+fn mutated(corpus: Vec<Vec<u8>>) -> impl Strategy<Value = Vec<u8>> {
+    (
+        prop::sample::select(corpus),
+        prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 0..8),
+        any::<prop::sample::Index>(),
+        prop::collection::vec(any::<u8>(), 0..16),
+    )
+        .prop_map(|(mut bytes, flips, cut, tail)| {
+            if !bytes.is_empty() {
+                for (at, value) in flips {
+                    let at = at.index(bytes.len());
+                    bytes[at] = value;
+                }
+                bytes.truncate(cut.index(bytes.len() + 1));
+            }
+            bytes.extend(tail);
+            bytes
+        })
+}
+
+/// `len` written as a borsh u32 length prefix followed by a few bytes.
+fn forged_length(prefix: &[u8], len: u32) -> Vec<u8> {
+    let mut bytes = prefix.to_vec();
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(b"short");
+    bytes
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1024))]
+
+    #[test]
+    fn action_decoding_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+        let (_, largest) = largest_allocation(|| decode_action(&bytes));
+        prop_assert!(largest <= ALLOCATION_LIMIT, "allocated {} bytes", largest);
+    }
+
+    #[test]
+    fn mutated_actions_never_panic(bytes in mutated(action_corpus())) {
+        let (_, largest) = largest_allocation(|| decode_action(&bytes));
+        prop_assert!(largest <= ALLOCATION_LIMIT, "allocated {} bytes", largest);
+    }
+
+    #[test]
+    fn state_decoding_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+        let (_, largest) = largest_allocation(|| decode_state(&bytes));
+        prop_assert!(largest <= ALLOCATION_LIMIT, "allocated {} bytes", largest);
+    }
+
+    #[test]
+    fn mutated_states_never_panic(bytes in mutated(state_corpus())) {
+        let (_, largest) = largest_allocation(|| decode_state(&bytes));
+        prop_assert!(largest <= ALLOCATION_LIMIT, "allocated {} bytes", largest);
+    }
+
+    #[test]
+    fn garbage_blobs_are_rejected_without_touching_state(
+        bytes in prop::collection::vec(any::<u8>(), 0..64)
+    ) {
+        prop_assume!(decode_action(&bytes).is_err());
+
+        let mut state = with_users(&["alice"]);
+        let before = state.commit();
+        let mut calldata = calldata(&identity("alice"), &MarketAction::GetBalance);
+        calldata.blobs = vec![Blob {
+            contract_name: ContractName(common::CONTRACT_NAME.to_string()),
+            data: BlobData(bytes),
+        }]
+        .into();
+
+        prop_assert!(state.execute(&calldata).is_err());
+        prop_assert_eq!(state.commit(), before);
+    }
+}
+
+#[test]
+fn corpus_round_trips() {
+    for bytes in action_corpus() {
+        let action = decode_action(&bytes).expect("seed action decodes");
+        assert_eq!(borsh::to_vec(&action).unwrap(), bytes);
+    }
+    for bytes in state_corpus() {
+        let state = decode_state(&bytes).expect("seed state decodes");
+        assert_eq!(state.commit().0, bytes);
+    }
+}
+
+#[test]
+fn forged_length_prefixes_stay_bounded() {
+    // Variant 2 is CreateMarket, whose description is a length-prefixed string.
+    let action = forged_length(&[2], u32::MAX);
+    let (result, largest) = largest_allocation(|| decode_action(&action));
+    assert!(result.is_err());
+    assert!(largest <= ALLOCATION_LIMIT, "allocated {} bytes", largest);
+
+    // The state starts with the users map, whose length is a u32 prefix.
+    let state = forged_length(&[], u32::MAX);
+    let (result, largest) = largest_allocation(|| decode_state(&state));
+    assert!(result.is_err());
+    assert!(largest <= ALLOCATION_LIMIT, "allocated {} bytes", largest);
+}
+
+/// Regression: malformed state used to panic through an `unwrap` in
+/// `From<StateCommitment>`, aborting the prover.
+#[test]
+fn malformed_state_is_an_error() {
+    for bytes in [vec![], vec![0xff; 3], forged_length(&[], 1)] {
+        let error = decode_state(&bytes).expect_err("malformed state is rejected");
+        assert!(error.starts_with("Could not decode parimutuel market state"), "{}", error);
+    }
+}
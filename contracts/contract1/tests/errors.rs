@@ -0,0 +1,239 @@
+//! Every failure path returns a specific `MarketError`, and `execute` reports
+//! it with the same message the contract always used.
+mod common;
+
+use common::{identity, run, with_users, CONTRACT_NAME};
+use contract1::{
+    validate_identity, Contract1, MarketAction, MarketError, MAX_COMMENTS_PER_MARKET, MAX_COMMENT_CHARS,
+    MAX_IDENTITY_LEN, MAX_PARLAY_LEGS, MIN_PARLAY_LEGS,
+};
+use sdk::{ContractName, Identity};
+
+fn market(state: &mut Contract1, creator: &str) -> u64 {
+    state.create_market(identity(creator), "Will it rain?".to_string(), None, None, vec![], None, None).unwrap();
+    state.next_market_id
+}
+
+fn resolved_market(state: &mut Contract1, bettor: &str, side: bool, outcome: bool) -> u64 {
+    let market_id = market(state, bettor);
+    state.place_bet(identity(bettor), market_id, side, 100, None).unwrap();
+    state.resolve_market(identity(bettor), market_id, outcome, None).unwrap();
+    market_id
+}
+
+#[test]
+fn identity_errors() {
+    let contract = ContractName(CONTRACT_NAME.to_string());
+    assert_eq!(
+        validate_identity(&Identity(String::new()), &contract),
+        Err(MarketError::EmptyIdentity)
+    );
+    assert_eq!(
+        validate_identity(&Identity("a".repeat(MAX_IDENTITY_LEN + 1)), &contract),
+        Err(MarketError::IdentityTooLong { max: MAX_IDENTITY_LEN })
+    );
+    assert_eq!(
+        validate_identity(&Identity("alice@elsewhere".to_string()), &contract),
+        Err(MarketError::MalformedIdentity {
+            identity: "alice@elsewhere".to_string(),
+            contract_name: CONTRACT_NAME.to_string(),
+        })
+    );
+}
+
+#[test]
+fn user_errors() {
+    let mut state = with_users(&["alice"]);
+    assert_eq!(state.initialize(identity("alice"), false), Err(MarketError::UserAlreadyInitialized));
+    assert_eq!(
+        state.create_market(identity("mallory"), "?".to_string(), None, None, vec![], None, None),
+        Err(MarketError::UserNotInitialized)
+    );
+    let market_id = market(&mut state, "alice");
+    assert_eq!(
+        state.place_bet(identity("mallory"), market_id, true, 1, None),
+        Err(MarketError::UserNotInitialized)
+    );
+    assert_eq!(
+        state.add_comment(identity("mallory"), market_id, "hi".to_string()),
+        Err(MarketError::UserNotInitialized)
+    );
+
+    let resolved = resolved_market(&mut state, "alice", true, true);
+    assert_eq!(state.claim_winnings(identity("mallory"), resolved), Err(MarketError::UserNotFound));
+}
+
+#[test]
+fn amount_errors() {
+    let mut state = with_users(&["alice"]);
+    let market_id = market(&mut state, "alice");
+    assert_eq!(state.place_bet(identity("alice"), market_id, true, 0, None), Err(MarketError::InvalidAmount));
+    assert_eq!(
+        state.place_bet(identity("alice"), market_id, true, 10_001, None),
+        Err(MarketError::InsufficientBalance { have: 10_000, need: 10_001 })
+    );
+
+    state.markets.get_mut(&market_id).unwrap().yes_pool = u128::MAX;
+    assert_eq!(state.place_bet(identity("alice"), market_id, true, 1, None), Err(MarketError::Overflow));
+    assert_eq!(state.users[&identity("alice")].balance, 10_000);
+
+    // The house edge is taken from a losing pool too large to multiply
+    state.house_edge_bps = 100;
+    assert_eq!(state.resolve_market(identity("alice"), market_id, false, None), Err(MarketError::Overflow));
+    let market = &state.markets[&market_id];
+    assert_eq!((market.status.clone(), market.rake), (contract1::MarketStatus::Open, 0));
+}
+
+#[test]
+fn market_errors() {
+    let mut state = with_users(&["alice", "bob"]);
+    assert_eq!(state.place_bet(identity("alice"), 9, true, 1, None), Err(MarketError::MarketNotFound));
+    assert_eq!(state.resolve_market(identity("alice"), 9, true, None), Err(MarketError::MarketNotFound));
+    assert_eq!(state.claim_winnings(identity("alice"), 9), Err(MarketError::MarketNotFound));
+    assert_eq!(state.get_market_info(9, None), Err(MarketError::MarketNotFound));
+    assert_eq!(
+        state.add_comment(identity("alice"), 9, "hi".to_string()),
+        Err(MarketError::MarketNotFound)
+    );
+
+    let open = market(&mut state, "alice");
+    assert_eq!(state.claim_winnings(identity("alice"), open), Err(MarketError::MarketNotResolved));
+
+    let resolved = resolved_market(&mut state, "alice", true, true);
+    assert_eq!(state.place_bet(identity("bob"), resolved, true, 1, None), Err(MarketError::BettingClosed));
+    assert_eq!(state.resolve_market(identity("bob"), resolved, true, None), Err(MarketError::MarketNotOpen));
+    assert_eq!(
+        state.add_comment(identity("bob"), resolved, "late".to_string()),
+        Err(MarketError::MarketNotOpen)
+    );
+    assert_eq!(state.claim_winnings(identity("bob"), resolved), Err(MarketError::NoUnclaimedBet));
+}
+
+#[test]
+fn claim_on_empty_winning_pool_is_an_error() {
+    let mut state = with_users(&["alice"]);
+    // Resolved straight from the state: the no-winner pool is normally swept
+    // to the treasury at resolution, leaving nothing to claim
+    let market_id = market(&mut state, "alice");
+    state.place_bet(identity("alice"), market_id, false, 100, None).unwrap();
+    state.markets.get_mut(&market_id).unwrap().status = contract1::MarketStatus::ResolvedYes;
+    state.users.get_mut(&identity("alice")).unwrap().bets[0].side = true;
+
+    assert_eq!(state.claim_winnings(identity("alice"), market_id), Err(MarketError::NoWinningPool));
+}
+
+#[test]
+fn admin_errors() {
+    let mut state = with_users(&["alice", "bob"]);
+    state.treasury = 50;
+    assert_eq!(
+        state.withdraw_treasury(identity("alice"), identity("alice"), 1),
+        Err(MarketError::NoAdmin)
+    );
+
+    state.set_admin(identity("alice"), identity("alice")).unwrap();
+    assert_eq!(
+        state.set_admin(identity("bob"), identity("bob")),
+        Err(MarketError::Unauthorized)
+    );
+    assert_eq!(
+        state.withdraw_treasury(identity("bob"), identity("bob"), 1),
+        Err(MarketError::Unauthorized)
+    );
+    assert_eq!(
+        state.withdraw_treasury(identity("alice"), identity("alice"), 0),
+        Err(MarketError::InvalidAmount)
+    );
+    assert_eq!(
+        state.withdraw_treasury(identity("alice"), identity("alice"), 51),
+        Err(MarketError::InsufficientTreasury { have: 50, need: 51 })
+    );
+    assert_eq!(
+        state.withdraw_treasury(identity("alice"), identity("nobody"), 1),
+        Err(MarketError::RecipientNotInitialized)
+    );
+}
+
+#[test]
+fn comment_errors() {
+    let mut state = with_users(&["alice"]);
+    let market_id = market(&mut state, "alice");
+    assert_eq!(
+        state.add_comment(identity("alice"), market_id, " ".to_string()),
+        Err(MarketError::EmptyComment)
+    );
+    assert_eq!(
+        state.add_comment(identity("alice"), market_id, "x".repeat(MAX_COMMENT_CHARS + 1)),
+        Err(MarketError::CommentTooLong { max: MAX_COMMENT_CHARS })
+    );
+    for _ in 0..MAX_COMMENTS_PER_MARKET {
+        state.add_comment(identity("alice"), market_id, "x".to_string()).unwrap();
+    }
+    assert_eq!(
+        state.add_comment(identity("alice"), market_id, "x".to_string()),
+        Err(MarketError::TooManyComments { market_id, max: MAX_COMMENTS_PER_MARKET })
+    );
+}
+
+#[test]
+fn parlay_errors() {
+    let mut state = with_users(&["alice"]);
+    state.treasury = 900;
+    let first = market(&mut state, "alice");
+    let second = market(&mut state, "alice");
+    let legs = vec![(first, true), (second, false)];
+    let leg_count = MarketError::ParlayLegCount { min: MIN_PARLAY_LEGS, max: MAX_PARLAY_LEGS };
+
+    assert_eq!(state.place_parlay(identity("alice"), vec![(first, true)], 10, None), Err(leg_count.clone()));
+    assert_eq!(
+        state.place_parlay(identity("alice"), vec![(first, true); MAX_PARLAY_LEGS + 1], 10, None),
+        Err(leg_count)
+    );
+    assert_eq!(
+        state.place_parlay(identity("alice"), vec![(first, true), (second, true), (first, false)], 10, None),
+        Err(MarketError::DuplicateParlayLeg { market_id: first })
+    );
+    assert_eq!(state.place_parlay(identity("alice"), legs.clone(), 0, None), Err(MarketError::InvalidAmount));
+    assert_eq!(
+        state.place_parlay(identity("mallory"), legs.clone(), 10, None),
+        Err(MarketError::UserNotInitialized)
+    );
+    assert_eq!(
+        state.place_parlay(identity("alice"), legs.clone(), 10_001, None),
+        Err(MarketError::InsufficientBalance { have: 10_000, need: 10_001 })
+    );
+    assert_eq!(
+        state.place_parlay(identity("alice"), vec![(first, true), (9, true)], 10, None),
+        Err(MarketError::MarketNotFound)
+    );
+    assert_eq!(
+        state.place_parlay(identity("alice"), legs.clone(), 101, None),
+        Err(MarketError::ParlayExposureExceeded { have: 900, need: 909 })
+    );
+
+    let resolved = resolved_market(&mut state, "alice", true, true);
+    assert_eq!(
+        state.place_parlay(identity("alice"), vec![(first, true), (resolved, true)], 10, None),
+        Err(MarketError::BettingClosed)
+    );
+
+    state.place_parlay(identity("alice"), legs, 100, None).unwrap();
+    assert_eq!(state.settle_parlay(1), Err(MarketError::ParlayLegOpen { market_id: first }));
+    assert_eq!(state.settle_parlay(2), Err(MarketError::ParlayNotFound));
+    state.resolve_market(identity("alice"), first, true, None).unwrap();
+    state.resolve_market(identity("alice"), second, true, None).unwrap();
+    state.settle_parlay(1).unwrap();
+    assert_eq!(state.settle_parlay(1), Err(MarketError::ParlayAlreadySettled));
+}
+
+#[test]
+fn execute_reports_the_display_message() {
+    let mut state = with_users(&["alice"]);
+    let err = run(&mut state, &identity("alice"), MarketAction::PlaceBet { market_id: 1, side: true, amount: 20_000 })
+        .unwrap_err();
+    assert_eq!(err, "Insufficient balance. Have: 10000, Need: 20000");
+    assert_eq!(err, MarketError::InsufficientBalance { have: 10_000, need: 20_000 }.to_string());
+
+    let err = run(&mut state, &identity("alice"), MarketAction::Initialize { idempotent: false }).unwrap_err();
+    assert_eq!(err, "User already initialized");
+}
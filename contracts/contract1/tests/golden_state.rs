@@ -0,0 +1,80 @@
+//! The prover and every verifier must derive the same `StateCommitment` from
+//! the same actions. This pins the commitment of a fixed scenario.
+//!
+//! If `commitment_matches_golden_hash` fails, the encoding of the state has
+//! changed: field order, map types, integer widths or payout rounding. States
+//! already committed on chain will no longer decode or match, so update
+//! `GOLDEN_COMMITMENT_SHA256` only together with a frozen copy of the
+//! previous layout in `src/legacy.rs` and a fixture it decodes in
+//! `tests/legacy_state.rs`.
+mod common;
+
+use std::collections::HashMap;
+
+use common::{identity, run};
+use contract1::{Contract1, MarketAction};
+use sdk::ZkContract;
+use sha2::{Digest, Sha256};
+
+const GOLDEN_COMMITMENT_SHA256: &str = "e1b8b7a09f753b79c63ac4db65d9a4f1e757ae5a89d359268158f32bb479ed27";
+
+/// 3 users, 2 markets, bets on both sides, a comment, one resolution and one
+/// claim.
+fn scenario(state: &mut Contract1) {
+    let steps: [(&str, MarketAction); 13] = [
+        ("alice", MarketAction::Initialize { idempotent: false }),
+        ("bob", MarketAction::Initialize { idempotent: false }),
+        ("carol", MarketAction::Initialize { idempotent: false }),
+        ("alice", MarketAction::CreateMarket { description: "Will it rain on Friday?".to_string(), opens_at: None, stake_cap: None, tags: vec![], challenge: None }),
+        ("bob", MarketAction::CreateMarket { description: "Will the train be late?".to_string(), opens_at: None, stake_cap: None, tags: vec![], challenge: None }),
+        ("alice", MarketAction::PlaceBet { market_id: 1, side: true, amount: 700 }),
+        ("bob", MarketAction::PlaceBet { market_id: 1, side: false, amount: 300 }),
+        ("carol", MarketAction::PlaceBet { market_id: 1, side: true, amount: 333 }),
+        ("carol", MarketAction::PlaceBet { market_id: 2, side: false, amount: 1_250 }),
+        ("alice", MarketAction::PlaceBet { market_id: 2, side: true, amount: 40 }),
+        ("carol", MarketAction::AddComment { market_id: 2, text: "Signal failure at the station".to_string() }),
+        ("bob", MarketAction::ResolveMarket { market_id: 1, outcome: true }),
+        ("bob", MarketAction::ClaimWinnings { market_id: 1 }),
+    ];
+    for (user, action) in steps {
+        run(state, &identity(user), action).unwrap_or_else(|e| panic!("{} failed: {}", user, e));
+    }
+}
+
+fn commitment_sha256(state: &Contract1) -> String {
+    hex::encode(Sha256::digest(state.commit().0))
+}
+
+#[test]
+fn commitment_matches_golden_hash() {
+    let mut state = Contract1::new();
+    scenario(&mut state);
+
+    assert_eq!(
+        commitment_sha256(&state),
+        GOLDEN_COMMITMENT_SHA256,
+        "the state encoding changed, see the module docs before updating the golden hash"
+    );
+}
+
+#[test]
+fn commitment_does_not_depend_on_map_layout() {
+    let mut fresh = Contract1::new();
+    scenario(&mut fresh);
+
+    // Same logical state reached through maps with other capacities, hash
+    // seeds and insertion histories
+    let mut churned = Contract1 {
+        users: HashMap::with_capacity(1024),
+        markets: HashMap::with_capacity(3),
+        ..Contract1::new()
+    };
+    for i in 0..200 {
+        run(&mut churned, &identity(&format!("ghost{}", i)), MarketAction::Initialize { idempotent: false }).unwrap();
+    }
+    churned.users.clear();
+    scenario(&mut churned);
+
+    assert_eq!(fresh.commit(), churned.commit());
+    assert_eq!(commitment_sha256(&fresh), commitment_sha256(&churned));
+}
@@ -0,0 +1,90 @@
+//! The HTTP bodies shared by the server and the bot: each survives a round
+//! trip through JSON, and omitted optional fields keep the defaults older
+//! clients relied on.
+use std::collections::HashSet;
+
+use contract1::{
+    api::ContractParams,
+    http::{
+        AcceptChallengeRequest, ApiRequest, CancelChallengeRequest, ChallengeRequest, ConfigResponse, CreateMarketRequest,
+        GetBalanceRequest, GetLeaderboardRequest, InitializeRequest, PlaceBetRequest, PlaceParlayRequest, TxState, TxStatus,
+        WithdrawTreasuryRequest, LEADERBOARD_DEFAULT_LIMIT, REQUEST_PATHS,
+    },
+    StakeCap,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+
+fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: T) {
+    let encoded = serde_json::to_string(&value).unwrap();
+    assert_eq!(serde_json::from_str::<T>(&encoded).unwrap(), value, "{}", encoded);
+}
+
+#[test]
+fn requests_survive_a_round_trip() {
+    round_trip(PlaceBetRequest { market_id: 3, side: true, amount: u128::MAX });
+    round_trip(InitializeRequest { idempotent: true });
+    round_trip(GetBalanceRequest {});
+    round_trip(GetLeaderboardRequest { limit: 25 });
+    round_trip(WithdrawTreasuryRequest { to: "alice@contract1".to_string(), amount: 500 });
+    round_trip(PlaceParlayRequest { legs: vec![(1, true), (2, false)], amount: 100 });
+    round_trip(CreateMarketRequest {
+        description: "Will it rain?".to_string(),
+        opens_at: Some(1_700_000_000),
+        stake_cap: Some(StakeCap::Absolute(1_000)),
+        tags: vec!["weather".to_string()],
+        challenge: Some(ChallengeRequest { opponent: "bob@contract1".to_string(), stake: 200 }),
+    });
+}
+
+#[test]
+fn omitted_fields_take_their_defaults() {
+    assert_eq!(serde_json::from_value::<InitializeRequest>(json!({})).unwrap(), InitializeRequest { idempotent: false });
+    assert_eq!(
+        serde_json::from_value::<GetLeaderboardRequest>(json!({})).unwrap(),
+        GetLeaderboardRequest { limit: LEADERBOARD_DEFAULT_LIMIT }
+    );
+    let market = serde_json::from_value::<CreateMarketRequest>(json!({ "description": "Will it rain?" })).unwrap();
+    assert_eq!((market.opens_at, market.stake_cap, market.tags.len(), market.challenge), (None, None, 0, None));
+}
+
+#[test]
+fn unset_options_are_left_out_of_requests() {
+    let market = CreateMarketRequest {
+        description: "Will it rain?".to_string(),
+        opens_at: None,
+        stake_cap: None,
+        tags: vec![],
+        challenge: None,
+    };
+
+    assert_eq!(serde_json::to_value(&market).unwrap(), json!({ "description": "Will it rain?", "tags": [] }));
+}
+
+#[test]
+fn every_route_takes_one_request_type() {
+    let unique: HashSet<_> = REQUEST_PATHS.iter().collect();
+    assert_eq!(unique.len(), REQUEST_PATHS.len());
+    // Accepting and cancelling a challenge take the same fields, but not the same type
+    assert_ne!(AcceptChallengeRequest::PATH, CancelChallengeRequest::PATH);
+}
+
+#[test]
+fn responses_read_older_servers() {
+    let config = serde_json::from_value::<ConfigResponse>(json!({ "contract_name": "contract1" })).unwrap();
+    assert_eq!(
+        config,
+        ConfigResponse {
+            contract_name: "contract1".to_string(),
+            api_version: 1,
+            params: ContractParams::default(),
+            state_epoch: None,
+            paused: false,
+        }
+    );
+    round_trip(ConfigResponse { api_version: 2, state_epoch: Some(3), paused: true, ..config });
+
+    let status = TxStatus { tx_hash: "ab".repeat(32), status: TxState::Failed, error: Some("Market is closed".to_string()) };
+    assert_eq!(serde_json::to_value(&status).unwrap()["status"], "failed");
+    round_trip(status);
+}
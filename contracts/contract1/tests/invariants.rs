@@ -0,0 +1,183 @@
+mod common;
+
+use common::{identity, run, total_funds};
+use contract1::{Contract1, MarketAction, ParlayStatus};
+use proptest::prelude::*;
+use sdk::ZkContract;
+
+const INITIAL_BALANCE: u128 = 10_000;
+const USERS: [&str; 4] = ["alice", "bob", "carol", "dave"];
+
+/// One step of a generated scenario. Users and markets are indexes so that
+/// shrinking keeps sequences readable.
+#[derive(Debug, Clone)]
+enum Op {
+    Initialize { user: usize, idempotent: bool },
+    CreateMarket { user: usize },
+    PlaceBet { user: usize, market: u64, side: bool, amount: u128 },
+    Resolve { user: usize, market: u64, outcome: bool },
+    Claim { user: usize, market: u64 },
+    SetAdmin { user: usize, admin: usize },
+    Withdraw { user: usize, to: usize, amount: u128 },
+    Parlay { user: usize, sides: (bool, bool), amount: u128 },
+    SettleParlay { user: usize, parlay: u64 },
+    Confiscate { user: usize, target: usize },
+    SetHouseEdge { user: usize, bps: u16 },
+    DistributeDividends { user: usize },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let user = 0..USERS.len();
+    let market = 1..=2u64;
+    prop_oneof![
+        1 => (user.clone(), any::<bool>()).prop_map(|(user, idempotent)| Op::Initialize { user, idempotent }),
+        1 => user.clone().prop_map(|user| Op::CreateMarket { user }),
+        4 => (user.clone(), market.clone(), any::<bool>(), 0..=3_000u128)
+            .prop_map(|(user, market, side, amount)| Op::PlaceBet { user, market, side, amount }),
+        1 => (user.clone(), market.clone(), any::<bool>())
+            .prop_map(|(user, market, outcome)| Op::Resolve { user, market, outcome }),
+        1 => (user.clone(), market).prop_map(|(user, market)| Op::Claim { user, market }),
+        1 => (user.clone(), user.clone()).prop_map(|(user, admin)| Op::SetAdmin { user, admin }),
+        1 => (user.clone(), user.clone(), 0..=1_000u128).prop_map(|(user, to, amount)| Op::Withdraw { user, to, amount }),
+        1 => (user.clone(), any::<(bool, bool)>(), 0..=100u128)
+            .prop_map(|(user, sides, amount)| Op::Parlay { user, sides, amount }),
+        1 => (user.clone(), 1..=3u64).prop_map(|(user, parlay)| Op::SettleParlay { user, parlay }),
+        1 => (user.clone(), user.clone()).prop_map(|(user, target)| Op::Confiscate { user, target }),
+        1 => (user.clone(), 0..=2_500u16).prop_map(|(user, bps)| Op::SetHouseEdge { user, bps }),
+        1 => user.prop_map(|user| Op::DistributeDividends { user }),
+    ]
+}
+
+impl Op {
+    fn user(&self) -> usize {
+        match self {
+            Op::Initialize { user, .. }
+            | Op::CreateMarket { user }
+            | Op::PlaceBet { user, .. }
+            | Op::Resolve { user, .. }
+            | Op::Claim { user, .. }
+            | Op::SetAdmin { user, .. }
+            | Op::Withdraw { user, .. }
+            | Op::Parlay { user, .. }
+            | Op::SettleParlay { user, .. }
+            | Op::Confiscate { user, .. }
+            | Op::SetHouseEdge { user, .. }
+            | Op::DistributeDividends { user } => *user,
+        }
+    }
+
+    fn action(&self) -> MarketAction {
+        match *self {
+            Op::Initialize { idempotent, .. } => MarketAction::Initialize { idempotent },
+            Op::CreateMarket { .. } => MarketAction::CreateMarket {
+                description: "generated".to_string(),
+                opens_at: None,
+                stake_cap: None,
+                tags: vec![],
+                challenge: None,
+            },
+            Op::PlaceBet { market, side, amount, .. } => MarketAction::PlaceBet {
+                market_id: market,
+                side,
+                amount,
+            },
+            Op::Resolve { market, outcome, .. } => MarketAction::ResolveMarket {
+                market_id: market,
+                outcome,
+            },
+            Op::Claim { market, .. } => MarketAction::ClaimWinnings { market_id: market },
+            Op::SetAdmin { admin, .. } => MarketAction::SetAdmin { new_admin: identity(USERS[admin]) },
+            Op::Withdraw { to, amount, .. } => MarketAction::WithdrawTreasury {
+                to: identity(USERS[to]),
+                amount,
+            },
+            Op::Parlay { sides: (first, second), amount, .. } => MarketAction::PlaceParlay {
+                legs: vec![(1, first), (2, second)],
+                amount,
+            },
+            Op::SettleParlay { parlay, .. } => MarketAction::SettleParlay { parlay_id: parlay },
+            Op::Confiscate { target, .. } => MarketAction::ConfiscateBalance { user: identity(USERS[target]) },
+            Op::SetHouseEdge { bps, .. } => MarketAction::SetHouseEdge { bps },
+            Op::DistributeDividends { .. } => MarketAction::DistributeDividends,
+        }
+    }
+}
+
+fn minted(state: &Contract1) -> u128 {
+    state.users.values().filter(|u| u.initialized).count() as u128 * INITIAL_BALANCE
+}
+
+/// Runs `ops` from an empty state, checking after every step that the funds
+/// add up to what was minted and that the parlay reserve covers what is owed.
+fn check_conservation(ops: &[Op]) -> Result<(), TestCaseError> {
+    let mut state = Contract1::new();
+
+    for (step, op) in ops.iter().enumerate() {
+        let _ = run(&mut state, &identity(USERS[op.user()]), op.action());
+
+        // Dust and pools nobody won go to the treasury, funds nobody is
+        // entitled to are burned, so nothing leaks
+        let minted = minted(&state);
+        let total = total_funds(&state);
+        prop_assert_eq!(
+            total, minted,
+            "step {}: {:?} broke conservation ({} balances + open pools + treasury {} + burned {}, {} minted)",
+            step, op, total, state.treasury, state.burned, minted
+        );
+
+        // The reserve holds exactly the largest payouts still owed
+        let owed: u128 = state
+            .parlays
+            .values()
+            .filter(|parlay| parlay.status == ParlayStatus::Open)
+            .map(|parlay| parlay.max_payout)
+            .sum();
+        prop_assert_eq!(state.parlay_reserve, owed, "step {}: {:?}", step, op);
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn funds_are_conserved(ops in prop::collection::vec(op(), 1..60)) {
+        check_conservation(&ops)?;
+    }
+
+    #[test]
+    fn pools_match_bettor_stakes(ops in prop::collection::vec(op(), 1..60)) {
+        let mut state = Contract1::new();
+        for op in &ops {
+            let _ = run(&mut state, &identity(USERS[op.user()]), op.action());
+        }
+
+        for market in state.markets.values() {
+            prop_assert_eq!(market.yes_pool, market.yes_bettors.values().sum::<u128>());
+            prop_assert_eq!(market.no_pool, market.no_bettors.values().sum::<u128>());
+        }
+    }
+
+    #[test]
+    fn commitment_decodes_to_same_state(ops in prop::collection::vec(op(), 1..40)) {
+        let mut state = Contract1::new();
+        for op in &ops {
+            let _ = run(&mut state, &identity(USERS[op.user()]), op.action());
+
+            let commitment = state.commit();
+            let decoded = Contract1::try_from(commitment.clone()).expect("valid state decodes");
+            prop_assert_eq!(decoded.commit(), commitment);
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    /// Long enough for admins to change and balances to be burned while
+    /// markets and parlays keep settling.
+    #[test]
+    fn funds_are_conserved_over_long_scenarios(ops in prop::collection::vec(op(), 200..400)) {
+        check_conservation(&ops)?;
+    }
+}
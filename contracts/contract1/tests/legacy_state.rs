@@ -0,0 +1,89 @@
+//! States committed by earlier versions of the contract still decode. Each
+//! fixture in `tests/fixtures/state_v*.hex` was committed by the contract of
+//! its layout: alice and bob initialized, alice admin, one market with a stake
+//! cap and a tag, a bet on each side in timed blocks and a comment by bob.
+//! Layouts from `StateV4` on also burn bob's balance, from `StateV6` on bob
+//! sends a nonced comment and nonces are required, and `StateV7` has a house
+//! edge.
+mod common;
+
+use std::path::Path;
+
+use common::identity;
+use contract1::{Contract1, MarketStatus, StakeCap};
+use sdk::{StateCommitment, ZkContract};
+
+fn decode(version: u32) -> Contract1 {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/state_v{}.hex", version));
+    let bytes = hex::decode(std::fs::read_to_string(path).unwrap().trim()).unwrap();
+    let state = Contract1::try_from(StateCommitment(bytes)).unwrap_or_else(|e| panic!("layout {}: {}", version, e));
+    // Committing it again writes the current layout, which decodes as is
+    let recommitted = Contract1::try_from(state.commit()).unwrap();
+    assert!(recommitted.commit() == state.commit());
+    state
+}
+
+/// What every layout holds the same way.
+fn assert_common(state: &Contract1, bob_balance: u128) {
+    assert_eq!(state.admin, Some(identity("alice")));
+    assert_eq!((state.next_market_id, state.state_epoch, state.paused), (1, 0, false));
+    assert_eq!(state.users[&identity("alice")].balance, 9_900);
+    assert_eq!(state.users[&identity("bob")].balance, bob_balance);
+    assert_eq!(state.users[&identity("alice")].bets.len(), 1);
+
+    let market = &state.markets[&1];
+    assert_eq!(market.description, "Will it rain?");
+    assert_eq!((market.yes_pool, market.no_pool, market.status.clone()), (100, 50, MarketStatus::Open));
+    assert_eq!(market.stake_cap, Some(StakeCap::Absolute(500)));
+    assert_eq!(market.tags, ["weather"]);
+    assert_eq!(market.history.len(), 2);
+    assert_eq!(market.yes_bettors[&identity("alice")], 100);
+    assert_eq!(market.comments[0].text, "Clouds");
+    assert_eq!((market.challenge.clone(), market.accept_by, market.rake), (None, None, 0));
+    // Layouts before it was recorded take it from the history
+    assert_eq!(market.last_bet_at, Some(1_700_000_120));
+}
+
+#[test]
+fn states_before_epochs_decode_with_epoch_zero() {
+    for version in [1, 2, 3] {
+        let state = decode(version);
+        assert_common(&state, 9_950);
+        assert_eq!(state.burned, 0);
+        // Markets recorded their creation time from the challenges on
+        let created_at = if version < 3 { 0 } else { 1_700_000_000 };
+        assert_eq!(state.markets[&1].created_at, created_at);
+        assert_eq!(state.users[&identity("bob")].next_nonce, 0);
+    }
+}
+
+#[test]
+fn states_before_nonces_keep_their_burns() {
+    for version in [4, 5] {
+        let state = decode(version);
+        assert_common(&state, 0);
+        assert_eq!((state.burned, state.require_nonces), (9_950, false));
+        assert_eq!(state.markets[&1].created_at, 1_700_000_000);
+    }
+}
+
+#[test]
+fn states_before_dividends_keep_their_nonces() {
+    let state = decode(6);
+    assert_common(&state, 0);
+    assert_eq!(state.markets[&1].created_at, 1_700_000_000);
+    assert_eq!((state.burned, state.require_nonces, state.house_edge_bps), (9_950, true, 0));
+    assert_eq!(state.users[&identity("bob")].next_nonce, 1);
+    assert_eq!(state.users[&identity("bob")].bets_since_dividend, 0);
+    assert_eq!(state.markets[&1].comments.len(), 2);
+}
+
+#[test]
+fn states_before_pauses_keep_their_house_edge() {
+    let state = decode(7);
+    assert_common(&state, 0);
+    assert_eq!(state.markets[&1].created_at, 1_700_000_000);
+    assert_eq!((state.house_edge_bps, state.dividend_pool, state.require_nonces), (500, 0, true));
+    assert_eq!(state.users[&identity("bob")].next_nonce, 1);
+    assert_eq!(state.users[&identity("alice")].bets_since_dividend, 1);
+}
@@ -0,0 +1,2342 @@
+mod common;
+
+use common::{balance, calldata, identity, run, run_at, total_funds, with_users};
+use contract1::{
+    api::{
+        BalanceDrift, ClaimResult, LargestMarket, LedgerBalance, LedgerMarket, MarketCounts, MarketEvent, MarketFilter, MarketStatusFilter, OpenExposure, PoolDrift, ReconcileSnapshot,
+        ResolveResult, SnapshotMarket, SnapshotResolution, SNAPSHOT_LEADERBOARD, SNAPSHOT_OPEN_MARKETS,
+    },
+    Challenge, Contract1, MarketAction, MarketError, MarketStatus, StakeCap, UserState, CHALLENGE_ACCEPT_WINDOW, MAX_BETTORS_PER_MARKET, MAX_COMMENTS_PER_MARKET,
+    MAX_COMMENT_CHARS, MAX_IDENTITY_LEN, MAX_LEADERBOARD_LIMIT, MAX_MARKET_HISTORY, MAX_HOUSE_EDGE_BPS, MAX_MARKET_LIFETIME, MAX_MARKET_TAGS, MAX_TAG_CHARS,
+    RESOLUTION_COOLDOWN,
+};
+use sdk::{Identity, ZkContract};
+
+const INITIAL_BALANCE: u128 = 10_000;
+
+fn create_market(state: &mut Contract1, creator: &str) -> u64 {
+    run(
+        state,
+        &identity(creator),
+        MarketAction::CreateMarket {
+            description: "Will it rain tomorrow?".to_string(),
+            opens_at: None,
+            stake_cap: None,
+            tags: vec![],
+            challenge: None,
+        },
+    )
+    .expect("create market");
+    state.next_market_id
+}
+
+fn bet(state: &mut Contract1, name: &str, market_id: u64, side: bool, amount: u128) -> Result<String, String> {
+    run(state, &identity(name), MarketAction::PlaceBet { market_id, side, amount })
+}
+
+// --------------------------------------------------------
+//     Initialize
+// --------------------------------------------------------
+
+#[test]
+fn initialize_credits_initial_balance() {
+    let state = with_users(&["alice"]);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+    assert!(state.users[&identity("alice")].initialized);
+}
+
+#[test]
+fn initialize_twice_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let err = run(&mut state, &identity("alice"), MarketAction::Initialize { idempotent: false }).unwrap_err();
+    assert_eq!(err, "User already initialized");
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+}
+
+#[test]
+fn idempotent_initialize_grants_only_once() {
+    let mut state = Contract1::new();
+    let alice = identity("alice");
+
+    let outputs: Vec<String> = (0..3)
+        .map(|_| run(&mut state, &alice, MarketAction::Initialize { idempotent: true }).unwrap())
+        .collect();
+
+    assert_eq!(
+        outputs,
+        vec![
+            format!("Initialized with {} balance", INITIAL_BALANCE),
+            format!("Already initialized with {} balance", INITIAL_BALANCE),
+            format!("Already initialized with {} balance", INITIAL_BALANCE),
+        ]
+    );
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+    assert_eq!(total_funds(&state), INITIAL_BALANCE);
+}
+
+#[test]
+fn idempotent_initialize_reports_the_current_balance() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 400).unwrap();
+
+    let output = run(&mut state, &identity("alice"), MarketAction::Initialize { idempotent: true }).unwrap();
+
+    assert_eq!(output, format!("Already initialized with {} balance", INITIAL_BALANCE - 400));
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 400);
+}
+
+#[test]
+fn garbage_blob_is_rejected() {
+    let mut state = Contract1::new();
+    let mut calldata = calldata(&identity("alice"), &MarketAction::Initialize { idempotent: false });
+    calldata.blobs = vec![sdk::Blob {
+        contract_name: sdk::ContractName(common::CONTRACT_NAME.to_string()),
+        data: sdk::BlobData(vec![0xff; 3]),
+    }]
+    .into();
+    assert!(state.execute(&calldata).is_err());
+    assert!(state.users.is_empty());
+}
+
+// --------------------------------------------------------
+//     Identity validation
+// --------------------------------------------------------
+
+fn malformed_identities() -> Vec<Identity> {
+    vec![
+        Identity(String::new()),
+        Identity(format!("{}@{}", "a".repeat(MAX_IDENTITY_LEN), common::CONTRACT_NAME)),
+        Identity("alice".to_string()),
+        Identity(format!("@{}", common::CONTRACT_NAME)),
+        Identity("alice@other_contract".to_string()),
+    ]
+}
+
+#[test]
+fn malformed_caller_is_rejected_before_touching_state() {
+    for caller in malformed_identities() {
+        let mut state = Contract1::new();
+        let before = state.commit();
+        assert!(run(&mut state, &caller, MarketAction::Initialize { idempotent: false }).is_err(), "{:?}", caller);
+        assert!(state.users.is_empty(), "{:?}", caller);
+        assert_eq!(state.commit(), before);
+    }
+}
+
+#[test]
+fn malformed_new_admin_is_rejected() {
+    for new_admin in malformed_identities() {
+        let mut state = with_users(&["alice"]);
+        let before = state.commit();
+        let err = run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: new_admin.clone() });
+        assert!(err.is_err(), "{:?}", new_admin);
+        assert_eq!(state.commit(), before);
+    }
+}
+
+#[test]
+fn identity_at_the_length_limit_is_accepted() {
+    let name = "a".repeat(MAX_IDENTITY_LEN - common::CONTRACT_NAME.len() - 1);
+    let mut state = Contract1::new();
+    run(&mut state, &identity(&name), MarketAction::Initialize { idempotent: false }).expect("initialize");
+    assert_eq!(balance(&state, &name), INITIAL_BALANCE);
+}
+
+// --------------------------------------------------------
+//     CreateMarket
+// --------------------------------------------------------
+
+#[test]
+fn create_market_assigns_increasing_ids() {
+    let mut state = with_users(&["alice"]);
+    assert_eq!(create_market(&mut state, "alice"), 1);
+    assert_eq!(create_market(&mut state, "alice"), 2);
+
+    let market = &state.markets[&2];
+    assert_eq!(market.creator, identity("alice"));
+    assert_eq!(market.status, MarketStatus::Open);
+    assert_eq!(market.yes_pool + market.no_pool, 0);
+}
+
+#[test]
+fn create_market_without_init_is_rejected() {
+    let mut state = Contract1::new();
+    let err = run(
+        &mut state,
+        &identity("mallory"),
+        MarketAction::CreateMarket { description: "?".to_string(), opens_at: None, stake_cap: None, tags: vec![], challenge: None },
+    )
+    .unwrap_err();
+    assert_eq!(err, "User not initialized");
+    assert!(state.markets.is_empty());
+}
+
+// --------------------------------------------------------
+//     PlaceBet
+// --------------------------------------------------------
+
+#[test]
+fn place_bet_moves_stake_into_pool() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+
+    bet(&mut state, "alice", market_id, true, 300).unwrap();
+    bet(&mut state, "bob", market_id, false, 200).unwrap();
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+
+    let market = &state.markets[&market_id];
+    assert_eq!(market.yes_pool, 400);
+    assert_eq!(market.no_pool, 200);
+    assert_eq!(market.yes_bettors[&identity("alice")], 400);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 400);
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE - 200);
+}
+
+#[test]
+fn bet_without_init_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    let err = bet(&mut state, "mallory", market_id, true, 10).unwrap_err();
+    assert_eq!(err, "User not initialized");
+}
+
+#[test]
+fn bet_on_missing_market_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let err = bet(&mut state, "alice", 42, true, 10).unwrap_err();
+    assert_eq!(err, "Market not found");
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+}
+
+#[test]
+fn bet_on_closed_market_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+
+    let err = bet(&mut state, "alice", market_id, true, 10).unwrap_err();
+    assert_eq!(err, "Market is not open for betting");
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+}
+
+#[test]
+fn bet_above_balance_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    let err = bet(&mut state, "alice", market_id, true, INITIAL_BALANCE + 1).unwrap_err();
+    assert!(err.starts_with("Insufficient balance"), "{}", err);
+    assert_eq!(state.markets[&market_id].yes_pool, 0);
+}
+
+#[test]
+fn full_market_refuses_new_bettors_only() {
+    let names: Vec<String> = (0..=MAX_BETTORS_PER_MARKET).map(|i| format!("user{}", i)).collect();
+    let mut state = with_users(&names.iter().map(String::as_str).collect::<Vec<_>>());
+    let market_id = create_market(&mut state, "user0");
+
+    // One short of the cap, with a bettor on both sides counted once
+    for (i, name) in names[..MAX_BETTORS_PER_MARKET - 1].iter().enumerate() {
+        bet(&mut state, name, market_id, i % 2 == 0, 10).unwrap();
+    }
+    bet(&mut state, "user1", market_id, true, 10).unwrap();
+    assert_eq!(state.markets[&market_id].bettor_count(), MAX_BETTORS_PER_MARKET - 1);
+
+    let last = &names[MAX_BETTORS_PER_MARKET - 1];
+    bet(&mut state, last, market_id, true, 10).unwrap();
+    let info = run(&mut state, &identity("user0"), MarketAction::GetMarketInfo { market_id }).unwrap();
+    assert!(info.contains(&format!("Bettors: {0}/{0}", MAX_BETTORS_PER_MARKET)), "{}", info);
+
+    let newcomer = &names[MAX_BETTORS_PER_MARKET];
+    let err = bet(&mut state, newcomer, market_id, false, 10).unwrap_err();
+    assert_eq!(
+        err,
+        format!("Market #{} already has {} bettors, only they can add to their bets", market_id, MAX_BETTORS_PER_MARKET)
+    );
+    assert_eq!(balance(&state, newcomer), INITIAL_BALANCE);
+
+    // Bettors already in can top up, on either side
+    bet(&mut state, last, market_id, true, 5).unwrap();
+    bet(&mut state, last, market_id, false, 5).unwrap();
+    assert_eq!(state.markets[&market_id].bettor_count(), MAX_BETTORS_PER_MARKET);
+
+    // The cap is per market
+    let other = create_market(&mut state, "user0");
+    bet(&mut state, newcomer, other, false, 10).unwrap();
+}
+
+// --------------------------------------------------------
+//     ResolveMarket
+// --------------------------------------------------------
+
+#[test]
+fn resolve_yes_pays_yes_bettors_pro_rata() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 300).unwrap();
+    bet(&mut state, "bob", market_id, true, 100).unwrap();
+    bet(&mut state, "carol", market_id, false, 200).unwrap();
+
+    run(&mut state, &identity("carol"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+
+    assert_eq!(state.markets[&market_id].status, MarketStatus::ResolvedYes);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 300 + 450);
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE - 100 + 150);
+    assert_eq!(balance(&state, "carol"), INITIAL_BALANCE - 200);
+}
+
+#[test]
+fn resolve_no_pays_no_bettors() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 500).unwrap();
+    bet(&mut state, "bob", market_id, false, 250).unwrap();
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: false }).unwrap();
+
+    assert_eq!(state.markets[&market_id].status, MarketStatus::ResolvedNo);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 500);
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE + 500);
+}
+
+#[test]
+fn resolve_missing_market_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let err = run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 7, outcome: true })
+        .unwrap_err();
+    assert_eq!(err, "Market not found");
+}
+
+#[test]
+fn resolve_twice_is_rejected() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "bob", market_id, true, 100).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    let paid = balance(&state, "bob");
+
+    let err = run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true })
+        .unwrap_err();
+    assert_eq!(err, "Market is not open");
+    assert_eq!(balance(&state, "bob"), paid);
+}
+
+#[test]
+fn resolve_sends_rounding_dust_to_treasury() {
+    let mut state = with_users(&["alice", "bob", "carol", "dave"]);
+    let market_id = create_market(&mut state, "alice");
+    for name in ["alice", "bob", "carol"] {
+        bet(&mut state, name, market_id, true, 1).unwrap();
+    }
+    bet(&mut state, "dave", market_id, false, 1).unwrap();
+
+    run(&mut state, &identity("dave"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+
+    // Each winner gets floor(4 / 3) = 1, the remaining unit is dust
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+    assert_eq!(state.treasury, 1);
+}
+
+#[test]
+fn resolve_without_winners_sends_pool_to_treasury() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, false, 400).unwrap();
+    bet(&mut state, "bob", market_id, false, 100).unwrap();
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    assert_eq!(state.treasury, 500);
+}
+
+// --------------------------------------------------------
+//     ClaimWinnings
+// --------------------------------------------------------
+
+#[test]
+fn claim_after_auto_distribution_pays_nothing_more() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+    bet(&mut state, "bob", market_id, false, 100).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    let paid = balance(&state, "alice");
+
+    let err = run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id }).unwrap_err();
+    assert_eq!(err, "No unclaimed bet found for this market");
+    assert_eq!(balance(&state, "alice"), paid);
+}
+
+#[test]
+fn claim_by_loser_marks_bet_claimed() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+    bet(&mut state, "bob", market_id, false, 100).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+
+    let msg = run(&mut state, &identity("bob"), MarketAction::ClaimWinnings { market_id }).unwrap();
+    assert_eq!(msg, "Your bet did not win");
+    assert!(state.users[&identity("bob")].bets[0].claimed);
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE - 100);
+}
+
+#[test]
+fn claim_on_open_market_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+
+    let err = run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id }).unwrap_err();
+    assert_eq!(err, "Market not resolved yet");
+}
+
+#[test]
+fn claim_on_missing_market_is_rejected() {
+    let mut state = with_users(&["alice"]);
+    let err = run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id: 3 }).unwrap_err();
+    assert_eq!(err, "Market not found");
+}
+
+#[test]
+fn resolve_and_claim_results_report_the_payouts() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+    bet(&mut state, "carol", market_id, true, 200).unwrap();
+    bet(&mut state, "bob", market_id, false, 101).unwrap();
+    assert_eq!(state.resolve_result(market_id), None);
+    assert_eq!(state.claim_result(&identity("alice"), market_id), None);
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+
+    let resolved = state.resolve_result(market_id).unwrap();
+    assert_eq!(resolved, ResolveResult { outcome: true, total_distributed: 400, winner_count: 2 });
+    let alice = state.claim_result(&identity("alice"), market_id).unwrap();
+    assert_eq!(alice, ClaimResult { market_id, payout: 133 });
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 100 + alice.payout);
+    assert_eq!(state.claim_result(&identity("bob"), market_id).unwrap().payout, 0);
+    assert_eq!(state.resolve_result(7), None);
+}
+
+/// Every ordering of one to three YES/NO legs.
+fn leg_orderings() -> Vec<Vec<bool>> {
+    (1..=3u32)
+        .flat_map(|len| (0..1u32 << len).map(move |bits| (0..len).map(|i| bits & (1 << i) != 0).collect()))
+        .collect()
+}
+
+/// Alice bets 100, 200, 300... on `legs`, against 1,000 from bob on each side.
+fn hedged_market(legs: &[bool]) -> (Contract1, u64) {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "bob");
+    bet(&mut state, "bob", market_id, true, 1_000).unwrap();
+    bet(&mut state, "bob", market_id, false, 1_000).unwrap();
+    for (i, side) in legs.iter().enumerate() {
+        bet(&mut state, "alice", market_id, *side, 100 * (i as u128 + 1)).unwrap();
+    }
+    (state, market_id)
+}
+
+/// Alice's balance once `legs` are settled as `outcome`: her whole stake on
+/// the winning side is paid once, the other legs pay nothing.
+fn settled_balance(legs: &[bool], outcome: bool) -> u128 {
+    let stakes: Vec<(bool, u128)> = legs.iter().enumerate().map(|(i, side)| (*side, 100 * (i as u128 + 1))).collect();
+    let staked: u128 = stakes.iter().map(|(_, amount)| amount).sum();
+    let winning: u128 = stakes.iter().filter(|(side, _)| *side == outcome).map(|(_, amount)| amount).sum();
+    let total_pool = 2_000 + staked;
+    let payout = if winning > 0 { contract1::parimutuel_payout(winning, 1_000 + winning, total_pool) } else { 0 };
+    INITIAL_BALANCE - staked + payout
+}
+
+#[test]
+fn hedged_legs_are_settled_once_at_resolution_in_any_order() {
+    for legs in leg_orderings() {
+        for outcome in [true, false] {
+            let (mut state, market_id) = hedged_market(&legs);
+            run(&mut state, &identity("bob"), MarketAction::ResolveMarket { market_id, outcome }).unwrap();
+
+            let case = format!("{:?} resolved {}", legs, outcome);
+            assert_eq!(balance(&state, "alice"), settled_balance(&legs, outcome), "{}", case);
+            let alice = &state.users[&identity("alice")];
+            // Winners are settled on both sides, losers keep their legs to claim
+            let backed_outcome = legs.contains(&outcome);
+            assert!(alice.bets.iter().all(|bet| bet.claimed == backed_outcome), "{}", case);
+
+            let claimed = run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id });
+            if backed_outcome {
+                assert_eq!(claimed, Err("No unclaimed bet found for this market".to_string()), "{}", case);
+            } else {
+                assert_eq!(claimed, Ok("Your bet did not win".to_string()), "{}", case);
+                assert!(state.users[&identity("alice")].bets.iter().all(|bet| bet.claimed), "{}", case);
+            }
+            assert_eq!(balance(&state, "alice"), settled_balance(&legs, outcome), "{}", case);
+            assert_eq!(total_funds(&state), 2 * INITIAL_BALANCE, "{}", case);
+        }
+    }
+}
+
+#[test]
+fn hedged_legs_are_claimed_once_in_any_order() {
+    for legs in leg_orderings() {
+        for outcome in [true, false] {
+            // Resolved without distributing, like markets resolved before
+            // payouts were automatic: every leg is left to claim
+            let (mut state, market_id) = hedged_market(&legs);
+            state.markets.get_mut(&market_id).unwrap().status =
+                if outcome { MarketStatus::ResolvedYes } else { MarketStatus::ResolvedNo };
+
+            let case = format!("{:?} resolved {}", legs, outcome);
+            let claimed = run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id }).unwrap();
+            assert_eq!(claimed.starts_with("Claimed"), legs.contains(&outcome), "{}: {}", case, claimed);
+            assert_eq!(balance(&state, "alice"), settled_balance(&legs, outcome), "{}", case);
+            assert!(state.users[&identity("alice")].bets.iter().all(|bet| bet.claimed), "{}", case);
+
+            let again = run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id });
+            assert_eq!(again, Err("No unclaimed bet found for this market".to_string()), "{}", case);
+            assert_eq!(balance(&state, "alice"), settled_balance(&legs, outcome), "{}", case);
+        }
+    }
+}
+
+#[test]
+fn winning_legs_settled_by_an_earlier_claim_are_not_paid_again() {
+    // A claim used to settle one leg at a time, paying the whole winning
+    // stake with the first winning leg it found
+    let (mut state, market_id) = hedged_market(&[true, false, true]);
+    state.markets.get_mut(&market_id).unwrap().status = MarketStatus::ResolvedYes;
+    let paid = contract1::parimutuel_payout(400, 1_400, 2_600);
+    let alice = state.users.get_mut(&identity("alice")).unwrap();
+    alice.bets[0].claimed = true;
+    alice.balance += paid;
+
+    let claimed = run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id }).unwrap();
+    assert_eq!(claimed, "Your bet did not win");
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 600 + paid);
+    assert!(state.users[&identity("alice")].bets.iter().all(|bet| bet.claimed));
+}
+
+// --------------------------------------------------------
+//     Streaks
+// --------------------------------------------------------
+
+fn streak(state: &Contract1, name: &str) -> u32 {
+    state.users[&identity(name)].current_streak
+}
+
+/// A market where every `yes` bettor stakes 100 on YES, every `no` bettor
+/// 100 on NO, resolved as YES.
+fn settle_yes(state: &mut Contract1, yes: &[&str], no: &[&str]) -> u64 {
+    let market_id = create_market(state, "alice");
+    for name in yes {
+        bet(state, name, market_id, true, 100).unwrap();
+    }
+    for name in no {
+        bet(state, name, market_id, false, 100).unwrap();
+    }
+    run(state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    market_id
+}
+
+#[test]
+fn streak_grows_with_wins_and_resets_on_a_loss() {
+    let mut state = with_users(&["alice", "bob"]);
+    settle_yes(&mut state, &["alice"], &["bob"]);
+    settle_yes(&mut state, &["alice"], &["bob"]);
+    assert_eq!((streak(&state, "alice"), streak(&state, "bob")), (2, 0));
+
+    settle_yes(&mut state, &["bob"], &["alice"]);
+    assert_eq!((streak(&state, "alice"), streak(&state, "bob")), (0, 1));
+}
+
+#[test]
+fn streak_milestone_is_paid_from_the_treasury() {
+    let mut state = with_users(&["alice", "bob"]);
+    state.treasury = 1_000;
+    for _ in 0..3 {
+        settle_yes(&mut state, &["alice"], &["bob"]);
+    }
+
+    assert_eq!(streak(&state, "alice"), 3);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE + 300 + 100);
+    assert_eq!(state.treasury, 900);
+}
+
+#[test]
+fn streak_bonus_is_skipped_when_the_treasury_is_short() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    state.treasury = 100;
+    let funds = total_funds(&state);
+    for _ in 0..3 {
+        settle_yes(&mut state, &["alice", "bob"], &["carol"]);
+    }
+
+    // Both reach the milestone together; the treasury covers one bonus,
+    // which goes to the first identity
+    assert_eq!((streak(&state, "alice"), streak(&state, "bob")), (3, 3));
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE + 150 + 100);
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE + 150);
+    assert_eq!(state.treasury, 0);
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn hedged_market_counts_as_a_loss() {
+    let mut state = with_users(&["alice", "bob"]);
+    state.treasury = 1_000;
+    settle_yes(&mut state, &["alice"], &["bob"]);
+    settle_yes(&mut state, &["alice"], &["bob"]);
+
+    // Backing both sides of the third market does not reach the milestone
+    settle_yes(&mut state, &["alice", "bob"], &["alice"]);
+    assert_eq!(streak(&state, "alice"), 0);
+    assert_eq!(streak(&state, "bob"), 1);
+    assert_eq!(state.treasury, 1_000);
+}
+
+#[test]
+fn several_bets_on_one_market_count_once() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    for _ in 0..3 {
+        bet(&mut state, "alice", market_id, true, 100).unwrap();
+    }
+    bet(&mut state, "bob", market_id, false, 100).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    assert_eq!(streak(&state, "alice"), 1);
+
+    // Collecting afterwards does not settle the market a second time
+    run(&mut state, &identity("alice"), MarketAction::ClaimWinnings { market_id }).unwrap_err();
+    run(&mut state, &identity("bob"), MarketAction::ClaimWinnings { market_id }).unwrap();
+    assert_eq!((streak(&state, "alice"), streak(&state, "bob")), (1, 0));
+}
+
+#[test]
+fn events_report_streak_milestones_with_their_bonus() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    state.treasury = 100;
+    settle_yes(&mut state, &["alice", "bob"], &["carol"]);
+    settle_yes(&mut state, &["alice", "bob"], &["carol"]);
+
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+    bet(&mut state, "bob", market_id, true, 100).unwrap();
+    bet(&mut state, "carol", market_id, false, 100).unwrap();
+    let before = state.clone();
+    run(&mut state, &identity("carol"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    let events = state.events_since(&before);
+    let milestones: Vec<&MarketEvent> =
+        events.iter().filter(|e| matches!(e, MarketEvent::StreakMilestone { .. })).collect();
+    assert_eq!(
+        milestones,
+        vec![
+            &MarketEvent::StreakMilestone { market_id, identity: identity("alice").0, streak: 3, bonus: 100 },
+            &MarketEvent::StreakMilestone { market_id, identity: identity("bob").0, streak: 3, bonus: 0 },
+        ]
+    );
+}
+
+#[test]
+fn get_user_stats_reports_the_streak() {
+    let mut state = with_users(&["alice", "bob"]);
+    settle_yes(&mut state, &["alice"], &["bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 50).unwrap();
+
+    let msg = run(&mut state, &identity("alice"), MarketAction::GetUserStats).unwrap();
+    assert_eq!(msg, format!("Balance: {}\nCurrent streak: 1\nBets: 2 (1 open)", INITIAL_BALANCE + 50));
+    let msg = run(&mut state, &identity("nobody"), MarketAction::GetUserStats).unwrap();
+    assert_eq!(msg, "Balance: 0\nCurrent streak: 0\nBets: 0 (0 open)");
+}
+
+// --------------------------------------------------------
+//     Leaderboard
+// --------------------------------------------------------
+
+fn leaderboard(state: &mut Contract1, limit: u32) -> String {
+    run(state, &identity("nobody"), MarketAction::GetLeaderboard { limit }).unwrap()
+}
+
+#[test]
+fn leaderboard_breaks_ties_by_identity() {
+    let mut state = with_users(&["carol", "bob", "alice", "dave"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "dave", market_id, true, 500).unwrap();
+    bet(&mut state, "carol", market_id, true, 100).unwrap();
+
+    assert_eq!(
+        leaderboard(&mut state, 10),
+        format!(
+            "Leaderboard:\n1. {}: 10000\n2. {}: 10000\n3. {}: 9900\n4. {}: 9500",
+            identity("alice").0,
+            identity("bob").0,
+            identity("carol").0,
+            identity("dave").0
+        )
+    );
+    assert_eq!(leaderboard(&mut state, 1), format!("Leaderboard:\n1. {}: 10000", identity("alice").0));
+}
+
+#[test]
+fn leaderboard_limit_is_clamped() {
+    let names: Vec<String> = (0..MAX_LEADERBOARD_LIMIT + 5).map(|i| format!("user{:03}", i)).collect();
+    let mut state = with_users(&names.iter().map(String::as_str).collect::<Vec<_>>());
+
+    let board = leaderboard(&mut state, u32::MAX);
+    assert_eq!(board.lines().count(), 1 + MAX_LEADERBOARD_LIMIT);
+    assert_eq!(leaderboard(&mut state, 0), "Leaderboard is empty");
+}
+
+#[test]
+fn leaderboard_of_an_empty_state() {
+    let mut state = Contract1::new();
+    assert_eq!(leaderboard(&mut state, 10), "Leaderboard is empty");
+    // Reading it registers nobody
+    assert!(state.users.is_empty());
+}
+
+// --------------------------------------------------------
+//     Comments
+// --------------------------------------------------------
+
+fn comment(state: &mut Contract1, name: &str, market_id: u64, text: &str) -> Result<String, String> {
+    run(state, &identity(name), MarketAction::AddComment { market_id, text: text.to_string() })
+}
+
+#[test]
+fn comments_are_stored_with_their_author() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+
+    assert_eq!(comment(&mut state, "bob", market_id, "  Saw clouds  ").unwrap(), "Comment #1 added to market #1");
+    let comments = &state.markets[&market_id].comments;
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].author, identity("bob"));
+    assert_eq!(comments[0].text, "Saw clouds");
+}
+
+#[test]
+fn comment_limits_are_enforced() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+
+    assert_eq!(comment(&mut state, "alice", market_id, "   ").unwrap_err(), "Comment is empty");
+    let too_long = "é".repeat(MAX_COMMENT_CHARS + 1);
+    assert_eq!(
+        comment(&mut state, "alice", market_id, &too_long).unwrap_err(),
+        format!("Comment is longer than {} characters", MAX_COMMENT_CHARS)
+    );
+    // The limit counts characters, not bytes
+    comment(&mut state, "alice", market_id, &"é".repeat(MAX_COMMENT_CHARS)).unwrap();
+
+    for _ in 1..MAX_COMMENTS_PER_MARKET {
+        comment(&mut state, "alice", market_id, "again").unwrap();
+    }
+    assert_eq!(
+        comment(&mut state, "alice", market_id, "one too many").unwrap_err(),
+        format!("Market #{} already has {} comments", market_id, MAX_COMMENTS_PER_MARKET)
+    );
+    assert_eq!(state.markets[&market_id].comments.len(), MAX_COMMENTS_PER_MARKET);
+}
+
+#[test]
+fn comments_need_an_open_market_and_an_initialized_user() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+
+    assert_eq!(comment(&mut state, "mallory", market_id, "hi").unwrap_err(), "User not initialized");
+    assert_eq!(comment(&mut state, "alice", 99, "hi").unwrap_err(), "Market not found");
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    assert_eq!(comment(&mut state, "alice", market_id, "late").unwrap_err(), "Market is not open");
+    assert!(state.markets[&market_id].comments.is_empty());
+}
+
+#[test]
+fn full_comment_log_stays_small() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    let before = state.commit().0.len();
+
+    let longest = "𝄞".repeat(MAX_COMMENT_CHARS);
+    for _ in 0..MAX_COMMENTS_PER_MARKET {
+        comment(&mut state, "alice", market_id, &longest).unwrap();
+    }
+    let growth = state.commit().0.len() - before;
+    assert!(growth < 14 * 1024, "a full comment log added {} bytes", growth);
+}
+
+// --------------------------------------------------------
+//     Admin and treasury
+// --------------------------------------------------------
+
+fn with_treasury(amount: u128) -> Contract1 {
+    let mut state = with_users(&["alice", "bob"]);
+    state.treasury = amount;
+    state
+}
+
+fn withdraw(state: &mut Contract1, caller: &str, to: &str, amount: u128) -> Result<String, String> {
+    run(state, &identity(caller), MarketAction::WithdrawTreasury { to: identity(to), amount })
+}
+
+#[test]
+fn first_set_admin_claims_the_role() {
+    let mut state = with_users(&["alice", "bob"]);
+    run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("alice") }).unwrap();
+    assert_eq!(state.admin, Some(identity("alice")));
+
+    let err = run(&mut state, &identity("bob"), MarketAction::SetAdmin { new_admin: identity("bob") }).unwrap_err();
+    assert_eq!(err, "Only the admin can do this");
+
+    run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("bob") }).unwrap();
+    assert_eq!(state.admin, Some(identity("bob")));
+}
+
+#[test]
+fn admin_withdraws_from_treasury() {
+    let mut state = with_treasury(300);
+    state.admin = Some(identity("alice"));
+
+    let msg = withdraw(&mut state, "alice", "bob", 120).unwrap();
+    assert_eq!(msg, format!("Withdrew 120 from the treasury to {}", identity("bob").0));
+    assert_eq!(state.treasury, 180);
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE + 120);
+}
+
+#[test]
+fn treasury_withdrawal_is_checked() {
+    let mut state = with_treasury(300);
+    assert_eq!(withdraw(&mut state, "alice", "alice", 10).unwrap_err(), "No admin set");
+
+    state.admin = Some(identity("alice"));
+    assert_eq!(withdraw(&mut state, "bob", "bob", 10).unwrap_err(), "Only the admin can do this");
+    assert_eq!(
+        withdraw(&mut state, "alice", "alice", 301).unwrap_err(),
+        "Insufficient treasury. Have: 300, Need: 301"
+    );
+    assert_eq!(withdraw(&mut state, "alice", "nobody", 10).unwrap_err(), "Recipient not initialized");
+    assert!(withdraw(&mut state, "alice", "", 10).is_err());
+
+    assert_eq!(state.treasury, 300);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+}
+
+#[test]
+fn get_treasury_is_public() {
+    let mut state = with_treasury(42);
+    let msg = run(&mut state, &identity("nobody"), MarketAction::GetTreasury).unwrap();
+    assert_eq!(msg, "Treasury: 42\nBurned: 0\nDividend pool: 0");
+}
+
+#[test]
+fn reset_balances_starts_a_new_season() {
+    let mut state = with_treasury(0);
+    state.admin = Some(identity("alice"));
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "bob", market_id, true, 400).unwrap();
+    // Known but never initialized: stays at zero
+    state.users.insert(identity("mallory"), UserState::default());
+
+    let err = run(&mut state, &identity("alice"), MarketAction::ResetBalances).unwrap_err();
+    assert_eq!(err, "1 market(s) are still open, resolve them before resetting balances");
+    let err = run(&mut state, &identity("bob"), MarketAction::ResetBalances).unwrap_err();
+    assert_eq!(err, "Only the admin can do this");
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: false }).unwrap();
+    let msg = run(&mut state, &identity("alice"), MarketAction::ResetBalances).unwrap();
+    assert_eq!(msg, format!("Reset 2 balances to {}", INITIAL_BALANCE));
+    assert_eq!((balance(&state, "alice"), balance(&state, "bob")), (INITIAL_BALANCE, INITIAL_BALANCE));
+    assert_eq!(balance(&state, "mallory"), 0);
+    // The season's history stays on-chain
+    assert_eq!(state.users[&identity("bob")].bets.len(), 1);
+}
+
+// --------------------------------------------------------
+//     Queries
+// --------------------------------------------------------
+
+#[test]
+fn get_balance_reports_balance() {
+    let mut state = with_users(&["alice"]);
+    let msg = run(&mut state, &identity("alice"), MarketAction::GetBalance).unwrap();
+    assert_eq!(msg, format!("Balance: {}", INITIAL_BALANCE));
+}
+
+#[test]
+fn get_balance_of_unknown_user_is_zero() {
+    let mut state = Contract1::new();
+    let msg = run(&mut state, &identity("nobody"), MarketAction::GetBalance).unwrap();
+    assert_eq!(msg, "Balance: 0");
+    assert!(state.users.is_empty());
+}
+
+#[test]
+fn user_info_of_unknown_user_is_the_default() {
+    let state = with_users(&["alice"]);
+    let info = state.user_info(&identity("nobody"));
+    assert_eq!(info.identity, identity("nobody").0);
+    assert_eq!(info.balance, 0);
+    assert!(!info.initialized);
+    assert!(info.bets.is_empty());
+    assert!(!state.users.contains_key(&identity("nobody")));
+}
+
+#[test]
+fn user_info_reports_balance_and_bets() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 300).unwrap();
+
+    let info = state.user_info(&identity("alice"));
+    assert!(info.initialized);
+    assert_eq!(info.balance, INITIAL_BALANCE - 300);
+    assert_eq!(info.bets.len(), 1);
+    assert_eq!(info.bets[0].market_status, Some(MarketStatus::Open));
+}
+
+#[test]
+fn events_since_reports_new_bets_and_resolutions() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 300).unwrap();
+    let before = state.clone();
+
+    bet(&mut state, "bob", market_id, false, 100).unwrap();
+    assert_eq!(
+        state.events_since(&before),
+        vec![MarketEvent::BetPlaced { market_id, bettor: identity("bob").0, side: false, amount: 100 }]
+    );
+
+    let before = state.clone();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    assert_eq!(
+        state.events_since(&before),
+        vec![MarketEvent::MarketResolved {
+            market_id,
+            description: "Will it rain tomorrow?".to_string(),
+            outcome: true,
+            yes_pool: 300,
+            no_pool: 100,
+        }]
+    );
+
+    // Market creation is not announced
+    let before = state.clone();
+    create_market(&mut state, "bob");
+    assert!(state.events_since(&before).is_empty());
+}
+
+#[test]
+fn snapshot_lists_open_markets_resolutions_and_volume() {
+    let mut state = with_users(&["alice", "bob"]);
+    let resolved = create_market(&mut state, "alice");
+    bet(&mut state, "alice", resolved, true, 300).unwrap();
+    bet(&mut state, "bob", resolved, false, 100).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: resolved, outcome: false }).unwrap();
+    let open = create_market(&mut state, "bob");
+    bet(&mut state, "bob", open, true, 250).unwrap();
+    bet(&mut state, "alice", open, false, 750).unwrap();
+
+    let snapshot = state.snapshot();
+
+    assert_eq!(
+        snapshot.open_markets,
+        vec![SnapshotMarket {
+            id: open,
+            description: "Will it rain tomorrow?".to_string(),
+            yes_pool: 250,
+            no_pool: 750,
+            yes_probability_bps: 2_500,
+            no_probability_bps: 7_500,
+        }]
+    );
+    assert_eq!(snapshot.open_market_count, 1);
+    assert_eq!(
+        snapshot.recent_resolutions,
+        vec![SnapshotResolution { id: resolved, description: "Will it rain tomorrow?".to_string(), outcome: false, total_pool: 400 }]
+    );
+    assert_eq!(snapshot.leaderboard, state.leaderboard(SNAPSHOT_LEADERBOARD));
+    assert_eq!(snapshot.total_volume, 1_400);
+}
+
+#[test]
+fn snapshot_is_capped() {
+    let mut state = with_users(&["alice"]);
+    for _ in 0..SNAPSHOT_OPEN_MARKETS + 5 {
+        create_market(&mut state, "alice");
+    }
+
+    let snapshot = state.snapshot();
+
+    assert_eq!(snapshot.open_markets.len(), SNAPSHOT_OPEN_MARKETS);
+    assert_eq!(snapshot.open_market_count, SNAPSHOT_OPEN_MARKETS + 5);
+    // Newest first
+    assert_eq!(snapshot.open_markets[0].id, state.next_market_id);
+}
+
+#[test]
+fn get_market_info_reports_pools() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, false, 40).unwrap();
+
+    let msg = run(&mut state, &identity("alice"), MarketAction::GetMarketInfo { market_id }).unwrap();
+    assert!(msg.contains("Status: Open"), "{}", msg);
+    assert!(msg.contains("NO pool: 40"), "{}", msg);
+    assert!(!msg.contains("Comments"), "{}", msg);
+
+    for text in ["first", "second", "third", "fourth"] {
+        comment(&mut state, "alice", market_id, text).unwrap();
+    }
+    let msg = run(&mut state, &identity("alice"), MarketAction::GetMarketInfo { market_id }).unwrap();
+    assert!(msg.contains("Comments (4):"), "{}", msg);
+    assert!(!msg.contains("first"), "{}", msg);
+    assert!(msg.ends_with(&format!("- {}: fourth", identity("alice").0)), "{}", msg);
+
+    let err = run(&mut state, &identity("alice"), MarketAction::GetMarketInfo { market_id: 99 }).unwrap_err();
+    assert_eq!(err, "Market not found");
+}
+
+// --------------------------------------------------------
+//     Scheduled markets
+// --------------------------------------------------------
+
+/// Opening instant of the scheduled markets below, in unix seconds
+const OPENS_AT: u64 = 1_750_000_000;
+
+fn scheduled_market(state: &mut Contract1, creator: &str) -> u64 {
+    run(
+        state,
+        &identity(creator),
+        MarketAction::CreateMarket {
+            description: "Who wins the final?".to_string(),
+            opens_at: Some(OPENS_AT),
+            stake_cap: None,
+            tags: vec![],
+            challenge: None,
+        },
+    )
+    .expect("create scheduled market");
+    state.next_market_id
+}
+
+fn bet_at(state: &mut Contract1, name: &str, market_id: u64, timestamp_ms: u128) -> Result<String, String> {
+    let action = MarketAction::PlaceBet { market_id, side: true, amount: 100 };
+    run_at(state, &identity(name), action, timestamp_ms)
+}
+
+#[test]
+fn scheduled_market_refuses_bets_until_it_opens() {
+    let mut state = with_users(&["alice"]);
+    let market_id = scheduled_market(&mut state, "alice");
+    let opens_at_ms = OPENS_AT as u128 * 1000;
+
+    // The last millisecond before the opening still falls in the previous second
+    let err = bet_at(&mut state, "alice", market_id, opens_at_ms - 1).unwrap_err();
+    assert_eq!(err, format!("Market #{} opens for betting at {}", market_id, OPENS_AT));
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+
+    bet_at(&mut state, "alice", market_id, opens_at_ms).unwrap();
+    assert_eq!(state.markets[&market_id].yes_pool, 100);
+    assert_eq!(state.markets[&market_id].status, MarketStatus::Open);
+}
+
+#[test]
+fn scheduled_market_refuses_bets_without_a_block_time() {
+    let mut state = with_users(&["alice"]);
+    let market_id = scheduled_market(&mut state, "alice");
+
+    let err = bet(&mut state, "alice", market_id, true, 100).unwrap_err();
+    assert!(err.contains("opens for betting"), "{}", err);
+
+    // Markets created without an opening time never need one
+    let open = create_market(&mut state, "alice");
+    bet(&mut state, "alice", open, true, 100).unwrap();
+}
+
+#[test]
+fn scheduled_market_is_presented_as_scheduled_until_it_opens() {
+    let mut state = with_users(&["alice"]);
+    let market_id = scheduled_market(&mut state, "alice");
+    let info = |state: &mut Contract1, timestamp_ms: u128| {
+        run_at(state, &identity("alice"), MarketAction::GetMarketInfo { market_id }, timestamp_ms).unwrap()
+    };
+
+    let msg = info(&mut state, (OPENS_AT as u128 - 1) * 1000);
+    assert!(msg.contains(&format!("Status: Scheduled (opens at {})", OPENS_AT)), "{}", msg);
+    let msg = info(&mut state, OPENS_AT as u128 * 1000);
+    assert!(msg.contains("Status: Open"), "{}", msg);
+
+    let listed = state.list_markets(&MarketFilter::default(), OPENS_AT - 1);
+    assert_eq!(listed[0].opens_at, Some(OPENS_AT));
+    assert!(listed[0].scheduled);
+    assert!(!state.list_markets(&MarketFilter::default(), OPENS_AT)[0].scheduled);
+
+    // Resolution ends the schedule too
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: false }).unwrap();
+    assert!(!state.list_markets(&MarketFilter::default(), OPENS_AT - 1)[0].scheduled);
+}
+
+// --------------------------------------------------------
+//     Stake caps
+// --------------------------------------------------------
+
+fn capped_market(state: &mut Contract1, creator: &str, stake_cap: StakeCap) -> Result<u64, String> {
+    let action = MarketAction::CreateMarket {
+        description: "Will the whale win?".to_string(),
+        opens_at: None,
+        stake_cap: Some(stake_cap),
+        tags: vec![],
+        challenge: None,
+    };
+    run(state, &identity(creator), action)?;
+    Ok(state.next_market_id)
+}
+
+/// The allowed stake stated by a stake cap rejection
+fn allowed(result: Result<String, String>) -> Option<u128> {
+    MarketError::stake_cap_allowance(&result.unwrap_err())
+}
+
+#[test]
+fn absolute_stake_cap_bounds_each_users_side() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = capped_market(&mut state, "alice", StakeCap::Absolute(500)).unwrap();
+
+    bet(&mut state, "alice", market_id, true, 300).unwrap();
+    let err = bet(&mut state, "alice", market_id, true, 300).unwrap_err();
+    assert_eq!(err, format!("Bet exceeds the stake cap, you can add at most 200 on market #{}", market_id));
+    bet(&mut state, "alice", market_id, true, 200).unwrap();
+
+    // The cap is per user and per side
+    bet(&mut state, "alice", market_id, false, 500).unwrap();
+    bet(&mut state, "bob", market_id, true, 500).unwrap();
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 1_000);
+}
+
+#[test]
+fn opposite_pool_stake_cap_follows_the_pools() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    let market_id = capped_market(&mut state, "alice", StakeCap::OppositePoolBps(5_000)).unwrap();
+
+    // Nothing to take over while the other side is empty
+    bet(&mut state, "alice", market_id, true, 1_000).unwrap();
+
+    assert_eq!(allowed(bet(&mut state, "bob", market_id, false, 600)), Some(500));
+    bet(&mut state, "bob", market_id, false, 500).unwrap();
+
+    assert_eq!(allowed(bet(&mut state, "carol", market_id, true, 300)), Some(250));
+    bet(&mut state, "carol", market_id, true, 250).unwrap();
+
+    // YES grew to 1,250: bob's cap rose from 500 to 625
+    bet(&mut state, "bob", market_id, false, 125).unwrap();
+    assert_eq!(allowed(bet(&mut state, "bob", market_id, false, 1)), Some(0));
+    // alice's 1,000 are far over half of NO's 625, she cannot add more
+    assert_eq!(allowed(bet(&mut state, "alice", market_id, true, 1)), Some(0));
+
+    let msg = run(&mut state, &identity("alice"), MarketAction::GetMarketInfo { market_id }).unwrap();
+    assert!(msg.contains("Stake cap: 50.00% of the opposite pool per user"), "{}", msg);
+}
+
+#[test]
+fn zero_stake_caps_are_refused() {
+    let mut state = with_users(&["alice"]);
+    for cap in [StakeCap::Absolute(0), StakeCap::OppositePoolBps(0)] {
+        assert_eq!(capped_market(&mut state, "alice", cap).unwrap_err(), "Stake cap must be positive");
+    }
+    assert!(state.markets.is_empty());
+}
+
+// --------------------------------------------------------
+//     Closing betting
+// --------------------------------------------------------
+
+fn close(state: &mut Contract1, name: &str, market_id: u64) -> Result<String, String> {
+    run(state, &identity(name), MarketAction::CloseBetting { market_id })
+}
+
+#[test]
+fn closed_market_refuses_bets_but_still_resolves() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "bob", market_id, true, 100).unwrap();
+
+    assert_eq!(close(&mut state, "alice", market_id).unwrap(), format!("Betting closed on market #{}", market_id));
+    assert!(state.markets[&market_id].betting_closed);
+    assert_eq!(bet(&mut state, "bob", market_id, true, 100).unwrap_err(), "Market is not open for betting");
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE);
+}
+
+#[test]
+fn closing_again_succeeds_without_change() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    close(&mut state, "alice", market_id).unwrap();
+
+    assert_eq!(
+        close(&mut state, "alice", market_id).unwrap(),
+        format!("Betting on market #{} is already closed", market_id)
+    );
+}
+
+#[test]
+fn only_the_creator_or_admin_closes_betting() {
+    let mut state = with_users(&["alice", "bob", "admin"]);
+    let market_id = create_market(&mut state, "alice");
+
+    assert_eq!(close(&mut state, "bob", market_id).unwrap_err(), "Only the admin can do this");
+    assert!(!state.markets[&market_id].betting_closed);
+
+    state.admin = Some(identity("admin"));
+    close(&mut state, "admin", market_id).unwrap();
+    assert_eq!(close(&mut state, "alice", 99).unwrap_err(), "Market not found");
+}
+
+#[test]
+fn resolved_market_cannot_be_closed() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: false }).unwrap();
+
+    assert_eq!(close(&mut state, "alice", market_id).unwrap_err(), "Market is not open");
+}
+
+// --------------------------------------------------------
+//     Tags
+// --------------------------------------------------------
+
+fn tagged_market(state: &mut Contract1, tags: &[&str]) -> Result<u64, String> {
+    let action = MarketAction::CreateMarket {
+        description: "Pizza on Friday?".to_string(),
+        opens_at: None,
+        stake_cap: None,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        challenge: None,
+    };
+    run(state, &identity("alice"), action)?;
+    Ok(state.next_market_id)
+}
+
+fn tagged(state: &Contract1, tag: &str) -> Vec<u64> {
+    let filter = MarketFilter { tag: Some(tag.to_string()), ..MarketFilter::default() };
+    state.list_markets(&filter, 0).iter().map(|market| market.id).collect()
+}
+
+#[test]
+fn tags_are_lowercased_and_deduplicated() {
+    let mut state = with_users(&["alice"]);
+    let market_id = tagged_market(&mut state, &["Food", "#WORK", " food "]).unwrap();
+
+    assert_eq!(state.markets[&market_id].tags, vec!["food", "work"]);
+    assert_eq!(state.list_markets(&MarketFilter::default(), 0)[0].tags, vec!["food", "work"]);
+}
+
+#[test]
+fn tag_count_and_length_are_capped() {
+    let mut state = with_users(&["alice"]);
+    let longest = "x".repeat(MAX_TAG_CHARS);
+    let too_long = "x".repeat(MAX_TAG_CHARS + 1);
+    let five = ["a", "b", "c", "d", "e"];
+    assert_eq!(five.len(), MAX_MARKET_TAGS);
+
+    tagged_market(&mut state, &five).unwrap();
+    tagged_market(&mut state, &[&longest]).unwrap();
+    // Duplicates only count once
+    tagged_market(&mut state, &["a", "b", "c", "d", "e", "A"]).unwrap();
+    let created = state.next_market_id;
+
+    let too_many = tagged_market(&mut state, &["a", "b", "c", "d", "e", "f"]).unwrap_err();
+    assert_eq!(too_many, format!("A market can have at most {} tags", MAX_MARKET_TAGS));
+    let invalid = format!("Tags must be 1 to {} characters, without spaces", MAX_TAG_CHARS);
+    for tag in [too_long.as_str(), "", "#", "two words"] {
+        assert_eq!(tagged_market(&mut state, &[tag]).unwrap_err(), invalid, "{:?}", tag);
+    }
+    assert_eq!(state.next_market_id, created);
+}
+
+#[test]
+fn markets_are_listed_by_tag() {
+    let mut state = with_users(&["alice"]);
+    let pizza = tagged_market(&mut state, &["food"]).unwrap();
+    let derby = tagged_market(&mut state, &["sports", "work"]).unwrap();
+    let lunch = tagged_market(&mut state, &["work", "food"]).unwrap();
+    let untagged = create_market(&mut state, "alice");
+
+    assert_eq!(tagged(&state, "food"), vec![lunch, pizza]);
+    assert_eq!(tagged(&state, "#Work"), vec![lunch, derby]);
+    assert!(tagged(&state, "music").is_empty());
+    assert_eq!(state.list_markets(&MarketFilter::default(), 0).len(), 4);
+    assert!(!tagged(&state, "food").contains(&untagged));
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: pizza, outcome: true }).unwrap();
+    let open_food = MarketFilter { status: Some(MarketStatusFilter::Open), tag: Some("food".to_string()) };
+    let listed: Vec<u64> = state.list_markets(&open_food, 0).iter().map(|market| market.id).collect();
+    assert_eq!(listed, vec![lunch]);
+}
+
+// --------------------------------------------------------
+//     Market history
+// --------------------------------------------------------
+
+fn history(state: &mut Contract1, market_id: u64) -> Result<String, String> {
+    run(state, &identity("alice"), MarketAction::GetMarketHistory { market_id })
+}
+
+#[test]
+fn history_records_each_bet_with_the_running_pools() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    assert_eq!(history(&mut state, market_id).unwrap(), format!("Market #{} has no bets yet", market_id));
+
+    run_at(&mut state, &identity("alice"), MarketAction::PlaceBet { market_id, side: true, amount: 300 }, 1_700_000_000_000).unwrap();
+    bet(&mut state, "bob", market_id, false, 100).unwrap();
+
+    let entries = &state.markets[&market_id].history;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].bettor, identity("alice"));
+    assert_eq!(entries[0].timestamp, Some(1_700_000_000));
+    assert_eq!((entries[0].yes_pool, entries[0].no_pool), (300, 0));
+    assert_eq!(entries[1].timestamp, None);
+    assert_eq!((entries[1].yes_pool, entries[1].no_pool), (300, 100));
+
+    assert_eq!(
+        history(&mut state, market_id).unwrap(),
+        format!(
+            "Market #{} history (2 bets):\n1. {} YES 300 at 1700000000 -> YES 300 / NO 0\n2. {} NO 100 -> YES 300 / NO 100",
+            market_id,
+            identity("alice").0,
+            identity("bob").0
+        )
+    );
+    assert_eq!(history(&mut state, 99).unwrap_err(), "Market not found");
+}
+
+#[test]
+fn rejected_bets_are_not_recorded() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, INITIAL_BALANCE + 1).unwrap_err();
+
+    assert!(state.markets[&market_id].history.is_empty());
+}
+
+#[test]
+fn history_drops_the_oldest_bets_past_the_cap() {
+    let mut state = with_users(&["alice"]);
+    let market_id = create_market(&mut state, "alice");
+
+    for placed in 1..=MAX_MARKET_HISTORY + 5 {
+        bet(&mut state, "alice", market_id, true, 1).unwrap();
+        assert_eq!(state.markets[&market_id].history.len(), placed.min(MAX_MARKET_HISTORY));
+    }
+
+    // The first five bets were evicted, in the order they were placed
+    let entries = &state.markets[&market_id].history;
+    assert_eq!(entries.first().unwrap().yes_pool, 6);
+    assert_eq!(entries.last().unwrap().yes_pool, MAX_MARKET_HISTORY as u128 + 5);
+    assert!(entries.windows(2).all(|pair| pair[0].yes_pool + 1 == pair[1].yes_pool));
+}
+
+// --------------------------------------------------------
+//     Reconciliation
+// --------------------------------------------------------
+
+/// A snapshot agreeing with a state where alice bet 100 YES and bob 50 NO on
+/// the market returned.
+fn reconciled_state() -> (Contract1, u64, ReconcileSnapshot) {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+    bet(&mut state, "bob", market_id, false, 50).unwrap();
+    let snapshot = ReconcileSnapshot {
+        balances: vec![
+            LedgerBalance { identity: identity("alice").0, balance: INITIAL_BALANCE - 100 },
+            LedgerBalance { identity: identity("bob").0, balance: INITIAL_BALANCE - 50 },
+        ],
+        open_markets: vec![LedgerMarket { market_id, yes_pool: 100, no_pool: 50 }],
+    };
+    (state, market_id, snapshot)
+}
+
+#[test]
+fn matching_snapshot_reconciles_cleanly() {
+    let (state, _, snapshot) = reconciled_state();
+
+    let report = state.reconcile(&snapshot);
+
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(report.totals.users, 2);
+    assert_eq!(report.totals.ledger_balance, report.totals.on_chain_balance);
+    assert_eq!((report.totals.ledger_open_markets, report.totals.on_chain_open_markets), (1, 1));
+}
+
+#[test]
+fn reconcile_reports_balance_drift_and_unknown_identities() {
+    let (state, _, mut snapshot) = reconciled_state();
+    snapshot.balances[1].balance += 7;
+    snapshot.balances.push(LedgerBalance { identity: identity("carol").0, balance: 500 });
+
+    let report = state.reconcile(&snapshot);
+
+    assert_eq!(
+        report.balances,
+        vec![
+            BalanceDrift { identity: identity("bob").0, ledger: INITIAL_BALANCE - 43, on_chain: Some(INITIAL_BALANCE - 50) },
+            BalanceDrift { identity: identity("carol").0, ledger: 500, on_chain: None },
+        ]
+    );
+    assert_eq!(report.totals.ledger_balance, report.totals.on_chain_balance + 507);
+}
+
+#[test]
+fn reconcile_reports_markets_open_on_one_side_only() {
+    let (mut state, market_id, mut snapshot) = reconciled_state();
+    // Resolved on-chain, but the ledger missed it
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    // Created on-chain, but the ledger never recorded it
+    let unrecorded = create_market(&mut state, "bob");
+    snapshot.open_markets.push(LedgerMarket { market_id: 42, yes_pool: 0, no_pool: 0 });
+
+    let report = state.reconcile(&snapshot);
+
+    assert_eq!(report.open_only_in_ledger, vec![market_id, 42]);
+    assert_eq!(report.open_only_on_chain, vec![unrecorded]);
+    assert!(report.pools.is_empty());
+}
+
+#[test]
+fn reconcile_reports_pool_drift() {
+    let (state, market_id, mut snapshot) = reconciled_state();
+    snapshot.open_markets[0].no_pool = 80;
+
+    let report = state.reconcile(&snapshot);
+
+    assert_eq!(report.pools, vec![PoolDrift { market_id, ledger: (100, 80), on_chain: (100, 50) }]);
+    assert!(report.open_only_in_ledger.is_empty() && report.open_only_on_chain.is_empty());
+}
+
+// --------------------------------------------------------
+//     State epochs
+// --------------------------------------------------------
+
+#[test]
+fn a_redeployed_contract_has_a_new_epoch_and_restarts_market_ids() {
+    let mut first = Contract1::with_epoch(1_700_000_000);
+    for name in ["alice", "bob"] {
+        run(&mut first, &identity(name), MarketAction::Initialize { idempotent: false }).unwrap();
+    }
+    create_market(&mut first, "alice");
+    create_market(&mut first, "alice");
+
+    let mut redeployed = Contract1::with_epoch(1_800_000_000);
+    run(&mut redeployed, &identity("alice"), MarketAction::Initialize { idempotent: false }).unwrap();
+
+    assert_eq!(create_market(&mut redeployed, "alice"), 1);
+    assert_ne!(redeployed.state_epoch, first.state_epoch);
+    let decoded = Contract1::try_from(redeployed.commit()).unwrap();
+    assert_eq!(decoded.state_epoch, 1_800_000_000);
+}
+
+// --------------------------------------------------------
+//     State stats
+// --------------------------------------------------------
+
+#[test]
+fn state_stats_count_a_decoded_state() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    let busy = create_market(&mut state, "alice");
+    let resolved = create_market(&mut state, "alice");
+    create_market(&mut state, "bob");
+    bet(&mut state, "alice", busy, true, 100).unwrap();
+    bet(&mut state, "bob", busy, false, 50).unwrap();
+    // Carol backs both sides but is one bettor
+    bet(&mut state, "carol", busy, true, 10).unwrap();
+    bet(&mut state, "carol", busy, false, 5).unwrap();
+    bet(&mut state, "bob", resolved, true, 30).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: resolved, outcome: false }).unwrap();
+
+    let commitment = state.commit();
+    let stats = Contract1::try_from(commitment.clone()).unwrap().state_stats();
+
+    assert_eq!(stats.state_bytes, commitment.0.len() as u64);
+    assert_eq!(stats.users, 3);
+    assert_eq!(stats.markets, MarketCounts { open: 2, resolved_no: 1, ..MarketCounts::default() });
+    assert_eq!(stats.parlays, 0);
+    assert_eq!(stats.open_pool, 165);
+    assert_eq!(stats.largest_market, Some(LargestMarket { market_id: busy, bettors: 3 }));
+    assert_eq!(stats.treasury, state.treasury);
+    assert_eq!(stats.burned, 0);
+    assert_eq!(Contract1::new().state_stats().largest_market, None);
+}
+
+// --------------------------------------------------------
+//     Snapshots
+// --------------------------------------------------------
+
+/// Alice is the admin, bob bet on the open market 1 and won market 2.
+fn backed_up_state() -> Contract1 {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("alice") }).unwrap();
+    let open = create_market(&mut state, "alice");
+    let resolved = create_market(&mut state, "alice");
+    bet(&mut state, "bob", open, true, 100).unwrap();
+    bet(&mut state, "bob", resolved, false, 40).unwrap();
+    bet(&mut state, "carol", resolved, true, 60).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: resolved, outcome: false }).unwrap();
+    state
+}
+
+#[test]
+fn only_the_admin_exports_snapshots() {
+    let mut state = backed_up_state();
+    let before = state.commit();
+
+    let message = run(&mut state, &identity("alice"), MarketAction::ExportSnapshot).unwrap();
+    assert_eq!(message, "Snapshot of 3 users and 2 markets at epoch 0");
+    let err = run(&mut state, &identity("bob"), MarketAction::ExportSnapshot).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    assert_eq!(state.commit(), before);
+    assert!(MarketAction::ExportSnapshot.is_read_only());
+}
+
+#[test]
+fn imported_snapshots_restore_the_whole_state() {
+    let original = backed_up_state();
+    let mut restored = Contract1::new_with_admin(identity("alice"));
+
+    let data = original.commit().0;
+    let message = run(&mut restored, &identity("alice"), MarketAction::ImportSnapshot { data }).unwrap();
+
+    assert_eq!(message, "Imported 3 users and 2 markets");
+    assert_eq!(restored.commit(), original.commit());
+    assert_eq!(restored.next_market_id, 2);
+    assert_eq!(restored.admin, Some(identity("alice")));
+    // Nothing happened on the restored contract itself
+    assert!(restored.events_since(&Contract1::new_with_admin(identity("alice"))).is_empty());
+    // The restored state keeps working: bob's bet is still live
+    run(&mut restored, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true }).unwrap();
+    assert_eq!(balance(&restored, "bob"), INITIAL_BALANCE + 60);
+}
+
+#[test]
+fn snapshots_only_import_into_an_empty_state() {
+    let data = backed_up_state().commit().0;
+
+    let mut used = with_users(&["dave"]);
+    let err = run(&mut used, &identity("dave"), MarketAction::ImportSnapshot { data: data.clone() }).unwrap_err();
+    assert_eq!(err, MarketError::StateNotEmpty { users: 1, markets: 0 }.to_string());
+
+    // A fresh deployment with an admin only takes snapshots from it, and keeps it
+    let mut deployed = Contract1::new_with_admin(identity("erin"));
+    let err = run(&mut deployed, &identity("alice"), MarketAction::ImportSnapshot { data: data.clone() }).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    let err = run(&mut deployed, &identity("erin"), MarketAction::ImportSnapshot { data: vec![1, 2, 3] }).unwrap_err();
+    assert_eq!(err, MarketError::InvalidSnapshot.to_string());
+    run(&mut deployed, &identity("erin"), MarketAction::ImportSnapshot { data }).unwrap();
+    assert_eq!(deployed.admin, Some(identity("erin")));
+    assert_eq!(deployed.users.len(), 3);
+}
+
+#[test]
+fn snapshots_need_an_admin_to_import() {
+    let data = backed_up_state().commit().0;
+
+    // Without an admin, anyone could otherwise overwrite the whole state
+    let mut fresh = Contract1::new();
+    let err = run(&mut fresh, &identity("mallory"), MarketAction::ImportSnapshot { data: data.clone() }).unwrap_err();
+    assert_eq!(err, MarketError::NoAdmin.to_string());
+    assert_eq!(fresh.commit(), Contract1::new().commit());
+
+    run(&mut fresh, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("alice") }).unwrap();
+    let err = run(&mut fresh, &identity("mallory"), MarketAction::ImportSnapshot { data: data.clone() }).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    run(&mut fresh, &identity("alice"), MarketAction::ImportSnapshot { data }).unwrap();
+    assert_eq!(fresh.users.len(), 3);
+}
+
+// --------------------------------------------------------
+//     Challenges
+// --------------------------------------------------------
+
+/// Block time of the challenges below, in milliseconds
+const CHALLENGED_AT_MS: u128 = 1_700_000_000_000;
+
+fn challenge(state: &mut Contract1, creator: &str, opponent: &str, stake: u128) -> Result<u64, String> {
+    let action = MarketAction::CreateMarket {
+        description: "Who finishes the marathon first?".to_string(),
+        opens_at: None,
+        stake_cap: None,
+        tags: vec![],
+        challenge: Some(Challenge { opponent: identity(opponent), stake }),
+    };
+    run_at(state, &identity(creator), action, CHALLENGED_AT_MS)?;
+    Ok(state.next_market_id)
+}
+
+#[test]
+fn challenge_escrows_the_stake_until_the_opponent_matches_it() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    let market_id = challenge(&mut state, "alice", "bob", 500).unwrap();
+    assert_eq!(state.markets[&market_id].status, MarketStatus::PendingAcceptance);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 500);
+    assert_eq!(total_funds(&state), 3 * INITIAL_BALANCE);
+
+    // Nobody bets on a challenge, and only the named opponent accepts it
+    let err = bet(&mut state, "carol", market_id, false, 500).unwrap_err();
+    assert_eq!(err, "Market is not open for betting");
+    let err = run(&mut state, &identity("carol"), MarketAction::AcceptChallenge { market_id }).unwrap_err();
+    assert_eq!(err, format!("Only the opponent named by challenge #{} can accept it", market_id));
+
+    let accepted = run(&mut state, &identity("bob"), MarketAction::AcceptChallenge { market_id }).unwrap();
+    assert_eq!(accepted, format!("Challenge #{} accepted, 1000 at stake", market_id));
+    let market = &state.markets[&market_id];
+    assert_eq!((market.status.clone(), market.yes_pool, market.no_pool), (MarketStatus::Open, 500, 500));
+    assert!(market.betting_closed);
+    assert!(bet(&mut state, "carol", market_id, true, 10).is_err());
+
+    // The winner takes the whole pot, once the stakes are a cooldown old
+    let resolvable_at_ms = CHALLENGED_AT_MS + RESOLUTION_COOLDOWN as u128 * 1_000;
+    run_at(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: false }, resolvable_at_ms)
+        .unwrap();
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE + 500);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 500);
+    assert_eq!(total_funds(&state), 3 * INITIAL_BALANCE);
+}
+
+#[test]
+fn invalid_challenges_are_rejected() {
+    let mut state = with_users(&["alice", "bob"]);
+    let invalid = MarketError::InvalidChallenge.to_string();
+
+    assert_eq!(challenge(&mut state, "alice", "alice", 500).unwrap_err(), invalid);
+    assert_eq!(challenge(&mut state, "alice", "bob", 0).unwrap_err(), invalid);
+    assert_eq!(
+        challenge(&mut state, "alice", "bob", INITIAL_BALANCE + 1).unwrap_err(),
+        format!("Insufficient balance. Have: {}, Need: {}", INITIAL_BALANCE, INITIAL_BALANCE + 1)
+    );
+    assert!(state.markets.is_empty());
+
+    // The opponent needs the stake too
+    let market_id = challenge(&mut state, "alice", "bob", 500).unwrap();
+    let other = create_market(&mut state, "bob");
+    bet(&mut state, "bob", other, true, INITIAL_BALANCE).unwrap();
+    let err = run(&mut state, &identity("bob"), MarketAction::AcceptChallenge { market_id }).unwrap_err();
+    assert_eq!(err, "Insufficient balance. Have: 0, Need: 500");
+}
+
+#[test]
+fn declined_challenge_refunds_its_creator() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    let market_id = challenge(&mut state, "alice", "bob", 500).unwrap();
+
+    let err = run(&mut state, &identity("carol"), MarketAction::CancelChallenge { market_id }).unwrap_err();
+    assert_eq!(err, MarketError::NotChallengeParty { market_id }.to_string());
+
+    let declined = run(&mut state, &identity("bob"), MarketAction::CancelChallenge { market_id }).unwrap();
+    assert_eq!(declined, format!("Challenge #{} cancelled, 500 refunded to alice@contract1", market_id));
+    assert_eq!(state.markets[&market_id].status, MarketStatus::Cancelled);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+    assert!(state.users[&identity("alice")].bets.is_empty());
+    assert_eq!(total_funds(&state), 3 * INITIAL_BALANCE);
+
+    for action in [MarketAction::AcceptChallenge { market_id }, MarketAction::CancelChallenge { market_id }] {
+        let err = run(&mut state, &identity("bob"), action).unwrap_err();
+        assert_eq!(err, format!("Market #{} is not a challenge waiting for its opponent", market_id));
+    }
+    let err = run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap_err();
+    assert_eq!(err, "Market is not open");
+    let resolved = MarketFilter { status: Some(MarketStatusFilter::Resolved), tag: None };
+    assert!(state.list_markets(&resolved, 0).is_empty());
+}
+
+#[test]
+fn expired_challenge_can_no_longer_be_accepted_and_anyone_cancels_it() {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    let market_id = challenge(&mut state, "alice", "bob", 500).unwrap();
+    let deadline_ms = CHALLENGED_AT_MS + CHALLENGE_ACCEPT_WINDOW as u128 * 1000;
+
+    let cancel = MarketAction::CancelChallenge { market_id };
+    let err = run_at(&mut state, &identity("carol"), cancel.clone(), deadline_ms).unwrap_err();
+    assert_eq!(err, MarketError::NotChallengeParty { market_id }.to_string());
+
+    let late = deadline_ms + 1000;
+    let err = run_at(&mut state, &identity("bob"), MarketAction::AcceptChallenge { market_id }, late).unwrap_err();
+    assert_eq!(err, format!("The challenge of market #{} expired", market_id));
+
+    run_at(&mut state, &identity("carol"), cancel, late).unwrap();
+    assert_eq!(state.markets[&market_id].status, MarketStatus::Cancelled);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+}
+
+#[test]
+fn pending_challenges_block_a_balance_reset() {
+    let mut state = with_users(&["alice", "bob"]);
+    run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("alice") }).unwrap();
+    let market_id = challenge(&mut state, "alice", "bob", 500).unwrap();
+
+    let err = run(&mut state, &identity("alice"), MarketAction::ResetBalances).unwrap_err();
+    assert_eq!(err, MarketError::MarketsStillOpen { open: 1 }.to_string());
+    let info = run(&mut state, &identity("bob"), MarketAction::GetMarketInfo { market_id }).unwrap();
+    assert!(info.contains("Status: Waiting for bob@contract1 to accept"), "{}", info);
+}
+
+// --------------------------------------------------------
+//     Market lifetime
+// --------------------------------------------------------
+
+const CREATED_AT_MS: u128 = 1_700_000_000_000;
+
+/// A market created at `CREATED_AT_MS`, whose lifetime is over at `expires_at`.
+fn dated_market(state: &mut Contract1, creator: &str) -> (u64, u64) {
+    let action = MarketAction::CreateMarket {
+        description: "Will it rain this year?".to_string(),
+        opens_at: None,
+        stake_cap: None,
+        tags: vec![],
+        challenge: None,
+    };
+    run_at(state, &identity(creator), action, CREATED_AT_MS).unwrap();
+    let expires_at = (CREATED_AT_MS / 1_000) as u64 + MAX_MARKET_LIFETIME;
+    (state.next_market_id, expires_at)
+}
+
+fn expire_at(state: &mut Contract1, market_id: u64, at_secs: u64) -> Result<String, String> {
+    run_at(state, &identity("carol"), MarketAction::ExpireMarket { market_id }, at_secs as u128 * 1_000)
+}
+
+#[test]
+fn markets_cannot_be_expired_before_their_lifetime_is_over() {
+    let mut state = with_users(&["alice", "carol"]);
+    let (market_id, expires_at) = dated_market(&mut state, "alice");
+    assert_eq!(state.markets[&market_id].expires_at(), Some(expires_at));
+
+    let err = expire_at(&mut state, market_id, expires_at - 1).unwrap_err();
+    assert_eq!(err, MarketError::MarketNotExpired { market_id, expires_at: Some(expires_at) }.to_string());
+    // Without a block time the lifetime cannot be proven over
+    let err = run(&mut state, &identity("carol"), MarketAction::ExpireMarket { market_id }).unwrap_err();
+    assert_eq!(err, format!("Market #{} cannot be expired before {}", market_id, expires_at));
+    // Nor without a creation time
+    let undated = create_market(&mut state, "alice");
+    let err = expire_at(&mut state, undated, expires_at).unwrap_err();
+    assert_eq!(err, format!("Market #{} has no creation time and cannot be expired", undated));
+
+    // Resolved markets stay resolved
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    assert_eq!(expire_at(&mut state, market_id, expires_at).unwrap_err(), "Market is not open");
+}
+
+#[test]
+fn expired_markets_refund_every_stake() {
+    let bettors: Vec<String> = (0..20).map(|i| format!("bettor{}", i)).collect();
+    let names: Vec<&str> = ["alice", "carol"].into_iter().chain(bettors.iter().map(String::as_str)).collect();
+    let mut state = with_users(&names);
+    let (market_id, expires_at) = dated_market(&mut state, "alice");
+    for (i, name) in bettors.iter().enumerate() {
+        bet(&mut state, name, market_id, i % 3 != 0, 10 + i as u128).unwrap();
+    }
+    // Both sides, and betting closed, count as well
+    bet(&mut state, "bettor1", market_id, false, 7).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::CloseBetting { market_id }).unwrap();
+    let funds = total_funds(&state);
+
+    let message = expire_at(&mut state, market_id, expires_at).unwrap();
+
+    assert_eq!(message, format!("Market #{} expired unresolved: refunded 397 to 20 bettors", market_id));
+    assert!(bettors.iter().all(|name| balance(&state, name) == INITIAL_BALANCE));
+    let market = &state.markets[&market_id];
+    assert_eq!(market.status, MarketStatus::Void);
+    assert_eq!(state.treasury, 0);
+    assert_eq!(total_funds(&state), funds);
+    assert!(state.users[&identity("bettor1")].bets.iter().all(|bet| bet.claimed));
+    assert!(state.get_market_info(market_id, None).unwrap().contains("Status: Void: expired unresolved, stakes refunded"));
+
+    // Voiding happens once, and neither resolution nor claims follow
+    assert_eq!(expire_at(&mut state, market_id, expires_at).unwrap_err(), "Market is not open");
+    let err = run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap_err();
+    assert_eq!(err, "Market is not open");
+    let err = run(&mut state, &identity("bettor1"), MarketAction::ClaimWinnings { market_id }).unwrap_err();
+    assert_eq!(err, MarketError::MarketNotResolved.to_string());
+}
+
+// --------------------------------------------------------
+//     Resolution cooldown
+// --------------------------------------------------------
+
+/// Unix seconds of alice's bet on the market of `cooldown_market`
+const LAST_BET_AT: u64 = 1_700_000_000;
+
+/// Alice's market with her bet of 100 on YES at `LAST_BET_AT`.
+fn cooldown_market() -> (Contract1, u64) {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet_at(&mut state, "alice", market_id, LAST_BET_AT as u128 * 1_000).unwrap();
+    (state, market_id)
+}
+
+fn resolve_at(state: &mut Contract1, market_id: u64, timestamp_ms: u128) -> Result<String, String> {
+    run_at(state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }, timestamp_ms)
+}
+
+#[test]
+fn markets_cannot_be_resolved_right_after_a_bet() {
+    let (mut state, market_id) = cooldown_market();
+    let resolvable_at = LAST_BET_AT + RESOLUTION_COOLDOWN;
+    assert_eq!(state.markets[&market_id].resolvable_at(), Some(resolvable_at));
+
+    // The last millisecond of the cooldown still falls in its last second
+    let err = resolve_at(&mut state, market_id, resolvable_at as u128 * 1_000 - 1).unwrap_err();
+    assert_eq!(err, MarketError::ResolutionTooEarly { market_id, resolvable_at }.to_string());
+    assert_eq!(MarketError::resolution_opens_at(&err), Some(resolvable_at));
+    // Without a block time the cooldown cannot be proven over
+    let err = run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap_err();
+    assert_eq!(MarketError::resolution_opens_at(&err), Some(resolvable_at));
+    assert_eq!(state.markets[&market_id].status, MarketStatus::Open);
+
+    resolve_at(&mut state, market_id, resolvable_at as u128 * 1_000).unwrap();
+    assert_eq!(state.markets[&market_id].status, MarketStatus::ResolvedYes);
+}
+
+#[test]
+fn each_bet_restarts_the_cooldown() {
+    let (mut state, market_id) = cooldown_market();
+    let later = LAST_BET_AT + RESOLUTION_COOLDOWN - 1;
+    bet_at(&mut state, "bob", market_id, later as u128 * 1_000).unwrap();
+
+    let err = resolve_at(&mut state, market_id, (LAST_BET_AT + RESOLUTION_COOLDOWN) as u128 * 1_000).unwrap_err();
+    assert_eq!(MarketError::resolution_opens_at(&err), Some(later + RESOLUTION_COOLDOWN));
+    // Bets without a block time leave the latest dated one in place
+    bet(&mut state, "bob", market_id, true, 10).unwrap();
+    assert_eq!(state.markets[&market_id].last_bet_at, Some(later));
+    resolve_at(&mut state, market_id, (later + RESOLUTION_COOLDOWN) as u128 * 1_000).unwrap();
+}
+
+#[test]
+fn closing_betting_right_after_a_bet_keeps_the_cooldown() {
+    let (mut state, market_id) = cooldown_market();
+    let resolvable_at = LAST_BET_AT + RESOLUTION_COOLDOWN;
+    // Bet, close and resolve in one breath
+    run_at(&mut state, &identity("alice"), MarketAction::CloseBetting { market_id }, LAST_BET_AT as u128 * 1_000).unwrap();
+    assert_eq!(state.markets[&market_id].resolvable_at(), Some(resolvable_at));
+
+    let err = resolve_at(&mut state, market_id, LAST_BET_AT as u128 * 1_000).unwrap_err();
+    assert_eq!(err, MarketError::ResolutionTooEarly { market_id, resolvable_at }.to_string());
+    let err = resolve_at(&mut state, market_id, resolvable_at as u128 * 1_000 - 1).unwrap_err();
+    assert_eq!(MarketError::resolution_opens_at(&err), Some(resolvable_at));
+    assert_eq!(state.markets[&market_id].status, MarketStatus::Open);
+
+    resolve_at(&mut state, market_id, resolvable_at as u128 * 1_000).unwrap();
+    assert_eq!(state.markets[&market_id].status, MarketStatus::ResolvedYes);
+}
+
+#[test]
+fn markets_closed_past_the_cooldown_resolve_right_away() {
+    let (mut state, market_id) = cooldown_market();
+    // Betting closes at a deadline the last bet came well before
+    let deadline = LAST_BET_AT + RESOLUTION_COOLDOWN;
+    run_at(&mut state, &identity("alice"), MarketAction::CloseBetting { market_id }, deadline as u128 * 1_000).unwrap();
+
+    resolve_at(&mut state, market_id, deadline as u128 * 1_000).unwrap();
+    assert_eq!(state.markets[&market_id].status, MarketStatus::ResolvedYes);
+}
+
+#[test]
+fn markets_without_dated_bets_have_no_cooldown() {
+    let mut state = with_users(&["alice"]);
+    let empty = create_market(&mut state, "alice");
+    let undated = create_market(&mut state, "alice");
+    bet(&mut state, "alice", undated, true, 100).unwrap();
+
+    for market_id in [empty, undated] {
+        assert_eq!(state.markets[&market_id].resolvable_at(), None);
+        resolve_at(&mut state, market_id, LAST_BET_AT as u128 * 1_000).unwrap();
+    }
+}
+
+// --------------------------------------------------------
+//     Nonces
+// --------------------------------------------------------
+
+fn nonced_bet(nonce: u64, amount: u128) -> MarketAction {
+    MarketAction::PlaceBet { market_id: 1, side: true, amount }.with_nonce(nonce)
+}
+
+#[test]
+fn replayed_nonced_blobs_are_refused() {
+    let mut state = with_users(&["alice", "bob"]);
+    create_market(&mut state, "alice");
+    assert_eq!(state.next_nonce(&identity("bob")), 0);
+
+    run(&mut state, &identity("bob"), nonced_bet(0, 100)).unwrap();
+    let err = run(&mut state, &identity("bob"), nonced_bet(0, 100)).unwrap_err();
+    assert_eq!(err, MarketError::NonceReplayed { nonce: 0, expected: 1 }.to_string());
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE - 100);
+
+    run(&mut state, &identity("bob"), nonced_bet(1, 100)).unwrap();
+    assert_eq!(state.next_nonce(&identity("bob")), 2);
+    // Nonces are per identity
+    run(&mut state, &identity("alice"), nonced_bet(0, 100)).unwrap();
+}
+
+#[test]
+fn nonces_must_not_skip_ahead_and_refusals_do_not_use_them() {
+    let mut state = with_users(&["alice", "bob"]);
+    create_market(&mut state, "alice");
+
+    let err = run(&mut state, &identity("bob"), nonced_bet(1, 100)).unwrap_err();
+    assert_eq!(err, MarketError::NonceGap { nonce: 1, expected: 0 }.to_string());
+    let err = run(&mut state, &identity("bob"), nonced_bet(0, INITIAL_BALANCE + 1)).unwrap_err();
+    assert!(err.starts_with("Insufficient balance"), "{}", err);
+    assert_eq!(state.next_nonce(&identity("bob")), 0);
+
+    run(&mut state, &identity("bob"), nonced_bet(0, 100)).unwrap();
+    // Actions without a nonce neither need nor advance one
+    bet(&mut state, "bob", 1, true, 100).unwrap();
+    assert_eq!(state.next_nonce(&identity("bob")), 1);
+}
+
+#[test]
+fn the_admin_can_require_nonces() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    let require = MarketAction::RequireNonces { required: true };
+    let err = run(&mut state, &identity("alice"), require.clone()).unwrap_err();
+    assert_eq!(err, MarketError::NoAdmin.to_string());
+    run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("alice") }).unwrap();
+    run(&mut state, &identity("alice"), require).unwrap();
+    assert!(state.require_nonces);
+
+    let err = bet(&mut state, "bob", market_id, true, 100).unwrap_err();
+    assert_eq!(err, MarketError::NonceRequired.to_string());
+    run(&mut state, &identity("bob"), nonced_bet(0, 100)).unwrap();
+    // Queries change nothing, so they need none and cannot carry one
+    run(&mut state, &identity("bob"), MarketAction::GetBalance).unwrap();
+    let err = run(&mut state, &identity("bob"), MarketAction::GetBalance.with_nonce(1)).unwrap_err();
+    assert_eq!(err, MarketError::InvalidNoncedAction.to_string());
+    let err = run(&mut state, &identity("bob"), nonced_bet(1, 100).with_nonce(1)).unwrap_err();
+    assert_eq!(err, MarketError::InvalidNoncedAction.to_string());
+    assert_eq!(state.next_nonce(&identity("bob")), 1);
+}
+
+#[test]
+fn a_paused_contract_refuses_every_action_changing_the_state() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "bob", market_id, true, 100).unwrap();
+    let err = run(&mut state, &identity("alice"), MarketAction::Pause).unwrap_err();
+    assert_eq!(err, MarketError::NoAdmin.to_string());
+    run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("alice") }).unwrap();
+    let err = run(&mut state, &identity("bob"), MarketAction::Pause).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    run(&mut state, &identity("alice"), MarketAction::Pause).unwrap();
+    assert!(state.paused);
+
+    let refused = [
+        MarketAction::SetAdmin { new_admin: identity("bob") },
+        MarketAction::Initialize { idempotent: true },
+        MarketAction::CreateMarket {
+            description: "Will it snow?".to_string(),
+            opens_at: None,
+            stake_cap: None,
+            tags: vec![],
+            challenge: None,
+        },
+        MarketAction::PlaceBet { market_id, side: false, amount: 100 },
+        MarketAction::ResolveMarket { market_id, outcome: true },
+        MarketAction::ClaimWinnings { market_id },
+        MarketAction::WithdrawTreasury { to: identity("alice"), amount: 1 },
+        MarketAction::AddComment { market_id, text: "Looks likely".to_string() },
+        MarketAction::PlaceParlay { legs: vec![(market_id, true), (market_id + 1, true)], amount: 10 },
+        MarketAction::SettleParlay { parlay_id: 1 },
+        MarketAction::CloseBetting { market_id },
+        MarketAction::ResetBalances,
+        MarketAction::AcceptChallenge { market_id },
+        MarketAction::CancelChallenge { market_id },
+        MarketAction::ExpireMarket { market_id },
+        MarketAction::ImportSnapshot { data: vec![] },
+        MarketAction::ConfiscateBalance { user: identity("bob") },
+        MarketAction::RequireNonces { required: true },
+        MarketAction::SetHouseEdge { bps: 100 },
+        MarketAction::DistributeDividends,
+        MarketAction::Pause,
+        MarketAction::PlaceBet { market_id, side: false, amount: 100 }.with_nonce(0),
+    ];
+    let before = state.commit();
+    for action in refused {
+        let err = run(&mut state, &identity("alice"), action.clone()).unwrap_err();
+        assert_eq!(err, MarketError::ContractPaused.to_string(), "{:?}", action);
+    }
+    assert!(state.commit() == before);
+    assert_eq!(state.next_nonce(&identity("alice")), 0);
+
+    // Queries keep working
+    for query in [
+        MarketAction::GetBalance,
+        MarketAction::GetMarketInfo { market_id },
+        MarketAction::GetTreasury,
+        MarketAction::GetUserStats,
+        MarketAction::GetOpenExposure,
+        MarketAction::GetLeaderboard { limit: 10 },
+        MarketAction::GetMarketHistory { market_id },
+        MarketAction::ExportSnapshot,
+    ] {
+        run(&mut state, &identity("alice"), query).unwrap();
+    }
+
+    let err = run(&mut state, &identity("bob"), MarketAction::Unpause).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    run(&mut state, &identity("alice"), MarketAction::Unpause).unwrap();
+    assert!(!state.paused);
+    bet(&mut state, "alice", market_id, false, 100).unwrap();
+    assert_eq!(state.markets[&market_id].no_pool, 100);
+}
+
+// --------------------------------------------------------
+//     Burns
+// --------------------------------------------------------
+
+/// Alice administers the contract and bet 100 on YES in market 1, against
+/// 50 from bob on NO.
+fn contested_market() -> Contract1 {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    run(&mut state, &identity("alice"), MarketAction::SetAdmin { new_admin: identity("alice") }).unwrap();
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+    bet(&mut state, "bob", market_id, false, 50).unwrap();
+    state
+}
+
+#[test]
+fn confiscated_balances_are_burned() {
+    let mut state = contested_market();
+    let funds = total_funds(&state);
+
+    let err = run(&mut state, &identity("bob"), MarketAction::ConfiscateBalance { user: identity("carol") }).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    let err = run(&mut state, &identity("alice"), MarketAction::ConfiscateBalance { user: identity("dave") }).unwrap_err();
+    assert_eq!(err, MarketError::UserNotInitialized.to_string());
+
+    let message = run(&mut state, &identity("alice"), MarketAction::ConfiscateBalance { user: identity("bob") }).unwrap();
+    assert_eq!(message, format!("Burned {} from {}", INITIAL_BALANCE - 50, identity("bob").0));
+    assert_eq!(balance(&state, "bob"), 0);
+    assert_eq!((state.burned, state.treasury), (INITIAL_BALANCE - 50, 0));
+    assert_eq!(total_funds(&state), funds);
+
+    // The stake already in play still settles
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: false }).unwrap();
+    assert_eq!(balance(&state, "bob"), 150);
+    assert_eq!(total_funds(&state), funds);
+    let message = run(&mut state, &identity("nobody"), MarketAction::GetTreasury).unwrap();
+    assert_eq!(message, format!("Treasury: 0\nBurned: {}\nDividend pool: 0", INITIAL_BALANCE - 50));
+    assert_eq!(state.state_stats().burned, INITIAL_BALANCE - 50);
+    assert_eq!(state.treasury_info().burned, INITIAL_BALANCE - 50);
+}
+
+#[test]
+fn payouts_of_winners_no_longer_known_are_burned() {
+    let mut state = contested_market();
+    bet(&mut state, "carol", 1, true, 33).unwrap();
+    // Nothing removes users today, but an imported or migrated state may
+    // still name bettors it lost
+    state.users.remove(&identity("carol"));
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true }).unwrap();
+
+    // 183 at stake: alice gets 100 * 183 / 133 and carol's 45 is burned
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 100 + 137);
+    assert_eq!(state.burned, 45);
+    assert_eq!(state.treasury, 1);
+}
+
+#[test]
+fn refunds_of_bettors_no_longer_known_are_burned() {
+    let mut state = with_users(&["alice", "bob", "carol", "dave"]);
+    let (market_id, expires_at) = dated_market(&mut state, "alice");
+    bet(&mut state, "bob", market_id, true, 70).unwrap();
+    bet(&mut state, "dave", market_id, false, 30).unwrap();
+    state.users.remove(&identity("dave"));
+
+    expire_at(&mut state, market_id, expires_at).unwrap();
+
+    assert_eq!(balance(&state, "bob"), INITIAL_BALANCE);
+    assert_eq!((state.burned, state.treasury), (30, 0));
+}
+
+// --------------------------------------------------------
+//     Open exposure
+// --------------------------------------------------------
+
+#[test]
+fn open_exposure_only_counts_markets_not_settled_yet() {
+    let mut state = with_users(&["alice", "bob"]);
+    // Resolved and paid out
+    let paid = create_market(&mut state, "alice");
+    bet(&mut state, "alice", paid, true, 100).unwrap();
+    bet(&mut state, "bob", paid, false, 50).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: paid, outcome: true }).unwrap();
+    // Resolved, but left unclaimed like markets resolved before payouts were automatic
+    let unclaimed = create_market(&mut state, "alice");
+    bet(&mut state, "alice", unclaimed, false, 30).unwrap();
+    bet(&mut state, "bob", unclaimed, true, 60).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: unclaimed, outcome: true }).unwrap();
+    for user_bet in state.users.get_mut(&identity("alice")).unwrap().bets.iter_mut() {
+        user_bet.claimed = user_bet.market_id != unclaimed;
+    }
+    // Open on both sides, with betting closed
+    let open = create_market(&mut state, "alice");
+    bet(&mut state, "alice", open, true, 40).unwrap();
+    bet(&mut state, "alice", open, true, 40).unwrap();
+    bet(&mut state, "alice", open, false, 10).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::CloseBetting { market_id: open }).unwrap();
+    // Escrowed until bob answers
+    challenge(&mut state, "alice", "bob", 200).unwrap();
+
+    assert_eq!(state.open_exposure(&identity("alice")), OpenExposure { yes: 280, no: 10, positions: 2 });
+    let message = run(&mut state, &identity("alice"), MarketAction::GetOpenExposure).unwrap();
+    assert_eq!(message, "Open exposure: 290 (YES 280, NO 10) in 2 markets");
+
+    assert_eq!(state.open_exposure(&identity("bob")), OpenExposure::default());
+    let message = run(&mut state, &identity("nobody"), MarketAction::GetOpenExposure).unwrap();
+    assert_eq!(message, "Open exposure: 0 (YES 0, NO 0) in 0 markets");
+}
+
+// --------------------------------------------------------
+//     House edge and dividends
+// --------------------------------------------------------
+
+#[test]
+fn only_the_admin_sets_a_bounded_house_edge() {
+    let mut state = contested_market();
+    let err = run(&mut state, &identity("bob"), MarketAction::SetHouseEdge { bps: 500 }).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    let too_high = MAX_HOUSE_EDGE_BPS + 1;
+    let err = run(&mut state, &identity("alice"), MarketAction::SetHouseEdge { bps: too_high }).unwrap_err();
+    assert_eq!(err, MarketError::InvalidHouseEdge { bps: too_high, max: MAX_HOUSE_EDGE_BPS }.to_string());
+
+    let message = run(&mut state, &identity("alice"), MarketAction::SetHouseEdge { bps: 500 }).unwrap();
+    assert_eq!(message, "House edge set to 500 bps");
+    assert_eq!(state.house_edge_bps, 500);
+}
+
+#[test]
+fn the_house_edge_comes_out_of_the_losing_pool() {
+    let mut state = contested_market();
+    let funds = total_funds(&state);
+    run(&mut state, &identity("alice"), MarketAction::SetHouseEdge { bps: 1_000 }).unwrap();
+
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true }).unwrap();
+
+    // 10% of bob's 50 is set aside, alice wins the other 45
+    assert_eq!((state.dividend_pool, state.markets[&1].rake), (5, 5));
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE + 45);
+    assert_eq!(state.treasury, 0);
+    assert_eq!(state.resolve_result(1).unwrap().total_distributed, 145);
+    assert_eq!(state.claim_result(&identity("alice"), 1).unwrap().payout, 145);
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn dividends_split_equally_among_recent_bettors_only() {
+    let mut state = contested_market();
+    run(&mut state, &identity("alice"), MarketAction::SetHouseEdge { bps: 2_000 }).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true }).unwrap();
+    let funds = total_funds(&state);
+    assert_eq!(state.dividend_pool, 10);
+
+    let err = run(&mut state, &identity("bob"), MarketAction::DistributeDividends).unwrap_err();
+    assert_eq!(err, MarketError::Unauthorized.to_string());
+    let (alice, bob, carol) = (balance(&state, "alice"), balance(&state, "bob"), balance(&state, "carol"));
+    // Carol never bet, so the pool splits in 2
+    let message = run(&mut state, &identity("alice"), MarketAction::DistributeDividends).unwrap();
+    assert_eq!(message, "Distributed 5 to each of 2 bettors");
+    assert_eq!(balance(&state, "alice"), alice + 5);
+    assert_eq!(balance(&state, "bob"), bob + 5);
+    assert_eq!(balance(&state, "carol"), carol);
+    assert_eq!(state.dividend_pool, 0);
+    assert_eq!(total_funds(&state), funds);
+
+    // Everyone starts counting again
+    assert!(state.users.values().all(|user| user.bets_since_dividend == 0));
+    let err = run(&mut state, &identity("alice"), MarketAction::DistributeDividends).unwrap_err();
+    assert_eq!(err, MarketError::NoDividends.to_string());
+}
+
+#[test]
+fn dividend_dust_stays_in_the_pool() {
+    let mut state = contested_market();
+    bet(&mut state, "carol", 1, true, 10).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::SetHouseEdge { bps: 2_000 }).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true }).unwrap();
+    let funds = total_funds(&state);
+
+    // 10 splits in 3 shares of 3, leaving 1 for the next distribution
+    run(&mut state, &identity("alice"), MarketAction::DistributeDividends).unwrap();
+    assert_eq!(state.dividend_pool, 1);
+    assert_eq!(total_funds(&state), funds);
+    let err = run(&mut state, &identity("alice"), MarketAction::DistributeDividends).unwrap_err();
+    assert_eq!(err, MarketError::NoDividendRecipients.to_string());
+}
+
+#[test]
+fn a_pool_smaller_than_its_bettors_waits_for_more() {
+    let mut state = contested_market();
+    run(&mut state, &identity("alice"), MarketAction::SetHouseEdge { bps: 200 }).unwrap();
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true }).unwrap();
+    assert_eq!(state.dividend_pool, 1);
+
+    let err = run(&mut state, &identity("alice"), MarketAction::DistributeDividends).unwrap_err();
+    assert_eq!(err, MarketError::DividendPoolTooSmall { pool: 1, recipients: 2 }.to_string());
+    // Both bettors keep their claim on a later distribution
+    assert_eq!(state.dividend_pool, 1);
+    assert_eq!(state.users[&identity("alice")].bets_since_dividend, 1);
+    assert_eq!(state.users[&identity("bob")].bets_since_dividend, 1);
+}
+
+// --------------------------------------------------------
+//     Read-only actions
+// --------------------------------------------------------
+
+/// The backed up state with more going on: bob also bet on market 3, market 4
+/// is alice's challenge to bob and carol holds parlay 1 over markets 1 and 3.
+fn busy_state() -> Contract1 {
+    let mut state = backed_up_state();
+    state.treasury = 1_000;
+    let third = create_market(&mut state, "alice");
+    bet(&mut state, "bob", third, false, 50).unwrap();
+    challenge(&mut state, "alice", "bob", 500).unwrap();
+    run(&mut state, &identity("carol"), MarketAction::PlaceParlay { legs: vec![(1, true), (third, false)], amount: 10 })
+        .unwrap();
+    state
+}
+
+/// Runs `action`, which must succeed and leave a different commitment.
+fn assert_mutates(state: &mut Contract1, caller: &str, action: MarketAction) {
+    assert!(!action.is_read_only(), "{:?}", action);
+    let before = state.commit();
+    if let Err(err) = run_at(state, &identity(caller), action.clone(), CHALLENGED_AT_MS) {
+        panic!("{:?} failed: {}", action, err);
+    }
+    assert!(state.commit() != before, "{:?} left the commitment as it was", action);
+}
+
+#[test]
+fn queries_leave_the_commitment_untouched() {
+    let mut state = busy_state();
+    let before = state.commit();
+    let queries = [
+        MarketAction::GetBalance,
+        MarketAction::GetMarketInfo { market_id: 1 },
+        MarketAction::GetMarketInfo { market_id: 99 },
+        MarketAction::GetTreasury,
+        MarketAction::GetUserStats,
+        MarketAction::GetOpenExposure,
+        MarketAction::GetLeaderboard { limit: 10 },
+        MarketAction::GetMarketHistory { market_id: 2 },
+        MarketAction::ExportSnapshot,
+    ];
+
+    for action in queries {
+        assert!(action.is_read_only(), "{:?}", action);
+        for caller in ["alice", "bob", "nobody"] {
+            let _ = run_at(&mut state, &identity(caller), action.clone(), CHALLENGED_AT_MS);
+            assert_eq!(state.commit(), before, "{:?} by {} changed the state", action, caller);
+        }
+    }
+}
+
+#[test]
+fn every_other_action_changes_the_commitment() {
+    let mut state = busy_state();
+    assert_mutates(&mut state, "dave", MarketAction::Initialize { idempotent: false });
+    assert_mutates(
+        &mut state,
+        "carol",
+        MarketAction::CreateMarket {
+            description: "Will the bus be late?".to_string(),
+            opens_at: None,
+            stake_cap: None,
+            tags: vec![],
+            challenge: None,
+        },
+    );
+    let carols = state.next_market_id;
+    assert_mutates(&mut state, "dave", MarketAction::PlaceBet { market_id: 1, side: true, amount: 10 });
+    assert_mutates(&mut state, "dave", MarketAction::AddComment { market_id: 1, text: "Looks likely".to_string() });
+    assert_mutates(&mut state, "carol", MarketAction::CloseBetting { market_id: carols });
+    assert_mutates(&mut state, "bob", MarketAction::AcceptChallenge { market_id: 4 });
+    let withdrawn = challenge(&mut state, "alice", "carol", 100).unwrap();
+    assert_mutates(&mut state, "alice", MarketAction::CancelChallenge { market_id: withdrawn });
+
+    // Dave's bet holds market 1 back for the cooldown
+    let resolvable_at_ms = CHALLENGED_AT_MS + RESOLUTION_COOLDOWN as u128 * 1_000;
+    let before = state.commit();
+    run_at(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 1, outcome: true }, resolvable_at_ms)
+        .unwrap();
+    assert!(state.commit() != before);
+    run(&mut state, &identity("alice"), MarketAction::ResolveMarket { market_id: 3, outcome: false }).unwrap();
+    assert_mutates(&mut state, "carol", MarketAction::ClaimWinnings { market_id: 2 });
+    assert_mutates(&mut state, "bob", MarketAction::SettleParlay { parlay_id: 1 });
+    assert_mutates(&mut state, "alice", MarketAction::WithdrawTreasury { to: identity("alice"), amount: 1 });
+
+    let (forgotten, expires_at) = dated_market(&mut state, "alice");
+    let before = state.commit();
+    expire_at(&mut state, forgotten, expires_at).unwrap();
+    assert!(state.commit() != before);
+
+    for market_id in [4, carols] {
+        let action = MarketAction::ResolveMarket { market_id, outcome: true };
+        run_at(&mut state, &identity("alice"), action, resolvable_at_ms).unwrap();
+    }
+    assert_mutates(&mut state, "alice", MarketAction::ResetBalances);
+    assert_mutates(&mut state, "alice", MarketAction::ConfiscateBalance { user: identity("carol") });
+    assert_mutates(&mut state, "alice", MarketAction::SetHouseEdge { bps: 500 });
+    assert_mutates(&mut state, "alice", MarketAction::Pause);
+    assert_mutates(&mut state, "alice", MarketAction::Unpause);
+    assert_mutates(&mut state, "alice", MarketAction::RequireNonces { required: true });
+    assert_mutates(&mut state, "bob", MarketAction::Initialize { idempotent: true }.with_nonce(0));
+    assert_mutates(&mut state, "alice", MarketAction::SetAdmin { new_admin: identity("dave") }.with_nonce(0));
+
+    let data = state.commit().0;
+    assert_mutates(&mut Contract1::new_with_admin(identity("alice")), "alice", MarketAction::ImportSnapshot { data });
+}
+
+// --------------------------------------------------------
+//     Invariants
+// --------------------------------------------------------
+
+#[test]
+fn full_lifecycle_conserves_funds() {
+    let mut state = with_users(&["alice", "bob", "carol", "dave"]);
+    let minted = 4 * INITIAL_BALANCE;
+    let market_id = create_market(&mut state, "alice");
+
+    bet(&mut state, "alice", market_id, true, 1_000).unwrap();
+    bet(&mut state, "bob", market_id, true, 3_000).unwrap();
+    bet(&mut state, "carol", market_id, false, 2_000).unwrap();
+    bet(&mut state, "dave", market_id, false, 2_000).unwrap();
+    assert_eq!(total_funds(&state), minted);
+
+    run(&mut state, &identity("dave"), MarketAction::ResolveMarket { market_id, outcome: true }).unwrap();
+    assert_eq!(total_funds(&state), minted);
+
+    for name in ["alice", "bob", "carol", "dave"] {
+        let _ = run(&mut state, &identity(name), MarketAction::ClaimWinnings { market_id });
+    }
+    assert_eq!(total_funds(&state), minted);
+}
+
+#[test]
+fn state_commitment_round_trips_through_borsh() {
+    let mut state = with_users(&["alice", "bob"]);
+    let market_id = create_market(&mut state, "alice");
+    bet(&mut state, "alice", market_id, true, 100).unwrap();
+    bet(&mut state, "bob", market_id, false, 50).unwrap();
+
+    let commitment = state.commit();
+    let decoded = Contract1::try_from(commitment.clone()).expect("valid state decodes");
+
+    assert_eq!(decoded.commit(), commitment);
+    assert_eq!(decoded.next_market_id, state.next_market_id);
+    assert_eq!(balance(&decoded, "bob"), balance(&state, "bob"));
+}
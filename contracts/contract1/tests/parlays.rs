@@ -0,0 +1,268 @@
+//! Parlays: one stake on several markets, settled once they all resolve.
+mod common;
+
+use common::{balance, identity, run, run_at, total_funds, with_users};
+use contract1::{Contract1, MarketAction, MarketStatus, ParlayStatus, MAX_MARKET_LIFETIME, MAX_PARLAY_MULTIPLIER};
+use sdk::ZkContract;
+
+const INITIAL_BALANCE: u128 = 10_000;
+const TREASURY: u128 = 10_000;
+
+fn create_market(state: &mut Contract1) -> u64 {
+    run(
+        state,
+        &identity("carol"),
+        MarketAction::CreateMarket {
+            description: "Will it rain tomorrow?".to_string(),
+            opens_at: None,
+            stake_cap: None,
+            tags: vec![],
+            challenge: None,
+        },
+    )
+    .expect("create market");
+    state.next_market_id
+}
+
+/// An open market with `yes` staked on YES by bob and `no` on NO by carol.
+fn market_with_pools(state: &mut Contract1, yes: u128, no: u128) -> u64 {
+    let market_id = create_market(state);
+    if yes > 0 {
+        run(state, &identity("bob"), MarketAction::PlaceBet { market_id, side: true, amount: yes }).unwrap();
+    }
+    if no > 0 {
+        run(state, &identity("carol"), MarketAction::PlaceBet { market_id, side: false, amount: no }).unwrap();
+    }
+    market_id
+}
+
+fn resolve(state: &mut Contract1, market_id: u64, outcome: bool) {
+    run(state, &identity("carol"), MarketAction::ResolveMarket { market_id, outcome }).unwrap();
+}
+
+fn parlay(state: &mut Contract1, legs: Vec<(u64, bool)>, amount: u128) -> Result<String, String> {
+    run(state, &identity("alice"), MarketAction::PlaceParlay { legs, amount })
+}
+
+fn settle(state: &mut Contract1, parlay_id: u64) -> Result<String, String> {
+    // Anyone may settle, the payout still goes to the owner
+    run(state, &identity("bob"), MarketAction::SettleParlay { parlay_id })
+}
+
+/// alice, bob and carol initialized, with a funded treasury.
+fn funded() -> (Contract1, u128) {
+    let mut state = with_users(&["alice", "bob", "carol"]);
+    state.treasury = TREASURY;
+    let funds = total_funds(&state);
+    (state, funds)
+}
+
+// --------------------------------------------------------
+//     Placement
+// --------------------------------------------------------
+
+#[test]
+fn placing_locks_the_stake_and_its_backing_outside_the_pools() {
+    let (mut state, funds) = funded();
+    let first = market_with_pools(&mut state, 100, 100);
+    let second = market_with_pools(&mut state, 100, 100);
+
+    let msg = parlay(&mut state, vec![(first, true), (second, false)], 100).unwrap();
+    assert_eq!(msg, "Parlay #1 placed: 100 on 2 legs, paying up to 1000");
+
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 100);
+    assert_eq!(state.treasury, TREASURY - 900);
+    assert_eq!(state.parlay_reserve, 100 * MAX_PARLAY_MULTIPLIER);
+    assert_eq!((state.markets[&first].yes_pool, state.markets[&second].no_pool), (100, 100));
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Open);
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn scheduled_legs_are_refused() {
+    let (mut state, _) = funded();
+    let first = create_market(&mut state);
+    run(
+        &mut state,
+        &identity("carol"),
+        MarketAction::CreateMarket { description: "Later".to_string(), opens_at: Some(2_000), stake_cap: None, tags: vec![], challenge: None },
+    )
+    .unwrap();
+    let scheduled = state.next_market_id;
+    let action = MarketAction::PlaceParlay { legs: vec![(first, true), (scheduled, true)], amount: 10 };
+
+    let err = run_at(&mut state, &identity("alice"), action.clone(), 1_000_000).unwrap_err();
+    assert_eq!(err, format!("Market #{} opens for betting at 2000", scheduled));
+    run_at(&mut state, &identity("alice"), action, 2_000_000).unwrap();
+}
+
+// --------------------------------------------------------
+//     Settlement
+// --------------------------------------------------------
+
+#[test]
+fn winning_parlay_multiplies_the_odds_of_its_legs() {
+    let (mut state, funds) = funded();
+    let first = market_with_pools(&mut state, 100, 300); // YES pays 4x
+    let second = market_with_pools(&mut state, 100, 100); // NO pays 2x
+    parlay(&mut state, vec![(first, true), (second, false)], 100).unwrap();
+    resolve(&mut state, first, true);
+    resolve(&mut state, second, false);
+
+    assert_eq!(settle(&mut state, 1).unwrap(), format!("Parlay #1 won: paid 800 to {}", identity("alice").0));
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Won { payout: 800 });
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 100 + 800);
+    // The unused part of the backing returns to the treasury
+    assert_eq!(state.treasury, TREASURY - 900 + 200);
+    assert_eq!(state.parlay_reserve, 0);
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn odds_are_those_the_markets_closed_at() {
+    let (mut state, _) = funded();
+    let first = market_with_pools(&mut state, 100, 100);
+    let second = market_with_pools(&mut state, 100, 100);
+    parlay(&mut state, vec![(first, true), (second, true)], 100).unwrap();
+
+    // Bets placed after the parlay still move the odds it is paid at
+    run(&mut state, &identity("carol"), MarketAction::PlaceBet { market_id: first, side: false, amount: 200 }).unwrap();
+    resolve(&mut state, first, true);
+    resolve(&mut state, second, true);
+
+    settle(&mut state, 1).unwrap();
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Won { payout: 100 * 4 * 2 });
+}
+
+#[test]
+fn payout_is_capped_at_the_multiplier() {
+    let (mut state, funds) = funded();
+    let first = market_with_pools(&mut state, 100, 900);
+    let second = market_with_pools(&mut state, 100, 900);
+    parlay(&mut state, vec![(first, true), (second, true)], 100).unwrap();
+    resolve(&mut state, first, true);
+    resolve(&mut state, second, true);
+
+    settle(&mut state, 1).unwrap();
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Won { payout: 100 * MAX_PARLAY_MULTIPLIER });
+    assert_eq!(state.treasury, TREASURY - 900);
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn one_losing_leg_loses_the_parlay() {
+    let (mut state, funds) = funded();
+    let first = market_with_pools(&mut state, 100, 300);
+    let second = market_with_pools(&mut state, 100, 100);
+    parlay(&mut state, vec![(first, true), (second, false)], 100).unwrap();
+    resolve(&mut state, first, true);
+    resolve(&mut state, second, true);
+
+    assert_eq!(settle(&mut state, 1).unwrap(), "Parlay #1 lost");
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Lost);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE - 100);
+    assert_eq!(state.treasury, TREASURY + 100);
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn partially_resolved_parlay_waits_for_its_last_leg() {
+    let (mut state, _) = funded();
+    let first = market_with_pools(&mut state, 100, 100);
+    let second = market_with_pools(&mut state, 100, 100);
+    parlay(&mut state, vec![(first, false), (second, true)], 100).unwrap();
+    resolve(&mut state, first, true);
+
+    // Even with a leg already lost, nothing settles before every market resolves
+    let before = state.clone();
+    let err = settle(&mut state, 1).unwrap_err();
+    assert_eq!(err, format!("Market #{} of the parlay is not resolved yet", second));
+    assert_eq!(state.commit(), before.commit());
+
+    resolve(&mut state, second, true);
+    assert_eq!(settle(&mut state, 1).unwrap(), "Parlay #1 lost");
+    assert_eq!(settle(&mut state, 1).unwrap_err(), "Parlay already settled");
+}
+
+#[test]
+fn leg_without_a_winning_pool_is_void() {
+    let (mut state, funds) = funded();
+    let first = market_with_pools(&mut state, 100, 300);
+    // Nobody backs NO: its pool is empty when NO wins
+    let void = market_with_pools(&mut state, 100, 0);
+    parlay(&mut state, vec![(first, true), (void, false)], 100).unwrap();
+    resolve(&mut state, first, true);
+    resolve(&mut state, void, false);
+
+    settle(&mut state, 1).unwrap();
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Won { payout: 400 });
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn parlay_of_void_legs_is_refunded() {
+    let (mut state, funds) = funded();
+    let first = market_with_pools(&mut state, 0, 100);
+    let second = market_with_pools(&mut state, 100, 0);
+    parlay(&mut state, vec![(first, true), (second, false)], 100).unwrap();
+    resolve(&mut state, first, true);
+    resolve(&mut state, second, false);
+
+    assert_eq!(
+        settle(&mut state, 1).unwrap(),
+        format!("Parlay #1 is void: refunded 100 to {}", identity("alice").0)
+    );
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Void);
+    assert_eq!(balance(&state, "alice"), INITIAL_BALANCE);
+    assert_eq!(state.parlay_reserve, 0);
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn leg_on_an_expired_market_is_void() {
+    let (mut state, funds) = funded();
+    let first = market_with_pools(&mut state, 100, 300);
+    let created_at_ms = 1_700_000_000_000;
+    let action = MarketAction::CreateMarket {
+        description: "Will anyone remember?".to_string(),
+        opens_at: None,
+        stake_cap: None,
+        tags: vec![],
+        challenge: None,
+    };
+    run_at(&mut state, &identity("carol"), action, created_at_ms).unwrap();
+    let forgotten = state.next_market_id;
+    run(&mut state, &identity("bob"), MarketAction::PlaceBet { market_id: forgotten, side: true, amount: 100 }).unwrap();
+    parlay(&mut state, vec![(first, true), (forgotten, true)], 100).unwrap();
+    resolve(&mut state, first, true);
+    let expired_at_ms = created_at_ms + MAX_MARKET_LIFETIME as u128 * 1_000;
+    run_at(&mut state, &identity("bob"), MarketAction::ExpireMarket { market_id: forgotten }, expired_at_ms).unwrap();
+    assert_eq!(state.markets[&forgotten].status, MarketStatus::Void);
+
+    settle(&mut state, 1).unwrap();
+    assert_eq!(state.parlays[&1].status, ParlayStatus::Won { payout: 400 });
+    assert_eq!(total_funds(&state), funds);
+}
+
+#[test]
+fn open_parlays_hold_the_treasury_back() {
+    let (mut state, _) = funded();
+    state.set_admin(identity("carol"), identity("carol")).unwrap();
+    let first = market_with_pools(&mut state, 100, 100);
+    let second = market_with_pools(&mut state, 100, 100);
+    parlay(&mut state, vec![(first, true), (second, true)], 1_000).unwrap();
+    assert_eq!(state.treasury, TREASURY - 9_000);
+
+    // The backing of the first parlay is not available to a second one...
+    let err = parlay(&mut state, vec![(first, false), (second, false)], 200).unwrap_err();
+    assert_eq!(err, "The treasury cannot back this parlay. Have: 1000, Need: 1800");
+    // ...nor to the admin
+    let err = run(
+        &mut state,
+        &identity("carol"),
+        MarketAction::WithdrawTreasury { to: identity("carol"), amount: 1_001 },
+    )
+    .unwrap_err();
+    assert_eq!(err, "Insufficient treasury. Have: 1000, Need: 1001");
+    assert_eq!(state.markets[&first].status, MarketStatus::Open);
+}
@@ -0,0 +1,50 @@
+//! Coarse timing guards for large states. The thresholds are an order of
+//! magnitude above what an unoptimized build needs, so they only trip on
+//! real regressions (an accidental clone of the state, quadratic loops).
+//! `cargo bench -p contract1` gives the precise numbers.
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{identity, run, synthetic_state};
+use contract1::MarketAction;
+use sdk::ZkContract;
+
+const USERS: usize = 2_000;
+const MARKETS: usize = 100;
+const BETTORS: usize = 40;
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+#[test]
+fn large_state_actions_stay_fast() {
+    let mut state = synthetic_state(USERS, MARKETS, BETTORS);
+    let market_id = state.next_market_id;
+
+    let (result, elapsed) = timed(|| {
+        run(&mut state, &identity("user0"), MarketAction::ResolveMarket { market_id, outcome: true })
+    });
+    result.expect("resolve");
+    assert!(elapsed < Duration::from_millis(50), "resolve_market took {:?}", elapsed);
+
+    let (result, elapsed) = timed(|| {
+        run(&mut state, &identity("user1"), MarketAction::PlaceBet { market_id: 1, side: false, amount: 5 })
+    });
+    result.expect("place bet");
+    assert!(elapsed < Duration::from_millis(20), "place_bet took {:?}", elapsed);
+}
+
+#[test]
+fn large_state_commit_stays_fast() {
+    let state = synthetic_state(USERS, MARKETS, BETTORS);
+
+    let (commitment, elapsed) = timed(|| state.commit());
+    assert!(elapsed < Duration::from_millis(500), "commit took {:?}", elapsed);
+    // ~100 bytes per user, ~40 per bet, ~70 per history entry and ~100 per market
+    let budget = USERS * 100 + MARKETS * (100 + BETTORS * (80 + 80));
+    assert!(commitment.0.len() < budget, "commitment is {} bytes", commitment.0.len());
+}
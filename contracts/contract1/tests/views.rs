@@ -0,0 +1,89 @@
+//! The read views the indexer serves, against JSON documents in
+//! `tests/fixtures/views/`. Each fixture decodes to the value it describes
+//! and encodes back to the same document, so a field renamed or dropped on
+//! either side of the API shows up here rather than as a failing client.
+mod common;
+
+use std::path::Path;
+
+use common::identity;
+use contract1::{
+    api::{LeaderboardEntry, MarketSummary, Odds, UserBetInfo},
+    Challenge, MarketStatus, StakeCap,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+/// Decodes `views/<name>.json`, checking it encodes back unchanged.
+fn fixture<T: Serialize + DeserializeOwned>(name: &str) -> T {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/views/{}.json", name));
+    let document: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let decoded: T = serde_json::from_value(document.clone()).unwrap_or_else(|e| panic!("{}: {}", name, e));
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), document, "{} does not encode back", name);
+    decoded
+}
+
+#[test]
+fn market_lists_decode() {
+    let markets: Vec<MarketSummary> = fixture("markets");
+    assert_eq!(markets.len(), 2);
+
+    let challenge = &markets[0];
+    assert_eq!((challenge.id, challenge.status.clone()), (2, MarketStatus::PendingAcceptance));
+    assert_eq!(challenge.challenge, Some(Challenge { opponent: identity("bob"), stake: 200 }));
+    assert_eq!(challenge.accept_by, Some(1_700_086_400));
+
+    let market = &markets[1];
+    assert_eq!((market.id, market.description.as_str(), market.creator.as_str()), (1, "Will it rain?", "alice@contract1"));
+    assert_eq!((market.yes_pool, market.no_pool, market.bettor_count), (300, 100, 2));
+    assert_eq!((market.opens_at, market.betting_closed), (Some(1_700_000_000), true));
+    assert_eq!(market.stake_cap, Some(StakeCap::Absolute(500)));
+    assert_eq!(market.tags, ["weather"]);
+}
+
+#[test]
+fn summaries_from_before_optional_fields_decode() {
+    let market: MarketSummary = serde_json::from_value(json!({
+        "id": 1,
+        "description": "Will it rain?",
+        "creator": "alice@contract1",
+        "status": "ResolvedYes",
+        "yes_pool": 300,
+        "no_pool": 100,
+        "bettor_count": 2,
+    }))
+    .unwrap();
+    assert_eq!(market.status, MarketStatus::ResolvedYes);
+    assert_eq!((market.opens_at, market.scheduled, market.stake_cap, market.betting_closed), (None, false, None, false));
+    assert_eq!((market.tags.len(), market.challenge, market.accept_by), (0, None, None));
+}
+
+#[test]
+fn odds_decode() {
+    let odds: Odds = fixture("odds");
+    assert_eq!(
+        odds,
+        Odds { market_id: 1, yes_pool: 300, no_pool: 100, yes_probability_bps: 7_500, no_probability_bps: 2_500 }
+    );
+}
+
+#[test]
+fn leaderboards_decode() {
+    let entries: Vec<LeaderboardEntry> = fixture("leaderboard");
+    let ranked: Vec<_> = entries.iter().map(|e| (e.identity.as_str(), e.balance)).collect();
+    assert_eq!(ranked, [("bob@contract1", 10_250), ("alice@contract1", 9_700)]);
+}
+
+#[test]
+fn user_bets_decode() {
+    let bets: Vec<UserBetInfo> = fixture("user_bets");
+    assert_eq!(
+        bets,
+        [
+            UserBetInfo { market_id: 1, side: true, amount: 300, claimed: false, market_status: Some(MarketStatus::Open) },
+            UserBetInfo { market_id: 3, side: false, amount: 50, claimed: true, market_status: Some(MarketStatus::ResolvedNo) },
+            // The market is gone from the state
+            UserBetInfo { market_id: 4, side: true, amount: 20, claimed: false, market_status: None },
+        ]
+    );
+}
@@ -1,37 +1,146 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::BTreeSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use axum::{
-    extract::{Json, State},
-    http::{HeaderMap, Method, StatusCode},
-    response::IntoResponse,
+    extract::{FromRequestParts, Json, Path, Query, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{
+        sse::{KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing::{get, post},
     Router,
 };
+use async_trait::async_trait;
 use client_sdk::{
     contract_indexer::AppError,
     rest_client::{NodeApiClient, NodeApiHttpClient},
 };
-use contract1::{Contract1, MarketAction};
+use contract1::{
+    api::{
+        ContractParams, CreatedMarket, EventFilter, InitializeOutcome, MarketFilter, MarketStatusFilter, MarketSummary, ReconcileSnapshot,
+        SnapshotExport, WebhookPayload,
+    },
+    client::{snapshot_data, snapshot_of, tx_executor_handler::metadata::PROGRAM_ID},
+    http::{
+        AcceptChallengeRequest, AddCommentRequest, ApiRequest, CancelChallengeRequest, ClaimWinningsRequest, CloseBettingRequest,
+        ConfigResponse, ConfiscateBalanceRequest, CreateMarketRequest, DeployRequest, DistributeDividendsRequest, ExpireMarketRequest,
+        ExportSnapshotRequest, GetBalanceRequest, GetLeaderboardRequest, GetMarketInfoRequest, GetOpenExposureRequest,
+        GetTreasuryRequest, GetUserStatsRequest, InitializeRequest, PauseRequest, PlaceBetRequest, PlaceParlayRequest,
+        ResetBalancesRequest, ResolveMarketRequest, SetAdminRequest, SetHouseEdgeRequest, SettleParlayRequest, UnpauseRequest,
+        WithdrawTreasuryRequest, LEADERBOARD_DEFAULT_LIMIT,
+    },
+    Challenge, Contract1, MarketAction, MAX_LEADERBOARD_LIMIT,
+};
 
 use hyle_modules::{
     bus::{BusClientReceiver, SharedMessageBus},
     module_bus_client, module_handle_messages,
     modules::{prover::AutoProverEvent, BuildApiContextInner, Module},
 };
-use sdk::{BlobTransaction, ContractName};
+use prometheus::Registry;
+use sdk::{api::APIRegisterContract, BlobTransaction, ContractName, Hashed, ProgramId, StateCommitment, TxHash, ZkContract};
 use serde::Serialize;
-use tokio::sync::Mutex;
-use tower_http::cors::{Any, CorsLayer};
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, RwLock};
+use tower_http::{
+    compression::CompressionLayer,
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+};
+use tracing::{info, warn};
+
+use crate::{
+    backpressure::{Backpressure, SubmissionLimits, RETRY_AFTER_SECS},
+    contract_check::{check_contract, ContractCheck},
+    cors::CorsConfig,
+    dedup::RecentSubmissions,
+    events::EventFeed,
+    history::{HistoryConfig, HistoryEntry, HistoryStore},
+    identity::{Identities, IdentityConfig},
+    init::VERIFIER,
+    metrics::StateGauges,
+    nonces::Nonces,
+    precheck::{Precheck, PrecheckMode},
+    webhook::{WebhookConfig, WebhookSender},
+};
 
 pub struct AppModule {
     bus: AppModuleBusClient,
+    indexed: IndexedState,
+    webhook: Option<WebhookSender>,
+    events: Arc<EventFeed>,
+    history: Option<Arc<HistoryStore>>,
+    gauges: Option<StateGauges>,
+    precheck: Arc<Precheck>,
+    nonces: Arc<Nonces>,
 }
 
+/// Latest contract state settled by the prover, served by the GET routes.
+/// Empty until the first transaction settles after startup.
+type IndexedState = Arc<RwLock<Option<Contract1>>>;
+
 pub struct AppModuleCtx {
     pub api: Arc<BuildApiContextInner>,
-    pub node_client: Arc<NodeApiHttpClient>,
+    pub node_client: Arc<dyn TxSubmitter>,
     pub contract1_cn: ContractName,
+    /// `None` reads it from the environment, see [`CorsConfig::from_env`]
+    pub cors: Option<CorsConfig>,
+    /// Who may send actions and how they prove it; `None` reads it from the
+    /// environment, see [`IdentityConfig::from_env`]
+    pub identity: Option<IdentityConfig>,
+    /// Secret the admin routes expect in `x-admin-key`; `None` disables them
+    pub admin_key: Option<String>,
+    /// User administering the contracts `/api/admin/deploy` registers, see
+    /// [`initial_state`]; `None` disables the route
+    pub contract_admin: Option<String>,
+    /// Largest request body accepted, in bytes
+    pub max_body_size: usize,
+    /// Compress responses for clients sending `Accept-Encoding`
+    pub compression: bool,
+    /// Where bets and resolutions are pushed as they settle
+    pub webhook: Option<WebhookConfig>,
+    /// Where submitted actions are logged per identity; `None` disables the history route
+    pub history: Option<HistoryConfig>,
+    /// How long an identical resubmission is answered with the original hash
+    pub duplicate_window: Duration,
+    /// Where the state gauges are registered; `None` leaves them out
+    pub metrics: Option<Registry>,
+    /// Whether actions are dry run against the indexed state before being submitted
+    pub precheck: PrecheckMode,
+    /// How many submissions may wait for their transaction at once
+    pub submission_limits: SubmissionLimits,
+}
+
+/// Where the routes send blob transactions. The node client in production;
+/// tests substitute an in-memory node.
+#[async_trait]
+pub trait TxSubmitter: Send + Sync {
+    async fn send_tx_blob(&self, tx: BlobTransaction) -> Result<TxHash>;
+    /// The contract's current state commitment, checked at startup
+    async fn get_contract_state(&self, contract_name: &ContractName) -> Result<StateCommitment>;
+    /// Registers a new contract, for `/api/admin/deploy`
+    async fn register_contract(&self, contract: APIRegisterContract) -> Result<()>;
+}
+
+#[async_trait]
+impl TxSubmitter for NodeApiHttpClient {
+    async fn send_tx_blob(&self, tx: BlobTransaction) -> Result<TxHash> {
+        NodeApiClient::send_tx_blob(self, tx).await
+    }
+
+    async fn get_contract_state(&self, contract_name: &ContractName) -> Result<StateCommitment> {
+        Ok(NodeApiClient::get_contract(self, contract_name.clone()).await?.state)
+    }
+
+    async fn register_contract(&self, contract: APIRegisterContract) -> Result<()> {
+        NodeApiClient::register_contract(self, contract).await?;
+        Ok(())
+    }
 }
 
 module_bus_client! {
@@ -45,32 +154,103 @@ impl Module for AppModule {
     type Context = Arc<AppModuleCtx>;
 
     async fn build(bus: SharedMessageBus, ctx: Self::Context) -> Result<Self> {
+        let indexed = IndexedState::default();
+        let contract_check = check_contract(ctx.node_client.as_ref(), &ctx.contract1_cn).await;
+        let history = match &ctx.history {
+            Some(config) => {
+                let store = Arc::new(HistoryStore::open(config).await.context("opening action history")?);
+                spawn_history_pruning(store.clone());
+                Some(store)
+            }
+            None => None,
+        };
+        let gauges = ctx.metrics.as_ref().map(StateGauges::register).transpose()?;
+        let events = Arc::new(EventFeed::default());
+        let identity = match &ctx.identity {
+            Some(identity) => identity.clone(),
+            None => IdentityConfig::from_env().context("reading identity providers")?,
+        };
+        info!("Accepting identities from {:?}", identity.providers);
+        let precheck = Arc::new(Precheck::new(ctx.precheck));
+        let nonces = Arc::new(Nonces::default());
+        let backpressure = Arc::new(Backpressure::new(ctx.submission_limits, ctx.metrics.as_ref())?);
         let state = RouterCtx {
             bus: Arc::new(Mutex::new(bus.new_handle())),
             contract1_cn: ctx.contract1_cn.clone(),
             client: ctx.node_client.clone(),
+            indexed: indexed.clone(),
+            admin_key: ctx.admin_key.as_deref().map(Arc::from),
+            contract_admin: ctx.contract_admin.as_deref().map(Arc::from),
+            contract_check: Arc::new(contract_check),
+            history: history.clone(),
+            recent_submissions: Arc::new(RecentSubmissions::new(ctx.duplicate_window)),
+            events: events.clone(),
+            identities: Arc::new(identity.build(&ctx.contract1_cn)),
+            precheck: precheck.clone(),
+            nonces: nonces.clone(),
+            deployed: Arc::default(),
+            backpressure,
         };
 
-        // Créer un middleware CORS
-        let cors = CorsLayer::new()
-            .allow_origin(Any) // Permet toutes les origines (peut être restreint)
-            .allow_methods(vec![Method::GET, Method::POST]) // Permet les méthodes nécessaires
-            .allow_headers(Any); // Permet tous les en-têtes
+        let cors = match &ctx.cors {
+            Some(cors) => cors.clone(),
+            None => CorsConfig::from_env().context("reading CORS configuration")?,
+        };
 
         let api = Router::new()
             .route("/_health", get(health))
             .route("/api/config", get(get_config))
             // Contract1 (Market) routes
-            .route("/api/market/set_admin", post(set_admin))
-            .route("/api/market/initialize", post(initialize))
-            .route("/api/market/create", post(create_market))
-            .route("/api/market/bet", post(place_bet))
-            .route("/api/market/resolve", post(resolve_market))
-            .route("/api/market/claim", post(claim_winnings))
-            .route("/api/market/balance", post(get_balance))
-            .route("/api/market/info", post(get_market_info))
+            .route(admin_route_of(set_admin), post(set_admin))
+            .route(route_of(initialize), post(initialize))
+            .route(route_of(create_market), post(create_market))
+            .route(route_of(place_bet), post(place_bet))
+            .route(route_of(close_betting), post(close_betting))
+            .route(route_of(accept_challenge), post(accept_challenge))
+            .route(route_of(cancel_challenge), post(cancel_challenge))
+            .route(route_of(expire_market), post(expire_market))
+            .route(route_of(resolve_market), post(resolve_market))
+            .route(route_of(claim_winnings), post(claim_winnings))
+            .route(route_of(get_balance), post(get_balance))
+            .route(route_of(get_market_info), post(get_market_info))
+            .route(route_of(add_comment), post(add_comment))
+            .route(route_of(get_treasury), post(get_treasury))
+            .route(admin_route_of(withdraw_treasury), post(withdraw_treasury))
+            .route(admin_route_of(reset_balances), post(reset_balances))
+            .route(admin_route_of(confiscate_balance), post(confiscate_balance))
+            .route(admin_route_of(set_house_edge), post(set_house_edge))
+            .route(admin_route_of(distribute_dividends), post(distribute_dividends))
+            .route(admin_route_of(pause), post(pause))
+            .route(admin_route_of(unpause), post(unpause))
+            .route(route_of(get_user_stats), post(get_user_stats))
+            .route(route_of(get_open_exposure), post(get_open_exposure))
+            // GET reads the indexed state, POST proves the same query on-chain
+            .route(route_of(get_leaderboard), get(read_leaderboard).post(get_leaderboard))
+            .route(route_of(place_parlay), post(place_parlay))
+            .route(route_of(settle_parlay), post(settle_parlay))
+            // Read-only routes, served from the indexed state without a transaction
+            .route("/api/market/{id}", get(read_market))
+            .route("/api/market/{id}/odds", get(read_odds))
+            .route("/api/market/{id}/history", get(read_market_history))
+            .route("/api/markets", get(read_markets))
+            .route("/api/snapshot", get(read_snapshot))
+            .route("/api/events", get(stream_events))
+            .route("/api/user/{identity}/balance", get(read_balance))
+            .route("/api/user/{identity}/history", get(read_history))
+            .route("/api/tx/{hash}", get(read_tx_status))
+            .route(route_of(reconcile), post(reconcile))
+            .route("/api/admin/state_stats", get(read_state_stats))
+            .route(admin_route_of(export_snapshot), post(export_snapshot))
+            .route(admin_route_of(import_snapshot), post(import_snapshot))
+            .route(route_of(deploy_contract), post(deploy_contract))
             .with_state(state)
-            .layer(cors); // Appliquer le middleware CORS
+            .layer(cors.layer())
+            // Oversized bodies are refused with a 413 before being buffered
+            .layer(RequestBodyLimitLayer::new(ctx.max_body_size))
+            .layer(CompressionLayer::new().gzip(ctx.compression).br(ctx.compression))
+            // Echo the caller's x-request-id (or a fresh one) on every response
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
         if let Ok(mut guard) = ctx.api.router.lock() {
             if let Some(router) = guard.take() {
@@ -79,12 +259,60 @@ impl Module for AppModule {
         }
         let bus = AppModuleBusClient::new_from_bus(bus.new_handle()).await;
 
-        Ok(AppModule { bus })
+        Ok(AppModule {
+            bus,
+            indexed,
+            webhook: ctx.webhook.clone().map(WebhookSender::new),
+            events,
+            history,
+            gauges,
+            precheck,
+            nonces,
+        })
     }
 
     async fn run(&mut self) -> Result<()> {
         module_handle_messages! {
             on_bus self.bus,
+            listen<AutoProverEvent<Contract1>> event => {
+                let (tx_hash, error) = match &event {
+                    AutoProverEvent::SuccessTx(tx_hash, _) => (tx_hash, None),
+                    AutoProverEvent::FailedTx(tx_hash, error) => (tx_hash, Some(error.as_str())),
+                };
+                self.precheck.settled(tx_hash, error);
+                // Released once the state is indexed, which then knows the nonce was used
+                let settled_hash = tx_hash.clone();
+                if let Some(history) = &self.history {
+                    if let Err(e) = history.settled(tx_hash, error).await {
+                        warn!("Failed to record the outcome of {} in the history: {:#}", tx_hash, e);
+                    }
+                }
+                if let AutoProverEvent::SuccessTx(tx_hash, state) = event {
+                    let mut indexed = self.indexed.write().await;
+                    // Nothing to compare against for the first transaction after startup
+                    if let Some(before) = indexed.as_ref() {
+                        let events = state.events_since(before);
+                        if !events.is_empty() {
+                            let payload = WebhookPayload { tx_hash: tx_hash.to_string(), events };
+                            if let Some(webhook) = &self.webhook {
+                                let webhook = webhook.clone();
+                                let payload = payload.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = webhook.deliver(&payload).await {
+                                        warn!("{:#}", e);
+                                    }
+                                });
+                            }
+                            self.events.publish(payload);
+                        }
+                    }
+                    if let Some(gauges) = &self.gauges {
+                        gauges.observe(&state.state_stats());
+                    }
+                    *indexed = Some(state);
+                }
+                self.nonces.release(&settled_hash);
+            }
         };
 
         Ok(())
@@ -94,88 +322,170 @@ impl Module for AppModule {
 #[derive(Clone)]
 struct RouterCtx {
     pub bus: Arc<Mutex<SharedMessageBus>>,
-    pub client: Arc<NodeApiHttpClient>,
+    pub client: Arc<dyn TxSubmitter>,
     pub contract1_cn: ContractName,
+    pub indexed: IndexedState,
+    pub admin_key: Option<Arc<str>>,
+    pub contract_admin: Option<Arc<str>>,
+    pub contract_check: Arc<ContractCheck>,
+    pub history: Option<Arc<HistoryStore>>,
+    pub recent_submissions: Arc<RecentSubmissions>,
+    pub events: Arc<EventFeed>,
+    pub identities: Arc<Identities>,
+    pub precheck: Arc<Precheck>,
+    pub nonces: Arc<Nonces>,
+    /// Contracts registered through `/api/admin/deploy` since startup
+    pub deployed: Arc<Mutex<BTreeSet<ContractName>>>,
+    pub backpressure: Arc<Backpressure>,
 }
 
-async fn health() -> impl IntoResponse {
-    Json("OK")
+/// Deletes history entries past their retention period once an hour.
+fn spawn_history_pruning(store: Arc<HistoryStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match store.prune().await {
+                Ok(0) => {}
+                Ok(pruned) => info!("Pruned {} expired history entries", pruned),
+                Err(e) => warn!("Failed to prune the action history: {:#}", e),
+            }
+        }
+    });
+}
+
+#[derive(Serialize)]
+struct HealthResponse<'a> {
+    status: &'static str,
+    contract: &'a ContractCheck,
+    submissions: SubmissionsHealth,
+    /// The admin paused the contract: actions changing the state are refused
+    paused: bool,
+}
+
+#[derive(Serialize)]
+struct SubmissionsHealth {
+    in_flight: usize,
+    limits: SubmissionLimits,
+}
+
+/// 503 while the contract state is incompatible, so the server is not
+/// routed transactions it would refuse. `busy` while actions changing the
+/// state are refused for load, which clients can back off from. `paused`
+/// tells whether the contract admin halted those actions.
+async fn health(State(ctx): State<RouterCtx>) -> impl IntoResponse {
+    let (code, status) = if ctx.contract_check.is_degraded() {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    } else if ctx.backpressure.is_saturated() {
+        (StatusCode::OK, "busy")
+    } else {
+        (StatusCode::OK, "OK")
+    };
+    let submissions = SubmissionsHealth {
+        in_flight: ctx.backpressure.in_flight(),
+        limits: ctx.backpressure.limits(),
+    };
+    let paused = ctx.indexed.read().await.as_ref().is_some_and(|state| state.paused);
+    (code, Json(HealthResponse { status, contract: &ctx.contract_check, submissions, paused })).into_response()
 }
 
 // --------------------------------------------------------
 //     Headers
 // --------------------------------------------------------
 
-const USER_HEADER: &str = "x-user";
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
 #[derive(Debug)]
 struct AuthHeaders {
+    /// The canonical identity the caller's blobs are sent as
     user: String,
+    request_id: String,
 }
 
 impl AuthHeaders {
-    fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
-        let user = headers
-            .get(USER_HEADER)
+    fn from_headers(headers: &HeaderMap, identities: &Identities) -> Result<Self, AppError> {
+        let user = identities
+            .identify(headers)
+            .map_err(|e| AppError(StatusCode::UNAUTHORIZED, anyhow::anyhow!(e)))?
+            .0;
+
+        let request_id = headers
+            .get(REQUEST_ID_HEADER)
             .and_then(|v| v.to_str().ok())
-            .ok_or_else(|| {
-                AppError(
-                    StatusCode::UNAUTHORIZED,
-                    anyhow::anyhow!("Missing signature"),
-                )
-            })?;
+            .unwrap_or("-");
 
         Ok(AuthHeaders {
-            user: user.to_string(),
+            user,
+            request_id: request_id.to_string(),
         })
     }
 }
 
-#[derive(Serialize)]
-struct ConfigResponse {
-    contract_name: String,
-}
+const ADMIN_KEY_HEADER: &str = "x-admin-key";
 
+/// Gates a route behind the operator secret: anyone can set `x-user`, so
+/// destructive routes take this extractor as well.
+struct AdminKey;
 
-#[derive(serde::Deserialize)]
-struct SetAdminRequest {
-    new_admin: String,
-}
+impl FromRequestParts<RouterCtx> for AdminKey {
+    type Rejection = AdminKeyRejection;
 
-#[derive(serde::Deserialize)]
-struct InitializeRequest {}
+    async fn from_request_parts(parts: &mut Parts, ctx: &RouterCtx) -> Result<Self, Self::Rejection> {
+        let Some(expected) = ctx.admin_key.as_deref() else {
+            return Err(AdminKeyRejection::Disabled);
+        };
+        match parts.headers.get(ADMIN_KEY_HEADER) {
+            None => Err(AdminKeyRejection::Missing),
+            Some(key) if keys_match(key.as_bytes(), expected.as_bytes()) => Ok(AdminKey),
+            Some(_) => Err(AdminKeyRejection::Invalid),
+        }
+    }
+}
 
-#[derive(serde::Deserialize)]
-struct CreateMarketRequest {
-    description: String,
+#[derive(Debug, Clone, Copy)]
+enum AdminKeyRejection {
+    Disabled,
+    Missing,
+    Invalid,
 }
 
-#[derive(serde::Deserialize)]
-struct PlaceBetRequest {
-    market_id: u64,
-    side: bool,
-    amount: u128,
+#[derive(Serialize)]
+struct AdminErrorResponse {
+    error: &'static str,
+    message: &'static str,
 }
 
-#[derive(serde::Deserialize)]
-struct ResolveMarketRequest {
-    market_id: u64,
-    outcome: bool,
+impl IntoResponse for AdminKeyRejection {
+    fn into_response(self) -> Response {
+        let (error, message) = match self {
+            AdminKeyRejection::Disabled => ("admin_disabled", "No admin key is configured on this server"),
+            AdminKeyRejection::Missing => ("admin_key_missing", "Missing x-admin-key header"),
+            AdminKeyRejection::Invalid => ("admin_key_invalid", "Invalid admin key"),
+        };
+        (StatusCode::FORBIDDEN, Json(AdminErrorResponse { error, message })).into_response()
+    }
 }
 
-#[derive(serde::Deserialize)]
-struct ClaimWinningsRequest {
-    market_id: u64,
+/// Compares in constant time so the key cannot be guessed byte by byte
+fn keys_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
 }
 
-#[derive(serde::Deserialize)]
-struct GetBalanceRequest {}
+/// Bumped when routes or response shapes change incompatibly. The first
+/// `/api/config` only carried the contract name and counts as version 1.
+pub const API_VERSION: u32 = 2;
 
-#[derive(serde::Deserialize)]
-struct GetMarketInfoRequest {
-    market_id: u64,
+/// Path of the route handled by `handler`, read off the JSON body it takes
+/// after two other extractors. The bot posts that same type to that same
+/// path, so a route cannot expect another body than the one its clients send.
+fn route_of<R: ApiRequest, A, B, Fut>(_handler: fn(A, B, Json<R>) -> Fut) -> &'static str {
+    R::PATH
 }
 
+/// Like [`route_of`], for handlers taking both the headers and the admin key.
+fn admin_route_of<R: ApiRequest, A, B, C, Fut>(_handler: fn(A, B, C, Json<R>) -> Fut) -> &'static str {
+    R::PATH
+}
 
 // --------------------------------------------------------
 //     Routes
@@ -185,9 +495,10 @@ struct GetMarketInfoRequest {
 async fn set_admin(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
+    _admin: AdminKey,
     Json(request): Json<SetAdminRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
     let action = MarketAction::SetAdmin { new_admin: sdk::Identity(request.new_admin) };
     send_market_action(ctx, auth, action).await
 }
@@ -195,11 +506,28 @@ async fn set_admin(
 async fn initialize(
     State(ctx): State<RouterCtx>,
     headers: HeaderMap,
-    Json(_request): Json<InitializeRequest>
+    Json(request): Json<InitializeRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
-    let action = MarketAction::Initialize {};
-    send_market_action(ctx, auth, action).await
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::Initialize { idempotent: request.idempotent };
+    if !request.idempotent {
+        return send_market_action(ctx, auth, action).await;
+    }
+
+    // Read before submitting: the settled state alone cannot tell a grant
+    // from a no-op
+    let identity = sdk::Identity(auth.user.clone());
+    let already_initialized = ctx
+        .indexed
+        .read()
+        .await
+        .as_ref()
+        .and_then(|state| state.users.get(&identity))
+        .is_some_and(|user| user.initialized);
+    submit_market_action(ctx, auth, action, move |state| {
+        state.users.get(&identity).map(|user| InitializeOutcome { balance: user.balance, already_initialized })
+    })
+    .await
 }
 
 async fn create_market(
@@ -207,9 +535,19 @@ async fn create_market(
     headers: HeaderMap,
     Json(request): Json<CreateMarketRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
-    let action = MarketAction::CreateMarket { description: request.description };
-    send_market_action(ctx, auth, action).await
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::CreateMarket {
+        description: request.description,
+        opens_at: request.opens_at,
+        stake_cap: request.stake_cap,
+        tags: request.tags,
+        challenge: request.challenge.map(|challenge| Challenge {
+            opponent: sdk::Identity(challenge.opponent),
+            stake: challenge.stake,
+        }),
+    };
+    // Ids are assigned in order, so the settled state's last id is this market's
+    submit_market_action(ctx, auth, action, |state| Some(CreatedMarket { market_id: state.next_market_id })).await
 }
 
 async fn place_bet(
@@ -217,7 +555,7 @@ async fn place_bet(
     headers: HeaderMap,
     Json(request): Json<PlaceBetRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
     let action = MarketAction::PlaceBet { 
         market_id: request.market_id,
         side: request.side,
@@ -231,11 +569,53 @@ async fn resolve_market(
     headers: HeaderMap,
     Json(request): Json<ResolveMarketRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
     let action = MarketAction::ResolveMarket {
         market_id: request.market_id,
         outcome: request.outcome,
     };
+    submit_market_action(ctx, auth, action, move |state| state.resolve_result(request.market_id)).await
+}
+
+async fn close_betting(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<CloseBettingRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::CloseBetting { market_id: request.market_id };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn accept_challenge(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<AcceptChallengeRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::AcceptChallenge { market_id: request.market_id };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn cancel_challenge(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<CancelChallengeRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::CancelChallenge { market_id: request.market_id };
+    send_market_action(ctx, auth, action).await
+}
+
+/// Voids a market left unresolved past its maximum lifetime. Anyone may
+/// call it; the contract refuses it earlier.
+async fn expire_market(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<ExpireMarketRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::ExpireMarket { market_id: request.market_id };
     send_market_action(ctx, auth, action).await
 }
 
@@ -244,9 +624,10 @@ async fn claim_winnings(
     headers: HeaderMap,
     Json(request): Json<ClaimWinningsRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let identity = sdk::Identity(auth.user.clone());
     let action = MarketAction::ClaimWinnings { market_id: request.market_id };
-    send_market_action(ctx, auth, action).await
+    submit_market_action(ctx, auth, action, move |state| state.claim_result(&identity, request.market_id)).await
 }
 
 async fn get_balance(
@@ -254,7 +635,7 @@ async fn get_balance(
     headers: HeaderMap,
     Json(_request): Json<GetBalanceRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
     let action = MarketAction::GetBalance;
     send_market_action(ctx, auth, action).await
 }
@@ -264,43 +645,656 @@ async fn get_market_info(
     headers: HeaderMap,
     Json(request): Json<GetMarketInfoRequest>
 ) -> Result<impl IntoResponse, AppError> {
-    let auth = AuthHeaders::from_headers(&headers)?;
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
     let action = MarketAction::GetMarketInfo { market_id: request.market_id };
     send_market_action(ctx, auth, action).await
 }
 
+async fn add_comment(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<AddCommentRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::AddComment {
+        market_id: request.market_id,
+        text: request.text,
+    };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn get_treasury(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(_request): Json<GetTreasuryRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::GetTreasury;
+    send_market_action(ctx, auth, action).await
+}
+
+async fn get_user_stats(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(_request): Json<GetUserStatsRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::GetUserStats;
+    send_market_action(ctx, auth, action).await
+}
+
+/// Proves the caller's stake in markets not settled yet, and answers it
+/// read from the settled state.
+async fn get_open_exposure(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(_request): Json<GetOpenExposureRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let identity = sdk::Identity(auth.user.clone());
+    submit_market_action(ctx, auth, MarketAction::GetOpenExposure, move |state| Some(state.open_exposure(&identity))).await
+}
+
+async fn get_leaderboard(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<GetLeaderboardRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::GetLeaderboard { limit: request.limit };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn place_parlay(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<PlaceParlayRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::PlaceParlay {
+        legs: request.legs,
+        amount: request.amount,
+    };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn settle_parlay(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Json(request): Json<SettleParlayRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::SettleParlay { parlay_id: request.parlay_id };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn withdraw_treasury(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(request): Json<WithdrawTreasuryRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::WithdrawTreasury {
+        to: sdk::Identity(request.to),
+        amount: request.amount,
+    };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn reset_balances(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(_request): Json<ResetBalancesRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    send_market_action(ctx, auth, MarketAction::ResetBalances).await
+}
+
+async fn confiscate_balance(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(request): Json<ConfiscateBalanceRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let action = MarketAction::ConfiscateBalance { user: sdk::Identity(request.user) };
+    send_market_action(ctx, auth, action).await
+}
+
+async fn set_house_edge(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(request): Json<SetHouseEdgeRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    send_market_action(ctx, auth, MarketAction::SetHouseEdge { bps: request.bps }).await
+}
+
+async fn distribute_dividends(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(_request): Json<DistributeDividendsRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    send_market_action(ctx, auth, MarketAction::DistributeDividends).await
+}
+
+/// Stops every action changing the state, e.g. while a payout bug is
+/// investigated. Queries and the read routes keep working.
+async fn pause(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(_request): Json<PauseRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    send_market_action(ctx, auth, MarketAction::Pause).await
+}
+
+async fn unpause(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(_request): Json<UnpauseRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    send_market_action(ctx, auth, MarketAction::Unpause).await
+}
+
+/// Backs the state up: the contract checks the caller is its admin, and the
+/// settled state comes back as a snapshot `/api/admin/import` accepts.
+async fn export_snapshot(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(_request): Json<ExportSnapshotRequest>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    submit_market_action(ctx, auth, MarketAction::ExportSnapshot, |state| Some(snapshot_of(state))).await
+}
+
+/// Restores an exported snapshot onto a freshly deployed, empty contract.
+async fn import_snapshot(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    _admin: AdminKey,
+    Json(snapshot): Json<SnapshotExport>
+) -> Result<impl IntoResponse, AppError> {
+    let auth = AuthHeaders::from_headers(&headers, &ctx.identities)?;
+    let data = snapshot_data(&snapshot).map_err(|e| AppError(StatusCode::BAD_REQUEST, e.context("invalid snapshot")))?;
+    send_market_action(ctx, auth, MarketAction::ImportSnapshot { data }).await
+}
+
+#[derive(Serialize)]
+struct DeployResponse {
+    /// What the server for the new group takes as `--contract1-cn`
+    contract_name: String,
+    program_id: String,
+    verifier: String,
+    state_epoch: u64,
+}
+
+/// The state a contract named `contract_name` is registered with: empty,
+/// under `state_epoch` and administered by `<admin>@<contract_name>`.
+pub fn initial_state(contract_name: &ContractName, admin: &str, state_epoch: u64) -> Contract1 {
+    let mut state = Contract1::with_epoch(state_epoch);
+    state.admin = Some(sdk::Identity(format!("{}@{}", admin, contract_name.0)));
+    state
+}
+
+/// Registers a fresh contract under a new name, e.g. for another group. Its
+/// transactions are proved by a server started with `--contract1-cn` set to it.
+async fn deploy_contract(
+    State(ctx): State<RouterCtx>,
+    _admin: AdminKey,
+    Json(request): Json<DeployRequest>
+) -> Result<impl IntoResponse, AppError> {
+    // Without one, whoever sent SetAdmin first would own the contract
+    let Some(admin) = ctx.contract_admin.clone() else {
+        return Err(AppError(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("No contract admin is configured on this server"),
+        ));
+    };
+    let name = request.contract_name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return Err(AppError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Contract names are made of letters, digits, '-', '_' and '.'"),
+        ));
+    }
+    let contract_name = ContractName(name.to_string());
+
+    // Held until the registration is sent, so two requests cannot race for one name
+    let mut deployed = ctx.deployed.lock().await;
+    let taken = contract_name == ctx.contract1_cn
+        || deployed.contains(&contract_name)
+        || ctx.client.get_contract_state(&contract_name).await.is_ok();
+    if taken {
+        return Err(AppError(StatusCode::CONFLICT, anyhow::anyhow!("Contract '{}' already exists", name)));
+    }
+
+    // A fresh epoch tells clients this deployment's market ids apart from earlier ones
+    let state_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    ctx.client
+        .register_contract(APIRegisterContract {
+            verifier: VERIFIER.into(),
+            program_id: ProgramId(PROGRAM_ID.to_vec()),
+            state_commitment: initial_state(&contract_name, &admin, state_epoch).commit(),
+            contract_name: contract_name.clone(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| AppError(StatusCode::BAD_GATEWAY, e.context("registering the contract")))?;
+    deployed.insert(contract_name.clone());
+    info!("Registered contract {} (epoch {})", name, state_epoch);
+
+    Ok(Json(DeployResponse {
+        contract_name: contract_name.0,
+        program_id: hex::encode(PROGRAM_ID),
+        verifier: VERIFIER.to_string(),
+        state_epoch,
+    }))
+}
+
+// --------------------------------------------------------
+//     Read-only routes
+// --------------------------------------------------------
+
+/// Indexed reads change with every settled transaction, so caches may only
+/// hold them briefly
+const READ_CACHE_CONTROL: &str = "public, max-age=5";
+const MARKETS_PAGE_SIZE: usize = 20;
+const HISTORY_DEFAULT_LIMIT: u32 = 50;
+const HISTORY_MAX_LIMIT: u32 = 500;
+
+#[derive(serde::Deserialize)]
+struct MarketsQuery {
+    status: Option<MarketStatusFilter>,
+    tag: Option<String>,
+    #[serde(default)]
+    page: usize,
+}
+
+#[derive(Serialize)]
+struct MarketsPage {
+    markets: Vec<MarketSummary>,
+    page: usize,
+    page_size: usize,
+    total: usize,
+}
+
+#[derive(serde::Deserialize)]
+struct LeaderboardQuery {
+    limit: Option<u32>,
+}
+
+#[derive(serde::Deserialize)]
+struct HistoryQuery {
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct HistoryResponse {
+    identity: String,
+    entries: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize)]
+struct BalanceResponse {
+    identity: String,
+    balance: u128,
+    initialized: bool,
+}
+
+/// Runs `read` against the indexed state and wraps the result with cache headers.
+async fn read_indexed<T: Serialize>(
+    ctx: &RouterCtx,
+    read: impl FnOnce(&Contract1) -> Result<T, AppError>,
+) -> Result<impl IntoResponse, AppError> {
+    let indexed = ctx.indexed.read().await;
+    let state = indexed.as_ref().ok_or_else(|| not_indexed_yet(ctx))?;
+    let body = read(state)?;
+    Ok(([(header::CACHE_CONTROL, READ_CACHE_CONTROL)], Json(body)))
+}
+
+fn not_indexed_yet(ctx: &RouterCtx) -> AppError {
+    AppError(
+        StatusCode::SERVICE_UNAVAILABLE,
+        anyhow::anyhow!("No state indexed yet for contract '{}'", ctx.contract1_cn.0),
+    )
+}
+
+/// Scheduled markets are presented against the server's clock.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn market_not_found(id: u64) -> AppError {
+    AppError(StatusCode::NOT_FOUND, anyhow::anyhow!("Market {} not found", id))
+}
+
+async fn read_market(
+    State(ctx): State<RouterCtx>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    read_indexed(&ctx, |state| {
+        state
+            .markets
+            .get(&id)
+            .map(|market| MarketSummary::of(market, unix_now()))
+            .ok_or_else(|| market_not_found(id))
+    })
+    .await
+}
+
+async fn read_odds(
+    State(ctx): State<RouterCtx>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    read_indexed(&ctx, |state| state.odds(id).ok_or_else(|| market_not_found(id))).await
+}
+
+async fn read_market_history(
+    State(ctx): State<RouterCtx>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    read_indexed(&ctx, |state| state.market_history(id).ok_or_else(|| market_not_found(id))).await
+}
+
+async fn read_markets(
+    State(ctx): State<RouterCtx>,
+    Query(query): Query<MarketsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    read_indexed(&ctx, |state| {
+        let markets = state.list_markets(&MarketFilter { status: query.status, tag: query.tag }, unix_now());
+        Ok(MarketsPage {
+            total: markets.len(),
+            markets: markets
+                .into_iter()
+                .skip(query.page.saturating_mul(MARKETS_PAGE_SIZE))
+                .take(MARKETS_PAGE_SIZE)
+                .collect(),
+            page: query.page,
+            page_size: MARKETS_PAGE_SIZE,
+        })
+    })
+    .await
+}
+
+/// Server-sent events: one message per settled transaction with market
+/// events, as delivered by the webhook. A reconnecting client sends the last
+/// id it saw in `Last-Event-ID` to get what it missed meanwhile.
+async fn stream_events(
+    State(ctx): State<RouterCtx>,
+    headers: HeaderMap,
+    Query(filter): Query<EventFilter>,
+) -> impl IntoResponse {
+    let last_event_id = headers.get("last-event-id").and_then(|value| value.to_str().ok());
+    Sse::new(ctx.events.subscribe(filter, last_event_id)).keep_alive(KeepAlive::default())
+}
+
+/// One document with everything a static dashboard shows. Its ETag hashes
+/// the body, so a page polling with `If-None-Match` gets a bodiless 304
+/// until the state changes.
+async fn read_snapshot(State(ctx): State<RouterCtx>, headers: HeaderMap) -> Result<Response, AppError> {
+    let body = {
+        let indexed = ctx.indexed.read().await;
+        let state = indexed.as_ref().ok_or_else(|| not_indexed_yet(&ctx))?;
+        serde_json::to_vec(&state.snapshot()).map_err(|e| AppError(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?
+    };
+    let etag = format!("\"{}\"", hex::encode(&Sha256::digest(&body)[..16]));
+    let unchanged = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        });
+    let cache_headers = [(header::CACHE_CONTROL, READ_CACHE_CONTROL.to_string()), (header::ETAG, etag)];
+    if unchanged {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+    Ok((cache_headers, [(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+async fn read_balance(
+    State(ctx): State<RouterCtx>,
+    Path(identity): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    read_indexed(&ctx, |state| {
+        let user = state.user_info(&sdk::Identity(identity));
+        Ok(BalanceResponse {
+            identity: user.identity,
+            balance: user.balance,
+            initialized: user.initialized,
+        })
+    })
+    .await
+}
+
+async fn read_leaderboard(
+    State(ctx): State<RouterCtx>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = query.limit.unwrap_or(LEADERBOARD_DEFAULT_LIMIT) as usize;
+    read_indexed(&ctx, |state| Ok(state.leaderboard(limit.min(MAX_LEADERBOARD_LIMIT)))).await
+}
+
+/// Actions submitted through this server for `identity`, oldest first. Served
+/// from the history store rather than the indexed state.
+async fn read_history(
+    State(ctx): State<RouterCtx>,
+    Path(identity): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let history = ctx.history.as_ref().ok_or_else(|| {
+        AppError(StatusCode::NOT_FOUND, anyhow::anyhow!("Action history is disabled"))
+    })?;
+    let limit = query.limit.unwrap_or(HISTORY_DEFAULT_LIMIT).clamp(1, HISTORY_MAX_LIMIT);
+    let entries = history
+        .for_identity(&identity, limit)
+        .await
+        .map_err(|e| AppError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(HistoryResponse { identity, entries }))
+}
+
+/// Outcome of a transaction submitted through this server, so a client that
+/// got a 202 or lost the answer can find out whether it went through.
+async fn read_tx_status(
+    State(ctx): State<RouterCtx>,
+    Path(hash): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let history = ctx.history.as_ref().ok_or_else(|| {
+        AppError(StatusCode::NOT_FOUND, anyhow::anyhow!("Action history is disabled"))
+    })?;
+    let status = history
+        .status(&hash)
+        .await
+        .map_err(|e| AppError(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| AppError(StatusCode::NOT_FOUND, anyhow::anyhow!("Unknown transaction {}", hash)))?;
+    Ok(Json(status))
+}
+
+/// Diff between an uploaded ledger snapshot (the bot's database) and the
+/// indexed state, for operators looking for drift.
+async fn reconcile(
+    State(ctx): State<RouterCtx>,
+    _admin: AdminKey,
+    Json(snapshot): Json<ReconcileSnapshot>,
+) -> Result<impl IntoResponse, AppError> {
+    let indexed = ctx.indexed.read().await;
+    let state = indexed.as_ref().ok_or_else(|| not_indexed_yet(&ctx))?;
+    Ok(Json(state.reconcile(&snapshot)))
+}
+
+/// Size and contents of the indexed state, to see it coming before it hits
+/// the caps that bound proof size.
+async fn read_state_stats(State(ctx): State<RouterCtx>, _admin: AdminKey) -> Result<impl IntoResponse, AppError> {
+    let indexed = ctx.indexed.read().await;
+    let state = indexed.as_ref().ok_or_else(|| not_indexed_yet(&ctx))?;
+    Ok(Json(state.state_stats()))
+}
 
 async fn get_config(State(ctx): State<RouterCtx>) -> impl IntoResponse {
+    let indexed = ctx.indexed.read().await;
+    // The house edge is the one parameter the admin changes at runtime
+    let params = ContractParams {
+        fee_bps: indexed.as_ref().map_or(0, |state| u32::from(state.house_edge_bps)),
+        ..ContractParams::default()
+    };
     Json(ConfigResponse {
         contract_name: ctx.contract1_cn.0,
+        api_version: API_VERSION,
+        params,
+        state_epoch: indexed.as_ref().map(|state| state.state_epoch),
+        paused: indexed.as_ref().is_some_and(|state| state.paused),
     })
 }
 
+/// Body of a settled action that reports more than its hash.
+#[derive(Serialize)]
+struct SettledAction<T> {
+    tx_hash: TxHash,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<T>,
+    /// Set when the action left the state as it was, e.g. a query
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unchanged: bool,
+}
+
 async fn send_market_action(
     ctx: RouterCtx,
     auth: AuthHeaders,
     action: MarketAction,
-) -> Result<impl IntoResponse, AppError> {
+) -> Result<Response, AppError> {
+    submit_market_action(ctx, auth, action, |_| None::<()>).await
+}
+
+/// Submits `action` and waits for it to settle. When `result` reads
+/// something from the settled state, it is sent alongside the hash.
+async fn submit_market_action<T: Serialize>(
+    ctx: RouterCtx,
+    auth: AuthHeaders,
+    action: MarketAction,
+    result: impl FnOnce(&Contract1) -> Option<T>,
+) -> Result<Response, AppError> {
+    if ctx.contract_check.is_degraded() {
+        return Err(AppError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            anyhow::anyhow!("contract state incompatible"),
+        ));
+    }
+    // Counted until the response is sent, settled or not. Past the limits
+    // nothing is read or sent, so a prover falling behind only costs this
+    let _in_flight = match ctx.backpressure.admit(action.is_read_only()) {
+        Ok(in_flight) => in_flight,
+        Err(overloaded) => {
+            warn!(request_id = %auth.request_id, "Refusing {:?} with {} submissions in flight", action, overloaded.in_flight);
+            let retry_after = [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())];
+            return Ok((StatusCode::SERVICE_UNAVAILABLE, retry_after, "too many transactions in flight, retry later").into_response());
+        }
+    };
     let identity = auth.user.clone();
+    info!(request_id = %auth.request_id, "Submitting {:?} for {}", action, identity);
 
     // Create the blob with the action
     let action_blob = action.as_blob(ctx.contract1_cn.clone());
+    // Read-only actions change nothing, so repeating them is harmless
+    let duplicate_key = (!action.is_read_only())
+        .then(|| RecentSubmissions::key(&identity, &action_blob.data.0));
     
     // Debug: print what we're sending
     eprintln!("Sending action: {:?}", action);
     eprintln!("Action blob contract_name: {:?}", action_blob.contract_name);
     eprintln!("Action blob data length: {}", action_blob.data.0.len());
     eprintln!("Action blob data (hex): {}", hex::encode(&action_blob.data.0));
-    
-    // Send just the action blob
-    let blobs = vec![action_blob];
 
-    let res = ctx
-        .client
-        .send_tx_blob(BlobTransaction::new(identity.clone(), blobs))
-        .await;
+    // Subscribe before submitting so a fast prover cannot settle the
+    // transaction before we are listening
+    let mut bus = {
+        let bus = ctx.bus.lock().await;
+        AppModuleBusClient::new_from_bus(bus.new_handle()).await
+    };
+
+    // Actions changing the state carry the sender's next nonce, so the
+    // contract refuses the blob if it is ever replayed. Until a state is
+    // indexed the nonce is unknown and the action is sent without one.
+    let (tx, dry_run_tx) = {
+        // Held while reserving: a settled transaction frees its nonce only
+        // once the indexed state counts it
+        let indexed = ctx.indexed.read().await;
+        match indexed.as_ref().filter(|_| !action.is_read_only()) {
+            Some(state) => {
+                let indexed_next = state.next_nonce(&sdk::Identity(identity.clone()));
+                ctx.nonces.reserve(&identity, indexed_next, |nonce| {
+                    let nonced = |nonce| {
+                        let blob = action.clone().with_nonce(nonce).as_blob(ctx.contract1_cn.clone());
+                        BlobTransaction::new(identity.clone(), vec![blob])
+                    };
+                    let tx = nonced(nonce);
+                    let tx_hash = tx.hashed();
+                    // The dry run sees the indexed state, which expects its own next nonce
+                    ((tx, nonced(indexed_next)), tx_hash)
+                })
+            }
+            None => {
+                let tx = BlobTransaction::new(identity.clone(), vec![action_blob]);
+                (tx.clone(), tx)
+            }
+        }
+    };
+    let submitted_hash = tx.hashed();
+    if let Some(key) = duplicate_key {
+        if let Some(original) = ctx.recent_submissions.claim(key, &submitted_hash, Instant::now()) {
+            info!(request_id = %auth.request_id, "Duplicate of transaction {}, not resubmitted", original);
+            ctx.nonces.release(&submitted_hash);
+            return Ok(Json(original).into_response());
+        }
+    }
+    // Actions the contract would refuse are answered without being proved
+    let dry_run = {
+        let indexed = ctx.indexed.read().await;
+        ctx.precheck.check(indexed.as_ref(), &dry_run_tx, &submitted_hash)
+    };
+    let changes_state = match dry_run {
+        // Without a dry run, only queries are known to leave the state as it was
+        Ok(changes_state) => changes_state.unwrap_or(!action.is_read_only()),
+        Err(error) => {
+            info!(request_id = %auth.request_id, "Dry run refused {:?}: {}", action, error);
+            ctx.nonces.release(&submitted_hash);
+            if let Some(key) = &duplicate_key {
+                ctx.recent_submissions.forget(key);
+            }
+            return Err(AppError(StatusCode::UNPROCESSABLE_ENTITY, anyhow::anyhow!(error)));
+        }
+    };
+    // Recorded before submitting: the outcome can be observed before the node answers
+    if let Some(history) = &ctx.history {
+        history.submitted(submitted_hash.clone(), &identity, &action);
+    }
+    let res = ctx.client.send_tx_blob(tx).await;
 
     if let Err(ref e) = res {
+        if let Some(history) = &ctx.history {
+            history.abandoned(&submitted_hash);
+        }
+        ctx.precheck.abandoned(&submitted_hash);
+        ctx.nonces.release(&submitted_hash);
+        // Nothing was sent, so an identical retry must go through
+        if let Some(key) = &duplicate_key {
+            ctx.recent_submissions.forget(key);
+        }
         let root_cause = e.root_cause().to_string();
         return Err(AppError(
             StatusCode::BAD_REQUEST,
@@ -309,27 +1303,38 @@ async fn send_market_action(
     }
 
     let tx_hash = res.unwrap();
+    info!(request_id = %auth.request_id, "Submitted transaction {}", tx_hash);
 
-    let mut bus = {
-        let bus = ctx.bus.lock().await;
-        AppModuleBusClient::new_from_bus(bus.new_handle()).await
-    };
-
-    tokio::time::timeout(Duration::from_secs(5), async {
+    let settled = tokio::time::timeout(Duration::from_secs(5), async {
         loop {
             match bus.recv().await? {
-                AutoProverEvent::<Contract1>::SuccessTx(sequenced_tx_hash, _) => {
+                AutoProverEvent::<Contract1>::SuccessTx(sequenced_tx_hash, state) => {
                     if sequenced_tx_hash == tx_hash {
-                        return Ok(Json(sequenced_tx_hash));
+                        return Ok((sequenced_tx_hash, state));
                     }
                 }
                 AutoProverEvent::<Contract1>::FailedTx(sequenced_tx_hash, error) => {
                     if sequenced_tx_hash == tx_hash {
+                        // A rejected action may succeed once the state changes
+                        if let Some(key) = &duplicate_key {
+                            ctx.recent_submissions.forget(key);
+                        }
                         return Err(AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(error)));
                     }
                 }
             }
         }
     })
-    .await?
+    .await;
+
+    match settled {
+        Ok(res) => res.map(|(tx_hash, state)| match (result(&state), changes_state) {
+            (None, true) => Json(tx_hash).into_response(),
+            (result, changes_state) => {
+                Json(SettledAction { tx_hash, result, unchanged: !changes_state }).into_response()
+            }
+        }),
+        // The transaction was submitted but not settled yet: let the client know it is pending
+        Err(_) => Ok((StatusCode::ACCEPTED, Json(tx_hash)).into_response()),
+    }
 }
@@ -0,0 +1,109 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use prometheus::{IntGauge, Registry};
+use serde::Serialize;
+
+/// Seconds a client refused for load is told to wait, about how long a
+/// submission waits for its transaction to settle.
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+/// How many submissions may be in flight at once, i.e. sent or about to be
+/// and still waiting to settle. 0 disables a limit.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SubmissionLimits {
+    /// Past it, actions changing the state are refused
+    pub soft: usize,
+    /// Past it, every submission is refused, read-only actions proved
+    /// on-chain included
+    pub hard: usize,
+}
+
+impl SubmissionLimits {
+    pub const UNLIMITED: Self = Self { soft: 0, hard: 0 };
+}
+
+/// Counts the submissions in flight, so a prover falling behind does not
+/// pile up waiting requests without bound.
+pub struct Backpressure {
+    limits: SubmissionLimits,
+    in_flight: AtomicUsize,
+    gauge: Option<IntGauge>,
+}
+
+/// A submission refused for load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overloaded {
+    pub in_flight: usize,
+}
+
+impl Backpressure {
+    /// Registers the depth gauge in `registry` when there is one.
+    pub fn new(limits: SubmissionLimits, registry: Option<&Registry>) -> Result<Self> {
+        let gauge = registry
+            .map(|registry| {
+                let gauge = IntGauge::new(
+                    "contract1_submissions_in_flight",
+                    "Submissions waiting for their transaction to settle",
+                )?;
+                registry
+                    .register(Box::new(gauge.clone()))
+                    .context("registering the submission gauge")?;
+                anyhow::Ok(gauge)
+            })
+            .transpose()?;
+        Ok(Self {
+            limits,
+            in_flight: AtomicUsize::new(0),
+            gauge,
+        })
+    }
+
+    pub fn limits(&self) -> SubmissionLimits {
+        self.limits
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Whether actions changing the state are being refused.
+    pub fn is_saturated(&self) -> bool {
+        reached(self.in_flight(), self.limits.soft)
+    }
+
+    /// Counts a new submission in until the returned guard drops, unless
+    /// the limit for its kind is reached.
+    pub fn admit(self: &Arc<Self>, read_only: bool) -> Result<InFlight, Overloaded> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if reached(in_flight, self.limits.hard)
+            || (!read_only && reached(in_flight, self.limits.soft))
+        {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            return Err(Overloaded { in_flight });
+        }
+        self.observe();
+        Ok(InFlight(self.clone()))
+    }
+
+    fn observe(&self) {
+        if let Some(gauge) = &self.gauge {
+            gauge.set(self.in_flight() as i64);
+        }
+    }
+}
+
+fn reached(in_flight: usize, limit: usize) -> bool {
+    limit > 0 && in_flight >= limit
+}
+
+/// A submission counted in flight until dropped.
+pub struct InFlight(Arc<Backpressure>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.0.observe();
+    }
+}
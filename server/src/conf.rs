@@ -2,6 +2,8 @@ use config::{Config, Environment, File};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::precheck::PrecheckMode;
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Conf {
     pub id: String,
@@ -16,6 +18,38 @@ pub struct Conf {
 
     pub rest_server_port: u16,
     pub rest_server_max_body_size: usize,
+    /// Largest request body the market routes accept; bigger ones get a 413
+    pub api_max_body_size: usize,
+    /// Gzip or brotli-compress market route responses when the client accepts it
+    pub api_compression: bool,
+
+    /// User administering the contracts this server registers, e.g. a
+    /// Telegram user id: each one starts with `<contract_admin>@<contract>`
+    /// as its admin, so nobody else can claim it with SetAdmin
+    pub contract_admin: Option<String>,
+
+    /// Bets and resolutions are POSTed here when set, signed with `bot_webhook_secret`
+    pub bot_webhook_url: Option<String>,
+    pub bot_webhook_secret: Option<String>,
+
+    /// Days the per-identity action history is kept; 0 keeps it forever
+    pub history_retention_days: u64,
+
+    /// Seconds during which resubmitting the same action answers with the
+    /// first transaction instead of sending another; 0 disables the check
+    pub duplicate_window_secs: u64,
+
+    /// Dry run actions against the indexed state before submitting them:
+    /// "enforce" refuses those the contract would reject, "advisory" only
+    /// logs when the dry run was wrong, "off" skips it
+    pub precheck: PrecheckMode,
+
+    /// Submissions waiting for their transaction past which actions changing
+    /// the state get a 503 with `Retry-After`; 0 disables the limit
+    pub submission_soft_limit: usize,
+    /// Submissions waiting for their transaction past which every action,
+    /// read-only ones included, gets a 503; 0 disables the limit
+    pub submission_hard_limit: usize,
 
     pub buffer_blocks: u32,
     pub max_txs_per_proof: usize,
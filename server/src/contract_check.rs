@@ -0,0 +1,90 @@
+use contract1::Contract1;
+use sdk::ContractName;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+
+use crate::app::TxSubmitter;
+
+/// What the node reported for the configured contract at startup.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ContractCheck {
+    /// The committed state decodes as a `Contract1`
+    Ok {
+        contract_name: String,
+        /// Hex SHA-256 of the committed state, to compare deployments
+        state_hash: String,
+        state_bytes: usize,
+        markets: usize,
+        users: usize,
+    },
+    /// The node did not return the contract: not registered under this
+    /// name, or the node was unreachable
+    Unavailable {
+        contract_name: String,
+        error: String,
+    },
+    /// The contract exists but its state is not a `Contract1`
+    Incompatible {
+        contract_name: String,
+        error: String,
+    },
+}
+
+impl ContractCheck {
+    /// Mutating routes are refused while the state cannot be decoded: every
+    /// transaction would fail in the prover.
+    pub fn is_degraded(&self) -> bool {
+        matches!(self, ContractCheck::Incompatible { .. })
+    }
+}
+
+/// Fetches the contract's state commitment from the node and decodes it.
+pub async fn check_contract(
+    client: &dyn TxSubmitter,
+    contract_name: &ContractName,
+) -> ContractCheck {
+    let name = contract_name.0.clone();
+    let commitment = match client.get_contract_state(contract_name).await {
+        Ok(commitment) => commitment,
+        Err(e) => {
+            warn!("Contract {} is not available on the node: {:#}", name, e);
+            return ContractCheck::Unavailable {
+                contract_name: name,
+                error: format!("{:#}", e),
+            };
+        }
+    };
+
+    let state_bytes = commitment.0.len();
+    let state_hash = hex::encode(Sha256::digest(&commitment.0));
+    match Contract1::try_from(commitment) {
+        Ok(state) => {
+            info!(
+                "Contract {} state {} decodes: {} markets, {} users",
+                name,
+                state_hash,
+                state.markets.len(),
+                state.users.len()
+            );
+            ContractCheck::Ok {
+                contract_name: name,
+                state_hash,
+                state_bytes,
+                markets: state.markets.len(),
+                users: state.users.len(),
+            }
+        }
+        Err(e) => {
+            error!(
+                "Contract {} state {} does not decode, refusing transactions: {}",
+                name, state_hash, e
+            );
+            ContractCheck::Incompatible {
+                contract_name: name,
+                error: e,
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{bail, Context, Result};
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// Which browser origins may call the API.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AllowedOrigins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsConfig {
+    pub origins: AllowedOrigins,
+    pub methods: Vec<Method>,
+    /// `None` allows any request header
+    pub headers: Option<Vec<HeaderName>>,
+    pub allow_credentials: bool,
+}
+
+impl CorsConfig {
+    /// Any origin, method and header: only for local development.
+    pub fn permissive() -> Self {
+        Self {
+            origins: AllowedOrigins::Any,
+            methods: vec![Method::GET, Method::POST],
+            headers: None,
+            allow_credentials: false,
+        }
+    }
+
+    /// Reads `CORS_ALLOWED_ORIGINS`, `CORS_ALLOWED_METHODS`,
+    /// `CORS_ALLOWED_HEADERS` and `CORS_ALLOW_CREDENTIALS` (comma-separated
+    /// lists and a boolean). `CORS_ALLOW_ANY=1` restores the permissive
+    /// layer instead. Release builds refuse to start with neither set.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        if var("CORS_ALLOW_ANY").is_some_and(|value| is_enabled(&value)) {
+            return Ok(Self::permissive());
+        }
+
+        let Some(origins) = var("CORS_ALLOWED_ORIGINS") else {
+            if cfg!(debug_assertions) {
+                tracing::warn!(
+                    "CORS_ALLOWED_ORIGINS is not set, allowing any origin in this debug build"
+                );
+                return Ok(Self::permissive());
+            }
+            bail!("Set CORS_ALLOWED_ORIGINS to a comma-separated list of origins, or CORS_ALLOW_ANY=1 to allow any origin");
+        };
+        let origins = split(&origins)
+            .map(|origin| {
+                if origin == "*" {
+                    bail!(
+                        "CORS_ALLOWED_ORIGINS cannot contain \"*\", set CORS_ALLOW_ANY=1 instead"
+                    );
+                }
+                HeaderValue::from_str(origin)
+                    .with_context(|| format!("invalid CORS origin {:?}", origin))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if origins.is_empty() {
+            bail!("CORS_ALLOWED_ORIGINS is empty");
+        }
+
+        let methods = match var("CORS_ALLOWED_METHODS") {
+            Some(methods) => split(&methods)
+                .map(|method| {
+                    Method::from_bytes(method.to_uppercase().as_bytes())
+                        .with_context(|| format!("invalid CORS method {:?}", method))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => vec![Method::GET, Method::POST],
+        };
+
+        let headers = match var("CORS_ALLOWED_HEADERS") {
+            Some(headers) => split(&headers)
+                .map(|header| {
+                    HeaderName::from_bytes(header.to_lowercase().as_bytes())
+                        .with_context(|| format!("invalid CORS header {:?}", header))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => DEFAULT_HEADERS
+                .iter()
+                .map(|h| HeaderName::from_static(h))
+                .collect(),
+        };
+
+        Ok(Self {
+            origins: AllowedOrigins::List(origins),
+            methods,
+            headers: Some(headers),
+            allow_credentials: var("CORS_ALLOW_CREDENTIALS")
+                .is_some_and(|value| is_enabled(&value)),
+        })
+    }
+
+    pub fn layer(&self) -> CorsLayer {
+        let layer = CorsLayer::new()
+            .allow_methods(self.methods.clone())
+            .allow_credentials(self.allow_credentials);
+        let layer = match &self.origins {
+            AllowedOrigins::Any => layer.allow_origin(Any),
+            AllowedOrigins::List(origins) => layer.allow_origin(AllowOrigin::list(origins.clone())),
+        };
+        match &self.headers {
+            Some(headers) => layer.allow_headers(headers.clone()),
+            None => layer.allow_headers(Any),
+        }
+    }
+}
+
+/// Headers the API reads from browsers
+const DEFAULT_HEADERS: [&str; 6] = [
+    "content-type",
+    "authorization",
+    "x-user",
+    "x-wallet-timestamp",
+    "x-wallet-signature",
+    "x-request-id",
+];
+
+fn split(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn is_enabled(value: &str) -> bool {
+    matches!(value.to_lowercase().as_str(), "1" | "true" | "on")
+}
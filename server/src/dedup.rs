@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sdk::TxHash;
+use sha2::{Digest, Sha256};
+
+/// Identifies an action: a hash of the identity and the blob data.
+pub type SubmissionKey = [u8; 32];
+
+/// Actions submitted within the last `window`, so a client resending the same
+/// request (a double click, a retry after a timeout) gets the first
+/// transaction back instead of betting twice.
+pub struct RecentSubmissions {
+    window: Duration,
+    entries: Mutex<HashMap<SubmissionKey, (Instant, TxHash)>>,
+}
+
+impl RecentSubmissions {
+    /// A zero `window` disables the check.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn key(identity: &str, blob_data: &[u8]) -> SubmissionKey {
+        let mut hasher = Sha256::new();
+        // Length-prefixed so no identity/data split collides with another
+        hasher.update((identity.len() as u64).to_le_bytes());
+        hasher.update(identity.as_bytes());
+        hasher.update(blob_data);
+        hasher.finalize().into()
+    }
+
+    /// The transaction `key` was submitted as within the window, if any.
+    /// Otherwise `tx_hash` is recorded for `key` and `None` returned, so of
+    /// two concurrent identical requests only one goes through.
+    pub fn claim(&self, key: SubmissionKey, tx_hash: &TxHash, now: Instant) -> Option<TxHash> {
+        if self.window.is_zero() {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (at, _)| now.saturating_duration_since(*at) < self.window);
+        if let Some((_, original)) = entries.get(&key) {
+            return Some(original.clone());
+        }
+        entries.insert(key, (now, tx_hash.clone()));
+        None
+    }
+
+    /// Forgets `key`, for submissions that failed and may be retried as is.
+    pub fn forget(&self, key: &SubmissionKey) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
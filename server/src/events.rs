@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use axum::response::sse::Event;
+use contract1::api::{EventFilter, WebhookPayload};
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// Transactions remembered for subscribers resuming with `Last-Event-ID`.
+const REPLAY_CAPACITY: usize = 256;
+
+/// Transactions a slow subscriber may fall behind before it misses some.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Events of settled transactions, fanned out to the `/api/events`
+/// subscribers. Every message carries its transaction hash as SSE id, so a
+/// client that reconnects can resume after the last one it saw.
+pub struct EventFeed {
+    sender: broadcast::Sender<WebhookPayload>,
+    recent: Mutex<VecDeque<WebhookPayload>>,
+}
+
+impl Default for EventFeed {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            recent: Mutex::new(VecDeque::with_capacity(REPLAY_CAPACITY)),
+        }
+    }
+}
+
+impl EventFeed {
+    pub fn publish(&self, payload: WebhookPayload) {
+        {
+            let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+            if recent.len() == REPLAY_CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(payload.clone());
+        }
+        // Nobody may be listening
+        let _ = self.sender.send(payload);
+    }
+
+    /// The transactions published after `last_event_id`, while it is still
+    /// remembered, followed by the live ones.
+    pub fn subscribe(
+        &self,
+        filter: EventFilter,
+        last_event_id: Option<&str>,
+    ) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+        // Subscribing first: a transaction published meanwhile comes twice
+        // rather than never, and clients drop repeated ids
+        let receiver = self.sender.subscribe();
+        let replay: Vec<WebhookPayload> = match last_event_id {
+            Some(id) => {
+                let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+                match recent.iter().position(|payload| payload.tx_hash == id) {
+                    Some(seen) => recent.iter().skip(seen + 1).cloned().collect(),
+                    None => vec![],
+                }
+            }
+            None => vec![],
+        };
+
+        let live = futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(payload) => return Some((payload, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("An event subscriber fell behind and missed {} transactions", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        futures::stream::iter(replay).chain(live).filter_map(move |payload| {
+            let event = filter.apply(payload).and_then(|payload| {
+                Event::default()
+                    .id(payload.tx_hash.clone())
+                    .json_data(&payload)
+                    .ok()
+            });
+            async move { event.map(Ok) }
+        })
+    }
+}
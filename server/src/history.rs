@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use contract1::{
+    http::{TxState, TxStatus},
+    MarketAction,
+};
+use sdk::TxHash;
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{FromRow, SqlitePool};
+
+#[derive(Debug, Clone)]
+pub struct HistoryConfig {
+    /// SQLite url, e.g. `sqlite://data/history.db`
+    pub database_url: String,
+    /// Rows older than this are deleted; `None` keeps everything
+    pub retention_days: Option<u64>,
+}
+
+/// One action submitted through the server, as settled by the prover.
+#[derive(Debug, Clone, Serialize, FromRow, PartialEq)]
+pub struct HistoryEntry {
+    pub action: String,
+    pub market_id: Option<i64>,
+    /// Coins moved by the action, as a decimal string since it is a `u128`
+    pub amount: Option<String>,
+    pub tx_hash: String,
+    /// `success` or `failed`
+    pub result: String,
+    pub error: Option<String>,
+    /// Unix seconds at which the outcome was observed
+    pub timestamp: i64,
+}
+
+/// What is known about a transaction between its submission and its outcome.
+#[derive(Debug, Clone)]
+struct PendingAction {
+    identity: String,
+    action: &'static str,
+    market_id: Option<u64>,
+    amount: Option<u128>,
+}
+
+impl PendingAction {
+    fn of(identity: &str, action: &MarketAction) -> Self {
+        let (name, market_id, amount) = match action {
+            MarketAction::SetAdmin { .. } => ("set_admin", None, None),
+            MarketAction::Initialize { .. } => ("initialize", None, None),
+            MarketAction::CreateMarket { .. } => ("create_market", None, None),
+            MarketAction::PlaceBet {
+                market_id, amount, ..
+            } => ("place_bet", Some(*market_id), Some(*amount)),
+            MarketAction::ResolveMarket { market_id, .. } => {
+                ("resolve_market", Some(*market_id), None)
+            }
+            MarketAction::ClaimWinnings { market_id } => ("claim_winnings", Some(*market_id), None),
+            MarketAction::GetBalance => ("get_balance", None, None),
+            MarketAction::GetMarketInfo { market_id } => {
+                ("get_market_info", Some(*market_id), None)
+            }
+            MarketAction::WithdrawTreasury { amount, .. } => {
+                ("withdraw_treasury", None, Some(*amount))
+            }
+            MarketAction::GetTreasury => ("get_treasury", None, None),
+            MarketAction::AddComment { market_id, .. } => ("add_comment", Some(*market_id), None),
+            MarketAction::GetUserStats => ("get_user_stats", None, None),
+            MarketAction::GetOpenExposure => ("get_open_exposure", None, None),
+            MarketAction::GetLeaderboard { .. } => ("get_leaderboard", None, None),
+            MarketAction::PlaceParlay { amount, .. } => ("place_parlay", None, Some(*amount)),
+            MarketAction::SettleParlay { .. } => ("settle_parlay", None, None),
+            MarketAction::CloseBetting { market_id } => ("close_betting", Some(*market_id), None),
+            MarketAction::GetMarketHistory { market_id } => {
+                ("get_market_history", Some(*market_id), None)
+            }
+            MarketAction::ResetBalances => ("reset_balances", None, None),
+            MarketAction::AcceptChallenge { market_id } => {
+                ("accept_challenge", Some(*market_id), None)
+            }
+            MarketAction::CancelChallenge { market_id } => {
+                ("cancel_challenge", Some(*market_id), None)
+            }
+            MarketAction::ExpireMarket { market_id } => ("expire_market", Some(*market_id), None),
+            MarketAction::ExportSnapshot => ("export_snapshot", None, None),
+            MarketAction::ImportSnapshot { .. } => ("import_snapshot", None, None),
+            MarketAction::ConfiscateBalance { .. } => ("confiscate_balance", None, None),
+            MarketAction::RequireNonces { .. } => ("require_nonces", None, None),
+            MarketAction::SetHouseEdge { .. } => ("set_house_edge", None, None),
+            MarketAction::DistributeDividends => ("distribute_dividends", None, None),
+            MarketAction::Pause => ("pause", None, None),
+            MarketAction::Unpause => ("unpause", None, None),
+            MarketAction::Nonced { action, .. } => return Self::of(identity, action),
+        };
+        Self {
+            identity: identity.to_string(),
+            action: name,
+            market_id,
+            amount,
+        }
+    }
+}
+
+/// Per-identity log of the actions submitted through the server. Only the
+/// transaction hash comes back with the prover's outcome, so each action is
+/// remembered from submission until its outcome is observed.
+pub struct HistoryStore {
+    pool: SqlitePool,
+    pending: Mutex<HashMap<TxHash, PendingAction>>,
+    retention_days: Option<u64>,
+}
+
+impl HistoryStore {
+    pub async fn open(config: &HistoryConfig) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(&config.database_url)?.create_if_missing(true);
+        // A single connection: writes come from the module loop one at a
+        // time, and it keeps `sqlite::memory:` databases alive
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS action_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                identity TEXT NOT NULL,
+                action TEXT NOT NULL,
+                market_id INTEGER,
+                amount TEXT,
+                tx_hash TEXT NOT NULL,
+                result TEXT NOT NULL,
+                error TEXT,
+                timestamp INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_action_history_identity ON action_history(identity, id)",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Self {
+            pool,
+            pending: Mutex::new(HashMap::new()),
+            retention_days: config.retention_days,
+        })
+    }
+
+    /// Remembers `action` until the outcome of `tx_hash` is recorded.
+    pub fn submitted(&self, tx_hash: TxHash, identity: &str, action: &MarketAction) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(tx_hash, PendingAction::of(identity, action));
+    }
+
+    /// Forgets a transaction the node refused.
+    pub fn abandoned(&self, tx_hash: &TxHash) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(tx_hash);
+    }
+
+    /// Stores the outcome of `tx_hash`; transactions submitted elsewhere are ignored.
+    pub async fn settled(&self, tx_hash: &TxHash, error: Option<&str>) -> Result<()> {
+        let Some(pending) = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(tx_hash)
+        else {
+            return Ok(());
+        };
+        sqlx::query(
+            "INSERT INTO action_history (identity, action, market_id, amount, tx_hash, result, error, timestamp)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&pending.identity)
+        .bind(pending.action)
+        .bind(pending.market_id.map(|id| id as i64))
+        .bind(pending.amount.map(|amount| amount.to_string()))
+        .bind(tx_hash.to_string())
+        .bind(if error.is_some() { "failed" } else { "success" })
+        .bind(error)
+        .bind(unix_now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Where `tx_hash` stands, or `None` if it was not submitted through this
+    /// server or its outcome was pruned. Pending transactions are forgotten on
+    /// restart, so they are unknown afterwards too.
+    pub async fn status(&self, tx_hash: &str) -> Result<Option<TxStatus>> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .any(|pending| pending.to_string() == tx_hash);
+        if pending {
+            return Ok(Some(TxStatus {
+                tx_hash: tx_hash.to_string(),
+                status: TxState::Pending,
+                error: None,
+            }));
+        }
+        let settled: Option<(String, Option<String>)> = sqlx::query_as(
+            "SELECT result, error FROM action_history WHERE tx_hash = ? ORDER BY id DESC LIMIT 1",
+        )
+        .bind(tx_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(settled.map(|(result, error)| TxStatus {
+            tx_hash: tx_hash.to_string(),
+            status: if result == "success" { TxState::Success } else { TxState::Failed },
+            error,
+        }))
+    }
+
+    /// The latest `limit` entries of `identity`, oldest first.
+    pub async fn for_identity(&self, identity: &str, limit: u32) -> Result<Vec<HistoryEntry>> {
+        let mut entries = sqlx::query_as::<_, HistoryEntry>(
+            "SELECT action, market_id, amount, tx_hash, result, error, timestamp
+             FROM action_history WHERE identity = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(identity)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Deletes entries past the retention period, returning how many were removed.
+    pub async fn prune(&self) -> Result<u64> {
+        let Some(days) = self.retention_days else {
+            return Ok(0);
+        };
+        let cutoff = unix_now() - Duration::from_secs(days * 24 * 60 * 60).as_secs() as i64;
+        let result = sqlx::query("DELETE FROM action_history WHERE timestamp < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use axum::http::{header, HeaderMap};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sdk::{ContractName, Identity};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, Secp256k1};
+use serde::Deserialize;
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+/// Header the bot, and wallet clients, name their user in.
+pub const USER_HEADER: &str = "x-user";
+
+/// Unix time, in seconds, a wallet client signed its request at.
+pub const WALLET_TIMESTAMP_HEADER: &str = "x-wallet-timestamp";
+
+/// The `personal_sign` signature of [`wallet_message`] by the wallet in
+/// `x-user`, 65 hex-encoded bytes.
+pub const WALLET_SIGNATURE_HEADER: &str = "x-wallet-signature";
+
+/// Seconds a wallet signature is accepted either side of its timestamp.
+pub const WALLET_SIGNATURE_WINDOW_SECS: u64 = 300;
+
+/// Seconds of clock skew tolerated on `exp` and `nbf`.
+const LEEWAY_SECS: u64 = 30;
+
+/// Shortest HS256 secret accepted: as long as the hash.
+const MIN_SECRET_BYTES: usize = 32;
+
+/// Why the credentials a request carries are refused.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IdentityError {
+    #[error("Missing signature")]
+    Missing,
+    #[error("malformed token")]
+    Malformed,
+    #[error("unsupported token algorithm {0:?}")]
+    Algorithm(String),
+    #[error("invalid token signature")]
+    Signature,
+    #[error("token expired")]
+    Expired,
+    #[error("token not valid yet")]
+    NotYetValid,
+    #[error("invalid token subject {0:?}")]
+    Subject(String),
+    #[error("invalid wallet address {0:?}")]
+    Wallet(String),
+    #[error("missing wallet signature")]
+    WalletUnsigned,
+    #[error("invalid wallet signature")]
+    WalletSignature,
+    #[error("wallet signature expired")]
+    WalletExpired,
+}
+
+/// Turns the credentials of a request into the identity its blobs are sent
+/// as, e.g. `42@contract1`.
+pub trait IdentityProvider: Send + Sync {
+    /// `Ok(None)` when the request carries no credentials this provider
+    /// reads, so the next one can try.
+    fn identify(&self, headers: &HeaderMap) -> Result<Option<Identity>, IdentityError>;
+}
+
+/// The bot's scheme: `x-user` carries `<telegram user id>@<contract>` and is
+/// trusted as is. Only enable it where the bot alone reaches the server.
+pub struct TelegramIdentity;
+
+impl IdentityProvider for TelegramIdentity {
+    fn identify(&self, headers: &HeaderMap) -> Result<Option<Identity>, IdentityError> {
+        Ok(user_header(headers).map(|user| Identity(user.to_string())))
+    }
+}
+
+/// `x-user` carries a wallet address, `0x` and 40 hex digits, lowercased
+/// into `<address>@<contract>`. The wallet proves it with `x-wallet-signature`
+/// over `x-wallet-timestamp`, see [`verify_wallet_signature`]. Other values
+/// are left to the next provider.
+pub struct WalletIdentity {
+    pub contract_name: ContractName,
+}
+
+impl IdentityProvider for WalletIdentity {
+    fn identify(&self, headers: &HeaderMap) -> Result<Option<Identity>, IdentityError> {
+        let Some(address) = user_header(headers).filter(|user| user.starts_with("0x")) else {
+            return Ok(None);
+        };
+        if address.len() != 42 || !address[2..].bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(IdentityError::Wallet(address.to_string()));
+        }
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let (Some(timestamp), Some(signature)) = (
+            header(WALLET_TIMESTAMP_HEADER),
+            header(WALLET_SIGNATURE_HEADER),
+        ) else {
+            return Err(IdentityError::WalletUnsigned);
+        };
+        let timestamp = timestamp
+            .trim()
+            .parse()
+            .map_err(|_| IdentityError::WalletSignature)?;
+        let address = address.to_lowercase();
+        verify_wallet_signature(
+            &address,
+            timestamp,
+            signature.trim(),
+            &self.contract_name,
+            unix_now(),
+        )?;
+        Ok(Some(Identity(format!(
+            "{}@{}",
+            address, self.contract_name.0
+        ))))
+    }
+}
+
+/// What a wallet signs to act as `address` (lowercase) on `contract_name`
+/// at `timestamp`.
+pub fn wallet_message(contract_name: &ContractName, address: &str, timestamp: u64) -> String {
+    format!(
+        "Sign in to {} as {} at {}",
+        contract_name.0, address, timestamp
+    )
+}
+
+/// Checks that `signature`, a `personal_sign` (EIP-191) signature of
+/// [`wallet_message`], was made by `address` within
+/// [`WALLET_SIGNATURE_WINDOW_SECS`] of `now`. A signature can be replayed
+/// inside that window, so clients sign again for every request.
+pub fn verify_wallet_signature(
+    address: &str,
+    timestamp: u64,
+    signature: &str,
+    contract_name: &ContractName,
+    now: u64,
+) -> Result<(), IdentityError> {
+    if timestamp.abs_diff(now) > WALLET_SIGNATURE_WINDOW_SECS {
+        return Err(IdentityError::WalletExpired);
+    }
+    let bytes = hex::decode(signature.strip_prefix("0x").unwrap_or(signature))
+        .map_err(|_| IdentityError::WalletSignature)?;
+    let Some((compact, [v])) = bytes.split_at_checked(64) else {
+        return Err(IdentityError::WalletSignature);
+    };
+    // Wallets put 27 or 28 in `v`, libraries sometimes 0 or 1
+    let recovery_id = RecoveryId::try_from(i32::from(v.checked_sub(27).unwrap_or(*v)))
+        .map_err(|_| IdentityError::WalletSignature)?;
+    let signature = RecoverableSignature::from_compact(compact, recovery_id)
+        .map_err(|_| IdentityError::WalletSignature)?;
+
+    let message = wallet_message(contract_name, address, timestamp);
+    let digest: [u8; 32] = Keccak256::new()
+        .chain_update(format!("\x19Ethereum Signed Message:\n{}", message.len()))
+        .chain_update(&message)
+        .finalize()
+        .into();
+    let key = Secp256k1::verification_only()
+        .recover_ecdsa(&Message::from_digest(digest), &signature)
+        .map_err(|_| IdentityError::WalletSignature)?;
+    // The address is the end of the hash of the uncompressed key, less its prefix
+    let signer = Keccak256::digest(&key.serialize_uncompressed()[1..]);
+    if address.get(2..) != Some(hex::encode(&signer[12..]).as_str()) {
+        return Err(IdentityError::WalletSignature);
+    }
+    Ok(())
+}
+
+/// `Authorization: Bearer <token>`, an HS256 JWT signed with a secret shared
+/// with the service that logged the user in. Its `sub` becomes
+/// `<sub>@<contract>`; `exp` is required and `nbf` honored.
+pub struct JwtIdentity {
+    pub secret: Vec<u8>,
+    pub contract_name: ContractName,
+}
+
+impl IdentityProvider for JwtIdentity {
+    fn identify(&self, headers: &HeaderMap) -> Result<Option<Identity>, IdentityError> {
+        let Some(authorization) = headers.get(header::AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(IdentityError::Malformed)?;
+        let claims = verify_hs256(token.trim(), &self.secret, unix_now())?;
+        Ok(Some(Identity(format!(
+            "{}@{}",
+            claims.sub, self.contract_name.0
+        ))))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: u64,
+    #[serde(default)]
+    pub nbf: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+/// Checks the signature and validity window of an HS256 `token` at `now`
+/// (seconds since the epoch) and returns its claims.
+pub fn verify_hs256(token: &str, secret: &[u8], now: u64) -> Result<Claims, IdentityError> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(IdentityError::Malformed);
+    };
+    let decode = |part: &str| {
+        URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|_| IdentityError::Malformed)
+    };
+
+    // The algorithm is checked before anything else is trusted, so a token
+    // claiming `none` or another key type cannot pass
+    let header: JwtHeader =
+        serde_json::from_slice(&decode(header)?).map_err(|_| IdentityError::Malformed)?;
+    if header.alg != "HS256" {
+        return Err(IdentityError::Algorithm(header.alg));
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&token.as_bytes()[..token.len() - signature.len() - 1]);
+    mac.verify_slice(&decode(signature)?)
+        .map_err(|_| IdentityError::Signature)?;
+
+    let claims: Claims =
+        serde_json::from_slice(&decode(payload)?).map_err(|_| IdentityError::Malformed)?;
+    if claims.exp.saturating_add(LEEWAY_SECS) <= now {
+        return Err(IdentityError::Expired);
+    }
+    if claims
+        .nbf
+        .is_some_and(|nbf| nbf > now.saturating_add(LEEWAY_SECS))
+    {
+        return Err(IdentityError::NotYetValid);
+    }
+    let valid_subject = !claims.sub.is_empty()
+        && claims
+            .sub
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && b != b'@');
+    if !valid_subject {
+        return Err(IdentityError::Subject(claims.sub));
+    }
+    Ok(claims)
+}
+
+fn user_header(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(USER_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// One of the identity schemes a deployment accepts.
+#[derive(Clone, PartialEq)]
+pub enum ProviderKind {
+    Telegram,
+    Wallet,
+    Jwt { secret: Vec<u8> },
+}
+
+impl fmt::Debug for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderKind::Telegram => write!(f, "Telegram"),
+            ProviderKind::Wallet => write!(f, "Wallet"),
+            // Keeps the secret out of logs
+            ProviderKind::Jwt { .. } => write!(f, "Jwt"),
+        }
+    }
+}
+
+/// The identity schemes a deployment accepts, tried in order: the first one
+/// whose credentials a request carries decides who sent it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentityConfig {
+    pub providers: Vec<ProviderKind>,
+}
+
+impl IdentityConfig {
+    /// The bot's scheme alone, as before providers could be chosen.
+    pub fn telegram() -> Self {
+        Self {
+            providers: vec![ProviderKind::Telegram],
+        }
+    }
+
+    /// Reads `IDENTITY_PROVIDERS`, a comma-separated list of `telegram`,
+    /// `wallet` and `jwt` (default `telegram`), and `JWT_SECRET`, the HS256
+    /// secret `jwt` needs.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let Some(names) = var("IDENTITY_PROVIDERS") else {
+            return Ok(Self::telegram());
+        };
+        let mut providers = vec![];
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let provider = match name.to_lowercase().as_str() {
+                "telegram" => ProviderKind::Telegram,
+                "wallet" => ProviderKind::Wallet,
+                "jwt" => {
+                    let Some(secret) = var("JWT_SECRET").filter(|secret| !secret.is_empty()) else {
+                        bail!("IDENTITY_PROVIDERS includes jwt but JWT_SECRET is not set");
+                    };
+                    if secret.len() < MIN_SECRET_BYTES {
+                        bail!("JWT_SECRET must be at least {} bytes", MIN_SECRET_BYTES);
+                    }
+                    ProviderKind::Jwt {
+                        secret: secret.into_bytes(),
+                    }
+                }
+                other => bail!(
+                    "unknown identity provider {:?}, expected telegram, wallet or jwt",
+                    other
+                ),
+            };
+            if providers.contains(&provider) {
+                bail!("identity provider {} is listed twice", name);
+            }
+            providers.push(provider);
+        }
+        if providers.is_empty() {
+            bail!("IDENTITY_PROVIDERS is empty");
+        }
+        Ok(Self { providers })
+    }
+
+    pub fn build(&self, contract_name: &ContractName) -> Identities {
+        Identities(
+            self.providers
+                .iter()
+                .map(|provider| -> Box<dyn IdentityProvider> {
+                    match provider {
+                        ProviderKind::Telegram => Box::new(TelegramIdentity),
+                        ProviderKind::Wallet => Box::new(WalletIdentity {
+                            contract_name: contract_name.clone(),
+                        }),
+                        ProviderKind::Jwt { secret } => Box::new(JwtIdentity {
+                            secret: secret.clone(),
+                            contract_name: contract_name.clone(),
+                        }),
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The providers a deployment accepts, resolved at startup.
+pub struct Identities(Vec<Box<dyn IdentityProvider>>);
+
+impl Identities {
+    /// Who sent a request, per the first provider whose credentials it carries.
+    pub fn identify(&self, headers: &HeaderMap) -> Result<Identity, IdentityError> {
+        for provider in &self.0 {
+            if let Some(identity) = provider.identify(headers)? {
+                return Ok(identity);
+            }
+        }
+        Err(IdentityError::Missing)
+    }
+}
@@ -4,6 +4,9 @@ use sdk::{api::APIRegisterContract, info, ContractName, ProgramId, StateCommitme
 use std::{sync::Arc, time::Duration};
 use tokio::time::timeout;
 
+/// Verifier contracts are registered with, matching the risc0 prover.
+pub const VERIFIER: &str = "risc0-1";
+
 pub struct ContractInit {
     pub name: ContractName,
     pub program_id: [u8; 32],
@@ -43,7 +46,7 @@ async fn init_contract(
         Err(_) => {
             info!("🚀 Registering {} contract", contract.name);
             node.register_contract(APIRegisterContract {
-                verifier: "risc0-1".into(),
+                verifier: VERIFIER.into(),
                 program_id: ProgramId(contract.program_id.to_vec()),
                 state_commitment: contract.initial_state,
                 contract_name: contract.name.clone(),
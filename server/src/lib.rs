@@ -0,0 +1,14 @@
+pub mod app;
+pub mod backpressure;
+pub mod conf;
+pub mod contract_check;
+pub mod cors;
+pub mod dedup;
+pub mod events;
+pub mod history;
+pub mod identity;
+pub mod init;
+pub mod metrics;
+pub mod nonces;
+pub mod precheck;
+pub mod webhook;
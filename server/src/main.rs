@@ -1,12 +1,10 @@
-use anyhow::{Context, Result};
-use app::{AppModule, AppModuleCtx};
+use anyhow::{bail, Context, Result};
 use axum::Router;
 use clap::Parser;
 use client_sdk::{
     helpers::risc0::Risc0Prover,
     rest_client::{IndexerApiHttpClient, NodeApiHttpClient},
 };
-use conf::Conf;
 use contract1::Contract1;
 use hyle_modules::{
     bus::{metrics::BusMetrics, SharedMessageBus},
@@ -20,13 +18,18 @@ use hyle_modules::{
     utils::logger::setup_tracing,
 };
 use prometheus::Registry;
-use sdk::{api::NodeInfo, info, ZkContract};
+use sdk::{api::NodeInfo, info, ContractName, ZkContract};
+use server::{
+    app::{initial_state, AppModule, AppModuleCtx},
+    backpressure::SubmissionLimits,
+    conf::Conf,
+    history::HistoryConfig,
+    init,
+    webhook::WebhookConfig,
+};
 use std::sync::{Arc, Mutex};
-use tracing::error;
-
-mod app;
-mod conf;
-mod init;
+use std::time::Duration;
+use tracing::{error, warn};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -37,6 +40,11 @@ pub struct Args {
     #[arg(long, default_value = "contract1")]
     pub contract1_cn: String,
 
+    /// Serves the files of this directory for paths no route matches, e.g. a
+    /// dashboard reading `/api/snapshot`
+    #[cfg(feature = "static-files")]
+    #[arg(long)]
+    pub serve_static: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
@@ -60,11 +68,25 @@ async fn main() -> Result<()> {
         IndexerApiHttpClient::new(config.indexer_url.clone()).context("build indexer client")?,
     );
 
+    // A fresh epoch tells clients this deployment's market ids apart from earlier ones
+    let state_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .context("system clock before 1970")?
+        .as_secs();
+    let fresh_state = match &config.contract_admin {
+        Some(admin) => initial_state(&ContractName(args.contract1_cn.clone()), admin, state_epoch),
+        None => {
+            warn!(
+                "No contract_admin configured: whoever first sends SetAdmin administers a freshly registered contract"
+            );
+            Contract1::with_epoch(state_epoch)
+        }
+    };
     let contracts = vec![
         init::ContractInit {
             name: args.contract1_cn.clone().into(),
             program_id: contract1::client::tx_executor_handler::metadata::PROGRAM_ID,
-            initial_state: Contract1::default().commit(),
+            initial_state: fresh_state.commit(),
         },
     ];
 
@@ -86,10 +108,42 @@ async fn main() -> Result<()> {
         openapi: Default::default(),
     });
 
+    // Shared by the REST module's metrics endpoint and the app's state gauges
+    let registry = Registry::new();
+
     let app_ctx = Arc::new(AppModuleCtx {
         api: api_ctx.clone(),
-        node_client,
+        node_client: node_client.clone(),
         contract1_cn: args.contract1_cn.clone().into(),
+        cors: None,
+        identity: None,
+        admin_key: std::env::var("ADMIN_API_KEY").ok().filter(|key| !key.is_empty()),
+        contract_admin: config.contract_admin.clone(),
+        max_body_size: config.api_max_body_size,
+        compression: config.api_compression,
+        webhook: match (&config.bot_webhook_url, &config.bot_webhook_secret) {
+            (Some(url), Some(secret)) => Some(WebhookConfig {
+                url: url.clone(),
+                secret: secret.clone(),
+                max_attempts: 5,
+            }),
+            (Some(_), None) => bail!("bot_webhook_url is set without bot_webhook_secret"),
+            (None, _) => None,
+        },
+        history: Some(HistoryConfig {
+            database_url: format!(
+                "sqlite://{}",
+                config.data_directory.join("history.db").display()
+            ),
+            retention_days: Some(config.history_retention_days).filter(|days| *days > 0),
+        }),
+        duplicate_window: Duration::from_secs(config.duplicate_window_secs),
+        metrics: Some(registry.clone()),
+        precheck: config.precheck,
+        submission_limits: SubmissionLimits {
+            soft: config.submission_soft_limit,
+            hard: config.submission_hard_limit,
+        },
     });
 
     handler.build_module::<AppModule>(app_ctx.clone()).await?;
@@ -107,7 +161,7 @@ async fn main() -> Result<()> {
             data_directory: config.data_directory.clone(),
             prover: Arc::new(Risc0Prover::new(contracts::CONTRACT1_ELF)),
             contract_name: args.contract1_cn.clone().into(),
-            node: app_ctx.node_client.clone(),
+            node: node_client.clone(),
             default_state: Default::default(),
             buffer_blocks: config.buffer_blocks,
             max_txs_per_proof: config.max_txs_per_proof,
@@ -131,6 +185,11 @@ async fn main() -> Result<()> {
         .expect("Context router should be available.")
         .take()
         .expect("Context router should be available.");
+    #[cfg(feature = "static-files")]
+    let router = match &args.serve_static {
+        Some(dir) => router.fallback_service(tower_http::services::ServeDir::new(dir)),
+        None => router,
+    };
     #[allow(clippy::expect_used, reason = "Fail on misconfiguration")]
     let openapi = api_ctx
         .openapi
@@ -142,7 +201,7 @@ async fn main() -> Result<()> {
         .build_module::<RestApi>(RestApiRunContext {
             port: config.rest_server_port,
             max_body_size: config.rest_server_max_body_size,
-            registry: Registry::new(),
+            registry,
             router,
             openapi,
             info: NodeInfo {
@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use contract1::api::StateStats;
+use prometheus::{core::Collector, Gauge, IntGauge, IntGaugeVec, Opts, Registry};
+
+/// Gauges mirroring [`StateStats`] on the metrics endpoint, refreshed every
+/// time a transaction settles.
+#[derive(Clone)]
+pub struct StateGauges {
+    state_bytes: IntGauge,
+    users: IntGauge,
+    markets: IntGaugeVec,
+    parlays: IntGauge,
+    open_pool: Gauge,
+    largest_market_bettors: IntGauge,
+    treasury: Gauge,
+    burned: Gauge,
+}
+
+impl StateGauges {
+    pub fn register(registry: &Registry) -> Result<Self> {
+        let gauges = Self {
+            state_bytes: IntGauge::new("contract1_state_bytes", "Size of the encoded contract state")?,
+            users: IntGauge::new("contract1_users", "Users with a balance")?,
+            markets: IntGaugeVec::new(Opts::new("contract1_markets", "Markets by status"), &["status"])?,
+            parlays: IntGauge::new("contract1_parlays", "Parlays placed")?,
+            open_pool: Gauge::new("contract1_open_pool", "Stakes in markets not settled yet")?,
+            largest_market_bettors: IntGauge::new(
+                "contract1_largest_market_bettors",
+                "Distinct bettors of the market with the most",
+            )?,
+            treasury: Gauge::new("contract1_treasury", "Treasury balance")?,
+            burned: Gauge::new("contract1_burned", "Funds burned")?,
+        };
+        let collectors: [Box<dyn Collector>; 8] = [
+            Box::new(gauges.state_bytes.clone()),
+            Box::new(gauges.users.clone()),
+            Box::new(gauges.markets.clone()),
+            Box::new(gauges.parlays.clone()),
+            Box::new(gauges.open_pool.clone()),
+            Box::new(gauges.largest_market_bettors.clone()),
+            Box::new(gauges.treasury.clone()),
+            Box::new(gauges.burned.clone()),
+        ];
+        for collector in collectors {
+            registry.register(collector).context("registering the state gauges")?;
+        }
+        Ok(gauges)
+    }
+
+    pub fn observe(&self, stats: &StateStats) {
+        self.state_bytes.set(stats.state_bytes as i64);
+        self.users.set(stats.users as i64);
+        for (status, count) in stats.markets.by_status() {
+            self.markets.with_label_values(&[status]).set(count as i64);
+        }
+        self.parlays.set(stats.parlays as i64);
+        // Token amounts can exceed an i64; gauges only need their magnitude
+        self.open_pool.set(stats.open_pool as f64);
+        self.largest_market_bettors
+            .set(stats.largest_market.as_ref().map_or(0, |market| market.bettors as i64));
+        self.treasury.set(stats.treasury as f64);
+        self.burned.set(stats.burned as f64);
+    }
+}
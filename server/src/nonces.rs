@@ -0,0 +1,72 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sdk::TxHash;
+
+/// How long a nonce handed out stays reserved without its transaction
+/// settling, e.g. when the node never sequenced it.
+pub const RESERVATION_TTL: Duration = Duration::from_secs(2 * 60);
+
+/// A nonce handed out to a transaction that has not settled yet.
+struct Reservation {
+    identity: String,
+    nonce: u64,
+    tx_hash: TxHash,
+    at: Instant,
+}
+
+/// Nonces of the transactions in flight. The indexed state only knows the
+/// settled ones, so an identity sending a second action before the first
+/// settles is handed the nonce after the one still in flight.
+#[derive(Default)]
+pub struct Nonces(Mutex<Vec<Reservation>>);
+
+impl Nonces {
+    /// Picks the next nonce of `identity`, `indexed_next` unless some are
+    /// still in flight, and reserves it for the transaction `build` makes
+    /// with it, identified by its hash.
+    pub fn reserve<T>(
+        &self,
+        identity: &str,
+        indexed_next: u64,
+        build: impl FnOnce(u64) -> (T, TxHash),
+    ) -> T {
+        self.reserve_at(identity, indexed_next, Instant::now(), build)
+    }
+
+    pub fn reserve_at<T>(
+        &self,
+        identity: &str,
+        indexed_next: u64,
+        now: Instant,
+        build: impl FnOnce(u64) -> (T, TxHash),
+    ) -> T {
+        let mut reservations = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        // Nonces below the indexed one were used already
+        reservations.retain(|r| {
+            now.saturating_duration_since(r.at) < RESERVATION_TTL
+                && (r.identity != identity || r.nonce >= indexed_next)
+        });
+        let nonce = reservations
+            .iter()
+            .filter(|r| r.identity == identity)
+            .map(|r| r.nonce + 1)
+            .fold(indexed_next, u64::max);
+        let (built, tx_hash) = build(nonce);
+        reservations.push(Reservation {
+            identity: identity.to_string(),
+            nonce,
+            tx_hash,
+            at: now,
+        });
+        built
+    }
+
+    /// Frees the nonce of a transaction that settled, or was never sent.
+    /// When it failed, the nonces reserved after it leave a gap and their
+    /// transactions fail too, after which the indexed state is trusted again.
+    pub fn release(&self, tx_hash: &TxHash) {
+        let mut reservations = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        reservations.retain(|r| &r.tx_hash != tx_hash);
+    }
+}
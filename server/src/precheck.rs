@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use client_sdk::transaction_builder::TxExecutorHandler;
+use contract1::client::{changes_state, decode_output};
+use contract1::Contract1;
+use sdk::{BlobIndex, BlobTransaction, Calldata, TimestampMs, TxContext, TxHash};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Dry runs remembered until their transaction settles. Older ones are
+/// dropped, e.g. those of transactions the node never sequenced.
+const PREDICTION_CAPACITY: usize = 1024;
+
+/// Whether actions are run against the indexed state before being
+/// submitted, and what a refusal does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrecheckMode {
+    /// Submit without a dry run
+    Off,
+    /// Answer actions the dry run refuses with the contract's error, without
+    /// submitting them
+    #[default]
+    Enforce,
+    /// Submit anyway, and only log when the settled outcome differs from
+    /// the dry run's
+    Advisory,
+}
+
+/// Runs `tx` against a copy of `state` as if sequenced now, the way the
+/// prover does, returning whether it changes the state or the contract's
+/// error when it refuses it.
+pub fn dry_run(state: &Contract1, tx: &BlobTransaction, tx_hash: &TxHash) -> Result<bool, String> {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let calldata = Calldata {
+        tx_hash: tx_hash.clone(),
+        identity: tx.identity.clone(),
+        blobs: tx.blobs.clone().into(),
+        tx_blob_count: tx.blobs.len(),
+        index: BlobIndex(0),
+        tx_ctx: Some(TxContext {
+            timestamp: TimestampMs(now_ms),
+            ..TxContext::default()
+        }),
+        private_input: vec![],
+    };
+    let output = state.clone().handle(&calldata).map_err(|e| e.to_string())?;
+    decode_output(&output)?;
+    Ok(changes_state(&output))
+}
+
+/// Dry runs submitted transactions against the indexed state, which saves
+/// proving actions bound to fail. The indexed state lags behind the
+/// transactions still in flight, so the verdicts are kept and compared with
+/// the settled outcomes: a mismatch means the state was stale.
+pub struct Precheck {
+    mode: PrecheckMode,
+    /// Dry-run errors of submitted transactions, `None` when it passed
+    predictions: Mutex<VecDeque<(TxHash, Option<String>)>>,
+}
+
+impl Precheck {
+    pub fn new(mode: PrecheckMode) -> Self {
+        Self {
+            mode,
+            predictions: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Dry runs `tx` before it is submitted. `Err` carries the contract's
+    /// error when the transaction must not be submitted, `Ok` whether the
+    /// dry run changed the state when it passed. Nothing is checked until a
+    /// state is indexed.
+    pub fn check(
+        &self,
+        state: Option<&Contract1>,
+        tx: &BlobTransaction,
+        tx_hash: &TxHash,
+    ) -> Result<Option<bool>, String> {
+        let Some(state) = state.filter(|_| self.mode != PrecheckMode::Off) else {
+            return Ok(None);
+        };
+        let outcome = dry_run(state, tx, tx_hash);
+        if self.mode == PrecheckMode::Enforce {
+            outcome.clone()?;
+        }
+        let mut predictions = self.predictions.lock().unwrap_or_else(|e| e.into_inner());
+        if predictions.len() == PREDICTION_CAPACITY {
+            predictions.pop_front();
+        }
+        predictions.push_back((tx_hash.clone(), outcome.clone().err()));
+        Ok(outcome.ok())
+    }
+
+    /// Forgets the dry run of a transaction the node did not accept.
+    pub fn abandoned(&self, tx_hash: &TxHash) {
+        self.take(tx_hash);
+    }
+
+    /// Compares the outcome of a settled transaction, `error` when it
+    /// failed, with its dry run, and returns whether they differ.
+    pub fn settled(&self, tx_hash: &TxHash, error: Option<&str>) -> bool {
+        let Some(predicted) = self.take(tx_hash) else {
+            return false;
+        };
+        match (predicted, error) {
+            (None, Some(error)) => {
+                warn!(
+                    "Dry run of {} passed but it failed on-chain ({}): the indexed state was stale",
+                    tx_hash, error
+                );
+                true
+            }
+            (Some(predicted), None) => {
+                warn!(
+                    "Dry run of {} failed ({}) but it settled on-chain: the indexed state was stale",
+                    tx_hash, predicted
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn take(&self, tx_hash: &TxHash) -> Option<Option<String>> {
+        let mut predictions = self.predictions.lock().unwrap_or_else(|e| e.into_inner());
+        let position = predictions.iter().position(|(hash, _)| hash == tx_hash)?;
+        predictions.remove(position).map(|(_, predicted)| predicted)
+    }
+}
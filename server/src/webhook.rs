@@ -0,0 +1,102 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use contract1::api::WebhookPayload;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::warn;
+
+/// Carries `sha256=<hex hmac of "<timestamp>.<body>">`, keyed with the
+/// shared secret.
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// Carries the unix seconds the delivery was signed at. Receivers should
+/// refuse deliveries signed more than 5 minutes away from their clock, so a
+/// captured delivery cannot be replayed later.
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub secret: String,
+    /// Total number of attempts per delivery, including the first one
+    pub max_attempts: u32,
+}
+
+/// Signature the receiver recomputes over the timestamp header, a `.` and
+/// the raw request body.
+pub fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Posts market events to the bot. Deliveries are retried with a doubling
+/// delay; events still undelivered after the last attempt are dropped.
+#[derive(Clone)]
+pub struct WebhookSender {
+    http: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl WebhookSender {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("static reqwest configuration"),
+            config,
+        }
+    }
+
+    pub async fn deliver(&self, payload: &WebhookPayload) -> Result<()> {
+        let body = serde_json::to_vec(payload)?;
+        let max_attempts = self.config.max_attempts.max(1);
+        let mut delay = Duration::from_millis(500);
+
+        let mut attempt = 1;
+        loop {
+            // Signed per attempt, so retries stay within the receiver's window
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let signature = sign(self.config.secret.as_bytes(), timestamp, &body);
+            let sent = self
+                .http
+                .post(&self.config.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(SIGNATURE_HEADER, signature)
+                .body(body.clone())
+                .send()
+                .await;
+            let failure = match sent {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                // The receiver rejected the payload itself: retrying cannot help
+                Ok(response) if response.status().is_client_error() => {
+                    bail!("webhook rejected tx {} with {}", payload.tx_hash, response.status())
+                }
+                Ok(response) => response.status().to_string(),
+                Err(e) => e.to_string(),
+            };
+            if attempt >= max_attempts {
+                bail!(
+                    "webhook delivery for tx {} failed after {} attempts: {}",
+                    payload.tx_hash,
+                    max_attempts,
+                    failure
+                );
+            }
+            warn!(
+                "Webhook delivery for tx {} failed ({}), attempt {}/{}, retrying in {:?}",
+                payload.tx_hash, failure, attempt, max_attempts, delay
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
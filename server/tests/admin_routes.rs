@@ -0,0 +1,347 @@
+mod common;
+
+use common::{identity, TestServer, ADMIN_KEY};
+use contract1::{client::tx_executor_handler::metadata::PROGRAM_ID, Contract1, MarketError};
+use sdk::Identity;
+use serde_json::json;
+
+fn set_admin() -> serde_json::Value {
+    json!({ "new_admin": identity("alice") })
+}
+
+#[tokio::test]
+async fn missing_admin_key_is_forbidden() {
+    let server = TestServer::start().await;
+
+    let (status, body) = server
+        .post_admin("alice", "/api/market/set_admin", set_admin(), None)
+        .await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"], "admin_key_missing");
+
+    // `post` only sets x-user, which is not enough
+    let (status, _) = server
+        .post(
+            "alice",
+            "/api/market/treasury/withdraw",
+            json!({ "to": identity("alice"), "amount": 1 }),
+        )
+        .await;
+    assert_eq!(status, 403);
+    assert!(server.node.submitted().is_empty());
+}
+
+#[tokio::test]
+async fn wrong_admin_key_is_forbidden() {
+    let server = TestServer::start().await;
+
+    for key in ["nope", "test-admin-kez", "test-admin-key-and-more", ""] {
+        let (status, body) = server
+            .post_admin("alice", "/api/market/set_admin", set_admin(), Some(key))
+            .await;
+        assert_eq!(status, 403, "{}", key);
+        assert_eq!(body["error"], "admin_key_invalid", "{}", key);
+    }
+    assert!(server.node.submitted().is_empty());
+}
+
+#[tokio::test]
+async fn correct_admin_key_reaches_the_contract() {
+    let server = TestServer::start().await;
+    server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+
+    let (status, _) = server
+        .post_admin(
+            "alice",
+            "/api/market/set_admin",
+            set_admin(),
+            Some(ADMIN_KEY),
+        )
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(server.state().admin, Some(sdk::Identity(identity("alice"))));
+}
+
+#[tokio::test]
+async fn admin_routes_are_disabled_without_a_configured_key() {
+    let server = TestServer::start_with(|ctx| ctx.admin_key = None).await;
+
+    let (status, body) = server
+        .post_admin(
+            "alice",
+            "/api/market/set_admin",
+            set_admin(),
+            Some(ADMIN_KEY),
+        )
+        .await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"], "admin_disabled");
+    assert!(server.node.submitted().is_empty());
+}
+
+#[tokio::test]
+async fn other_routes_do_not_need_the_admin_key() {
+    let server = TestServer::start().await;
+
+    let (status, _) = server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn reset_balances_needs_the_key_and_the_contract_admin() {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+    server.post_admin("alice", "/api/market/set_admin", set_admin(), Some(ADMIN_KEY)).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("bob", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 300 })).await;
+
+    let (status, _) = server.post("alice", "/api/market/reset_balances", json!({})).await;
+    assert_eq!(status, 403);
+
+    // The contract refuses while a market is open
+    let (status, body) = server.post_admin("alice", "/api/market/reset_balances", json!({}), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("still open"), "{}", body);
+
+    server.post("alice", "/api/market/resolve", json!({ "market_id": 1, "outcome": false })).await;
+    let (status, body) = server.post_admin("bob", "/api/market/reset_balances", json!({}), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("Only the admin"), "{}", body);
+
+    let (status, _) = server.post_admin("alice", "/api/market/reset_balances", json!({}), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200);
+    assert_eq!(server.balance("bob"), 10_000);
+}
+
+#[tokio::test]
+async fn confiscated_balances_are_burned() {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+    server.post_admin("alice", "/api/market/set_admin", set_admin(), Some(ADMIN_KEY)).await;
+    let confiscate = json!({ "user": identity("bob") });
+
+    let (status, _) = server.post("alice", "/api/market/confiscate", confiscate.clone()).await;
+    assert_eq!(status, 403);
+    let (status, body) = server.post_admin("bob", "/api/market/confiscate", confiscate.clone(), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("Only the admin"), "{}", body);
+
+    let (status, _) = server.post_admin("alice", "/api/market/confiscate", confiscate, Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200);
+    assert_eq!(server.balance("bob"), 0);
+    assert_eq!(server.state().burned, 10_000);
+    let response = server.get_raw("/api/admin/state_stats", &[("x-admin-key", ADMIN_KEY)]).await;
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["burned"], 10_000);
+}
+
+#[tokio::test]
+async fn state_stats_need_the_key_and_report_the_indexed_state() {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it rain?" })).await;
+    server.post("alice", "/api/market/resolve", json!({ "market_id": 2, "outcome": false })).await;
+    server.post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 100 })).await;
+    server.post("bob", "/api/market/bet", json!({ "market_id": 1, "side": false, "amount": 40 })).await;
+    server
+        .get_until("/api/market/1/odds", |status, body| status == 200 && body["no_pool"] == 40)
+        .await;
+
+    let (status, _) = server.get("/api/admin/state_stats").await;
+    assert_eq!(status, 403);
+
+    let response = server.get_raw("/api/admin/state_stats", &[("x-admin-key", ADMIN_KEY)]).await;
+    assert_eq!(response.status(), 200);
+    let stats: serde_json::Value = response.json().await.unwrap();
+    let state = server.state();
+    assert_eq!(stats["state_bytes"], state.as_bytes().unwrap().len());
+    assert_eq!(stats["users"], 2);
+    assert_eq!(stats["markets"]["open"], 1);
+    assert_eq!(stats["markets"]["resolved_no"], 1);
+    assert_eq!(stats["open_pool"], 140);
+    assert_eq!(stats["largest_market"], json!({ "market_id": 1, "bettors": 2 }));
+    assert_eq!(stats["treasury"], state.treasury as u64);
+}
+
+#[tokio::test]
+async fn exported_snapshots_import_into_a_fresh_deployment() {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+    server.post_admin("alice", "/api/market/set_admin", set_admin(), Some(ADMIN_KEY)).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("bob", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 300 })).await;
+
+    let (status, body) = server.post_admin("bob", "/api/admin/export", json!({}), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("Only the admin"), "{}", body);
+
+    let (status, body) = server.post_admin("alice", "/api/admin/export", json!({}), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200, "{}", body);
+    let snapshot = body["result"].clone();
+
+    // The exported state is already populated
+    let (status, body) = server.post_admin("alice", "/api/admin/import", snapshot.clone(), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("empty state"), "{}", body);
+
+    let fresh = TestServer::start().await;
+    let (status, _) = fresh.post("alice", "/api/admin/import", snapshot.clone()).await;
+    assert_eq!(status, 403);
+    let garbled = json!({ "state_epoch": 0, "total_bytes": 2, "chunks": ["zz"] });
+    let (status, body) = fresh.post_admin("alice", "/api/admin/import", garbled, Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400, "{}", body);
+    // A fresh contract has no admin yet, who must be set before importing
+    let (status, body) = fresh.post_admin("alice", "/api/admin/import", snapshot.clone(), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("No admin"), "{}", body);
+    fresh.post_admin("alice", "/api/market/set_admin", set_admin(), Some(ADMIN_KEY)).await;
+    let (status, body) = fresh.post_admin("bob", "/api/admin/import", snapshot.clone(), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("Only the admin"), "{}", body);
+
+    let (status, body) = fresh.post_admin("alice", "/api/admin/import", snapshot, Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(fresh.state().as_bytes().unwrap(), server.state().as_bytes().unwrap());
+    assert_eq!(fresh.balance("bob"), 9_700);
+}
+
+#[tokio::test]
+async fn deploy_registers_a_fresh_contract_once() {
+    let server = TestServer::start().await;
+    let deploy = json!({ "contract_name": "book-club" });
+
+    let (status, _) = server.post("alice", "/api/admin/deploy", deploy.clone()).await;
+    assert_eq!(status, 403);
+
+    let (status, body) = server.post_admin("alice", "/api/admin/deploy", deploy.clone(), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["contract_name"], "book-club");
+    let registered = server.node.registered();
+    assert_eq!(registered.len(), 1);
+    let contract = &registered[0];
+    assert_eq!(contract.contract_name.0, "book-club");
+    assert_eq!(contract.verifier.0, "risc0-1");
+    assert_eq!(contract.program_id.0, PROGRAM_ID.to_vec());
+    assert_eq!(body["program_id"], hex::encode(PROGRAM_ID));
+    let state = Contract1::try_from(contract.state_commitment.clone()).unwrap();
+    assert!(state.users.is_empty() && state.markets.is_empty());
+    assert_eq!(body["state_epoch"], state.state_epoch);
+
+    // Neither the new name nor the server's own contract can be taken again
+    for name in ["book-club", "contract1"] {
+        let (status, body) = server
+            .post_admin("alice", "/api/admin/deploy", json!({ "contract_name": name }), Some(ADMIN_KEY))
+            .await;
+        assert_eq!(status, 409, "{}", name);
+        assert!(body.to_string().contains("already exists"), "{}", body);
+    }
+    let (status, _) = server
+        .post_admin("alice", "/api/admin/deploy", json!({ "contract_name": "book club" }), Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(server.node.registered().len(), 1);
+}
+
+#[tokio::test]
+async fn fresh_deployments_refuse_set_admin_from_a_stranger() {
+    let server = TestServer::start().await;
+    let (status, body) = server
+        .post_admin("bob", "/api/admin/deploy", json!({ "contract_name": "book-club" }), Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 200, "{}", body);
+
+    // Administered by the configured user from the start, whoever deployed it
+    let contract = &server.node.registered()[0];
+    let mut state = Contract1::try_from(contract.state_commitment.clone()).unwrap();
+    let alice = Identity("alice@book-club".to_string());
+    assert_eq!(state.admin, Some(alice.clone()));
+    let mallory = Identity("mallory@book-club".to_string());
+    assert_eq!(state.set_admin(mallory.clone(), mallory), Err(MarketError::Unauthorized));
+    assert_eq!(state.admin, Some(alice));
+}
+
+#[tokio::test]
+async fn deploying_needs_a_contract_admin() {
+    let server = TestServer::start_with(|ctx| ctx.contract_admin = None).await;
+    let (status, body) = server
+        .post_admin("alice", "/api/admin/deploy", json!({ "contract_name": "book-club" }), Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 403);
+    assert!(body.to_string().contains("No contract admin"), "{}", body);
+    assert!(server.node.registered().is_empty());
+}
+
+#[tokio::test]
+async fn the_house_edge_is_paid_out_as_dividends() {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob", "carol"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+    server.post_admin("alice", "/api/market/set_admin", set_admin(), Some(ADMIN_KEY)).await;
+
+    let edge = json!({ "bps": 2_000 });
+    let (status, _) = server.post("alice", "/api/market/house_edge", edge.clone()).await;
+    assert_eq!(status, 403);
+    let (status, _) = server.post_admin("alice", "/api/market/house_edge", edge, Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200);
+    let (_, config) = server.get_until("/api/config", |_, body| body["params"]["fee_bps"] == 2_000).await;
+    assert_eq!(config["params"]["fee_bps"], 2_000, "{}", config);
+
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 100 })).await;
+    server.post("bob", "/api/market/bet", json!({ "market_id": 1, "side": false, "amount": 50 })).await;
+    server.post("alice", "/api/market/close", json!({ "market_id": 1 })).await;
+    let (status, _) = server.post("alice", "/api/market/resolve", json!({ "market_id": 1, "outcome": true })).await;
+    assert_eq!(status, 200);
+    assert_eq!(server.state().dividend_pool, 10);
+
+    let (status, _) = server
+        .post_admin("alice", "/api/market/dividends/distribute", json!({}), Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 200);
+    // Carol never bet
+    assert_eq!(server.balance("alice"), 10_000 + 40 + 5);
+    assert_eq!(server.balance("bob"), 10_000 - 50 + 5);
+    assert_eq!(server.balance("carol"), 10_000);
+}
+
+#[tokio::test]
+async fn a_paused_contract_only_answers_queries() {
+    let server = TestServer::start().await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server.post_admin("alice", "/api/market/set_admin", set_admin(), Some(ADMIN_KEY)).await;
+
+    let (status, _) = server.post("alice", "/api/market/pause", json!({})).await;
+    assert_eq!(status, 403);
+    let (status, _) = server.post_admin("alice", "/api/market/pause", json!({}), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200);
+    assert!(server.state().paused);
+    server.get_until("/api/config", |_, body| body["paused"] == true).await;
+    let (_, health) = server.get("/_health").await;
+    assert_eq!(health["paused"], true, "{}", health);
+
+    let (status, body) = server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("Contract is paused"), "{}", body);
+    let (status, _) = server.post("alice", "/api/market/balance", json!({})).await;
+    assert_eq!(status, 200);
+
+    let (status, _) = server.post_admin("alice", "/api/market/unpause", json!({}), Some(ADMIN_KEY)).await;
+    assert_eq!(status, 200);
+    let (status, _) = server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    assert_eq!(status, 200);
+    server.get_until("/api/config", |_, body| body["paused"] == false).await;
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{identity, TestServer};
+use serde_json::json;
+use server::backpressure::{SubmissionLimits, RETRY_AFTER_SECS};
+
+async fn start(limits: SubmissionLimits) -> Arc<TestServer> {
+    let server = TestServer::start_with(|ctx| ctx.submission_limits = limits).await;
+    // Nothing settles, so every submission stays in flight until it times out
+    server.node.stall();
+    Arc::new(server)
+}
+
+/// POSTs `path` as `user` in the background; the response comes once the
+/// submission times out.
+fn post_later(
+    server: &Arc<TestServer>,
+    user: &'static str,
+    path: &'static str,
+) -> tokio::task::JoinHandle<u16> {
+    let server = server.clone();
+    tokio::spawn(async move { server.post(user, path, json!({})).await.0 })
+}
+
+async fn until_submitted(server: &TestServer, count: usize) {
+    for _ in 0..100 {
+        if server.node.submitted().len() >= count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("{} transactions were never submitted", count);
+}
+
+#[tokio::test]
+async fn limits_refuse_mutating_then_every_submission() {
+    let server = start(SubmissionLimits { soft: 2, hard: 3 }).await;
+    let stalled = vec![
+        post_later(&server, "alice", "/api/market/initialize"),
+        post_later(&server, "bob", "/api/market/initialize"),
+    ];
+    until_submitted(&server, 2).await;
+
+    // Past the soft limit, actions changing the state are told to come back
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/market/initialize", server.url))
+        .header("x-user", identity("carol"))
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 503);
+    assert_eq!(
+        response.headers()["retry-after"],
+        RETRY_AFTER_SECS.to_string().as_str()
+    );
+
+    // Read-only ones still go through until the hard limit
+    let stalled_read = post_later(&server, "carol", "/api/market/balance");
+    until_submitted(&server, 3).await;
+    let (status, _) = server.post("dave", "/api/market/balance", json!({})).await;
+    assert_eq!(status, 503);
+    assert_eq!(server.node.submitted().len(), 3);
+
+    let (status, health) = server.get("/_health").await;
+    assert_eq!(status, 200);
+    assert_eq!(health["status"], "busy");
+    assert_eq!(health["submissions"]["in_flight"], 3);
+    assert_eq!(
+        health["submissions"]["limits"],
+        json!({ "soft": 2, "hard": 3 })
+    );
+
+    // Unsettled submissions answer 202 and leave the count
+    for request in stalled.into_iter().chain([stalled_read]) {
+        assert_eq!(request.await.unwrap(), 202);
+    }
+    let (_, health) = server.get("/_health").await;
+    assert_eq!(health["status"], "OK");
+    assert_eq!(health["submissions"]["in_flight"], 0);
+}
+
+#[tokio::test]
+async fn a_burst_is_cut_at_the_soft_limit() {
+    let server = start(SubmissionLimits { soft: 4, hard: 8 }).await;
+    let users = ["u0", "u1", "u2", "u3", "u4", "u5", "u6", "u7", "u8", "u9"];
+    let burst: Vec<_> = users
+        .into_iter()
+        .map(|user| post_later(&server, user, "/api/market/initialize"))
+        .collect();
+
+    let mut statuses = vec![];
+    for request in burst {
+        statuses.push(request.await.unwrap());
+    }
+    statuses.sort();
+    assert_eq!(statuses, [[202; 4].as_slice(), &[503; 6]].concat());
+    // The refused ones never reached the node
+    assert_eq!(server.node.submitted().len(), 4);
+}
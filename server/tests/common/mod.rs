@@ -0,0 +1,356 @@
+//! End-to-end harness: runs `AppModule`'s routes on a local port against an
+//! in-memory node that executes each blob with the real contract logic and
+//! reports the outcome on the bus, the way the auto prover does.
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use axum::Router;
+use contract1::{Contract1, MarketAction};
+use hyle_modules::{
+    bus::{metrics::BusMetrics, BusClientSender, SharedMessageBus},
+    module_bus_client,
+    modules::{prover::AutoProverEvent, BuildApiContextInner, Module},
+};
+use sdk::{
+    api::APIRegisterContract, BlobIndex, BlobTransaction, Calldata, ContractName, Hashed,
+    StateCommitment, TxHash, ZkContract,
+};
+use serde_json::Value;
+use server::{
+    app::{AppModule, AppModuleCtx, TxSubmitter},
+    backpressure::SubmissionLimits,
+    cors::CorsConfig,
+    history::HistoryConfig,
+    identity::IdentityConfig,
+    precheck::PrecheckMode,
+};
+
+pub const CONTRACT_NAME: &str = "contract1";
+pub const ADMIN_KEY: &str = "test-admin-key";
+
+pub fn identity(name: &str) -> String {
+    format!("{}@{}", name, CONTRACT_NAME)
+}
+
+module_bus_client! {
+#[derive(Debug)]
+pub struct FakeNodeBusClient {
+    sender(AutoProverEvent<Contract1>),
+}
+}
+
+/// Stands in for the node and the prover: applies every submitted blob to
+/// `state` and publishes `SuccessTx` or `FailedTx` for it.
+pub struct FakeNode {
+    state: Mutex<Contract1>,
+    submitted: Mutex<Vec<BlobTransaction>>,
+    registered: Mutex<Vec<APIRegisterContract>>,
+    /// Set while the prover "falls behind": transactions are accepted and
+    /// applied but never reported as settled
+    stalled: AtomicBool,
+    bus: tokio::sync::Mutex<FakeNodeBusClient>,
+}
+
+impl FakeNode {
+    async fn new(bus: &SharedMessageBus) -> Self {
+        Self {
+            state: Mutex::new(Contract1::new()),
+            submitted: Mutex::new(vec![]),
+            registered: Mutex::new(vec![]),
+            stalled: AtomicBool::new(false),
+            bus: tokio::sync::Mutex::new(FakeNodeBusClient::new_from_bus(bus.new_handle()).await),
+        }
+    }
+
+    pub fn state(&self) -> Contract1 {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn submitted(&self) -> Vec<BlobTransaction> {
+        self.submitted.lock().unwrap().clone()
+    }
+
+    pub fn stall(&self) {
+        self.stalled.store(true, Ordering::SeqCst);
+    }
+
+    /// Contracts registered besides [`CONTRACT_NAME`], in order.
+    pub fn registered(&self) -> Vec<APIRegisterContract> {
+        self.registered.lock().unwrap().clone()
+    }
+
+    /// Settles `tx` without telling the server, whose indexed state goes
+    /// stale, e.g. as if another server instance had sent it.
+    pub fn apply_unannounced(&self, tx: BlobTransaction) {
+        let tx_hash = tx.hashed();
+        self.apply(&tx, &tx_hash);
+        self.submitted.lock().unwrap().push(tx);
+    }
+
+    fn apply(&self, tx: &BlobTransaction, tx_hash: &TxHash) -> AutoProverEvent<Contract1> {
+        let calldata = Calldata {
+            tx_hash: tx_hash.clone(),
+            identity: tx.identity.clone(),
+            blobs: tx.blobs.clone().into(),
+            tx_blob_count: tx.blobs.len(),
+            index: BlobIndex(0),
+            tx_ctx: None,
+            private_input: vec![],
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let mut next = state.clone();
+        match next.execute(&calldata) {
+            Ok(_) => {
+                *state = next;
+                AutoProverEvent::SuccessTx(tx_hash.clone(), state.clone())
+            }
+            Err(error) => AutoProverEvent::FailedTx(tx_hash.clone(), error),
+        }
+    }
+}
+
+#[async_trait]
+impl TxSubmitter for FakeNode {
+    async fn send_tx_blob(&self, tx: BlobTransaction) -> Result<TxHash> {
+        let tx_hash = tx.hashed();
+        let event = self.apply(&tx, &tx_hash);
+        self.submitted.lock().unwrap().push(tx);
+        if !self.stalled.load(Ordering::SeqCst) {
+            self.bus.lock().await.send(event)?;
+        }
+        Ok(tx_hash)
+    }
+
+    async fn get_contract_state(&self, contract_name: &ContractName) -> Result<StateCommitment> {
+        if contract_name.0 == CONTRACT_NAME {
+            return Ok(self.state.lock().unwrap().commit());
+        }
+        match self
+            .registered()
+            .into_iter()
+            .find(|contract| &contract.contract_name == contract_name)
+        {
+            Some(contract) => Ok(contract.state_commitment),
+            None => bail!("contract {} not found", contract_name.0),
+        }
+    }
+
+    async fn register_contract(&self, contract: APIRegisterContract) -> Result<()> {
+        self.registered.lock().unwrap().push(contract);
+        Ok(())
+    }
+}
+
+pub struct TestServer {
+    pub url: String,
+    pub node: Arc<FakeNode>,
+    http: reqwest::Client,
+    // Kept alive so the app module's bus handles stay connected
+    _bus: SharedMessageBus,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Starts with the module context adjusted by `configure`.
+    pub async fn start_with(configure: impl FnOnce(&mut AppModuleCtx)) -> Self {
+        let bus = SharedMessageBus::new(BusMetrics::global("e2e".to_string()));
+        let node = Arc::new(FakeNode::new(&bus).await);
+
+        let api = Arc::new(BuildApiContextInner {
+            router: std::sync::Mutex::new(Some(Router::new())),
+            openapi: Default::default(),
+        });
+        let mut ctx = AppModuleCtx {
+            api: api.clone(),
+            node_client: node.clone(),
+            contract1_cn: ContractName(CONTRACT_NAME.to_string()),
+            cors: Some(CorsConfig::permissive()),
+            identity: Some(IdentityConfig::telegram()),
+            admin_key: Some(ADMIN_KEY.to_string()),
+            contract_admin: Some("alice".to_string()),
+            max_body_size: 65_536,
+            compression: true,
+            webhook: None,
+            history: Some(HistoryConfig {
+                database_url: "sqlite::memory:".to_string(),
+                retention_days: None,
+            }),
+            // Tests repeat identical actions on purpose; tests/duplicates.rs turns it on
+            duplicate_window: Duration::ZERO,
+            metrics: None,
+            // Most tests check how refused transactions settle; tests/precheck.rs turns it on
+            precheck: PrecheckMode::Off,
+            submission_limits: SubmissionLimits::UNLIMITED,
+        };
+        configure(&mut ctx);
+        let mut module = AppModule::build(bus.new_handle(), Arc::new(ctx))
+            .await
+            .expect("build app module");
+        // The module keeps the state served by the GET routes up to date
+        tokio::spawn(async move {
+            let _ = module.run().await;
+        });
+
+        let router = api.router.lock().unwrap().take().expect("app router");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.expect("serve");
+        });
+
+        Self {
+            url,
+            node,
+            http: reqwest::Client::new(),
+            _bus: bus,
+        }
+    }
+
+    /// POSTs `body` to `path` as `user` and returns the status with the JSON body.
+    pub async fn post(&self, user: &str, path: &str, body: Value) -> (u16, Value) {
+        let response = self
+            .http
+            .post(format!("{}{}", self.url, path))
+            .header("x-user", identity(user))
+            .json(&body)
+            .send()
+            .await
+            .expect("request reaches the server");
+        read(response).await
+    }
+
+    /// Like [`TestServer::post`], sending `admin_key` in `x-admin-key` when set.
+    pub async fn post_admin(
+        &self,
+        user: &str,
+        path: &str,
+        body: Value,
+        admin_key: Option<&str>,
+    ) -> (u16, Value) {
+        let mut request = self
+            .http
+            .post(format!("{}{}", self.url, path))
+            .header("x-user", identity(user))
+            .json(&body);
+        if let Some(key) = admin_key {
+            request = request.header("x-admin-key", key);
+        }
+        read(request.send().await.expect("request reaches the server")).await
+    }
+
+    pub async fn post_anonymous(&self, path: &str, body: Value) -> (u16, Value) {
+        let response = self
+            .http
+            .post(format!("{}{}", self.url, path))
+            .json(&body)
+            .send()
+            .await
+            .expect("request reaches the server");
+        read(response).await
+    }
+
+    /// POSTs `body` to `path` with `headers` only, e.g. other credentials than `x-user`.
+    pub async fn post_with(
+        &self,
+        path: &str,
+        body: Value,
+        headers: &[(&str, &str)],
+    ) -> (u16, Value) {
+        let mut request = self.http.post(format!("{}{}", self.url, path)).json(&body);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        read(request.send().await.expect("request reaches the server")).await
+    }
+
+    pub async fn get(&self, path: &str) -> (u16, Value) {
+        let response = self
+            .http
+            .get(format!("{}{}", self.url, path))
+            .send()
+            .await
+            .expect("request reaches the server");
+        read(response).await
+    }
+
+    /// GETs `path` with extra `headers`, leaving the body undecoded.
+    pub async fn get_raw(&self, path: &str, headers: &[(&str, &str)]) -> reqwest::Response {
+        let mut request = self.http.get(format!("{}{}", self.url, path));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send().await.expect("request reaches the server")
+    }
+
+    /// Sends a CORS preflight for `method` on `path` from `origin` and returns
+    /// the status with the `access-control-allow-origin` header, if any.
+    pub async fn preflight(&self, path: &str, origin: &str, method: &str) -> (u16, Option<String>) {
+        let response = self
+            .http
+            .request(reqwest::Method::OPTIONS, format!("{}{}", self.url, path))
+            .header("origin", origin)
+            .header("access-control-request-method", method)
+            .header("access-control-request-headers", "content-type")
+            .send()
+            .await
+            .expect("request reaches the server");
+        let allowed = response
+            .headers()
+            .get("access-control-allow-origin")
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status().as_u16(), allowed)
+    }
+
+    /// GETs `path` until `done` accepts the response: the GET routes read
+    /// state the app module indexes in the background.
+    pub async fn get_until(&self, path: &str, done: impl Fn(u16, &Value) -> bool) -> (u16, Value) {
+        let mut response = self.get(path).await;
+        for _ in 0..100 {
+            if done(response.0, &response.1) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            response = self.get(path).await;
+        }
+        response
+    }
+
+    pub fn state(&self) -> Contract1 {
+        self.node.state()
+    }
+
+    pub fn balance(&self, user: &str) -> u128 {
+        self.state()
+            .users
+            .get(&sdk::Identity(identity(user)))
+            .map(|u| u.balance)
+            .unwrap_or(0)
+    }
+}
+
+/// The action `tx` carries, without the nonce the server adds once a state is indexed.
+pub fn action_of(tx: &BlobTransaction) -> MarketAction {
+    match borsh::from_slice(&tx.blobs[0].data.0).unwrap() {
+        MarketAction::Nonced { action, .. } => *action,
+        action => action,
+    }
+}
+
+/// Error bodies are plain text, success bodies JSON; both come back as a `Value`.
+async fn read(response: reqwest::Response) -> (u16, Value) {
+    let status = response.status().as_u16();
+    let text = response.text().await.expect("response body");
+    let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+    (status, body)
+}
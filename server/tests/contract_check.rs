@@ -0,0 +1,101 @@
+mod common;
+
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use common::TestServer;
+use sdk::{api::APIRegisterContract, BlobTransaction, ContractName, StateCommitment, TxHash};
+use serde_json::json;
+use server::app::TxSubmitter;
+
+/// Forwards transactions to the fake node but reports `state` for the contract.
+struct CommitmentOverride {
+    node: Arc<dyn TxSubmitter>,
+    state: Option<Vec<u8>>,
+}
+
+#[async_trait]
+impl TxSubmitter for CommitmentOverride {
+    async fn send_tx_blob(&self, tx: BlobTransaction) -> Result<TxHash> {
+        self.node.send_tx_blob(tx).await
+    }
+
+    async fn get_contract_state(&self, contract_name: &ContractName) -> Result<StateCommitment> {
+        match &self.state {
+            Some(state) => Ok(StateCommitment(state.clone())),
+            None => bail!("contract {} not found", contract_name.0),
+        }
+    }
+
+    async fn register_contract(&self, contract: APIRegisterContract) -> Result<()> {
+        self.node.register_contract(contract).await
+    }
+}
+
+async fn start_reporting(state: Option<Vec<u8>>) -> TestServer {
+    TestServer::start_with(|ctx| {
+        ctx.node_client = Arc::new(CommitmentOverride {
+            node: ctx.node_client.clone(),
+            state,
+        })
+    })
+    .await
+}
+
+#[tokio::test]
+async fn decodable_state_is_reported() {
+    let server = TestServer::start().await;
+
+    let (status, health) = server.get("/_health").await;
+    assert_eq!(status, 200);
+    let contract = &health["contract"];
+    assert_eq!(contract["status"], "ok");
+    assert_eq!(contract["contract_name"], "contract1");
+    assert_eq!(contract["users"], 0);
+    assert_eq!(contract["state_hash"].as_str().unwrap().len(), 64);
+}
+
+#[tokio::test]
+async fn undecodable_state_degrades_the_server() {
+    let server = start_reporting(Some(vec![0xde, 0xad, 0xbe, 0xef])).await;
+
+    let (status, health) = server.get("/_health").await;
+    assert_eq!(status, 503);
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["contract"]["status"], "incompatible");
+
+    let (status, body) = server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    assert_eq!(status, 503);
+    assert!(
+        body.to_string().contains("contract state incompatible"),
+        "{}",
+        body
+    );
+    assert_eq!(server.node.submitted().len(), 0);
+
+    // Reads do not depend on the node's state
+    let (status, _) = server.get("/api/config").await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn missing_contract_is_reported_without_degrading() {
+    let server = start_reporting(None).await;
+
+    let (status, health) = server.get("/_health").await;
+    assert_eq!(status, 200);
+    assert_eq!(health["contract"]["status"], "unavailable");
+    assert!(health["contract"]["error"]
+        .as_str()
+        .unwrap()
+        .contains("not found"));
+
+    let (status, _) = server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(server.node.submitted().len(), 1);
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::collections::HashMap;
+
+use axum::http::{HeaderValue, Method};
+use common::TestServer;
+use server::cors::{AllowedOrigins, CorsConfig};
+
+const APP: &str = "https://market.example";
+
+fn from_vars(vars: &[(&str, &str)]) -> anyhow::Result<CorsConfig> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    CorsConfig::from_vars(|name| vars.get(name).cloned())
+}
+
+fn restricted() -> CorsConfig {
+    from_vars(&[("CORS_ALLOWED_ORIGINS", APP)]).unwrap()
+}
+
+#[test]
+fn reads_origins_methods_and_credentials() {
+    let cors = from_vars(&[
+        (
+            "CORS_ALLOWED_ORIGINS",
+            "https://a.example, https://b.example,",
+        ),
+        ("CORS_ALLOWED_METHODS", "get,post,put"),
+        ("CORS_ALLOW_CREDENTIALS", "true"),
+    ])
+    .unwrap();
+    assert_eq!(
+        cors.origins,
+        AllowedOrigins::List(vec![
+            HeaderValue::from_static("https://a.example"),
+            HeaderValue::from_static("https://b.example"),
+        ])
+    );
+    assert_eq!(cors.methods, vec![Method::GET, Method::POST, Method::PUT]);
+    assert!(cors.allow_credentials);
+
+    let cors = restricted();
+    assert_eq!(cors.methods, vec![Method::GET, Method::POST]);
+    assert!(!cors.allow_credentials);
+}
+
+#[test]
+fn allow_any_is_explicit() {
+    assert_eq!(
+        from_vars(&[("CORS_ALLOW_ANY", "1")]).unwrap(),
+        CorsConfig::permissive()
+    );
+    assert_eq!(
+        from_vars(&[("CORS_ALLOW_ANY", "1"), ("CORS_ALLOWED_ORIGINS", APP)]).unwrap(),
+        CorsConfig::permissive()
+    );
+    assert_eq!(
+        from_vars(&[("CORS_ALLOW_ANY", "0"), ("CORS_ALLOWED_ORIGINS", APP)]).unwrap(),
+        restricted()
+    );
+}
+
+#[test]
+fn rejects_malformed_lists() {
+    assert!(from_vars(&[("CORS_ALLOWED_ORIGINS", " , ")]).is_err());
+    assert!(from_vars(&[
+        ("CORS_ALLOWED_ORIGINS", APP),
+        ("CORS_ALLOWED_METHODS", "GE T")
+    ])
+    .is_err());
+    assert!(from_vars(&[("CORS_ALLOWED_ORIGINS", "https://a\u{1}.example")]).is_err());
+    assert!(from_vars(&[("CORS_ALLOWED_ORIGINS", "*")]).is_err());
+}
+
+#[test]
+fn missing_configuration() {
+    let result = from_vars(&[]);
+    if cfg!(debug_assertions) {
+        assert_eq!(result.unwrap(), CorsConfig::permissive());
+    } else {
+        let err = result.unwrap_err().to_string();
+        assert!(
+            err.contains("CORS_ALLOWED_ORIGINS") && err.contains("CORS_ALLOW_ANY"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[tokio::test]
+async fn preflight_from_allowed_origin() {
+    let server = TestServer::start_with(|ctx| ctx.cors = Some(restricted())).await;
+    let (status, allowed) = server.preflight("/api/market/bet", APP, "POST").await;
+    assert_eq!(status, 200);
+    assert_eq!(allowed.as_deref(), Some(APP));
+}
+
+#[tokio::test]
+async fn preflight_from_other_origin() {
+    let server = TestServer::start_with(|ctx| ctx.cors = Some(restricted())).await;
+    let (_, allowed) = server
+        .preflight("/api/market/bet", "https://evil.example", "POST")
+        .await;
+    assert_eq!(allowed, None);
+}
+
+#[tokio::test]
+async fn preflight_with_allow_any() {
+    let server = TestServer::start().await;
+    let (_, allowed) = server
+        .preflight("/api/market/bet", "https://evil.example", "POST")
+        .await;
+    assert_eq!(allowed.as_deref(), Some("*"));
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TestServer;
+use serde_json::json;
+
+async fn start(window: Duration) -> TestServer {
+    TestServer::start_with(|ctx| ctx.duplicate_window = window).await
+}
+
+#[tokio::test]
+async fn identical_resubmission_returns_the_original_hash() {
+    let server = start(Duration::from_secs(30)).await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+
+    let create = json!({ "description": "Will it snow?" });
+    let (status, first) = server.post("alice", "/api/market/create", create.clone()).await;
+    assert_eq!(status, 200);
+    let (status, second) = server.post("alice", "/api/market/create", create).await;
+    assert_eq!(status, 200);
+
+    // The duplicate only gets the hash back, not the market id
+    assert_eq!(first["tx_hash"], second);
+    assert_eq!(server.node.submitted().len(), 2);
+    assert_eq!(server.state().markets.len(), 1);
+}
+
+#[tokio::test]
+async fn slightly_different_payloads_are_both_submitted() {
+    let server = start(Duration::from_secs(30)).await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    let market_id = server.state().next_market_id;
+
+    let (_, first) = server
+        .post("alice", "/api/market/bet", json!({ "market_id": market_id, "side": true, "amount": 100 }))
+        .await;
+    let (_, second) = server
+        .post("alice", "/api/market/bet", json!({ "market_id": market_id, "side": true, "amount": 101 }))
+        .await;
+
+    assert_ne!(first, second);
+    assert_eq!(server.node.submitted().len(), 4);
+}
+
+#[tokio::test]
+async fn the_same_action_from_another_identity_is_submitted() {
+    let server = start(Duration::from_secs(30)).await;
+
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server.post("bob", "/api/market/initialize", json!({})).await;
+
+    assert_eq!(server.node.submitted().len(), 2);
+    assert!(server.balance("bob") > 0);
+}
+
+#[tokio::test]
+async fn resubmission_after_the_window_is_submitted() {
+    let server = start(Duration::from_millis(200)).await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+
+    let create = json!({ "description": "Will it snow?" });
+    server.post("alice", "/api/market/create", create.clone()).await;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    server.post("alice", "/api/market/create", create).await;
+
+    assert_eq!(server.state().markets.len(), 2);
+}
+
+#[tokio::test]
+async fn read_only_actions_are_never_deduplicated() {
+    let server = start(Duration::from_secs(30)).await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+
+    server.post("alice", "/api/market/balance", json!({})).await;
+    server.post("alice", "/api/market/balance", json!({})).await;
+
+    assert_eq!(server.node.submitted().len(), 3);
+}
+
+#[tokio::test]
+async fn rejected_actions_can_be_retried_as_is() {
+    let server = start(Duration::from_secs(30)).await;
+    let bet = json!({ "market_id": 1, "side": true, "amount": 100 });
+
+    let (status, _) = server.post("alice", "/api/market/bet", bet.clone()).await;
+    assert_eq!(status, 400);
+    let (status, _) = server.post("alice", "/api/market/bet", bet).await;
+    assert_eq!(status, 400);
+
+    assert_eq!(server.node.submitted().len(), 2);
+}
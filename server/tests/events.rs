@@ -0,0 +1,72 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{identity, TestServer};
+use contract1::api::{MarketEvent, WebhookPayload};
+use serde_json::json;
+
+/// Reads server-sent events until `count` messages came, with their ids.
+async fn read_messages(response: &mut reqwest::Response, count: usize) -> Vec<(String, WebhookPayload)> {
+    let mut buffer = String::new();
+    let mut messages = vec![];
+    while messages.len() < count {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+            .await
+            .expect("an event in time")
+            .unwrap()
+            .expect("the stream stays open");
+        buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        while let Some(end) = buffer.find("\n\n") {
+            let frame: String = buffer.drain(..end + 2).collect();
+            let field = |name: &str| {
+                frame
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(|value| value.trim_start().to_string())
+            };
+            // Keep-alive comments have neither
+            if let (Some(id), Some(data)) = (field("id:"), field("data:")) {
+                messages.push((id, serde_json::from_str(&data).unwrap()));
+            }
+        }
+    }
+    messages
+}
+
+fn bet(market_id: u64, user: &str, amount: u128) -> MarketEvent {
+    MarketEvent::BetPlaced { market_id, bettor: identity(user), side: true, amount }
+}
+
+#[tokio::test]
+async fn subscribers_receive_filtered_events_and_resume_after_their_last_id() {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it rain?" })).await;
+
+    let mut everything = server.get_raw("/api/events", &[]).await;
+    let mut second_market = server.get_raw("/api/events?market_id=2", &[]).await;
+    assert_eq!(everything.status(), 200);
+    assert_eq!(everything.headers()["content-type"], "text/event-stream");
+
+    server.post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 100 })).await;
+    server.post("bob", "/api/market/bet", json!({ "market_id": 2, "side": true, "amount": 40 })).await;
+    server.post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 60 })).await;
+
+    let messages = read_messages(&mut everything, 3).await;
+    let events: Vec<_> = messages.iter().flat_map(|(_, payload)| payload.events.clone()).collect();
+    assert_eq!(events, [bet(1, "alice", 100), bet(2, "bob", 40), bet(1, "alice", 60)]);
+    for (id, payload) in &messages {
+        assert_eq!(id, &payload.tx_hash);
+    }
+    let filtered = read_messages(&mut second_market, 1).await;
+    assert_eq!(filtered[0].1.events, [bet(2, "bob", 40)]);
+
+    // Reconnecting after the first message replays the two that followed
+    let mut resumed = server.get_raw("/api/events", &[("last-event-id", messages[0].0.as_str())]).await;
+    let replayed = read_messages(&mut resumed, 2).await;
+    assert_eq!(replayed, messages[1..]);
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use common::{identity, TestServer};
+use sdk::Hashed;
+use serde_json::{json, Value};
+
+fn actions(body: &Value) -> Vec<&str> {
+    body["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn market_lifecycle_is_recorded_in_order() {
+    let server = TestServer::start().await;
+
+    server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    let (status, created) = server
+        .post(
+            "alice",
+            "/api/market/create",
+            json!({ "description": "Will it snow?" }),
+        )
+        .await;
+    assert_eq!(status, 200);
+    let market_id = server.state().next_market_id;
+    server
+        .post(
+            "alice",
+            "/api/market/bet",
+            json!({ "market_id": market_id, "side": true, "amount": 300 }),
+        )
+        .await;
+    server
+        .post(
+            "alice",
+            "/api/market/resolve",
+            json!({ "market_id": market_id, "outcome": true }),
+        )
+        .await;
+
+    let path = format!("/api/user/{}/history?limit=3", identity("alice"));
+    let (status, body) = server
+        .get_until(&path, |_, body| {
+            body["entries"].as_array().is_some_and(|e| e.len() == 3)
+        })
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["identity"], identity("alice"));
+    assert_eq!(
+        actions(&body),
+        ["create_market", "place_bet", "resolve_market"]
+    );
+
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries[0]["tx_hash"], created["tx_hash"]);
+    assert_eq!(entries[1]["market_id"], market_id);
+    assert_eq!(entries[1]["amount"], "300");
+    assert!(entries.iter().all(|entry| entry["result"] == "success"));
+    assert!(entries
+        .windows(2)
+        .all(|w| w[0]["timestamp"].as_i64() <= w[1]["timestamp"].as_i64()));
+}
+
+#[tokio::test]
+async fn failed_actions_are_recorded_with_their_error() {
+    let server = TestServer::start().await;
+
+    let (status, _) = server
+        .post(
+            "bob",
+            "/api/market/create",
+            json!({ "description": "too early" }),
+        )
+        .await;
+    assert_eq!(status, 400);
+
+    let path = format!("/api/user/{}/history", identity("bob"));
+    let (_, body) = server
+        .get_until(&path, |_, body| {
+            body["entries"].as_array().is_some_and(|e| !e.is_empty())
+        })
+        .await;
+    let entry = &body["entries"][0];
+    assert_eq!(entry["action"], "create_market");
+    assert_eq!(entry["result"], "failed");
+    assert!(
+        entry["error"].as_str().unwrap().contains("not initialized"),
+        "{}",
+        entry
+    );
+}
+
+#[tokio::test]
+async fn history_is_per_identity() {
+    let server = TestServer::start().await;
+
+    server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    let path = format!("/api/user/{}/history", identity("alice"));
+    server
+        .get_until(&path, |_, body| {
+            body["entries"].as_array().is_some_and(|e| !e.is_empty())
+        })
+        .await;
+
+    let (status, body) = server
+        .get(&format!("/api/user/{}/history", identity("bob")))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["entries"], json!([]));
+}
+
+#[tokio::test]
+async fn history_route_is_disabled_without_a_store() {
+    let server = TestServer::start_with(|ctx| ctx.history = None).await;
+
+    let (status, _) = server
+        .get(&format!("/api/user/{}/history", identity("alice")))
+        .await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn transaction_status_reports_the_outcome() {
+    let server = TestServer::start().await;
+
+    server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    let initialized = server.node.submitted()[0].hashed();
+    let (status, _) = server
+        .post(
+            "bob",
+            "/api/market/create",
+            json!({ "description": "too early" }),
+        )
+        .await;
+    assert_eq!(status, 400);
+    let rejected = server.node.submitted().last().unwrap().hashed();
+
+    let (status, body) = server
+        .get_until(&format!("/api/tx/{}", initialized), |status, body| {
+            status == 200 && body["status"] != "pending"
+        })
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["tx_hash"], initialized.to_string());
+    assert_eq!(body["status"], "success");
+    assert_eq!(body["error"], Value::Null);
+
+    let (_, body) = server
+        .get_until(&format!("/api/tx/{}", rejected), |status, body| {
+            status == 200 && body["status"] != "pending"
+        })
+        .await;
+    assert_eq!(body["status"], "failed");
+    assert!(
+        body["error"].as_str().unwrap().contains("not initialized"),
+        "{}",
+        body
+    );
+
+    let (status, _) = server.get("/api/tx/0000").await;
+    assert_eq!(status, 404);
+}
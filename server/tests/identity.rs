@@ -0,0 +1,443 @@
+mod common;
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{identity, TestServer, CONTRACT_NAME};
+use hmac::{Hmac, Mac};
+use sdk::{ContractName, Identity};
+use secp256k1::{Message, Secp256k1, SecretKey};
+use serde_json::{json, Value};
+use server::identity::{
+    verify_hs256, verify_wallet_signature, wallet_message, Claims, IdentityConfig, IdentityError,
+    ProviderKind,
+};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+const SECRET: &[u8] = b"a shared secret of at least 32 bytes";
+const NOW: u64 = 1_750_000_000;
+const WALLET_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+/// The address of `WALLET_KEY`
+const WALLET: &str = "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23";
+
+fn sign(secret: &[u8], header: &Value, claims: &Value) -> String {
+    let encode = |value: &Value| URL_SAFE_NO_PAD.encode(serde_json::to_vec(value).unwrap());
+    let signed = format!("{}.{}", encode(header), encode(claims));
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(signed.as_bytes());
+    format!(
+        "{}.{}",
+        signed,
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    )
+}
+
+fn token(claims: Value) -> String {
+    sign(SECRET, &json!({ "alg": "HS256", "typ": "JWT" }), &claims)
+}
+
+/// What a wallet's `personal_sign` returns for the login message of `address`.
+fn wallet_sign(key: &str, address: &str, timestamp: u64) -> String {
+    let message = wallet_message(
+        &ContractName(CONTRACT_NAME.to_string()),
+        &address.to_lowercase(),
+        timestamp,
+    );
+    let digest: [u8; 32] = Keccak256::new()
+        .chain_update(format!("\x19Ethereum Signed Message:\n{}", message.len()))
+        .chain_update(message)
+        .finalize()
+        .into();
+    let key = SecretKey::from_slice(&hex::decode(key).unwrap()).unwrap();
+    let (recovery_id, compact) = Secp256k1::new()
+        .sign_ecdsa_recoverable(&Message::from_digest(digest), &key)
+        .serialize_compact();
+    let mut signature = compact.to_vec();
+    signature.push(27 + i32::from(recovery_id) as u8);
+    format!("0x{}", hex::encode(signature))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn from_vars(vars: &[(&str, &str)]) -> anyhow::Result<IdentityConfig> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    IdentityConfig::from_vars(|name| vars.get(name).cloned())
+}
+
+#[test]
+fn valid_tokens_yield_their_subject() {
+    let claims = verify_hs256(
+        &token(json!({ "sub": "user-7", "exp": NOW + 60 })),
+        SECRET,
+        NOW,
+    )
+    .unwrap();
+    assert_eq!(
+        claims,
+        Claims {
+            sub: "user-7".to_string(),
+            exp: NOW + 60,
+            nbf: None
+        }
+    );
+
+    // A little clock skew is tolerated both ways
+    assert!(verify_hs256(
+        &token(json!({ "sub": "user-7", "exp": NOW - 10 })),
+        SECRET,
+        NOW
+    )
+    .is_ok());
+    assert!(verify_hs256(
+        &token(json!({ "sub": "user-7", "exp": NOW + 60, "nbf": NOW + 10 })),
+        SECRET,
+        NOW
+    )
+    .is_ok());
+}
+
+#[test]
+fn expired_and_premature_tokens_are_refused() {
+    let expired = token(json!({ "sub": "user-7", "exp": NOW - 3_600 }));
+    assert_eq!(
+        verify_hs256(&expired, SECRET, NOW),
+        Err(IdentityError::Expired)
+    );
+    let premature = token(json!({ "sub": "user-7", "exp": NOW + 7_200, "nbf": NOW + 3_600 }));
+    assert_eq!(
+        verify_hs256(&premature, SECRET, NOW),
+        Err(IdentityError::NotYetValid)
+    );
+    // Tokens that never expire are not accepted
+    let forever = token(json!({ "sub": "user-7" }));
+    assert_eq!(
+        verify_hs256(&forever, SECRET, NOW),
+        Err(IdentityError::Malformed)
+    );
+}
+
+#[test]
+fn forged_tokens_are_refused() {
+    let claims = json!({ "sub": "user-7", "exp": NOW + 60 });
+    let other_secret = sign(
+        b"another secret, also 32 bytes long",
+        &json!({ "alg": "HS256" }),
+        &claims,
+    );
+    assert_eq!(
+        verify_hs256(&other_secret, SECRET, NOW),
+        Err(IdentityError::Signature)
+    );
+
+    // A genuine signature over other claims
+    let genuine = token(claims);
+    let mut parts: Vec<&str> = genuine.split('.').collect();
+    let admin = URL_SAFE_NO_PAD.encode(json!({ "sub": "admin", "exp": NOW + 60 }).to_string());
+    parts[1] = &admin;
+    assert_eq!(
+        verify_hs256(&parts.join("."), SECRET, NOW),
+        Err(IdentityError::Signature)
+    );
+
+    let unsigned = format!(
+        "{}.",
+        sign(
+            SECRET,
+            &json!({ "alg": "none" }),
+            &json!({ "sub": "admin", "exp": NOW + 60 })
+        )
+        .rsplit_once('.')
+        .unwrap()
+        .0
+    );
+    assert_eq!(
+        verify_hs256(&unsigned, SECRET, NOW),
+        Err(IdentityError::Algorithm("none".to_string()))
+    );
+    let asymmetric = sign(
+        SECRET,
+        &json!({ "alg": "RS256" }),
+        &json!({ "sub": "admin", "exp": NOW + 60 }),
+    );
+    assert_eq!(
+        verify_hs256(&asymmetric, SECRET, NOW),
+        Err(IdentityError::Algorithm("RS256".to_string()))
+    );
+
+    for malformed in ["", "a.b", "a.b.c.d", "!!.??.##"] {
+        assert_eq!(
+            verify_hs256(malformed, SECRET, NOW),
+            Err(IdentityError::Malformed),
+            "{}",
+            malformed
+        );
+    }
+    let spoofed = token(json!({ "sub": "42@contract1", "exp": NOW + 60 }));
+    assert_eq!(
+        verify_hs256(&spoofed, SECRET, NOW),
+        Err(IdentityError::Subject("42@contract1".to_string()))
+    );
+}
+
+#[test]
+fn wallet_signatures_prove_the_address() {
+    let contract = ContractName(CONTRACT_NAME.to_string());
+    let address = WALLET.to_lowercase();
+    let signature = wallet_sign(WALLET_KEY, WALLET, NOW);
+    assert_eq!(
+        verify_wallet_signature(&address, NOW, &signature, &contract, NOW + 60),
+        Ok(())
+    );
+    // The window holds both ways, for clocks behind or ahead of the server
+    assert_eq!(
+        verify_wallet_signature(&address, NOW, &signature, &contract, NOW - 300),
+        Ok(())
+    );
+    assert_eq!(
+        verify_wallet_signature(&address, NOW, &signature, &contract, NOW + 301),
+        Err(IdentityError::WalletExpired)
+    );
+
+    // Signed by another key, for another time or for another contract
+    let other_key = "0000000000000000000000000000000000000000000000000000000000000001";
+    let cases = [
+        (wallet_sign(other_key, WALLET, NOW), NOW, contract.clone()),
+        (signature.clone(), NOW + 1, contract.clone()),
+        (
+            signature.clone(),
+            NOW,
+            ContractName("contract2".to_string()),
+        ),
+        ("0x1234".to_string(), NOW, contract.clone()),
+        (
+            format!("{}zz", &signature[..signature.len() - 2]),
+            NOW,
+            contract,
+        ),
+    ];
+    for (signature, timestamp, contract) in cases {
+        assert_eq!(
+            verify_wallet_signature(&address, timestamp, &signature, &contract, NOW),
+            Err(IdentityError::WalletSignature),
+            "{} at {}",
+            signature,
+            timestamp
+        );
+    }
+}
+
+#[test]
+fn providers_are_read_from_the_environment() {
+    assert_eq!(from_vars(&[]).unwrap(), IdentityConfig::telegram());
+    let secret = std::str::from_utf8(SECRET).unwrap();
+    let config = from_vars(&[
+        ("IDENTITY_PROVIDERS", "jwt, Wallet,telegram"),
+        ("JWT_SECRET", secret),
+    ])
+    .unwrap();
+    assert_eq!(
+        config.providers,
+        vec![
+            ProviderKind::Jwt {
+                secret: SECRET.to_vec()
+            },
+            ProviderKind::Wallet,
+            ProviderKind::Telegram
+        ]
+    );
+    // The secret never shows in logs
+    assert_eq!(
+        format!("{:?}", config),
+        "IdentityConfig { providers: [Jwt, Wallet, Telegram] }"
+    );
+
+    let errors = [
+        (
+            vec![("IDENTITY_PROVIDERS", "jwt")],
+            "IDENTITY_PROVIDERS includes jwt but JWT_SECRET is not set",
+        ),
+        (
+            vec![("IDENTITY_PROVIDERS", "jwt"), ("JWT_SECRET", "short")],
+            "JWT_SECRET must be at least 32 bytes",
+        ),
+        (
+            vec![("IDENTITY_PROVIDERS", "oauth")],
+            "unknown identity provider \"oauth\", expected telegram, wallet or jwt",
+        ),
+        (
+            vec![("IDENTITY_PROVIDERS", "wallet,wallet")],
+            "identity provider wallet is listed twice",
+        ),
+        (
+            vec![("IDENTITY_PROVIDERS", " , ")],
+            "IDENTITY_PROVIDERS is empty",
+        ),
+    ];
+    for (vars, expected) in errors {
+        assert_eq!(from_vars(&vars).unwrap_err().to_string(), expected);
+    }
+}
+
+async fn multi_provider_server() -> TestServer {
+    TestServer::start_with(|ctx| {
+        ctx.identity = Some(IdentityConfig {
+            providers: vec![
+                ProviderKind::Jwt {
+                    secret: SECRET.to_vec(),
+                },
+                ProviderKind::Wallet,
+                ProviderKind::Telegram,
+            ],
+        });
+    })
+    .await
+}
+
+fn bearer(claims: Value) -> String {
+    format!("Bearer {}", token(claims))
+}
+
+#[tokio::test]
+async fn web_users_and_the_bot_share_the_routes() {
+    let server = multi_provider_server().await;
+
+    let authorization = bearer(json!({ "sub": "google-1234", "exp": unix_now() + 600 }));
+    let (status, _) = server
+        .post_with(
+            "/api/market/initialize",
+            json!({}),
+            &[("authorization", &authorization)],
+        )
+        .await;
+    assert_eq!(status, 200);
+    let now = unix_now();
+    let signature = wallet_sign(WALLET_KEY, WALLET, now);
+    let (status, _) = server
+        .post_with(
+            "/api/market/initialize",
+            json!({}),
+            &[
+                ("x-user", WALLET),
+                ("x-wallet-timestamp", &now.to_string()),
+                ("x-wallet-signature", &signature),
+            ],
+        )
+        .await;
+    assert_eq!(status, 200);
+    server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+
+    let users = server.state().users;
+    for user in [
+        identity("google-1234"),
+        identity("0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"),
+        identity("alice"),
+    ] {
+        assert!(
+            users.contains_key(&Identity(user.clone())),
+            "{} is missing",
+            user
+        );
+    }
+    // A valid token decides, whatever x-user claims
+    let (status, _) = server
+        .post_with(
+            "/api/market/create",
+            json!({ "description": "Rain?" }),
+            &[
+                ("authorization", &authorization),
+                ("x-user", &identity("alice")),
+            ],
+        )
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        server.state().markets[&1].creator,
+        Identity(identity("google-1234"))
+    );
+}
+
+#[tokio::test]
+async fn refused_credentials_are_unauthorized_before_submission() {
+    let server = multi_provider_server().await;
+
+    let expired = bearer(json!({ "sub": "google-1234", "exp": unix_now() - 600 }));
+    let forged = format!(
+        "Bearer {}",
+        sign(
+            b"not the secret the server shares!!",
+            &json!({ "alg": "HS256" }),
+            &json!({ "sub": "google-1234", "exp": unix_now() + 600 })
+        )
+    );
+    let stale = (unix_now() - 3_600).to_string();
+    let stale_signature = wallet_sign(WALLET_KEY, WALLET, unix_now() - 3_600);
+    let now = unix_now().to_string();
+    let impostor = wallet_sign(
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        WALLET,
+        unix_now(),
+    );
+    let cases = [
+        (vec![("authorization", expired.as_str())], "token expired"),
+        // A refused token is not overridden by another credential
+        (
+            vec![
+                ("authorization", forged.as_str()),
+                ("x-user", "alice@contract1"),
+            ],
+            "invalid token signature",
+        ),
+        (
+            vec![("authorization", "Basic YWxpY2U6c2VjcmV0")],
+            "malformed token",
+        ),
+        (
+            vec![("x-user", "0x1234")],
+            "invalid wallet address \"0x1234\"",
+        ),
+        // A bare address is not taken as is, nor left to the bot's scheme
+        (vec![("x-user", WALLET)], "missing wallet signature"),
+        (
+            vec![
+                ("x-user", WALLET),
+                ("x-wallet-timestamp", now.as_str()),
+                ("x-wallet-signature", impostor.as_str()),
+            ],
+            "invalid wallet signature",
+        ),
+        (
+            vec![
+                ("x-user", WALLET),
+                ("x-wallet-timestamp", stale.as_str()),
+                ("x-wallet-signature", stale_signature.as_str()),
+            ],
+            "wallet signature expired",
+        ),
+        (vec![], "Missing signature"),
+    ];
+    for (headers, expected) in cases {
+        let (status, body) = server
+            .post_with("/api/market/initialize", json!({}), &headers)
+            .await;
+        assert_eq!(status, 401, "{:?}", headers);
+        assert!(
+            body.to_string().contains(expected),
+            "{:?}: {}",
+            headers,
+            body
+        );
+    }
+    assert!(server.node.submitted().is_empty());
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::TestServer;
+use serde_json::json;
+
+#[tokio::test]
+async fn oversized_body_is_rejected_before_the_handler() {
+    let server = TestServer::start().await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+
+    let description = "x".repeat(100_000);
+    let (status, _) = server
+        .post("alice", "/api/market/create", json!({ "description": description }))
+        .await;
+    assert_eq!(status, 413);
+    // Only the initialize transaction reached the node
+    assert_eq!(server.node.submitted().len(), 1);
+}
+
+#[tokio::test]
+async fn body_limit_is_configurable() {
+    let server = TestServer::start_with(|ctx| ctx.max_body_size = 32).await;
+
+    let (status, _) = server
+        .post("alice", "/api/market/create", json!({ "description": "Will the description fit?" }))
+        .await;
+    assert_eq!(status, 413);
+    assert!(server.node.submitted().is_empty());
+}
+
+async fn with_markets(server: &TestServer, count: usize) {
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    for i in 0..count {
+        let description = format!("Will market number {} settle YES before the end of the season?", i);
+        server.post("alice", "/api/market/create", json!({ "description": description })).await;
+    }
+    server
+        .get_until("/api/markets", |status, body| status == 200 && body["total"] == count)
+        .await;
+}
+
+#[tokio::test]
+async fn list_is_compressed_when_accepted() {
+    let server = TestServer::start().await;
+    with_markets(&server, 20).await;
+
+    let response = server.get_raw("/api/markets", &[("accept-encoding", "gzip")]).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+
+    let response = server.get_raw("/api/markets", &[]).await;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn compression_can_be_disabled() {
+    let server = TestServer::start_with(|ctx| ctx.compression = false).await;
+    with_markets(&server, 20).await;
+
+    let response = server.get_raw("/api/markets", &[("accept-encoding", "gzip, br")]).await;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("content-encoding").is_none());
+}
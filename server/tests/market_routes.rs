@@ -0,0 +1,328 @@
+mod common;
+
+use common::{action_of, identity, TestServer, ADMIN_KEY};
+use contract1::{MarketAction, MarketStatus, RESOLUTION_COOLDOWN};
+use serde_json::json;
+
+const INITIAL_BALANCE: u128 = 10_000;
+
+fn body_text(body: &serde_json::Value) -> String {
+    match body {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn health_and_config() {
+    let server = TestServer::start().await;
+
+    let (status, health) = server.get("/_health").await;
+    assert_eq!(status, 200);
+    assert_eq!(health["status"], "OK");
+    assert_eq!(health["contract"]["status"], "ok");
+    assert_eq!(health["contract"]["markets"], 0);
+    let (status, body) = server.get("/api/config").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["contract_name"], "contract1");
+    assert_eq!(body["api_version"], server::app::API_VERSION);
+    let params = &body["params"];
+    assert_eq!(params["initial_balance"], INITIAL_BALANCE as u64);
+    assert_eq!(params["min_bet"], 1);
+    assert_eq!(params["max_bet"], serde_json::Value::Null);
+    assert_eq!(params["fee_bps"], 0);
+    assert_eq!(params["resolution_cooldown_secs"], RESOLUTION_COOLDOWN);
+    assert_eq!(params["features"]["deadlines"], false);
+}
+
+#[tokio::test]
+async fn full_market_lifecycle() {
+    let server = TestServer::start().await;
+
+    for user in ["alice", "bob"] {
+        let (status, body) = server.post(user, "/api/market/initialize", json!({})).await;
+        assert_eq!(status, 200, "initialize {}: {}", user, body);
+        assert!(body.is_string(), "success returns the tx hash: {}", body);
+    }
+
+    let (status, _) = server
+        .post("alice", "/api/market/create", json!({ "description": "Will it snow?" }))
+        .await;
+    assert_eq!(status, 200);
+    let market_id = server.state().next_market_id;
+    assert_eq!(server.state().markets[&market_id].description, "Will it snow?");
+
+    let (status, _) = server
+        .post("alice", "/api/market/bet", json!({ "market_id": market_id, "side": true, "amount": 300 }))
+        .await;
+    assert_eq!(status, 200);
+    let (status, _) = server
+        .post("bob", "/api/market/bet", json!({ "market_id": market_id, "side": false, "amount": 100 }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(server.balance("alice"), INITIAL_BALANCE - 300);
+    assert_eq!(server.balance("bob"), INITIAL_BALANCE - 100);
+    let (status, body) = server.post("alice", "/api/market/exposure", json!({})).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["result"], json!({ "yes": 300, "no": 0, "positions": 1 }));
+
+    let (status, body) = server
+        .post("alice", "/api/market/resolve", json!({ "market_id": market_id, "outcome": true }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["result"], json!({ "outcome": true, "total_distributed": 400, "winner_count": 1 }));
+    let (_, body) = server.post("alice", "/api/market/exposure", json!({})).await;
+    assert_eq!(body["result"], json!({ "yes": 0, "no": 0, "positions": 0 }));
+
+    // Bob's losing bet pays nothing
+    let (status, body) = server.post("bob", "/api/market/claim", json!({ "market_id": market_id })).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["result"], json!({ "market_id": market_id, "payout": 0 }));
+
+    let (status, _) = server.post("alice", "/api/market/balance", json!({})).await;
+    assert_eq!(status, 200);
+    let (status, _) = server
+        .post("alice", "/api/market/info", json!({ "market_id": market_id }))
+        .await;
+    assert_eq!(status, 200);
+    let (status, _) = server.post("alice", "/api/market/stats", json!({})).await;
+    assert_eq!(status, 200);
+
+    let state = server.state();
+    assert_eq!(state.markets[&market_id].status, MarketStatus::ResolvedYes);
+    assert_eq!(state.users[&sdk::Identity(identity("alice"))].current_streak, 1);
+    assert_eq!(state.users[&sdk::Identity(identity("bob"))].current_streak, 0);
+    assert_eq!(server.balance("alice"), INITIAL_BALANCE + 100);
+    assert_eq!(server.balance("bob"), INITIAL_BALANCE - 100);
+}
+
+#[tokio::test]
+async fn create_reports_the_market_id_and_config_the_state_epoch() {
+    let server = TestServer::start().await;
+
+    let (_, body) = server.get("/api/config").await;
+    assert_eq!(body["state_epoch"], serde_json::Value::Null, "nothing indexed yet: {}", body);
+
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    for expected in [1, 2] {
+        let (status, body) = server
+            .post("alice", "/api/market/create", json!({ "description": "Will it snow?" }))
+            .await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], json!({ "market_id": expected }));
+        assert!(body["tx_hash"].is_string(), "{}", body);
+    }
+
+    // The test node starts from `Contract1::new()`, whose epoch is 0
+    server.get_until("/api/config", |status, body| status == 200 && body["state_epoch"] == 0).await;
+}
+
+#[tokio::test]
+async fn idempotent_initialize_grants_once_and_reports_the_balance() {
+    let server = TestServer::start().await;
+
+    let (status, body) = server.post("alice", "/api/market/initialize", json!({ "idempotent": true })).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["result"], json!({ "balance": INITIAL_BALANCE as u64, "already_initialized": false }));
+    assert!(body["tx_hash"].is_string(), "{}", body);
+    // The outcome is read against the indexed state
+    server
+        .get_until("/api/market/leaderboard", |status, body| status == 200 && body[0]["balance"] == INITIAL_BALANCE as u64)
+        .await;
+
+    for _ in 0..2 {
+        let (status, body) = server.post("alice", "/api/market/initialize", json!({ "idempotent": true })).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], json!({ "balance": INITIAL_BALANCE as u64, "already_initialized": true }));
+    }
+    assert_eq!(server.balance("alice"), INITIAL_BALANCE);
+
+    // Without the flag a second initialization is still an error
+    let (status, body) = server.post("alice", "/api/market/initialize", json!({})).await;
+    assert_eq!(status, 400);
+    assert!(body_text(&body).contains("already initialized"), "{}", body);
+}
+
+#[tokio::test]
+async fn routes_submit_the_expected_blob() {
+    let server = TestServer::start().await;
+
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server
+        .post("alice", "/api/market/create", json!({ "description": "blob check" }))
+        .await;
+
+    let submitted = server.node.submitted();
+    assert_eq!(submitted.len(), 2);
+    assert_eq!(submitted[1].identity.0, identity("alice"));
+    assert_eq!(submitted[1].blobs.len(), 1);
+    assert_eq!(submitted[1].blobs[0].contract_name.0, "contract1");
+    let action = action_of(&submitted[1]);
+    assert_eq!(
+        action,
+        MarketAction::CreateMarket { description: "blob check".to_string(), opens_at: None, stake_cap: None, tags: vec![], challenge: None }
+    );
+}
+
+#[tokio::test]
+async fn parlay_routes_submit_their_legs() {
+    let server = TestServer::start().await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+
+    server
+        .post("alice", "/api/market/parlay", json!({ "legs": [[3, true], [5, false]], "amount": 100 }))
+        .await;
+    server.post("alice", "/api/market/parlay/settle", json!({ "parlay_id": 1 })).await;
+
+    let submitted = server.node.submitted();
+    let actions: Vec<MarketAction> = submitted[1..].iter().map(action_of).collect();
+    assert_eq!(
+        actions,
+        vec![
+            MarketAction::PlaceParlay { legs: vec![(3, true), (5, false)], amount: 100 },
+            MarketAction::SettleParlay { parlay_id: 1 },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn challenge_routes_escrow_both_stakes() {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob", "carol"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+
+    let challenge = json!({ "description": "Who wins at chess?", "challenge": { "opponent": identity("bob"), "stake": 500 } });
+    let (status, body) = server.post("alice", "/api/market/create", challenge.clone()).await;
+    assert_eq!(status, 200, "{}", body);
+    let accepted = server.state().next_market_id;
+    assert_eq!(server.state().markets[&accepted].status, MarketStatus::PendingAcceptance);
+    assert_eq!(server.balance("alice"), INITIAL_BALANCE - 500);
+
+    let (status, body) = server.post("carol", "/api/market/challenge/accept", json!({ "market_id": accepted })).await;
+    assert_eq!(status, 400);
+    assert!(body_text(&body).contains("Only the opponent"), "{}", body);
+    let (status, _) = server.post("bob", "/api/market/challenge/accept", json!({ "market_id": accepted })).await;
+    assert_eq!(status, 200);
+    assert_eq!(server.state().markets[&accepted].status, MarketStatus::Open);
+    assert_eq!(server.balance("bob"), INITIAL_BALANCE - 500);
+
+    // A declined challenge gives the creator's stake back
+    server.post("alice", "/api/market/create", challenge).await;
+    let declined = server.state().next_market_id;
+    let (status, _) = server.post("bob", "/api/market/challenge/cancel", json!({ "market_id": declined })).await;
+    assert_eq!(status, 200);
+    assert_eq!(server.state().markets[&declined].status, MarketStatus::Cancelled);
+    assert_eq!(server.balance("alice"), INITIAL_BALANCE - 500);
+}
+
+#[tokio::test]
+async fn expire_route_refuses_markets_within_their_lifetime() {
+    let server = TestServer::start().await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it rain?" })).await;
+    let market_id = server.state().next_market_id;
+
+    let (status, body) = server.post("bob", "/api/market/expire", json!({ "market_id": market_id })).await;
+    assert_eq!(status, 400);
+    assert!(body_text(&body).contains("cannot be expired"), "{}", body);
+    assert_eq!(server.state().markets[&market_id].status, MarketStatus::Open);
+}
+
+#[tokio::test]
+async fn contract_errors_are_bad_requests() {
+    let server = TestServer::start().await;
+
+    let (status, body) = server
+        .post("alice", "/api/market/create", json!({ "description": "too early" }))
+        .await;
+    assert_eq!(status, 400);
+    assert!(body_text(&body).contains("not initialized"), "{}", body);
+
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    let (status, body) = server.post("alice", "/api/market/initialize", json!({})).await;
+    assert_eq!(status, 400);
+    assert!(body_text(&body).contains("already initialized"), "{}", body);
+
+    let (status, body) = server
+        .post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 1 }))
+        .await;
+    assert_eq!(status, 400);
+    assert!(body_text(&body).contains("Market not found"), "{}", body);
+
+    // Failed transactions leave the state untouched
+    assert!(server.state().markets.is_empty());
+    assert_eq!(server.balance("alice"), INITIAL_BALANCE);
+}
+
+#[tokio::test]
+async fn treasury_withdrawal_is_admin_only() {
+    let server = TestServer::start().await;
+
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server.post("alice", "/api/market/create", json!({ "description": "nobody backs YES" })).await;
+    let market_id = server.state().next_market_id;
+    server
+        .post("alice", "/api/market/bet", json!({ "market_id": market_id, "side": false, "amount": 500 }))
+        .await;
+    server
+        .post("alice", "/api/market/resolve", json!({ "market_id": market_id, "outcome": true }))
+        .await;
+    assert_eq!(server.state().treasury, 500);
+
+    let (status, _) = server.post("bob", "/api/market/treasury", json!({})).await;
+    assert_eq!(status, 200);
+
+    let (status, _) = server
+        .post_admin("alice", "/api/market/set_admin", json!({ "new_admin": identity("alice") }), Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 200);
+
+    // The admin key gets past the server, the contract still checks the caller
+    let withdraw = json!({ "to": identity("alice"), "amount": 200 });
+    let (status, body) = server
+        .post_admin("bob", "/api/market/treasury/withdraw", withdraw.clone(), Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 400);
+    assert!(body_text(&body).contains("Only the admin"), "{}", body);
+
+    let (status, _) = server
+        .post_admin("alice", "/api/market/treasury/withdraw", withdraw, Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(server.state().treasury, 300);
+    assert_eq!(server.balance("alice"), INITIAL_BALANCE - 300);
+}
+
+#[tokio::test]
+async fn missing_user_header_is_unauthorized() {
+    let server = TestServer::start().await;
+
+    let (status, _) = server.post_anonymous("/api/market/initialize", json!({})).await;
+    assert_eq!(status, 401);
+    assert!(server.node.submitted().is_empty());
+}
+
+#[tokio::test]
+async fn malformed_bodies_are_rejected_before_submission() {
+    let server = TestServer::start().await;
+
+    let (status, _) = server
+        .post("alice", "/api/market/bet", json!({ "market_id": "one", "side": true }))
+        .await;
+    assert!((400..500).contains(&status), "status {}", status);
+    assert!(server.node.submitted().is_empty());
+}
+
+#[tokio::test]
+async fn request_id_is_echoed() {
+    let server = TestServer::start().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/_health", server.url))
+        .header("x-request-id", "e2e-check")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "e2e-check");
+}
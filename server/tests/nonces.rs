@@ -0,0 +1,103 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{identity, TestServer};
+use contract1::MarketAction;
+use sdk::{Identity, TxHash};
+use serde_json::json;
+use server::app::TxSubmitter;
+use server::nonces::{Nonces, RESERVATION_TTL};
+
+fn reserve(nonces: &Nonces, user: &str, indexed_next: u64, at: Instant) -> u64 {
+    nonces.reserve_at(user, indexed_next, at, |nonce| {
+        (nonce, TxHash(format!("{}-{}", user, nonce)))
+    })
+}
+
+#[test]
+fn nonces_in_flight_are_skipped_until_settled() {
+    let nonces = Nonces::default();
+    let now = Instant::now();
+    assert_eq!(reserve(&nonces, "alice", 3, now), 3);
+    assert_eq!(reserve(&nonces, "alice", 3, now), 4);
+    assert_eq!(reserve(&nonces, "bob", 0, now), 0);
+
+    // Settled and indexed: the indexed state takes over
+    nonces.release(&TxHash("alice-3".to_string()));
+    nonces.release(&TxHash("alice-4".to_string()));
+    assert_eq!(reserve(&nonces, "alice", 5, now), 5);
+
+    // Transactions that never settle stop holding nonces back
+    assert_eq!(reserve(&nonces, "bob", 0, now + RESERVATION_TTL), 0);
+    assert_eq!(
+        reserve(
+            &nonces,
+            "bob",
+            0,
+            now + RESERVATION_TTL + Duration::from_secs(1)
+        ),
+        1
+    );
+}
+
+#[tokio::test]
+async fn actions_carry_the_next_nonce_and_replays_fail() {
+    let server = TestServer::start().await;
+    server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    server
+        .post(
+            "alice",
+            "/api/market/create",
+            json!({ "description": "Will it snow?" }),
+        )
+        .await;
+    let market_id = server.state().next_market_id;
+    server
+        .get_until(&format!("/api/market/{}", market_id), |status, _| {
+            status == 200
+        })
+        .await;
+
+    for amount in [100, 200] {
+        let (status, body) = server
+            .post(
+                "alice",
+                "/api/market/bet",
+                json!({ "market_id": market_id, "side": true, "amount": amount }),
+            )
+            .await;
+        assert_eq!(status, 200, "{}", body);
+    }
+
+    let submitted = server.node.submitted();
+    let nonces: Vec<u64> = submitted[submitted.len() - 2..]
+        .iter()
+        .map(|tx| match borsh::from_slice(&tx.blobs[0].data.0).unwrap() {
+            MarketAction::Nonced { nonce, action } => {
+                assert!(
+                    matches!(*action, MarketAction::PlaceBet { .. }),
+                    "{:?}",
+                    action
+                );
+                nonce
+            }
+            action => panic!("{:?} was sent without a nonce", action),
+        })
+        .collect();
+    assert_eq!(nonces[1], nonces[0] + 1);
+    let alice = Identity(identity("alice"));
+    assert_eq!(server.state().next_nonce(&alice), nonces[1] + 1);
+
+    // The node applying the last bet again refuses it
+    let balance = server.balance("alice");
+    server
+        .node
+        .send_tx_blob(submitted[submitted.len() - 1].clone())
+        .await
+        .unwrap();
+    assert_eq!(server.balance("alice"), balance);
+    assert_eq!(server.state().next_nonce(&alice), nonces[1] + 1);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{identity, TestServer, CONTRACT_NAME};
+use contract1::MarketAction;
+use sdk::{BlobTransaction, ContractName};
+use serde_json::{json, Value};
+use server::precheck::PrecheckMode;
+
+/// What the server logged while a test ran.
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Logs {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+/// Alice with a market to bet on, once the server has indexed it.
+async fn market(server: &TestServer) -> u64 {
+    server
+        .post("alice", "/api/market/initialize", json!({}))
+        .await;
+    server
+        .post(
+            "alice",
+            "/api/market/create",
+            json!({ "description": "Will it snow?" }),
+        )
+        .await;
+    let market_id = server.state().next_market_id;
+    server
+        .get_until(&format!("/api/market/{}", market_id), |status, _| {
+            status == 200
+        })
+        .await;
+    market_id
+}
+
+async fn bet(server: &TestServer, market_id: u64, amount: u128) -> (u16, Value) {
+    server
+        .post(
+            "alice",
+            "/api/market/bet",
+            json!({ "market_id": market_id, "side": true, "amount": amount }),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn doomed_actions_are_refused_without_being_submitted() {
+    let server = TestServer::start_with(|ctx| ctx.precheck = PrecheckMode::Enforce).await;
+    let market_id = market(&server).await;
+    let submitted = server.node.submitted().len();
+
+    let (status, body) = bet(&server, market_id, server.balance("alice") + 1).await;
+    assert_eq!(status, 422);
+    assert!(
+        body.to_string().contains("Insufficient balance"),
+        "{}",
+        body
+    );
+    assert_eq!(server.node.submitted().len(), submitted);
+
+    let (status, body) = bet(&server, market_id, 100).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(server.node.submitted().len(), submitted + 1);
+}
+
+#[tokio::test]
+async fn advisory_dry_runs_log_when_the_state_was_stale() {
+    let logs = Logs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer({
+            let logs = logs.clone();
+            move || logs.clone()
+        })
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let server = TestServer::start_with(|ctx| ctx.precheck = PrecheckMode::Advisory).await;
+    let market_id = market(&server).await;
+
+    // Alice spends most of her balance behind the server's back
+    let action = MarketAction::PlaceBet {
+        market_id,
+        side: false,
+        amount: server.balance("alice") - 500,
+    };
+    server.node.apply_unannounced(BlobTransaction::new(
+        identity("alice"),
+        vec![action.as_blob(ContractName(CONTRACT_NAME.to_string()))],
+    ));
+
+    // The dry run still sees her whole balance, so the bet is submitted
+    let (status, body) = bet(&server, market_id, 1_000).await;
+    assert_eq!(status, 400);
+    assert!(
+        body.to_string().contains("Insufficient balance"),
+        "{}",
+        body
+    );
+    for _ in 0..100 {
+        if logs.text().contains("the indexed state was stale") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let logged = logs.text();
+    assert!(
+        logged.contains("passed but it failed on-chain (Insufficient balance"),
+        "{}",
+        logged
+    );
+}
+
+#[tokio::test]
+async fn settled_queries_report_the_state_unchanged() {
+    let server = TestServer::start_with(|ctx| ctx.precheck = PrecheckMode::Enforce).await;
+    let market_id = market(&server).await;
+
+    let (status, body) = server.post("alice", "/api/market/balance", json!({})).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["unchanged"], true, "{}", body);
+    let (status, body) = server
+        .post("alice", "/api/market/exposure", json!({}))
+        .await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["unchanged"], true, "{}", body);
+    assert_eq!(body["result"]["positions"], 0);
+
+    // Actions changing the state still answer with their hash alone
+    let (status, body) = bet(&server, market_id, 100).await;
+    assert_eq!(status, 200, "{}", body);
+    assert!(body.is_string(), "{}", body);
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use common::{identity, TestServer};
+use serde_json::json;
+
+const INITIAL_BALANCE: u128 = 10_000;
+
+/// alice and bob initialized, market 1 open with bets on both sides, market 2
+/// resolved.
+async fn seeded() -> TestServer {
+    let server = TestServer::start().await;
+    for user in ["alice", "bob"] {
+        server.post(user, "/api/market/initialize", json!({})).await;
+    }
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("bob", "/api/market/create", json!({ "description": "Will it rain?" })).await;
+    server.post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 300 })).await;
+    server.post("bob", "/api/market/bet", json!({ "market_id": 1, "side": false, "amount": 100 })).await;
+    server.post("bob", "/api/market/resolve", json!({ "market_id": 2, "outcome": false })).await;
+
+    // Wait until the last transaction is indexed
+    server.get_until("/api/market/2", |status, body| status == 200 && body["status"] == "ResolvedNo").await;
+    server
+}
+
+#[tokio::test]
+async fn reads_wait_for_indexed_state() {
+    let server = TestServer::start().await;
+    let (status, _) = server.get("/api/market/1").await;
+    assert_eq!(status, 503);
+}
+
+#[tokio::test]
+async fn market_and_odds() {
+    let server = seeded().await;
+
+    let (status, market) = server.get("/api/market/1").await;
+    assert_eq!(status, 200);
+    assert_eq!(market["description"], "Will it snow?");
+    assert_eq!(market["yes_pool"], 300);
+    assert_eq!(market["no_pool"], 100);
+    assert_eq!(market["bettor_count"], 2);
+
+    let (status, odds) = server.get("/api/market/1/odds").await;
+    assert_eq!(status, 200);
+    assert_eq!(odds["yes_probability_bps"], 7_500);
+    assert_eq!(odds["no_probability_bps"], 2_500);
+
+    assert_eq!(server.get("/api/market/9").await.0, 404);
+    assert_eq!(server.get("/api/market/9/odds").await.0, 404);
+}
+
+#[tokio::test]
+async fn market_history_lists_bets_in_order() {
+    let server = seeded().await;
+
+    let (status, history) = server.get("/api/market/1/history").await;
+    assert_eq!(status, 200);
+    assert_eq!(history.as_array().unwrap().len(), 2);
+    assert_eq!(history[0]["bettor"], identity("alice"));
+    assert_eq!(history[0]["yes_pool"], 300);
+    assert_eq!(history[0]["no_pool"], 0);
+    assert_eq!(history[1]["side"], false);
+    assert_eq!(history[1]["no_pool"], 100);
+
+    assert_eq!(server.get("/api/market/9/history").await.0, 404);
+}
+
+#[tokio::test]
+async fn scheduled_markets_are_presented_until_they_open() {
+    let server = TestServer::start().await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    // Far in the future, and in the past
+    for opens_at in [4_000_000_000u64, 1_000_000_000] {
+        let body = json!({ "description": "Who wins the final?", "opens_at": opens_at });
+        assert_eq!(server.post("alice", "/api/market/create", body).await.0, 200);
+    }
+
+    let (_, market) = server.get_until("/api/market/2", |status, _| status == 200).await;
+    assert_eq!(market["opens_at"], 1_000_000_000);
+    assert_eq!(market["scheduled"], false);
+    let (_, market) = server.get("/api/market/1").await;
+    assert_eq!(market["status"], "Open");
+    assert_eq!(market["scheduled"], true);
+
+    // The fake node stamps no block time, so the opening cannot be proven
+    let (status, body) = server
+        .post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 10 }))
+        .await;
+    assert_eq!(status, 400);
+    assert!(body.to_string().contains("opens for betting at 4000000000"), "{}", body);
+}
+
+#[tokio::test]
+async fn markets_are_filtered_and_paged() {
+    let server = seeded().await;
+
+    let (status, page) = server.get("/api/markets").await;
+    assert_eq!(status, 200);
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["markets"][0]["id"], 2, "newest first");
+
+    let (_, open) = server.get("/api/markets?status=open").await;
+    assert_eq!(open["total"], 1);
+    assert_eq!(open["markets"][0]["id"], 1);
+
+    let (_, past_the_end) = server.get("/api/markets?page=1").await;
+    assert_eq!(past_the_end["page"], 1);
+    assert_eq!(past_the_end["markets"], json!([]));
+    assert_eq!(past_the_end["total"], 2);
+
+    assert_eq!(server.get("/api/markets?status=pending").await.0, 400);
+}
+
+#[tokio::test]
+async fn markets_are_filtered_by_tag() {
+    let server = TestServer::start().await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    let body = json!({ "description": "Pizza on Friday?", "tags": ["Food", "work"] });
+    assert_eq!(server.post("alice", "/api/market/create", body).await.0, 200);
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.get_until("/api/market/2", |status, _| status == 200).await;
+
+    let (_, food) = server.get("/api/markets?tag=FOOD").await;
+    assert_eq!(food["total"], 1);
+    assert_eq!(food["markets"][0]["id"], 1);
+    assert_eq!(food["markets"][0]["tags"], json!(["food", "work"]));
+    let (_, open_work) = server.get("/api/markets?status=open&tag=work").await;
+    assert_eq!(open_work["total"], 1);
+    assert_eq!(server.get("/api/markets?tag=music").await.1["total"], 0);
+}
+
+#[tokio::test]
+async fn balances_default_for_unknown_users() {
+    let server = seeded().await;
+
+    let (status, alice) = server.get(&format!("/api/user/{}/balance", identity("alice"))).await;
+    assert_eq!(status, 200);
+    assert_eq!(alice["balance"], INITIAL_BALANCE - 300);
+    assert_eq!(alice["initialized"], true);
+
+    let (status, nobody) = server.get(&format!("/api/user/{}/balance", identity("nobody"))).await;
+    assert_eq!(status, 200);
+    assert_eq!(nobody["balance"], 0);
+    assert_eq!(nobody["initialized"], false);
+}
+
+#[tokio::test]
+async fn leaderboard_is_indexed_or_proven_on_chain() {
+    let server = seeded().await;
+
+    let (status, board) = server.get("/api/market/leaderboard?limit=1").await;
+    assert_eq!(status, 200);
+    assert_eq!(board, json!([{ "identity": identity("bob"), "balance": INITIAL_BALANCE - 100 }]));
+    let (_, board) = server.get("/api/market/leaderboard").await;
+    assert_eq!(board.as_array().unwrap().len(), 2);
+
+    let submitted = server.node.submitted().len();
+    let (status, _) = server.post("alice", "/api/market/leaderboard", json!({ "limit": 500 })).await;
+    assert_eq!(status, 200);
+    assert_eq!(server.node.submitted().len(), submitted + 1);
+}
+
+#[tokio::test]
+async fn reads_are_briefly_cacheable_and_submit_nothing() {
+    let server = seeded().await;
+    let submitted = server.node.submitted().len();
+
+    let response = reqwest::get(format!("{}/api/market/1", server.url)).await.unwrap();
+    assert_eq!(response.headers()["cache-control"], "public, max-age=5");
+    assert_eq!(server.node.submitted().len(), submitted);
+}
+
+#[tokio::test]
+async fn snapshot_gathers_the_dashboard() {
+    let server = seeded().await;
+
+    let (status, snapshot) = server.get("/api/snapshot").await;
+    assert_eq!(status, 200);
+    assert_eq!(snapshot["open_market_count"], 1);
+    assert_eq!(
+        snapshot["open_markets"],
+        json!([{
+            "id": 1,
+            "description": "Will it snow?",
+            "yes_pool": 300,
+            "no_pool": 100,
+            "yes_probability_bps": 7_500,
+            "no_probability_bps": 2_500,
+        }])
+    );
+    assert_eq!(
+        snapshot["recent_resolutions"],
+        json!([{ "id": 2, "description": "Will it rain?", "outcome": false, "total_pool": 0 }])
+    );
+    assert_eq!(snapshot["leaderboard"][0], json!({ "identity": identity("bob"), "balance": INITIAL_BALANCE - 100 }));
+    assert_eq!(snapshot["total_volume"], 400);
+}
+
+#[tokio::test]
+async fn snapshot_is_not_resent_while_unchanged() {
+    let server = seeded().await;
+
+    let response = server.get_raw("/api/snapshot", &[]).await;
+    assert_eq!(response.status(), 200);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+    let response = server.get_raw("/api/snapshot", &[("if-none-match", &etag)]).await;
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.headers()["etag"], etag.as_str());
+    assert!(response.bytes().await.unwrap().is_empty());
+
+    server.post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 50 })).await;
+    server.get_until("/api/market/1", |_, market| market["yes_pool"] == 350).await;
+    let response = server.get_raw("/api/snapshot", &[("if-none-match", &etag)]).await;
+    assert_eq!(response.status(), 200);
+    assert_ne!(response.headers()["etag"], etag.as_str());
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use common::{identity, TestServer, ADMIN_KEY};
+use serde_json::{json, Value};
+
+const INITIAL_BALANCE: u128 = 10_000;
+
+/// Alice and bob are initialized, alice created markets 1 and 2, and bet 100
+/// YES on 1; market 2 is resolved.
+async fn setup() -> TestServer {
+    let server = TestServer::start().await;
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server.post("bob", "/api/market/initialize", json!({})).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it rain?" })).await;
+    server.post("alice", "/api/market/resolve", json!({ "market_id": 2, "outcome": false })).await;
+    server
+        .post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 100 }))
+        .await;
+    server
+        .get_until("/api/market/1/odds", |status, body| status == 200 && body["yes_pool"] == 100)
+        .await;
+    server
+}
+
+async fn reconcile(server: &TestServer, snapshot: Value) -> Value {
+    let (status, body) = server
+        .post_admin("alice", "/api/admin/reconcile", snapshot, Some(ADMIN_KEY))
+        .await;
+    assert_eq!(status, 200, "{}", body);
+    body
+}
+
+#[tokio::test]
+async fn reconcile_requires_the_admin_key() {
+    let server = setup().await;
+
+    let (status, body) = server
+        .post_admin("alice", "/api/admin/reconcile", json!({}), None)
+        .await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"], "admin_key_missing");
+}
+
+#[tokio::test]
+async fn matching_snapshot_reports_no_drift() {
+    let server = setup().await;
+
+    let report = reconcile(
+        &server,
+        json!({
+            "balances": [
+                { "identity": identity("alice"), "balance": INITIAL_BALANCE - 100 },
+                { "identity": identity("bob"), "balance": INITIAL_BALANCE },
+            ],
+            "open_markets": [{ "market_id": 1, "yes_pool": 100, "no_pool": 0 }],
+        }),
+    )
+    .await;
+
+    assert_eq!(report["balances"], json!([]));
+    assert_eq!(report["open_only_in_ledger"], json!([]));
+    assert_eq!(report["open_only_on_chain"], json!([]));
+    assert_eq!(report["pools"], json!([]));
+    assert_eq!(report["totals"]["users"], 2);
+    assert_eq!(report["totals"]["on_chain_open_markets"], 1);
+}
+
+#[tokio::test]
+async fn each_kind_of_drift_is_reported() {
+    let server = setup().await;
+
+    let report = reconcile(
+        &server,
+        json!({
+            "balances": [
+                { "identity": identity("alice"), "balance": INITIAL_BALANCE },
+                { "identity": identity("carol"), "balance": 50 },
+            ],
+            "open_markets": [
+                { "market_id": 1, "yes_pool": 100, "no_pool": 20 },
+                { "market_id": 2, "yes_pool": 0, "no_pool": 0 },
+            ],
+        }),
+    )
+    .await;
+
+    assert_eq!(
+        report["balances"],
+        json!([
+            { "identity": identity("alice"), "ledger": INITIAL_BALANCE, "on_chain": INITIAL_BALANCE - 100 },
+            { "identity": identity("carol"), "ledger": 50, "on_chain": null },
+        ])
+    );
+    assert_eq!(report["pools"], json!([{ "market_id": 1, "ledger": [100, 20], "on_chain": [100, 0] }]));
+    assert_eq!(report["open_only_in_ledger"], json!([2]));
+
+    // Market 1 left out of the snapshot is open on-chain only
+    let report = reconcile(&server, json!({ "balances": [], "open_markets": [] })).await;
+    assert_eq!(report["open_only_on_chain"], json!([1]));
+    // Reconciling only reads the indexed state
+    assert_eq!(server.node.submitted().len(), 6);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Router};
+use common::{identity, TestServer};
+use contract1::api::{MarketEvent, WebhookPayload};
+use serde_json::json;
+use server::webhook::{sign, WebhookConfig, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+const SECRET: &str = "webhook-secret";
+
+type Received = Arc<Mutex<Vec<(u64, String, Bytes)>>>;
+
+/// A stand-in for the bot's listener, recording every delivery.
+async fn receiver() -> (String, Received) {
+    let received = Received::default();
+    let router = Router::new()
+        .route(
+            "/events",
+            post(|State(received): State<Received>, headers: HeaderMap, body: Bytes| async move {
+                let timestamp = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+                let signature = headers[SIGNATURE_HEADER].to_str().unwrap().to_string();
+                received.lock().unwrap().push((timestamp, signature, body));
+            }),
+        )
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/events", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (url, received)
+}
+
+async fn wait_for(received: &Received, count: usize) -> Vec<(u64, String, Bytes)> {
+    for _ in 0..100 {
+        if received.lock().unwrap().len() >= count {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    received.lock().unwrap().clone()
+}
+
+#[tokio::test]
+async fn bets_and_resolutions_are_pushed_signed() {
+    let (url, received) = receiver().await;
+    let server = TestServer::start_with(|ctx| {
+        ctx.webhook = Some(WebhookConfig {
+            url,
+            secret: SECRET.to_string(),
+            max_attempts: 1,
+        })
+    })
+    .await;
+
+    server.post("alice", "/api/market/initialize", json!({})).await;
+    server.post("alice", "/api/market/create", json!({ "description": "Will it snow?" })).await;
+    server.post("alice", "/api/market/bet", json!({ "market_id": 1, "side": true, "amount": 300 })).await;
+    server.post("alice", "/api/market/resolve", json!({ "market_id": 1, "outcome": true })).await;
+
+    let deliveries = wait_for(&received, 2).await;
+    assert_eq!(deliveries.len(), 2);
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    for (timestamp, signature, body) in &deliveries {
+        assert!(now.abs_diff(*timestamp) <= 60, "signed at {}", timestamp);
+        assert_eq!(signature, &sign(SECRET.as_bytes(), *timestamp, body));
+    }
+
+    let events: Vec<MarketEvent> = deliveries
+        .iter()
+        .flat_map(|(_, _, body)| serde_json::from_slice::<WebhookPayload>(body).unwrap().events)
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            MarketEvent::BetPlaced { market_id: 1, bettor: identity("alice"), side: true, amount: 300 },
+            MarketEvent::MarketResolved {
+                market_id: 1,
+                description: "Will it snow?".to_string(),
+                outcome: true,
+                yes_pool: 300,
+                no_pool: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn signature_depends_on_secret_timestamp_and_body() {
+    let signature = sign(SECRET.as_bytes(), 1_700_000_000, b"{}");
+    assert!(signature.starts_with("sha256="));
+    assert_eq!(signature.len(), "sha256=".len() + 64);
+    assert_ne!(signature, sign(b"other-secret", 1_700_000_000, b"{}"));
+    assert_ne!(signature, sign(SECRET.as_bytes(), 1_700_000_001, b"{}"));
+    assert_ne!(signature, sign(SECRET.as_bytes(), 1_700_000_000, b"{ }"));
+}